@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use raytracer::hdr::decode_hdr;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = decode_hdr(data);
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use raytracer::netpbm::decode_pfm;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = decode_pfm(data);
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use raytracer::decode_mesh;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = decode_mesh(data, "ply");
+});
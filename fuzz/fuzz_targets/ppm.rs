@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use raytracer::netpbm::decode_ppm;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = decode_ppm(data);
+});
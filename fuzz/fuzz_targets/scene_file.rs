@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use raytracer::parse_scene;
+
+use std::path::Path;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(source) = std::str::from_utf8(data) {
+        // Relative environment maps resolve to a missing directory, so only
+        // the parser itself is exercised.
+        let _ = parse_scene(source, "fuzz.scene", Path::new("/nonexistent"));
+    }
+});
@@ -0,0 +1,239 @@
+use crate::bvh::{Bvh, BvhQuality, Precision, RAY_PACKET_WIDTH};
+use crate::camera::Camera;
+use crate::grid::SphereGrid;
+use crate::kd_tree::SphereKdTree;
+use crate::maths::*;
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::sphere::Sphere;
+
+use std::time::{Duration, Instant};
+
+/// Test of a ray against an object other than a sphere, by the structure
+/// holding it in its place: the index of the object and the closest hit so
+/// far give the parameter of a closer hit, if any.
+pub type ShapeTest<'a> = dyn FnMut(usize, Float) -> Option<Float> + 'a;
+
+/// Structure the objects of a scene are found through, so rays don't test
+/// every one of them. Spheres are tested by the structure itself, other
+/// objects through the callers, from their bounding spheres.
+pub trait Accelerator: Send + Sync {
+    /// Closest hit of `ray` in (`t_min`, `t_max`), as the index of the object
+    /// and the ray parameter, the sphere or `test` finding the same hit.
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+        test: &mut ShapeTest,
+    ) -> Option<(usize, Float)>;
+
+    /// Indices of the objects that may hold `point`, each once: every object
+    /// whose bounding box holds it is among them, and callers test the
+    /// objects themselves.
+    fn candidates(&self, point: Vec3) -> Vec<usize>;
+
+    /// Closest hits of up to `RAY_PACKET_WIDTH` `rays`, each between `t_min`
+    /// and its own `t_max`, like `hit` gives them, one at a time unless the
+    /// structure traces them together. `test` takes the lane of the ray
+    /// first.
+    fn hit_packet(
+        &self,
+        rays: &[Ray],
+        t_min: Float,
+        t_max: &[Float],
+        test: &mut dyn FnMut(usize, usize, Float) -> Option<Float>,
+    ) -> [Option<(usize, Float)>; RAY_PACKET_WIDTH] {
+        assert!(rays.len() <= RAY_PACKET_WIDTH && t_max.len() == rays.len());
+        let mut closest = [None; RAY_PACKET_WIDTH];
+        for (lane, ray) in rays.iter().enumerate() {
+            let mut test = |shape, closest_t| test(lane, shape, closest_t);
+            closest[lane] = self.hit(ray, t_min, t_max[lane], &mut test);
+        }
+        closest
+    }
+}
+
+impl Accelerator for Bvh {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+        test: &mut ShapeTest,
+    ) -> Option<(usize, Float)> {
+        Bvh::hit_with(self, ray, t_min, t_max, test)
+    }
+
+    fn candidates(&self, point: Vec3) -> Vec<usize> {
+        Bvh::candidates(self, point)
+    }
+
+    fn hit_packet(
+        &self,
+        rays: &[Ray],
+        t_min: Float,
+        t_max: &[Float],
+        test: &mut dyn FnMut(usize, usize, Float) -> Option<Float>,
+    ) -> [Option<(usize, Float)>; RAY_PACKET_WIDTH] {
+        Bvh::hit_packet_with(self, rays, t_min, t_max, test)
+    }
+}
+
+impl Accelerator for SphereKdTree {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+        test: &mut ShapeTest,
+    ) -> Option<(usize, Float)> {
+        SphereKdTree::hit_with(self, ray, t_min, t_max, test)
+    }
+
+    fn candidates(&self, point: Vec3) -> Vec<usize> {
+        SphereKdTree::candidates(self, point)
+    }
+}
+
+impl Accelerator for SphereGrid {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+        test: &mut ShapeTest,
+    ) -> Option<(usize, Float)> {
+        SphereGrid::hit_with(self, ray, t_min, t_max, test)
+    }
+
+    fn candidates(&self, point: Vec3) -> Vec<usize> {
+        SphereGrid::candidates(self, point)
+    }
+}
+
+/// Which structure a scene finds its spheres and the triangles of its
+/// meshes through.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AcceleratorKind {
+    /// Bounding volume hierarchy, built following the `BvhQuality` of the
+    /// scene. Meshes keep a hierarchy of their own.
+    #[default]
+    Bvh,
+    /// Kd-tree, slower to build, but whose cells don't overlap, so rays
+    /// stop at the first cell holding a hit (see `KdTree`).
+    KdTree,
+    /// Uniform grid, the fastest to build, for scenes changing every frame,
+    /// but slow when objects crowd a few cells (see `Grid`).
+    Grid,
+}
+
+impl AcceleratorKind {
+    pub const ALL: [AcceleratorKind; 3] = [
+        AcceleratorKind::Bvh,
+        AcceleratorKind::KdTree,
+        AcceleratorKind::Grid,
+    ];
+
+    /// Name of the structure in scene files and on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            AcceleratorKind::Bvh => "bvh",
+            AcceleratorKind::KdTree => "kd-tree",
+            AcceleratorKind::Grid => "grid",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        AcceleratorKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.name() == name)
+    }
+
+    /// Structure of this kind over the spheres of `indices` and the objects
+    /// of `shapes`, which `spheres` only bounds. Kd-trees and grids keep
+    /// their boxes in full precision whatever `precision`.
+    pub fn build(
+        self,
+        spheres: &[Sphere],
+        indices: Vec<usize>,
+        shapes: Vec<usize>,
+        quality: BvhQuality,
+        precision: Precision,
+    ) -> Box<dyn Accelerator> {
+        match self {
+            AcceleratorKind::Bvh => Box::new(
+                Bvh::of_objects(spheres, indices, shapes, quality).with_precision(precision),
+            ),
+            AcceleratorKind::KdTree => Box::new(SphereKdTree::of_objects(spheres, indices, shapes)),
+            AcceleratorKind::Grid => Box::new(SphereGrid::of_objects(spheres, indices, shapes)),
+        }
+    }
+}
+
+/// Timings of one structure in `benchmark_accelerators`.
+#[derive(Clone, Copy, Debug)]
+pub struct AcceleratorTimings {
+    pub kind: AcceleratorKind,
+    /// Time taken to build the structures of the scene and of its meshes.
+    pub build: Duration,
+    /// Time taken to find the closest hits of the rays.
+    pub trace: Duration,
+    pub rays: usize,
+    /// Rays that hit something, the same for every structure.
+    pub hits: usize,
+}
+
+impl AcceleratorTimings {
+    /// Millions of rays traced per second.
+    pub fn rays_per_second(&self) -> Float {
+        self.rays as Float / self.trace.as_secs_f64().max(1e-9) as Float / 1e6
+    }
+}
+
+/// Builds every kind of structure for `scene` in turn, then times the
+/// closest hits of a ray through the center of every pixel of a `width` by
+/// `height` image of `camera`. The scene keeps its own kind of structure.
+pub fn benchmark_accelerators(
+    scene: &mut Scene,
+    camera: &Camera,
+    width: usize,
+    height: usize,
+) -> Vec<AcceleratorTimings> {
+    let rays: Vec<Ray> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let s = (x as Float + 0.5) / width as Float;
+            let t = 1.0 - (y as Float + 0.5) / height as Float;
+            camera.get_ray(s, t, (0.5, 0.5))
+        })
+        .collect();
+    let t_min = scene.epsilon();
+
+    let original = scene.accelerator_kind();
+    let timings = AcceleratorKind::ALL
+        .iter()
+        .map(|&kind| {
+            let start = Instant::now();
+            scene.set_accelerator_kind(kind);
+            scene.accelerator();
+            let build = start.elapsed();
+
+            let start = Instant::now();
+            let hits = rays
+                .iter()
+                .filter(|ray| scene.hit(ray, t_min, Float::INFINITY).is_some())
+                .count();
+            AcceleratorTimings {
+                kind,
+                build,
+                trace: start.elapsed(),
+                rays: rays.len(),
+                hits,
+            }
+        })
+        .collect();
+    scene.set_accelerator_kind(original);
+    timings
+}
@@ -0,0 +1,261 @@
+use crate::camera::CameraView;
+use crate::maths::*;
+use crate::scene::ObjectId;
+
+/// How a track goes from a keyframe to the next.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Interpolation {
+    /// Straight at a constant speed.
+    Linear,
+    /// Along a curve through the keyframes around, easing between changes
+    /// of speed, for flythroughs.
+    Smooth,
+    /// Holding the value of the keyframe until the next.
+    Step,
+}
+
+impl Interpolation {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "linear" => Some(Interpolation::Linear),
+            "smooth" => Some(Interpolation::Smooth),
+            "step" => Some(Interpolation::Step),
+            _ => None,
+        }
+    }
+}
+
+/// Values tracks interpolate, by sums of keyframes with weights adding up
+/// to 1.
+pub trait Animatable: Copy {
+    fn weighted_sum(values: &[(Self, Float)]) -> Self;
+}
+
+impl Animatable for CameraView {
+    fn weighted_sum(values: &[(Self, Float)]) -> Self {
+        let sum = |field: &dyn Fn(&CameraView) -> Vec3| {
+            values
+                .iter()
+                .map(|(view, weight)| field(view) * *weight)
+                .sum()
+        };
+        CameraView {
+            look_from: sum(&|view| view.look_from),
+            look_at: sum(&|view| view.look_at),
+            up: sum(&|view| view.up),
+            vertical_fov: values
+                .iter()
+                .map(|(view, weight)| view.vertical_fov * weight)
+                .sum(),
+        }
+    }
+}
+
+/// Placement of an object relative to where it was added: turned by
+/// `rotation`, in degrees around x, then y, then z, and scaled by `scale`
+/// about its center, then moved by `offset`.
+#[derive(Copy, Clone, Debug)]
+pub struct Pose {
+    pub offset: Vec3,
+    pub rotation: Vec3,
+    pub scale: Float,
+}
+
+impl Default for Pose {
+    fn default() -> Self {
+        Pose {
+            offset: Vec3::new(0.0, 0.0, 0.0),
+            rotation: Vec3::new(0.0, 0.0, 0.0),
+            scale: 1.0,
+        }
+    }
+}
+
+impl Pose {
+    /// Transform of an object centered on `pivot`, for
+    /// `Scene::update_transform`.
+    pub fn transform(&self, pivot: Vec3) -> Mat4 {
+        let rotation = Mat4::rotation_z(self.rotation.z.to_radians())
+            * Mat4::rotation_y(self.rotation.y.to_radians())
+            * Mat4::rotation_x(self.rotation.x.to_radians());
+        Mat4::translation(pivot + self.offset)
+            * rotation
+            * Mat4::scaling(Vec3::new(self.scale, self.scale, self.scale))
+            * Mat4::translation(-pivot)
+    }
+}
+
+impl Animatable for Pose {
+    fn weighted_sum(values: &[(Self, Float)]) -> Self {
+        Pose {
+            offset: values
+                .iter()
+                .map(|(pose, weight)| pose.offset * *weight)
+                .sum(),
+            rotation: values
+                .iter()
+                .map(|(pose, weight)| pose.rotation * *weight)
+                .sum(),
+            scale: values
+                .iter()
+                .map(|(pose, weight)| pose.scale * weight)
+                .sum(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Keyframe<T> {
+    /// Time of the keyframe, in seconds.
+    pub time: Float,
+    pub value: T,
+    /// How the value goes on to the next keyframe.
+    pub interpolation: Interpolation,
+}
+
+/// Keyframes of a value, in order of time. Before the first keyframe and
+/// after the last, the value holds.
+#[derive(Clone, Debug)]
+pub struct Track<T> {
+    keyframes: Vec<Keyframe<T>>,
+}
+
+impl<T> Default for Track<T> {
+    fn default() -> Self {
+        Track {
+            keyframes: Vec::new(),
+        }
+    }
+}
+
+impl<T: Animatable> Track<T> {
+    /// Adds `keyframe`, replacing the one at the same time if any.
+    pub fn insert(&mut self, keyframe: Keyframe<T>) {
+        let index = self
+            .keyframes
+            .partition_point(|other| other.time < keyframe.time);
+        match self.keyframes.get(index) {
+            Some(other) if other.time == keyframe.time => self.keyframes[index] = keyframe,
+            _ => self.keyframes.insert(index, keyframe),
+        }
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe<T>] {
+        &self.keyframes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    /// Value at `time`, `None` without keyframes.
+    pub fn at(&self, time: Float) -> Option<T> {
+        let keys = &self.keyframes;
+        let next = keys.partition_point(|key| key.time <= time);
+        if next == 0 {
+            return keys.first().map(|key| key.value);
+        }
+        if next == keys.len() {
+            return keys.last().map(|key| key.value);
+        }
+        let (start, end) = (&keys[next - 1], &keys[next]);
+        let t = (time - start.time) / (end.time - start.time);
+        Some(match start.interpolation {
+            Interpolation::Step => start.value,
+            Interpolation::Linear => T::weighted_sum(&[(start.value, 1.0 - t), (end.value, t)]),
+            Interpolation::Smooth => {
+                // Cubic Hermite curve, with the slopes of Catmull-Rom
+                // splines scaled to uneven spacing, flat at the ends of the
+                // track.
+                let before = &keys[next.saturating_sub(2)];
+                let after = &keys[(next + 1).min(keys.len() - 1)];
+                let span = end.time - start.time;
+                let slope_start = span / (end.time - before.time);
+                let slope_end = span / (after.time - start.time);
+                let (t2, t3) = (t * t, t * t * t);
+                let h00 = 2.0 * t3 - 3.0 * t2 + 1.0;
+                let h10 = t3 - 2.0 * t2 + t;
+                let h01 = 3.0 * t2 - 2.0 * t3;
+                let h11 = t3 - t2;
+                T::weighted_sum(&[
+                    (before.value, -h10 * slope_start),
+                    (start.value, h00 - h11 * slope_end),
+                    (end.value, h01 + h10 * slope_start),
+                    (after.value, h11 * slope_end),
+                ])
+            }
+        })
+    }
+}
+
+/// Camera and object keyframes of a scene, so turntables and flythroughs
+/// can be rendered frame by frame.
+#[derive(Clone, Debug, Default)]
+pub struct Animation {
+    pub camera: Track<CameraView>,
+    /// Tracks of the animated spheres, with their center as they were
+    /// added, which they turn and scale about.
+    pub objects: Vec<(ObjectId, Vec3, Track<Pose>)>,
+}
+
+impl Animation {
+    pub fn is_empty(&self) -> bool {
+        self.camera.is_empty() && self.objects.is_empty()
+    }
+
+    /// Track of `sphere`, centered on `pivot`, added if it has none.
+    pub fn object_track(&mut self, sphere: ObjectId, pivot: Vec3) -> &mut Track<Pose> {
+        let index = match self.objects.iter().position(|(id, ..)| *id == sphere) {
+            Some(index) => index,
+            None => {
+                self.objects.push((sphere, pivot, Track::default()));
+                self.objects.len() - 1
+            }
+        };
+        &mut self.objects[index].2
+    }
+
+    /// Time of the last keyframe.
+    pub fn duration(&self) -> Float {
+        let objects = self.objects.iter().map(|(_, _, track)| track.keyframes());
+        let camera = self.camera.keyframes().iter().map(|key| key.time);
+        objects
+            .flat_map(|keys| keys.iter().map(|key| key.time))
+            .chain(camera)
+            .fold(0.0, Float::max)
+    }
+
+    /// Transforms of the animated objects at `time`, for
+    /// `Scene::update_transform`.
+    pub fn transforms_at(&self, time: Float) -> Vec<(ObjectId, Mat4)> {
+        self.objects
+            .iter()
+            .filter_map(|(sphere, pivot, track)| Some((*sphere, track.at(time)?.transform(*pivot))))
+            .collect()
+    }
+
+    /// Scales the positions of the keyframes by `factor`, like
+    /// `Scene::convert_to`.
+    pub fn scale(&mut self, factor: Float) {
+        for key in &mut self.camera.keyframes {
+            key.value.look_from = key.value.look_from * factor;
+            key.value.look_at = key.value.look_at * factor;
+        }
+        for (_, pivot, track) in &mut self.objects {
+            *pivot = *pivot * factor;
+            for key in &mut track.keyframes {
+                key.value.offset = key.value.offset * factor;
+            }
+        }
+    }
+
+    /// Follows the spheres to their new ids, given by `Scene::remove_spheres`,
+    /// dropping the tracks of removed ones.
+    pub fn remap(&mut self, new_ids: &[Option<ObjectId>]) {
+        self.objects = self
+            .objects
+            .drain(..)
+            .filter_map(|(id, pivot, track)| Some((new_ids[id.0]?, pivot, track)))
+            .collect();
+    }
+}
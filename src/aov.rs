@@ -0,0 +1,139 @@
+use crate::maths::*;
+
+/// Auxiliary data of a camera ray, taken at its first hit.
+#[derive(Clone, Copy, Debug)]
+pub struct Aov {
+    pub albedo: Vec3,
+    /// World space shading normal, zero when the ray missed.
+    pub normal: Vec3,
+    /// Distance to the first hit in scene units, infinite when the ray missed.
+    pub depth: Float,
+    /// Where the first hit was in the previous frame, see
+    /// `Scene::previous_position`.
+    pub previous_position: Vec3,
+    /// Pixels the first hit, or the environment for rays that missed,
+    /// moved across the image since the previous frame, x to the right and
+    /// y down.
+    pub motion: (Float, Float),
+}
+
+impl Default for Aov {
+    fn default() -> Self {
+        Aov {
+            albedo: Vec3::new(0.0, 0.0, 0.0),
+            normal: Vec3::new(0.0, 0.0, 0.0),
+            depth: Float::INFINITY,
+            previous_position: Vec3::new(0.0, 0.0, 0.0),
+            motion: (0.0, 0.0),
+        }
+    }
+}
+
+/// Resolved auxiliary images, row-major.
+pub struct AovImages {
+    /// Linear RGB albedo.
+    pub albedo: Vec<Float>,
+    /// XYZ normals, averaged over the samples of each pixel.
+    pub normal: Vec<Float>,
+    /// Mean depth of the samples that hit something, infinite for pixels
+    /// where every sample missed.
+    pub depth: Vec<Float>,
+    /// XY motion vectors in pixels, averaged over the samples of each
+    /// pixel, for motion blur, temporal denoising or frame interpolation.
+    pub motion: Vec<Float>,
+}
+
+/// Albedo and normal sums, depth sum, hit count, sample count and motion
+/// sums per pixel.
+const STRIDE: usize = 11;
+
+/// Accumulates the auxiliary data of the samples falling in each pixel of a
+/// window of the image. Unlike the beauty film, samples are not filtered, so
+/// edges stay sharp for denoisers relying on them.
+pub struct AovBuffer {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    data: Vec<Float>,
+}
+
+impl AovBuffer {
+    /// Buffer covering the `width` x `height` pixels starting at (`x`, `y`).
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        AovBuffer {
+            x,
+            y,
+            width,
+            height,
+            data: vec![0.0; width * height * STRIDE],
+        }
+    }
+
+    /// Adds a sample of pixel (`i`, `j`), in image coordinates.
+    pub fn add_sample(&mut self, i: usize, j: usize, aov: &Aov) {
+        let index = ((j - self.y) * self.width + (i - self.x)) * STRIDE;
+        let pixel = &mut self.data[index..index + STRIDE];
+        pixel[0] += aov.albedo.x;
+        pixel[1] += aov.albedo.y;
+        pixel[2] += aov.albedo.z;
+        pixel[3] += aov.normal.x;
+        pixel[4] += aov.normal.y;
+        pixel[5] += aov.normal.z;
+        if aov.depth.is_finite() {
+            pixel[6] += aov.depth;
+            pixel[7] += 1.0;
+        }
+        pixel[8] += 1.0;
+        pixel[9] += aov.motion.0;
+        pixel[10] += aov.motion.1;
+    }
+
+    /// Adds the overlapping part of `other` to this buffer.
+    pub fn merge(&mut self, other: &AovBuffer) {
+        let x0 = self.x.max(other.x);
+        let y0 = self.y.max(other.y);
+        let x1 = (self.x + self.width).min(other.x + other.width);
+        let y1 = (self.y + self.height).min(other.y + other.height);
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let to = ((y - self.y) * self.width + (x - self.x)) * STRIDE;
+                let from = ((y - other.y) * other.width + (x - other.x)) * STRIDE;
+                for channel in 0..STRIDE {
+                    self.data[to + channel] += other.data[from + channel];
+                }
+            }
+        }
+    }
+
+    pub fn resolve(&self) -> AovImages {
+        let pixels = self.width * self.height;
+        let mut images = AovImages {
+            albedo: Vec::with_capacity(pixels * 3),
+            normal: Vec::with_capacity(pixels * 3),
+            depth: Vec::with_capacity(pixels),
+            motion: Vec::with_capacity(pixels * 2),
+        };
+
+        for pixel in self.data.chunks(STRIDE) {
+            let samples = pixel[8].max(1.0);
+            images
+                .albedo
+                .extend(pixel[0..3].iter().map(|&x| x / samples));
+            images
+                .normal
+                .extend(pixel[3..6].iter().map(|&x| x / samples));
+            images.depth.push(if pixel[7] > 0.0 {
+                pixel[6] / pixel[7]
+            } else {
+                Float::INFINITY
+            });
+            images
+                .motion
+                .extend(pixel[9..11].iter().map(|&x| x / samples));
+        }
+
+        images
+    }
+}
@@ -0,0 +1,121 @@
+use crate::maths::*;
+
+use std::sync::OnceLock;
+
+/// Side, in pixels, of the texture returned by `BlueNoise::shared`.
+const SHARED_SIZE: usize = 64;
+
+/// Standard deviation, in pixels, of the Gaussian weighing how much points
+/// crowd each other.
+const SIGMA: Float = 1.5;
+
+/// Tileable square texture holding every rank from 0 to its pixel count once,
+/// arranged so that each threshold of the ranks gives evenly spread points:
+/// its values change from pixel to pixel without the low frequency clumps of
+/// white noise. Made with the void-and-cluster method (Ulichney 1993).
+pub struct BlueNoise {
+    size: usize,
+    ranks: Vec<u32>,
+}
+
+impl BlueNoise {
+    /// Texture of `size` by `size` pixels, `seed` picking the initial points.
+    pub fn generate(size: usize, seed: u64) -> Self {
+        let pixels = size * size;
+        // Weight of a point on the pixels around it, the texture wrapping
+        // around in both directions.
+        let kernel: Vec<Float> = (0..pixels)
+            .map(|offset| {
+                let (dx, dy) = (offset % size, offset / size);
+                let (dx, dy) = (dx.min(size - dx) as Float, dy.min(size - dy) as Float);
+                (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+            })
+            .collect();
+        let update = |energy: &mut [Float], point: usize, sign: Float| {
+            let (px, py) = (point % size, point / size);
+            for (pixel, energy) in energy.iter_mut().enumerate() {
+                let dx = (pixel % size + size - px) % size;
+                let dy = (pixel / size + size - py) % size;
+                *energy += sign * kernel[dy * size + dx];
+            }
+        };
+        // Point with the most crowded (`is_point`) or the emptiest (not
+        // `is_point`) surroundings.
+        let find = |points: &[bool], energy: &[Float], is_point: bool| {
+            let candidates = (0..pixels).filter(|&pixel| points[pixel] == is_point);
+            if is_point {
+                candidates.max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            } else {
+                candidates.min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            }
+        };
+
+        // Random initial points, a tenth of the pixels.
+        let mut points = vec![false; pixels];
+        let mut energy = vec![0.0; pixels];
+        let mut count = 0;
+        for index in 0.. {
+            if count == (pixels / 10).max(1) {
+                break;
+            }
+            let pixel = (mix_seed(seed, index) % pixels as u64) as usize;
+            if !points[pixel] {
+                points[pixel] = true;
+                update(&mut energy, pixel, 1.0);
+                count += 1;
+            }
+        }
+
+        // Move the most crowded point to the emptiest spot until that puts it
+        // back where it was: the points are then evenly spread.
+        while let Some(cluster) = find(&points, &energy, true) {
+            points[cluster] = false;
+            update(&mut energy, cluster, -1.0);
+            let void = find(&points, &energy, false).unwrap_or(cluster);
+            points[void] = true;
+            update(&mut energy, void, 1.0);
+            if void == cluster {
+                break;
+            }
+        }
+
+        // Ranks below the initial points are given by removing them from the
+        // most crowded first, the ranks above by filling the emptiest spots.
+        // Past half the pixels, the emptiest spot among the points left is
+        // also the most crowded of the pixels not filled yet, so filling
+        // carries on the same way.
+        let mut ranks = vec![0; pixels];
+        let (mut removed, mut removed_energy) = (points.clone(), energy.clone());
+        for rank in (0..count).rev() {
+            let cluster = find(&removed, &removed_energy, true).unwrap();
+            removed[cluster] = false;
+            update(&mut removed_energy, cluster, -1.0);
+            ranks[cluster] = rank as u32;
+        }
+        for rank in count..pixels {
+            let void = find(&points, &energy, false).unwrap();
+            points[void] = true;
+            update(&mut energy, void, 1.0);
+            ranks[void] = rank as u32;
+        }
+
+        BlueNoise { size, ranks }
+    }
+
+    /// 64 by 64 texture, generated on first use.
+    pub fn shared() -> &'static BlueNoise {
+        static SHARED: OnceLock<BlueNoise> = OnceLock::new();
+        SHARED.get_or_init(|| BlueNoise::generate(SHARED_SIZE, 0))
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Value in (0, 1) of pixel (`x`, `y`), the texture repeating in both
+    /// directions. Values are evenly spaced, so uniformly distributed.
+    pub fn value(&self, x: usize, y: usize) -> Float {
+        let rank = self.ranks[(y % self.size) * self.size + x % self.size];
+        (rank as Float + 0.5) / self.ranks.len() as Float
+    }
+}
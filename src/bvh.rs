@@ -0,0 +1,826 @@
+use crate::maths::*;
+use crate::ray::{Ray, RayExt};
+use crate::sphere::{Sphere, SpherePacket, PACKET_WIDTH};
+
+/// Number of candidate split planes per axis of the SAH build.
+const SAH_BINS: usize = 16;
+
+/// Number of rays traversing a `Bvh` together in `Bvh::hit_packet`.
+pub const RAY_PACKET_WIDTH: usize = 8;
+
+/// How a `Bvh` picks where to split its nodes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum BvhQuality {
+    /// Halves the spheres at their median along the widest axis. Quick to
+    /// build, but nodes overlap more, so rays visit more of them.
+    Median,
+    /// Picks, among evenly spaced planes on every axis, the one minimizing
+    /// the surface area heuristic: the area of each child times the number
+    /// of spheres in it, as the odds of a ray entering the child times the
+    /// cost of testing it.
+    #[default]
+    Sah,
+}
+
+/// Precision the hierarchies of a scene and of its meshes are stored and
+/// traversed in.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Precision {
+    /// Everything in the precision of `Float`.
+    #[default]
+    Full,
+    /// Boxes of hierarchies and the corners of meshes stored in single
+    /// precision, taking about half the memory, and rays traversing the
+    /// boxes in single precision, hits and shading staying in `Float`, for
+    /// large scenes. Boxes are rounded outwards and rays padded by the
+    /// rounding of their origin, so no hit inside a box is lost, and
+    /// triangles get a watertight test, so no ray slips between them.
+    Mixed,
+}
+
+impl Precision {
+    /// Name of the precision in scene files and on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Precision::Full => "full",
+            Precision::Mixed => "mixed",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Precision::Full, Precision::Mixed]
+            .iter()
+            .copied()
+            .find(|precision| precision.name() == name)
+    }
+}
+
+/// Axis aligned bounding box.
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Box holding nothing, which any box merges into unchanged.
+    pub fn empty() -> Self {
+        Aabb {
+            min: Vec3::new(Float::INFINITY, Float::INFINITY, Float::INFINITY),
+            max: Vec3::new(
+                Float::NEG_INFINITY,
+                Float::NEG_INFINITY,
+                Float::NEG_INFINITY,
+            ),
+        }
+    }
+
+    pub fn of_sphere(sphere: &Sphere) -> Self {
+        let radius = sphere.radius.abs();
+        let extent = Vec3::new(radius, radius, radius);
+        Aabb {
+            min: sphere.position - extent,
+            max: sphere.position + extent,
+        }
+    }
+
+    pub fn merge(self, other: Aabb) -> Self {
+        Aabb {
+            min: Vec3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Vec3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    /// Grows the box to hold `point`.
+    pub fn include(self, point: Vec3) -> Self {
+        self.merge(Aabb {
+            min: point,
+            max: point,
+        })
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        (self.min.x..=self.max.x).contains(&point.x)
+            && (self.min.y..=self.max.y).contains(&point.y)
+            && (self.min.z..=self.max.z).contains(&point.z)
+    }
+
+    pub fn surface_area(&self) -> Float {
+        let size = self.max - self.min;
+        if size.x < 0.0 {
+            return 0.0;
+        }
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    /// Part of `ray` inside the box between `t_min` and `t_max`, as the ray
+    /// parameters it enters and leaves at. Rounding only ever makes boxes
+    /// look larger, so no hit inside them is lost (Ize 2013).
+    pub fn clip(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<(Float, Float)> {
+        let inverse_dir = ray.inverse_dir();
+        let negative = ray.dir_is_negative();
+        let (mut near, mut far) = (t_min, t_max);
+        for (origin, inverse, min, max, negative) in [
+            (
+                ray.origin.x,
+                inverse_dir.x,
+                self.min.x,
+                self.max.x,
+                negative[0],
+            ),
+            (
+                ray.origin.y,
+                inverse_dir.y,
+                self.min.y,
+                self.max.y,
+                negative[1],
+            ),
+            (
+                ray.origin.z,
+                inverse_dir.z,
+                self.min.z,
+                self.max.z,
+                negative[2],
+            ),
+        ] {
+            // The sign of the direction tells which plane the ray meets first.
+            let (first, last) = if negative { (max, min) } else { (min, max) };
+            // `max` and `min` drop the NaN of rays lying in a slab plane.
+            near = near.max((first - origin) * inverse);
+            far = far.min((last - origin) * inverse * (1.0 + 4.0 * Float::EPSILON));
+        }
+        if near <= far {
+            Some((near, far))
+        } else {
+            None
+        }
+    }
+
+    /// Whether `ray` enters the box between `t_min` and `t_max`, see `clip`.
+    pub fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        self.clip(ray, t_min, t_max).is_some()
+    }
+}
+
+/// Single precision float at most `x`.
+fn round_down(x: Float) -> f32 {
+    let rounded = to_f32(x);
+    if rounded as Float > x {
+        rounded.next_down()
+    } else {
+        rounded
+    }
+}
+
+/// Single precision float at least `x`.
+fn round_up(x: Float) -> f32 {
+    let rounded = to_f32(x);
+    if (rounded as Float) < x {
+        rounded.next_up()
+    } else {
+        rounded
+    }
+}
+
+/// `Aabb` in single precision, for `Precision::Mixed`, holding the box it
+/// was made from.
+#[derive(Clone, Copy, Debug)]
+pub struct CompactAabb {
+    min: [f32; 3],
+    max: [f32; 3],
+}
+
+impl CompactAabb {
+    pub fn new(bounds: &Aabb) -> Self {
+        CompactAabb {
+            min: [
+                round_down(bounds.min.x),
+                round_down(bounds.min.y),
+                round_down(bounds.min.z),
+            ],
+            max: [
+                round_up(bounds.max.x),
+                round_up(bounds.max.y),
+                round_up(bounds.max.z),
+            ],
+        }
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        let vector = |[x, y, z]: [f32; 3]| Vec3::new(x as Float, y as Float, z as Float);
+        Aabb {
+            min: vector(self.min),
+            max: vector(self.max),
+        }
+    }
+
+    /// Whether `ray` may enter the box between `t_min` and `t_max`, never
+    /// missing it when the `Ray` it was made from does.
+    pub fn hit(&self, ray: &CompactRay, t_min: Float, t_max: Float) -> bool {
+        let (mut near, mut far) = (to_f32(t_min), to_f32(t_max));
+        for axis in 0..3 {
+            let (first, last) = if ray.negative[axis] {
+                (self.max[axis], self.min[axis])
+            } else {
+                (self.min[axis], self.max[axis])
+            };
+            let (origin, inverse) = (ray.origin[axis], ray.inverse_dir[axis]);
+            let padding = ray.padding[axis];
+            near = near.max((first - origin) * inverse - padding);
+            far = far.min((last - origin) * inverse + padding);
+        }
+        // Rounding the parameters, the subtractions, the products and the
+        // inverse of the direction only scales them by a few epsilons
+        // (Ize 2013).
+        near <= far * (1.0 + 10.0 * f32::EPSILON)
+    }
+}
+
+/// `Ray` in single precision, for traversing `CompactAabb`s.
+#[derive(Clone, Copy, Debug)]
+pub struct CompactRay {
+    origin: [f32; 3],
+    inverse_dir: [f32; 3],
+    negative: [bool; 3],
+    /// Change of the parameters of the slabs along each axis that the
+    /// rounding of the origin may make, at least: widening the slabs by as
+    /// much holds them around the true origin.
+    padding: [f32; 3],
+}
+
+impl CompactRay {
+    pub fn new(ray: &Ray) -> Self {
+        let origin = [ray.origin.x, ray.origin.y, ray.origin.z];
+        let inverse = ray.inverse_dir();
+        let inverse = [inverse.x, inverse.y, inverse.z];
+        let rounded = origin.map(to_f32);
+        let mut padding = [0.0; 3];
+        for axis in 0..3 {
+            let error = (origin[axis] - rounded[axis] as Float).abs();
+            // Without error, padding rays parallel to the axis would give
+            // NaN.
+            if error > 0.0 {
+                padding[axis] =
+                    round_up(error * inverse[axis].abs() * (1.0 + 4.0 * Float::EPSILON));
+            }
+        }
+        CompactRay {
+            origin: rounded,
+            inverse_dir: inverse.map(to_f32),
+            negative: ray.dir_is_negative(),
+            padding,
+        }
+    }
+}
+
+/// Boxes of the nodes of hierarchies, in the precision of `Float` or in
+/// single precision.
+pub trait NodeBounds: Copy {
+    /// Rays as the boxes test them.
+    type Ray;
+
+    fn ray(ray: &Ray) -> Self::Ray;
+
+    /// Whether `ray` may enter the box between `t_min` and `t_max`.
+    fn hit(&self, ray: &Self::Ray, t_min: Float, t_max: Float) -> bool;
+
+    /// Box in the precision of `Float`.
+    fn aabb(&self) -> Aabb;
+}
+
+impl NodeBounds for Aabb {
+    type Ray = Ray;
+
+    fn ray(ray: &Ray) -> Ray {
+        *ray
+    }
+
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        Aabb::hit(self, ray, t_min, t_max)
+    }
+
+    fn aabb(&self) -> Aabb {
+        *self
+    }
+}
+
+impl NodeBounds for CompactAabb {
+    type Ray = CompactRay;
+
+    fn ray(ray: &Ray) -> CompactRay {
+        CompactRay::new(ray)
+    }
+
+    fn hit(&self, ray: &CompactRay, t_min: Float, t_max: Float) -> bool {
+        CompactAabb::hit(self, ray, t_min, t_max)
+    }
+
+    fn aabb(&self) -> Aabb {
+        CompactAabb::aabb(self)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct BvhNode<B> {
+    bounds: B,
+    /// Leaves: index of their packet. Inner nodes: index of their second
+    /// child, the first one following them.
+    index: u32,
+    is_leaf: bool,
+}
+
+impl BvhNode<Aabb> {
+    fn compact(&self) -> BvhNode<CompactAabb> {
+        BvhNode {
+            bounds: CompactAabb::new(&self.bounds),
+            index: self.index,
+            is_leaf: self.is_leaf,
+        }
+    }
+}
+
+/// Nodes of a `Bvh`, depth first, every inner node followed by its first
+/// child.
+enum BvhNodes {
+    Full(Vec<BvhNode<Aabb>>),
+    Compact(Vec<BvhNode<CompactAabb>>),
+}
+
+/// Bounding volume hierarchy over the spheres of a scene, whose leaves hold
+/// up to a `SpherePacket` of spheres each. Other objects of the scene sit in
+/// the leaves by their bounding spheres, rays testing them through the
+/// callback of `hit_with`.
+pub struct Bvh {
+    nodes: BvhNodes,
+    packets: Vec<SpherePacket>,
+    /// Index of the sphere in every lane of every packet.
+    lanes: Vec<[usize; PACKET_WIDTH]>,
+    /// Indices of the other objects of every leaf.
+    shapes: Vec<Vec<usize>>,
+}
+
+impl Bvh {
+    pub fn new(spheres: &[Sphere], quality: BvhQuality) -> Self {
+        Bvh::of_indices(spheres, (0..spheres.len()).collect(), quality)
+    }
+
+    /// BVH over the spheres of `indices` alone, leaving the others to the
+    /// caller.
+    pub fn of_indices(spheres: &[Sphere], indices: Vec<usize>, quality: BvhQuality) -> Self {
+        Bvh::of_objects(spheres, indices, Vec::new(), quality)
+    }
+
+    /// BVH over the spheres of `indices` and the objects of `shapes`, which
+    /// `spheres` only bounds, leaving the others to the caller.
+    pub fn of_objects(
+        spheres: &[Sphere],
+        mut indices: Vec<usize>,
+        shapes: Vec<usize>,
+        quality: BvhQuality,
+    ) -> Self {
+        let mut bvh = Bvh {
+            nodes: BvhNodes::Full(Vec::new()),
+            packets: Vec::new(),
+            lanes: Vec::new(),
+            shapes: Vec::new(),
+        };
+        let mut is_shape = vec![false; spheres.len()];
+        for &shape in &shapes {
+            is_shape[shape] = true;
+        }
+        indices.extend(shapes);
+        if !indices.is_empty() {
+            bvh.build(spheres, &is_shape, &mut indices, quality);
+        }
+        bvh
+    }
+
+    /// Adds the subtree of the objects of `indices`, those of `is_shape`
+    /// being tested through the callback.
+    fn build(
+        &mut self,
+        spheres: &[Sphere],
+        is_shape: &[bool],
+        indices: &mut [usize],
+        quality: BvhQuality,
+    ) {
+        let bounds = indices.iter().fold(Aabb::empty(), |bounds, &index| {
+            bounds.merge(Aabb::of_sphere(&spheres[index]))
+        });
+        let nodes = match &mut self.nodes {
+            BvhNodes::Full(nodes) => nodes,
+            BvhNodes::Compact(_) => unreachable!("hierarchies are built in full precision"),
+        };
+        let node = nodes.len();
+
+        if indices.len() <= PACKET_WIDTH {
+            let (shapes, members): (Vec<usize>, Vec<usize>) =
+                indices.iter().partition(|&&index| is_shape[index]);
+            let mut lanes = [indices[0]; PACKET_WIDTH];
+            lanes[..members.len()].copy_from_slice(&members);
+            let members: Vec<Sphere> = members.iter().map(|&index| spheres[index]).collect();
+            nodes.push(BvhNode {
+                bounds,
+                index: self.packets.len() as u32,
+                is_leaf: true,
+            });
+            self.packets.push(SpherePacket::new(&members));
+            self.lanes.push(lanes);
+            self.shapes.push(shapes);
+            return;
+        }
+
+        nodes.push(BvhNode {
+            bounds,
+            index: 0,
+            is_leaf: false,
+        });
+        let middle = match quality {
+            BvhQuality::Median => median_split(spheres, indices),
+            BvhQuality::Sah => sah_split(spheres, indices),
+        };
+        let (first, second) = indices.split_at_mut(middle);
+        self.build(spheres, is_shape, first, quality);
+        let second_child = self.node_count() as u32;
+        if let BvhNodes::Full(nodes) = &mut self.nodes {
+            nodes[node].index = second_child;
+        }
+        self.build(spheres, is_shape, second, quality);
+    }
+
+    fn node_count(&self) -> usize {
+        match &self.nodes {
+            BvhNodes::Full(nodes) => nodes.len(),
+            BvhNodes::Compact(nodes) => nodes.len(),
+        }
+    }
+
+    /// Hierarchy with its boxes stored and traversed in `precision`.
+    pub fn with_precision(mut self, precision: Precision) -> Self {
+        self.nodes = match (self.nodes, precision) {
+            (BvhNodes::Full(nodes), Precision::Mixed) => {
+                BvhNodes::Compact(nodes.iter().map(BvhNode::compact).collect())
+            }
+            (BvhNodes::Compact(nodes), Precision::Full) => BvhNodes::Full(
+                nodes
+                    .iter()
+                    .map(|node| BvhNode {
+                        bounds: node.bounds.aabb(),
+                        index: node.index,
+                        is_leaf: node.is_leaf,
+                    })
+                    .collect(),
+            ),
+            (nodes, _) => nodes,
+        };
+        self
+    }
+
+    pub fn precision(&self) -> Precision {
+        match self.nodes {
+            BvhNodes::Full(_) => Precision::Full,
+            BvhNodes::Compact(_) => Precision::Mixed,
+        }
+    }
+
+    /// Closest hit of `ray` in (`t_min`, `t_max`), as the index of the sphere
+    /// and the ray parameter. The distances being those of `Sphere::hit`,
+    /// the sphere finds the same hit.
+    pub fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<(usize, Float)> {
+        self.hit_with(ray, t_min, t_max, |_, _| None)
+    }
+
+    /// Like `hit`, `test` giving the parameter at which `ray` hits an object
+    /// other than a sphere before the closest hit so far, its second
+    /// argument.
+    pub fn hit_with(
+        &self,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+        mut test: impl FnMut(usize, Float) -> Option<Float>,
+    ) -> Option<(usize, Float)> {
+        match &self.nodes {
+            BvhNodes::Full(nodes) => self.hit_nodes(nodes, ray, t_min, t_max, &mut test),
+            BvhNodes::Compact(nodes) => self.hit_nodes(nodes, ray, t_min, t_max, &mut test),
+        }
+    }
+
+    fn hit_nodes<B: NodeBounds>(
+        &self,
+        nodes: &[BvhNode<B>],
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+        test: &mut impl FnMut(usize, Float) -> Option<Float>,
+    ) -> Option<(usize, Float)> {
+        if nodes.is_empty() {
+            return None;
+        }
+
+        let mut closest: Option<(usize, Float)> = None;
+        let mut closest_t = t_max;
+
+        let node_ray = B::ray(ray);
+        let mut stack = Vec::with_capacity(64);
+        stack.push(0);
+        while let Some(index) = stack.pop() {
+            let node = &nodes[index];
+            if !node.bounds.hit(&node_ray, t_min, closest_t) {
+                continue;
+            }
+
+            let node_index = node.index as usize;
+            if node.is_leaf {
+                let distances = self.packets[node_index].hit_distances(ray, t_min, closest_t);
+                for (lane, &distance) in distances.iter().enumerate() {
+                    if distance < closest_t {
+                        closest_t = distance;
+                        closest = Some((self.lanes[node_index][lane], distance));
+                    }
+                }
+                for &shape in &self.shapes[node_index] {
+                    if let Some(distance) = test(shape, closest_t) {
+                        closest_t = distance;
+                        closest = Some((shape, distance));
+                    }
+                }
+            } else {
+                stack.push(node_index);
+                stack.push(index + 1);
+            }
+        }
+
+        closest
+    }
+
+    /// Indices of the objects that may hold `point`, each once: every object
+    /// whose bounding box holds it is among them, and callers test the
+    /// objects themselves.
+    pub fn candidates(&self, point: Vec3) -> Vec<usize> {
+        match &self.nodes {
+            BvhNodes::Full(nodes) => self.candidates_in(nodes, point),
+            BvhNodes::Compact(nodes) => self.candidates_in(nodes, point),
+        }
+    }
+
+    fn candidates_in<B: NodeBounds>(&self, nodes: &[BvhNode<B>], point: Vec3) -> Vec<usize> {
+        let mut candidates = Vec::new();
+        if nodes.is_empty() {
+            return candidates;
+        }
+
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &nodes[index];
+            if !node.bounds.aabb().contains(point) {
+                continue;
+            }
+
+            let node_index = node.index as usize;
+            if node.is_leaf {
+                // Unused lanes repeat the first object of the leaf.
+                for &object in self.lanes[node_index]
+                    .iter()
+                    .chain(&self.shapes[node_index])
+                {
+                    if !candidates.contains(&object) {
+                        candidates.push(object);
+                    }
+                }
+            } else {
+                stack.push(node_index);
+                stack.push(index + 1);
+            }
+        }
+
+        candidates
+    }
+
+    /// Closest hits of up to `RAY_PACKET_WIDTH` `rays`, each between `t_min`
+    /// and its own `t_max`, like `hit` gives them. The rays go through the
+    /// tree together: nodes are fetched once for all of them and their boxes
+    /// tested for every ray at once, which pays off when the rays are
+    /// coherent, like camera rays through the same pixel. Hierarchies in
+    /// mixed precision trace them one at a time.
+    pub fn hit_packet(
+        &self,
+        rays: &[Ray],
+        t_min: Float,
+        t_max: &[Float],
+    ) -> [Option<(usize, Float)>; RAY_PACKET_WIDTH] {
+        self.hit_packet_with(rays, t_min, t_max, |_, _, _| None)
+    }
+
+    /// Like `hit_packet`, `test` giving the parameter at which the ray of a
+    /// lane, its first argument, hits an object other than a sphere, like
+    /// for `hit_with`.
+    pub fn hit_packet_with(
+        &self,
+        rays: &[Ray],
+        t_min: Float,
+        t_max: &[Float],
+        mut test: impl FnMut(usize, usize, Float) -> Option<Float>,
+    ) -> [Option<(usize, Float)>; RAY_PACKET_WIDTH] {
+        assert!(rays.len() <= RAY_PACKET_WIDTH && t_max.len() == rays.len());
+        let mut closest = [None; RAY_PACKET_WIDTH];
+        let nodes = match &self.nodes {
+            BvhNodes::Full(nodes) => nodes,
+            BvhNodes::Compact(nodes) => {
+                for (lane, ray) in rays.iter().enumerate() {
+                    let mut test = |shape, closest_t| test(lane, shape, closest_t);
+                    closest[lane] = self.hit_nodes(nodes, ray, t_min, t_max[lane], &mut test);
+                }
+                return closest;
+            }
+        };
+        if nodes.is_empty() {
+            return closest;
+        }
+
+        // Rays laid out field by field. Unused lanes can't hit anything
+        // closer than minus infinity.
+        let mut origin = [[0.0; RAY_PACKET_WIDTH]; 3];
+        let mut inverse_dir = [[0.0; RAY_PACKET_WIDTH]; 3];
+        let mut closest_t = [Float::NEG_INFINITY; RAY_PACKET_WIDTH];
+        for (lane, ray) in rays.iter().enumerate() {
+            origin[0][lane] = ray.origin.x;
+            origin[1][lane] = ray.origin.y;
+            origin[2][lane] = ray.origin.z;
+            let inverse = ray.inverse_dir();
+            inverse_dir[0][lane] = inverse.x;
+            inverse_dir[1][lane] = inverse.y;
+            inverse_dir[2][lane] = inverse.z;
+            closest_t[lane] = t_max[lane];
+        }
+
+        let mut stack = Vec::with_capacity(64);
+        stack.push(0);
+        while let Some(index) = stack.pop() {
+            let node = &nodes[index];
+
+            // `Aabb::hit` for every lane.
+            let mut near = [t_min; RAY_PACKET_WIDTH];
+            let mut far = closest_t;
+            let bounds = [
+                (node.bounds.min.x, node.bounds.max.x),
+                (node.bounds.min.y, node.bounds.max.y),
+                (node.bounds.min.z, node.bounds.max.z),
+            ];
+            for (axis, &(min, max)) in bounds.iter().enumerate() {
+                for lane in 0..RAY_PACKET_WIDTH {
+                    let t0 = (min - origin[axis][lane]) * inverse_dir[axis][lane];
+                    let t1 = (max - origin[axis][lane]) * inverse_dir[axis][lane];
+                    near[lane] = near[lane].max(t0.min(t1));
+                    far[lane] = far[lane].min(t0.max(t1) * (1.0 + 4.0 * Float::EPSILON));
+                }
+            }
+            let mut active = [false; RAY_PACKET_WIDTH];
+            for lane in 0..RAY_PACKET_WIDTH {
+                active[lane] = near[lane] <= far[lane];
+            }
+            if !active.contains(&true) {
+                continue;
+            }
+
+            let node_index = node.index as usize;
+            if !node.is_leaf {
+                stack.push(node_index);
+                stack.push(index + 1);
+                continue;
+            }
+            let packet = &self.packets[node_index];
+            for (lane, ray) in rays.iter().enumerate() {
+                if !active[lane] {
+                    continue;
+                }
+                let distances = packet.hit_distances(ray, t_min, closest_t[lane]);
+                for (sphere_lane, &distance) in distances.iter().enumerate() {
+                    if distance < closest_t[lane] {
+                        closest_t[lane] = distance;
+                        closest[lane] = Some((self.lanes[node_index][sphere_lane], distance));
+                    }
+                }
+                for &shape in &self.shapes[node_index] {
+                    if let Some(distance) = test(lane, shape, closest_t[lane]) {
+                        closest_t[lane] = distance;
+                        closest[lane] = Some((shape, distance));
+                    }
+                }
+            }
+        }
+
+        closest
+    }
+}
+
+fn centroid_bounds(spheres: &[Sphere], indices: &[usize]) -> Aabb {
+    indices.iter().fold(Aabb::empty(), |bounds, &index| {
+        bounds.include(spheres[index].position)
+    })
+}
+
+fn axis(vector: Vec3, axis: usize) -> Float {
+    match axis {
+        0 => vector.x,
+        1 => vector.y,
+        _ => vector.z,
+    }
+}
+
+/// Axis along which the centers of the spheres spread the most.
+fn widest_axis(centroids: &Aabb) -> usize {
+    let size = centroids.max - centroids.min;
+    if size.x >= size.y && size.x >= size.z {
+        0
+    } else if size.y >= size.z {
+        1
+    } else {
+        2
+    }
+}
+
+/// Moves the half of `indices` with the lowest centers along the widest axis
+/// first, returning where the second half starts.
+fn median_split(spheres: &[Sphere], indices: &mut [usize]) -> usize {
+    let dimension = widest_axis(&centroid_bounds(spheres, indices));
+    let middle = indices.len() / 2;
+    indices.select_nth_unstable_by(middle, |&a, &b| {
+        let a = axis(spheres[a].position, dimension);
+        a.total_cmp(&axis(spheres[b].position, dimension))
+    });
+    middle
+}
+
+/// Moves the spheres of `indices` below the cheapest of `SAH_BINS` planes
+/// per axis first, returning where the others start. Falls back to the
+/// median when the centers are too close together to be told apart.
+fn sah_split(spheres: &[Sphere], indices: &mut [usize]) -> usize {
+    let centroids = centroid_bounds(spheres, indices);
+    let bin_of = |sphere: &Sphere, dimension: usize| {
+        let (min, max) = (
+            axis(centroids.min, dimension),
+            axis(centroids.max, dimension),
+        );
+        let bin = (axis(sphere.position, dimension) - min) / (max - min) * SAH_BINS as Float;
+        (bin as usize).min(SAH_BINS - 1)
+    };
+
+    let mut best: Option<(Float, usize, usize)> = None;
+    for dimension in 0..3 {
+        if axis(centroids.max, dimension) <= axis(centroids.min, dimension) {
+            continue;
+        }
+
+        let mut bins = [(Aabb::empty(), 0); SAH_BINS];
+        for &index in indices.iter() {
+            let bin = &mut bins[bin_of(&spheres[index], dimension)];
+            bin.0 = bin.0.merge(Aabb::of_sphere(&spheres[index]));
+            bin.1 += 1;
+        }
+
+        // Area and count below every plane, then the cost with those above.
+        let mut below = [(0.0, 0); SAH_BINS];
+        let mut bounds = Aabb::empty();
+        let mut count = 0;
+        for plane in 1..SAH_BINS {
+            bounds = bounds.merge(bins[plane - 1].0);
+            count += bins[plane - 1].1;
+            below[plane] = (bounds.surface_area(), count);
+        }
+        let (mut bounds, mut count) = (Aabb::empty(), 0);
+        for plane in (1..SAH_BINS).rev() {
+            bounds = bounds.merge(bins[plane].0);
+            count += bins[plane].1;
+            let (area_below, count_below) = below[plane];
+            if count == 0 || count_below == 0 {
+                continue;
+            }
+            let cost = area_below * count_below as Float + bounds.surface_area() * count as Float;
+            if best.is_none_or(|(best, _, _)| cost < best) {
+                best = Some((cost, dimension, plane));
+            }
+        }
+    }
+
+    let (_, dimension, plane) = match best {
+        Some(best) => best,
+        None => return median_split(spheres, indices),
+    };
+    let mut middle = 0;
+    for position in 0..indices.len() {
+        if bin_of(&spheres[indices[position]], dimension) < plane {
+            indices.swap(position, middle);
+            middle += 1;
+        }
+    }
+    middle
+}
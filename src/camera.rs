@@ -0,0 +1,480 @@
+use crate::content_hash::*;
+use crate::maths::*;
+use crate::ray::Ray;
+
+// #[derive(Copy, Clone)]
+// struct SimpleCamera {
+//     origin: Vec3,
+//     lower_left: Vec3,
+//     vertical: Vec3,
+//     horizontal: Vec3,
+// }
+
+// impl SimpleCamera {
+//     fn new(origin: Vec3, lower_left: Vec3, vertical: Vec3, horizontal: Vec3) -> Self {
+//         SimpleCamera {
+//             origin,
+//             lower_left,
+//             vertical,
+//             horizontal,
+//         }
+//     }
+
+//     fn get(self, u: Float, v: Float) -> Vec3 {
+//         self.lower_left + self.horizontal * u + self.vertical * v
+//     }
+
+//     fn get_ray(self, u: Float, v: Float) -> Ray {
+//         Ray::new(self.origin, self.get(u, v) - self.origin)
+//     }
+// }
+
+/// How the camera maps image positions to rays.
+#[derive(Copy, Clone, Debug)]
+pub enum Projection {
+    /// Thin lens perspective, using the field of view given to `Camera::new`.
+    Perspective,
+    /// Parallel rays, `height` being the vertical extent of the view in scene units.
+    Orthographic { height: Float },
+    /// Equidistant fisheye, `fov_degrees` being the angle covered by the image height.
+    Fisheye { fov_degrees: Float },
+    /// Full 360° by 180° panorama, longitude across the image width and
+    /// latitude across its height. Meant for 2:1 images.
+    Equirectangular,
+}
+
+/// Photographic exposure of the camera, turning the radiance reaching it,
+/// read as luminance in cd/m², into image values, 1 being the brightest white.
+/// Like a real camera, it takes the `iso` sensitivity of the sensor, the time
+/// the shutter is open, in seconds, and the f-number of the aperture, so
+/// lights set up in physical units get the brightness a photographer would
+/// expect.
+///
+/// Radiance is scaled by 1 / (1.2 * 2^EV100), following the saturation based
+/// sensitivity of ISO 12232 as in Lagarde and de Rousiers 2014, "Moving
+/// Frostbite to Physically Based Rendering". The f-number only sets the
+/// brightness, depth of field keeps following the aperture given to
+/// `Camera::new`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Exposure {
+    pub iso: Float,
+    pub shutter: Float,
+    pub f_number: Float,
+}
+
+impl Default for Exposure {
+    /// The "sunny 16" rule, ISO 100 at f/16 and 1/100 second, for daylight.
+    fn default() -> Self {
+        Exposure {
+            iso: 100.0,
+            shutter: 0.01,
+            f_number: 16.0,
+        }
+    }
+}
+
+impl Exposure {
+    /// Exposure value at ISO 100, 0 for f/1 and 1 second, every step halving
+    /// the light reaching the image.
+    pub fn ev100(&self) -> Float {
+        (self.f_number * self.f_number / self.shutter * 100.0 / self.iso).log2()
+    }
+
+    /// Factor from luminance to image values.
+    pub fn scale(&self) -> Float {
+        1.0 / (1.2 * self.ev100().exp2())
+    }
+
+    /// ISO 100 exposure at f/16 whose shutter time makes `scale` 2 to the
+    /// power `stops`.
+    pub fn from_stops(stops: Float) -> Self {
+        Exposure {
+            iso: 100.0,
+            shutter: 16.0 * 16.0 * 1.2 * stops.exp2(),
+            f_number: 16.0,
+        }
+    }
+}
+
+/// Where a camera stands and where it looks, for scenes coming with their
+/// own camera, like glTF files.
+#[derive(Copy, Clone, Debug)]
+pub struct CameraView {
+    pub look_from: Vec3,
+    pub look_at: Vec3,
+    pub up: Vec3,
+    /// Vertical field of view, in degrees.
+    pub vertical_fov: Float,
+}
+
+impl Default for CameraView {
+    /// View of the random scene.
+    fn default() -> Self {
+        CameraView {
+            look_from: Vec3::new(13.0, 2.0, 3.0),
+            look_at: Vec3::new(0.0, 0.0, 0.0),
+            up: Vec3::new(0.0, 1.0, 0.0),
+            vertical_fov: 20.0,
+        }
+    }
+}
+
+impl CameraView {
+    /// View from the camera turned by `degrees` around the `up` axis through
+    /// `look_at`, counterclockwise seen from above, at the same distance and
+    /// elevation, for turntables.
+    pub fn orbited(&self, degrees: Float) -> Self {
+        let axis = self.up.unit();
+        let offset = self.look_from - self.look_at;
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        // Rodrigues' rotation formula.
+        let turned =
+            offset * cos + axis.cross(offset) * sin + axis * (axis.dot(offset) * (1.0 - cos));
+        CameraView {
+            look_from: self.look_at + turned,
+            ..*self
+        }
+    }
+
+    /// View from the camera tilted by `degrees` around `look_at`, up over
+    /// what it looks at for positive angles, stopping a degree short of
+    /// looking straight down or up along `up`.
+    pub fn tilted(&self, degrees: Float) -> Self {
+        let axis = self.up.unit();
+        let offset = self.look_from - self.look_at;
+        let distance = offset.length();
+        let height = axis.dot(offset);
+        let across = offset - axis * height;
+        if across.length_squared() == 0.0 {
+            return *self;
+        }
+        let elevation = (height / distance).clamp(-1.0, 1.0).asin().to_degrees();
+        let elevation = (elevation + degrees).clamp(-89.0, 89.0).to_radians();
+        let (sin, cos) = elevation.sin_cos();
+        CameraView {
+            look_from: self.look_at + (across.unit() * cos + axis * sin) * distance,
+            ..*self
+        }
+    }
+
+    /// View from the camera stepped `forward` and to the `right`, in scene
+    /// units, along the ground square to `up`, still looking the same way.
+    pub fn walked(&self, forward: Float, right: Float) -> Self {
+        let axis = self.up.unit();
+        let ahead = self.look_at - self.look_from;
+        let ahead = ahead - axis * axis.dot(ahead);
+        if ahead.length_squared() == 0.0 {
+            return *self;
+        }
+        let ahead = ahead.unit();
+        let step = ahead * forward + ahead.cross(axis) * right;
+        CameraView {
+            look_from: self.look_from + step,
+            look_at: self.look_at + step,
+            ..*self
+        }
+    }
+}
+
+/// Change of view asked for by the keys or mouse of an interactive viewer.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Navigation {
+    /// Steps, as fractions of the distance to what the camera looks at,
+    /// see `CameraView::walked`.
+    Walk { forward: Float, right: Float },
+    /// Turn around what the camera looks at, in degrees, see
+    /// `CameraView::orbited` and `CameraView::tilted`.
+    Orbit { yaw: Float, pitch: Float },
+}
+
+impl Navigation {
+    /// Navigation of a key: W, A, S and D walk a tenth of the distance to
+    /// what the camera looks at, and I, J, K and L orbit by 5 degrees, for
+    /// viewers without a mouse.
+    pub fn from_key(key: char) -> Option<Self> {
+        let walk = |forward, right| Some(Navigation::Walk { forward, right });
+        let orbit = |yaw, pitch| Some(Navigation::Orbit { yaw, pitch });
+        match key.to_ascii_lowercase() {
+            'w' => walk(0.1, 0.0),
+            's' => walk(-0.1, 0.0),
+            'a' => walk(0.0, -0.1),
+            'd' => walk(0.0, 0.1),
+            'i' => orbit(0.0, 5.0),
+            'k' => orbit(0.0, -5.0),
+            'j' => orbit(-5.0, 0.0),
+            'l' => orbit(5.0, 0.0),
+            _ => None,
+        }
+    }
+
+    /// Orbit of a mouse drag by `dx` and `dy` pixels, right and down, over
+    /// an image `height` pixels high seen through `view`, turning the camera
+    /// so what is under the mouse follows it.
+    pub fn from_drag(dx: Float, dy: Float, height: usize, view: &CameraView) -> Self {
+        let degrees_per_pixel = view.vertical_fov / height as Float;
+        Navigation::Orbit {
+            yaw: -dx * degrees_per_pixel,
+            pitch: dy * degrees_per_pixel,
+        }
+    }
+
+    /// `view` changed by the navigation.
+    pub fn apply(&self, view: &CameraView) -> CameraView {
+        match *self {
+            Navigation::Walk { forward, right } => {
+                let distance = (view.look_at - view.look_from).length();
+                view.walked(forward * distance, right * distance)
+            }
+            Navigation::Orbit { yaw, pitch } => view.orbited(yaw).tilted(pitch),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Camera {
+    origin: Vec3,
+    lower_left: Vec3,
+    vertical: Vec3,
+    horizontal: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    aspect: Float,
+    lens_radius: Float,
+    projection: Projection,
+    exposure: Option<Exposure>,
+}
+
+impl Camera {
+    pub fn new(
+        lookfrom: Vec3,
+        lookat: Vec3,
+        vup: Vec3,
+        vertical_fov_degrees: Float,
+        aspect: Float,
+        aperture: Float,
+        focus_dist: Float,
+    ) -> Self {
+        let origin = lookfrom;
+        let lens_radius = aperture / 2.0;
+
+        let theta = deg_to_rad(vertical_fov_degrees);
+        let half_height = Float::tan(theta / 2.0);
+        let half_width = aspect * half_height;
+
+        let w = (lookfrom - lookat).unit();
+        let u = (vup.cross(w)).unit();
+
+        let v = w.cross(u);
+
+        let lower_left =
+            origin - half_width * focus_dist * u - half_height * focus_dist * v - focus_dist * w;
+
+        let horizontal = 2.0 * half_width * focus_dist * u;
+        let vertical = 2.0 * half_height * focus_dist * v;
+
+        Camera {
+            origin,
+            lower_left,
+            vertical,
+            horizontal,
+            u,
+            v,
+            w,
+            aspect,
+            lens_radius,
+            projection: Projection::Perspective,
+            exposure: None,
+        }
+    }
+
+    pub fn with_projection(self, projection: Projection) -> Self {
+        Camera { projection, ..self }
+    }
+
+    /// Camera scaling the images it renders by `exposure`. Without one,
+    /// images show radiance as it is.
+    pub fn with_exposure(self, exposure: Exposure) -> Self {
+        Camera {
+            exposure: Some(exposure),
+            ..self
+        }
+    }
+
+    pub fn exposure(&self) -> Option<Exposure> {
+        self.exposure
+    }
+
+    /// Moves and reorients the camera, keeping its field of view, aperture,
+    /// focus distance and projection.
+    pub fn update_view(&mut self, lookfrom: Vec3, lookat: Vec3, vup: Vec3) {
+        let center = self.lower_left + self.horizontal / 2.0 + self.vertical / 2.0;
+        let focus_dist = (center - self.origin).length();
+        let half_width = self.horizontal.length() / 2.0;
+        let half_height = self.vertical.length() / 2.0;
+
+        self.origin = lookfrom;
+        self.w = (lookfrom - lookat).unit();
+        self.u = (vup.cross(self.w)).unit();
+        self.v = self.w.cross(self.u);
+
+        self.horizontal = 2.0 * half_width * self.u;
+        self.vertical = 2.0 * half_height * self.v;
+        self.lower_left =
+            self.origin - half_width * self.u - half_height * self.v - focus_dist * self.w;
+    }
+
+    /// Ray through image position (`s`, `t`), (0, 0) being the bottom left
+    /// corner, leaving the lens at the point mapped from `lens`, a point of
+    /// the unit square whose center is the middle of the lens.
+    /// Width of the image over its height.
+    pub fn aspect_ratio(&self) -> Float {
+        self.aspect
+    }
+
+    pub fn get_ray(self, s: Float, t: Float, lens: (Float, Float)) -> Ray {
+        // Image coordinates centered on the view direction, y spanning [-1, 1].
+        let x = (2.0 * s - 1.0) * self.aspect;
+        let y = 2.0 * t - 1.0;
+
+        match self.projection {
+            Projection::Perspective => {
+                let rd: Vec3 = self.lens_radius * sample_unit_disk(lens);
+                let offset = self.u * rd.x + self.v * rd.y;
+
+                Ray::new(
+                    self.origin + offset,
+                    self.lower_left + self.horizontal * s + self.vertical * t
+                        - self.origin
+                        - offset,
+                )
+            }
+            Projection::Orthographic { height } => {
+                let half_height = height / 2.0;
+                Ray::new(
+                    self.origin + self.u * (x * half_height) + self.v * (y * half_height),
+                    -self.w,
+                )
+            }
+            Projection::Fisheye { fov_degrees } => {
+                // The angle from the view direction grows linearly with the
+                // distance to the image center.
+                let r = Float::sqrt(x * x + y * y);
+                let theta = Float::min(r * deg_to_rad(fov_degrees) / 2.0, consts::PI);
+                let phi = y.atan2(x);
+
+                let direction = self.u * (theta.sin() * phi.cos())
+                    + self.v * (theta.sin() * phi.sin())
+                    - self.w * theta.cos();
+                Ray::new(self.origin, direction)
+            }
+            Projection::Equirectangular => {
+                // The view direction sits in the middle of the image.
+                let longitude = (s - 0.5) * 2.0 * consts::PI;
+                let latitude = (t - 0.5) * consts::PI;
+
+                let direction = latitude.cos()
+                    * (self.u * longitude.sin() - self.w * longitude.cos())
+                    + self.v * latitude.sin();
+                Ray::new(self.origin, direction)
+            }
+        }
+    }
+
+    /// Image position (`s`, `t`) whose ray leaves the middle of the lens
+    /// towards `point`, as `get_ray` takes them. `None` for points behind
+    /// perspective cameras or in the blind spot of fisheyes.
+    pub fn project(&self, point: Vec3) -> Option<(Float, Float)> {
+        let offset = point - self.origin;
+        match self.projection {
+            Projection::Orthographic { height } => {
+                let half_height = height / 2.0;
+                let x = offset.dot(self.u) / half_height;
+                let y = offset.dot(self.v) / half_height;
+                Some(((x / self.aspect + 1.0) / 2.0, (y + 1.0) / 2.0))
+            }
+            _ => self.project_direction(offset),
+        }
+    }
+
+    /// Image position of the environment seen along `direction`, like
+    /// `project` gives it for points infinitely far away. `None` for
+    /// orthographic cameras, which see all of it in every direction.
+    pub fn project_direction(&self, direction: Vec3) -> Option<(Float, Float)> {
+        let (x, y, z) = (
+            direction.dot(self.u),
+            direction.dot(self.v),
+            -direction.dot(self.w),
+        );
+        match self.projection {
+            Projection::Perspective => {
+                if z <= 0.0 {
+                    return None;
+                }
+                let (width, height) = (self.horizontal.length(), self.vertical.length());
+                let focus_dist = (self.lower_left + self.horizontal / 2.0 + self.vertical / 2.0
+                    - self.origin)
+                    .length();
+                let scale = focus_dist / z;
+                Some((0.5 + x * scale / width, 0.5 + y * scale / height))
+            }
+            Projection::Orthographic { .. } => None,
+            Projection::Fisheye { fov_degrees } => {
+                let theta = Float::atan2(Float::sqrt(x * x + y * y), z);
+                if theta >= consts::PI {
+                    return None;
+                }
+                let r = theta * 2.0 / deg_to_rad(fov_degrees);
+                let phi = y.atan2(x);
+                let (x, y) = (r * phi.cos(), r * phi.sin());
+                Some(((x / self.aspect + 1.0) / 2.0, (y + 1.0) / 2.0))
+            }
+            Projection::Equirectangular => {
+                let longitude = x.atan2(z);
+                let latitude = (y / direction.length()).asin();
+                Some((
+                    longitude / (2.0 * consts::PI) + 0.5,
+                    latitude / consts::PI + 0.5,
+                ))
+            }
+        }
+    }
+}
+
+impl ContentHash for Camera {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        for vector in &[
+            self.origin,
+            self.lower_left,
+            self.vertical,
+            self.horizontal,
+            self.u,
+            self.v,
+            self.w,
+        ] {
+            vector.content_hash(hasher);
+        }
+        hasher.write_float(self.aspect);
+        hasher.write_float(self.lens_radius);
+        match self.projection {
+            Projection::Perspective => hasher.write_str("perspective"),
+            Projection::Orthographic { height } => {
+                hasher.write_str("orthographic");
+                hasher.write_float(height);
+            }
+            Projection::Fisheye { fov_degrees } => {
+                hasher.write_str("fisheye");
+                hasher.write_float(fov_degrees);
+            }
+            Projection::Equirectangular => hasher.write_str("equirectangular"),
+        }
+        // Left out when unset, so images rendered before exposures existed
+        // stay up to date.
+        if let Some(exposure) = self.exposure {
+            hasher.write_str("exposure");
+            hasher.write_float(exposure.iso);
+            hasher.write_float(exposure.shutter);
+            hasher.write_float(exposure.f_number);
+        }
+    }
+}
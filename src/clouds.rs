@@ -0,0 +1,304 @@
+use crate::content_hash::*;
+use crate::environment::Environment;
+use crate::maths::consts::PI;
+use crate::maths::*;
+use crate::ray::Ray;
+
+/// Steps of the march through background clouds, and from each of them
+/// towards the sun.
+const VIEW_STEPS: usize = 48;
+const LIGHT_STEPS: usize = 6;
+
+/// Layers of noise shaping the clouds.
+const OCTAVES: u32 = 5;
+
+/// How rays meet a layer of `Clouds`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CloudMode {
+    /// Drawn over the environment behind rays escaping the scene, by ray
+    /// marching, lit by the sun of the sky and the sky above them. Cheap,
+    /// but the clouds cast no shadows and hide nothing inside the layer.
+    Background,
+    /// Medium every ray is tracked through, scattering off the clouds or
+    /// going through them, so they shadow the scene under them.
+    Medium,
+}
+
+/// Layer of clouds between two heights, going on in every horizontal
+/// direction, their density shaped by fractal noise. Droplets scatter light
+/// mostly forward, following the Henyey-Greenstein phase function.
+///
+/// Rays are followed through the layer by delta tracking against the
+/// densest the clouds get, and shadow rays see through it by ratio
+/// tracking, both unbiased however the density varies.
+#[derive(Clone, Debug)]
+pub struct Clouds {
+    /// Heights of the base and the top of the layer, in scene units.
+    pub bottom: Float,
+    pub top: Float,
+    /// Share of the sky the clouds roughly cover, from 0 to 1.
+    pub coverage: Float,
+    /// Chance per scene unit of meeting a droplet in the thickest parts of
+    /// the clouds.
+    pub density: Float,
+    /// Size of the largest clouds, in scene units.
+    pub feature_size: Float,
+    /// Share of the light droplets scatter rather than absorb.
+    pub albedo: Float,
+    /// Mean cosine of the scattering angle, from -1, back towards the
+    /// light, to 1, on along its way.
+    pub anisotropy: Float,
+    /// Farthest rays are followed through the layer from their origin, in
+    /// scene units, as the layer never ends.
+    pub range: Float,
+    pub seed: u64,
+    pub mode: CloudMode,
+}
+
+impl Clouds {
+    /// Layer from `bottom` to `top` covering about `coverage` of the sky,
+    /// with clouds about four times as wide as the layer is thick.
+    pub fn new(
+        bottom: Float,
+        top: Float,
+        coverage: Float,
+        density: Float,
+        mode: CloudMode,
+    ) -> Self {
+        let thickness = top - bottom;
+        Clouds {
+            bottom,
+            top,
+            coverage,
+            density,
+            feature_size: 4.0 * thickness,
+            albedo: 0.99,
+            anisotropy: 0.6,
+            range: 50.0 * thickness,
+            seed: 0,
+            mode,
+        }
+    }
+
+    /// Chance per scene unit of meeting a droplet at `point`, thinning
+    /// towards the base and the top of the layer.
+    pub fn density_at(&self, point: Vec3) -> Float {
+        if point.y <= self.bottom || point.y >= self.top || self.coverage <= 0.0 {
+            return 0.0;
+        }
+        let height = (point.y - self.bottom) / (self.top - self.bottom);
+        let profile = 4.0 * height * (1.0 - height);
+        let p = point / self.feature_size;
+        let noise = fractal_noise_3d(self.seed, p, OCTAVES);
+        let cover = clamp((noise - (1.0 - self.coverage)) / self.coverage, 0.0, 1.0);
+        self.density * profile * cover
+    }
+
+    /// Range of parameters of `ray` inside the layer, between `t_min` and
+    /// `t_max` and within `range` of the origin.
+    fn span(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<(Float, Float)> {
+        let (mut start, mut end) = (t_min, t_max.min(self.range / ray.dir.length()));
+        if ray.dir.y == 0.0 {
+            if ray.origin.y <= self.bottom || ray.origin.y >= self.top {
+                return None;
+            }
+        } else {
+            let a = (self.bottom - ray.origin.y) / ray.dir.y;
+            let b = (self.top - ray.origin.y) / ray.dir.y;
+            start = start.max(a.min(b));
+            end = end.min(a.max(b));
+        }
+        if start < end && self.density > 0.0 {
+            Some((start, end))
+        } else {
+            None
+        }
+    }
+
+    /// Parameter where `ray` meets a droplet between `t_min` and `t_max`,
+    /// `None` when it goes through.
+    pub fn sample_collision(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<Float> {
+        let (mut t, end) = self.span(ray, t_min, t_max)?;
+        // Extinction of the densest clouds per unit of the ray parameter.
+        let majorant = self.density * ray.dir.length();
+        loop {
+            t -= (1.0 - random_01()).ln() / majorant;
+            if t >= end {
+                return None;
+            }
+            if random_01() * self.density < self.density_at(ray.at(t)) {
+                return Some(t);
+            }
+        }
+    }
+
+    /// Share of the light going through the clouds along `ray` between
+    /// `t_min` and `t_max`, estimated without bias.
+    pub fn transmittance(&self, ray: &Ray, t_min: Float, t_max: Float) -> Float {
+        let (mut t, end) = match self.span(ray, t_min, t_max) {
+            Some(span) => span,
+            None => return 1.0,
+        };
+        let majorant = self.density * ray.dir.length();
+        let mut transmittance = 1.0;
+        loop {
+            t -= (1.0 - random_01()).ln() / majorant;
+            if t >= end {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.density_at(ray.at(t)) / self.density;
+            // Russian roulette ends the walks that hardly carry any light.
+            if transmittance < 0.1 {
+                if random_01() < 0.5 {
+                    return 0.0;
+                }
+                transmittance *= 2.0;
+            }
+        }
+    }
+
+    /// Density of droplets scattering light by an angle of cosine `cos`.
+    pub fn phase(&self, cos: Float) -> Float {
+        let g = self.anisotropy;
+        let denominator = 1.0 + g * g - 2.0 * g * cos;
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+
+    /// Direction light going along `direction` is scattered towards, from a
+    /// uniform point `(u1, u2)` of the unit square, following `phase`.
+    pub fn sample_phase(&self, direction: Vec3, (u1, u2): (Float, Float)) -> Vec3 {
+        let g = self.anisotropy;
+        let cos = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u1
+        } else {
+            let ratio = (1.0 - g * g) / (1.0 - g + 2.0 * g * u1);
+            clamp((1.0 + g * g - ratio * ratio) / (2.0 * g), -1.0, 1.0)
+        };
+        let sin = Float::sqrt((1.0 - cos * cos).max(0.0));
+        let phi = 2.0 * PI * u2;
+        Onb::from_w(direction.unit()).local(Vec3::new(phi.cos() * sin, phi.sin() * sin, cos))
+    }
+
+    /// `background`, the radiance `ray` escaping the scene gets from the
+    /// environment, seen through the clouds, which scatter the light of the
+    /// sun and of the sky above towards the ray.
+    pub fn over_background(&self, ray: &Ray, background: Vec3, environment: &Environment) -> Vec3 {
+        let (start, end) = match self.span(ray, 0.0, Float::INFINITY) {
+            Some(span) => span,
+            None => return background,
+        };
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let (sun, ambient) = match environment.sun() {
+            Some(sky) => {
+                let direction = sky.sun_direction();
+                let radiance = sky.radiance(direction) - sky.sky_radiance(direction);
+                // Radiance over the solid angle of the disk.
+                let irradiance = radiance / sky.sun_pdf(direction);
+                (Some((direction, irradiance)), sky.sky_radiance(up))
+            }
+            None => (None, environment.radiance(up)),
+        };
+
+        let direction = ray.dir.unit();
+        let step = (end - start) / VIEW_STEPS as Float;
+        let length = step * ray.dir.length();
+        let mut transmittance = 1.0;
+        let mut scattered = Vec3::new(0.0, 0.0, 0.0);
+        for index in 0..VIEW_STEPS {
+            let point = ray.at(start + (index as Float + 0.5) * step);
+            let density = self.density_at(point);
+            if density <= 0.0 {
+                continue;
+            }
+            let mut light = ambient;
+            if let Some((sun_direction, irradiance)) = sun {
+                let depth = self.optical_depth(point, sun_direction);
+                let phase = self.phase(direction.dot(sun_direction));
+                light += irradiance * ((-depth).exp() * phase);
+            }
+            scattered += light * (transmittance * density * self.albedo * length);
+            transmittance *= (-density * length).exp();
+            if transmittance < 1e-4 {
+                break;
+            }
+        }
+        background * transmittance + scattered
+    }
+
+    /// Density integrated from `point` out of the layer along `direction`,
+    /// marched in a few steps.
+    fn optical_depth(&self, point: Vec3, direction: Vec3) -> Float {
+        let ray = Ray::new(point, direction);
+        let (start, end) = match self.span(&ray, 0.0, Float::INFINITY) {
+            Some(span) => span,
+            None => return 0.0,
+        };
+        let step = (end - start) / LIGHT_STEPS as Float;
+        (0..LIGHT_STEPS)
+            .map(|index| self.density_at(ray.at(start + (index as Float + 0.5) * step)) * step)
+            .sum()
+    }
+
+    /// Converts the lengths of the layer, scaling them by `factor`.
+    pub fn scale(&mut self, factor: Float) {
+        self.bottom *= factor;
+        self.top *= factor;
+        self.feature_size *= factor;
+        self.range *= factor;
+        self.density /= factor;
+    }
+}
+
+impl ContentHash for Clouds {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        for value in [
+            self.bottom,
+            self.top,
+            self.coverage,
+            self.density,
+            self.feature_size,
+            self.albedo,
+            self.anisotropy,
+            self.range,
+        ] {
+            hasher.write_float(value);
+        }
+        hasher.write_u64(self.seed);
+        hasher.write_u64(self.mode as u64);
+    }
+}
+
+/// Fractal value noise at `point`, from 0 to 1, like `fractal_noise` but in
+/// three dimensions.
+fn fractal_noise_3d(seed: u64, point: Vec3, octaves: u32) -> Float {
+    let lattice = |octave: u32, i: i64, j: i64, k: i64| {
+        let cell = mix_seed(mix_seed(i as u64, j as u64), k as u64);
+        uniform_from_bits(mix_seed(mix_seed(seed, octave as u64), cell))
+    };
+    let smooth = |t: Float| t * t * (3.0 - 2.0 * t);
+    let lerp = |a: Float, b: Float, t: Float| a * (1.0 - t) + b * t;
+
+    let (mut sum, mut total) = (0.0, 0.0);
+    let (mut frequency, mut amplitude) = (1.0, 1.0);
+    for octave in 0..octaves {
+        let p = point * frequency;
+        let (i, j, k) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (tx, ty, tz) = (smooth(p.x - i), smooth(p.y - j), smooth(p.z - k));
+        let (i, j, k) = (i as i64, j as i64, k as i64);
+        let corner = |di: i64, dj: i64, dk: i64| lattice(octave, i + di, j + dj, k + dk);
+        let layer = |dk: i64| {
+            let bottom = lerp(corner(0, 0, dk), corner(1, 0, dk), tx);
+            let top = lerp(corner(0, 1, dk), corner(1, 1, dk), tx);
+            lerp(bottom, top, ty)
+        };
+        sum += lerp(layer(0), layer(1), tz) * amplitude;
+        total += amplitude;
+        frequency *= 2.0;
+        amplitude *= 0.5;
+    }
+    if total > 0.0 {
+        sum / total
+    } else {
+        0.0
+    }
+}
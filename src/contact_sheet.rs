@@ -0,0 +1,199 @@
+use crate::maths::*;
+
+/// Linear gray behind the cells and their labels.
+const BACKGROUND: Float = 0.02;
+
+/// Rows of the glyphs of the label font, top to bottom, 3 pixels wide.
+const GLYPH_HEIGHT: usize = 5;
+const GLYPH_WIDTH: usize = 3;
+
+/// Grid of images of the same size, each with a label under it, assembled
+/// into a single image to compare renders side by side, like the frames of
+/// a sweep.
+///
+/// Images are linear, premultiplied RGBA like the ones renderers return.
+/// Labels are drawn in a small uppercase pixel font, so lowercase letters
+/// show as uppercase and characters it lacks as `?`.
+#[derive(Clone, Debug)]
+pub struct ContactSheet {
+    cell_width: usize,
+    cell_height: usize,
+    /// Number of cells per row, 0 to make the grid about square.
+    columns: usize,
+    cells: Vec<(Vec<Float>, String)>,
+}
+
+impl ContactSheet {
+    /// Empty sheet of `cell_width` x `cell_height` images.
+    pub fn new(cell_width: usize, cell_height: usize) -> Self {
+        ContactSheet {
+            cell_width,
+            cell_height,
+            columns: 0,
+            cells: Vec::new(),
+        }
+    }
+
+    pub fn with_columns(mut self, columns: usize) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    /// Adds the RGBA `pixels` of an image, after the ones already added.
+    pub fn add(&mut self, pixels: &[Float], label: &str) {
+        assert_eq!(pixels.len(), self.cell_width * self.cell_height * 4);
+        self.cells.push((pixels.to_vec(), label.to_string()));
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// Number of cells per row and rows.
+    pub fn grid(&self) -> (usize, usize) {
+        let count = self.cells.len().max(1);
+        let columns = if self.columns > 0 {
+            self.columns.min(count)
+        } else {
+            (count as Float).sqrt().ceil() as usize
+        };
+        (columns, count.div_ceil(columns))
+    }
+
+    /// Size of the font pixels, growing with the cells so labels stay
+    /// readable next to large images.
+    fn scale(&self) -> usize {
+        (self.cell_height / 64).clamp(1, 4)
+    }
+
+    /// Gap between cells and around the sheet, in pixels.
+    fn margin(&self) -> usize {
+        4 * self.scale()
+    }
+
+    /// Height of the strip under each cell holding its label.
+    fn label_height(&self) -> usize {
+        (GLYPH_HEIGHT + 2) * self.scale()
+    }
+
+    /// Position of the top left corner of cell `index`.
+    pub fn cell_position(&self, index: usize) -> (usize, usize) {
+        let (columns, _) = self.grid();
+        let margin = self.margin();
+        let (column, row) = (index % columns, index / columns);
+        (
+            margin + column * (self.cell_width + margin),
+            margin + row * (self.cell_height + self.label_height() + margin),
+        )
+    }
+
+    /// Width and height of the assembled sheet.
+    pub fn size(&self) -> (usize, usize) {
+        let (columns, rows) = self.grid();
+        let margin = self.margin();
+        (
+            margin + columns * (self.cell_width + margin),
+            margin + rows * (self.cell_height + self.label_height() + margin),
+        )
+    }
+
+    /// Assembles the sheet, returning its RGBA pixels, opaque outside the
+    /// cells.
+    pub fn compose(&self) -> Vec<Float> {
+        let (width, height) = self.size();
+        let mut pixels = [BACKGROUND, BACKGROUND, BACKGROUND, 1.0].repeat(width * height);
+
+        for (index, (cell, label)) in self.cells.iter().enumerate() {
+            let (x, y) = self.cell_position(index);
+            for row in 0..self.cell_height {
+                let source = row * self.cell_width * 4;
+                let target = ((y + row) * width + x) * 4;
+                pixels[target..target + self.cell_width * 4]
+                    .copy_from_slice(&cell[source..source + self.cell_width * 4]);
+            }
+
+            let scale = self.scale();
+            let top = y + self.cell_height + scale;
+            let characters = (self.cell_width + scale) / ((GLYPH_WIDTH + 1) * scale);
+            for (position, character) in label.chars().take(characters).enumerate() {
+                let left = x + position * (GLYPH_WIDTH + 1) * scale;
+                for (glyph_y, bits) in glyph(character).iter().enumerate() {
+                    for glyph_x in 0..GLYPH_WIDTH {
+                        if (bits >> (GLYPH_WIDTH - 1 - glyph_x)) & 1 == 0 {
+                            continue;
+                        }
+                        for dy in 0..scale {
+                            let row = (top + glyph_y * scale + dy) * width;
+                            let start = (row + left + glyph_x * scale) * 4;
+                            for pixel in pixels[start..start + scale * 4].chunks_mut(4) {
+                                pixel.copy_from_slice(&[1.0, 1.0, 1.0, 1.0]);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        pixels
+    }
+}
+
+/// Rows of `character` in the label font, the leftmost pixel in the highest
+/// of the 3 bits.
+fn glyph(character: char) -> [u8; GLYPH_HEIGHT] {
+    match character.to_ascii_uppercase() {
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b011, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        '(' => [0b010, 0b100, 0b100, 0b100, 0b010],
+        ')' => [0b010, 0b001, 0b001, 0b001, 0b010],
+        _ => [0b111, 0b001, 0b010, 0b000, 0b010],
+    }
+}
@@ -0,0 +1,585 @@
+use crate::camera::Camera;
+use crate::environment::Environment;
+use crate::film::Filter;
+use crate::hitable::Hitable;
+use crate::light::DeltaLight;
+use crate::material::{Fresnel, MaterialId, MaterialType};
+use crate::maths::*;
+use crate::render::RenderSettings;
+use crate::sampler::SamplerType;
+use crate::scene::{ObjectId, Scene};
+use crate::sdf::DistanceFunction;
+use crate::shape::Shape;
+
+/// 64-bit FNV-1a hasher. Unlike `std::hash::Hasher` implementations, its
+/// results don't change between runs, platforms or compiler versions, so
+/// they can be stored in files and compared later.
+#[derive(Clone, Copy, Debug)]
+pub struct ContentHasher(u64);
+
+impl Default for ContentHasher {
+    fn default() -> Self {
+        ContentHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl ContentHasher {
+    pub fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    /// Hashes the bits of `value`, with both zeros hashing alike.
+    pub fn write_float(&mut self, value: Float) {
+        let value = if value == 0.0 { 0.0 } else { value };
+        self.write(&value.to_le_bytes());
+    }
+
+    pub fn write_str(&mut self, value: &str) {
+        self.write_u64(value.len() as u64);
+        self.write(value.as_bytes());
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Values hashed by what they contain, see `ContentHasher`.
+pub trait ContentHash {
+    fn content_hash(&self, hasher: &mut ContentHasher);
+
+    /// Hash of the value alone.
+    fn content_digest(&self) -> u64 {
+        let mut hasher = ContentHasher::default();
+        self.content_hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+impl ContentHash for Vec3 {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        hasher.write_float(self.x);
+        hasher.write_float(self.y);
+        hasher.write_float(self.z);
+    }
+}
+
+impl<T: ContentHash> ContentHash for [T] {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        hasher.write_u64(self.len() as u64);
+        for item in self {
+            item.content_hash(hasher);
+        }
+    }
+}
+
+impl ContentHash for MaterialType {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        match self {
+            MaterialType::Lambertian { albedo } => {
+                hasher.write_str("lambertian");
+                albedo.content_hash(hasher);
+            }
+            MaterialType::Metal { albedo, fuzziness } => {
+                hasher.write_str("metal");
+                albedo.content_hash(hasher);
+                hasher.write_float(*fuzziness);
+            }
+            MaterialType::Dialectric { refractive_index } => {
+                hasher.write_str("dielectric");
+                hasher.write_float(*refractive_index);
+            }
+            MaterialType::DiffuseLight { emit } => {
+                hasher.write_str("diffuse_light");
+                emit.content_hash(hasher);
+            }
+            MaterialType::Microfacet { roughness, fresnel } => {
+                hasher.write_str("microfacet");
+                hasher.write_float(*roughness);
+                match fresnel {
+                    Fresnel::Conductor { f0 } => {
+                        hasher.write_str("conductor");
+                        f0.content_hash(hasher);
+                    }
+                    Fresnel::Dielectric { refractive_index } => {
+                        hasher.write_str("dielectric");
+                        hasher.write_float(*refractive_index);
+                    }
+                }
+            }
+            MaterialType::AnisotropicMetal {
+                f0,
+                roughness_u,
+                roughness_v,
+                rotation,
+            } => {
+                hasher.write_str("anisotropic_metal");
+                f0.content_hash(hasher);
+                hasher.write_float(*roughness_u);
+                hasher.write_float(*roughness_v);
+                hasher.write_float(*rotation);
+            }
+            MaterialType::Subsurface {
+                albedo,
+                radius,
+                refractive_index,
+            } => {
+                hasher.write_str("subsurface");
+                albedo.content_hash(hasher);
+                radius.content_hash(hasher);
+                hasher.write_float(*refractive_index);
+            }
+            MaterialType::ThinGlass {
+                refractive_index,
+                tint,
+            } => {
+                hasher.write_str("thin_glass");
+                hasher.write_float(*refractive_index);
+                tint.content_hash(hasher);
+            }
+            MaterialType::Principled(principled) => {
+                hasher.write_str("principled");
+                principled.base_color.content_hash(hasher);
+                for value in &[
+                    principled.metallic,
+                    principled.roughness,
+                    principled.specular,
+                    principled.sheen,
+                    principled.clearcoat,
+                    principled.transmission,
+                ] {
+                    hasher.write_float(*value);
+                }
+                principled.emission.content_hash(hasher);
+                if let Some(textures) = &principled.textures {
+                    for texture in [
+                        &textures.base_color,
+                        &textures.metallic_roughness,
+                        &textures.emission,
+                    ] {
+                        hasher.write_u64(texture.is_some() as u64);
+                        if let Some(texture) = texture {
+                            texture.content_hash(hasher);
+                        }
+                    }
+                }
+            }
+            MaterialType::Measured(brdf) => {
+                hasher.write_str("measured");
+                brdf.content_hash(hasher);
+            }
+            MaterialType::Hair(hair) => {
+                hasher.write_str("hair");
+                hair.color.content_hash(hasher);
+                for value in &[
+                    hair.longitudinal_roughness,
+                    hair.azimuthal_roughness,
+                    hair.scale_angle,
+                    hair.refractive_index,
+                ] {
+                    hasher.write_float(*value);
+                }
+            }
+        }
+    }
+}
+
+impl ContentHash for DeltaLight {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        match self {
+            DeltaLight::Point {
+                position,
+                intensity,
+            } => {
+                hasher.write_str("point");
+                position.content_hash(hasher);
+                intensity.content_hash(hasher);
+            }
+            DeltaLight::Directional {
+                direction,
+                irradiance,
+            } => {
+                hasher.write_str("directional");
+                direction.content_hash(hasher);
+                irradiance.content_hash(hasher);
+            }
+            DeltaLight::Spot {
+                position,
+                direction,
+                intensity,
+                inner_angle,
+                outer_angle,
+            } => {
+                hasher.write_str("spot");
+                position.content_hash(hasher);
+                direction.content_hash(hasher);
+                intensity.content_hash(hasher);
+                hasher.write_float(*inner_angle);
+                hasher.write_float(*outer_angle);
+            }
+        }
+    }
+}
+
+/// Materials are left out, being hashed with the bounding spheres.
+impl ContentHash for Shape {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        match self {
+            Shape::Plane(plane) => {
+                hasher.write_str("plane");
+                plane.point.content_hash(hasher);
+                plane.normal.content_hash(hasher);
+            }
+            Shape::Disk(disk) => {
+                hasher.write_str("disk");
+                disk.center.content_hash(hasher);
+                disk.normal.content_hash(hasher);
+                hasher.write_float(disk.radius);
+            }
+            Shape::Cylinder(cylinder) => {
+                hasher.write_str("cylinder");
+                cylinder.base.content_hash(hasher);
+                cylinder.axis.content_hash(hasher);
+                hasher.write_float(cylinder.height);
+                hasher.write_float(cylinder.radius);
+            }
+            Shape::Cone(cone) => {
+                hasher.write_str("cone");
+                cone.base.content_hash(hasher);
+                cone.axis.content_hash(hasher);
+                hasher.write_float(cone.height);
+                hasher.write_float(cone.radius);
+            }
+            Shape::Capsule(capsule) => {
+                hasher.write_str("capsule");
+                capsule.start.content_hash(hasher);
+                capsule.end.content_hash(hasher);
+                hasher.write_float(capsule.radius);
+            }
+            Shape::Sdf(sdf) => {
+                hasher.write_str("sdf");
+                sdf.function.content_hash(hasher);
+                sdf.center.content_hash(hasher);
+                hasher.write_float(sdf.scale);
+            }
+            Shape::Heightfield(terrain) => {
+                hasher.write_str("heightfield");
+                terrain.content_hash(hasher);
+            }
+            Shape::Curves(curves) => {
+                hasher.write_str("curves");
+                curves.content_hash(hasher);
+            }
+            Shape::Mesh(mesh) => {
+                hasher.write_str("mesh");
+                mesh.content_hash(hasher);
+            }
+            Shape::Instance(instance) => {
+                hasher.write_str("instance");
+                instance.levels().content_hash(hasher);
+                for row in &instance.transform().m {
+                    for &cell in row {
+                        hasher.write_float(cell);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Custom functions can't be looked into, only their bounds are hashed, so
+/// scenes differing in them alone hash the same.
+impl ContentHash for DistanceFunction {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        match self {
+            DistanceFunction::Sphere { center, radius } => {
+                hasher.write_str("sphere");
+                center.content_hash(hasher);
+                hasher.write_float(*radius);
+            }
+            DistanceFunction::Mandelbulb { power, iterations } => {
+                hasher.write_str("mandelbulb");
+                hasher.write_float(*power);
+                hasher.write_u64(*iterations as u64);
+            }
+            DistanceFunction::Menger { iterations } => {
+                hasher.write_str("menger");
+                hasher.write_u64(*iterations as u64);
+            }
+            DistanceFunction::SmoothUnion(a, b, k) => {
+                hasher.write_str("smooth union");
+                a.content_hash(hasher);
+                b.content_hash(hasher);
+                hasher.write_float(*k);
+            }
+            DistanceFunction::Custom { radius, .. } => {
+                hasher.write_str("custom");
+                hasher.write_float(*radius);
+            }
+        }
+    }
+}
+
+impl ContentHash for Environment {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        match self {
+            Environment::Constant(color) => {
+                hasher.write_str("constant");
+                color.content_hash(hasher);
+            }
+            Environment::Gradient { bottom, top } => {
+                hasher.write_str("gradient");
+                bottom.content_hash(hasher);
+                top.content_hash(hasher);
+            }
+            Environment::Map(map) => {
+                hasher.write_str("map");
+                map.content_hash(hasher);
+            }
+            Environment::Sky(sky) => {
+                let (elevation, azimuth, turbidity) = sky.parameters();
+                hasher.write_str("sky");
+                hasher.write_float(elevation);
+                hasher.write_float(azimuth);
+                hasher.write_float(turbidity);
+            }
+        }
+    }
+}
+
+impl ContentHash for Filter {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        match *self {
+            Filter::Box { radius } => {
+                hasher.write_str("box");
+                hasher.write_float(radius);
+            }
+            Filter::Tent { radius } => {
+                hasher.write_str("tent");
+                hasher.write_float(radius);
+            }
+            Filter::Gaussian { radius, alpha } => {
+                hasher.write_str("gaussian");
+                hasher.write_float(radius);
+                hasher.write_float(alpha);
+            }
+        }
+    }
+}
+
+impl ContentHash for SamplerType {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        hasher.write_str(match self {
+            SamplerType::Independent => "independent",
+            SamplerType::Stratified => "stratified",
+            SamplerType::Halton => "halton",
+            SamplerType::Sobol => "sobol",
+        });
+    }
+}
+
+impl ContentHash for RenderSettings {
+    /// Covers everything that changes the image, so not the thread count.
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        hasher.write_u64(self.width as u64);
+        hasher.write_u64(self.height as u64);
+        hasher.write_u64(self.samples_per_pixel as u64);
+        hasher.write_u64(self.max_depth as u64);
+        hasher.write_u64(self.bounce_limits.diffuse as u64);
+        hasher.write_u64(self.bounce_limits.glossy as u64);
+        hasher.write_u64(self.bounce_limits.transmission as u64);
+        hasher.write_u64(self.bounce_limits.volume as u64);
+        hasher.write_u64(self.transparent_background as u64);
+        hasher.write_float(self.max_distance);
+        hasher.write_float(self.distance_fade);
+        hasher.write_float(self.clamp_direct);
+        hasher.write_float(self.clamp_indirect);
+        hasher.write_float(self.clamp_sample);
+        hasher.write_float(self.outlier_sigma);
+        hasher.write_u64(self.median_of_means as u64);
+        self.filter.content_hash(hasher);
+        self.sampler.content_hash(hasher);
+        hasher.write_u64(self.blue_noise as u64);
+        hasher.write_u64(self.light_cache as u64);
+        hasher.write_u64(self.preview as u64);
+        hasher.write_u64(self.seed);
+        // Leaves the hashes of whole images alone.
+        if let Some(crop) = self.crop {
+            for &value in &[crop.x, crop.y, crop.width, crop.height] {
+                hasher.write_u64(value as u64);
+            }
+        }
+    }
+}
+
+/// Prefix of the metadata keys holding the hashes in output images.
+const METADATA_PREFIX: &str = "raytracer.";
+
+/// Content hashes of the parts of a render, stored in its outputs so that
+/// outputs made from a scene or settings that have since changed can be
+/// told apart, along with what changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RenderHashes {
+    /// Spheres, the lights and shadow catchers picked among them, the
+    /// delta lights and the self-intersection offset, in meters.
+    pub geometry: u64,
+    pub materials: u64,
+    pub environment: u64,
+    pub camera: u64,
+    pub settings: u64,
+}
+
+impl RenderHashes {
+    pub fn new(scene: &Scene, camera: &Camera, settings: &RenderSettings) -> Self {
+        let mut geometry = ContentHasher::default();
+        // Spheres are compared in meters whatever unit the scene uses.
+        let meters = scene.units.meters_per_unit();
+        geometry.write_u64(scene.spheres.len() as u64);
+        for sphere in &scene.spheres {
+            (sphere.position * meters).content_hash(&mut geometry);
+            geometry.write_float(sphere.radius * meters);
+            geometry.write_u64(sphere.material.0 as u64);
+            geometry.write_u64(sphere.cull_backfaces as u64);
+        }
+        for index in 0..scene.spheres.len() {
+            geometry.write_u64(scene.is_shadow_catcher(ObjectId(index)) as u64);
+            let projection = scene.uv_projection(ObjectId(index));
+            geometry.write_u64(projection.map_or(0, |projection| projection as u64 + 1));
+            if let Some(shape) = scene.shape(ObjectId(index)) {
+                let mut shape = shape.clone();
+                shape.scale(meters);
+                shape.content_hash(&mut geometry);
+            }
+        }
+        geometry.write_u64(scene.lights.len() as u64);
+        for light in &scene.lights {
+            geometry.write_u64(light.0 as u64);
+        }
+        geometry.write_u64(scene.delta_lights.len() as u64);
+        for light in &scene.delta_lights {
+            let mut light = *light;
+            light.scale(meters);
+            light.content_hash(&mut geometry);
+        }
+        geometry.write_float(scene.epsilon() * meters);
+
+        let mut materials = ContentHasher::default();
+        scene.materials[..].content_hash(&mut materials);
+        for index in 0..scene.materials.len() {
+            materials.write_u64(scene.two_sided(MaterialId(index)) as u64);
+            materials.write_u64(scene.priority(MaterialId(index)) as u64);
+            // Absorption is compared per meter.
+            (scene.absorption(MaterialId(index)) / meters).content_hash(&mut materials);
+            match scene.normal_map(MaterialId(index)) {
+                Some(normal_map) => {
+                    materials.write_u64(1);
+                    normal_map.content_hash(&mut materials);
+                }
+                None => materials.write_u64(0),
+            }
+            // Bump heights are compared in meters, like spheres.
+            match scene.bump_map(MaterialId(index)) {
+                Some(bump_map) => {
+                    materials.write_u64(1);
+                    bump_map.texture.content_hash(&mut materials);
+                    materials.write_float(bump_map.height * meters);
+                }
+                None => materials.write_u64(0),
+            }
+            match scene.ocean(MaterialId(index)) {
+                Some(ocean) => {
+                    let (wind_speed, wind_direction, seed) = ocean.parameters();
+                    materials.write_u64(1);
+                    materials.write_float(wind_speed);
+                    materials.write_float(wind_direction);
+                    materials.write_u64(seed);
+                    materials.write_float(ocean.time);
+                }
+                None => materials.write_u64(0),
+            }
+            match scene.thin_film(MaterialId(index)) {
+                Some(film) => {
+                    materials.write_u64(1);
+                    materials.write_float(film.thickness);
+                    materials.write_float(film.refractive_index);
+                }
+                None => materials.write_u64(0),
+            }
+        }
+
+        // Clouds belong with the sky, and scenes without any keep the hash
+        // of their environment alone.
+        let mut environment = ContentHasher::default();
+        scene.environment.content_hash(&mut environment);
+        if let Some(clouds) = &scene.clouds {
+            clouds.content_hash(&mut environment);
+        }
+
+        RenderHashes {
+            geometry: geometry.finish(),
+            materials: materials.finish(),
+            environment: environment.finish(),
+            camera: camera.content_digest(),
+            settings: settings.content_digest(),
+        }
+    }
+
+    /// Name and hash of every component, in a fixed order.
+    pub fn components(&self) -> [(&'static str, u64); 5] {
+        [
+            ("geometry", self.geometry),
+            ("materials", self.materials),
+            ("environment", self.environment),
+            ("camera", self.camera),
+            ("settings", self.settings),
+        ]
+    }
+
+    /// Names of the components whose hash differs in `other`.
+    pub fn changed(&self, other: &RenderHashes) -> Vec<&'static str> {
+        self.components()
+            .iter()
+            .zip(other.components().iter())
+            .filter(|((_, a), (_, b))| a != b)
+            .map(|((name, _), _)| *name)
+            .collect()
+    }
+
+    /// Key and value pairs to store in image metadata.
+    pub fn to_metadata(&self) -> Vec<(String, String)> {
+        self.components()
+            .iter()
+            .map(|(name, hash)| {
+                (
+                    format!("{}{}", METADATA_PREFIX, name),
+                    format!("{:016x}", hash),
+                )
+            })
+            .collect()
+    }
+
+    /// Hashes stored by `to_metadata`, `None` when any is missing or invalid.
+    pub fn from_metadata(metadata: &[(String, String)]) -> Option<Self> {
+        let find = |name: &str| {
+            metadata
+                .iter()
+                .find(|(key, _)| key.strip_prefix(METADATA_PREFIX) == Some(name))
+                .and_then(|(_, value)| u64::from_str_radix(value.trim(), 16).ok())
+        };
+
+        Some(RenderHashes {
+            geometry: find("geometry")?,
+            materials: find("materials")?,
+            environment: find("environment")?,
+            camera: find("camera")?,
+            settings: find("settings")?,
+        })
+    }
+}
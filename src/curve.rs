@@ -0,0 +1,538 @@
+use crate::bvh::Aabb;
+use crate::content_hash::*;
+use crate::hitable::*;
+use crate::material::MaterialId;
+use crate::maths::*;
+use crate::ray::Ray;
+use crate::sphere::{Sphere, UvFrame};
+
+use std::fs::File;
+use std::io::Read;
+use std::sync::Arc;
+
+/// Straight pieces every curve is cut into to be intersected.
+const SEGMENTS: usize = 8;
+
+/// Most curves in a leaf of the hierarchy of `Curves`.
+const LEAF_SIZE: usize = 4;
+
+/// Distance from a fiber, relative to its radius, under which rays starting
+/// there are leaving it and don't hit it again.
+const LEAVING: Float = 1.01;
+
+/// Cubic Bézier curve swept by a circle, like a hair or a wire, its radius
+/// going linearly from the root to the tip.
+#[derive(Clone, Copy, Debug)]
+pub struct Curve {
+    /// Control points, from the root.
+    pub points: [Vec3; 4],
+    /// Radius at the root and at the tip.
+    pub radii: (Float, Float),
+}
+
+impl Curve {
+    pub fn new(points: [Vec3; 4], radii: (Float, Float)) -> Self {
+        Curve { points, radii }
+    }
+
+    /// Catmull-Rom spline through `points`, as one curve between every two,
+    /// of the radius of `radii` at each point.
+    pub fn catmull_rom(points: &[Vec3], radii: &[Float]) -> Vec<Curve> {
+        assert_eq!(points.len(), radii.len());
+        let count = points.len();
+        (1..count)
+            .map(|i| {
+                // Ends are repeated, so the spline stops at them.
+                let before = points[i.saturating_sub(2)];
+                let (start, end) = (points[i - 1], points[i]);
+                let after = points[(i + 1).min(count - 1)];
+                let control = [
+                    start,
+                    start + (end - before) / 6.0,
+                    end - (after - start) / 6.0,
+                    end,
+                ];
+                Curve::new(control, (radii[i - 1], radii[i]))
+            })
+            .collect()
+    }
+
+    /// Point of the axis at `u`, from 0 at the root to 1 at the tip.
+    pub fn point(&self, u: Float) -> Vec3 {
+        let [p0, p1, p2, p3] = self.points;
+        let v = 1.0 - u;
+        p0 * (v * v * v) + p1 * (3.0 * v * v * u) + p2 * (3.0 * v * u * u) + p3 * (u * u * u)
+    }
+
+    /// Derivative of the axis at `u`, along the curve towards the tip.
+    pub fn derivative(&self, u: Float) -> Vec3 {
+        let [p0, p1, p2, p3] = self.points;
+        let v = 1.0 - u;
+        let derivative =
+            (p1 - p0) * (3.0 * v * v) + (p2 - p1) * (6.0 * v * u) + (p3 - p2) * (3.0 * u * u);
+        if derivative.length_squared() > 0.0 {
+            derivative
+        } else {
+            // Control points on top of the ends.
+            p3 - p0
+        }
+    }
+
+    pub fn radius(&self, u: Float) -> Float {
+        self.radii.0 + (self.radii.1 - self.radii.0) * u
+    }
+
+    /// Box around the control points, which the curve stays within, grown by
+    /// the radius.
+    pub fn bounds(&self) -> Aabb {
+        let radius = self.radii.0.max(self.radii.1);
+        let extent = Vec3::new(radius, radius, radius);
+        self.points.iter().fold(Aabb::empty(), |bounds, &point| {
+            bounds.merge(Aabb {
+                min: point - extent,
+                max: point + extent,
+            })
+        })
+    }
+
+    /// Ends of the straight pieces the curve is cut into.
+    fn segment_ends(&self) -> [Vec3; SEGMENTS + 1] {
+        let mut ends = [self.points[0]; SEGMENTS + 1];
+        for (k, end) in ends.iter_mut().enumerate().skip(1) {
+            *end = self.point(k as Float / SEGMENTS as Float);
+        }
+        ends
+    }
+
+    /// Point of the axis closest to `point`, and where it is along the curve.
+    pub fn closest(&self, point: Vec3) -> (Float, Vec3) {
+        let ends = self.segment_ends();
+        let mut closest = (0.0, ends[0]);
+        let mut closest_distance = Float::INFINITY;
+        for (k, piece) in ends.windows(2).enumerate() {
+            let axis = piece[1] - piece[0];
+            let s = projection(point - piece[0], axis);
+            let on_axis = piece[0] + axis * s;
+            let distance = (point - on_axis).length_squared();
+            if distance < closest_distance {
+                closest_distance = distance;
+                closest = ((k as Float + s) / SEGMENTS as Float, on_axis);
+            }
+        }
+        closest
+    }
+
+    /// Closest hit of `ray` on the curve, its ray parameter, where it is
+    /// along the curve and the point of the axis under it.
+    ///
+    /// Rays are tested against the straight pieces of the curve, hitting
+    /// those passing within their radius where they come closest to them.
+    /// The surface there is taken as round, so fibers look like tubes.
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<(Float, Float, Vec3)> {
+        let length = ray.dir.length();
+        let dir = ray.dir / length;
+        let ends = self.segment_ends();
+        let mut closest = None;
+        let mut closest_t = t_max;
+        for (k, piece) in ends.windows(2).enumerate() {
+            let axis = piece[1] - piece[0];
+            let offset = ray.origin - piece[0];
+            let along = dir.dot(axis);
+            let squared = axis.length_squared();
+            let ahead = dir.dot(offset);
+            let denominator = squared - along * along;
+            let s = if denominator > 1e-9 * squared {
+                clamp((axis.dot(offset) - along * ahead) / denominator, 0.0, 1.0)
+            } else {
+                projection(offset, axis)
+            };
+            let u = (k as Float + s) / SEGMENTS as Float;
+            let radius = self.radius(u);
+
+            // Rays leaving the surface of the fiber go through it.
+            let origin_on_axis = piece[0] + axis * projection(offset, axis);
+            if (ray.origin - origin_on_axis).length() < radius * LEAVING {
+                continue;
+            }
+
+            let on_axis = piece[0] + axis * s;
+            let t = along * s - ahead;
+            let distance = (ray.origin + dir * t - on_axis).length();
+            if distance >= radius {
+                continue;
+            }
+            let t = (t - (radius * radius - distance * distance).sqrt()) / length;
+            if t > t_min && t < closest_t {
+                closest_t = t;
+                closest = Some((t, u, on_axis));
+            }
+        }
+        closest
+    }
+}
+
+/// Where the projection of `offset` falls along `axis`, from 0 to 1.
+fn projection(offset: Vec3, axis: Vec3) -> Float {
+    let squared = axis.length_squared();
+    if squared > 0.0 {
+        clamp(offset.dot(axis) / squared, 0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct CurveNode {
+    bounds: Aabb,
+    /// Leaves: index of their first curve. Inner nodes: index of their
+    /// second child, the first one following them.
+    index: usize,
+    /// Number of curves of leaves, 0 for inner nodes.
+    count: usize,
+}
+
+/// Many `Curve`s of one material, like the strands of a head of hair,
+/// found by rays through a hierarchy of boxes of their own. Texture
+/// coordinates go from 0 to 1 along the curves one after the other, and
+/// around them.
+#[derive(Clone, Debug)]
+pub struct Curves {
+    pub material: MaterialId,
+    /// In the order of the leaves of the hierarchy.
+    curves: Arc<Vec<Curve>>,
+    /// Depth first, every inner node followed by its first child.
+    nodes: Arc<Vec<CurveNode>>,
+}
+
+impl Curves {
+    pub fn new(mut curves: Vec<Curve>, material: MaterialId) -> Self {
+        let mut nodes = Vec::new();
+        if !curves.is_empty() {
+            build(&mut curves, 0, &mut nodes);
+        }
+        Curves {
+            material,
+            curves: Arc::new(curves),
+            nodes: Arc::new(nodes),
+        }
+    }
+
+    pub fn curves(&self) -> &[Curve] {
+        &self.curves
+    }
+
+    /// Curve whose axis comes closest to `point`, which is on the surface,
+    /// with where it comes closest along it.
+    fn closest(&self, point: Vec3) -> Option<(usize, Float, Vec3)> {
+        let mut closest = None;
+        let mut closest_distance = Float::INFINITY;
+        let mut stack = Vec::with_capacity(64);
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let (min, max) = (node.bounds.min, node.bounds.max);
+            let inside = point.x >= min.x
+                && point.y >= min.y
+                && point.z >= min.z
+                && point.x <= max.x
+                && point.y <= max.y
+                && point.z <= max.z;
+            if !inside {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(node.index);
+                stack.push(index + 1);
+                continue;
+            }
+            for curve_index in node.index..node.index + node.count {
+                let curve = &self.curves[curve_index];
+                let (u, on_axis) = curve.closest(point);
+                let distance = ((point - on_axis).length() - curve.radius(u)).abs();
+                if distance < closest_distance {
+                    closest_distance = distance;
+                    closest = Some((curve_index, u, on_axis));
+                }
+            }
+        }
+        closest
+    }
+
+    /// Outward unit normal at `point`, on the surface.
+    pub fn normal_at(&self, point: Vec3) -> Vec3 {
+        match self.closest(point) {
+            Some((_, _, on_axis)) => (point - on_axis).unit(),
+            None => Vec3::new(0.0, 1.0, 0.0),
+        }
+    }
+
+    /// Texture coordinates of `point`, on the surface, with the frame normal
+    /// and bump maps bend normals in, the tangent along the curve.
+    pub fn uv_frame(&self, point: Vec3) -> UvFrame {
+        let (index, u, on_axis) = match self.closest(point) {
+            Some(closest) => closest,
+            None => (0, 0.0, point),
+        };
+        let curve = &self.curves[index];
+        let derivative = curve.derivative(u);
+        let normal = (point - on_axis).unit();
+        let around = Onb::from_w(derivative);
+        let angle = normal.dot(around.v).atan2(normal.dot(around.u));
+        let count = self.curves.len() as Float;
+        let radius = curve.radius(u);
+        UvFrame {
+            uv: (
+                (index as Float + u) / count,
+                angle.rem_euclid(2.0 * consts::PI) / (2.0 * consts::PI),
+            ),
+            tangents: (derivative.unit(), normal.cross(around.w)),
+            lengths: (derivative.length() * count, 2.0 * consts::PI * radius),
+        }
+    }
+
+    /// Point and outward normal at texture coordinates (`u`, `v`).
+    pub fn surface_at(&self, u: Float, v: Float) -> (Vec3, Vec3) {
+        if self.curves.is_empty() {
+            return (Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        }
+        let count = self.curves.len();
+        let position = clamp(u, 0.0, 1.0) * count as Float;
+        let index = (position as usize).min(count - 1);
+        let (curve, u) = (&self.curves[index], position - index as Float);
+        let around = Onb::from_w(curve.derivative(u));
+        let angle = 2.0 * consts::PI * v;
+        let normal = around.u * angle.cos() + around.v * angle.sin();
+        (curve.point(u) + normal * curve.radius(u), normal)
+    }
+
+    pub fn bounding_sphere(&self) -> Sphere {
+        match self.nodes.first() {
+            Some(root) => {
+                let (min, max) = (root.bounds.min, root.bounds.max);
+                Sphere::new((min + max) / 2.0, (max - min).length() / 2.0, self.material)
+            }
+            None => Sphere::new(Vec3::new(0.0, 0.0, 0.0), 0.0, self.material),
+        }
+    }
+
+    /// Copy of the curves moved by `transform`, their radii following its
+    /// average scale.
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        let factor = transform.determinant3().abs().cbrt();
+        let curves = self
+            .curves
+            .iter()
+            .map(|curve| {
+                let mut points = curve.points;
+                for point in &mut points {
+                    *point = transform.transform_point(*point);
+                }
+                Curve::new(points, (curve.radii.0 * factor, curve.radii.1 * factor))
+            })
+            .collect();
+        Curves::new(curves, self.material)
+    }
+}
+
+/// Adds the subtree of `curves`, the first of which is at `offset`, sorting
+/// them in the order of its leaves.
+fn build(curves: &mut [Curve], offset: usize, nodes: &mut Vec<CurveNode>) {
+    let bounds = curves
+        .iter()
+        .fold(Aabb::empty(), |bounds, curve| bounds.merge(curve.bounds()));
+    let node = nodes.len();
+    nodes.push(CurveNode {
+        bounds,
+        index: offset,
+        count: curves.len(),
+    });
+    if curves.len() <= LEAF_SIZE {
+        return;
+    }
+
+    // Halves the curves at their median along the widest axis.
+    let center = |curve: &Curve| (curve.points[0] + curve.points[3]) / 2.0;
+    let centers = curves.iter().fold(Aabb::empty(), |bounds, curve| {
+        let center = center(curve);
+        bounds.merge(Aabb {
+            min: center,
+            max: center,
+        })
+    });
+    let extent = centers.max - centers.min;
+    let axis = |point: Vec3| {
+        if extent.x >= extent.y && extent.x >= extent.z {
+            point.x
+        } else if extent.y >= extent.z {
+            point.y
+        } else {
+            point.z
+        }
+    };
+    curves.sort_by(|a, b| axis(center(a)).total_cmp(&axis(center(b))));
+
+    let middle = curves.len() / 2;
+    let (first, second) = curves.split_at_mut(middle);
+    nodes[node].count = 0;
+    build(first, offset, nodes);
+    nodes[node].index = nodes.len();
+    build(second, offset + middle, nodes);
+}
+
+impl Hitable for Curves {
+    fn scale(&mut self, factor: Float) {
+        let curves = self
+            .curves
+            .iter()
+            .map(|curve| {
+                let mut points = curve.points;
+                for point in &mut points {
+                    *point = *point * factor;
+                }
+                Curve::new(points, (curve.radii.0 * factor, curve.radii.1 * factor))
+            })
+            .collect();
+        *self = Curves::new(curves, self.material);
+    }
+
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let mut closest: Option<(usize, Float, Float, Vec3)> = None;
+        let mut closest_t = t_max;
+        let mut stack = Vec::with_capacity(64);
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node.bounds.hit(ray, t_min, closest_t) {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(node.index);
+                stack.push(index + 1);
+                continue;
+            }
+            for curve_index in node.index..node.index + node.count {
+                if let Some((t, u, on_axis)) = self.curves[curve_index].hit(ray, t_min, closest_t) {
+                    closest_t = t;
+                    closest = Some((curve_index, t, u, on_axis));
+                }
+            }
+        }
+
+        let (index, t, u, on_axis) = closest?;
+        let position = ray.at(t);
+        let mut record = HitRecord::new(ray, position, position - on_axis, t, self.material);
+        // Hair materials lay their frame along the fiber.
+        record.tangent = Some(self.curves[index].derivative(u).unit());
+        Some(record)
+    }
+}
+
+/// Curves are hashed with their placement, materials being left out like
+/// for shapes.
+impl ContentHash for Curves {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        hasher.write_u64(self.curves.len() as u64);
+        for curve in self.curves.iter() {
+            for point in &curve.points {
+                point.content_hash(hasher);
+            }
+            hasher.write_float(curve.radii.0);
+            hasher.write_float(curve.radii.1);
+        }
+    }
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Reads the strands of a `.hair` file, see `parse_hair`.
+pub fn load_hair(name: &str) -> std::io::Result<Vec<Curve>> {
+    let mut bytes = Vec::new();
+    File::open(name)?.read_to_end(&mut bytes)?;
+    parse_hair(&bytes)
+}
+
+/// Reads the strands of a file of Cem Yuksel's `.hair` format as
+/// Catmull-Rom splines through their points, their thickness their
+/// diameter. The header of 128 bytes starts with `HAIR`, the number of
+/// strands and of points, flags telling which arrays follow, the number of
+/// segments of strands and their thickness when there are no such arrays,
+/// then a default transparency, color and text the curves don't use. The
+/// arrays give segments per strand as 16-bit integers, then points,
+/// thicknesses, transparencies and colors as 32-bit floats, all little
+/// endian.
+pub fn parse_hair(bytes: &[u8]) -> std::io::Result<Vec<Curve>> {
+    const HEADER: usize = 128;
+    const HAS_SEGMENTS: u32 = 1;
+    const HAS_POINTS: u32 = 2;
+    const HAS_THICKNESS: u32 = 4;
+    if bytes.len() < HEADER || &bytes[..4] != b"HAIR" {
+        return Err(invalid("not a hair file"));
+    }
+    let word = |at: usize| [bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]];
+    let strands = u32::from_le_bytes(word(4)) as usize;
+    let point_count = u32::from_le_bytes(word(8)) as usize;
+    let flags = u32::from_le_bytes(word(12));
+    let default_segments = u32::from_le_bytes(word(16)) as usize;
+    let default_thickness = f32::from_le_bytes(word(20)) as Float;
+    if flags & HAS_POINTS == 0 {
+        return Err(invalid("hair files without points"));
+    }
+
+    let mut at = HEADER;
+    let mut take = |length: usize| {
+        let start = at;
+        at += length;
+        bytes
+            .get(start..at)
+            .ok_or_else(|| invalid("truncated hair file"))
+    };
+    let segments: Vec<usize> = if flags & HAS_SEGMENTS != 0 {
+        take(strands * 2)?
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]) as usize)
+            .collect()
+    } else {
+        vec![default_segments; strands]
+    };
+    let floats = |data: &[u8]| -> Vec<Float> {
+        data.chunks_exact(4)
+            .map(|word| f32::from_le_bytes([word[0], word[1], word[2], word[3]]) as Float)
+            .collect()
+    };
+    let coordinates = floats(take(point_count * 12)?);
+    let thickness = if flags & HAS_THICKNESS != 0 {
+        floats(take(point_count * 4)?)
+    } else {
+        vec![default_thickness; point_count]
+    };
+    if segments.iter().map(|count| count + 1).sum::<usize>() != point_count {
+        return Err(invalid("hair file segments don't match its points"));
+    }
+
+    let mut curves = Vec::with_capacity(point_count);
+    let mut first = 0;
+    for count in segments {
+        let points: Vec<Vec3> = (first..=first + count)
+            .map(|i| {
+                Vec3::new(
+                    coordinates[3 * i],
+                    coordinates[3 * i + 1],
+                    coordinates[3 * i + 2],
+                )
+            })
+            .collect();
+        let radii: Vec<Float> = thickness[first..=first + count]
+            .iter()
+            .map(|thickness| thickness / 2.0)
+            .collect();
+        curves.extend(Curve::catmull_rom(&points, &radii));
+        first += count + 1;
+    }
+    Ok(curves)
+}
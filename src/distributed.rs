@@ -0,0 +1,258 @@
+use crate::content_hash::RenderHashes;
+use crate::image::Image;
+use crate::maths::Float;
+use crate::render::Crop;
+
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Mutex;
+use std::thread;
+
+/// Side of the square regions images are farmed out in, in pixels: big
+/// enough to keep workers busy between requests, small enough to share the
+/// work of uneven scenes out evenly.
+pub const REGION_SIZE: usize = 128;
+
+/// Bytes of the floats the renderer computes in, sent as they are.
+const FLOAT_SIZE: usize = std::mem::size_of::<Float>();
+
+/// Regions of at most `size` x `size` pixels covering a `width` x `height`
+/// image, row by row.
+pub fn split_image(width: usize, height: usize, size: usize) -> Vec<Crop> {
+    let mut regions = Vec::new();
+    for y in (0..height).step_by(size) {
+        for x in (0..width).step_by(size) {
+            regions.push(Crop {
+                x,
+                y,
+                width: size.min(width - x),
+                height: size.min(height - y),
+            });
+        }
+    }
+    regions
+}
+
+/// Request for the pixels of `region` of the render whose parts hash to
+/// `hashes`, one line of text.
+fn request_line(hashes: &RenderHashes, region: Crop) -> String {
+    let mut line = String::from("render");
+    for (_, hash) in hashes.components().iter() {
+        line.push_str(&format!(" {:016x}", hash));
+    }
+    line.push_str(&format!(
+        " {} {} {} {}\n",
+        region.x, region.y, region.width, region.height
+    ));
+    line
+}
+
+/// Hashes and region of a request line, `None` when it isn't one.
+fn parse_request(line: &str) -> Option<(RenderHashes, Crop)> {
+    let mut words = line.split_whitespace();
+    if words.next() != Some("render") {
+        return None;
+    }
+    let mut hash = || u64::from_str_radix(words.next()?, 16).ok();
+    let hashes = RenderHashes {
+        geometry: hash()?,
+        materials: hash()?,
+        environment: hash()?,
+        camera: hash()?,
+        settings: hash()?,
+    };
+    let mut number = || words.next()?.parse().ok();
+    let region = Crop {
+        x: number()?,
+        y: number()?,
+        width: number()?,
+        height: number()?,
+    };
+    match words.next() {
+        Some(_) => None,
+        None => Some((hashes, region)),
+    }
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Answers the requests of coordinators connecting to `listener`, one
+/// connection at a time, as long as it accepts them.
+///
+/// Requests are lines of the hashes of the render they are part of (see
+/// `RenderHashes`) and of the region they want, `x`, `y`, `width` and
+/// `height`. Those of the render of `hashes`, the worker's own, are
+/// answered by a `pixels N BYTES` line followed by the `N` floats `render`
+/// gives for the region, little-endian, of `BYTES` bytes each, others by an
+/// `error` line telling what differs, as are those `render` fails, like
+/// regions going past the image.
+pub fn serve(
+    listener: TcpListener,
+    hashes: &RenderHashes,
+    mut render: impl FnMut(Crop) -> std::io::Result<Image>,
+) -> std::io::Result<()> {
+    for stream in listener.incoming() {
+        // A coordinator going away leaves the worker waiting for the next.
+        let _ = answer(stream?, hashes, &mut render);
+    }
+    Ok(())
+}
+
+/// Answers the requests of a coordinator until it disconnects.
+fn answer(
+    stream: TcpStream,
+    hashes: &RenderHashes,
+    render: &mut impl FnMut(Crop) -> std::io::Result<Image>,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 {
+        match parse_request(&line) {
+            Some((wanted, _)) if wanted != *hashes => {
+                let changed = hashes.changed(&wanted).join(", ");
+                writeln!(writer, "error different {}", changed)?;
+            }
+            Some((_, region)) => match render(region) {
+                Ok(image) => {
+                    let pixels = image.pixels();
+                    writeln!(writer, "pixels {} {}", pixels.len(), FLOAT_SIZE)?;
+                    for &value in pixels {
+                        writer.write_all(&value.to_le_bytes())?;
+                    }
+                }
+                Err(error) => writeln!(writer, "error {}", error)?,
+            },
+            None => writeln!(writer, "error unknown request '{}'", line.trim())?,
+        }
+        writer.flush()?;
+        line.clear();
+    }
+    Ok(())
+}
+
+/// Asks the worker behind `reader` and `writer` for `region`.
+fn request(
+    reader: &mut impl BufRead,
+    writer: &mut impl Write,
+    hashes: &RenderHashes,
+    region: Crop,
+) -> std::io::Result<Vec<Float>> {
+    writer.write_all(request_line(hashes, region).as_bytes())?;
+    writer.flush()?;
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if let Some(error) = line.trim().strip_prefix("error ") {
+        return Err(invalid_data(error.to_string()));
+    }
+    let bad_answer = || invalid_data(format!("bad answer '{}'", line.trim()));
+    let mut words = line.split_whitespace();
+    if words.next() != Some("pixels") {
+        return Err(bad_answer());
+    }
+    let mut number = || words.next()?.parse::<usize>().ok();
+    let (count, size) = match (number(), number()) {
+        (Some(count), Some(size)) => (count, size),
+        _ => return Err(bad_answer()),
+    };
+    if size != FLOAT_SIZE {
+        return Err(invalid_data(format!(
+            "the worker computes with {}-bit floats",
+            size * 8
+        )));
+    }
+    if count != region.width * region.height * 4 {
+        return Err(invalid_data(format!(
+            "{} values for a {}x{} region",
+            count, region.width, region.height
+        )));
+    }
+    let mut bytes = vec![0; count * FLOAT_SIZE];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes
+        .chunks(FLOAT_SIZE)
+        .map(|chunk| {
+            let mut value = [0; FLOAT_SIZE];
+            value.copy_from_slice(chunk);
+            Float::from_le_bytes(value)
+        })
+        .collect())
+}
+
+/// Renders a `width` x `height` image by farming its regions out to the
+/// `workers`, `host:port` addresses of `serve`, each asking for the next
+/// region left as soon as it is done with one. Regions of workers that fail
+/// go to the others, and the render only fails when they all have.
+///
+/// Workers load the scene and settings themselves, and only answer when
+/// they hash to `hashes`, so they render the same image down to the seed.
+pub fn render_distributed(
+    workers: &[String],
+    hashes: &RenderHashes,
+    width: usize,
+    height: usize,
+) -> std::io::Result<Image> {
+    let regions = Mutex::new(split_image(width, height, REGION_SIZE));
+    let pixels = Mutex::new(vec![0.0; width * height * 4]);
+    let errors = Mutex::new(Vec::new());
+
+    let work = |address: &str| -> std::io::Result<()> {
+        let stream = TcpStream::connect(address)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = BufWriter::new(stream);
+        loop {
+            let region = match regions.lock().unwrap().pop() {
+                Some(region) => region,
+                None => return Ok(()),
+            };
+            match request(&mut reader, &mut writer, hashes, region) {
+                Ok(region_pixels) => {
+                    let mut pixels = pixels.lock().unwrap();
+                    for (row, values) in region_pixels.chunks(region.width * 4).enumerate() {
+                        let start = ((region.y + row) * width + region.x) * 4;
+                        pixels[start..start + values.len()].copy_from_slice(values);
+                    }
+                }
+                Err(error) => {
+                    regions.lock().unwrap().push(region);
+                    return Err(error);
+                }
+            }
+        }
+    };
+    // Workers finding no region left stop, so those failed by the others
+    // are handed out again to the workers still up.
+    let mut alive: Vec<&String> = workers.iter().collect();
+    while !alive.is_empty() && !regions.lock().unwrap().is_empty() {
+        alive = thread::scope(|scope| {
+            let work = &work;
+            let handles: Vec<_> = alive
+                .iter()
+                .map(|&address| (address, scope.spawn(move || work(address))))
+                .collect();
+            handles
+                .into_iter()
+                .filter_map(|(address, handle)| match handle.join().unwrap() {
+                    Ok(()) => Some(address),
+                    Err(error) => {
+                        let message = format!("{}: {}", address, error);
+                        errors.lock().unwrap().push(message);
+                        None
+                    }
+                })
+                .collect()
+        });
+    }
+
+    if !regions.into_inner().unwrap().is_empty() {
+        let errors = errors.into_inner().unwrap();
+        return Err(std::io::Error::other(format!(
+            "every worker failed ({})",
+            errors.join("; ")
+        )));
+    }
+    Ok(Image::new(width, height, pixels.into_inner().unwrap()))
+}
@@ -0,0 +1,266 @@
+use crate::content_hash::*;
+use crate::hdr::read_hdr;
+use crate::maths::*;
+use crate::netpbm::read_pfm;
+use crate::sky::PhysicalSky;
+
+use crate::maths::consts::PI;
+use std::path::Path;
+
+/// Latitude/longitude radiance map, as produced by the equirectangular camera.
+#[derive(Clone, Debug)]
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    /// RGB radiance, rows from top to bottom.
+    pixels: Vec<Vec3>,
+    /// Rotation from world directions to directions of the map.
+    world_to_map: Mat4,
+    ground: Option<GroundProjection>,
+}
+
+/// Ground the lower half of an environment map is projected on, so objects
+/// stand on the photographed ground rather than float over it. Rays
+/// escaping the scene look the map up from where the photograph was taken
+/// towards where they meet the ground, or the dome around it.
+#[derive(Clone, Copy, Debug)]
+pub struct GroundProjection {
+    /// Point of the ground under where the photograph was taken.
+    pub center: Vec3,
+    /// Height the photograph was taken at, above the ground.
+    pub height: Float,
+    /// Radius of the dome over the ground, around `center`.
+    pub radius: Float,
+}
+
+impl GroundProjection {
+    /// Direction of the map seen along `direction` from `origin`, inside
+    /// the dome.
+    fn direction(&self, origin: Vec3, direction: Vec3) -> Vec3 {
+        let direction = direction.unit();
+        // Far side of the dome, which rays from inside always meet.
+        let offset = origin - self.center;
+        let half_b = offset.dot(direction);
+        let c = offset.length_squared() - self.radius * self.radius;
+        let discriminant = half_b * half_b - c;
+        if c > 0.0 || discriminant < 0.0 {
+            return direction;
+        }
+        let mut t = -half_b + discriminant.sqrt();
+        if direction.y < 0.0 && origin.y >= self.center.y {
+            t = t.min((self.center.y - origin.y) / direction.y);
+        }
+        let eye = self.center + Vec3::new(0.0, self.height, 0.0);
+        let seen = origin + direction * t - eye;
+        if seen.length_squared() > 0.0 {
+            seen
+        } else {
+            direction
+        }
+    }
+
+    pub fn scale(&mut self, factor: Float) {
+        self.center = self.center * factor;
+        self.height *= factor;
+        self.radius *= factor;
+    }
+}
+
+/// Changes to an environment map, to art direct it without editing the image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MapAdjustments {
+    /// Rotation of the map around the vertical axis, counterclockwise seen
+    /// from above.
+    pub yaw_degrees: Float,
+    /// Rotation of the map around the x axis, applied before the yaw,
+    /// positive values lifting the part facing -z.
+    pub pitch_degrees: Float,
+    /// Stops of exposure: radiance is multiplied by 2 to this power.
+    pub exposure: Float,
+    /// 0 turns the map gray, 1 keeps its colors and larger values make them
+    /// more vivid. Luminance is kept.
+    pub saturation: Float,
+}
+
+impl Default for MapAdjustments {
+    fn default() -> Self {
+        MapAdjustments {
+            yaw_degrees: 0.0,
+            pitch_degrees: 0.0,
+            exposure: 0.0,
+            saturation: 1.0,
+        }
+    }
+}
+
+impl EnvironmentMap {
+    pub fn new(pixels: &[f32], width: u32, height: u32) -> Self {
+        EnvironmentMap {
+            width: width as usize,
+            height: height as usize,
+            pixels: pixels
+                .chunks(3)
+                .map(|pixel| Vec3::new(pixel[0] as Float, pixel[1] as Float, pixel[2] as Float))
+                .collect(),
+            world_to_map: Mat4::identity(),
+            ground: None,
+        }
+    }
+
+    /// Projects the lower half of the map on `ground`.
+    pub fn with_ground_projection(mut self, ground: Option<GroundProjection>) -> Self {
+        self.ground = ground;
+        self
+    }
+
+    pub fn ground_projection(&self) -> Option<&GroundProjection> {
+        self.ground.as_ref()
+    }
+
+    pub fn ground_projection_mut(&mut self) -> Option<&mut GroundProjection> {
+        self.ground.as_mut()
+    }
+
+    /// Applies `adjustments` on top of the previous ones.
+    pub fn adjust(mut self, adjustments: &MapAdjustments) -> Self {
+        let scale = adjustments.exposure.exp2();
+        for pixel in &mut self.pixels {
+            let gray = luminance(*pixel);
+            let color = Vec3::new(gray, gray, gray)
+                + (*pixel - Vec3::new(gray, gray, gray)) * adjustments.saturation;
+            // Oversaturating can push channels below zero.
+            *pixel = Vec3::new(color.x.max(0.0), color.y.max(0.0), color.z.max(0.0)) * scale;
+        }
+
+        // Turning the map by pitch then yaw turns lookup directions the other
+        // way, in reverse order.
+        let inverse = Mat4::rotation_x(-deg_to_rad(adjustments.pitch_degrees))
+            * Mat4::rotation_y(-deg_to_rad(adjustments.yaw_degrees));
+        self.world_to_map = self.world_to_map * inverse;
+        self
+    }
+
+    /// Loads a `.hdr` or `.pfm` image.
+    pub fn load(name: &str) -> std::io::Result<Self> {
+        let extension = Path::new(name)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("");
+        let (pixels, width, height) = match extension {
+            "hdr" => read_hdr(name)?,
+            "pfm" => read_pfm(name)?,
+            _ => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "environment maps must be .hdr or .pfm files",
+                ))
+            }
+        };
+        if width == 0 || height == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "empty environment map",
+            ));
+        }
+
+        Ok(EnvironmentMap::new(&pixels, width, height))
+    }
+
+    /// Radiance arriving from `direction`, looked up with the nearest texel.
+    ///
+    /// The middle of the map faces -z, with +y at the top.
+    pub fn lookup(&self, direction: Vec3) -> Vec3 {
+        let direction = self.world_to_map.transform_vector(direction).unit();
+        let longitude = direction.x.atan2(-direction.z);
+        let latitude = clamp(direction.y, -1.0, 1.0).asin();
+
+        let s = longitude / (2.0 * PI) + 0.5;
+        let t = 0.5 - latitude / PI;
+
+        let x = ((s * self.width as Float) as usize).min(self.width - 1);
+        let y = ((t * self.height as Float) as usize).min(self.height - 1);
+        self.pixels[y * self.width + x]
+    }
+
+    /// Radiance reaching a ray leaving the scene from `origin` along
+    /// `direction`, which only differs from `lookup` on projected ground.
+    pub fn lookup_from(&self, origin: Vec3, direction: Vec3) -> Vec3 {
+        match &self.ground {
+            Some(ground) => self.lookup(ground.direction(origin, direction)),
+            None => self.lookup(direction),
+        }
+    }
+}
+
+/// Radiance reaching rays that escape the scene.
+#[derive(Clone, Debug)]
+pub enum Environment {
+    Constant(Vec3),
+    /// Blend from `bottom` to `top` following the height of the direction.
+    Gradient {
+        bottom: Vec3,
+        top: Vec3,
+    },
+    Map(EnvironmentMap),
+    Sky(PhysicalSky),
+}
+
+impl ContentHash for EnvironmentMap {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        hasher.write_u64(self.width as u64);
+        hasher.write_u64(self.height as u64);
+        self.pixels[..].content_hash(hasher);
+        for row in &self.world_to_map.m {
+            for &value in row {
+                hasher.write_float(value);
+            }
+        }
+        if let Some(ground) = &self.ground {
+            ground.center.content_hash(hasher);
+            hasher.write_float(ground.height);
+            hasher.write_float(ground.radius);
+        }
+    }
+}
+
+impl Default for Environment {
+    /// The white to light blue sky.
+    fn default() -> Self {
+        Environment::Gradient {
+            bottom: Vec3::new(1.0, 1.0, 1.0),
+            top: Vec3::new(0.5, 0.7, 1.0),
+        }
+    }
+}
+
+impl Environment {
+    pub fn radiance(&self, direction: Vec3) -> Vec3 {
+        match self {
+            Environment::Constant(color) => *color,
+            Environment::Gradient { bottom, top } => {
+                let t = 0.5 * (direction.unit().y + 1.0);
+                *bottom * (1.0 - t) + *top * t
+            }
+            Environment::Map(map) => map.lookup(direction),
+            Environment::Sky(sky) => sky.radiance(direction),
+        }
+    }
+
+    /// Radiance reaching a ray leaving the scene from `origin` along
+    /// `direction`, like `radiance` but for the ground maps are projected
+    /// on, see `GroundProjection`.
+    pub fn radiance_from(&self, origin: Vec3, direction: Vec3) -> Vec3 {
+        match self {
+            Environment::Map(map) => map.lookup_from(origin, direction),
+            _ => self.radiance(direction),
+        }
+    }
+
+    /// Sky whose sun is sampled for direct lighting, if any.
+    pub fn sun(&self) -> Option<&PhysicalSky> {
+        match self {
+            Environment::Sky(sky) => Some(sky),
+            _ => None,
+        }
+    }
+}
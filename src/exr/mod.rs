@@ -0,0 +1,208 @@
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufWriter;
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+const PIXEL_TYPE_FLOAT: i32 = 2;
+
+fn write_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+/// Small copy of an image embedded in its file, 8-bit RGBA pixels ready to
+/// show, gamma encoded, row-major.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preview {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// Writes linear RGB (or RGBA when `alpha` is set) `pixels` as an
+/// uncompressed, 32-bit float, scanline OpenEXR file, with `metadata` stored
+/// as string attributes and `preview` as the preview attribute.
+pub fn create_exr(
+    name: &str,
+    pixels: &[f32],
+    width: u32,
+    height: u32,
+    alpha: bool,
+    metadata: &[(String, String)],
+    preview: Option<&Preview>,
+) -> std::io::Result<()> {
+    let channels = if alpha { 4 } else { 3 };
+    // EXR stores channels in alphabetical order, so remember where each one
+    // lives in the interleaved input.
+    let layout: &[(&str, usize)] = if alpha {
+        &[("A", 3), ("B", 2), ("G", 1), ("R", 0)]
+    } else {
+        &[("B", 2), ("G", 1), ("R", 0)]
+    };
+
+    let mut channel_list = Vec::new();
+    for (channel, _) in layout {
+        channel_list.extend_from_slice(channel.as_bytes());
+        channel_list.push(0);
+        channel_list.extend_from_slice(&PIXEL_TYPE_FLOAT.to_le_bytes());
+        // pLinear and reserved bytes, then x and y sampling.
+        channel_list.extend_from_slice(&[0, 0, 0, 0]);
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channel_list.push(0);
+
+    let mut window = Vec::new();
+    for value in &[0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&MAGIC);
+    header.extend_from_slice(&2i32.to_le_bytes());
+    write_attribute(&mut header, "channels", "chlist", &channel_list);
+    write_attribute(&mut header, "compression", "compression", &[0]);
+    write_attribute(&mut header, "dataWindow", "box2i", &window);
+    write_attribute(&mut header, "displayWindow", "box2i", &window);
+    write_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    write_attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1.0f32.to_le_bytes(),
+    );
+    let mut center = Vec::new();
+    center.extend_from_slice(&0.0f32.to_le_bytes());
+    center.extend_from_slice(&0.0f32.to_le_bytes());
+    write_attribute(&mut header, "screenWindowCenter", "v2f", &center);
+    write_attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1.0f32.to_le_bytes(),
+    );
+    for (key, value) in metadata {
+        write_attribute(&mut header, key, "string", value.as_bytes());
+    }
+    if let Some(preview) = preview {
+        let mut value = Vec::with_capacity(8 + preview.pixels.len());
+        value.extend_from_slice(&preview.width.to_le_bytes());
+        value.extend_from_slice(&preview.height.to_le_bytes());
+        value.extend_from_slice(&preview.pixels);
+        write_attribute(&mut header, "preview", "preview", &value);
+    }
+    header.push(0);
+
+    let line_size = (width as usize * channels * 4) as i32;
+    let block_size = 8 + line_size as u64;
+    let first_block = header.len() as u64 + 8 * u64::from(height);
+
+    let mut file = BufWriter::new(File::create(name)?);
+    file.write_all(&header)?;
+    for y in 0..u64::from(height) {
+        file.write_all(&(first_block + y * block_size).to_le_bytes())?;
+    }
+
+    let stride = width as usize * channels;
+    for (y, row) in pixels.chunks(stride).enumerate() {
+        file.write_all(&(y as i32).to_le_bytes())?;
+        file.write_all(&line_size.to_le_bytes())?;
+        for &(_, offset) in layout {
+            for pixel in row.chunks(channels) {
+                file.write_all(&pixel[offset].to_le_bytes())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the names, types and values of the attributes of the header of an
+/// OpenEXR file.
+fn read_attributes(name: &str) -> std::io::Result<Vec<(String, String, Vec<u8>)>> {
+    let invalid_data = |message| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+
+    let mut bytes = Vec::new();
+    File::open(name)?.read_to_end(&mut bytes)?;
+    if !bytes.starts_with(&MAGIC) {
+        return Err(invalid_data("not an OpenEXR file"));
+    }
+
+    // Null terminated strings of the attribute names and types.
+    let read_string = |position: &mut usize| {
+        let rest = bytes.get(*position..).unwrap_or(&[]);
+        let length = rest
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or_else(|| invalid_data("truncated header"))?;
+        *position += length + 1;
+        Ok::<_, std::io::Error>(String::from_utf8_lossy(&rest[..length]).into_owned())
+    };
+
+    let mut attributes = Vec::new();
+    // Magic number and version.
+    let mut position = 8;
+    loop {
+        let name = read_string(&mut position)?;
+        if name.is_empty() {
+            break;
+        }
+        let kind = read_string(&mut position)?;
+        let size = bytes
+            .get(position..position + 4)
+            .map(|size| i32::from_le_bytes([size[0], size[1], size[2], size[3]]))
+            .filter(|&size| size >= 0)
+            .ok_or_else(|| invalid_data("truncated header"))? as usize;
+        position += 4;
+        let value = bytes
+            .get(position..)
+            .and_then(|rest| rest.get(..size))
+            .ok_or_else(|| invalid_data("truncated header"))?;
+        attributes.push((name, kind, value.to_vec()));
+        position += size;
+    }
+
+    Ok(attributes)
+}
+
+/// Reads the string attributes of the header of an OpenEXR file.
+pub fn read_exr_metadata(name: &str) -> std::io::Result<Vec<(String, String)>> {
+    Ok(read_attributes(name)?
+        .into_iter()
+        .filter(|(_, kind, _)| kind == "string")
+        .map(|(name, _, value)| (name, String::from_utf8_lossy(&value).into_owned()))
+        .collect())
+}
+
+/// Reads the preview attribute of an OpenEXR file, if it has one.
+pub fn read_exr_preview(name: &str) -> std::io::Result<Option<Preview>> {
+    let preview = read_attributes(name)?
+        .into_iter()
+        .find(|(_, kind, _)| kind == "preview");
+    let value = match preview {
+        Some((_, _, value)) => value,
+        None => return Ok(None),
+    };
+    let size = |start: usize| {
+        value
+            .get(start..start + 4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+    match (size(0), size(4)) {
+        (Some(width), Some(height)) if value.len() == 8 + width as usize * height as usize * 4 => {
+            Ok(Some(Preview {
+                width,
+                height,
+                pixels: value[8..].to_vec(),
+            }))
+        }
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "invalid preview",
+        )),
+    }
+}
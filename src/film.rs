@@ -0,0 +1,264 @@
+use crate::aov::AovImages;
+use crate::maths::*;
+
+/// Pixel reconstruction filter, samples contribute to every pixel whose
+/// center lies within `radius` of them.
+#[derive(Clone, Copy, Debug)]
+pub enum Filter {
+    Box { radius: Float },
+    Tent { radius: Float },
+    Gaussian { radius: Float, alpha: Float },
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::Box { radius: 0.5 }
+    }
+}
+
+impl Filter {
+    pub fn radius(&self) -> Float {
+        match *self {
+            Filter::Box { radius } => radius,
+            Filter::Tent { radius } => radius,
+            Filter::Gaussian { radius, .. } => radius,
+        }
+    }
+
+    /// Weight of a sample at offset (`dx`, `dy`) from a pixel center.
+    pub fn evaluate(&self, dx: Float, dy: Float) -> Float {
+        match *self {
+            Filter::Box { radius } => {
+                if dx.abs() <= radius && dy.abs() <= radius {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Filter::Tent { radius } => {
+                Float::max(radius - dx.abs(), 0.0) * Float::max(radius - dy.abs(), 0.0)
+            }
+            Filter::Gaussian { radius, alpha } => {
+                let gaussian = |d: Float| {
+                    Float::max(
+                        (-alpha * d * d).exp() - (-alpha * radius * radius).exp(),
+                        0.0,
+                    )
+                };
+                gaussian(dx) * gaussian(dy)
+            }
+        }
+    }
+}
+
+/// Accumulates filtered samples over a rectangle of pixels of an image.
+///
+/// Films are not shared between threads: every tile splats into its own film,
+/// grown by the filter radius so samples can reach neighbouring tiles, and
+/// tile films are merged afterwards in a fixed order, which keeps the result
+/// deterministic.
+#[derive(Clone, Debug)]
+pub struct Film {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    /// Weighted RGBA sums followed by the sum of weights, per pixel.
+    data: Vec<Float>,
+}
+
+const STRIDE: usize = 5;
+
+impl Film {
+    /// Film covering the `width` x `height` pixels starting at (`x`, `y`).
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Film {
+            x,
+            y,
+            width,
+            height,
+            data: vec![0.0; width * height * STRIDE],
+        }
+    }
+
+    /// Adds a sample taken at raster position (`sx`, `sy`), with (0, 0) the
+    /// top left corner of the image, to every pixel of the film it reaches.
+    pub fn add_sample(&mut self, sx: Float, sy: Float, color: Vec3, alpha: Float, filter: &Filter) {
+        let radius = filter.radius();
+        let x0 = Float::max((sx - radius - 0.5).ceil(), self.x as Float) as usize;
+        let y0 = Float::max((sy - radius - 0.5).ceil(), self.y as Float) as usize;
+        let x1 = Float::min(
+            (sx + radius - 0.5).floor(),
+            (self.x + self.width) as Float - 1.0,
+        );
+        let y1 = Float::min(
+            (sy + radius - 0.5).floor(),
+            (self.y + self.height) as Float - 1.0,
+        );
+        if x1 < 0.0 || y1 < 0.0 {
+            return;
+        }
+
+        for y in y0..=y1 as usize {
+            for x in x0..=x1 as usize {
+                let weight = filter.evaluate(sx - (x as Float + 0.5), sy - (y as Float + 0.5));
+                if weight == 0.0 {
+                    continue;
+                }
+
+                let index = ((y - self.y) * self.width + (x - self.x)) * STRIDE;
+                let pixel = &mut self.data[index..index + STRIDE];
+                pixel[0] += color.x * weight;
+                pixel[1] += color.y * weight;
+                pixel[2] += color.z * weight;
+                pixel[3] += alpha * weight;
+                pixel[4] += weight;
+            }
+        }
+    }
+
+    /// Adds the overlapping part of `other` to this film.
+    pub fn merge(&mut self, other: &Film) {
+        let x0 = self.x.max(other.x);
+        let y0 = self.y.max(other.y);
+        let x1 = (self.x + self.width).min(other.x + other.width);
+        let y1 = (self.y + self.height).min(other.y + other.height);
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let to = ((y - self.y) * self.width + (x - self.x)) * STRIDE;
+                let from = ((y - other.y) * other.width + (x - other.x)) * STRIDE;
+                for channel in 0..STRIDE {
+                    self.data[to + channel] += other.data[from + channel];
+                }
+            }
+        }
+    }
+
+    /// Row-major, premultiplied RGBA pixels normalized by the filter weights.
+    pub fn resolve(&self) -> Vec<Float> {
+        self.data
+            .chunks(STRIDE)
+            .flat_map(|pixel| {
+                let weight = pixel[4];
+                if weight > 0.0 {
+                    vec![
+                        pixel[0] / weight,
+                        pixel[1] / weight,
+                        pixel[2] / weight,
+                        pixel[3] / weight,
+                    ]
+                } else {
+                    vec![0.0; 4]
+                }
+            })
+            .collect()
+    }
+}
+
+/// Images of the successive passes of a progressive render, averaged
+/// together, started over when what they show changes, or carried over to
+/// the next view by `reproject`.
+#[derive(Clone, Debug, Default)]
+pub struct Accumulation {
+    sum: Vec<Float>,
+    /// Passes summed up in each pixel, some carried over from earlier views.
+    weights: Vec<Float>,
+    passes: usize,
+}
+
+impl Accumulation {
+    /// Adds a pass, rendered with as many samples per pixel as the others.
+    pub fn add(&mut self, pixels: &[Float]) {
+        if self.sum.is_empty() {
+            self.sum = vec![0.0; pixels.len()];
+            self.weights = vec![0.0; pixels.len() / 4];
+        }
+        assert_eq!(pixels.len(), self.sum.len(), "passes must be the same size");
+        for ((sum, weight), pixel) in self
+            .sum
+            .chunks_mut(4)
+            .zip(&mut self.weights)
+            .zip(pixels.chunks(4))
+        {
+            for (sum, value) in sum.iter_mut().zip(pixel) {
+                *sum += value;
+            }
+            *weight += 1.0;
+        }
+        self.passes += 1;
+    }
+
+    /// Drops the passes so far, for a new view.
+    pub fn reset(&mut self) {
+        self.sum.clear();
+        self.weights.clear();
+        self.passes = 0;
+    }
+
+    /// Number of passes added since the last reset or reprojection.
+    pub fn passes(&self) -> usize {
+        self.passes
+    }
+
+    /// Mean of the passes so far, empty before the first.
+    pub fn mean(&self) -> Vec<Float> {
+        self.sum
+            .chunks(4)
+            .zip(&self.weights)
+            .flat_map(|(sum, &weight)| {
+                let scale = if weight > 0.0 { 1.0 / weight } else { 0.0 };
+                sum.iter().map(move |&sum| sum * scale)
+            })
+            .collect()
+    }
+
+    /// Carries the passes so far over to a new view, `width` pixels wide,
+    /// whose first pass gave `aovs`, the camera having moved by `moved`.
+    /// Each pixel takes the mean of the one its motion vector comes from,
+    /// counting for at most `max_weight` passes, so the new view soon
+    /// outweighs what is left of the old one.
+    ///
+    /// The depth a pixel had in the old view, `previous_depth`, can only
+    /// differ from its new depth by how far the camera moved, give or take
+    /// the spread of the samples. Pixels further off show what the old view
+    /// didn't see, and start over like those that came into view.
+    pub fn reproject(
+        &mut self,
+        aovs: &AovImages,
+        previous_depth: &[Float],
+        moved: Float,
+        width: usize,
+        max_weight: Float,
+    ) {
+        let previous = self.mean();
+        let height = self.weights.len() / width.max(1);
+        let mut sum = vec![0.0; previous.len()];
+        let mut weights = vec![0.0; self.weights.len()];
+        for (index, weight) in weights.iter_mut().enumerate() {
+            let (motion_x, motion_y) = (aovs.motion[index * 2], aovs.motion[index * 2 + 1]);
+            let x = ((index % width) as Float + 0.5 - motion_x).floor();
+            let y = ((index / width) as Float + 0.5 - motion_y).floor();
+            if x < 0.0 || y < 0.0 || x >= width as Float || y >= height as Float {
+                continue;
+            }
+            let from = y as usize * width + x as usize;
+            let (depth, before) = (aovs.depth[index], previous_depth[from]);
+            let same_surface = if depth.is_finite() && before.is_finite() {
+                (depth - before).abs() <= moved + 0.05 * depth
+            } else {
+                depth.is_finite() == before.is_finite()
+            };
+            if !same_surface {
+                continue;
+            }
+            *weight = self.weights[from].min(max_weight);
+            for channel in 0..4 {
+                sum[index * 4 + channel] = previous[from * 4 + channel] * *weight;
+            }
+        }
+        self.sum = sum;
+        self.weights = weights;
+        self.passes = 0;
+    }
+}
@@ -0,0 +1,929 @@
+use crate::camera::CameraView;
+use crate::light::DeltaLight;
+use crate::material::{MaterialId, MaterialType};
+use crate::maths::*;
+use crate::mesh::Mesh;
+use crate::principled::{Principled, PrincipledTextures};
+use crate::scene::Scene;
+use crate::shape::Shape;
+use crate::texture::{NormalMap, Texture};
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Deepest nesting of JSON values, and of glTF nodes, read before giving
+/// up on a file.
+const MAX_DEPTH: usize = 256;
+
+/// Stands for missing objects, which have none of their optional members.
+static EMPTY: Json = Json::Object(Vec::new());
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Value of a JSON document, objects keeping their keys in order.
+#[derive(Clone, Debug)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// Items of the array `key`, none if it is missing.
+    fn list(&self, key: &str) -> &[Json] {
+        self.get(key).map_or(&[], Json::items)
+    }
+
+    fn number(&self) -> Option<Float> {
+        match self {
+            Json::Number(number) => Some(*number as Float),
+            _ => None,
+        }
+    }
+
+    fn index(&self) -> Option<usize> {
+        match self {
+            Json::Number(number) if *number >= 0.0 && number.fract() == 0.0 => {
+                Some(*number as usize)
+            }
+            _ => None,
+        }
+    }
+
+    fn string(&self) -> Option<&str> {
+        match self {
+            Json::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// Items of arrays, and nothing for other values.
+    fn items(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => &[],
+        }
+    }
+
+    /// Number `key`, or `default` when it is missing.
+    fn number_or(&self, key: &str, default: Float) -> std::io::Result<Float> {
+        match self.get(key) {
+            None => Ok(default),
+            Some(value) => value
+                .number()
+                .ok_or_else(|| invalid(&format!("glTF {} isn't a number", key))),
+        }
+    }
+
+    /// Array `key` of `N` numbers, or `default` when it is missing.
+    fn numbers_or<const N: usize>(
+        &self,
+        key: &str,
+        default: [Float; N],
+    ) -> std::io::Result<[Float; N]> {
+        let value = match self.get(key) {
+            None => return Ok(default),
+            Some(value) => value,
+        };
+        let mut numbers = default;
+        let items = value.items();
+        if items.len() != N {
+            return Err(invalid(&format!("glTF {} must have {} numbers", key, N)));
+        }
+        for (number, item) in numbers.iter_mut().zip(items) {
+            *number = item
+                .number()
+                .ok_or_else(|| invalid(&format!("glTF {} isn't numbers", key)))?;
+        }
+        Ok(numbers)
+    }
+
+    /// Index `key`, if there is one.
+    fn index_of(&self, key: &str) -> std::io::Result<Option<usize>> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(value) => match value.index() {
+                Some(index) => Ok(Some(index)),
+                None => Err(invalid(&format!("glTF {} isn't an index", key))),
+            },
+        }
+    }
+}
+
+/// Reads a JSON document from its bytes.
+struct JsonParser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.bytes.get(self.position) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> std::io::Result<()> {
+        self.skip_whitespace();
+        if self.bytes.get(self.position) != Some(&byte) {
+            return Err(invalid(&format!("JSON without '{}'", byte as char)));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    /// Whether the next byte is `byte`, skipping it if so.
+    fn next_is(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.bytes.get(self.position) == Some(&byte);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn value(&mut self, depth: usize) -> std::io::Result<Json> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("JSON nested too deep"));
+        }
+        self.skip_whitespace();
+        let rest = &self.bytes[self.position..];
+        for (word, value) in [
+            ("true", Json::Bool(true)),
+            ("false", Json::Bool(false)),
+            ("null", Json::Null),
+        ] {
+            if rest.starts_with(word.as_bytes()) {
+                self.position += word.len();
+                return Ok(value);
+            }
+        }
+        match rest.first() {
+            Some(b'{') => {
+                self.position += 1;
+                let mut members = Vec::new();
+                if !self.next_is(b'}') {
+                    loop {
+                        self.skip_whitespace();
+                        let key = self.string()?;
+                        self.expect(b':')?;
+                        members.push((key, self.value(depth + 1)?));
+                        if !self.next_is(b',') {
+                            break;
+                        }
+                    }
+                    self.expect(b'}')?;
+                }
+                Ok(Json::Object(members))
+            }
+            Some(b'[') => {
+                self.position += 1;
+                let mut items = Vec::new();
+                if !self.next_is(b']') {
+                    loop {
+                        items.push(self.value(depth + 1)?);
+                        if !self.next_is(b',') {
+                            break;
+                        }
+                    }
+                    self.expect(b']')?;
+                }
+                Ok(Json::Array(items))
+            }
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(_) => {
+                let length = rest
+                    .iter()
+                    .take_while(|byte| {
+                        matches!(byte, b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E')
+                    })
+                    .count();
+                let number = std::str::from_utf8(&rest[..length])
+                    .ok()
+                    .and_then(|text| text.parse::<f64>().ok())
+                    .ok_or_else(|| invalid("invalid JSON value"))?;
+                self.position += length;
+                Ok(Json::Number(number))
+            }
+            None => Err(invalid("JSON ends early")),
+        }
+    }
+
+    fn string(&mut self) -> std::io::Result<String> {
+        if self.bytes.get(self.position) != Some(&b'"') {
+            return Err(invalid("JSON without a string"));
+        }
+        self.position += 1;
+        let mut bytes = Vec::new();
+        loop {
+            let byte = *self
+                .bytes
+                .get(self.position)
+                .ok_or_else(|| invalid("JSON string without an end"))?;
+            self.position += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let escape = *self
+                        .bytes
+                        .get(self.position)
+                        .ok_or_else(|| invalid("JSON string without an end"))?;
+                    self.position += 1;
+                    let character = match escape {
+                        b'n' => '\n',
+                        b't' => '\t',
+                        b'r' => '\r',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            // Characters outside the basic plane come as
+                            // pairs of surrogates.
+                            if (0xd800..0xdc00).contains(&code)
+                                && self.bytes[self.position..].starts_with(b"\\u")
+                            {
+                                self.position += 2;
+                                let low = self.hex4()?;
+                                code = 0x10000
+                                    + ((code - 0xd800) << 10)
+                                    + (low.wrapping_sub(0xdc00) & 0x3ff);
+                            }
+                            char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        other => other as char,
+                    };
+                    let mut buffer = [0; 4];
+                    bytes.extend_from_slice(character.encode_utf8(&mut buffer).as_bytes());
+                }
+                _ => bytes.push(byte),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| invalid("JSON string isn't UTF-8"))
+    }
+
+    fn hex4(&mut self) -> std::io::Result<u32> {
+        let digits = self
+            .bytes
+            .get(self.position..self.position + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| invalid("invalid JSON escape"))?;
+        self.position += 4;
+        Ok(digits)
+    }
+}
+
+fn parse_json(bytes: &[u8]) -> std::io::Result<Json> {
+    let mut parser = JsonParser { bytes, position: 0 };
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    if parser.position != bytes.len() {
+        return Err(invalid("JSON with trailing data"));
+    }
+    Ok(value)
+}
+
+/// Bytes of base64 `text`, padded or not.
+fn decode_base64(text: &str) -> std::io::Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut count = 0;
+    for byte in text.bytes().filter(|&byte| byte != b'=') {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return Err(invalid("invalid base64")),
+        };
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+/// `uri` with its `%XX` escapes replaced by the bytes they stand for.
+fn decode_uri(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = bytes
+            .get(index + 1..index + 3)
+            .filter(|_| bytes[index] == b'%')
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Bytes `uri` stands for, in a data URI or a file relative to
+/// `directory`.
+fn read_uri(uri: &str, directory: &Path) -> std::io::Result<Vec<u8>> {
+    if uri.starts_with("data:") {
+        return match uri.split_once(";base64,") {
+            Some((_, data)) => decode_base64(data),
+            None => Err(invalid("glTF data URIs must be base64")),
+        };
+    }
+    let file = directory.join(decode_uri(uri));
+    let mut bytes = Vec::new();
+    File::open(&file)
+        .and_then(|mut file| file.read_to_end(&mut bytes))
+        .map_err(|error| invalid(&format!("{}: {}", file.display(), error)))?;
+    Ok(bytes)
+}
+
+/// JSON document and binary chunk of a `.glb` file.
+fn split_glb(bytes: &[u8]) -> std::io::Result<(Json, Option<&[u8]>)> {
+    let word = |offset: usize| {
+        bytes
+            .get(offset..offset + 4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]) as usize)
+            .ok_or_else(|| invalid("GLB file ends early"))
+    };
+    if word(4)? != 2 {
+        return Err(invalid("only glTF 2.0 is supported"));
+    }
+    let mut document = None;
+    let mut binary = None;
+    let mut offset = 12;
+    while offset < bytes.len().min(word(8)?) {
+        let length = word(offset)?;
+        let kind = word(offset + 4)?;
+        let chunk = bytes
+            .get(offset + 8..offset + 8 + length)
+            .ok_or_else(|| invalid("GLB chunk ends early"))?;
+        match kind {
+            0x4e4f_534a if document.is_none() => document = Some(parse_json(chunk)?),
+            0x004e_4942 if binary.is_none() => binary = Some(chunk),
+            _ => {}
+        }
+        offset += 8 + length;
+    }
+    let document = document.ok_or_else(|| invalid("GLB file without JSON"))?;
+    Ok((document, binary))
+}
+
+/// Matrix of glTF `node`, from its `matrix` or its translation, rotation
+/// and scale.
+fn node_transform(node: &Json) -> std::io::Result<Mat4> {
+    if node.get("matrix").is_some() {
+        let values = node.numbers_or("matrix", [0.0; 16])?;
+        let mut matrix = Mat4::identity();
+        // Columns one after the other.
+        for (row, cells) in matrix.m.iter_mut().enumerate() {
+            for (column, cell) in cells.iter_mut().enumerate() {
+                *cell = values[column * 4 + row];
+            }
+        }
+        return Ok(matrix);
+    }
+    let [x, y, z] = node.numbers_or("translation", [0.0; 3])?;
+    let [qx, qy, qz, qw] = node.numbers_or("rotation", [0.0, 0.0, 0.0, 1.0])?;
+    let [sx, sy, sz] = node.numbers_or("scale", [1.0; 3])?;
+    let mut rotation = Mat4::identity();
+    rotation.m[0][0] = 1.0 - 2.0 * (qy * qy + qz * qz);
+    rotation.m[0][1] = 2.0 * (qx * qy - qz * qw);
+    rotation.m[0][2] = 2.0 * (qx * qz + qy * qw);
+    rotation.m[1][0] = 2.0 * (qx * qy + qz * qw);
+    rotation.m[1][1] = 1.0 - 2.0 * (qx * qx + qz * qz);
+    rotation.m[1][2] = 2.0 * (qy * qz - qx * qw);
+    rotation.m[2][0] = 2.0 * (qx * qz - qy * qw);
+    rotation.m[2][1] = 2.0 * (qy * qz + qx * qw);
+    rotation.m[2][2] = 1.0 - 2.0 * (qx * qx + qy * qy);
+    Ok(Mat4::translation(Vec3::new(x, y, z)) * rotation * Mat4::scaling(Vec3::new(sx, sy, sz)))
+}
+
+/// Materials, meshes, lights and cameras of a glTF document being added to
+/// a scene.
+struct Importer<'a> {
+    document: &'a Json,
+    /// Where files the document refers to are.
+    directory: &'a Path,
+    buffers: Vec<Vec<u8>>,
+    /// Materials added for those of the document, and for primitives
+    /// without one, as they are used.
+    materials: HashMap<Option<usize>, MaterialId>,
+    /// Textures decoded from the images of the document, and whether they
+    /// were in sRGB, as they are used.
+    textures: HashMap<(usize, bool), Texture>,
+    /// Whether each node has been added, nodes making trees.
+    added: Vec<bool>,
+    /// Scene units per meter, the unit of glTF.
+    factor: Float,
+}
+
+impl<'a> Importer<'a> {
+    /// Item `index` of the top level array `key`.
+    fn item(&self, key: &str, index: usize) -> std::io::Result<&'a Json> {
+        self.document
+            .list(key)
+            .get(index)
+            .ok_or_else(|| invalid(&format!("glTF {} {} is missing", key, index)))
+    }
+
+    /// Bytes of buffer view `index`.
+    fn buffer_view(&self, index: usize) -> std::io::Result<&[u8]> {
+        let view = self.item("bufferViews", index)?;
+        let buffer = view
+            .index_of("buffer")?
+            .and_then(|buffer| self.buffers.get(buffer))
+            .ok_or_else(|| invalid("glTF buffer view of a missing buffer"))?;
+        let start = view.index_of("byteOffset")?.unwrap_or(0);
+        let length = view.index_of("byteLength")?.unwrap_or(0);
+        start
+            .checked_add(length)
+            .and_then(|end| buffer.get(start..end))
+            .ok_or_else(|| invalid("glTF buffer view outside of its buffer"))
+    }
+
+    /// Texture of the texture info `info` of a material, if given, `srgb`
+    /// for colors rather than data. Images must be PNGs.
+    fn texture(&mut self, info: Option<&Json>, srgb: bool) -> std::io::Result<Option<Texture>> {
+        let info = match info {
+            Some(info) => info,
+            None => return Ok(None),
+        };
+        if info.index_of("texCoord")?.unwrap_or(0) != 0 {
+            return Err(invalid(
+                "glTF textures must use the first texture coordinates",
+            ));
+        }
+        let texture = info
+            .index_of("index")?
+            .ok_or_else(|| invalid("glTF texture info without an index"))?;
+        let image = self
+            .item("textures", texture)?
+            .index_of("source")?
+            .ok_or_else(|| invalid(&format!("glTF texture {} without an image", texture)))?;
+        if let Some(texture) = self.textures.get(&(image, srgb)) {
+            return Ok(Some(texture.clone()));
+        }
+
+        let source = self.item("images", image)?;
+        let bytes = match (
+            source.get("uri").and_then(Json::string),
+            source.index_of("bufferView")?,
+        ) {
+            (Some(uri), _) => read_uri(uri, self.directory)?,
+            (None, Some(view)) => self.buffer_view(view)?.to_vec(),
+            (None, None) => return Err(invalid(&format!("glTF image {} without data", image))),
+        };
+        if bytes.starts_with(&[0xff, 0xd8]) {
+            return Err(invalid(&format!(
+                "glTF image {} is a JPEG, and only PNG textures are supported",
+                image
+            )));
+        }
+        let texture = Texture::decode_png(&bytes, srgb)
+            .map_err(|error| invalid(&format!("glTF image {}: {}", image, error)))?;
+        self.textures.insert((image, srgb), texture.clone());
+        Ok(Some(texture))
+    }
+
+    /// Values of accessor `index`, of `components` numbers each.
+    fn accessor(&self, index: usize, components: usize) -> std::io::Result<Vec<f64>> {
+        let accessor = self.item("accessors", index)?;
+        if accessor.get("sparse").is_some() {
+            return Err(invalid("sparse glTF accessors aren't supported"));
+        }
+        let kind = ["SCALAR", "VEC2", "VEC3", "VEC4"][components - 1];
+        if accessor.get("type").and_then(Json::string) != Some(kind) {
+            return Err(invalid(&format!("glTF accessor {} isn't {}", index, kind)));
+        }
+        let component_type = accessor.index_of("componentType")?.unwrap_or(0);
+        let size = match component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            _ => return Err(invalid("unknown glTF component type")),
+        };
+        let normalized = matches!(accessor.get("normalized"), Some(Json::Bool(true)));
+        let count = accessor.index_of("count")?.unwrap_or(0);
+        let view = match accessor.index_of("bufferView")? {
+            Some(view) => view,
+            None => {
+                return Err(invalid(
+                    "glTF accessors without buffer views aren't supported",
+                ))
+            }
+        };
+        let bytes = self.buffer_view(view)?;
+        let element = size * components;
+        let stride = self
+            .item("bufferViews", view)?
+            .index_of("byteStride")?
+            .unwrap_or(element);
+        let offset = accessor.index_of("byteOffset")?.unwrap_or(0);
+        // One past the last byte read, checked against overflows.
+        let end = match count {
+            0 => Some(0),
+            count => (count - 1)
+                .checked_mul(stride)
+                .and_then(|last| last.checked_add(offset))
+                .and_then(|last| last.checked_add(element)),
+        };
+        if stride < element || end.is_none_or(|end| end > bytes.len()) {
+            return Err(invalid("glTF accessor outside of its buffer view"));
+        }
+
+        let mut values = Vec::with_capacity(count * components);
+        for index in 0..count {
+            for component in 0..components {
+                let at = offset + index * stride + component * size;
+                let b = &bytes[at..at + size];
+                let (value, range) = match component_type {
+                    5120 => (b[0] as i8 as f64, 127.0),
+                    5121 => (b[0] as f64, 255.0),
+                    5122 => (i16::from_le_bytes([b[0], b[1]]) as f64, 32767.0),
+                    5123 => (u16::from_le_bytes([b[0], b[1]]) as f64, 65535.0),
+                    5125 => (u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64, 1.0),
+                    _ => (f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64, 1.0),
+                };
+                values.push(if normalized {
+                    (value / range).max(-1.0)
+                } else {
+                    value
+                });
+            }
+        }
+        Ok(values)
+    }
+
+    /// Material of the scene for the material `index` of the document, or
+    /// for primitives without a material.
+    fn material(&mut self, scene: &mut Scene, index: Option<usize>) -> std::io::Result<MaterialId> {
+        if let Some(&material) = self.materials.get(&index) {
+            return Ok(material);
+        }
+        let source = match index {
+            Some(index) => self.item("materials", index)?,
+            None => &EMPTY,
+        };
+        let pbr = source.get("pbrMetallicRoughness").unwrap_or(&EMPTY);
+        let textures = PrincipledTextures {
+            base_color: self.texture(pbr.get("baseColorTexture"), true)?,
+            metallic_roughness: self.texture(pbr.get("metallicRoughnessTexture"), false)?,
+            emission: self.texture(source.get("emissiveTexture"), true)?,
+        };
+        let normal_map = match self.texture(source.get("normalTexture"), false)? {
+            Some(texture) => Some(NormalMap {
+                texture,
+                strength: source
+                    .get("normalTexture")
+                    .unwrap_or(&EMPTY)
+                    .number_or("scale", 1.0)?,
+            }),
+            None => None,
+        };
+        let textured = textures.base_color.is_some()
+            || textures.metallic_roughness.is_some()
+            || textures.emission.is_some();
+        let extension = |name: &str| {
+            source
+                .get("extensions")
+                .and_then(|extensions| extensions.get(name))
+                .unwrap_or(&EMPTY)
+        };
+
+        let strength =
+            extension("KHR_materials_emissive_strength").number_or("emissiveStrength", 1.0)?;
+        let [r, g, b] = source.numbers_or("emissiveFactor", [0.0; 3])?;
+        let emit = Vec3::new(r, g, b) * strength;
+        // Emissive textures vary the light, which principled materials
+        // follow, while lights glow evenly.
+        let material_type = if emit.length_squared() > 0.0 && textures.emission.is_none() {
+            MaterialType::DiffuseLight { emit }
+        } else {
+            let [r, g, b, _] = pbr.numbers_or("baseColorFactor", [1.0; 4])?;
+            MaterialType::Principled(Principled {
+                base_color: Vec3::new(r, g, b),
+                metallic: pbr.number_or("metallicFactor", 1.0)?,
+                roughness: pbr.number_or("roughnessFactor", 1.0)?,
+                transmission: extension("KHR_materials_transmission")
+                    .number_or("transmissionFactor", 0.0)?,
+                emission: emit,
+                textures: Some(Arc::new(textures)).filter(|_| textured),
+                ..Principled::default()
+            })
+        };
+        let material = scene.add_material(material_type);
+        scene.set_normal_map(material, normal_map);
+        if let Some(Json::Bool(true)) = source.get("doubleSided") {
+            scene.set_two_sided(material, true);
+        }
+        if let Some(name) = source.get("name").and_then(Json::string) {
+            if scene.material_named(name).is_none() {
+                scene.set_material_name(material, name);
+            }
+        }
+        self.materials.insert(index, material);
+        Ok(material)
+    }
+
+    /// Adds the triangles of mesh `index`, placed by `transform`.
+    fn add_mesh(
+        &mut self,
+        scene: &mut Scene,
+        index: usize,
+        transform: &Mat4,
+        name: Option<&str>,
+    ) -> std::io::Result<()> {
+        for primitive in self.item("meshes", index)?.list("primitives") {
+            // Points and lines have no surface to hit.
+            if primitive.index_of("mode")?.unwrap_or(4) != 4 {
+                continue;
+            }
+            let attributes = primitive.get("attributes").unwrap_or(&EMPTY);
+            let position = attributes
+                .index_of("POSITION")?
+                .ok_or_else(|| invalid("glTF primitive without positions"))?;
+            let positions: Vec<Vec3> = self
+                .accessor(position, 3)?
+                .chunks(3)
+                .map(|p| Vec3::new(p[0] as Float, p[1] as Float, p[2] as Float))
+                .collect();
+            let corners: Vec<usize> = match primitive.index_of("indices")? {
+                Some(indices) => self
+                    .accessor(indices, 1)?
+                    .iter()
+                    .map(|&index| index as usize)
+                    .collect(),
+                None => (0..positions.len()).collect(),
+            };
+            if corners.iter().any(|&corner| corner >= positions.len()) {
+                return Err(invalid("glTF triangle of a missing vertex"));
+            }
+            let triangles: Vec<[usize; 3]> = corners
+                .chunks_exact(3)
+                .map(|corners| [corners[0], corners[1], corners[2]])
+                .collect();
+            if triangles.is_empty() {
+                continue;
+            }
+
+            let normals = match attributes.index_of("NORMAL")? {
+                Some(normals) => {
+                    let normals: Vec<Vec3> = self
+                        .accessor(normals, 3)?
+                        .chunks(3)
+                        .map(|n| Vec3::new(n[0] as Float, n[1] as Float, n[2] as Float))
+                        .collect();
+                    if normals.len() != positions.len() {
+                        return Err(invalid("glTF primitives take a normal per position"));
+                    }
+                    Some(normals)
+                }
+                None => None,
+            };
+
+            // Texture coordinates go down images in glTF, and up in
+            // textures.
+            let uvs = match attributes.index_of("TEXCOORD_0")? {
+                Some(uvs) => {
+                    let uvs: Vec<(Float, Float)> = self
+                        .accessor(uvs, 2)?
+                        .chunks(2)
+                        .map(|uv| (uv[0] as Float, 1.0 - uv[1] as Float))
+                        .collect();
+                    if uvs.len() != positions.len() {
+                        return Err(invalid(
+                            "glTF primitives take texture coordinates per position",
+                        ));
+                    }
+                    Some(uvs)
+                }
+                None => None,
+            };
+
+            let material = self.material(scene, primitive.index_of("material")?)?;
+            let mut mesh = Mesh::new(positions, triangles, material);
+            // Primitives without normals are flat shaded.
+            if let Some(normals) = normals {
+                mesh = mesh.with_normals(normals);
+            }
+            if let Some(uvs) = uvs {
+                mesh = mesh.with_uvs(uvs);
+            }
+            let mesh = mesh.transformed(transform);
+            let id = scene.add_shape(Shape::Mesh(mesh));
+            if let Some(name) = name {
+                scene.set_name(id, name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the light `index` of `KHR_lights_punctual`, placed by
+    /// `transform`.
+    fn add_light(&self, scene: &mut Scene, index: usize, transform: &Mat4) -> std::io::Result<()> {
+        let light = self
+            .document
+            .get("extensions")
+            .and_then(|extensions| extensions.get("KHR_lights_punctual"))
+            .unwrap_or(&EMPTY)
+            .list("lights")
+            .get(index)
+            .ok_or_else(|| invalid(&format!("glTF light {} is missing", index)))?;
+        let [r, g, b] = light.numbers_or("color", [1.0; 3])?;
+        let power = Vec3::new(r, g, b) * light.number_or("intensity", 1.0)?;
+        let position = transform.transform_point(Vec3::new(0.0, 0.0, 0.0));
+        let direction = transform.transform_vector(Vec3::new(0.0, 0.0, -1.0));
+        // Intensities are in candela, giving the irradiance one meter away.
+        let intensity = power * (self.factor * self.factor);
+        let light = match light.get("type").and_then(Json::string) {
+            Some("point") => DeltaLight::Point {
+                position,
+                intensity,
+            },
+            Some("directional") => DeltaLight::Directional {
+                direction,
+                irradiance: power,
+            },
+            Some("spot") => {
+                let spot = light.get("spot").unwrap_or(&EMPTY);
+                DeltaLight::Spot {
+                    position,
+                    direction,
+                    intensity,
+                    inner_angle: spot.number_or("innerConeAngle", 0.0)?.to_degrees(),
+                    outer_angle: spot
+                        .number_or("outerConeAngle", consts::FRAC_PI_4)?
+                        .to_degrees(),
+                }
+            }
+            _ => return Err(invalid("unknown glTF light type")),
+        };
+        scene.delta_lights.push(light);
+        Ok(())
+    }
+
+    /// Adds node `index` and its children, `parent` placing the node.
+    fn add_node(
+        &mut self,
+        scene: &mut Scene,
+        index: usize,
+        parent: &Mat4,
+        depth: usize,
+    ) -> std::io::Result<()> {
+        let node = self.item("nodes", index)?;
+        if depth > MAX_DEPTH || std::mem::replace(&mut self.added[index], true) {
+            return Err(invalid("glTF nodes must make trees"));
+        }
+        let transform = *parent * node_transform(node)?;
+        let name = node.get("name").and_then(Json::string);
+
+        if let Some(mesh) = node.index_of("mesh")? {
+            self.add_mesh(scene, mesh, &transform, name)?;
+        }
+        if let Some(camera) = node.index_of("camera")? {
+            let camera = self.item("cameras", camera)?;
+            // The renderer has one camera, looking through the first one.
+            if let (None, Some(perspective)) = (&scene.camera, camera.get("perspective")) {
+                let look_from = transform.transform_point(Vec3::new(0.0, 0.0, 0.0));
+                let forward = transform.transform_vector(Vec3::new(0.0, 0.0, -1.0));
+                scene.camera = Some(CameraView {
+                    look_from,
+                    look_at: look_from + forward.unit(),
+                    up: transform.transform_vector(Vec3::new(0.0, 1.0, 0.0)).unit(),
+                    vertical_fov: perspective.number_or("yfov", 0.0)?.to_degrees(),
+                });
+            }
+        }
+        let light = node
+            .get("extensions")
+            .and_then(|extensions| extensions.get("KHR_lights_punctual"));
+        if let Some(light) = light {
+            if let Some(light) = light.index_of("light")? {
+                self.add_light(scene, light, &transform)?;
+            }
+        }
+
+        for child in node.list("children") {
+            let child = child
+                .index()
+                .ok_or_else(|| invalid("glTF child isn't an index"))?;
+            self.add_node(scene, child, &transform, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Extensions of glTF files understood by `parse_gltf`, the others being
+/// left out unless files require them.
+const EXTENSIONS: [&str; 3] = [
+    "KHR_lights_punctual",
+    "KHR_materials_emissive_strength",
+    "KHR_materials_transmission",
+];
+
+/// Adds the default scene of the glTF 2.0 file `bytes`, JSON or binary, to
+/// `scene`: its meshes, smooth shaded where they have normals, its point,
+/// spot and directional lights, and its first perspective camera if the
+/// scene has none. Buffers in other files are relative to `directory`.
+///
+/// Materials are principled, from the base color, metallic and roughness
+/// factors and the transmission factor of `KHR_materials_transmission`.
+/// Their base color, metallic-roughness, emissive and normal textures are
+/// looked up by the first texture coordinates of meshes, repeating across
+/// and clamped up and down whatever their samplers say, and must be PNGs.
+/// Occlusion textures are left out. Emissive materials become lights,
+/// following `KHR_materials_emissive_strength`, or glow with their
+/// textures. Lengths are converted from meters into the units of the
+/// scene.
+pub fn parse_gltf(bytes: &[u8], directory: &Path, scene: &mut Scene) -> std::io::Result<()> {
+    let (document, binary) = if bytes.starts_with(b"glTF") {
+        split_glb(bytes)?
+    } else {
+        (parse_json(bytes)?, None)
+    };
+    let version = document
+        .get("asset")
+        .and_then(|asset| asset.get("version"))
+        .and_then(Json::string);
+    if !version.is_some_and(|version| version.starts_with("2.")) {
+        return Err(invalid("only glTF 2.0 is supported"));
+    }
+    for required in document.list("extensionsRequired") {
+        let name = required.string().unwrap_or("");
+        if !EXTENSIONS.contains(&name) {
+            return Err(invalid(&format!("glTF extension {} isn't supported", name)));
+        }
+    }
+
+    let mut buffers = Vec::new();
+    for (index, buffer) in document.list("buffers").iter().enumerate() {
+        let bytes = match buffer.get("uri").and_then(Json::string) {
+            None if index == 0 && binary.is_some() => binary.unwrap_or(&[]).to_vec(),
+            None => return Err(invalid("glTF buffer without data")),
+            Some(uri) => read_uri(uri, directory)?,
+        };
+        if bytes.len() < buffer.index_of("byteLength")?.unwrap_or(0) {
+            return Err(invalid("glTF buffer shorter than its length"));
+        }
+        buffers.push(bytes);
+    }
+
+    let factor = scene.units.from_meters(1.0);
+    let mut importer = Importer {
+        document: &document,
+        directory,
+        buffers,
+        materials: HashMap::new(),
+        textures: HashMap::new(),
+        added: vec![false; document.list("nodes").len()],
+        factor,
+    };
+    let index = document.index_of("scene")?.unwrap_or(0);
+    let placement = Mat4::scaling(Vec3::new(factor, factor, factor));
+    for root in importer.item("scenes", index)?.list("nodes") {
+        let root = root
+            .index()
+            .ok_or_else(|| invalid("glTF scene node isn't an index"))?;
+        importer.add_node(scene, root, &placement, 0)?;
+    }
+    Ok(())
+}
+
+/// Adds the glTF 2.0 file `name`, a `.gltf` or a `.glb`, to `scene`, see
+/// `parse_gltf`.
+pub fn load_gltf(name: &str, scene: &mut Scene) -> std::io::Result<()> {
+    let mut bytes = Vec::new();
+    File::open(name)?.read_to_end(&mut bytes)?;
+    let directory = Path::new(name).parent().unwrap_or_else(|| Path::new(""));
+    parse_gltf(&bytes, directory, scene)
+}
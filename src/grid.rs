@@ -0,0 +1,341 @@
+use crate::bvh::Aabb;
+use crate::hitable::Hitable;
+use crate::maths::*;
+use crate::ray::{Ray, RayExt};
+use crate::sphere::Sphere;
+
+/// Cells per item the grid aims for.
+const CELLS_PER_ITEM: Float = 3.0;
+
+/// Most cells along an axis.
+const MAX_RESOLUTION: usize = 128;
+
+/// Times the median size past which items are left out of the cells.
+const LARGE_ITEM_SIZE: Float = 16.0;
+
+/// Uniform grid over items given by their bounding boxes, each cell listing
+/// the items whose box overlaps it. Built in two passes over the items, so
+/// far faster than a `Bvh` or a `KdTree`, which suits scenes rebuilt every
+/// frame, like animation previews. Rays step from cell to cell in order by
+/// 3D DDA (Amanatides and Woo, "A Fast Voxel Traversal Algorithm for Ray
+/// Tracing"), stopping at the first cell whose far side is past the
+/// closest hit, but crowded cells don't get split, so grids suit items of
+/// similar sizes spread evenly. Items far larger than most, like the ground,
+/// are tested by every ray instead, so they don't stretch the cells.
+#[derive(Clone, Debug)]
+pub struct Grid {
+    bounds: Aabb,
+    /// Box around the items of the cells, which the cells divide.
+    grid_bounds: Aabb,
+    /// Items left out of the cells.
+    large: Vec<usize>,
+    /// Cells along x, y and z.
+    resolution: [usize; 3],
+    cell_size: [Float; 3],
+    /// Items of cell `i` are `items[offsets[i]..offsets[i + 1]]`, cells
+    /// going along x, then y, then z.
+    offsets: Vec<usize>,
+    items: Vec<usize>,
+}
+
+impl Grid {
+    /// Grid over the items of `boxes`, numbered by their position in it.
+    pub fn new(boxes: &[Aabb]) -> Self {
+        let bounds = boxes
+            .iter()
+            .fold(Aabb::empty(), |bounds, &item| bounds.merge(item));
+        let size = |item: &Aabb| {
+            let extent = item.max - item.min;
+            extent.x.max(extent.y).max(extent.z)
+        };
+        let mut sizes: Vec<Float> = boxes.iter().map(size).collect();
+        let median = match sizes.len() {
+            0 => 0.0,
+            count => *sizes.select_nth_unstable_by(count / 2, Float::total_cmp).1,
+        };
+        let (large, small): (Vec<usize>, Vec<usize>) = (0..boxes.len())
+            .partition(|&item| median > 0.0 && size(&boxes[item]) > LARGE_ITEM_SIZE * median);
+        let grid_bounds = small
+            .iter()
+            .fold(Aabb::empty(), |bounds, &item| bounds.merge(boxes[item]));
+        let mut grid = Grid {
+            bounds,
+            grid_bounds,
+            large,
+            resolution: [0; 3],
+            cell_size: [1.0; 3],
+            offsets: vec![0],
+            items: Vec::new(),
+        };
+        if small.is_empty() {
+            return grid;
+        }
+
+        // Cells about as wide along every axis, flat scenes getting a single
+        // layer of them.
+        let extent = grid_bounds.max - grid_bounds.min;
+        let extent = [extent.x, extent.y, extent.z];
+        let largest = extent[0].max(extent[1]).max(extent[2]).max(1e-9);
+        let volume: Float = extent
+            .iter()
+            .map(|&side| side.max(largest * 1e-3))
+            .product();
+        let cell_width = (volume / (CELLS_PER_ITEM * small.len() as Float)).cbrt();
+        for (axis, &side) in extent.iter().enumerate() {
+            grid.resolution[axis] = ((side / cell_width).round() as usize).clamp(1, MAX_RESOLUTION);
+            grid.cell_size[axis] = (side / grid.resolution[axis] as Float).max(1e-9);
+        }
+
+        // Counts the items of every cell, then lays them out one after the
+        // other.
+        let cells = grid.resolution.iter().product::<usize>();
+        let mut counts = vec![0; cells + 1];
+        for &item in &small {
+            grid.for_cells(&boxes[item], |cell| counts[cell + 1] += 1);
+        }
+        for cell in 0..cells {
+            counts[cell + 1] += counts[cell];
+        }
+        let mut next = counts.clone();
+        let mut items = vec![0; counts[cells]];
+        for &item in &small {
+            grid.for_cells(&boxes[item], |cell| {
+                items[next[cell]] = item;
+                next[cell] += 1;
+            });
+        }
+        grid.offsets = counts;
+        grid.items = items;
+        grid
+    }
+
+    /// Box around every item.
+    pub fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    /// Cells along x, y and z.
+    pub fn resolution(&self) -> [usize; 3] {
+        self.resolution
+    }
+
+    /// Cell holding `coordinate` along `axis`, those outside the grid
+    /// clamped to its sides.
+    fn cell_along(&self, coordinate: Float, axis: usize) -> usize {
+        let min = component(self.grid_bounds.min, axis);
+        let cell = ((coordinate - min) / self.cell_size[axis]).floor();
+        (cell.max(0.0) as usize).min(self.resolution[axis] - 1)
+    }
+
+    fn cell_index(&self, [x, y, z]: [usize; 3]) -> usize {
+        (z * self.resolution[1] + y) * self.resolution[0] + x
+    }
+
+    /// Calls `visit` with the index of every cell `item` overlaps.
+    fn for_cells(&self, item: &Aabb, mut visit: impl FnMut(usize)) {
+        let low: Vec<usize> = (0..3)
+            .map(|axis| self.cell_along(component(item.min, axis), axis))
+            .collect();
+        let high: Vec<usize> = (0..3)
+            .map(|axis| self.cell_along(component(item.max, axis), axis))
+            .collect();
+        for z in low[2]..=high[2] {
+            for y in low[1]..=high[1] {
+                for x in low[0]..=high[0] {
+                    visit(self.cell_index([x, y, z]));
+                }
+            }
+        }
+    }
+
+    fn cell_items(&self, cell: usize) -> &[usize] {
+        &self.items[self.offsets[cell]..self.offsets[cell + 1]]
+    }
+
+    /// Closest hit of `ray` in (`t_min`, `t_max`), as the item and the ray
+    /// parameter, `test` giving the parameter at which `ray` hits an item
+    /// before the closest hit so far, its second argument. Items spanning
+    /// several cells may be tested more than once.
+    pub fn hit_with(
+        &self,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+        mut test: impl FnMut(usize, Float) -> Option<Float>,
+    ) -> Option<(usize, Float)> {
+        let mut closest: Option<(usize, Float)> = None;
+        let mut closest_t = t_max;
+        for &item in &self.large {
+            if let Some(t) = test(item, closest_t) {
+                closest_t = t;
+                closest = Some((item, t));
+            }
+        }
+        if self.items.is_empty() {
+            return closest;
+        }
+        let near = match self.grid_bounds.clip(ray, t_min, closest_t) {
+            Some((near, _)) => near,
+            None => return closest,
+        };
+
+        let origin = [ray.origin.x, ray.origin.y, ray.origin.z];
+        let dir = [ray.dir.x, ray.dir.y, ray.dir.z];
+        let inverse = ray.inverse_dir();
+        let inverse = [inverse.x, inverse.y, inverse.z];
+        let start = ray.at(near);
+        let mut cell = [0; 3];
+        let mut step = [0isize; 3];
+        // Parameters where the ray leaves the current cell along each axis,
+        // and crosses a whole cell.
+        let mut next = [Float::INFINITY; 3];
+        let mut delta = [Float::INFINITY; 3];
+        for axis in 0..3 {
+            cell[axis] = self.cell_along(component(start, axis), axis);
+            if dir[axis] == 0.0 {
+                continue;
+            }
+            let min = component(self.grid_bounds.min, axis);
+            let side = if dir[axis] > 0.0 {
+                step[axis] = 1;
+                cell[axis] + 1
+            } else {
+                step[axis] = -1;
+                cell[axis]
+            };
+            let plane = min + side as Float * self.cell_size[axis];
+            next[axis] = (plane - origin[axis]) * inverse[axis];
+            delta[axis] = self.cell_size[axis] * inverse[axis].abs();
+        }
+
+        loop {
+            for &item in self.cell_items(self.cell_index(cell)) {
+                if let Some(t) = test(item, closest_t) {
+                    closest_t = t;
+                    closest = Some((item, t));
+                }
+            }
+            let axis = if next[0] < next[1] {
+                if next[0] < next[2] {
+                    0
+                } else {
+                    2
+                }
+            } else if next[1] < next[2] {
+                1
+            } else {
+                2
+            };
+            // Cells further along can't hold anything closer.
+            if closest_t <= next[axis] {
+                return closest;
+            }
+            let moved = cell[axis] as isize + step[axis];
+            if moved < 0 || moved >= self.resolution[axis] as isize {
+                return closest;
+            }
+            cell[axis] = moved as usize;
+            next[axis] += delta[axis];
+        }
+    }
+
+    /// Items whose box may hold `point`, each once.
+    pub fn candidates(&self, point: Vec3) -> Vec<usize> {
+        let mut candidates = self.large.clone();
+        if !self.items.is_empty() && self.grid_bounds.contains(point) {
+            let cell = [
+                self.cell_along(point.x, 0),
+                self.cell_along(point.y, 1),
+                self.cell_along(point.z, 2),
+            ];
+            candidates.extend_from_slice(self.cell_items(self.cell_index(cell)));
+        }
+        candidates
+    }
+}
+
+fn component(vector: Vec3, axis: usize) -> Float {
+    match axis {
+        0 => vector.x,
+        1 => vector.y,
+        _ => vector.z,
+    }
+}
+
+/// `Grid` over spheres of a scene, which it keeps copies of, and the other
+/// objects of the scene, by their bounding spheres, rays testing them
+/// through the callback of `hit_with`.
+pub struct SphereGrid {
+    grid: Grid,
+    spheres: Vec<Sphere>,
+    /// Index in the scene of every object of the grid.
+    indices: Vec<usize>,
+    /// Whether every object of the grid is tested through the callback.
+    is_shape: Vec<bool>,
+}
+
+impl SphereGrid {
+    pub fn new(spheres: &[Sphere]) -> Self {
+        SphereGrid::of_indices(spheres, (0..spheres.len()).collect())
+    }
+
+    /// Grid over the spheres of `indices` alone, leaving the others to the
+    /// caller.
+    pub fn of_indices(spheres: &[Sphere], indices: Vec<usize>) -> Self {
+        SphereGrid::of_objects(spheres, indices, Vec::new())
+    }
+
+    /// Grid over the spheres of `indices` and the objects of `shapes`, which
+    /// `spheres` only bounds, leaving the others to the caller.
+    pub fn of_objects(spheres: &[Sphere], mut indices: Vec<usize>, shapes: Vec<usize>) -> Self {
+        let mut is_shape = vec![false; indices.len()];
+        is_shape.resize(indices.len() + shapes.len(), true);
+        indices.extend(shapes);
+        let spheres: Vec<Sphere> = indices.iter().map(|&index| spheres[index]).collect();
+        let boxes: Vec<Aabb> = spheres.iter().map(Aabb::of_sphere).collect();
+        SphereGrid {
+            grid: Grid::new(&boxes),
+            spheres,
+            indices,
+            is_shape,
+        }
+    }
+
+    /// Closest hit of `ray` in (`t_min`, `t_max`), as the index of the sphere
+    /// and the ray parameter, the one of `Sphere::hit`.
+    pub fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<(usize, Float)> {
+        self.hit_with(ray, t_min, t_max, |_, _| None)
+    }
+
+    /// Like `hit`, `test` giving the parameter at which `ray` hits an object
+    /// other than a sphere before the closest hit so far, its second
+    /// argument.
+    pub fn hit_with(
+        &self,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+        mut test: impl FnMut(usize, Float) -> Option<Float>,
+    ) -> Option<(usize, Float)> {
+        self.grid
+            .hit_with(ray, t_min, t_max, |item, closest_t| {
+                if self.is_shape[item] {
+                    return test(self.indices[item], closest_t);
+                }
+                self.spheres[item]
+                    .hit(ray, t_min, closest_t)
+                    .map(|record| record.t)
+            })
+            .map(|(item, t)| (self.indices[item], t))
+    }
+
+    /// Indices of the objects whose bounding box may hold `point`, each
+    /// once.
+    pub fn candidates(&self, point: Vec3) -> Vec<usize> {
+        self.grid
+            .candidates(point)
+            .into_iter()
+            .map(|item| self.indices[item])
+            .collect()
+    }
+}
@@ -0,0 +1,348 @@
+use crate::material::BounceKind;
+use crate::maths::consts::PI;
+use crate::maths::*;
+
+/// Number of lobes followed on their own: light reflected off the fiber,
+/// through it, and reflected once inside. The rest is lumped together.
+const P_MAX: usize = 3;
+
+/// Hair and fur fibers, after Chiang et al. 2016, "A Practical and
+/// Controllable Hair and Fur Model for Production Path Tracing", as pbrt
+/// implements it.
+///
+/// Directions are given in the frame of the fiber: x along it, z facing
+/// the outgoing direction across it and y across both. Where the fiber is
+/// hit, `h`, goes from -1 to 1 across its width, as seen from the outgoing
+/// direction.
+#[derive(Clone, Copy, Debug)]
+pub struct Hair {
+    /// Color of a lock of the fiber, which sets how much it absorbs.
+    pub color: Vec3,
+    /// Roughness along the fiber, from 0 to 1, which blurs the highlights
+    /// along it.
+    pub longitudinal_roughness: Float,
+    /// Roughness around the fiber, from 0 to 1, which spreads light around
+    /// it.
+    pub azimuthal_roughness: Float,
+    /// Tilt of the scales of the fiber, in degrees, shifting its highlights.
+    pub scale_angle: Float,
+    pub refractive_index: Float,
+}
+
+impl Hair {
+    /// Human hair of `color`, with the scales and index of real hair.
+    pub fn new(color: Vec3, longitudinal_roughness: Float, azimuthal_roughness: Float) -> Self {
+        Hair {
+            color,
+            longitudinal_roughness,
+            azimuthal_roughness,
+            scale_angle: 2.0,
+            refractive_index: 1.55,
+        }
+    }
+
+    /// Absorption per unit of the diameter of the fiber giving a lock of it
+    /// its `color`.
+    pub fn absorption(&self) -> Vec3 {
+        let beta_n = clamp(self.azimuthal_roughness, 0.0, 1.0);
+        let fit = 5.969 - 0.215 * beta_n + 2.532 * beta_n.powi(2) - 10.73 * beta_n.powi(3)
+            + 5.574 * beta_n.powi(4)
+            + 0.245 * beta_n.powi(5);
+        let channel = |color: Float| (clamp(color, 1e-4, 1.0).ln() / fit).powi(2);
+        Vec3::new(
+            channel(self.color.x),
+            channel(self.color.y),
+            channel(self.color.z),
+        )
+    }
+
+    /// BSDF times the cosine towards `wi`, and the density `sample` picks
+    /// `wi` with, for light leaving towards `wo`.
+    pub fn eval(&self, wo: Vec3, wi: Vec3, h: Float) -> (Vec3, Float) {
+        let lobes = Lobes::new(self, wo, h);
+        let (sin_theta_i, cos_theta_i, phi_i) = angles(wi);
+        let phi = phi_i - lobes.phi_o;
+        let weights = lobes.weights();
+
+        let mut value = Vec3::new(0.0, 0.0, 0.0);
+        let mut pdf = 0.0;
+        for (p, weight) in weights.iter().enumerate() {
+            let (sin_theta_op, cos_theta_op) = lobes.tilted(p);
+            let m = longitudinal(
+                (sin_theta_i, cos_theta_i),
+                (sin_theta_op, cos_theta_op),
+                lobes.variances[p],
+            );
+            let n = if p < P_MAX {
+                azimuthal(phi, p, lobes.spread, lobes.gamma_o, lobes.gamma_t)
+            } else {
+                1.0 / (2.0 * PI)
+            };
+            value += lobes.attenuations[p] * (m * n);
+            pdf += weight * m * n;
+        }
+        (value, pdf)
+    }
+
+    /// Direction light leaving towards `wo` comes from, from uniform numbers
+    /// picking the lobe, then the angles along and around the fiber.
+    pub fn sample(
+        &self,
+        wo: Vec3,
+        h: Float,
+        lobe: Float,
+        (u1, u2): (Float, Float),
+        around: Float,
+    ) -> (Vec3, BounceKind) {
+        let lobes = Lobes::new(self, wo, h);
+        let weights = lobes.weights();
+        let mut p = 0;
+        let mut rest = lobe;
+        while p < P_MAX && rest >= weights[p] {
+            rest -= weights[p];
+            p += 1;
+        }
+
+        // Longitudinal angle around the tilted outgoing one.
+        let (sin_theta_op, cos_theta_op) = lobes.tilted(p);
+        let v = lobes.variances[p];
+        let u1 = u1.max(1e-5);
+        let cos_theta = 1.0 + v * (u1 + (1.0 - u1) * (-2.0 / v).exp()).ln();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let cos_phi = (2.0 * PI * u2).cos();
+        let sin_theta_i = clamp(
+            -cos_theta * sin_theta_op + sin_theta * cos_phi * cos_theta_op,
+            -1.0,
+            1.0,
+        );
+        let cos_theta_i = (1.0 - sin_theta_i * sin_theta_i).max(0.0).sqrt();
+
+        let dphi = if p < P_MAX {
+            phi(p, lobes.gamma_o, lobes.gamma_t) + sample_trimmed_logistic(around, lobes.spread)
+        } else {
+            2.0 * PI * around
+        };
+        let phi_i = lobes.phi_o + dphi;
+        let wi = Vec3::new(
+            sin_theta_i,
+            cos_theta_i * phi_i.cos(),
+            cos_theta_i * phi_i.sin(),
+        );
+        // Light going through the fiber doesn't enter a medium, so counts
+        // as glossy like the highlights.
+        let kind = if p == P_MAX {
+            BounceKind::Diffuse
+        } else {
+            BounceKind::Glossy
+        };
+        (wi, kind)
+    }
+}
+
+/// What the lobes of a `Hair` share for one outgoing direction and offset.
+struct Lobes {
+    sin_theta_o: Float,
+    cos_theta_o: Float,
+    phi_o: Float,
+    gamma_o: Float,
+    gamma_t: Float,
+    /// Variance of the longitudinal distribution of every lobe.
+    variances: [Float; P_MAX + 1],
+    /// Scale of the azimuthal distributions.
+    spread: Float,
+    /// Sines and cosines of once, twice and four times the scale angle.
+    scale_rotations: [(Float, Float); 3],
+    attenuations: [Vec3; P_MAX + 1],
+}
+
+impl Lobes {
+    fn new(hair: &Hair, wo: Vec3, h: Float) -> Self {
+        let h = clamp(h, -1.0, 1.0);
+        let eta = hair.refractive_index;
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+
+        // Refraction into the fiber, seen along it and across it.
+        let sin_theta_t = sin_theta_o / eta;
+        let cos_theta_t = (1.0 - sin_theta_t * sin_theta_t).max(0.0).sqrt();
+        let eta_p = (eta * eta - sin_theta_o * sin_theta_o).sqrt() / cos_theta_o.max(1e-6);
+        let sin_gamma_t = clamp(h / eta_p, -1.0, 1.0);
+        let cos_gamma_t = (1.0 - sin_gamma_t * sin_gamma_t).max(0.0).sqrt();
+        let gamma_t = sin_gamma_t.asin();
+
+        // Light going through the fiber once.
+        let path = 2.0 * cos_gamma_t / cos_theta_t.max(1e-6);
+        let sigma = hair.absorption();
+        let through = Vec3::new(
+            (-sigma.x * path).exp(),
+            (-sigma.y * path).exp(),
+            (-sigma.z * path).exp(),
+        );
+        let cos_gamma_o = (1.0 - h * h).max(0.0).sqrt();
+        let f = fresnel_dielectric(cos_theta_o * cos_gamma_o, eta);
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let mut attenuations = [white * f; P_MAX + 1];
+        attenuations[1] = through * ((1.0 - f) * (1.0 - f));
+        for p in 2..P_MAX {
+            attenuations[p] = attenuations[p - 1] * through * f;
+        }
+        // Geometric series of the bounces left.
+        let rest = |through: Float| through * f / (1.0 - through * f);
+        attenuations[P_MAX] =
+            attenuations[P_MAX - 1] * Vec3::new(rest(through.x), rest(through.y), rest(through.z));
+
+        let beta_m = clamp(hair.longitudinal_roughness, 0.0, 1.0);
+        let v = (0.726 * beta_m + 0.812 * beta_m * beta_m + 3.7 * beta_m.powi(20)).powi(2);
+        let v = v.max(1e-4);
+        let beta_n = clamp(hair.azimuthal_roughness, 0.0, 1.0);
+        let spread = (PI / 8.0).sqrt()
+            * (0.265 * beta_n + 1.194 * beta_n * beta_n + 5.372 * beta_n.powi(22));
+
+        let sin_alpha = hair.scale_angle.to_radians().sin();
+        let cos_alpha = (1.0 - sin_alpha * sin_alpha).max(0.0).sqrt();
+        let mut scale_rotations = [(sin_alpha, cos_alpha); 3];
+        for k in 1..3 {
+            let (sin, cos) = scale_rotations[k - 1];
+            scale_rotations[k] = (2.0 * cos * sin, cos * cos - sin * sin);
+        }
+
+        Lobes {
+            sin_theta_o,
+            cos_theta_o,
+            phi_o,
+            gamma_o: h.asin(),
+            gamma_t,
+            variances: [v, 0.25 * v, 4.0 * v, 4.0 * v],
+            spread: spread.max(1e-3),
+            scale_rotations,
+            attenuations,
+        }
+    }
+
+    /// Outgoing longitudinal angle of lobe `p`, tilted by the scales.
+    fn tilted(&self, p: usize) -> (Float, Float) {
+        let (sin_o, cos_o) = (self.sin_theta_o, self.cos_theta_o);
+        let (sin_theta, cos_theta) = match p {
+            0 => {
+                let (sin, cos) = self.scale_rotations[1];
+                (sin_o * cos - cos_o * sin, cos_o * cos + sin_o * sin)
+            }
+            1 => {
+                let (sin, cos) = self.scale_rotations[0];
+                (sin_o * cos + cos_o * sin, cos_o * cos - sin_o * sin)
+            }
+            2 => {
+                let (sin, cos) = self.scale_rotations[2];
+                (sin_o * cos + cos_o * sin, cos_o * cos - sin_o * sin)
+            }
+            _ => (sin_o, cos_o),
+        };
+        (sin_theta, cos_theta.abs())
+    }
+
+    /// Chances of sampling each lobe, following the luminance it carries.
+    fn weights(&self) -> [Float; P_MAX + 1] {
+        let mut weights = [0.0; P_MAX + 1];
+        for (weight, attenuation) in weights.iter_mut().zip(&self.attenuations) {
+            *weight = luminance(*attenuation).max(0.0);
+        }
+        let total: Float = weights.iter().sum();
+        if total > 0.0 {
+            for weight in &mut weights {
+                *weight /= total;
+            }
+        } else {
+            weights = [1.0 / (P_MAX + 1) as Float; P_MAX + 1];
+        }
+        weights
+    }
+}
+
+/// Sine and cosine of the angle of `w` from the plane across the fiber, and
+/// its angle around the fiber.
+fn angles(w: Vec3) -> (Float, Float, Float) {
+    let sin_theta = clamp(w.x, -1.0, 1.0);
+    let cos_theta = (1.0 - sin_theta * sin_theta).max(0.0).sqrt();
+    (sin_theta, cos_theta, w.z.atan2(w.y))
+}
+
+/// Fresnel reflectance of a dielectric of relative index `eta`.
+fn fresnel_dielectric(cos_theta_i: Float, eta: Float) -> Float {
+    let cos_theta_i = clamp(cos_theta_i, 0.0, 1.0);
+    let sin_theta_t = (1.0 - cos_theta_i * cos_theta_i).max(0.0).sqrt() / eta;
+    if sin_theta_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin_theta_t * sin_theta_t).max(0.0).sqrt();
+    let parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (parallel * parallel + perpendicular * perpendicular) / 2.0
+}
+
+/// Modified Bessel function of the first kind, of order 0.
+fn bessel_i0(x: Float) -> Float {
+    let mut value = 0.0;
+    let (mut x_2i, mut factorial, mut four_i) = (1.0, 1.0, 1.0);
+    for i in 0..10 {
+        if i > 1 {
+            factorial *= i as Float;
+        }
+        value += x_2i / (four_i * factorial * factorial);
+        x_2i *= x * x;
+        four_i *= 4.0;
+    }
+    value
+}
+
+fn log_bessel_i0(x: Float) -> Float {
+    if x > 12.0 {
+        x + 0.5 * (-(2.0 * PI).ln() + (1.0 / x).ln() + 1.0 / (8.0 * x))
+    } else {
+        bessel_i0(x).ln()
+    }
+}
+
+/// Longitudinal scattering from the incoming angle to the outgoing one, of
+/// variance `v`.
+fn longitudinal(
+    (sin_theta_i, cos_theta_i): (Float, Float),
+    (sin_theta_o, cos_theta_o): (Float, Float),
+    v: Float,
+) -> Float {
+    let a = cos_theta_i * cos_theta_o / v;
+    let b = sin_theta_i * sin_theta_o / v;
+    if v <= 0.1 {
+        (log_bessel_i0(a) - b - 1.0 / v + consts::LN_2 + (1.0 / (2.0 * v)).ln()).exp()
+    } else {
+        (-b).exp() * bessel_i0(a) / ((1.0 / v).sinh() * 2.0 * v)
+    }
+}
+
+/// Angle around the fiber lobe `p` leaves at.
+fn phi(p: usize, gamma_o: Float, gamma_t: Float) -> Float {
+    let p = p as Float;
+    2.0 * p * gamma_t - 2.0 * gamma_o + p * PI
+}
+
+fn logistic(x: Float, s: Float) -> Float {
+    let x = x.abs();
+    let e = (-x / s).exp();
+    e / (s * (1.0 + e) * (1.0 + e))
+}
+
+fn logistic_cdf(x: Float, s: Float) -> Float {
+    1.0 / (1.0 + (-x / s).exp())
+}
+
+/// Azimuthal scattering of lobe `p` at `dphi` around the fiber.
+fn azimuthal(dphi: Float, p: usize, s: Float, gamma_o: Float, gamma_t: Float) -> Float {
+    let mut x = dphi - phi(p, gamma_o, gamma_t);
+    x = (x + PI).rem_euclid(2.0 * PI) - PI;
+    logistic(x, s) / (logistic_cdf(PI, s) - logistic_cdf(-PI, s))
+}
+
+/// Logistic of scale `s` trimmed to [-π, π], from a uniform number.
+fn sample_trimmed_logistic(u: Float, s: Float) -> Float {
+    let k = logistic_cdf(PI, s) - logistic_cdf(-PI, s);
+    let x = -s * (1.0 / (u * k + logistic_cdf(-PI, s)) - 1.0).ln();
+    clamp(x, -PI, PI)
+}
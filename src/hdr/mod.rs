@@ -0,0 +1,162 @@
+use std::fs::File;
+use std::io::prelude::*;
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+fn rgbe_to_rgb(rgbe: &[u8]) -> [f32; 3] {
+    if rgbe[3] == 0 {
+        return [0.0; 3];
+    }
+
+    let scale = 2f32.powi(rgbe[3] as i32 - 136);
+    [
+        (rgbe[0] as f32 + 0.5) * scale,
+        (rgbe[1] as f32 + 0.5) * scale,
+        (rgbe[2] as f32 + 0.5) * scale,
+    ]
+}
+
+/// Decodes one new-style run length encoded scanline, each of the four
+/// components being encoded separately.
+fn read_rle_scanline(
+    data: &[u8],
+    position: &mut usize,
+    scanline: &mut [u8],
+) -> std::io::Result<()> {
+    let width = scanline.len() / 4;
+
+    for component in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = *data
+                .get(*position)
+                .ok_or_else(|| invalid_data("truncated scanline"))?
+                as usize;
+            *position += 1;
+
+            if count > 128 {
+                // A run of the same value.
+                let count = count - 128;
+                let value = *data
+                    .get(*position)
+                    .ok_or_else(|| invalid_data("truncated scanline"))?;
+                *position += 1;
+                if x + count > width {
+                    return Err(invalid_data("scanline overrun"));
+                }
+                for i in x..x + count {
+                    scanline[i * 4 + component] = value;
+                }
+                x += count;
+            } else {
+                if count == 0 || x + count > width {
+                    return Err(invalid_data("scanline overrun"));
+                }
+                let values = data
+                    .get(*position..*position + count)
+                    .ok_or_else(|| invalid_data("truncated scanline"))?;
+                *position += count;
+                for (i, &value) in values.iter().enumerate() {
+                    scanline[(x + i) * 4 + component] = value;
+                }
+                x += count;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a Radiance RGBE (`.hdr`) file as RGB floats, rows from top to bottom.
+///
+/// Only the usual `-Y H +X W` orientation is supported, with either flat or
+/// new-style run length encoded scanlines.
+pub fn read_hdr(name: &str) -> std::io::Result<(Vec<f32>, u32, u32)> {
+    let mut bytes = Vec::new();
+    File::open(name)?.read_to_end(&mut bytes)?;
+    decode_hdr(&bytes)
+}
+
+/// Decodes the content of a Radiance RGBE file, as `read_hdr`.
+pub fn decode_hdr(bytes: &[u8]) -> std::io::Result<(Vec<f32>, u32, u32)> {
+    if !bytes.starts_with(b"#?") {
+        return Err(invalid_data("not a Radiance HDR file"));
+    }
+
+    // Header lines end with an empty line, followed by the resolution line.
+    let mut position = 0;
+    let mut lines = Vec::new();
+    loop {
+        let end = bytes[position..]
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .ok_or_else(|| invalid_data("truncated header"))?;
+        let line = String::from_utf8_lossy(&bytes[position..position + end]).into_owned();
+        position += end + 1;
+
+        if !lines.is_empty() && line.starts_with(['-', '+']) {
+            lines.push(line);
+            break;
+        }
+        lines.push(line);
+    }
+
+    for line in &lines {
+        if line.starts_with("FORMAT=") && line.trim() != "FORMAT=32-bit_rle_rgbe" {
+            return Err(invalid_data("unsupported pixel format"));
+        }
+    }
+
+    let resolution: Vec<&str> = lines[lines.len() - 1].split_whitespace().collect();
+    let (height, width) = match resolution.as_slice() {
+        ["-Y", height, "+X", width] => (
+            height
+                .parse::<u32>()
+                .map_err(|_| invalid_data("invalid height"))?,
+            width
+                .parse::<u32>()
+                .map_err(|_| invalid_data("invalid width"))?,
+        ),
+        _ => return Err(invalid_data("unsupported image orientation")),
+    };
+
+    // Runs hold at most 127 pixels in two bytes, which bounds the size of an
+    // image the remaining data can describe.
+    let remaining = bytes.len() - position;
+    if width as usize > remaining * 64 || height as usize > remaining {
+        return Err(invalid_data("truncated pixel data"));
+    }
+
+    let mut pixels = Vec::new();
+    let mut scanline = vec![0u8; width as usize * 4];
+    for _ in 0..height {
+        let rle = match bytes.get(position..position + 4) {
+            Some(&[2, 2, high, low]) if (8..0x8000).contains(&width) && high & 0x80 == 0 => {
+                Some(((high as usize) << 8) | low as usize)
+            }
+            _ => None,
+        };
+
+        if let Some(encoded_width) = rle {
+            if encoded_width != width as usize {
+                return Err(invalid_data("scanline width mismatch"));
+            }
+            position += 4;
+            read_rle_scanline(bytes, &mut position, &mut scanline)?;
+        } else {
+            let flat = bytes
+                .get(position..position + scanline.len())
+                .ok_or_else(|| invalid_data("truncated pixel data"))?;
+            scanline.copy_from_slice(flat);
+            position += scanline.len();
+        }
+
+        for rgbe in scanline.chunks(4) {
+            pixels.extend_from_slice(&rgbe_to_rgb(rgbe));
+        }
+    }
+
+    Ok((pixels, width, height))
+}
@@ -0,0 +1,410 @@
+use crate::bvh::Aabb;
+use crate::content_hash::*;
+use crate::hitable::*;
+use crate::material::MaterialId;
+use crate::maths::*;
+use crate::ray::{Ray, RayExt};
+use crate::sphere::{Sphere, UvFrame};
+use crate::texture::Texture;
+
+use std::sync::Arc;
+
+/// Terrain over a rectangle of the xz plane, its heights sampled on a regular
+/// grid. Every cell of the grid is split into two flat triangles along the
+/// diagonal from its lowest x and z corner, so landscapes take a number per
+/// sample rather than meshes. Rays walk the cells they cross, skipping those
+/// whose heights they pass over or under. Texture coordinates go from 0 to 1
+/// along x and z, like a map seen from above with -z at the top.
+#[derive(Clone, Debug)]
+pub struct Heightfield {
+    /// Corner with the lowest x and z, at the height of samples at 0.
+    pub origin: Vec3,
+    /// Size along x and z.
+    pub size: (Float, Float),
+    /// Height of samples at 1 above the origin.
+    pub height: Float,
+    pub material: MaterialId,
+    columns: usize,
+    rows: usize,
+    /// Rows of samples from the lowest z, each from the lowest x.
+    samples: Arc<Vec<Float>>,
+    /// Lowest and highest sample around every cell, rows first like the
+    /// samples.
+    cell_ranges: Arc<Vec<(Float, Float)>>,
+    /// Lowest and highest sample.
+    range: (Float, Float),
+}
+
+impl Heightfield {
+    /// Heightfield of `columns` by `rows` `samples`, at least two each way,
+    /// stored in rows from the lowest z, each from the lowest x.
+    pub fn new(
+        samples: Vec<Float>,
+        (columns, rows): (usize, usize),
+        origin: Vec3,
+        size: (Float, Float),
+        height: Float,
+        material: MaterialId,
+    ) -> Self {
+        assert!(columns >= 2 && rows >= 2, "heightfields need 2x2 samples");
+        assert_eq!(samples.len(), columns * rows);
+        let mut cell_ranges = Vec::with_capacity((columns - 1) * (rows - 1));
+        for j in 0..rows - 1 {
+            for i in 0..columns - 1 {
+                let corners = [
+                    samples[j * columns + i],
+                    samples[j * columns + i + 1],
+                    samples[(j + 1) * columns + i],
+                    samples[(j + 1) * columns + i + 1],
+                ];
+                let low = corners.iter().cloned().fold(Float::INFINITY, Float::min);
+                let high = corners
+                    .iter()
+                    .cloned()
+                    .fold(Float::NEG_INFINITY, Float::max);
+                cell_ranges.push((low, high));
+            }
+        }
+        let range = cell_ranges.iter().fold(
+            (Float::INFINITY, Float::NEG_INFINITY),
+            |(low, high), cell| (low.min(cell.0), high.max(cell.1)),
+        );
+        Heightfield {
+            origin,
+            size,
+            height,
+            material,
+            columns,
+            rows,
+            range,
+            samples: Arc::new(samples),
+            cell_ranges: Arc::new(cell_ranges),
+        }
+    }
+
+    /// Heightfield following the luminance of `texture`, one sample per
+    /// texel, its top row along the lowest z.
+    pub fn from_texture(
+        texture: &Texture,
+        origin: Vec3,
+        size: (Float, Float),
+        height: Float,
+        material: MaterialId,
+    ) -> Self {
+        let (columns, rows) = (texture.width().max(2), texture.height().max(2));
+        let samples = (0..rows)
+            .flat_map(|j| (0..columns).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                let x = i.min(texture.width() - 1);
+                let y = j.min(texture.height() - 1);
+                luminance(texture.pixel(x, y))
+            })
+            .collect();
+        Heightfield::new(samples, (columns, rows), origin, size, height, material)
+    }
+
+    /// Heightfield of `columns` by `rows` samples of `function`, given the
+    /// texture coordinates of each.
+    pub fn from_function(
+        function: impl Fn(Float, Float) -> Float,
+        (columns, rows): (usize, usize),
+        origin: Vec3,
+        size: (Float, Float),
+        height: Float,
+        material: MaterialId,
+    ) -> Self {
+        let samples = (0..rows)
+            .flat_map(|j| (0..columns).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                let u = i as Float / (columns - 1).max(1) as Float;
+                let v = j as Float / (rows - 1).max(1) as Float;
+                function(u, v)
+            })
+            .collect();
+        Heightfield::new(samples, (columns, rows), origin, size, height, material)
+    }
+
+    /// Number of samples along x and z.
+    pub fn resolution(&self) -> (usize, usize) {
+        (self.columns, self.rows)
+    }
+
+    /// Size of a cell along x and z.
+    fn cell_size(&self) -> (Float, Float) {
+        (
+            self.size.0 / (self.columns - 1) as Float,
+            self.size.1 / (self.rows - 1) as Float,
+        )
+    }
+
+    fn sample(&self, i: usize, j: usize) -> Float {
+        self.samples[j * self.columns + i] * self.height
+    }
+
+    /// Cell holding the point at `x`, `z` from the origin, clamped to the
+    /// grid, and the position of the point inside it, from 0 to 1.
+    fn locate(&self, x: Float, z: Float) -> ((usize, usize), (Float, Float)) {
+        let (width, depth) = self.cell_size();
+        let (gx, gz) = (x / width, z / depth);
+        let i = (gx.floor().max(0.0) as usize).min(self.columns - 2);
+        let j = (gz.floor().max(0.0) as usize).min(self.rows - 2);
+        ((i, j), (gx - i as Float, gz - j as Float))
+    }
+
+    /// Height above the origin at `x`, `z` from it, and its slopes along x
+    /// and z, following the triangle of the cell under the point.
+    fn surface(&self, x: Float, z: Float) -> (Float, Float, Float) {
+        let ((i, j), (fx, fz)) = self.locate(x, z);
+        let (rise_x, rise_z) = self.triangle_rises(i, j, fx >= fz);
+        let (width, depth) = self.cell_size();
+        let height = self.sample(i, j) + rise_x * fx + rise_z * fz;
+        (height, rise_x / width, rise_z / depth)
+    }
+
+    /// Rise of the plane of a triangle of cell (`i`, `j`) across the cell
+    /// along x and z, for the triangle below the diagonal when `lower`.
+    fn triangle_rises(&self, i: usize, j: usize, lower: bool) -> (Float, Float) {
+        let h00 = self.sample(i, j);
+        let h11 = self.sample(i + 1, j + 1);
+        if lower {
+            let h10 = self.sample(i + 1, j);
+            (h10 - h00, h11 - h10)
+        } else {
+            let h01 = self.sample(i, j + 1);
+            (h11 - h01, h01 - h00)
+        }
+    }
+
+    /// Upward unit normal of the terrain at `point`, above or below it.
+    pub fn normal_at(&self, point: Vec3) -> Vec3 {
+        let offset = point - self.origin;
+        let (_, slope_x, slope_z) = self.surface(offset.x, offset.z);
+        Vec3::new(-slope_x, 1.0, -slope_z).unit()
+    }
+
+    /// Texture coordinates of `point`, on the surface, with the frame normal
+    /// and bump maps bend normals in.
+    pub fn uv_frame(&self, point: Vec3) -> UvFrame {
+        let offset = point - self.origin;
+        let (_, slope_x, slope_z) = self.surface(offset.x, offset.z);
+        let u = offset.x / self.size.0;
+        let v = offset.z / self.size.1;
+        UvFrame {
+            uv: (u, 1.0 - v),
+            tangents: (
+                Vec3::new(1.0, slope_x, 0.0).unit(),
+                Vec3::new(0.0, -slope_z, -1.0).unit(),
+            ),
+            lengths: (
+                self.size.0 * slope_x.hypot(1.0),
+                self.size.1 * slope_z.hypot(1.0),
+            ),
+        }
+    }
+
+    /// Point and upward normal at texture coordinates (`u`, `v`).
+    pub fn surface_at(&self, u: Float, v: Float) -> (Vec3, Vec3) {
+        let (x, z) = (u * self.size.0, (1.0 - v) * self.size.1);
+        let (height, slope_x, slope_z) = self.surface(x, z);
+        let point = self.origin + Vec3::new(x, height, z);
+        (point, Vec3::new(-slope_x, 1.0, -slope_z).unit())
+    }
+
+    /// Lowest and highest points of the terrain.
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        let (low, high) = (self.range.0 * self.height, self.range.1 * self.height);
+        (
+            self.origin + Vec3::new(0.0, low.min(high), 0.0),
+            self.origin + Vec3::new(self.size.0, low.max(high), self.size.1),
+        )
+    }
+
+    pub fn bounding_sphere(&self) -> Sphere {
+        let (min, max) = self.bounds();
+        Sphere::new((min + max) / 2.0, (max - min).length() / 2.0, self.material)
+    }
+
+    /// Copy of the terrain moved by `transform`, following its translation
+    /// and average scale. Heightfields stay level.
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        let factor = transform.determinant3().abs().cbrt();
+        Heightfield {
+            origin: transform.transform_point(self.origin),
+            size: (self.size.0 * factor, self.size.1 * factor),
+            height: self.height * factor,
+            ..self.clone()
+        }
+    }
+
+    /// Closest hit of `ray` on the two triangles of cell (`i`, `j`).
+    fn hit_cell(
+        &self,
+        ray: &Ray,
+        (i, j): (usize, usize),
+        t_min: Float,
+        t_max: Float,
+    ) -> Option<HitRecord> {
+        // Lets rays through the edges between triangles and cells.
+        const TOLERANCE: Float = 1e-5;
+        let (width, depth) = self.cell_size();
+        let local = ray.origin - self.origin;
+        let fx0 = local.x / width - i as Float;
+        let fz0 = local.z / depth - j as Float;
+        let (dfx, dfz) = (ray.dir.x / width, ray.dir.z / depth);
+        let h00 = self.sample(i, j);
+
+        let mut closest: Option<(Float, Vec3)> = None;
+        for &lower in &[true, false] {
+            let (rise_x, rise_z) = self.triangle_rises(i, j, lower);
+            // Height above the plane of the triangle along the ray.
+            let above = local.y - h00 - rise_x * fx0 - rise_z * fz0;
+            let rate = ray.dir.y - rise_x * dfx - rise_z * dfz;
+            if rate == 0.0 {
+                continue;
+            }
+            let t = -above / rate;
+            if t <= t_min || t >= t_max || closest.is_some_and(|(best, _)| t >= best) {
+                continue;
+            }
+            let (fx, fz) = (fx0 + dfx * t, fz0 + dfz * t);
+            let inside = fx >= -TOLERANCE
+                && fz >= -TOLERANCE
+                && fx <= 1.0 + TOLERANCE
+                && fz <= 1.0 + TOLERANCE;
+            let on_side = if lower {
+                fx >= fz - TOLERANCE
+            } else {
+                fx <= fz + TOLERANCE
+            };
+            if inside && on_side {
+                let normal = Vec3::new(-rise_x / width, 1.0, -rise_z / depth);
+                closest = Some((t, normal));
+            }
+        }
+        let (t, normal) = closest?;
+        Some(HitRecord::new(ray, ray.at(t), normal, t, self.material))
+    }
+}
+
+impl Hitable for Heightfield {
+    fn scale(&mut self, factor: Float) {
+        self.origin = self.origin * factor;
+        self.size = (self.size.0 * factor, self.size.1 * factor);
+        self.height *= factor;
+    }
+
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let (min, max) = self.bounds();
+        let (t_enter, t_exit) = Aabb { min, max }.clip(ray, t_min, t_max)?;
+
+        // Walk the cells under the ray, in the coordinates of the grid.
+        let (width, depth) = self.cell_size();
+        let start = ray.at(t_enter) - self.origin;
+        let ((mut i, mut j), _) = self.locate(start.x, start.z);
+        let inverse_dir = ray.inverse_dir();
+        let [negative_x, _, negative_z] = ray.dir_is_negative();
+        let next_boundary = |cell: usize, size: Float, origin: Float, inverse: Float, negative| {
+            if inverse.is_infinite() {
+                Float::INFINITY
+            } else if negative {
+                (cell as Float * size - origin) * inverse
+            } else {
+                ((cell + 1) as Float * size - origin) * inverse
+            }
+        };
+        let local = ray.origin - self.origin;
+        let mut t_next_x = next_boundary(i, width, local.x, inverse_dir.x, negative_x);
+        let mut t_next_z = next_boundary(j, depth, local.z, inverse_dir.z, negative_z);
+        let t_delta_x = width * inverse_dir.x.abs();
+        let t_delta_z = depth * inverse_dir.z.abs();
+
+        let mut t_cell = t_enter;
+        loop {
+            let t_leave = t_next_x.min(t_next_z).min(t_exit);
+            let (low, high) = self.cell_ranges[j * (self.columns - 1) + i];
+            let (low, high) = (low * self.height, high * self.height);
+            let y_in = local.y + ray.dir.y * t_cell;
+            let y_out = local.y + ray.dir.y * t_leave;
+            if y_in.min(y_out) <= low.max(high) && y_in.max(y_out) >= low.min(high) {
+                if let Some(hit) = self.hit_cell(ray, (i, j), t_min, t_max) {
+                    return Some(hit);
+                }
+            }
+
+            if t_leave >= t_exit {
+                return None;
+            }
+            t_cell = t_leave;
+            if t_next_x < t_next_z {
+                if !negative_x {
+                    i += 1;
+                } else if i > 0 {
+                    i -= 1;
+                } else {
+                    return None;
+                }
+                t_next_x += t_delta_x;
+            } else {
+                if !negative_z {
+                    j += 1;
+                } else if j > 0 {
+                    j -= 1;
+                } else {
+                    return None;
+                }
+                t_next_z += t_delta_z;
+            }
+            if i > self.columns - 2 || j > self.rows - 2 {
+                return None;
+            }
+        }
+    }
+}
+
+/// Samples are hashed with the placement, materials being left out like
+/// for shapes.
+impl ContentHash for Heightfield {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        self.origin.content_hash(hasher);
+        hasher.write_float(self.size.0);
+        hasher.write_float(self.size.1);
+        hasher.write_float(self.height);
+        hasher.write_u64(self.columns as u64);
+        hasher.write_u64(self.rows as u64);
+        for &sample in self.samples.iter() {
+            hasher.write_float(sample);
+        }
+    }
+}
+
+/// Fractal value noise at (`x`, `y`), from 0 to 1, adding `octaves` layers
+/// of lattice noise each twice finer and half as strong as the last. The same
+/// `seed` gives the same noise, features of the first layer being a unit
+/// apart.
+pub fn fractal_noise(seed: u64, x: Float, y: Float, octaves: u32) -> Float {
+    let lattice = |octave: u32, i: i64, j: i64| {
+        let cell = ((i as u32 as u64) << 32) | j as u32 as u64;
+        uniform_from_bits(mix_seed(mix_seed(seed, octave as u64), cell))
+    };
+    let smooth = |t: Float| t * t * (3.0 - 2.0 * t);
+
+    let (mut sum, mut total) = (0.0, 0.0);
+    let (mut frequency, mut amplitude) = (1.0, 1.0);
+    for octave in 0..octaves {
+        let (px, py) = (x * frequency, y * frequency);
+        let (i, j) = (px.floor(), py.floor());
+        let (tx, ty) = (smooth(px - i), smooth(py - j));
+        let (i, j) = (i as i64, j as i64);
+        let bottom = lattice(octave, i, j) * (1.0 - tx) + lattice(octave, i + 1, j) * tx;
+        let top = lattice(octave, i, j + 1) * (1.0 - tx) + lattice(octave, i + 1, j + 1) * tx;
+        sum += (bottom * (1.0 - ty) + top * ty) * amplitude;
+        total += amplitude;
+        frequency *= 2.0;
+        amplitude *= 0.5;
+    }
+    if total > 0.0 {
+        sum / total
+    } else {
+        0.0
+    }
+}
@@ -0,0 +1,79 @@
+use crate::material::{MaterialId, ThinFilm};
+use crate::maths::*;
+use crate::ray::Ray;
+
+#[derive(Clone, Copy)]
+pub struct HitRecord {
+    pub position: Vec3,
+    /// Unit normal facing the incoming ray, whichever side was hit.
+    pub normal: Vec3,
+    pub t: Float,
+    /// Whether the ray hit the outside of the surface.
+    pub front_face: bool,
+    /// Material of the surface, to be looked up in the scene.
+    pub material: MaterialId,
+    /// Refractive index of the medium on the other side of the surface
+    /// from the material: the one the ray comes from when it hit the front
+    /// face, the one it enters otherwise. Air unless the renderer tracks
+    /// nested media.
+    pub outside_index: Float,
+    /// Film coating the surface, see `Scene::set_thin_film`.
+    pub film: Option<ThinFilm>,
+    /// Unit tangent along increasing `u` of the texture coordinates, for
+    /// anisotropic materials, see `Scene::apply_shading_normal`.
+    pub tangent: Option<Vec3>,
+    /// Texture coordinates of the hit, on surfaces that carry their own,
+    /// like meshes given some with `Mesh::with_uvs`.
+    pub uv: Option<(Float, Float)>,
+}
+
+impl HitRecord {
+    /// Hit of `ray` on a surface whose normal at `position` is
+    /// `outward_normal`, which needn't be normalized. The side that was hit
+    /// follows from the direction of the ray.
+    pub fn new(
+        ray: &Ray,
+        position: Vec3,
+        outward_normal: Vec3,
+        t: Float,
+        material: MaterialId,
+    ) -> Self {
+        let front_face = ray.dir.dot(outward_normal) < 0.0;
+        let normal = if front_face {
+            outward_normal
+        } else {
+            -outward_normal
+        };
+        let normal = normal.unit();
+        HitRecord {
+            position,
+            normal,
+            t,
+            front_face,
+            material,
+            outside_index: 1.0,
+            film: None,
+            tangent: None,
+            uv: None,
+        }
+    }
+}
+
+pub trait Hitable: Sync {
+    /// Uniformly scales the object around the world origin.
+    fn scale(&mut self, factor: Float);
+
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord>;
+
+    /// Solid angle density of `sample_direction_towards(origin, u)` returning
+    /// `direction`. Only needed for objects used as lights.
+    fn pdf_value(&self, _origin: Vec3, _direction: Vec3) -> Float {
+        0.0
+    }
+
+    /// Direction from `origin` towards the object, from a uniform point `u`
+    /// of the unit square.
+    fn sample_direction_towards(&self, _origin: Vec3, _u: (Float, Float)) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+}
@@ -0,0 +1,132 @@
+use crate::maths::*;
+use crate::render::Crop;
+
+/// Rendered image held in memory: row-major RGBA pixels, linear and
+/// premultiplied by alpha, in the `Float`s the renderer computes in unless
+/// converted with `to_f32` or `to_f64`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image<T = Float> {
+    width: usize,
+    height: usize,
+    pixels: Vec<T>,
+}
+
+impl<T: Copy> Image<T> {
+    /// Image of the RGBA `pixels`, which must be `width` x `height` of them.
+    pub fn new(width: usize, height: usize, pixels: Vec<T>) -> Self {
+        assert_eq!(
+            pixels.len(),
+            width * height * 4,
+            "a {}x{} image has {} RGBA values",
+            width,
+            height,
+            width * height * 4
+        );
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// RGBA values of the pixel in column `x` of row `y`, from the top left.
+    pub fn pixel(&self, x: usize, y: usize) -> [T; 4] {
+        let index = self.index(x, y);
+        let pixel = &self.pixels[index..index + 4];
+        [pixel[0], pixel[1], pixel[2], pixel[3]]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, value: [T; 4]) {
+        let index = self.index(x, y);
+        self.pixels[index..index + 4].copy_from_slice(&value);
+    }
+
+    /// RGBA values of all the pixels, row by row.
+    pub fn pixels(&self) -> &[T] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [T] {
+        &mut self.pixels
+    }
+
+    pub fn into_pixels(self) -> Vec<T> {
+        self.pixels
+    }
+
+    /// The pixels of `area` alone.
+    pub fn cropped(&self, area: Crop) -> Self {
+        assert!(
+            area.x.saturating_add(area.width) <= self.width
+                && area.y.saturating_add(area.height) <= self.height,
+            "the crop goes past the image"
+        );
+        let mut pixels = Vec::with_capacity(area.width * area.height * 4);
+        for y in area.y..area.y + area.height {
+            let start = self.index(area.x, y);
+            pixels.extend_from_slice(&self.pixels[start..start + area.width * 4]);
+        }
+        Image::new(area.width, area.height, pixels)
+    }
+
+    /// Image of `convert` applied to every value of this one.
+    pub fn map<U: Copy>(&self, convert: impl Fn(T) -> U) -> Image<U> {
+        Image::new(
+            self.width,
+            self.height,
+            self.pixels.iter().map(|&value| convert(value)).collect(),
+        )
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        assert!(
+            x < self.width && y < self.height,
+            "pixel ({}, {}) is outside the {}x{} image",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        (y * self.width + x) * 4
+    }
+}
+
+impl Image {
+    /// The image in single precision channels.
+    pub fn to_f32(&self) -> Image<f32> {
+        self.map(to_f32)
+    }
+
+    /// The image in double precision channels.
+    pub fn to_f64(&self) -> Image<f64> {
+        self.map(to_f64)
+    }
+
+    /// This image composited over `background`, of the same size, which
+    /// shows through where it is transparent.
+    pub fn over(&self, background: &Image) -> Image {
+        assert_eq!(
+            (self.width, self.height),
+            (background.width, background.height),
+            "images composited must be the same size"
+        );
+        let pixels = self
+            .pixels
+            .chunks(4)
+            .zip(background.pixels.chunks(4))
+            .flat_map(|(front, back)| {
+                let coverage = 1.0 - front[3];
+                (0..4).map(move |channel| front[channel] + back[channel] * coverage)
+            })
+            .collect();
+        Image::new(self.width, self.height, pixels)
+    }
+}
@@ -0,0 +1,256 @@
+use crate::content_hash::*;
+use crate::hitable::*;
+use crate::material::MaterialId;
+use crate::maths::*;
+use crate::mesh::Mesh;
+use crate::ray::Ray;
+use crate::sphere::{Sphere, UvFrame};
+
+use std::sync::Arc;
+
+/// Length of the stretch over which a level fades in, past the distance it
+/// is used from, as a share of the range of distances of the level before.
+const BLEND: Float = 0.25;
+
+/// One model as meshes of decreasing resolutions, each used from a distance
+/// of its own, in the units of the meshes. Instances share them, so many
+/// copies of a model only take the memory of one.
+#[derive(Debug)]
+pub struct LevelsOfDetail {
+    /// The finest mesh first, used from 0.
+    levels: Vec<(Float, Mesh)>,
+}
+
+impl LevelsOfDetail {
+    pub fn new(finest: Mesh) -> Self {
+        LevelsOfDetail {
+            levels: vec![(0.0, finest)],
+        }
+    }
+
+    /// Adds a coarser level, used from `distance` away from the center of
+    /// the finest one, further than the levels before.
+    pub fn with_level(mut self, distance: Float, mesh: Mesh) -> Self {
+        let (last, _) = self.levels[self.levels.len() - 1];
+        assert!(distance > last, "levels must get used further and further");
+        self.levels.push((distance, mesh));
+        self
+    }
+
+    /// Sphere around every level, centered on the finest one.
+    pub fn bounding_sphere(&self) -> Sphere {
+        let finest = self.level(0).bounding_sphere();
+        let radius = self
+            .levels
+            .iter()
+            .map(|(_, mesh)| {
+                let bounds = mesh.bounding_sphere();
+                (bounds.position - finest.position).length() + bounds.radius
+            })
+            .fold(0.0, Float::max);
+        Sphere::new(finest.position, radius, finest.material)
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Level of `index`, the finest being 0.
+    pub fn level(&self, index: usize) -> &Mesh {
+        &self.levels[index].1
+    }
+}
+
+impl ContentHash for LevelsOfDetail {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        hasher.write_u64(self.levels.len() as u64);
+        for (distance, mesh) in &self.levels {
+            hasher.write_float(*distance);
+            mesh.content_hash(hasher);
+        }
+    }
+}
+
+/// Copy of a model placed by a transform of its own, rays being moved into
+/// the space of its meshes rather than the meshes into the scene.
+///
+/// Each ray picks the level of the distance from its origin to the model,
+/// measured in the units of the meshes, so larger instances, which look
+/// larger from the same place, keep their detail further away. Just past
+/// the distance of a level, rays pick it or the level before at random, the
+/// share of the new level going up linearly, so levels blend into each other
+/// instead of popping (see `BLEND`). The pick is hashed from the ray, rays of
+/// a path getting theirs independently. Levels should only change further
+/// away than the size of the model, so that rays leaving its surface see
+/// the level they left.
+#[derive(Clone, Debug)]
+pub struct Instance {
+    pub material: MaterialId,
+    levels: Arc<LevelsOfDetail>,
+    to_world: Mat4,
+    to_object: Mat4,
+}
+
+impl Instance {
+    /// Instance of `levels` placed by `transform`, which must not flatten
+    /// them.
+    pub fn new(levels: Arc<LevelsOfDetail>, transform: &Mat4, material: MaterialId) -> Self {
+        Instance {
+            material,
+            levels,
+            to_world: *transform,
+            to_object: transform.inverse(),
+        }
+    }
+
+    pub fn levels(&self) -> &LevelsOfDetail {
+        &self.levels
+    }
+
+    /// Level `ray`, in the space of the meshes, hits.
+    fn pick_level(&self, ray: &Ray) -> usize {
+        let center = self.levels.level(0).bounding_sphere().position;
+        let distance = (ray.origin - center).length();
+        let levels = &self.levels.levels;
+        let level = levels
+            .iter()
+            .rposition(|&(start, _)| distance >= start)
+            .unwrap_or(0);
+        if level == 0 {
+            return 0;
+        }
+        let start = levels[level - 1].0;
+        let fade_end = levels[level].0 + (levels[level].0 - start) * BLEND;
+        if distance >= fade_end {
+            return level;
+        }
+        let share = (distance - levels[level].0) / (fade_end - levels[level].0);
+        let mut hasher = ContentHasher::default();
+        ray.origin.content_hash(&mut hasher);
+        ray.dir.content_hash(&mut hasher);
+        if hashed_uniform(hasher.finish()) < share {
+            level
+        } else {
+            level - 1
+        }
+    }
+
+    /// Level of detail `ray` hits, the finest being 0.
+    pub fn level_for(&self, ray: &Ray) -> usize {
+        self.pick_level(&self.local_ray(ray))
+    }
+
+    fn local_ray(&self, ray: &Ray) -> Ray {
+        Ray::new(
+            self.to_object.transform_point(ray.origin),
+            self.to_object.transform_vector(ray.dir),
+        )
+    }
+
+    /// Level whose surface `point`, in the space of the meshes, lies on.
+    fn level_at(&self, point: Vec3) -> &Mesh {
+        let levels = &self.levels.levels;
+        levels
+            .iter()
+            .map(|(_, mesh)| (mesh.distance_to(point), mesh))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map_or(&levels[0].1, |(_, mesh)| mesh)
+    }
+
+    /// Outward unit normal at `point`, on the surface.
+    pub fn normal_at(&self, point: Vec3) -> Vec3 {
+        let point = self.to_object.transform_point(point);
+        let normal = self.level_at(point).normal_at(point);
+        self.to_world.transform_normal(normal).unit()
+    }
+
+    /// Texture coordinates of the meshes at `point`, with their frame placed
+    /// along with them.
+    pub fn uv_frame(&self, point: Vec3) -> UvFrame {
+        let local = self.to_object.transform_point(point);
+        let frame = self.level_at(local).uv_frame(local);
+        let (tangent, bitangent) = frame.tangents;
+        let (tangent, bitangent) = (
+            self.to_world.transform_vector(tangent),
+            self.to_world.transform_vector(bitangent),
+        );
+        UvFrame {
+            uv: frame.uv,
+            tangents: (tangent.unit(), bitangent.unit()),
+            lengths: (
+                frame.lengths.0 * tangent.length(),
+                frame.lengths.1 * bitangent.length(),
+            ),
+        }
+    }
+
+    /// Point and outward normal of the finest level at texture coordinates
+    /// (`u`, `v`), see `Mesh::surface_at`.
+    pub fn surface_at(&self, u: Float, v: Float) -> (Vec3, Vec3) {
+        let (point, normal) = self.levels.level(0).surface_at(u, v);
+        (
+            self.to_world.transform_point(point),
+            self.to_world.transform_normal(normal).unit(),
+        )
+    }
+
+    /// Sphere around every level.
+    pub fn bounding_sphere(&self) -> Sphere {
+        let bounds = self.levels.bounding_sphere();
+        // No direction is stretched by more than the square root of the
+        // largest row sum of the transpose of the transform times itself,
+        // which rotations and even scales make exact.
+        let m = &self.to_world.m;
+        let column = |j: usize| Vec3::new(m[0][j], m[1][j], m[2][j]);
+        let stretch = (0..3)
+            .map(|i| {
+                (0..3)
+                    .map(|j| column(i).dot(column(j)).abs())
+                    .sum::<Float>()
+            })
+            .fold(0.0, Float::max)
+            .sqrt();
+        Sphere::new(
+            self.to_world.transform_point(bounds.position),
+            bounds.radius * stretch,
+            self.material,
+        )
+    }
+
+    /// Copy of the instance moved by `transform`, sharing its levels.
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        Instance::new(
+            Arc::clone(&self.levels),
+            &(*transform * self.to_world),
+            self.material,
+        )
+    }
+
+    /// Transform from the space of the meshes to the scene.
+    pub fn transform(&self) -> &Mat4 {
+        &self.to_world
+    }
+}
+
+/// Uniform number in [0, 1) hashed from `seed`.
+fn hashed_uniform(seed: u64) -> Float {
+    uniform_from_bits(mix_bits(seed))
+}
+
+impl Hitable for Instance {
+    fn scale(&mut self, factor: Float) {
+        *self = self.transformed(&Mat4::scaling(Vec3::new(factor, factor, factor)));
+    }
+
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        // Moving the whole ray keeps the point of every t, even as the length
+        // of its direction changes.
+        let local = self.local_ray(ray);
+        let level = self.levels.level(self.pick_level(&local));
+        let mut record = level.hit(&local, t_min, t_max)?;
+        record.position = ray.at(record.t);
+        record.normal = self.to_world.transform_normal(record.normal).unit();
+        record.material = self.material;
+        Some(record)
+    }
+}
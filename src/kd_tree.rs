@@ -0,0 +1,417 @@
+use crate::bvh::Aabb;
+use crate::hitable::Hitable;
+use crate::maths::*;
+use crate::ray::{Ray, RayExt};
+use crate::sphere::Sphere;
+
+/// Most items in a leaf left without looking for a split.
+const LEAF_ITEMS: usize = 2;
+
+/// Costs of stepping through a node and of testing an item, relative to
+/// each other, for the surface area heuristic.
+const TRAVERSAL_COST: Float = 1.0;
+const INTERSECTION_COST: Float = 1.5;
+
+/// Nodes with more items only try evenly spaced planes, this many per
+/// axis, instead of sorting the edges of every box.
+const BINNED_ITEMS: usize = 512;
+const BINS: usize = 32;
+
+/// Share of the cost of splits leaving one side empty, which cut away empty
+/// space rays then skip for free.
+const EMPTY_BONUS: Float = 0.8;
+
+#[derive(Clone, Copy, Debug)]
+struct KdNode {
+    /// Position of the splitting plane of inner nodes.
+    split: Float,
+    /// Axis of the plane, 3 for leaves.
+    axis: u8,
+    /// Leaves: index of their first item. Inner nodes: index of their child
+    /// above the plane, the one below following them.
+    index: usize,
+    /// Number of items of leaves.
+    count: usize,
+}
+
+/// Kd-tree over items given by their bounding boxes, splitting space by
+/// planes along the axes rather than the items into groups like a `Bvh`.
+/// Items straddling a plane go on both sides, so the cells of the tree
+/// never overlap and rays visit them front to back, stopping at the first
+/// holding a hit. Planes are picked at the edges of the boxes by the
+/// surface area heuristic.
+#[derive(Clone, Debug)]
+pub struct KdTree {
+    /// Depth first, every inner node followed by its child below its plane.
+    nodes: Vec<KdNode>,
+    /// Items of the leaves, one after the other.
+    items: Vec<usize>,
+    bounds: Aabb,
+}
+
+impl KdTree {
+    /// Tree over the items of `boxes`, numbered by their position in it.
+    pub fn new(boxes: &[Aabb]) -> Self {
+        let bounds = boxes
+            .iter()
+            .fold(Aabb::empty(), |bounds, &item| bounds.merge(item));
+        let mut tree = KdTree {
+            nodes: Vec::new(),
+            items: Vec::new(),
+            bounds,
+        };
+        if !boxes.is_empty() {
+            // Deep enough for the splits to pay off, as items get copied.
+            let depth = 8 + (1.3 * (boxes.len() as Float).log2()).round() as usize;
+            tree.build(boxes, (0..boxes.len()).collect(), bounds, depth);
+        }
+        tree
+    }
+
+    /// Box around every item.
+    pub fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    /// Adds the subtree of the items of `items`, in the cell `bounds`.
+    fn build(&mut self, boxes: &[Aabb], items: Vec<usize>, bounds: Aabb, depth: usize) {
+        let node = self.nodes.len();
+        let split = if items.len() > LEAF_ITEMS && depth > 0 {
+            best_split(boxes, &items, &bounds)
+        } else {
+            None
+        };
+        let (axis, split) = match split {
+            Some(split) => split,
+            None => {
+                self.nodes.push(KdNode {
+                    split: 0.0,
+                    axis: 3,
+                    index: self.items.len(),
+                    count: items.len(),
+                });
+                self.items.extend(items);
+                return;
+            }
+        };
+
+        self.nodes.push(KdNode {
+            split,
+            axis: axis as u8,
+            index: 0,
+            count: 0,
+        });
+        let (mut below, mut above) = (Vec::new(), Vec::new());
+        for item in items {
+            let (min, max) = (
+                component(boxes[item].min, axis),
+                component(boxes[item].max, axis),
+            );
+            // Items flat in the plane go below it.
+            if min < split || max == split {
+                below.push(item);
+            }
+            if max > split {
+                above.push(item);
+            }
+        }
+        let (mut low, mut high) = (bounds, bounds);
+        set_component(&mut low.max, axis, split);
+        set_component(&mut high.min, axis, split);
+        self.build(boxes, below, low, depth - 1);
+        self.nodes[node].index = self.nodes.len();
+        self.build(boxes, above, high, depth - 1);
+    }
+
+    /// Closest hit of `ray` in (`t_min`, `t_max`), as the item and the ray
+    /// parameter, `test` giving the parameter at which `ray` hits an item
+    /// before the closest hit so far, its second argument.
+    pub fn hit_with(
+        &self,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+        mut test: impl FnMut(usize, Float) -> Option<Float>,
+    ) -> Option<(usize, Float)> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let (mut near, mut far) = self.bounds.clip(ray, t_min, t_max)?;
+
+        let origin = [ray.origin.x, ray.origin.y, ray.origin.z];
+        let dir = [ray.dir.x, ray.dir.y, ray.dir.z];
+        let inverse = ray.inverse_dir();
+        let inverse = [inverse.x, inverse.y, inverse.z];
+
+        let mut closest: Option<(usize, Float)> = None;
+        let mut closest_t = t_max;
+        let mut stack: Vec<(usize, Float, Float)> = Vec::with_capacity(64);
+        let mut index = 0;
+        loop {
+            let node = &self.nodes[index];
+            if node.axis == 3 {
+                for &item in &self.items[node.index..node.index + node.count] {
+                    if let Some(t) = test(item, closest_t) {
+                        closest_t = t;
+                        closest = Some((item, t));
+                    }
+                }
+                // Cells further along can't hold anything closer.
+                if closest_t <= far {
+                    return closest;
+                }
+                loop {
+                    let (next, next_near, next_far) = match stack.pop() {
+                        Some(next) => next,
+                        None => return closest,
+                    };
+                    if next_near <= closest_t {
+                        (index, near, far) = (next, next_near, next_far);
+                        break;
+                    }
+                }
+                continue;
+            }
+
+            let axis = node.axis as usize;
+            let (below, above) = (index + 1, node.index);
+            // Rays lying in the plane go on below it, like flat items.
+            let starts_below =
+                origin[axis] < node.split || (origin[axis] == node.split && dir[axis] <= 0.0);
+            let (first, second) = if starts_below {
+                (below, above)
+            } else {
+                (above, below)
+            };
+            let t_split = (node.split - origin[axis]) * inverse[axis];
+            if dir[axis] == 0.0 || t_split > far || t_split <= 0.0 {
+                index = first;
+            } else if t_split < near {
+                index = second;
+            } else {
+                stack.push((second, t_split, far));
+                index = first;
+                far = t_split;
+            }
+        }
+    }
+
+    /// Items whose box may hold `point`, each once.
+    pub fn candidates(&self, point: Vec3) -> Vec<usize> {
+        let mut candidates = Vec::new();
+        if self.nodes.is_empty() || !self.bounds.contains(point) {
+            return candidates;
+        }
+        let position = [point.x, point.y, point.z];
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.axis == 3 {
+                for &item in &self.items[node.index..node.index + node.count] {
+                    if !candidates.contains(&item) {
+                        candidates.push(item);
+                    }
+                }
+                continue;
+            }
+            let coordinate = position[node.axis as usize];
+            if coordinate <= node.split {
+                stack.push(index + 1);
+            }
+            if coordinate >= node.split {
+                stack.push(node.index);
+            }
+        }
+        candidates
+    }
+}
+
+/// Axis and position of the cheapest plane splitting the cell `bounds` of
+/// `items`, `None` when keeping them in a leaf is cheaper.
+fn best_split(boxes: &[Aabb], items: &[usize], bounds: &Aabb) -> Option<(usize, Float)> {
+    let area = bounds.surface_area();
+    if area <= 0.0 {
+        return None;
+    }
+    let mut best_cost = INTERSECTION_COST * items.len() as Float;
+    let mut best = None;
+    let mut consider = |axis: usize, position: Float, below: usize, above: usize| {
+        let (mut low_cell, mut high_cell) = (*bounds, *bounds);
+        set_component(&mut low_cell.max, axis, position);
+        set_component(&mut high_cell.min, axis, position);
+        let mut cost = TRAVERSAL_COST
+            + INTERSECTION_COST
+                * (low_cell.surface_area() * below as Float
+                    + high_cell.surface_area() * above as Float)
+                / area;
+        if below == 0 || above == 0 {
+            cost *= EMPTY_BONUS;
+        }
+        if cost < best_cost {
+            best_cost = cost;
+            best = Some((axis, position));
+        }
+    };
+
+    if items.len() > BINNED_ITEMS {
+        for axis in 0..3 {
+            let (low, high) = (component(bounds.min, axis), component(bounds.max, axis));
+            if high <= low {
+                continue;
+            }
+            let bin = |value: Float| {
+                let bin = (value - low) / (high - low) * BINS as Float;
+                (bin.max(0.0) as usize).min(BINS - 1)
+            };
+            // Boxes starting and ending in every bin, counted on the safe
+            // side of the planes between them.
+            let (mut starts, mut ends) = ([0; BINS], [0; BINS]);
+            for &item in items {
+                starts[bin(component(boxes[item].min, axis))] += 1;
+                ends[bin(component(boxes[item].max, axis))] += 1;
+            }
+            let (mut below, mut above) = (0, items.len());
+            for plane in 1..BINS {
+                below += starts[plane - 1];
+                above -= ends[plane - 1];
+                let position = low + (high - low) * plane as Float / BINS as Float;
+                consider(axis, position, below, above);
+            }
+        }
+        return best;
+    }
+
+    let mut events: Vec<(Float, bool)> = Vec::with_capacity(2 * items.len());
+    for axis in 0..3 {
+        let (low, high) = (component(bounds.min, axis), component(bounds.max, axis));
+        if high <= low {
+            continue;
+        }
+        // Edges of the boxes, ends before starts at the same place.
+        events.clear();
+        for &item in items {
+            let (min, max) = (
+                component(boxes[item].min, axis),
+                component(boxes[item].max, axis),
+            );
+            events.push((min.clamp(low, high), true));
+            events.push((max.clamp(low, high), false));
+        }
+        events.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+
+        let (mut below, mut above) = (0, items.len());
+        let mut event = 0;
+        while event < events.len() {
+            let position = events[event].0;
+            let (mut starts, mut ends) = (0, 0);
+            while event < events.len() && events[event].0 == position {
+                if events[event].1 {
+                    starts += 1;
+                } else {
+                    ends += 1;
+                }
+                event += 1;
+            }
+            above -= ends;
+            if position > low && position < high {
+                consider(axis, position, below, above);
+            }
+            below += starts;
+        }
+    }
+    best
+}
+
+fn component(vector: Vec3, axis: usize) -> Float {
+    match axis {
+        0 => vector.x,
+        1 => vector.y,
+        _ => vector.z,
+    }
+}
+
+fn set_component(vector: &mut Vec3, axis: usize, value: Float) {
+    match axis {
+        0 => vector.x = value,
+        1 => vector.y = value,
+        _ => vector.z = value,
+    }
+}
+
+/// `KdTree` over spheres of a scene, which it keeps copies of, and the other
+/// objects of the scene, by their bounding spheres, rays testing them
+/// through the callback of `hit_with`.
+pub struct SphereKdTree {
+    tree: KdTree,
+    spheres: Vec<Sphere>,
+    /// Index in the scene of every object of the tree.
+    indices: Vec<usize>,
+    /// Whether every object of the tree is tested through the callback.
+    is_shape: Vec<bool>,
+}
+
+impl SphereKdTree {
+    pub fn new(spheres: &[Sphere]) -> Self {
+        SphereKdTree::of_indices(spheres, (0..spheres.len()).collect())
+    }
+
+    /// Tree over the spheres of `indices` alone, leaving the others to the
+    /// caller.
+    pub fn of_indices(spheres: &[Sphere], indices: Vec<usize>) -> Self {
+        SphereKdTree::of_objects(spheres, indices, Vec::new())
+    }
+
+    /// Tree over the spheres of `indices` and the objects of `shapes`, which
+    /// `spheres` only bounds, leaving the others to the caller.
+    pub fn of_objects(spheres: &[Sphere], mut indices: Vec<usize>, shapes: Vec<usize>) -> Self {
+        let mut is_shape = vec![false; indices.len()];
+        is_shape.resize(indices.len() + shapes.len(), true);
+        indices.extend(shapes);
+        let spheres: Vec<Sphere> = indices.iter().map(|&index| spheres[index]).collect();
+        let boxes: Vec<Aabb> = spheres.iter().map(Aabb::of_sphere).collect();
+        SphereKdTree {
+            tree: KdTree::new(&boxes),
+            spheres,
+            indices,
+            is_shape,
+        }
+    }
+
+    /// Closest hit of `ray` in (`t_min`, `t_max`), as the index of the sphere
+    /// and the ray parameter, the one of `Sphere::hit`.
+    pub fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<(usize, Float)> {
+        self.hit_with(ray, t_min, t_max, |_, _| None)
+    }
+
+    /// Like `hit`, `test` giving the parameter at which `ray` hits an object
+    /// other than a sphere before the closest hit so far, its second
+    /// argument.
+    pub fn hit_with(
+        &self,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+        mut test: impl FnMut(usize, Float) -> Option<Float>,
+    ) -> Option<(usize, Float)> {
+        self.tree
+            .hit_with(ray, t_min, t_max, |item, closest_t| {
+                if self.is_shape[item] {
+                    return test(self.indices[item], closest_t);
+                }
+                self.spheres[item]
+                    .hit(ray, t_min, closest_t)
+                    .map(|record| record.t)
+            })
+            .map(|(item, t)| (self.indices[item], t))
+    }
+
+    /// Indices of the objects whose bounding box may hold `point`, each
+    /// once.
+    pub fn candidates(&self, point: Vec3) -> Vec<usize> {
+        self.tree
+            .candidates(point)
+            .into_iter()
+            .map(|item| self.indices[item])
+            .collect()
+    }
+}
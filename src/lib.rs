@@ -0,0 +1,108 @@
+pub mod exr;
+pub mod hdr;
+pub mod maths;
+pub mod netpbm;
+pub mod png;
+pub mod video;
+
+mod accelerator;
+mod animation;
+mod aov;
+mod blue_noise;
+mod bvh;
+mod camera;
+mod clouds;
+mod contact_sheet;
+mod content_hash;
+mod curve;
+mod distributed;
+mod environment;
+mod film;
+mod gltf;
+mod grid;
+mod hair;
+mod heightfield;
+mod hitable;
+mod image;
+mod instance;
+mod kd_tree;
+mod light;
+mod light_cache;
+mod material;
+mod measured;
+mod mesh;
+mod ocean;
+#[cfg(feature = "denoise")]
+mod oidn;
+mod paths;
+mod plane;
+mod postprocess;
+mod preview;
+mod preview_server;
+mod principled;
+mod quadric;
+mod ray;
+mod render;
+mod sampler;
+mod scene;
+mod scene_defaults;
+mod scene_file;
+mod sdf;
+mod shape;
+mod sky;
+mod sphere;
+mod stats;
+mod streaming;
+mod sweep;
+mod texture;
+
+pub use accelerator::*;
+pub use animation::*;
+pub use aov::*;
+pub use blue_noise::*;
+pub use bvh::*;
+pub use camera::*;
+pub use clouds::*;
+pub use contact_sheet::*;
+pub use content_hash::*;
+pub use curve::*;
+pub use distributed::*;
+pub use environment::*;
+pub use film::*;
+pub use gltf::*;
+pub use grid::*;
+pub use hair::*;
+pub use heightfield::*;
+pub use hitable::*;
+pub use image::*;
+pub use instance::*;
+pub use kd_tree::*;
+pub use light::*;
+pub use light_cache::*;
+pub use material::*;
+pub use measured::*;
+pub use mesh::*;
+pub use ocean::*;
+#[cfg(feature = "denoise")]
+pub use oidn::*;
+pub use paths::*;
+pub use plane::*;
+pub use postprocess::*;
+pub use preview::*;
+pub use preview_server::*;
+pub use principled::*;
+pub use quadric::*;
+pub use ray::*;
+pub use render::*;
+pub use sampler::*;
+pub use scene::*;
+pub use scene_defaults::*;
+pub use scene_file::*;
+pub use sdf::*;
+pub use shape::*;
+pub use sky::*;
+pub use sphere::*;
+pub use stats::*;
+pub use streaming::*;
+pub use sweep::*;
+pub use texture::*;
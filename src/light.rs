@@ -0,0 +1,111 @@
+use crate::maths::*;
+
+/// Light without any extent, which rays can never hit, so it only shows
+/// through the shadow rays every non-specular surface sends towards it.
+#[derive(Clone, Copy, Debug)]
+pub enum DeltaLight {
+    /// Light shining evenly all around `position`, `intensity` giving the
+    /// irradiance at a distance of one scene unit.
+    Point { position: Vec3, intensity: Vec3 },
+    /// Light coming from infinitely far away, like the sun, travelling along
+    /// `direction` and giving `irradiance` to surfaces facing it.
+    Directional { direction: Vec3, irradiance: Vec3 },
+    /// Point light restricted to a cone around `direction`, full inside
+    /// `inner_angle` and fading out smoothly to nothing at `outer_angle`,
+    /// both half angles in degrees.
+    Spot {
+        position: Vec3,
+        direction: Vec3,
+        intensity: Vec3,
+        inner_angle: Float,
+        outer_angle: Float,
+    },
+}
+
+/// Light reaching a point from a `DeltaLight`.
+#[derive(Clone, Copy, Debug)]
+pub struct DeltaLightSample {
+    /// Unit direction towards the light.
+    pub direction: Vec3,
+    /// Distance to the light, infinite for directional lights.
+    pub distance: Float,
+    /// Irradiance on a surface facing the light.
+    pub irradiance: Vec3,
+}
+
+impl DeltaLight {
+    /// Light reaching `point`, `None` when none does.
+    pub fn illuminate(&self, point: Vec3) -> Option<DeltaLightSample> {
+        match *self {
+            DeltaLight::Point {
+                position,
+                intensity,
+            } => Self::from_position(point, position, intensity),
+            DeltaLight::Directional {
+                direction,
+                irradiance,
+            } => Some(DeltaLightSample {
+                direction: -direction.unit(),
+                distance: Float::INFINITY,
+                irradiance,
+            }),
+            DeltaLight::Spot {
+                position,
+                direction,
+                intensity,
+                inner_angle,
+                outer_angle,
+            } => {
+                let sample = Self::from_position(point, position, intensity)?;
+                let cos = (-sample.direction).dot(direction.unit());
+                let cos_inner = inner_angle.to_radians().cos();
+                let cos_outer = outer_angle.to_radians().cos();
+                let falloff = if cos >= cos_inner {
+                    1.0
+                } else if cos <= cos_outer {
+                    return None;
+                } else {
+                    let t = (cos - cos_outer) / (cos_inner - cos_outer);
+                    t * t * (3.0 - 2.0 * t)
+                };
+                Some(DeltaLightSample {
+                    irradiance: sample.irradiance * falloff,
+                    ..sample
+                })
+            }
+        }
+    }
+
+    fn from_position(point: Vec3, position: Vec3, intensity: Vec3) -> Option<DeltaLightSample> {
+        let offset = position - point;
+        let distance = offset.length();
+        if distance == 0.0 {
+            return None;
+        }
+        Some(DeltaLightSample {
+            direction: offset / distance,
+            distance,
+            irradiance: intensity / (distance * distance),
+        })
+    }
+
+    /// Rescales the light along with a scene whose lengths are multiplied by
+    /// `factor`, keeping the irradiance it gives.
+    pub fn scale(&mut self, factor: Float) {
+        match self {
+            DeltaLight::Point {
+                position,
+                intensity,
+            }
+            | DeltaLight::Spot {
+                position,
+                intensity,
+                ..
+            } => {
+                *position = *position * factor;
+                *intensity = *intensity * (factor * factor);
+            }
+            DeltaLight::Directional { .. } => {}
+        }
+    }
+}
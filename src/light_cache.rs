@@ -0,0 +1,118 @@
+use crate::bvh::Aabb;
+use crate::camera::Camera;
+use crate::maths::*;
+use crate::ray::Ray;
+use crate::scene::{ObjectId, Scene};
+
+/// Columns of the grid of camera rays finding the surfaces a `LightCache` is
+/// built from, the rows following the aspect ratio of the camera.
+const CACHE_RAY_COLUMNS: usize = 256;
+
+/// Surface points per voxel whose shadow rays decide which lights it sees.
+const POINTS_PER_VOXEL: usize = 16;
+
+/// Lights each voxel of a coarse grid over the visible surfaces can see.
+///
+/// Lights found hidden from every point tried in a voxel aren't sampled
+/// there, so interiors lit through small openings stop spending shadow rays
+/// on lights the walls block. Light sampling and its density skip the same
+/// lights, and paths still find them by scattering, so the estimate stays
+/// unbiased even where a light is wrongly culled, only noisier.
+pub struct LightCache {
+    bounds: Aabb,
+    resolution: usize,
+    /// Lights of every voxel, `None` for voxels no camera ray reached, which
+    /// keep every light.
+    voxels: Vec<Option<Vec<ObjectId>>>,
+}
+
+impl LightCache {
+    /// Cache of `resolution` voxels per side over the surfaces `camera` sees,
+    /// each light being tested from up to `POINTS_PER_VOXEL` of them. Only
+    /// depends on the scene and camera, and reseeds the random sequence of
+    /// the current thread.
+    pub fn build(scene: &Scene, camera: &Camera, resolution: usize) -> Self {
+        let resolution = resolution.max(1);
+        let columns = CACHE_RAY_COLUMNS;
+        let rows = ((columns as Float / camera.aspect_ratio()).round() as usize).max(1);
+        let t_min = scene.epsilon();
+
+        // Surfaces seen by the camera, moved off the surface towards it.
+        let mut points = Vec::new();
+        for j in 0..rows {
+            for i in 0..columns {
+                let u = (i as Float + 0.5) / columns as Float;
+                let v = (j as Float + 0.5) / rows as Float;
+                let ray = camera.get_ray(u, v, (0.5, 0.5));
+                if let Some(hit) = scene.hit(&ray, t_min, Float::INFINITY) {
+                    points.push(hit.position + hit.normal * t_min);
+                }
+            }
+        }
+
+        let bounds = points
+            .iter()
+            .fold(Aabb::empty(), |bounds, &point| bounds.include(point));
+        let mut cache = LightCache {
+            bounds,
+            resolution,
+            voxels: vec![None; resolution * resolution * resolution],
+        };
+
+        let mut voxel_points = vec![Vec::new(); cache.voxels.len()];
+        for &point in &points {
+            if let Some(voxel) = cache.voxel(point) {
+                if voxel_points[voxel].len() < POINTS_PER_VOXEL {
+                    voxel_points[voxel].push(point);
+                }
+            }
+        }
+
+        seed_random(0);
+        for (voxel, points) in voxel_points.iter().enumerate() {
+            if points.is_empty() {
+                continue;
+            }
+            let visible = scene
+                .lights
+                .iter()
+                .copied()
+                .filter(|&light| {
+                    points.iter().any(|&point| {
+                        let u = (random_01(), random_01());
+                        let direction = scene.surface(light).sample_direction_towards(point, u);
+                        let ray = Ray::new(point, direction);
+                        let hit = scene.hit_object(&ray, t_min, Float::INFINITY);
+                        hit.is_some_and(|(sphere, _)| sphere == light)
+                    })
+                })
+                .collect();
+            cache.voxels[voxel] = Some(visible);
+        }
+
+        cache
+    }
+
+    fn voxel(&self, point: Vec3) -> Option<usize> {
+        let size = self.bounds.max - self.bounds.min;
+        let cell = |value: Float, min: Float, size: Float| {
+            let cell =
+                ((value - min) / size.max(Float::MIN_POSITIVE) * self.resolution as Float).floor();
+            if cell >= 0.0 && cell <= self.resolution as Float {
+                Some((cell as usize).min(self.resolution - 1))
+            } else {
+                None
+            }
+        };
+        let x = cell(point.x, self.bounds.min.x, size.x)?;
+        let y = cell(point.y, self.bounds.min.y, size.y)?;
+        let z = cell(point.z, self.bounds.min.z, size.z)?;
+        Some((z * self.resolution + y) * self.resolution + x)
+    }
+
+    /// Lights worth sampling from `point`, `None` when the cache knows
+    /// nothing about it.
+    pub fn lights(&self, point: Vec3) -> Option<&[ObjectId]> {
+        self.voxels[self.voxel(point)?].as_deref()
+    }
+}
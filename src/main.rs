@@ -1,454 +1,1162 @@
-mod maths;
-use maths::*;
-
-mod netpbm;
-use netpbm::*;
-
-use rayon::prelude::*;
-
+use raytracer::exr::*;
+use raytracer::hdr::*;
+use raytracer::maths::*;
+use raytracer::netpbm::*;
+use raytracer::png::*;
+use raytracer::video::*;
+use raytracer::*;
+
+use std::io::BufRead;
+use std::net::TcpListener;
+use std::path::Path;
 use std::time::Instant;
 
-fn random_in_unit_disk() -> Vec3 {
-    loop {
-        let p = Vec3::new(random_between(-1.0, 1.0), random_between(-1.0, 1.0), 0.0);
-        if p.length_squared() >= 1.0 {
-            continue;
-        } else {
-            return p;
-        }
-    }
-}
-
-fn reflect(v: Vec3, n: Vec3) -> Vec3 {
-    v - v.dot(n) * n * 2.0
-}
-
-fn refract(uv: Vec3, n: Vec3, etai_over_etat: f64) -> Vec3 {
-    let cos_theta = (-uv).dot(n);
-    let r_out_parallel = etai_over_etat * (uv + cos_theta * n);
-    let r_out_perp = -f64::sqrt(1.0 - r_out_parallel.length_squared()) * n;
-    r_out_parallel + r_out_perp
+const USAGE: &str = "Usage: raytracer [OPTIONS]
+
+Options:
+  --scene FILE               Scene file to render instead of the random scene
+  --stream N                 Load the meshes of the scene in the background, writing previews of
+                             N samples per pixel to OUTPUT as they come in
+  --interactive N            Render in passes of N samples per pixel, rewriting OUTPUT after each,
+                             and move the camera with keys read from the standard input:
+                             W A S D walk, I J K L orbit, drag DX DY orbits like a mouse
+                             dragged by DX, DY pixels, inspect X Y prints the statistics of
+                             pixel (X, Y) like --inspect-pixel, q quits
+  --preview-server ADDRESS   Serve the previews of --stream or --interactive over HTTP on ADDRESS
+                             (like 0.0.0.0:8080), to watch them from a browser
+  --ocean                    Render balls floating on waves instead of the random scene
+  --output FILE              Output image (.ppm, .png or .exr)
+  --transparent-background   Camera rays missing the scene get zero alpha
+  --alpha-mode MODE          Colors of images with alpha: straight (default for .png) or
+                             premultiplied (default for .exr)
+  --shadow-floor             Only show the shadows and reflections on the sphere named ground
+  --sweep TARGET.PARAM=A:B:S Render OUTPUT.000, OUTPUT.001... with PARAM going from A to B by S,
+                             for material, a sphere name (roughness, ior), lights (intensity),
+                             sun (elevation, azimuth, turbidity) or ocean (time)
+  --contact-sheet FILE       With --sweep, also assemble the frames into FILE, labeled with
+                             their values
+  --frames N                 Render N frames of the keyframes of the scene to OUTPUT_0001,
+                             OUTPUT_0002..., or in place of the #s of OUTPUT like frame_####.png
+                             Video outputs (.mp4, .mkv, .mov or .webm) get the frames
+                             piped into ffmpeg instead
+  --turntable N              Render N frames of the camera going once around what it looks at
+                             at the same distance and height, like --frames
+  --fps F                    Frames per second of --frames (default 24)
+  --ffmpeg PROGRAM           ffmpeg executable encoding video outputs (default ffmpeg)
+  --width N, --height N      Image resolution
+  --crop X,Y,W,H             Only render the W x H pixels from (X, Y), the rest black, or
+                             transparent with --transparent-background
+  --samples N                Samples per pixel
+  --max-depth N              Maximum number of bounces
+  --max-diffuse N            Maximum number of diffuse bounces
+  --max-glossy N             Maximum number of glossy reflections
+  --max-transmission N       Maximum number of refractions
+  --max-volume N             Maximum number of scattering events inside media and clouds
+  --max-distance D           Distance, in meters, past which rays ignore surfaces
+  --distance-fade F          Fraction of the maximum distance over which surfaces fade
+                             out, 0 to cut them off at once (default 0.1)
+  --clamp-direct L           Maximum luminance of light bouncing once, straight from lights
+  --clamp-indirect L         Maximum luminance of light bouncing more than once
+  --clamp-sample L           Maximum luminance of a sample
+  --reject-outliers K        Scale down samples K standard deviations brighter than
+                             the rest of their pixel
+  --median-of-means K        Take the median of the means of K groups of samples per pixel
+  --seed N                   Seed of the random sequences
+  --scene-seed N             Seed of the random and ocean scenes, apart from the sampling
+                             (default the --seed)
+  --threads N                Number of render threads
+  --projection NAME[:VALUE]  Camera projection: perspective, orthographic[:HEIGHT],
+                             fisheye[:FOV] or equirectangular
+  --iso N, --shutter S, --f-number N
+                             Expose like a camera, radiance being luminance in cd/m²,
+                             the shutter time in seconds or as 1/N (default ISO 100,
+                             1/100 s, f/16 when any is given)
+  --filter NAME              Pixel filter: box, tent or gaussian
+  --filter-radius R          Pixel filter radius, in pixels
+  --sampler NAME             Sample sequences: independent, stratified, halton or sobol
+  --blue-noise               Spread the noise of neighbouring pixels evenly, for previews
+  --light-cache N            Skip lights hidden from the cells of an N^3 grid over the scene
+  --preview N                Only shade what the camera sees, lit by the environment
+                             prefiltered into N columns, for quick looks
+  --ray-packets              Trace the camera rays of each pixel together, faster with many samples
+  --accelerator NAME         Structure rays find objects through: bvh, kd-tree or grid
+  --benchmark-accelerators   Only time building and tracing the scene with every accelerator
+  --precision NAME           Precision hierarchies are stored and traversed in: full or mixed
+  --trace-pixel X,Y          Only trace pixel (X, Y) and dump its paths
+  --trace-output FILE        Path dump file (.obj or .json)
+  --inspect-pixel X,Y        Only print the sample statistics of pixel (X, Y)
+  --bake-uv N                Render the material of sphere N into its UV space
+  --stats                    Print intersection statistics per object after rendering
+  --optimize                 Flatten instances, merge static meshes and remove spheres that can't
+                             change the image before rendering
+  --aovs                     Also write albedo, normal, depth and motion images next to the output
+  --thumbnail N              Embed a preview, N pixels on its longest side, in .png and .exr outputs
+  --denoise                  Smooth the noise of the image, guided by normals, albedos and depths
+  --sensor-noise N, --read-noise S
+                             Add the noise of a camera collecting N photons per pixel of
+                             value 1, with read noise S photons, at 1080 lines (default
+                             10000 and 3 when either is given)
+  --grain G, --grain-size W  Add film grain of strength G, W pixels wide at 1080 lines
+                             (default 1.5)
+  --importance-prior N       Spread samples where a prior pass of N samples per pixel is noisiest
+  --importance-map FILE      Spread samples following the brightness of an image (.ppm, .pfm or .hdr)
+  --check-output FILE        Tell which parts of the scene and settings changed since FILE was
+                             rendered, exiting with 1 when any did
+  --worker ADDRESS           Only render the regions of the image coordinators connecting to
+                             ADDRESS (like 0.0.0.0:7878) ask for, given the same options
+  --workers A,B...           Farm the regions of the image out to the workers at A, B...,
+                             started with the same scene and options, --seed included";
+
+struct Options {
+    scene: Option<String>,
+    ocean: bool,
+    /// Seed of the generated scenes, the render seed when unset.
+    scene_seed: Option<u64>,
+    output: String,
+    projection: Projection,
+    settings: RenderSettings,
+    trace_pixel: Option<(usize, usize)>,
+    trace_output: String,
+    inspect_pixel: Option<(usize, usize)>,
+    bake_uv: Option<usize>,
+    stats: bool,
+    optimize: bool,
+    aovs: bool,
+    thumbnail: Option<u32>,
+    /// Format default when unset, see `alpha_mode`.
+    alpha_mode: Option<AlphaMode>,
+    denoise: bool,
+    importance_prior: Option<usize>,
+    importance_map: Option<String>,
+    check_output: Option<String>,
+    /// Address the regions asked for by coordinators are served on.
+    worker: Option<String>,
+    /// Addresses of the workers the image is farmed out to, rendered here
+    /// when empty.
+    workers: Vec<String>,
+    shadow_floor: bool,
+    /// Replaces the accelerator of the scene when set.
+    accelerator: Option<AcceleratorKind>,
+    benchmark_accelerators: bool,
+    /// Replaces the precision of the scene when set.
+    precision: Option<Precision>,
+    /// Samples per pixel of the previews rendered while the meshes of the
+    /// scene stream in, loading them all first when unset.
+    stream: Option<usize>,
+    /// Samples per pixel of the passes of the interactive viewer, rendering
+    /// once when unset.
+    interactive: Option<usize>,
+    /// Address the previews are served on over HTTP.
+    preview_address: Option<String>,
+    /// Server started on `preview_address` once the options are checked.
+    preview_server: Option<PreviewServer>,
+    sweep: Option<Sweep>,
+    contact_sheet: Option<String>,
+    /// Frames of the animation to render, a single image when unset.
+    frames: Option<usize>,
+    /// Frames of a turn of the camera around what it looks at, set along
+    /// with `frames`.
+    turntable: Option<usize>,
+    fps: Float,
+    /// Encodes video outputs.
+    ffmpeg: String,
+    exposure: Option<Exposure>,
+    sensor_noise: Option<SensorNoise>,
 }
 
-fn schlick(cosine: f64, ref_idx: f64) -> f64 {
-    let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
-    r0 = r0 * r0;
-    r0 + (1.0 - r0) * (1.0 - cosine).powf(5.0)
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(1);
 }
 
-#[derive(Copy, Clone, Debug)]
-struct Ray {
-    origin: Vec3,
-    dir: Vec3,
+fn parse_value<T: std::str::FromStr>(value: Option<String>) -> T {
+    value
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_else(|| usage())
 }
 
-impl Ray {
-    fn new(origin: Vec3, dir: Vec3) -> Self {
-        Ray { origin, dir }
-    }
-
-    fn at(self, t: f64) -> Vec3 {
-        self.origin + self.dir * t
+fn parse_pair<T: std::str::FromStr>(value: Option<String>) -> (T, T) {
+    let value = value.unwrap_or_else(|| usage());
+    let mut parts = value.split(',');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(a), Some(b), None) => (
+            parse_value(Some(a.to_string())),
+            parse_value(Some(b.to_string())),
+        ),
+        _ => usage(),
     }
 }
 
-// #[derive(Copy, Clone)]
-// struct SimpleCamera {
-//     origin: Vec3,
-//     lower_left: Vec3,
-//     vertical: Vec3,
-//     horizontal: Vec3,
-// }
-
-// impl SimpleCamera {
-//     fn new(origin: Vec3, lower_left: Vec3, vertical: Vec3, horizontal: Vec3) -> Self {
-//         SimpleCamera {
-//             origin,
-//             lower_left,
-//             vertical,
-//             horizontal,
-//         }
-//     }
-
-//     fn get(self, u: f64, v: f64) -> Vec3 {
-//         self.lower_left + self.horizontal * u + self.vertical * v
-//     }
-
-//     fn get_ray(self, u: f64, v: f64) -> Ray {
-//         Ray::new(self.origin, self.get(u, v) - self.origin)
-//     }
-// }
-
-#[derive(Copy, Clone)]
-struct Camera {
-    origin: Vec3,
-    lower_left: Vec3,
-    vertical: Vec3,
-    horizontal: Vec3,
-    u: Vec3,
-    v: Vec3,
-    w: Vec3,
-    lens_radius: f64,
+/// Rectangle given as `X,Y,W,H`.
+fn parse_crop(value: Option<String>) -> Crop {
+    let value = value.unwrap_or_else(|| usage());
+    let parts: Vec<usize> = value
+        .split(',')
+        .map(|part| parse_value(Some(part.to_string())))
+        .collect();
+    match parts[..] {
+        [x, y, width, height] => Crop {
+            x,
+            y,
+            width,
+            height,
+        },
+        _ => usage(),
+    }
 }
 
-impl Camera {
-    fn new(
-        lookfrom: Vec3,
-        lookat: Vec3,
-        vup: Vec3,
-        vertical_fov_degrees: f64,
-        aspect: f64,
-        aperture: f64,
-        focus_dist: f64,
-    ) -> Self {
-        let origin = lookfrom;
-        let lens_radius = aperture / 2.0;
-
-        let theta = deg_to_rad(vertical_fov_degrees);
-        let half_height = f64::tan(theta / 2.0);
-        let half_width = aspect * half_height;
-
-        let w = (lookfrom - lookat).unit();
-        let u = (vup.cross(w)).unit();
-
-        let v = w.cross(u);
-
-        let lower_left =
-            origin - half_width * focus_dist * u - half_height * focus_dist * v - focus_dist * w;
-
-        let horizontal = 2.0 * half_width * focus_dist * u;
-        let vertical = 2.0 * half_height * focus_dist * v;
-
-        Camera {
-            origin,
-            lower_left,
-            vertical,
-            horizontal,
-            u,
-            v,
-            w,
-            lens_radius,
-        }
-    }
-
-    fn get_ray(self, s: f64, t: f64) -> Ray {
-        let rd: Vec3 = self.lens_radius * random_in_unit_disk();
-        let offset = self.u * rd.x + self.v * rd.y;
-
-        Ray::new(
-            self.origin + offset,
-            self.lower_left + self.horizontal * s + self.vertical * t - self.origin - offset,
-        )
+/// Shutter time in seconds, either as a number or as `1/N`.
+fn parse_shutter(value: Option<String>) -> Float {
+    let value = value.unwrap_or_else(|| usage());
+    match value.strip_prefix("1/") {
+        Some(denominator) => 1.0 / parse_value::<Float>(Some(denominator.to_string())),
+        None => parse_value(Some(value)),
     }
 }
 
-fn ray_color(ray: &Ray, objects: &[Box<dyn Hitable>], depth: i32) -> Vec3 {
-    let t_min = 0.0001;
-    let t_max = std::f64::INFINITY;
-
-    if depth <= 0 {
-        return Vec3::new(0.0, 0.0, 0.0);
+fn parse_projection(value: Option<String>) -> Projection {
+    let value = value.unwrap_or_else(|| usage());
+    let mut parts = value.splitn(2, ':');
+    let name = parts.next().unwrap_or("");
+    let parameter = parts.next().map(String::from);
+
+    match name {
+        "perspective" if parameter.is_none() => Projection::Perspective,
+        "equirectangular" if parameter.is_none() => Projection::Equirectangular,
+        "orthographic" => Projection::Orthographic {
+            height: parameter.map_or(4.0, |height| parse_value(Some(height))),
+        },
+        "fisheye" => Projection::Fisheye {
+            fov_degrees: parameter.map_or(180.0, |fov| parse_value(Some(fov))),
+        },
+        _ => usage(),
     }
+}
 
-    let mut closest: HitRecord = HitRecord::new(
-        Vec3::new(0.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 0.0),
-        std::f64::INFINITY,
-        false,
-        MaterialType::Lambertian {
-            albedo: Vec3::new(1.0, 0.0, 1.0),
+fn parse_args() -> Options {
+    let mut options = Options {
+        scene: None,
+        ocean: false,
+        scene_seed: None,
+        output: String::from("result.ppm"),
+        projection: Projection::Perspective,
+        trace_pixel: None,
+        trace_output: String::from("paths.obj"),
+        inspect_pixel: None,
+        bake_uv: None,
+        stats: false,
+        optimize: false,
+        aovs: false,
+        thumbnail: None,
+        alpha_mode: None,
+        denoise: false,
+        importance_prior: None,
+        importance_map: None,
+        check_output: None,
+        worker: None,
+        workers: Vec::new(),
+        shadow_floor: false,
+        accelerator: None,
+        benchmark_accelerators: false,
+        precision: None,
+        stream: None,
+        interactive: None,
+        preview_address: None,
+        preview_server: None,
+        sweep: None,
+        contact_sheet: None,
+        frames: None,
+        turntable: None,
+        fps: 24.0,
+        ffmpeg: String::from("ffmpeg"),
+        exposure: None,
+        sensor_noise: None,
+        settings: RenderSettings {
+            seed: random_seed(),
+            ..RenderSettings::default()
         },
-    );
-
-    for object in objects {
-        match object.hit(&ray, t_min, t_max) {
-            Some(record) => {
-                let close = closest;
-                if record.t < close.t {
-                    closest = record;
+    };
+
+    let mut filter = String::from("box");
+    let mut filter_radius = None;
+    let (mut photons, mut read_noise) = (None, None);
+    let (mut grain, mut grain_size) = (None, None);
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let settings = &mut options.settings;
+        match arg.as_str() {
+            "--scene" => options.scene = Some(args.next().unwrap_or_else(|| usage())),
+            "--output" => options.output = args.next().unwrap_or_else(|| usage()),
+            "--transparent-background" => settings.transparent_background = true,
+            "--alpha-mode" => {
+                options.alpha_mode = match args.next().as_deref() {
+                    Some("straight") => Some(AlphaMode::Straight),
+                    Some("premultiplied") => Some(AlphaMode::Premultiplied),
+                    _ => usage(),
+                }
+            }
+            "--ocean" => options.ocean = true,
+            "--shadow-floor" => options.shadow_floor = true,
+            "--sweep" => {
+                let text = args.next().unwrap_or_else(|| usage());
+                match Sweep::parse(&text) {
+                    Ok(sweep) => options.sweep = Some(sweep),
+                    Err(error) => {
+                        eprintln!("Invalid sweep: {}", error);
+                        std::process::exit(1);
+                    }
                 }
             }
-            None => continue,
+            "--contact-sheet" => {
+                options.contact_sheet = Some(args.next().unwrap_or_else(|| usage()))
+            }
+            "--stream" => options.stream = Some(parse_value(args.next())),
+            "--interactive" => options.interactive = Some(parse_value(args.next())),
+            "--preview-server" => {
+                options.preview_address = Some(args.next().unwrap_or_else(|| usage()))
+            }
+            "--frames" => options.frames = Some(parse_value(args.next())),
+            "--turntable" => options.turntable = Some(parse_value(args.next())),
+            "--fps" => options.fps = parse_value(args.next()),
+            "--ffmpeg" => options.ffmpeg = args.next().unwrap_or_else(|| usage()),
+            "--width" => settings.width = parse_value(args.next()),
+            "--height" => settings.height = parse_value(args.next()),
+            "--crop" => settings.crop = Some(parse_crop(args.next())),
+            "--samples" => settings.samples_per_pixel = parse_value(args.next()),
+            "--max-depth" => settings.max_depth = parse_value(args.next()),
+            "--max-diffuse" => settings.bounce_limits.diffuse = parse_value(args.next()),
+            "--max-glossy" => settings.bounce_limits.glossy = parse_value(args.next()),
+            "--max-transmission" => settings.bounce_limits.transmission = parse_value(args.next()),
+            "--max-volume" => settings.bounce_limits.volume = parse_value(args.next()),
+            "--max-distance" => settings.max_distance = parse_value(args.next()),
+            "--distance-fade" => settings.distance_fade = parse_value(args.next()),
+            "--clamp-direct" => settings.clamp_direct = parse_value(args.next()),
+            "--clamp-indirect" => settings.clamp_indirect = parse_value(args.next()),
+            "--clamp-sample" => settings.clamp_sample = parse_value(args.next()),
+            "--reject-outliers" => settings.outlier_sigma = parse_value(args.next()),
+            "--median-of-means" => settings.median_of_means = parse_value(args.next()),
+            "--seed" => settings.seed = parse_value(args.next()),
+            "--scene-seed" => options.scene_seed = Some(parse_value(args.next())),
+            "--threads" => settings.threads = parse_value(args.next()),
+            "--projection" => options.projection = parse_projection(args.next()),
+            "--iso" => {
+                let exposure = options.exposure.get_or_insert_with(Exposure::default);
+                exposure.iso = parse_value(args.next());
+            }
+            "--shutter" => {
+                let exposure = options.exposure.get_or_insert_with(Exposure::default);
+                exposure.shutter = parse_shutter(args.next());
+            }
+            "--f-number" => {
+                let exposure = options.exposure.get_or_insert_with(Exposure::default);
+                exposure.f_number = parse_value(args.next());
+            }
+            "--sensor-noise" => photons = Some(parse_value(args.next())),
+            "--read-noise" => read_noise = Some(parse_value(args.next())),
+            "--grain" => grain = Some(parse_value(args.next())),
+            "--grain-size" => grain_size = Some(parse_value(args.next())),
+            "--filter" => filter = args.next().unwrap_or_else(|| usage()),
+            "--filter-radius" => filter_radius = Some(parse_value(args.next())),
+            "--sampler" => {
+                settings.sampler = match args.next().as_deref() {
+                    Some("independent") => SamplerType::Independent,
+                    Some("stratified") => SamplerType::Stratified,
+                    Some("halton") => SamplerType::Halton,
+                    Some("sobol") => SamplerType::Sobol,
+                    _ => usage(),
+                }
+            }
+            "--blue-noise" => settings.blue_noise = true,
+            "--light-cache" => settings.light_cache = parse_value(args.next()),
+            "--preview" => settings.preview = parse_value(args.next()),
+            "--ray-packets" => settings.ray_packets = true,
+            "--accelerator" => {
+                let name = args.next().unwrap_or_else(|| usage());
+                options.accelerator =
+                    Some(AcceleratorKind::from_name(&name).unwrap_or_else(|| usage()));
+            }
+            "--benchmark-accelerators" => options.benchmark_accelerators = true,
+            "--precision" => {
+                let name = args.next().unwrap_or_else(|| usage());
+                options.precision = Some(Precision::from_name(&name).unwrap_or_else(|| usage()));
+            }
+            "--trace-pixel" => options.trace_pixel = Some(parse_pair(args.next())),
+            "--trace-output" => options.trace_output = args.next().unwrap_or_else(|| usage()),
+            "--inspect-pixel" => options.inspect_pixel = Some(parse_pair(args.next())),
+            "--bake-uv" => options.bake_uv = Some(parse_value(args.next())),
+            "--stats" => options.stats = true,
+            "--optimize" => options.optimize = true,
+            "--aovs" => options.aovs = true,
+            "--thumbnail" => options.thumbnail = Some(parse_value(args.next())),
+            "--denoise" => options.denoise = true,
+            "--importance-prior" => options.importance_prior = Some(parse_value(args.next())),
+            "--importance-map" => {
+                options.importance_map = Some(args.next().unwrap_or_else(|| usage()))
+            }
+            "--check-output" => options.check_output = Some(args.next().unwrap_or_else(|| usage())),
+            "--worker" => options.worker = Some(args.next().unwrap_or_else(|| usage())),
+            "--workers" => {
+                let workers = args.next().unwrap_or_else(|| usage());
+                options.workers = workers.split(',').map(String::from).collect();
+            }
+            _ => usage(),
         }
     }
 
-    let hit_info = closest;
-
-    if hit_info.t < std::f64::INFINITY {
-        let scatter_res = hit_info.material.scatter(ray, &hit_info);
+    if options.settings.width == 0 || options.settings.height == 0 {
+        usage();
+    }
 
-        match scatter_res {
-            Some((attenuation, scattered)) => {
-                return attenuation * ray_color(&scattered, objects, depth - 1)
+    options.settings.filter = match filter.as_str() {
+        "box" => Filter::Box {
+            radius: filter_radius.unwrap_or(0.5),
+        },
+        "tent" => Filter::Tent {
+            radius: filter_radius.unwrap_or(1.0),
+        },
+        "gaussian" => Filter::Gaussian {
+            radius: filter_radius.unwrap_or(1.5),
+            alpha: 2.0,
+        },
+        _ => usage(),
+    };
+    if let Some(crop) = options.settings.crop {
+        if !crop.fits(options.settings.width, options.settings.height) {
+            usage();
+        }
+    }
+    let max_distance = options.settings.max_distance;
+    if options.settings.filter.radius() <= 0.0 || max_distance.is_nan() || max_distance <= 0.0 {
+        usage();
+    }
+    if !(0.0..=1.0).contains(&options.settings.distance_fade) {
+        usage();
+    }
+    let settings = &options.settings;
+    for &value in &[
+        settings.clamp_direct,
+        settings.clamp_indirect,
+        settings.clamp_sample,
+        settings.outlier_sigma,
+    ] {
+        if value.is_nan() || value <= 0.0 {
+            usage();
+        }
+    }
+    if options.importance_prior.is_some() && options.importance_map.is_some() {
+        usage();
+    }
+    if options.contact_sheet.is_some() && options.sweep.is_none() {
+        usage();
+    }
+    if let Some(frames) = options.turntable {
+        if options.frames.is_some() {
+            usage();
+        }
+        options.frames = Some(frames);
+    }
+    if options.frames == Some(0) || (options.frames.is_some() && options.sweep.is_some()) {
+        usage();
+    }
+    // Streamed meshes are put in place of objects, which can't move or go
+    // away before they come in.
+    if options.stream == Some(0)
+        || (options.stream.is_some()
+            && (options.scene.is_none()
+                || options.frames.is_some()
+                || options.sweep.is_some()
+                || options.optimize))
+    {
+        usage();
+    }
+    // Workers render the colors of single images, which they can't spread
+    // their samples over.
+    let distributed = options.worker.is_some() || !options.workers.is_empty();
+    if (options.worker.is_some() && !options.workers.is_empty())
+        || (distributed
+            && (options.frames.is_some()
+                || options.sweep.is_some()
+                || options.interactive.is_some()
+                || options.aovs
+                || options.denoise
+                || options.importance_prior.is_some()
+                || options.importance_map.is_some()))
+    {
+        usage();
+    }
+    // The viewer shows the colors of a single view.
+    if options.interactive == Some(0)
+        || (options.interactive.is_some()
+            && (options.frames.is_some()
+                || options.sweep.is_some()
+                || options.aovs
+                || options.denoise))
+    {
+        usage();
+    }
+    // Only streamed and interactive renders have previews to serve.
+    if options.preview_address.is_some()
+        && options.stream.is_none()
+        && options.interactive.is_none()
+    {
+        usage();
+    }
+    if !options.fps.is_finite() || options.fps <= 0.0 {
+        usage();
+    }
+    // Videos hold the frames of animations alone.
+    if is_video(&options.output) && (options.frames.is_none() || options.aovs) {
+        usage();
+    }
+    if let Some(exposure) = options.exposure {
+        for &value in &[exposure.iso, exposure.shutter, exposure.f_number] {
+            if !value.is_finite() || value <= 0.0 {
+                usage();
             }
-            None => return Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+    if photons.is_some() || read_noise.is_some() || grain.is_some() || grain_size.is_some() {
+        // Grain alone leaves out the noise of the sensor.
+        let defaults = if photons.is_some() || read_noise.is_some() {
+            SensorNoise::default()
+        } else {
+            SensorNoise {
+                photons: 0.0,
+                read_noise: 0.0,
+                ..SensorNoise::default()
+            }
+        };
+        let noise = SensorNoise {
+            photons: photons.unwrap_or(defaults.photons),
+            read_noise: read_noise.unwrap_or(defaults.read_noise),
+            grain: grain.unwrap_or(defaults.grain),
+            grain_size: grain_size.unwrap_or(defaults.grain_size),
+            seed: options.settings.seed,
+        };
+        for &value in &[noise.photons, noise.read_noise, noise.grain] {
+            if !value.is_finite() || value < 0.0 {
+                usage();
+            }
+        }
+        if !noise.grain_size.is_finite() || noise.grain_size <= 0.0 {
+            usage();
+        }
+        options.sensor_noise = Some(noise);
+    }
+    for &(x, y) in options.trace_pixel.iter().chain(&options.inspect_pixel) {
+        if x >= options.settings.width || y >= options.settings.height {
+            usage();
         }
     }
 
-    let unit_vec = ray.dir.unit();
-    let t = 0.5 * (unit_vec.y + 1.0);
-    Vec3::new(1.0, 1.0, 1.0) * (1.0 - t) + Vec3::new(0.5, 0.7, 1.0) * t
+    options
 }
 
-struct Sphere {
-    position: Vec3,
-    radius: f64,
-
-    material: MaterialType,
+fn file_extension(name: &str) -> &str {
+    Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("")
 }
 
-impl Sphere {
-    fn new(position: Vec3, radius: f64, material: MaterialType) -> Self {
-        Sphere {
-            position,
-            radius,
-            material,
-        }
-    }
+/// Converts a linear color channel to a gamma corrected byte.
+fn color_to_byte(x: Float) -> u8 {
+    // Do gamma correction, clamp values between 0 and 1, convert to 0 -> 256 range
+    (255.9 * clamp(Float::sqrt(x), 0.0, 0.9999)) as u8
 }
 
-impl Hitable for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = ray.origin - self.position;
-        let a = ray.dir.dot(ray.dir);
-        let b = 2.0 * oc.dot(ray.dir);
-        let c = oc.dot(oc) - self.radius * self.radius;
-
-        let discriminant = b * b - 4.0 * a * c;
-
-        if discriminant < 0.0 {
-            None
-        } else {
-            let root = f64::sqrt(discriminant);
-            let t1 = (-b - root) / (2.0 * a);
-            let t2 = (-b + root) / (2.0 * a);
-
-            let t = if t1 < t_max && t1 > t_min {
-                t1
-            } else if t2 < t_max && t2 > t_min {
-                t2
-            } else {
-                return None;
-            };
-
-            let normal = ray.at(t) - self.position;
-
-            Some(HitRecord::new(
-                ray.at(t),
-                normal,
-                t,
-                ray.dir.dot(normal) < 0.0,
-                self.material,
-            ))
-        }
-    }
+fn alpha_to_byte(x: Float) -> u8 {
+    (255.9 * clamp(x, 0.0, 0.9999)) as u8
 }
 
-#[derive(Clone, Copy)]
-enum MaterialType {
-    Lambertian { albedo: Vec3 },
-    Metal { albedo: Vec3, fuzziness: f64 },
-    Dialectric { refractive_index: f64 },
+/// How the colors of `name` relate to its alpha: as `requested`, or
+/// following its format, straight for .png as the PNG specification wants
+/// and premultiplied for the others, like OpenEXR.
+fn alpha_mode(name: &str, requested: Option<AlphaMode>) -> AlphaMode {
+    match (requested, file_extension(name)) {
+        (Some(mode), _) => mode,
+        (None, "png") => AlphaMode::Straight,
+        (None, _) => AlphaMode::Premultiplied,
+    }
 }
 
-#[derive(Clone, Copy)]
-struct HitRecord {
-    position: Vec3,
-    normal: Vec3,
-    t: f64,
-    front_face: bool,
-    material: MaterialType,
+/// What output images store besides their pixels.
+#[derive(Default)]
+struct ImageHeader {
+    metadata: Vec<(String, String)>,
+    /// Only kept by .png and .exr files.
+    preview: Option<Preview>,
 }
 
-impl HitRecord {
-    fn new(position: Vec3, normal: Vec3, t: f64, front_face: bool, material: MaterialType) -> Self {
-        let mut normal = if front_face { normal } else { -normal };
-        normal = normal.unit();
-        HitRecord {
-            position,
-            normal,
-            t,
-            front_face,
-            material,
+impl ImageHeader {
+    fn new(metadata: &[(String, String)]) -> Self {
+        ImageHeader {
+            metadata: metadata.to_vec(),
+            preview: None,
         }
     }
 }
 
-trait Hitable: Sync {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
-}
-trait Material {
-    fn scatter(&self, ray: &Ray, rec: &HitRecord) -> Option<(Vec3, Ray)>;
+/// Writes linear, premultiplied RGBA `pixels`, picking the file format from the
+/// extension of `name`. Alpha is dropped when `alpha` is unset or the format
+/// cannot store it, and kept with colors following the given mode otherwise.
+/// 8-bit formats are gamma encoded when `gamma` is set, and store values as
+/// they are otherwise. `header` goes in the file header.
+fn write_image(
+    name: &str,
+    pixels: &[Float],
+    width: u32,
+    height: u32,
+    alpha: Option<AlphaMode>,
+    gamma: bool,
+    header: &ImageHeader,
+) -> std::io::Result<()> {
+    let (metadata, preview) = (&header.metadata, header.preview.as_ref());
+    let extension = file_extension(name);
+    let channels = if alpha.is_some() { 4 } else { 3 };
+    let color_to_byte = if gamma { color_to_byte } else { alpha_to_byte };
+
+    match extension {
+        "exr" => {
+            let mut pixels = pixels.to_vec();
+            if alpha == Some(AlphaMode::Straight) {
+                unpremultiply(&mut pixels);
+            }
+            let output_pixels: Vec<f32> = pixels
+                .chunks(4)
+                .flat_map(|pixel| pixel[..channels].iter().map(|&x| to_f32(x)))
+                .collect();
+            create_exr(
+                name,
+                &output_pixels,
+                width,
+                height,
+                alpha.is_some(),
+                metadata,
+                preview,
+            )
+        }
+        "png" => {
+            let mut straight = pixels.to_vec();
+            unpremultiply(&mut straight);
+            let output_pixels: Vec<u8> = pixels
+                .chunks(4)
+                .zip(straight.chunks(4))
+                .flat_map(|(pixel, straight)| match alpha {
+                    None => pixel[..3].iter().map(|&x| color_to_byte(x)).collect(),
+                    Some(mode) => {
+                        // Gamma encoding doesn't commute with the scaling by
+                        // alpha, so colors are encoded straight, then
+                        // premultiplied as 8-bit values, which is what
+                        // compositing packages undo.
+                        let scale = match mode {
+                            AlphaMode::Straight => 1.0,
+                            AlphaMode::Premultiplied => pixel[3],
+                        };
+                        let mut bytes: Vec<u8> = straight[..3]
+                            .iter()
+                            .map(|&x| (color_to_byte(x) as Float * scale).round() as u8)
+                            .collect();
+                        bytes.push(alpha_to_byte(pixel[3]));
+                        bytes
+                    }
+                })
+                .collect();
+            create_png(
+                name,
+                &output_pixels,
+                width,
+                height,
+                alpha.is_some(),
+                metadata,
+                preview,
+            )
+        }
+        _ => {
+            let output_pixels: Vec<u8> = pixels
+                .chunks(4)
+                .flat_map(|pixel| pixel[..3].iter().map(|&x| color_to_byte(x)))
+                .collect();
+            create_ppm(name, &output_pixels, width, height, metadata)
+        }
+    }
 }
 
-impl Material for MaterialType {
-    fn scatter(&self, ray: &Ray, rec: &HitRecord) -> Option<(Vec3, Ray)> {
-        match &self {
-            MaterialType::Lambertian { albedo } => {
-                let scatter_direction = rec.normal + random_in_hemisphere(rec.normal);
-                let scattered = Ray::new(rec.position, scatter_direction);
-                let attenuation = *albedo;
-                Some((attenuation, scattered))
-            }
-            MaterialType::Metal { albedo, fuzziness } => {
-                let reflected = reflect(ray.dir.unit(), rec.normal);
-                let scattered = Ray::new(
-                    rec.position,
-                    reflected + *fuzziness * (random_in_hemisphere(rec.normal)),
-                );
-                let attenuation = albedo;
-                if scattered.dir.dot(rec.normal) > 0.0 {
-                    Some((*attenuation, scattered))
-                } else {
-                    None
+/// Preview of linear, premultiplied RGBA `pixels`, `size` pixels on its
+/// longest side, each the average of the pixels it covers.
+fn thumbnail(pixels: &[Float], width: usize, height: usize, size: u32) -> Preview {
+    let scale = width.max(height) as Float / size.max(1) as Float;
+    let preview_width = ((width as Float / scale).round() as usize).max(1);
+    let preview_height = ((height as Float / scale).round() as usize).max(1);
+
+    let mut preview = Vec::with_capacity(preview_width * preview_height * 4);
+    for y in 0..preview_height {
+        let (top, bottom) = (
+            y * height / preview_height,
+            (y + 1) * height / preview_height,
+        );
+        for x in 0..preview_width {
+            let (left, right) = (x * width / preview_width, (x + 1) * width / preview_width);
+            let mut sum = [0.0; 4];
+            for row in top..bottom.max(top + 1) {
+                for column in left..right.max(left + 1) {
+                    let pixel = &pixels[(row * width + column) * 4..][..4];
+                    for (sum, &value) in sum.iter_mut().zip(pixel) {
+                        *sum += value;
+                    }
                 }
             }
-            MaterialType::Dialectric { refractive_index } => {
-                let attenuation = Vec3::new(1.0, 1.0, 1.0);
-                let etai_over_etat = if rec.front_face {
-                    1.0 / refractive_index
-                } else {
-                    *refractive_index
-                };
+            let count = ((bottom - top).max(1) * (right - left).max(1)) as Float;
+            preview.push(color_to_byte(sum[0] / count));
+            preview.push(color_to_byte(sum[1] / count));
+            preview.push(color_to_byte(sum[2] / count));
+            preview.push(alpha_to_byte(sum[3] / count));
+        }
+    }
 
-                let unit_direction = ray.dir.unit();
-                let cos_theta = f64::min(-unit_direction.dot(rec.normal), 1.0);
-                let sin_theta = f64::sqrt(1.0 - cos_theta * cos_theta);
+    Preview {
+        width: preview_width as u32,
+        height: preview_height as u32,
+        pixels: preview,
+    }
+}
 
-                if etai_over_etat * sin_theta > 1.0 {
-                    let reflected = reflect(unit_direction, rec.normal);
-                    let scattered = Ray::new(rec.position, reflected);
-                    return Some((attenuation, scattered));
-                }
+/// Loads the brightness of the image `name` as one importance value per
+/// pixel, row-major.
+fn load_importance_map(name: &str, width: usize, height: usize) -> std::io::Result<Vec<Float>> {
+    let (pixels, map_width, map_height) = if name.ends_with(".ppm") {
+        let (pixels, map_width, map_height) = read_ppm(name)?;
+        let pixels = pixels.iter().map(|&x| x as f32 / 255.0).collect();
+        (pixels, map_width, map_height)
+    } else if name.ends_with(".pfm") {
+        read_pfm(name)?
+    } else if name.ends_with(".hdr") {
+        read_hdr(name)?
+    } else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "importance maps must be .ppm, .pfm or .hdr files",
+        ));
+    };
+
+    if map_width as usize != width || map_height as usize != height {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "the map is {}x{} but the image is {}x{}",
+                map_width, map_height, width, height
+            ),
+        ));
+    }
 
-                let reflect_prob = schlick(cos_theta, etai_over_etat);
-                if random_01() < reflect_prob {
-                    let reflected = reflect(unit_direction, rec.normal);
-                    let scattered = Ray::new(rec.position, reflected);
-                    return Some((attenuation, scattered));
-                }
+    Ok(pixels
+        .chunks(3)
+        .map(|pixel| {
+            luminance(Vec3::new(
+                pixel[0] as Float,
+                pixel[1] as Float,
+                pixel[2] as Float,
+            ))
+        })
+        .collect())
+}
 
-                let refracted = refract(unit_direction, rec.normal, etai_over_etat);
-                let scattered = Ray::new(rec.position, refracted);
-                Some((attenuation, scattered))
-            }
-        }
+/// Name of the `pass` image written next to `output`, like `out.normal.png`,
+/// or of a sweep frame, like `out.003.png`.
+fn aov_file_name(output: &str, pass: &str) -> String {
+    let path = Path::new(output);
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => path
+            .with_extension(format!("{}.{}", pass, extension))
+            .to_string_lossy()
+            .into_owned(),
+        None => format!("{}.{}", output, pass),
     }
 }
 
-fn make_random_scene() -> Vec<Box<dyn Hitable>> {
-    let mut objects: Vec<Box<dyn Hitable>> = Vec::new();
+/// Name of frame `number` of an animation rendered to `output`: in place of
+/// its first run of `#`, padded with zeros to its length, like
+/// `frame_####.png`, or else before its extension, like `out_0001.png`.
+fn frame_file_name(output: &str, number: usize) -> String {
+    if let Some(start) = output.find('#') {
+        let length = output[start..]
+            .find(|c| c != '#')
+            .unwrap_or(output.len() - start);
+        return format!(
+            "{}{:0width$}{}",
+            &output[..start],
+            number,
+            &output[start + length..],
+            width = length
+        );
+    }
+    let path = Path::new(output);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}_{:04}.{}", stem, number, extension.to_string_lossy()),
+        None => format!("{}_{:04}", stem, number),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
 
-    objects.push(Box::new(Sphere::new(
-        Vec3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        MaterialType::Lambertian {
-            albedo: Vec3::new(0.5, 0.5, 0.5),
-        },
-    )));
+/// Writes the albedo, normal, depth and motion images next to `output`, in
+/// the same format. 8-bit formats get normals remapped to [0, 1], depths
+/// divided by the largest one and motion vectors remapped around 0.5 by the
+/// longest one, floating point formats keep the raw values.
+fn write_aovs(
+    output: &str,
+    aovs: &AovImages,
+    width: u32,
+    height: u32,
+    metadata: &[(String, String)],
+) -> std::io::Result<()> {
+    let float = output.ends_with(".exr");
+    let header = ImageHeader::new(metadata);
+    let rgba = |values: &[Float], channels: usize, remap: &dyn Fn(Float) -> Float| -> Vec<Float> {
+        values
+            .chunks(channels)
+            .flat_map(|pixel| {
+                let mut rgba: Vec<Float> = (0..3)
+                    .map(|channel| remap(pixel[channel.min(channels - 1)]))
+                    .collect();
+                rgba.push(1.0);
+                rgba
+            })
+            .collect()
+    };
+
+    let albedo = rgba(&aovs.albedo, 3, &|x| x);
+    write_image(
+        &aov_file_name(output, "albedo"),
+        &albedo,
+        width,
+        height,
+        None,
+        true,
+        &header,
+    )?;
+
+    let normal = if float {
+        rgba(&aovs.normal, 3, &|x| x)
+    } else {
+        rgba(&aovs.normal, 3, &|x| 0.5 * x + 0.5)
+    };
+    write_image(
+        &aov_file_name(output, "normal"),
+        &normal,
+        width,
+        height,
+        None,
+        false,
+        &header,
+    )?;
 
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = random_01();
+    let far = aovs
+        .depth
+        .iter()
+        .cloned()
+        .filter(|depth| depth.is_finite())
+        .fold(0.0, Float::max);
+    let depth = if float {
+        rgba(&aovs.depth, 1, &|x| x)
+    } else {
+        rgba(&aovs.depth, 1, &|x| {
+            if x.is_finite() {
+                x / far.max(1e-9)
+            } else {
+                1.0
+            }
+        })
+    };
+    write_image(
+        &aov_file_name(output, "depth"),
+        &depth,
+        width,
+        height,
+        None,
+        false,
+        &header,
+    )?;
 
-            let center = Vec3::new(
-                a as f64 + 0.9 * random_01(),
-                0.2,
-                b as f64 + 0.9 * random_01(),
-            );
+    let longest = aovs
+        .motion
+        .iter()
+        .fold(0.0, |longest: Float, x| longest.max(x.abs()));
+    let scale = if float { 1.0 } else { 0.5 / longest.max(1e-9) };
+    let offset = if float { 0.0 } else { 0.5 };
+    let motion: Vec<Float> = aovs
+        .motion
+        .chunks(2)
+        .flat_map(|pixel| {
+            [
+                pixel[0] * scale + offset,
+                pixel[1] * scale + offset,
+                0.0,
+                1.0,
+            ]
+        })
+        .collect();
+    write_image(
+        &aov_file_name(output, "motion"),
+        &motion,
+        width,
+        height,
+        None,
+        false,
+        &header,
+    )
+}
 
-            if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                if choose_mat < 0.8 {
-                    // diffuse
-                    let albedo = Vec3::new(random_01(), random_01(), random_01());
-                    objects.push(Box::new(Sphere::new(
-                        center,
-                        0.2,
-                        MaterialType::Lambertian { albedo },
-                    )));
-                } else if choose_mat < 0.95 {
-                    let albedo = Vec3::new(random_between(0.5, 1.0), random_between(0.5, 1.0), 1.0);
-                    let fuzziness = random_between(0.0, 0.5);
-                    objects.push(Box::new(Sphere::new(
-                        center,
-                        0.2,
-                        MaterialType::Metal { albedo, fuzziness },
-                    )));
-                } else {
-                    objects.push(Box::new(Sphere::new(
-                        center,
-                        0.2,
-                        MaterialType::Dialectric {
-                            refractive_index: 1.5,
-                        },
-                    )));
-                }
-            }
+/// Compares the hashes stored in the image `name` with `hashes`, printing
+/// the state of every component, and returns whether they all match.
+fn check_output(name: &str, hashes: &RenderHashes) -> bool {
+    let metadata = match file_extension(name) {
+        "exr" => read_exr_metadata(name),
+        "png" => read_png_metadata(name),
+        _ => read_ppm_metadata(name),
+    };
+    let stored = match metadata {
+        Ok(metadata) => RenderHashes::from_metadata(&metadata),
+        Err(error) => {
+            eprintln!("Could not read {}: {}", name, error);
+            return false;
+        }
+    };
+    let stored = match stored {
+        Some(stored) => stored,
+        None => {
+            println!("{} holds no render hashes", name);
+            return false;
         }
+    };
+
+    let changed = stored.changed(hashes);
+    for (component, _) in hashes.components().iter() {
+        let state = if changed.contains(component) {
+            "changed"
+        } else {
+            "up to date"
+        };
+        println!("{:<12} {}", component, state);
     }
+    changed.is_empty()
+}
 
-    objects.push(Box::new(Sphere::new(
-        Vec3::new(0.0, 1.0, 0.0),
-        1.0,
-        MaterialType::Dialectric {
-            refractive_index: 1.5,
-        },
-    )));
+/// Number of rows of the statistics tables.
+const STATS_ROWS: usize = 20;
 
-    objects.push(Box::new(Sphere::new(
-        Vec3::new(-4.0, 1.0, 0.0),
-        1.0,
-        MaterialType::Lambertian {
-            albedo: Vec3::new(0.4, 0.2, 0.1),
-        },
-    )));
-
-    objects.push(Box::new(Sphere::new(
-        Vec3::new(4.0, 1.0, 0.0),
-        1.0,
-        MaterialType::Metal {
-            albedo: Vec3::new(0.7, 0.6, 0.5),
-            fuzziness: 0.0,
-        },
-    )));
+fn print_stats_table(title: &str, rows: &mut [(String, ObjectStats)]) {
+    let total: u64 = rows.iter().map(|(_, stats)| stats.nanoseconds).sum();
+    rows.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.nanoseconds));
 
-    objects
+    println!();
+    println!(
+        "{:<24} {:>12} {:>12} {:>10} {:>6}",
+        title, "tests", "hits", "time (ms)", "share"
+    );
+    for (label, stats) in rows.iter().take(STATS_ROWS) {
+        println!(
+            "{:<24} {:>12} {:>12} {:>10.1} {:>5.1}%",
+            label,
+            stats.tests,
+            stats.hits,
+            stats.nanoseconds as Float / 1e6,
+            100.0 * stats.nanoseconds as Float / total.max(1) as Float
+        );
+    }
+    if rows.len() > STATS_ROWS {
+        println!("... and {} more", rows.len() - STATS_ROWS);
+    }
 }
 
-fn index_2d_to_1d(i: usize, j: usize, width: usize, _height: usize) -> usize {
-    j * width + i
-}
+/// Prints the slowest objects and materials to intersect.
+fn print_stats(scene: &Scene) {
+    let stats = match scene.stats() {
+        Some(stats) => stats,
+        None => return,
+    };
+
+    let mut objects = Vec::new();
+    let mut materials = vec![ObjectStats::default(); scene.materials.len()];
+    for (index, object_stats) in stats.iter().enumerate() {
+        let sphere = ObjectId(index);
+        let label = match scene.name(sphere) {
+            Some(name) => format!("#{} {}", index, name),
+            None => format!("#{}", index),
+        };
+        objects.push((label, *object_stats));
+        materials[scene.sphere(sphere).material.0].add(object_stats);
+    }
 
-fn index_1d_to_2d(index: usize, width: usize, _height: usize) -> (usize, usize) {
-    (index % width, index / width)
+    let mut materials: Vec<(String, ObjectStats)> = materials
+        .into_iter()
+        .enumerate()
+        .filter(|(_, stats)| stats.tests > 0)
+        .map(|(index, stats)| {
+            let label = match scene.material_name(MaterialId(index)) {
+                Some(name) => format!("#{} {}", index, name),
+                None => format!("#{}", index),
+            };
+            (label, stats)
+        })
+        .collect();
+
+    print_stats_table("object", &mut objects);
+    print_stats_table("material", &mut materials);
 }
 
 fn main() {
+    let mut options = parse_args();
+    let settings = options.settings;
+
+    if let Some(address) = &options.preview_address {
+        let listener = match TcpListener::bind(address) {
+            Ok(listener) => listener,
+            Err(error) => {
+                eprintln!("Could not listen on {}: {}", address, error);
+                std::process::exit(1);
+            }
+        };
+        println!("Serving the previews on http://{}/", address);
+        options.preview_server = Some(PreviewServer::start(listener));
+    }
+
     println!("Hello, raytracer!");
 
-    let image_width = 1920;
-    let image_height = 1080;
-    let samples_per_pixel = 100;
-    let max_depth = 50;
+    let mut renderer = match Renderer::new(settings) {
+        Ok(renderer) => renderer,
+        Err(error) => {
+            eprintln!("Could not start the render threads: {}", error);
+            std::process::exit(1);
+        }
+    };
 
-    let aspect_ratio = image_width as f64 / image_height as f64;
-    let lookfrom = Vec3::new(13.0, 2.0, 3.0);
-    let lookat = Vec3::new(0.0, 0.0, 0.0);
-    let vup = Vec3::new(0.0, 1.0, 0.0);
-    let dist_to_focus = 10.0;
+    let aspect_ratio = settings.width as Float / settings.height as Float;
+    let mut dist_to_focus = 10.0;
     let aperture = 0.1;
 
-    let camera = Camera::new(
-        lookfrom,
-        lookat,
-        vup,
-        20.0,
-        aspect_ratio,
-        aperture,
-        dist_to_focus,
-    );
+    let scene_seed = options.scene_seed.unwrap_or(settings.seed);
+    let (mut scene, stream) = match &options.scene {
+        Some(name) => {
+            let loaded = match options.stream {
+                Some(_) => load_scene_streamed(name).map(|(scene, stream)| (scene, Some(stream))),
+                None => load_scene(name).map(|scene| (scene, None)),
+            };
+            match loaded {
+                // The camera is set up in meters.
+                Ok((mut scene, stream)) => {
+                    scene.convert_to(Units::Meters);
+                    (scene, stream)
+                }
+                Err(error) => {
+                    eprintln!("Could not load {}: {}", name, error);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None if options.ocean => (make_ocean_scene(scene_seed), None),
+        None => (make_random_scene(scene_seed), None),
+    };
+    if let Some(kind) = options.accelerator {
+        scene.set_accelerator_kind(kind);
+    }
+    if let Some(precision) = options.precision {
+        scene.set_precision(precision);
+    }
+    // Animated scenes start from their first frame.
+    scene.animate(0.0, None);
+    // Scenes coming with a camera, like glTF files, are seen through it,
+    // unless it has keyframes.
+    let view_at = |scene: &Scene, time| {
+        let view = scene.animation.camera.at(time);
+        view.or(scene.camera).unwrap_or_default()
+    };
+    let view = view_at(&scene, 0.0);
+
+    let make_camera = |view: CameraView, dist_to_focus, exposure: Option<Exposure>| {
+        let camera = Camera::new(
+            view.look_from,
+            view.look_at,
+            view.up,
+            view.vertical_fov,
+            aspect_ratio,
+            aperture,
+            dist_to_focus,
+        )
+        .with_projection(options.projection);
+        match exposure {
+            Some(exposure) => camera.with_exposure(exposure),
+            None => camera,
+        }
+    };
+    let mut exposure = options.exposure;
+    let mut camera = make_camera(view, dist_to_focus, exposure);
+    // The defaults below follow the whole scene.
+    if let Some(stream) = stream {
+        preview_stream(stream, &mut scene, &mut renderer, &camera, &options);
+    }
 
-    let objects = make_random_scene();
+    // Scene files can't set the offsets, focus or exposure, so they follow
+    // the scene, the exposure only when the command line leaves it out.
+    if options.scene.is_some() {
+        let defaults = SceneDefaults::derive(&scene, &camera);
+        println!("Scene defaults: {}", defaults);
+        scene.set_epsilon(Some(defaults.epsilon));
+        exposure = options
+            .exposure
+            .or_else(|| defaults.exposure_stops.map(Exposure::from_stops));
+        dist_to_focus = defaults.focus_distance.unwrap_or(dist_to_focus);
+        camera = make_camera(view, dist_to_focus, exposure);
+    }
+    let describe_camera = |view: &CameraView| {
+        format!(
+            "from {} {} {}, at {} {} {}, {} degrees, aperture {}, focus {}, {:?}",
+            view.look_from.x,
+            view.look_from.y,
+            view.look_from.z,
+            view.look_at.x,
+            view.look_at.y,
+            view.look_at.z,
+            view.vertical_fov,
+            aperture,
+            dist_to_focus,
+            options.projection
+        )
+    };
+    let camera_description = describe_camera(&view);
+
+    if options.shadow_floor {
+        match scene.sphere_named("ground") {
+            Some(ground) => scene.set_shadow_catcher(ground, true),
+            None => {
+                eprintln!("No sphere is named ground");
+                std::process::exit(1);
+            }
+        }
+    }
+    if options.optimize {
+        if options.sweep.is_some() {
+            eprintln!("Cannot optimize a swept scene, sweeps can bring removed spheres back");
+            std::process::exit(1);
+        }
+        println!("Optimized the scene, {}", scene.optimize());
+    }
+    let mut sweep = options.sweep.clone();
+    if let Some(sweep) = &mut sweep {
+        if let Err(error) = sweep.bind(&scene) {
+            eprintln!("Cannot sweep: {}", error);
+            std::process::exit(1);
+        }
+    }
+    if settings.light_cache > 0 {
+        let cache = LightCache::build(&scene, &camera, settings.light_cache);
+        scene.set_light_cache(Some(cache));
+    }
+    if settings.preview > 0 {
+        let preview = PreviewEnvironment::build(&scene.environment, settings.preview);
+        scene.set_preview_environment(Some(preview));
+    }
 
     // let mut objects: Vec<Box<dyn Hitable>> = Vec::new();
     // objects.push(Box::new(Sphere::new(
@@ -488,52 +1196,608 @@ fn main() {
     //     },
     // )));
 
-    println!("Start rendering");
-    let start_time = Instant::now();
+    if options.benchmark_accelerators {
+        let timings = benchmark_accelerators(&mut scene, &camera, settings.width, settings.height);
+        println!(
+            "{:<10} {:>12} {:>12} {:>12} {:>10}",
+            "", "build (ms)", "trace (ms)", "Mrays/s", "hits"
+        );
+        for timing in &timings {
+            println!(
+                "{:<10} {:>12.2} {:>12.2} {:>12.3} {:>10}",
+                timing.kind.name(),
+                timing.build.as_secs_f64() * 1000.0,
+                timing.trace.as_secs_f64() * 1000.0,
+                timing.rays_per_second(),
+                timing.hits
+            );
+        }
+        return;
+    }
 
-    let pixels: Vec<_> = (0..image_width * image_height)
-        .into_par_iter()
-        .map(|index| {
-            let (i, j) = index_1d_to_2d(index, image_width, image_height);
+    let hashes = RenderHashes::new(&scene, &camera, &settings);
+    if let Some(name) = &options.check_output {
+        let up_to_date = check_output(name, &hashes);
+        std::process::exit(if up_to_date { 0 } else { 1 });
+    }
+    if let Some(address) = &options.worker {
+        let listener = match TcpListener::bind(address) {
+            Ok(listener) => listener,
+            Err(error) => {
+                eprintln!("Could not listen on {}: {}", address, error);
+                std::process::exit(1);
+            }
+        };
+        println!("Rendering the regions asked for on {}", address);
+        let res = serve(listener, &hashes, |region| {
+            let start_time = Instant::now();
+            let image = renderer.render_region(&scene, &camera, region)?;
+            println!(
+                "Rendered {}x{} pixels from ({}, {}) ({:?})",
+                region.width,
+                region.height,
+                region.x,
+                region.y,
+                start_time.elapsed()
+            );
+            Ok(image)
+        });
+        if let Err(error) = res {
+            eprintln!("Could not serve on {}: {}", address, error);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let description = render_metadata(&settings, &camera_description);
+    let metadata = [hashes.to_metadata(), description.clone()].concat();
 
-            let mut color: Vec3 = (0..samples_per_pixel)
-                .into_par_iter()
-                .map(|_| {
-                    let u: f64 = ((i as f64) + random_01()) / image_width as f64;
-                    let v: f64 =
-                        (((image_height - 1 - j) as f64) + random_01()) / image_height as f64;
+    if let Some((x, y)) = options.inspect_pixel {
+        print_pixel_info(x, y, &inspect_pixel(x, y, &scene, &camera, &settings));
+        return;
+    }
 
-                    let ray = camera.get_ray(u, v);
+    if let Some((x, y)) = options.trace_pixel {
+        let paths = trace_pixel(x, y, &scene, &camera, &settings);
 
-                    ray_color(&ray, &objects, max_depth)
-                })
-                .sum();
+        let res = if options.trace_output.ends_with(".json") {
+            create_paths_json(&options.trace_output, &paths)
+        } else {
+            create_paths_obj(&options.trace_output, &paths)
+        };
+
+        match res {
+            Ok(()) => println!(
+                "Wrote {} paths of pixel ({}, {}) to {}",
+                paths.len(),
+                x,
+                y,
+                options.trace_output
+            ),
+            Err(error) => eprintln!("Could not write {}: {}", options.trace_output, error),
+        }
+        return;
+    }
 
-            color = color / (samples_per_pixel as f64);
-            vec![color.x, color.y, color.z]
-        })
-        .collect();
+    if let Some(index) = options.bake_uv {
+        if index >= scene.spheres.len() {
+            eprintln!("There is no sphere #{}", index);
+            std::process::exit(1);
+        }
+
+        let pixels = renderer.bake_uv(&scene, ObjectId(index));
+        let res = write_image(
+            &options.output,
+            &pixels,
+            settings.width as u32,
+            settings.height as u32,
+            None,
+            true,
+            &ImageHeader::new(&metadata),
+        );
+        match res {
+            Ok(()) => println!("Baked sphere #{} to {}", index, options.output),
+            Err(error) => eprintln!("Could not write {}: {}", options.output, error),
+        }
+        return;
+    }
+
+    if options.stats {
+        scene.enable_stats();
+    }
+
+    if let Some(samples) = options.interactive {
+        let make_camera = |view| make_camera(view, dist_to_focus, exposure);
+        run_viewer(
+            samples,
+            view,
+            make_camera,
+            describe_camera,
+            &mut renderer,
+            &scene,
+            &options,
+        );
+        return;
+    }
+
+    match (&sweep, options.frames) {
+        (None, Some(frames)) => {
+            let mut video = if is_video(&options.output) {
+                let video = VideoWriter::new(
+                    &options.ffmpeg,
+                    &options.output,
+                    settings.width as u32,
+                    settings.height as u32,
+                    options.fps,
+                );
+                match video {
+                    Ok(video) => Some(video),
+                    Err(error) => {
+                        eprintln!("Could not write {}: {}", options.output, error);
+                        std::process::exit(1);
+                    }
+                }
+            } else {
+                None
+            };
+            let step = 1.0 / options.fps;
+            // Turntables end a frame before they come back to the start, so
+            // they loop.
+            let orbit = |view: CameraView, frame: Float| match options.turntable {
+                Some(frames) => view.orbited(360.0 * frame / frames as Float),
+                None => view,
+            };
+            for frame in 0..frames {
+                let time = frame as Float * step;
+                scene.animate(time, Some(time - step));
+                let view = orbit(view_at(&scene, time), frame as Float);
+                let camera = make_camera(view, dist_to_focus, exposure);
+                let previous = orbit(view_at(&scene, time - step), frame as Float - 1.0);
+                let previous = make_camera(previous, dist_to_focus, exposure);
+                renderer.set_previous_camera(Some(previous));
+                if let Some(video) = &mut video {
+                    println!("Frame {}: {:.3} s", frame + 1, time);
+                    let (pixels, _) = render_pixels(&mut renderer, &scene, &camera, &options);
+                    let bytes: Vec<u8> = pixels
+                        .chunks(4)
+                        .flat_map(|pixel| pixel[..3].iter().map(|&x| color_to_byte(x)))
+                        .collect();
+                    if let Err(error) = video.write_frame(&bytes) {
+                        eprintln!("Could not write {}: {}", options.output, error);
+                        std::process::exit(1);
+                    }
+                    continue;
+                }
+                let output = frame_file_name(&options.output, frame + 1);
+                println!("{}: {:.3} s", output, time);
+                let hashes = RenderHashes::new(&scene, &camera, &settings);
+                let description = render_metadata(&settings, &describe_camera(&view));
+                let metadata = [hashes.to_metadata(), description].concat();
+                render_output(&output, &mut renderer, &scene, &camera, &options, &metadata);
+            }
+            if let Some(video) = video {
+                let frames = video.frames();
+                match video.finish() {
+                    Ok(()) => println!("Wrote {} frames to {}", frames, options.output),
+                    Err(error) => eprintln!("Could not write {}: {}", options.output, error),
+                }
+            }
+        }
+        (None, None) => {
+            render_output(
+                &options.output,
+                &mut renderer,
+                &scene,
+                &camera,
+                &options,
+                &metadata,
+            );
+        }
+        (Some(sweep), _) => {
+            let mut sheet = ContactSheet::new(settings.width, settings.height);
+            for (index, &value) in sweep.values().iter().enumerate() {
+                sweep.apply(&mut scene, value);
+                let output = aov_file_name(&options.output, &format!("{:03}", index));
+                println!("{}: {}", output, sweep.label(value));
+                let hashes = RenderHashes::new(&scene, &camera, &settings);
+                let metadata = [hashes.to_metadata(), description.clone()].concat();
+                let pixels =
+                    render_output(&output, &mut renderer, &scene, &camera, &options, &metadata);
+                if options.contact_sheet.is_some() {
+                    sheet.add(&pixels, &sweep.label(value));
+                }
+            }
+
+            if let Some(name) = &options.contact_sheet {
+                let (width, height) = sheet.size();
+                let res = write_image(
+                    name,
+                    &sheet.compose(),
+                    width as u32,
+                    height as u32,
+                    settings
+                        .transparent_background
+                        .then(|| alpha_mode(name, options.alpha_mode)),
+                    true,
+                    &ImageHeader::default(),
+                );
+                match res {
+                    Ok(()) => println!("Wrote {} frames to {}", sheet.len(), name),
+                    Err(error) => eprintln!("Could not write {}: {}", name, error),
+                }
+            }
+        }
+    }
+
+    if options.stats {
+        print_stats(&scene);
+    }
+}
+
+/// Prints the statistics of pixel (`x`, `y`) gathered by `inspect_pixel`.
+fn print_pixel_info(x: usize, y: usize, info: &PixelInfo) {
+    println!("Pixel ({}, {})", x, y);
+    println!("  samples:  {}", info.samples);
+    println!(
+        "  radiance: {:.5} {:.5} {:.5} (alpha {:.3})",
+        info.mean.x, info.mean.y, info.mean.z, info.alpha
+    );
+    println!(
+        "  variance: {:.5} {:.5} {:.5}",
+        info.variance.x, info.variance.y, info.variance.z
+    );
+    match &info.first_hit {
+        Some(hit) => {
+            println!("  object:   #{}", hit.object.0);
+            println!("  material: {:?}", hit.material);
+            println!("  depth:    {:.5}", hit.depth);
+            println!(
+                "  normal:   {:.5} {:.5} {:.5}",
+                hit.normal.x, hit.normal.y, hit.normal.z
+            );
+        }
+        None => println!("  no hit"),
+    }
+}
+
+/// Metadata telling how an image was rendered, alongside the hashes of
+/// what went into it, for the image to be reproduced.
+fn render_metadata(settings: &RenderSettings, camera: &str) -> Vec<(String, String)> {
+    let version = env!("CARGO_PKG_VERSION");
+    vec![
+        (String::from("Software"), format!("raytracer {}", version)),
+        (String::from("raytracer.version"), String::from(version)),
+        (String::from("raytracer.seed"), settings.seed.to_string()),
+        (
+            String::from("raytracer.samples"),
+            settings.samples_per_pixel.to_string(),
+        ),
+        (
+            String::from("raytracer.size"),
+            format!("{}x{}", settings.width, settings.height),
+        ),
+        (String::from("raytracer.view"), String::from(camera)),
+    ]
+}
+
+/// Writes the pixels of a preview to the output, without metadata, and
+/// hands them to the preview server, if any.
+fn write_preview(pixels: &[Float], options: &Options) {
+    let settings = options.settings;
+    if let Some(server) = &options.preview_server {
+        let bytes: Vec<u8> = pixels
+            .chunks(4)
+            .flat_map(|pixel| pixel[..3].iter().map(|&x| color_to_byte(x)))
+            .collect();
+        let (width, height) = (settings.width as u32, settings.height as u32);
+        server.publish(encode_png(&bytes, width, height, false, &[], None));
+    }
+    let res = write_image(
+        &options.output,
+        pixels,
+        settings.width as u32,
+        settings.height as u32,
+        settings
+            .transparent_background
+            .then(|| alpha_mode(&options.output, options.alpha_mode)),
+        true,
+        &ImageHeader::new(&[]),
+    );
+    if let Err(error) = res {
+        eprintln!("Could not write {}: {}", options.output, error);
+    }
+}
+
+/// Renders `scene` seen from `view` progressively, in passes of `samples`
+/// samples per pixel until it has the samples asked for, rewriting the
+/// output after each pass, and starts over from where the keys read from
+/// the standard input move the camera, until they quit.
+fn run_viewer(
+    samples: usize,
+    mut view: CameraView,
+    make_camera: impl Fn(CameraView) -> Camera,
+    describe_camera: impl Fn(&CameraView) -> String,
+    renderer: &mut Renderer,
+    scene: &Scene,
+    options: &Options,
+) {
+    let settings = options.settings;
+    let (sender, commands) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let passes = settings.samples_per_pixel.div_ceil(samples);
+    renderer.settings.samples_per_pixel = samples;
+    let mut camera = make_camera(view);
+    let mut accumulation = Accumulation::default();
+    // Depths seen by the camera, to tell which pixels of its passes the
+    // next view can reuse.
+    let mut depth: Vec<Float> = Vec::new();
+    let mut start_time = Instant::now();
+    println!("W A S D walk, I J K L orbit, drag DX DY orbits, inspect X Y prints a pixel, q quits");
+    loop {
+        // Finished images wait for the next move, or for the input to end.
+        let lines: Vec<String> = if accumulation.passes() >= passes {
+            match commands.recv() {
+                Ok(line) => vec![line],
+                Err(_) => return,
+            }
+        } else {
+            commands.try_iter().collect()
+        };
+        let (previous_view, previous_camera) = (view, camera);
+        let mut moved = false;
+        for line in &lines {
+            let mut words = line.split_whitespace();
+            while let Some(word) = words.next() {
+                let navigations = match word {
+                    "q" | "quit" => return,
+                    "inspect" => {
+                        let mut coordinate = || words.next().and_then(|word| word.parse().ok());
+                        match (coordinate(), coordinate()) {
+                            (Some(x), Some(y)) if x < settings.width && y < settings.height => {
+                                let info =
+                                    inspect_pixel(x, y, scene, &make_camera(view), &settings);
+                                print_pixel_info(x, y, &info);
+                            }
+                            _ => eprintln!("inspect takes the column and row of a pixel"),
+                        }
+                        continue;
+                    }
+                    "drag" => {
+                        let mut pixels = || words.next().and_then(|word| word.parse().ok());
+                        match (pixels(), pixels()) {
+                            (Some(dx), Some(dy)) => {
+                                vec![Navigation::from_drag(dx, dy, settings.height, &view)]
+                            }
+                            _ => {
+                                eprintln!("drag takes the pixels moved right and down");
+                                continue;
+                            }
+                        }
+                    }
+                    keys => match keys.chars().map(Navigation::from_key).collect() {
+                        Some(navigations) => navigations,
+                        None => {
+                            eprintln!("Unknown keys '{}'", keys);
+                            continue;
+                        }
+                    },
+                };
+                for navigation in navigations {
+                    view = navigation.apply(&view);
+                    moved = true;
+                }
+            }
+        }
+        if moved {
+            camera = make_camera(view);
+            start_time = Instant::now();
+            println!("View {}", describe_camera(&view));
+        } else if accumulation.passes() >= passes {
+            // Lines that don't move a finished view leave it as it is.
+            continue;
+        }
+
+        // Every pass draws other samples.
+        renderer.settings.seed = settings.seed.wrapping_add(accumulation.passes() as u64);
+        if accumulation.passes() == 0 || moved {
+            // The first pass of a view also gives where its pixels were in
+            // the last one, whose passes are carried over rather than
+            // dropped, and how far they are.
+            renderer.set_previous_camera(Some(previous_camera));
+            let (image, aovs) = renderer.render_with_aovs(scene, &camera);
+            renderer.set_previous_camera(None);
+            if moved {
+                let distance = (view.look_from - previous_view.look_from).length();
+                // Passes carried over never outweigh the new ones once the
+                // view is done.
+                let max_weight = (passes / 2).max(1) as Float;
+                accumulation.reproject(&aovs, &depth, distance, settings.width, max_weight);
+            }
+            accumulation.add(image.pixels());
+            depth = aovs.depth;
+        } else {
+            accumulation.add(renderer.render(scene, &camera).pixels());
+        }
+        write_preview(&accumulation.mean(), options);
+        println!(
+            "Pass {} of {} ({:?})",
+            accumulation.passes(),
+            passes,
+            start_time.elapsed()
+        );
+    }
+}
+
+/// Renders quick previews of `scene` into the output as the meshes of
+/// `stream` come in, each with those that came in while the last one
+/// rendered, until they are all in.
+fn preview_stream(
+    mut stream: MeshStream,
+    scene: &mut Scene,
+    renderer: &mut Renderer,
+    camera: &Camera,
+    options: &Options,
+) {
+    let samples = renderer.settings.samples_per_pixel;
+    renderer.settings.samples_per_pixel = options.stream.unwrap_or(1);
+    let start_time = Instant::now();
+    loop {
+        write_preview(renderer.render(scene, camera).pixels(), options);
+        println!(
+            "Preview with {} of {} meshes ({:?})",
+            stream.total() - stream.pending(),
+            stream.total(),
+            start_time.elapsed()
+        );
+        if stream.pending() == 0 {
+            break;
+        }
+        if let Err(error) = stream.wait(scene) {
+            let name = options.scene.as_deref().unwrap_or_default();
+            eprintln!("Could not load {}: {}", name, error);
+            std::process::exit(1);
+        }
+    }
+    renderer.settings.samples_per_pixel = samples;
+}
+
+/// Renders `scene`, denoised and with sensor noise as asked for, along with
+/// its auxiliary images when they are written or guide the denoiser.
+fn render_pixels(
+    renderer: &mut Renderer,
+    scene: &Scene,
+    camera: &Camera,
+    options: &Options,
+) -> (Vec<Float>, Option<AovImages>) {
+    let settings = options.settings;
+    println!(
+        "Start rendering (seed {}, {} threads)",
+        settings.seed,
+        renderer.threads()
+    );
+    let start_time = Instant::now();
+
+    if let Some(samples) = options.importance_prior {
+        let importance = renderer.noise_map(scene, camera, samples);
+        renderer.set_importance(&importance);
+        println!("Prior pass done ({:?})", start_time.elapsed());
+    }
+    if let Some(name) = &options.importance_map {
+        match load_importance_map(name, settings.width, settings.height) {
+            Ok(importance) => renderer.set_importance(&importance),
+            Err(error) => {
+                eprintln!("Could not load {}: {}", name, error);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let (mut pixels, aovs) = if !options.workers.is_empty() {
+        let hashes = RenderHashes::new(scene, camera, &settings);
+        let (width, height) = (settings.width, settings.height);
+        match render_distributed(&options.workers, &hashes, width, height) {
+            Ok(image) => (image.into_pixels(), None),
+            Err(error) => {
+                eprintln!("Could not render on the workers: {}", error);
+                std::process::exit(1);
+            }
+        }
+    } else if options.aovs || options.denoise {
+        let (image, aovs) = renderer.render_with_aovs(scene, camera);
+        (image.into_pixels(), Some(aovs))
+    } else {
+        (renderer.render(scene, camera).into_pixels(), None)
+    };
 
     println!("Done! ({:?})", start_time.elapsed());
 
-    println!("Generating image!");
+    if let (true, Some(aovs)) = (options.denoise, &aovs) {
+        let start_time = Instant::now();
+        #[cfg(feature = "denoise")]
+        match oidn_denoise(&pixels, aovs, settings.width, settings.height) {
+            Ok(denoised) => pixels = denoised,
+            Err(error) => {
+                eprintln!("Could not denoise: {}", error);
+                std::process::exit(1);
+            }
+        }
+        #[cfg(not(feature = "denoise"))]
+        {
+            pixels = denoise(
+                &pixels,
+                aovs,
+                settings.width,
+                settings.height,
+                &DenoiseSettings::default(),
+            );
+        }
+        println!("Denoised ({:?})", start_time.elapsed());
+    }
+    if let Some(noise) = &options.sensor_noise {
+        add_sensor_noise(&mut pixels, settings.width, settings.height, noise);
+    }
 
-    let output_pixels: Vec<u8> = pixels
-        .iter()
-        .flatten()
-        // Do gamma correction
-        .map(|&x| f64::sqrt(x))
-        // Clamp values between 0 and 1
-        .map(|x| clamp(x, 0.0, 0.9999))
-        // Convert to 0 -> 256 range
-        .map(|x| (255.9 * x))
-        .map(|x| x as u8)
-        .collect();
+    (pixels, aovs)
+}
+
+/// Renders `scene` into `output`, along with the auxiliary outputs asked for,
+/// and returns the pixels written.
+fn render_output(
+    output: &str,
+    renderer: &mut Renderer,
+    scene: &Scene,
+    camera: &Camera,
+    options: &Options,
+    metadata: &[(String, String)],
+) -> Vec<Float> {
+    let settings = options.settings;
+    let start_time = Instant::now();
+    let (pixels, aovs) = render_pixels(renderer, scene, camera, options);
 
-    let _res = create_ppm(
-        "result.ppm",
-        &output_pixels,
-        image_width as u32,
-        image_height as u32,
+    println!("Generating image!");
+
+    let mut header = ImageHeader::new(metadata);
+    header.metadata.push((
+        String::from("raytracer.render-time"),
+        format!("{:.3} s", start_time.elapsed().as_secs_f64()),
+    ));
+    header.preview = options
+        .thumbnail
+        .map(|size| thumbnail(&pixels, settings.width, settings.height, size));
+    let res = write_image(
+        output,
+        &pixels,
+        settings.width as u32,
+        settings.height as u32,
+        settings
+            .transparent_background
+            .then(|| alpha_mode(output, options.alpha_mode)),
+        true,
+        &header,
     );
+
+    if let Err(error) = res {
+        eprintln!("Could not write {}: {}", output, error);
+    }
+
+    if let (true, Some(aovs)) = (options.aovs, aovs) {
+        let res = write_aovs(
+            output,
+            &aovs,
+            settings.width as u32,
+            settings.height as u32,
+            metadata,
+        );
+        if let Err(error) = res {
+            eprintln!("Could not write the auxiliary outputs: {}", error);
+        }
+    }
+
+    pixels
 }
@@ -0,0 +1,883 @@
+use crate::hair::Hair;
+use crate::hitable::HitRecord;
+use crate::maths::shading::*;
+use crate::maths::*;
+use crate::measured::MeasuredBrdf;
+use crate::principled::Principled;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+
+use std::sync::Arc;
+
+#[derive(Clone, Debug)]
+pub enum MaterialType {
+    Lambertian {
+        albedo: Vec3,
+    },
+    Metal {
+        albedo: Vec3,
+        fuzziness: Float,
+    },
+    Dialectric {
+        refractive_index: Float,
+    },
+    DiffuseLight {
+        emit: Vec3,
+    },
+    /// Rough surface made of GGX microfacets, `roughness` going from 0,
+    /// polished, to 1, matte. `fresnel` tells how much light the facets
+    /// reflect and what becomes of the rest.
+    Microfacet {
+        roughness: Float,
+        fresnel: Fresnel,
+    },
+    /// Brushed metal, its GGX microfacets `roughness_u` rough along the
+    /// tangent of the surface and `roughness_v` across, which stretches
+    /// highlights across the grooves. `rotation` turns the tangent around
+    /// the normal, in degrees. Reflects `f0` head on like a `Microfacet`
+    /// conductor.
+    AnisotropicMetal {
+        f0: Vec3,
+        roughness_u: Float,
+        roughness_v: Float,
+        rotation: Float,
+    },
+    /// All-purpose material of many layers, see `Principled`.
+    Principled(Principled),
+    /// Reflectance measured from a real material, shared between the
+    /// copies of the material.
+    Measured(Arc<MeasuredBrdf>),
+    /// Hair and fur fibers, see `Hair`. Meant for curves, whose hits carry
+    /// the direction of the fiber.
+    Hair(Hair),
+    /// Translucent material like skin, wax or marble, light wandering
+    /// inside it before leaving somewhere else (see `Medium`). `albedo` is
+    /// the color it takes overall and `radius` how far light goes inside,
+    /// per channel, in scene units. Its smooth surface reflects light
+    /// following `refractive_index`.
+    Subsurface {
+        albedo: Vec3,
+        radius: Vec3,
+        refractive_index: Float,
+    },
+    /// Glass so thin its two faces are one surface, like window panes,
+    /// bubbles and bottles modelled as shells. Light bouncing between the
+    /// faces is summed up analytically, following `refractive_index`, and
+    /// the light getting through goes on in the same direction, `tint` being
+    /// its color after crossing the glass head on. There is no medium
+    /// inside, so shells don't darken at their edges like solid glass does.
+    ThinGlass {
+        refractive_index: Float,
+        tint: Vec3,
+    },
+}
+
+/// Thin glass of everyday objects, for `MaterialType::thin_glass_preset`:
+/// name, refractive index and color after crossing the glass head on.
+const THIN_GLASS_PRESETS: [(&str, Float, [Float; 3]); 5] = [
+    ("window", 1.52, [0.95, 0.98, 0.96]),
+    ("clear", 1.5, [1.0, 1.0, 1.0]),
+    ("bottle", 1.52, [0.45, 0.72, 0.35]),
+    ("amber", 1.52, [0.75, 0.42, 0.12]),
+    ("bubble", 1.33, [1.0, 1.0, 1.0]),
+];
+
+/// Shares of the light arriving with a cosine of `cos_theta` on thin glass
+/// of relative refractive index `eta` that it reflects and lets through,
+/// per channel. Light goes back and forth between the faces, `tint`
+/// absorbed once per crossing head on, and more at an angle.
+pub fn thin_glass_split(cos_theta: Float, eta: Float, tint: Vec3) -> (Vec3, Vec3) {
+    let cos_theta = clamp(cos_theta.abs(), 0.0, 1.0);
+    let reflectance = fresnel_dielectric(cos_theta, eta);
+    let sin2_inside = (1.0 - cos_theta * cos_theta) / (eta * eta);
+    let cos_inside = Float::sqrt((1.0 - sin2_inside).max(1e-6));
+    let channel = |tint: Float| {
+        let through = clamp(tint, 0.0, 1.0).powf(1.0 / cos_inside);
+        let bounces = 1.0 - reflectance * reflectance * through * through;
+        let entered = (1.0 - reflectance) * (1.0 - reflectance);
+        let reflected = reflectance + entered * reflectance * through * through / bounces;
+        (reflected, entered * through / bounces)
+    };
+    let (x, y, z) = (channel(tint.x), channel(tint.y), channel(tint.z));
+    (Vec3::new(x.0, y.0, z.0), Vec3::new(x.1, y.1, z.1))
+}
+
+/// Reflectance of the facets of a `Microfacet` material.
+#[derive(Clone, Copy, Debug)]
+pub enum Fresnel {
+    /// Metal reflecting `f0` head on and turning white at grazing angles,
+    /// following Schlick's approximation.
+    Conductor { f0: Vec3 },
+    /// Rough glass refracting the light it doesn't reflect.
+    Dielectric { refractive_index: Float },
+}
+
+/// Transparent layer a few hundred nanometers thick coating a metal or a
+/// dielectric, like oil on water or the wall of a soap bubble (see
+/// `Scene::set_thin_film`). Light reflected off its top and off its bottom
+/// interferes, tinting reflections with colors changing with the angle.
+#[derive(Clone, Copy, Debug)]
+pub struct ThinFilm {
+    /// Nanometers, whatever the units of the scene.
+    pub thickness: Float,
+    pub refractive_index: Float,
+}
+
+/// Wavelengths, in nanometers, whose reflectances thin films average into
+/// red, green and blue.
+const FILM_WAVELENGTHS: [[Float; 4]; 3] = [
+    [595.0, 625.0, 655.0, 685.0],
+    [500.0, 525.0, 550.0, 575.0],
+    [410.0, 435.0, 460.0, 485.0],
+];
+
+impl ThinFilm {
+    /// Reflectance, per channel, of light arriving with a cosine of
+    /// `cos_theta` from a medium of index `outside` on the film over a
+    /// surface of indices `base`, one per channel.
+    pub fn reflectance(&self, cos_theta: Float, outside: Float, base: Vec3) -> Vec3 {
+        let channel = |wavelengths: &[Float; 4], base: Float| {
+            let indices = (outside, self.refractive_index, base);
+            wavelengths
+                .iter()
+                .map(|&wavelength| {
+                    thin_film_reflectance(cos_theta, indices, self.thickness, wavelength)
+                })
+                .sum::<Float>()
+                / wavelengths.len() as Float
+        };
+        Vec3::new(
+            channel(&FILM_WAVELENGTHS[0], base.x),
+            channel(&FILM_WAVELENGTHS[1], base.y),
+            channel(&FILM_WAVELENGTHS[2], base.z),
+        )
+    }
+}
+
+/// Real refractive indices reflecting `f0` head on, standing in for the
+/// complex ones of a metal under a thin film.
+fn conductor_indices(f0: Vec3) -> Vec3 {
+    let index = |f0: Float| {
+        let r = clamp(f0, 0.0, 0.99).sqrt();
+        (1.0 + r) / (1.0 - r)
+    };
+    Vec3::new(index(f0.x), index(f0.y), index(f0.z))
+}
+
+/// Reflectance of a metal reflecting `f0` head on, for light arriving at
+/// `rec` with a cosine of `cos_theta` to the normal of the facet.
+fn conductor_reflectance(cos_theta: Float, f0: Vec3, rec: &HitRecord) -> Vec3 {
+    match &rec.film {
+        Some(film) => film.reflectance(cos_theta, rec.outside_index, conductor_indices(f0)),
+        None => fresnel_schlick_color(clamp(cos_theta, 0.0, 1.0), f0),
+    }
+}
+
+/// Tint `film` gives the light a dielectric of facing `indices` reflects or
+/// lets through from `wo` to `wi`, in the shading frame, over the share its
+/// bare facets would.
+fn film_tint(film: &ThinFilm, wo: Vec3, wi: Vec3, (eta_o, eta_i): (Float, Float)) -> Vec3 {
+    let white = Vec3::new(1.0, 1.0, 1.0);
+    let m = if wi.z > 0.0 {
+        half_vector(wo, wi)
+    } else {
+        refraction_half_vector(wo, wi, eta_o, eta_i)
+    };
+    let cos_theta = match m {
+        Some(m) => wo.dot(m),
+        None => return white,
+    };
+    let bare = fresnel_dielectric(cos_theta, eta_i / eta_o);
+    let coated = film.reflectance(cos_theta, eta_o, white * eta_i);
+    if wi.z > 0.0 && bare > 0.0 {
+        coated / bare
+    } else if wi.z <= 0.0 && bare < 1.0 {
+        (white - coated) / (1.0 - bare)
+    } else {
+        white
+    }
+}
+
+/// Homogeneous medium filling an object, which rays go through by random
+/// walks, scattering off its particles in any direction until they leave.
+#[derive(Clone, Copy, Debug)]
+pub struct Medium {
+    /// Chance per unit of length of meeting a particle, per channel.
+    pub extinction: Vec3,
+    /// Share of the light particles scatter rather than absorb.
+    pub scattering_albedo: Vec3,
+}
+
+impl Medium {
+    /// Medium giving a `Subsurface` material its color and radius, after
+    /// Chiang et al. 2016, "Practical and Controllable Subsurface Scattering
+    /// for Production Path Tracing".
+    pub fn from_subsurface(albedo: Vec3, radius: Vec3) -> Self {
+        let channel = |albedo: Float, radius: Float| {
+            let albedo = clamp(albedo, 0.0, 1.0);
+            // Light keeps more of its color from single scatterings than
+            // from the many of a walk, so particles scatter more than
+            // `albedo` would suggest.
+            let root = 4.09712 + 4.20863 * albedo
+                - (9.59217 + 41.6808 * albedo + 17.7126 * albedo * albedo).sqrt();
+            let scattering_albedo = 1.0 - root * root;
+            let scale = 1.9 - albedo + 3.5 * (albedo - 0.8) * (albedo - 0.8);
+            (1.0 / (radius.max(1e-6) * scale), scattering_albedo)
+        };
+        let (x, y, z) = (
+            channel(albedo.x, radius.x),
+            channel(albedo.y, radius.y),
+            channel(albedo.z, radius.z),
+        );
+        Medium {
+            extinction: Vec3::new(x.0, y.0, z.0),
+            scattering_albedo: Vec3::new(x.1, y.1, z.1),
+        }
+    }
+}
+
+impl MaterialType {
+    /// `ThinGlass` called `name`: `window`, float glass tinted slightly
+    /// green, `clear`, `bottle`, green glass, `amber`, brown glass, or
+    /// `bubble`, soapy water.
+    pub fn thin_glass_preset(name: &str) -> Option<Self> {
+        THIN_GLASS_PRESETS
+            .iter()
+            .find(|preset| preset.0 == name)
+            .map(
+                |&(_, refractive_index, [r, g, b])| MaterialType::ThinGlass {
+                    refractive_index,
+                    tint: Vec3::new(r, g, b),
+                },
+            )
+    }
+
+    /// Medium inside the material, for those light walks through.
+    pub fn medium(&self) -> Option<Medium> {
+        match self {
+            MaterialType::Subsurface { albedo, radius, .. } => {
+                Some(Medium::from_subsurface(*albedo, *radius))
+            }
+            _ => None,
+        }
+    }
+
+    /// Refractive index of materials light goes through, `None` for opaque
+    /// ones.
+    pub fn refractive_index(&self) -> Option<Float> {
+        match self {
+            MaterialType::Dialectric { refractive_index }
+            | MaterialType::Microfacet {
+                fresnel: Fresnel::Dielectric { refractive_index },
+                ..
+            } => Some(*refractive_index),
+            MaterialType::Principled(principled) if principled.transmission > 0.0 => {
+                Some(principled.refractive_index())
+            }
+            _ => None,
+        }
+    }
+
+    /// `scattering_pdf` of a `Microfacet` material towards `scattered`, and
+    /// the density of sampling it.
+    fn microfacet(&self, ray: &Ray, rec: &HitRecord, scattered: &Ray) -> (Float, Float) {
+        let (roughness, fresnel) = match self {
+            MaterialType::Microfacet { roughness, fresnel } => (*roughness, fresnel),
+            _ => return (0.0, 0.0),
+        };
+        let uvw = Onb::from_w(rec.normal);
+        let wo = uvw.world_to_local(-ray.dir.unit());
+        let wi = uvw.world_to_local(scattered.dir.unit());
+        ggx_scattering(wo, wi, ggx_alpha(roughness), fresnel.indices(rec))
+    }
+}
+
+/// Shading frame of an anisotropic surface hit at `rec`, `u` along its
+/// tangent turned by `rotation` degrees around the normal.
+fn anisotropic_frame(rec: &HitRecord, rotation: Float) -> Onb {
+    let frame = match rec.tangent {
+        Some(tangent) => Onb::from_w_and_u(rec.normal, tangent),
+        None => Onb::from_w(rec.normal),
+    };
+    let (sin, cos) = rotation.to_radians().sin_cos();
+    Onb {
+        u: frame.u * cos + frame.v * sin,
+        v: frame.v * cos - frame.u * sin,
+        w: frame.w,
+    }
+}
+
+/// `ggx_anisotropic_reflection` of an `AnisotropicMetal` material towards
+/// `scattered`, with the normal of the facet reflecting it.
+fn anisotropic_eval(
+    (roughness_u, roughness_v, rotation): (Float, Float, Float),
+    ray: &Ray,
+    rec: &HitRecord,
+    scattered: &Ray,
+) -> (Float, Float) {
+    let uvw = anisotropic_frame(rec, rotation);
+    let wo = uvw.world_to_local(-ray.dir.unit());
+    let wi = uvw.world_to_local(scattered.dir.unit());
+    let alphas = (ggx_alpha(roughness_u), ggx_alpha(roughness_v));
+    ggx_anisotropic_reflection(wo, wi, alphas)
+}
+
+impl Fresnel {
+    /// Refractive indices on the side `rec` was hit from and on the other
+    /// side of a dielectric, `None` for conductors.
+    fn indices(&self, rec: &HitRecord) -> Option<(Float, Float)> {
+        match *self {
+            Fresnel::Dielectric { refractive_index } => Some(facing_indices(refractive_index, rec)),
+            Fresnel::Conductor { .. } => None,
+        }
+    }
+}
+
+/// `Principled::eval` towards `scattered`, from the world space directions,
+/// with the textures of the material at `rec`.
+fn principled_eval(
+    principled: &Principled,
+    ray: &Ray,
+    rec: &HitRecord,
+    scattered: &Ray,
+) -> (Vec3, Float) {
+    let principled = principled.at(rec.uv);
+    let uvw = Onb::from_w(rec.normal);
+    let wo = uvw.world_to_local(-ray.dir.unit());
+    let wi = uvw.world_to_local(scattered.dir.unit());
+    principled.eval(wo, wi, facing_indices(principled.refractive_index(), rec))
+}
+
+/// Frame of the fiber at `rec`, as `Hair` expects it for light leaving
+/// along `ray`, and where across the fiber it was hit.
+fn hair_frame(ray: &Ray, rec: &HitRecord) -> (Onb, Float) {
+    let wo = -ray.dir.unit();
+    let along = rec
+        .tangent
+        .unwrap_or_else(|| Onb::from_w(rec.normal).u)
+        .unit();
+    let facing = Onb::from_w_and_u(wo, along);
+    let frame = Onb {
+        u: along,
+        v: facing.v,
+        w: along.cross(facing.v),
+    };
+    (frame, rec.normal.dot(facing.v))
+}
+
+/// `Hair::eval` towards `scattered`, from the world space directions.
+fn hair_eval(hair: &Hair, ray: &Ray, rec: &HitRecord, scattered: &Ray) -> (Vec3, Float) {
+    let (frame, h) = hair_frame(ray, rec);
+    let wo = frame.world_to_local(-ray.dir.unit());
+    let wi = frame.world_to_local(scattered.dir.unit());
+    hair.eval(wo, wi, h)
+}
+
+/// `MeasuredBrdf::eval` towards `scattered`, from the world space directions.
+fn measured_eval(
+    brdf: &MeasuredBrdf,
+    ray: &Ray,
+    rec: &HitRecord,
+    scattered: &Ray,
+) -> (Vec3, Float) {
+    let uvw = Onb::from_w(rec.normal);
+    let wo = uvw.world_to_local(-ray.dir.unit());
+    let wi = uvw.world_to_local(scattered.dir.unit());
+    brdf.eval(wo, wi)
+}
+
+/// Refractive indices on the side `rec` was hit from and on the other side
+/// of a surface of `refractive_index`.
+fn facing_indices(refractive_index: Float, rec: &HitRecord) -> (Float, Float) {
+    if rec.front_face {
+        (rec.outside_index, refractive_index)
+    } else {
+        (refractive_index, rec.outside_index)
+    }
+}
+
+/// Handle to a material stored in `Scene::materials`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MaterialId(pub usize);
+
+/// Kind of interaction a scattered ray comes from, each kind having its own
+/// bounce limit in `RenderSettings::bounce_limits`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BounceKind {
+    Diffuse,
+    /// Reflection off mirrors, metals and glass.
+    Glossy,
+    /// Refraction through glass.
+    Transmission,
+    /// Scattering off the particles of media and clouds, which materials
+    /// don't sample.
+    Volume,
+}
+
+/// Outcome of a material sampling a new direction, in the spirit of
+/// "Ray Tracing: The Rest of Your Life".
+///
+/// For non-specular records, the contribution of the scattered ray is
+/// `attenuation * scattering_pdf / pdf`. Specular records (mirrors,
+/// glass...) have no meaningful density and are simply attenuated.
+#[derive(Clone, Copy)]
+pub struct ScatterRecord {
+    pub attenuation: Vec3,
+    pub scattered: Ray,
+    /// Density with which `scattered` was sampled.
+    pub pdf: Float,
+    pub is_specular: bool,
+    pub kind: BounceKind,
+}
+
+impl ScatterRecord {
+    pub fn new(attenuation: Vec3, scattered: Ray, pdf: Float) -> Self {
+        ScatterRecord {
+            attenuation,
+            scattered,
+            pdf,
+            is_specular: false,
+            kind: BounceKind::Diffuse,
+        }
+    }
+
+    pub fn specular(attenuation: Vec3, scattered: Ray) -> Self {
+        ScatterRecord {
+            attenuation,
+            scattered,
+            pdf: 0.0,
+            is_specular: true,
+            kind: BounceKind::Glossy,
+        }
+    }
+
+    /// Specular record of a ray refracted through the surface.
+    pub fn transmission(attenuation: Vec3, scattered: Ray) -> Self {
+        ScatterRecord {
+            kind: BounceKind::Transmission,
+            ..ScatterRecord::specular(attenuation, scattered)
+        }
+    }
+}
+
+pub trait Material {
+    /// Picks how `ray` leaves the surface at `rec`, its random decisions
+    /// drawn from `sampler`.
+    fn scatter(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord>;
+
+    /// Scattering distribution of the material towards `scattered`, cosine
+    /// term included, so that `attenuation * scattering_pdf` is the BRDF
+    /// times cosine. Zero for materials that only scatter specularly.
+    fn scattering_pdf(&self, _ray: &Ray, _rec: &HitRecord, _scattered: &Ray) -> Float {
+        0.0
+    }
+
+    /// Density with which `scatter` picks `scattered`, for materials not
+    /// sampling proportionally to `scattering_pdf`.
+    fn sampling_pdf(&self, ray: &Ray, rec: &HitRecord, scattered: &Ray) -> Float {
+        self.scattering_pdf(ray, rec, scattered)
+    }
+
+    /// Attenuation of light scattered towards `scattered`, for materials
+    /// whose color changes with the direction, like rough metals. `None`
+    /// when it is the `attenuation` of any record `scatter` returns.
+    fn directional_attenuation(
+        &self,
+        _ray: &Ray,
+        _rec: &HitRecord,
+        _scattered: &Ray,
+    ) -> Option<Vec3> {
+        None
+    }
+
+    fn emitted(&self, _rec: &HitRecord) -> Vec3 {
+        Vec3::new(0.0, 0.0, 0.0)
+    }
+
+    /// Overall surface color, as written to the albedo output.
+    fn albedo(&self, rec: &HitRecord) -> Vec3;
+}
+
+impl Material for MaterialType {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        match &self {
+            MaterialType::Lambertian { albedo } => {
+                let uvw = Onb::from_w(rec.normal);
+                let scatter_direction = uvw.local(sample_cosine_direction(sampler.get_2d()));
+                let scattered = Ray::new(rec.position, scatter_direction);
+                let attenuation = *albedo;
+                let pdf = self.scattering_pdf(ray, rec, &scattered);
+                Some(ScatterRecord::new(attenuation, scattered, pdf))
+            }
+            MaterialType::Metal { albedo, fuzziness } => {
+                let reflected = reflect(ray.dir.unit(), rec.normal);
+                let scattered = Ray::new(
+                    rec.position,
+                    reflected + *fuzziness * sample_hemisphere(rec.normal, sampler.get_2d()),
+                );
+                let attenuation = match &rec.film {
+                    Some(film) => {
+                        let cos_theta = -ray.dir.unit().dot(rec.normal);
+                        film.reflectance(cos_theta, rec.outside_index, conductor_indices(*albedo))
+                    }
+                    None => *albedo,
+                };
+                if scattered.dir.dot(rec.normal) > 0.0 {
+                    Some(ScatterRecord::specular(attenuation, scattered))
+                } else {
+                    None
+                }
+            }
+            MaterialType::Dialectric { refractive_index } => {
+                let attenuation = Vec3::new(1.0, 1.0, 1.0);
+                let etai_over_etat = if rec.front_face {
+                    rec.outside_index / refractive_index
+                } else {
+                    refractive_index / rec.outside_index
+                };
+
+                let unit_direction = ray.dir.unit();
+                let cos_theta = Float::min(-unit_direction.dot(rec.normal), 1.0);
+                let sin_theta = Float::sqrt(1.0 - cos_theta * cos_theta);
+
+                if etai_over_etat * sin_theta > 1.0 {
+                    let reflected = reflect(unit_direction, rec.normal);
+                    let scattered = Ray::new(rec.position, reflected);
+                    return Some(ScatterRecord::specular(attenuation, scattered));
+                }
+
+                if let Some(film) = &rec.film {
+                    // Rays pick a side with the average reflectance, and
+                    // carry the color the film gives that side.
+                    let (outside, inside) = facing_indices(*refractive_index, rec);
+                    let reflectance = film.reflectance(cos_theta, outside, attenuation * inside);
+                    let average = (reflectance.x + reflectance.y + reflectance.z) / 3.0;
+                    if sampler.get_1d() < average {
+                        let reflected = reflect(unit_direction, rec.normal);
+                        let scattered = Ray::new(rec.position, reflected);
+                        return Some(ScatterRecord::specular(reflectance / average, scattered));
+                    }
+                    let refracted = refract(unit_direction, rec.normal, etai_over_etat);
+                    let scattered = Ray::new(rec.position, refracted);
+                    let transmittance = (attenuation - reflectance) / (1.0 - average);
+                    return Some(ScatterRecord::transmission(transmittance, scattered));
+                }
+
+                let reflect_prob = fresnel_schlick(cos_theta, schlick_f0(etai_over_etat));
+                if sampler.get_1d() < reflect_prob {
+                    let reflected = reflect(unit_direction, rec.normal);
+                    let scattered = Ray::new(rec.position, reflected);
+                    return Some(ScatterRecord::specular(attenuation, scattered));
+                }
+
+                let refracted = refract(unit_direction, rec.normal, etai_over_etat);
+                let scattered = Ray::new(rec.position, refracted);
+                Some(ScatterRecord::transmission(attenuation, scattered))
+            }
+            MaterialType::ThinGlass {
+                refractive_index,
+                tint,
+            } => {
+                let unit_direction = ray.dir.unit();
+                let cos_theta = -unit_direction.dot(rec.normal);
+                let eta = refractive_index / rec.outside_index;
+                let (reflectance, transmittance) = thin_glass_split(cos_theta, eta, *tint);
+                let average = |color: Vec3| (color.x + color.y + color.z) / 3.0;
+                let reflect_prob = average(reflectance)
+                    / (average(reflectance) + average(transmittance)).max(1e-12);
+                if sampler.get_1d() < reflect_prob {
+                    let reflected = reflect(unit_direction, rec.normal);
+                    let scattered = Ray::new(rec.position, reflected);
+                    return Some(ScatterRecord::specular(
+                        reflectance / reflect_prob,
+                        scattered,
+                    ));
+                }
+                let scattered = Ray::new(rec.position, ray.dir);
+                Some(ScatterRecord::transmission(
+                    transmittance / (1.0 - reflect_prob),
+                    scattered,
+                ))
+            }
+            MaterialType::DiffuseLight { .. } => None,
+            MaterialType::Microfacet { roughness, fresnel } => {
+                let alpha = ggx_alpha(*roughness);
+                let uvw = Onb::from_w(rec.normal);
+                let wo = uvw.world_to_local(-ray.dir.unit());
+                if wo.z <= 0.0 {
+                    return None;
+                }
+                let (u1, u2) = sampler.get_2d();
+                let m = sample_ggx_visible(wo, alpha, u1, u2);
+
+                let (wi, attenuation, kind) = match fresnel {
+                    Fresnel::Conductor { f0 } => {
+                        let attenuation = conductor_reflectance(wo.dot(m), *f0, rec);
+                        (reflect(-wo, m), attenuation, BounceKind::Glossy)
+                    }
+                    Fresnel::Dielectric { .. } => {
+                        let (eta_o, eta_i) = fresnel.indices(rec)?;
+                        let reflectance = fresnel_dielectric(wo.dot(m), eta_i / eta_o);
+                        let white = Vec3::new(1.0, 1.0, 1.0);
+                        if sampler.get_1d() < reflectance {
+                            (reflect(-wo, m), white, BounceKind::Glossy)
+                        } else {
+                            let wi = refract(-wo, m, eta_o / eta_i);
+                            (wi, white, BounceKind::Transmission)
+                        }
+                    }
+                };
+
+                // Rays reflected below the surface or refracted back above it
+                // are lost.
+                let (_, pdf) = ggx_scattering(wo, wi, alpha, fresnel.indices(rec));
+                if pdf <= 0.0 || (wi.z > 0.0) != (kind == BounceKind::Glossy) {
+                    return None;
+                }
+                let attenuation = match (&rec.film, fresnel.indices(rec)) {
+                    (Some(film), Some(indices)) => attenuation * film_tint(film, wo, wi, indices),
+                    _ => attenuation,
+                };
+                let scattered = Ray::new(rec.position, uvw.local(wi));
+                Some(ScatterRecord {
+                    kind,
+                    ..ScatterRecord::new(attenuation, scattered, pdf)
+                })
+            }
+            MaterialType::AnisotropicMetal {
+                f0,
+                roughness_u,
+                roughness_v,
+                rotation,
+            } => {
+                let alphas = (ggx_alpha(*roughness_u), ggx_alpha(*roughness_v));
+                let uvw = anisotropic_frame(rec, *rotation);
+                let wo = uvw.world_to_local(-ray.dir.unit());
+                if wo.z <= 0.0 {
+                    return None;
+                }
+                let (u1, u2) = sampler.get_2d();
+                let m = sample_ggx_visible_anisotropic(wo, alphas, u1, u2);
+                let wi = reflect(-wo, m);
+                let (_, pdf) = ggx_anisotropic_reflection(wo, wi, alphas);
+                if pdf <= 0.0 {
+                    return None;
+                }
+                let attenuation = conductor_reflectance(wo.dot(m), *f0, rec);
+                let scattered = Ray::new(rec.position, uvw.local(wi));
+                Some(ScatterRecord {
+                    kind: BounceKind::Glossy,
+                    ..ScatterRecord::new(attenuation, scattered, pdf)
+                })
+            }
+            MaterialType::Subsurface {
+                refractive_index, ..
+            } => {
+                let white = Vec3::new(1.0, 1.0, 1.0);
+                // Light entering is reflected by the surface first, and
+                // leaving light has been through it already.
+                let unit_direction = ray.dir.unit();
+                let cos_theta = -unit_direction.dot(rec.normal);
+                let eta = refractive_index / rec.outside_index;
+                let u = sampler.get_1d();
+                if rec.front_face && u < fresnel_dielectric(cos_theta, eta) {
+                    let reflected = reflect(unit_direction, rec.normal);
+                    return Some(ScatterRecord::specular(
+                        white,
+                        Ray::new(rec.position, reflected),
+                    ));
+                }
+
+                // Either way the ray crosses the surface diffusely.
+                let uvw = Onb::from_w(-rec.normal);
+                let direction = uvw.local(sample_cosine_direction(sampler.get_2d()));
+                let scattered = Ray::new(rec.position, direction);
+                let pdf = self.scattering_pdf(ray, rec, &scattered);
+                Some(ScatterRecord {
+                    kind: BounceKind::Transmission,
+                    ..ScatterRecord::new(white, scattered, pdf)
+                })
+            }
+            MaterialType::Principled(principled) => {
+                let principled = principled.at(rec.uv);
+                let uvw = Onb::from_w(rec.normal);
+                let wo = uvw.world_to_local(-ray.dir.unit());
+                let indices = facing_indices(principled.refractive_index(), rec);
+                let u = sampler.get_1d();
+                let (wi, kind) = principled.sample(wo, indices, u, sampler.get_2d())?;
+
+                // The whole BSDF goes in the attenuation, scattering_pdf
+                // being the sampling density.
+                let (value, pdf) = principled.eval(wo, wi, indices);
+                if pdf <= 0.0 {
+                    return None;
+                }
+                let scattered = Ray::new(rec.position, uvw.local(wi));
+                Some(ScatterRecord {
+                    kind,
+                    ..ScatterRecord::new(value / pdf, scattered, pdf)
+                })
+            }
+            MaterialType::Measured(brdf) => {
+                let uvw = Onb::from_w(rec.normal);
+                let wo = uvw.world_to_local(-ray.dir.unit());
+                let wi = brdf.sample(wo, sampler.get_1d(), sampler.get_2d())?;
+
+                // Like principled materials, the attenuation holds the whole BRDF.
+                let (value, pdf) = brdf.eval(wo, wi);
+                if pdf <= 0.0 {
+                    return None;
+                }
+                let scattered = Ray::new(rec.position, uvw.local(wi));
+                Some(ScatterRecord::new(value / pdf, scattered, pdf))
+            }
+            MaterialType::Hair(hair) => {
+                let (frame, h) = hair_frame(ray, rec);
+                let wo = frame.world_to_local(-ray.dir.unit());
+                let lobe = sampler.get_1d();
+                let angles = sampler.get_2d();
+                let (wi, kind) = hair.sample(wo, h, lobe, angles, sampler.get_1d());
+
+                // Like principled materials, the attenuation holds the whole BSDF.
+                let (value, pdf) = hair.eval(wo, wi, h);
+                if pdf <= 0.0 {
+                    return None;
+                }
+                let scattered = Ray::new(rec.position, frame.local(wi));
+                Some(ScatterRecord {
+                    kind,
+                    ..ScatterRecord::new(value / pdf, scattered, pdf)
+                })
+            }
+        }
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, rec: &HitRecord, scattered: &Ray) -> Float {
+        match &self {
+            MaterialType::Lambertian { .. } => {
+                let cosine = rec.normal.dot(scattered.dir.unit());
+                Float::max(cosine, 0.0) / consts::PI
+            }
+            MaterialType::Microfacet { .. } => self.microfacet(ray, rec, scattered).0,
+            MaterialType::AnisotropicMetal {
+                roughness_u,
+                roughness_v,
+                rotation,
+                ..
+            } => {
+                let roughness = (*roughness_u, *roughness_v, *rotation);
+                anisotropic_eval(roughness, ray, rec, scattered).0
+            }
+            MaterialType::Subsurface { .. } => {
+                let cosine = -rec.normal.dot(scattered.dir.unit());
+                Float::max(cosine, 0.0) / consts::PI
+            }
+            MaterialType::Principled(principled) => {
+                principled_eval(principled, ray, rec, scattered).1
+            }
+            MaterialType::Measured(brdf) => measured_eval(brdf, ray, rec, scattered).1,
+            MaterialType::Hair(hair) => hair_eval(hair, ray, rec, scattered).1,
+            _ => 0.0,
+        }
+    }
+
+    fn sampling_pdf(&self, ray: &Ray, rec: &HitRecord, scattered: &Ray) -> Float {
+        match &self {
+            MaterialType::Microfacet { .. } => self.microfacet(ray, rec, scattered).1,
+            MaterialType::AnisotropicMetal {
+                roughness_u,
+                roughness_v,
+                rotation,
+                ..
+            } => {
+                let roughness = (*roughness_u, *roughness_v, *rotation);
+                anisotropic_eval(roughness, ray, rec, scattered).1
+            }
+            _ => self.scattering_pdf(ray, rec, scattered),
+        }
+    }
+
+    fn directional_attenuation(&self, ray: &Ray, rec: &HitRecord, scattered: &Ray) -> Option<Vec3> {
+        match &self {
+            MaterialType::Microfacet {
+                fresnel: Fresnel::Conductor { f0 },
+                ..
+            }
+            | MaterialType::AnisotropicMetal { f0, .. } => {
+                let wo = -ray.dir.unit();
+                let m = half_vector(wo, scattered.dir)?;
+                Some(conductor_reflectance(wo.dot(m), *f0, rec))
+            }
+            MaterialType::Microfacet {
+                fresnel: fresnel @ Fresnel::Dielectric { .. },
+                ..
+            } => {
+                let film = rec.film.as_ref()?;
+                let uvw = Onb::from_w(rec.normal);
+                let wo = uvw.world_to_local(-ray.dir.unit());
+                let wi = uvw.world_to_local(scattered.dir.unit());
+                Some(film_tint(film, wo, wi, fresnel.indices(rec)?))
+            }
+            MaterialType::Principled(principled) => {
+                let (value, pdf) = principled_eval(principled, ray, rec, scattered);
+                Some(if pdf > 0.0 { value / pdf } else { value })
+            }
+            MaterialType::Measured(brdf) => {
+                let (value, pdf) = measured_eval(brdf, ray, rec, scattered);
+                Some(if pdf > 0.0 { value / pdf } else { value })
+            }
+            MaterialType::Hair(hair) => {
+                let (value, pdf) = hair_eval(hair, ray, rec, scattered);
+                Some(if pdf > 0.0 { value / pdf } else { value })
+            }
+            _ => None,
+        }
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Vec3 {
+        match &self {
+            MaterialType::DiffuseLight { emit } => *emit,
+            MaterialType::Principled(principled) => principled.at(rec.uv).emission,
+            _ => Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        match &self {
+            MaterialType::Lambertian { albedo } | MaterialType::Metal { albedo, .. } => *albedo,
+            MaterialType::Dialectric { .. }
+            | MaterialType::Microfacet {
+                fresnel: Fresnel::Dielectric { .. },
+                ..
+            } => Vec3::new(1.0, 1.0, 1.0),
+            MaterialType::Microfacet {
+                fresnel: Fresnel::Conductor { f0 },
+                ..
+            }
+            | MaterialType::AnisotropicMetal { f0, .. } => *f0,
+            MaterialType::Principled(principled) => principled.at(rec.uv).base_color,
+            MaterialType::Measured(brdf) => brdf.albedo(),
+            MaterialType::Hair(hair) => hair.color,
+            MaterialType::Subsurface { albedo, .. } => *albedo,
+            MaterialType::ThinGlass { tint, .. } => *tint,
+            MaterialType::DiffuseLight { .. } => {
+                let emit = self.emitted(rec);
+                Vec3::new(
+                    clamp(emit.x, 0.0, 1.0),
+                    clamp(emit.y, 0.0, 1.0),
+                    clamp(emit.z, 0.0, 1.0),
+                )
+            }
+        }
+    }
+}
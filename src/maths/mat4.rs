@@ -0,0 +1,155 @@
+use crate::maths::vec3::*;
+use crate::maths::Float;
+
+use std::ops::Mul;
+
+/// Affine transform as a row-major 4x4 matrix, acting on column vectors.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mat4 {
+    pub m: [[Float; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn identity() -> Self {
+        Mat4 {
+            m: [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        let mut result = Mat4::identity();
+        result.m[0][3] = offset.x;
+        result.m[1][3] = offset.y;
+        result.m[2][3] = offset.z;
+        result
+    }
+
+    pub fn scaling(factor: Vec3) -> Self {
+        let mut result = Mat4::identity();
+        result.m[0][0] = factor.x;
+        result.m[1][1] = factor.y;
+        result.m[2][2] = factor.z;
+        result
+    }
+
+    /// Rotation of `angle` radians around the x axis.
+    pub fn rotation_x(angle: Float) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        let mut result = Mat4::identity();
+        result.m[1][1] = cos;
+        result.m[1][2] = -sin;
+        result.m[2][1] = sin;
+        result.m[2][2] = cos;
+        result
+    }
+
+    /// Rotation of `angle` radians around the y axis.
+    pub fn rotation_y(angle: Float) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        let mut result = Mat4::identity();
+        result.m[0][0] = cos;
+        result.m[0][2] = sin;
+        result.m[2][0] = -sin;
+        result.m[2][2] = cos;
+        result
+    }
+
+    /// Rotation of `angle` radians around the z axis.
+    pub fn rotation_z(angle: Float) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        let mut result = Mat4::identity();
+        result.m[0][0] = cos;
+        result.m[0][1] = -sin;
+        result.m[1][0] = sin;
+        result.m[1][1] = cos;
+        result
+    }
+
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+            m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+            m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3],
+        )
+    }
+
+    /// Transforms a direction, ignoring the translation.
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+
+    /// Transforms a normal by the inverse transpose of the upper 3x3 block,
+    /// up to a positive factor, so it stays perpendicular to the transformed
+    /// surface. The result isn't normalized.
+    pub fn transform_normal(&self, n: Vec3) -> Vec3 {
+        // Cofactors make the inverse transpose times the determinant.
+        let c = |i, j| self.cofactor(i, j);
+        let sign = self.determinant3().signum();
+        Vec3::new(
+            c(0, 0) * n.x + c(0, 1) * n.y + c(0, 2) * n.z,
+            c(1, 0) * n.x + c(1, 1) * n.y + c(1, 2) * n.z,
+            c(2, 0) * n.x + c(2, 1) * n.y + c(2, 2) * n.z,
+        ) * sign
+    }
+
+    /// Inverse of the transform, which must not flatten space.
+    pub fn inverse(&self) -> Self {
+        let determinant = self.determinant3();
+        assert!(determinant != 0.0, "flat transforms have no inverse");
+        let mut result = Mat4::identity();
+        for i in 0..3 {
+            for j in 0..3 {
+                result.m[i][j] = self.cofactor(j, i) / determinant;
+            }
+        }
+        let offset = Vec3::new(self.m[0][3], self.m[1][3], self.m[2][3]);
+        let offset = result.transform_vector(offset);
+        result.m[0][3] = -offset.x;
+        result.m[1][3] = -offset.y;
+        result.m[2][3] = -offset.z;
+        result
+    }
+
+    /// Signed minor of the cell at `row` and `column` of the upper 3x3
+    /// block.
+    fn cofactor(&self, row: usize, column: usize) -> Float {
+        let m = &self.m;
+        let (r0, r1) = ((row + 1) % 3, (row + 2) % 3);
+        let (c0, c1) = ((column + 1) % 3, (column + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    }
+
+    /// Determinant of the upper 3x3 block, the factor by which volumes scale.
+    pub fn determinant3(&self) -> Float {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+}
+
+impl Mul<Mat4> for Mat4 {
+    type Output = Mat4;
+
+    /// Composes transforms, `rhs` being applied first.
+    fn mul(self, rhs: Mat4) -> Mat4 {
+        let mut result = Mat4 { m: [[0.0; 4]; 4] };
+        for (i, row) in result.m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        result
+    }
+}
@@ -1,5 +1,43 @@
+mod mat4;
+mod onb;
+pub mod shading;
 mod utils;
 mod vec3;
 
+pub use mat4::*;
+pub use onb::*;
 pub use utils::*;
 pub use vec3::*;
+
+/// Scalar type of the renderer: `f64`, or `f32` when built with the `f32`
+/// feature for speed at the cost of precision.
+#[cfg(not(feature = "f32"))]
+pub type Float = f64;
+#[cfg(feature = "f32")]
+pub type Float = f32;
+
+#[cfg(feature = "f32")]
+pub use std::f32::consts;
+/// Mathematical constants of `Float`.
+#[cfg(not(feature = "f32"))]
+pub use std::f64::consts;
+
+/// `value` rounded to single precision, for file formats storing `f32`.
+#[cfg(not(feature = "f32"))]
+pub fn to_f32(value: Float) -> f32 {
+    value as f32
+}
+#[cfg(feature = "f32")]
+pub fn to_f32(value: Float) -> f32 {
+    value
+}
+
+/// `value` in double precision.
+#[cfg(not(feature = "f32"))]
+pub fn to_f64(value: Float) -> f64 {
+    value
+}
+#[cfg(feature = "f32")]
+pub fn to_f64(value: Float) -> f64 {
+    f64::from(value)
+}
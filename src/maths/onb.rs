@@ -0,0 +1,50 @@
+use crate::maths::vec3::*;
+
+/// Orthonormal basis, with `w` usually aligned with a surface normal.
+#[derive(Copy, Clone, Debug)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn from_w(n: Vec3) -> Self {
+        let w = n.unit();
+        let a = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(a).unit();
+        let u = w.cross(v);
+
+        Onb { u, v, w }
+    }
+
+    /// Basis with `w` along `n` and `u` along the part of `tangent`
+    /// perpendicular to it, falling back on `from_w` when there is none.
+    pub fn from_w_and_u(n: Vec3, tangent: Vec3) -> Self {
+        let w = n.unit();
+        let u = tangent - w * w.dot(tangent);
+        if u.length() < 1e-6 {
+            return Onb::from_w(n);
+        }
+        let u = u.unit();
+        Onb {
+            u,
+            v: w.cross(u),
+            w,
+        }
+    }
+
+    /// Converts coordinates expressed in this basis to world space.
+    pub fn local(&self, a: Vec3) -> Vec3 {
+        self.u * a.x + self.v * a.y + self.w * a.z
+    }
+
+    /// Converts world space coordinates to this basis, undoing `local`.
+    pub fn world_to_local(&self, a: Vec3) -> Vec3 {
+        Vec3::new(a.dot(self.u), a.dot(self.v), a.dot(self.w))
+    }
+}
@@ -0,0 +1,377 @@
+//! Building blocks of scattering functions.
+//!
+//! Functions working in a shading frame expect directions expressed in it,
+//! with the surface normal along +z as in `Onb::local`. Directions point away
+//! from the surface unless stated otherwise, and sampling functions take their
+//! uniform numbers as arguments so callers pick where those come from.
+
+use crate::maths::utils::clamp;
+use crate::maths::vec3::*;
+use crate::maths::Float;
+
+use crate::maths::consts::PI;
+
+/// Unit direction at polar angle `theta` from +z and azimuth `phi` from +x.
+pub fn spherical_direction(theta: Float, phi: Float) -> Vec3 {
+    let (sin_theta, cos_theta) = theta.sin_cos();
+    let (sin_phi, cos_phi) = phi.sin_cos();
+    Vec3::new(sin_theta * cos_phi, sin_theta * sin_phi, cos_theta)
+}
+
+/// Polar angle in [0, pi] and azimuth in [0, 2 pi) of `direction`, which
+/// needn't be normalized.
+pub fn spherical_angles(direction: Vec3) -> (Float, Float) {
+    let direction = direction.unit();
+    let theta = clamp(direction.z, -1.0, 1.0).acos();
+    let phi = direction.y.atan2(direction.x);
+    (theta, if phi < 0.0 { phi + 2.0 * PI } else { phi })
+}
+
+/// Whether `a` and `b` lie strictly on the same side of the surface of normal `normal`.
+pub fn same_hemisphere(a: Vec3, b: Vec3, normal: Vec3) -> bool {
+    a.dot(normal) * b.dot(normal) > 0.0
+}
+
+/// Mirror of the incoming direction `v` about `n`.
+pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
+    v - v.dot(n) * n * 2.0
+}
+
+/// Refraction of the unit incoming direction `uv` through a surface of unit
+/// normal `n` facing it, `etai_over_etat` being the ratio of the refractive
+/// indices on the incoming and outgoing sides. Assumes no total internal
+/// reflection happens.
+pub fn refract(uv: Vec3, n: Vec3, etai_over_etat: Float) -> Vec3 {
+    let cos_theta = (-uv).dot(n);
+    let r_out_parallel = etai_over_etat * (uv + cos_theta * n);
+    let r_out_perp = -Float::sqrt(1.0 - r_out_parallel.length_squared()) * n;
+    r_out_parallel + r_out_perp
+}
+
+/// Normalized half vector of a reflection between `wo` and `wi`, `None` when
+/// they are opposite.
+pub fn half_vector(wo: Vec3, wi: Vec3) -> Option<Vec3> {
+    let h = wo.unit() + wi.unit();
+    if h.length_squared() < 1e-12 {
+        None
+    } else {
+        Some(h.unit())
+    }
+}
+
+/// Normalized half vector of a refraction from `wo`, on the side of index
+/// `eta_o`, to `wi`, on the side of index `eta_i`, oriented towards `wo`.
+/// `None` when both indices are equal and the directions opposite.
+pub fn refraction_half_vector(wo: Vec3, wi: Vec3, eta_o: Float, eta_i: Float) -> Option<Vec3> {
+    let h = -(wo.unit() * eta_o + wi.unit() * eta_i);
+    if h.length_squared() < 1e-12 {
+        None
+    } else if h.dot(wo) < 0.0 {
+        Some(-h.unit())
+    } else {
+        Some(h.unit())
+    }
+}
+
+/// Reflectance at normal incidence of an interface between media of
+/// refractive index ratio `eta`.
+pub fn schlick_f0(eta: Float) -> Float {
+    let r0 = (1.0 - eta) / (1.0 + eta);
+    r0 * r0
+}
+
+/// Schlick's approximation of the Fresnel reflectance for a cosine of
+/// `cos_theta` between the direction and the normal.
+pub fn fresnel_schlick(cos_theta: Float, f0: Float) -> Float {
+    f0 + (1.0 - f0) * (1.0 - cos_theta).powf(5.0)
+}
+
+/// Schlick's approximation for colored reflectances, as those of metals.
+pub fn fresnel_schlick_color(cos_theta: Float, f0: Vec3) -> Vec3 {
+    let weight = (1.0 - cos_theta).powf(5.0);
+    f0 + (Vec3::new(1.0, 1.0, 1.0) - f0) * weight
+}
+
+/// Exact Fresnel reflectance of unpolarized light between dielectrics.
+/// `cos_theta_i` is the cosine with the normal on the incoming side,
+/// negative when arriving from behind, and `eta` the index behind the
+/// surface over the index in front. Total internal reflection returns 1.
+pub fn fresnel_dielectric(cos_theta_i: Float, eta: Float) -> Float {
+    let (cos_theta_i, eta) = if cos_theta_i < 0.0 {
+        (-cos_theta_i, 1.0 / eta)
+    } else {
+        (cos_theta_i, eta)
+    };
+    let cos_theta_i = cos_theta_i.min(1.0);
+
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+
+    let parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (parallel * parallel + perpendicular * perpendicular) / 2.0
+}
+
+/// GGX (Trowbridge-Reitz) density of microfacet normals `m`, in the shading
+/// frame, for a roughness `alpha`. Zero below the surface.
+pub fn ggx_d(m: Vec3, alpha: Float) -> Float {
+    if m.z <= 0.0 {
+        return 0.0;
+    }
+    let alpha2 = alpha * alpha;
+    let cos2 = m.z * m.z;
+    let denominator = cos2 * (alpha2 - 1.0) + 1.0;
+    alpha2 / (PI * denominator * denominator)
+}
+
+/// Smith masking term of the GGX distribution for direction `w`, in the
+/// shading frame.
+pub fn ggx_g1(w: Vec3, alpha: Float) -> Float {
+    let cos2 = w.z * w.z;
+    if cos2 == 0.0 {
+        return 0.0;
+    }
+    let tan2 = (w.x * w.x + w.y * w.y) / cos2;
+    2.0 / (1.0 + (1.0 + alpha * alpha * tan2).sqrt())
+}
+
+/// Separable Smith shadowing-masking term for the directions `wo` and `wi`.
+pub fn ggx_g(wo: Vec3, wi: Vec3, alpha: Float) -> Float {
+    ggx_g1(wo, alpha) * ggx_g1(wi, alpha)
+}
+
+/// Microfacet normal distributed proportionally to `ggx_d(m) * m.z`, from
+/// two uniform numbers in [0, 1).
+pub fn sample_ggx(alpha: Float, u1: Float, u2: Float) -> Vec3 {
+    let tan2_theta = alpha * alpha * u1 / (1.0 - u1);
+    let cos_theta = 1.0 / (1.0 + tan2_theta).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+/// Density, over solid angle, of the normals drawn by `sample_ggx`.
+pub fn ggx_pdf(m: Vec3, alpha: Float) -> Float {
+    ggx_d(m, alpha) * m.z.max(0.0)
+}
+
+/// Microfacet normal visible from `wo`, distributed proportionally to
+/// `ggx_g1(wo) * max(wo.m, 0) * ggx_d(m) / wo.z` (Heitz 2018), from two uniform
+/// numbers in [0, 1). `wo` must be a unit vector above the surface.
+pub fn sample_ggx_visible(wo: Vec3, alpha: Float, u1: Float, u2: Float) -> Vec3 {
+    sample_ggx_visible_anisotropic(wo, (alpha, alpha), u1, u2)
+}
+
+/// `sample_ggx_visible` for widths `alpha_x` and `alpha_y` along the x and y
+/// axes of the shading frame.
+pub fn sample_ggx_visible_anisotropic(
+    wo: Vec3,
+    (alpha_x, alpha_y): (Float, Float),
+    u1: Float,
+    u2: Float,
+) -> Vec3 {
+    // Stretch the view so the distribution becomes a hemisphere of radius 1.
+    let v = Vec3::new(alpha_x * wo.x, alpha_y * wo.y, wo.z).unit();
+
+    let length2 = v.x * v.x + v.y * v.y;
+    let t1 = if length2 > 0.0 {
+        Vec3::new(-v.y, v.x, 0.0) / length2.sqrt()
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let t2 = v.cross(t1);
+
+    // Uniform point of the projected disk, squashed to its visible part.
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1.0 + v.z);
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+    let p3 = (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+    let n = t1 * p1 + t2 * p2 + v * p3;
+
+    // Unstretch back to the microfacet normal.
+    Vec3::new(alpha_x * n.x, alpha_y * n.y, n.z.max(0.0)).unit()
+}
+
+/// Density, over solid angle, of the normals drawn by `sample_ggx_visible`.
+pub fn ggx_visible_pdf(wo: Vec3, m: Vec3, alpha: Float) -> Float {
+    if wo.z <= 0.0 {
+        return 0.0;
+    }
+    ggx_g1(wo, alpha) * wo.dot(m).max(0.0) * ggx_d(m, alpha) / wo.z
+}
+
+/// Anisotropic GGX density of microfacet normals `m`, with widths `alpha_x`
+/// and `alpha_y` along the x and y axes of the shading frame (Heitz 2014,
+/// "Understanding the Masking-Shadowing Function in Microfacet-Based
+/// BRDFs"). Zero below the surface.
+pub fn ggx_d_anisotropic(m: Vec3, (alpha_x, alpha_y): (Float, Float)) -> Float {
+    if m.z <= 0.0 {
+        return 0.0;
+    }
+    let (x, y) = (m.x / alpha_x, m.y / alpha_y);
+    let denominator = x * x + y * y + m.z * m.z;
+    1.0 / (PI * alpha_x * alpha_y * denominator * denominator)
+}
+
+/// Smith masking term of the anisotropic GGX distribution for direction
+/// `w`, in the shading frame.
+pub fn ggx_g1_anisotropic(w: Vec3, (alpha_x, alpha_y): (Float, Float)) -> Float {
+    let cos2 = w.z * w.z;
+    if cos2 == 0.0 {
+        return 0.0;
+    }
+    let (x, y) = (alpha_x * w.x, alpha_y * w.y);
+    2.0 / (1.0 + (1.0 + (x * x + y * y) / cos2).sqrt())
+}
+
+/// Density, over solid angle, of the normals drawn by
+/// `sample_ggx_visible_anisotropic`.
+pub fn ggx_visible_pdf_anisotropic(wo: Vec3, m: Vec3, alphas: (Float, Float)) -> Float {
+    if wo.z <= 0.0 {
+        return 0.0;
+    }
+    ggx_g1_anisotropic(wo, alphas) * wo.dot(m).max(0.0) * ggx_d_anisotropic(m, alphas) / wo.z
+}
+
+/// Reflection of anisotropic GGX microfacets reflecting everything from
+/// `wo` to `wi`, like `ggx_scattering` without indices: the BRDF times the
+/// cosine of `wi`, and the density with which
+/// `sample_ggx_visible_anisotropic` followed by a reflection draws `wi`.
+pub fn ggx_anisotropic_reflection(wo: Vec3, wi: Vec3, alphas: (Float, Float)) -> (Float, Float) {
+    if wo.z <= 0.0 || wi.z <= 0.0 {
+        return (0.0, 0.0);
+    }
+    let m = match half_vector(wo, wi) {
+        Some(m) => m,
+        None => return (0.0, 0.0),
+    };
+    let cos_o = wo.dot(m);
+    if cos_o <= 0.0 {
+        return (0.0, 0.0);
+    }
+    let masking = ggx_g1_anisotropic(wo, alphas) * ggx_g1_anisotropic(wi, alphas);
+    let value = ggx_d_anisotropic(m, alphas) * masking / (4.0 * wo.z);
+    let pdf = ggx_visible_pdf_anisotropic(wo, m, alphas) / (4.0 * cos_o);
+    (value, pdf)
+}
+
+/// GGX width for a perceptual `roughness` in [0, 1], squared so roughness
+/// changes look even, and kept off zero where the distribution becomes a
+/// spike.
+pub fn ggx_alpha(roughness: Float) -> Float {
+    clamp(roughness * roughness, 1e-3, 1.0)
+}
+
+/// Scattering of GGX microfacets of width `alpha` from `wo` to `wi`: the BSDF
+/// times the cosine of `wi`, and the density with which `sample_ggx_visible`
+/// followed by a reflection, or a refraction picked with the probability
+/// the Fresnel term leaves, draws `wi`. Follows Walter et al. 2007,
+/// "Microfacet Models for Refraction through Rough Surfaces".
+///
+/// Dielectrics are given the refractive `indices` on the side of `wo` and
+/// on the other side, and both reflect and refract. Without indices, facets
+/// reflect everything, leaving colored Fresnel terms to the caller.
+/// Refraction doesn't rescale radiance by the squared ratio of the indices,
+/// so both reflection and refraction weigh sampled rays by the masking of
+/// `wi`.
+pub fn ggx_scattering(
+    wo: Vec3,
+    wi: Vec3,
+    alpha: Float,
+    indices: Option<(Float, Float)>,
+) -> (Float, Float) {
+    if wo.z <= 0.0 || wi.z == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    if wi.z > 0.0 {
+        let m = match half_vector(wo, wi) {
+            Some(m) => m,
+            None => return (0.0, 0.0),
+        };
+        let cos_o = wo.dot(m);
+        if cos_o <= 0.0 {
+            return (0.0, 0.0);
+        }
+        let reflectance = match indices {
+            Some((eta_o, eta_i)) => fresnel_dielectric(cos_o, eta_i / eta_o),
+            None => 1.0,
+        };
+        let value = reflectance * ggx_d(m, alpha) * ggx_g(wo, wi, alpha) / (4.0 * wo.z);
+        let pdf = reflectance * ggx_visible_pdf(wo, m, alpha) / (4.0 * cos_o);
+        return (value, pdf);
+    }
+
+    let (eta_o, eta_i) = match indices {
+        Some(indices) => indices,
+        None => return (0.0, 0.0),
+    };
+    let m = match refraction_half_vector(wo, wi, eta_o, eta_i) {
+        Some(m) => m,
+        None => return (0.0, 0.0),
+    };
+    let (cos_o, cos_i) = (wo.dot(m), wi.dot(m));
+    if cos_o <= 0.0 || cos_i >= 0.0 {
+        return (0.0, 0.0);
+    }
+    let transmittance = 1.0 - fresnel_dielectric(cos_o, eta_i / eta_o);
+    // Change of density from half vectors to refracted directions.
+    let denominator = eta_o * cos_o + eta_i * cos_i;
+    let jacobian = eta_i * eta_i * -cos_i / (denominator * denominator);
+    let value = transmittance * ggx_d(m, alpha) * ggx_g(wo, wi, alpha) * cos_o * jacobian / wo.z;
+    let pdf = transmittance * ggx_visible_pdf(wo, m, alpha) * jacobian;
+    (value, pdf)
+}
+
+/// Reflectance of unpolarized light of `wavelength` off a film `thickness`
+/// thick, both in the same unit, coating a surface (Airy's formula). The
+/// light arrives with a cosine of `cos_theta` from a medium of index
+/// `indices.0`, the film has index `indices.1` and the surface `indices.2`.
+/// Light reflected off both sides of the film interferes, which colors
+/// soap bubbles and oil slicks. Total internal reflection returns 1.
+pub fn thin_film_reflectance(
+    cos_theta: Float,
+    indices: (Float, Float, Float),
+    thickness: Float,
+    wavelength: Float,
+) -> Float {
+    let (n1, n2, n3) = indices;
+    let cos1 = clamp(cos_theta, 0.0, 1.0);
+    let sin2_1 = 1.0 - cos1 * cos1;
+    let cosine_in = |n: Float| {
+        let sin2 = sin2_1 * (n1 / n) * (n1 / n);
+        if sin2 >= 1.0 {
+            None
+        } else {
+            Some((1.0 - sin2).sqrt())
+        }
+    };
+    let (cos2, cos3) = match (cosine_in(n2), cosine_in(n3)) {
+        (Some(cos2), Some(cos3)) => (cos2, cos3),
+        _ => return 1.0,
+    };
+
+    // Phase the light going back and forth through the film lags by.
+    let delta = 4.0 * PI * n2 * thickness * cos2 / wavelength;
+    let airy = |r12: Float, r23: Float| {
+        let cross = 2.0 * r12 * r23 * delta.cos();
+        (r12 * r12 + r23 * r23 + cross) / (1.0 + r12 * r12 * r23 * r23 + cross)
+    };
+    let perpendicular = |ni: Float, cos_i: Float, nj: Float, cos_j: Float| {
+        (ni * cos_i - nj * cos_j) / (ni * cos_i + nj * cos_j)
+    };
+    let parallel = |ni: Float, cos_i: Float, nj: Float, cos_j: Float| {
+        (nj * cos_i - ni * cos_j) / (nj * cos_i + ni * cos_j)
+    };
+    let s = airy(
+        perpendicular(n1, cos1, n2, cos2),
+        perpendicular(n2, cos2, n3, cos3),
+    );
+    let p = airy(parallel(n1, cos1, n2, cos2), parallel(n2, cos2, n3, cos3));
+    clamp((s + p) / 2.0, 0.0, 1.0)
+}
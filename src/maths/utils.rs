@@ -1,17 +1,189 @@
 use rand::prelude::*;
 
+use std::cell::Cell;
+
 use crate::maths::vec3::*;
+use crate::maths::{consts, Float};
+
+/// Odd constant close to 2^64 / golden ratio, the SplitMix64 increment.
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Counter based random generator: number `counter` of the sequence `key` is a
+/// hash of both, so any position of a sequence can be reached directly.
+#[derive(Clone, Copy)]
+struct CounterRng {
+    key: u64,
+    counter: u64,
+}
+
+thread_local! {
+    static RNG: Cell<CounterRng> = Cell::new(CounterRng {
+        key: thread_rng().gen(),
+        counter: 0,
+    });
+}
+
+/// SplitMix64 finalizer, scrambling every bit of `z` into every bit of the result.
+pub fn mix_bits(z: u64) -> u64 {
+    let z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Hash of `seed` and `index`, used to derive decorrelated seeds.
+pub fn mix_seed(seed: u64, index: u64) -> u64 {
+    mix_bits(seed ^ index.wrapping_mul(GOLDEN_GAMMA))
+}
+
+/// Restarts the random sequence of the current thread from `seed`.
+pub fn seed_random(seed: u64) {
+    RNG.with(|rng| {
+        rng.set(CounterRng {
+            key: seed,
+            counter: 0,
+        })
+    });
+}
+
+/// Position in the random sequence of a thread, see `save_random`.
+#[derive(Clone, Copy)]
+pub struct RandomState(CounterRng);
+
+/// Current position in the random sequence of the current thread, which
+/// `restore_random` goes back to, so work can be interleaved with other work
+/// reseeding the thread.
+pub fn save_random() -> RandomState {
+    RandomState(RNG.with(|rng| rng.get()))
+}
+
+pub fn restore_random(state: RandomState) {
+    RNG.with(|rng| rng.set(state.0));
+}
+
+/// Skips the next `count` numbers of the random sequence of the current
+/// thread, in constant time.
+pub fn skip_random(count: u64) {
+    RNG.with(|rng| {
+        let state = rng.get();
+        rng.set(CounterRng {
+            counter: state.counter.wrapping_add(count),
+            ..state
+        });
+    });
+}
+
+/// Seed drawn from the system entropy, for when no seed was requested.
+pub fn random_seed() -> u64 {
+    thread_rng().gen()
+}
+
+impl CounterRng {
+    fn next_u64(&mut self) -> u64 {
+        self.counter = self.counter.wrapping_add(1);
+        mix_bits(
+            self.key
+                .wrapping_add(self.counter.wrapping_mul(GOLDEN_GAMMA)),
+        )
+    }
+}
+
+/// Next 64 random bits of the sequence of the current thread.
+pub fn random_u64() -> u64 {
+    RNG.with(|rng| {
+        let mut state = rng.get();
+        let bits = state.next_u64();
+        rng.set(state);
+        bits
+    })
+}
+
+/// Random sequence of its own, apart from the one of the thread that path
+/// sampling draws from, for scene generators and procedural textures: the
+/// same seed and name always give the same numbers, whatever the sampler,
+/// the sample count or the other streams did.
+#[derive(Clone, Copy)]
+pub struct RandomStream(CounterRng);
+
+impl RandomStream {
+    /// Stream `name` of `seed`, streams of different names being unrelated.
+    pub fn new(seed: u64, name: &str) -> Self {
+        // FNV-1a, stable across builds unlike the standard hasher.
+        let hash = name.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        RandomStream(CounterRng {
+            key: mix_seed(seed, hash),
+            counter: 0,
+        })
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0.next_u64()
+    }
+
+    /// Uniform number in [0, 1).
+    pub fn next_01(&mut self) -> Float {
+        let bits = Float::MANTISSA_DIGITS;
+        (self.next_u64() >> (64 - bits)) as Float / (1u64 << bits) as Float
+    }
+
+    pub fn between(&mut self, min: Float, max: Float) -> Float {
+        min + (max - min) * self.next_01()
+    }
+}
+
+/// Direction uniformly distributed over the unit sphere, from a uniform point
+/// of the unit square.
+pub fn sample_unit_sphere((u1, u2): (Float, Float)) -> Vec3 {
+    let a = 2.0 * consts::PI * u1;
+    let z = 2.0 * u2 - 1.0;
+    let r = Float::sqrt(1.0 - z * z);
+
+    Vec3::new(r * Float::cos(a), r * Float::sin(a), z)
+}
+
+/// Point uniformly distributed over the unit disk of the xy plane, from a
+/// uniform point of the unit square. The concentric mapping keeps nearby
+/// points of the square nearby on the disk, preserving their stratification.
+pub fn sample_unit_disk((u1, u2): (Float, Float)) -> Vec3 {
+    let x = 2.0 * u1 - 1.0;
+    let y = 2.0 * u2 - 1.0;
+    if x == 0.0 && y == 0.0 {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+
+    let quarter = consts::FRAC_PI_4;
+    let (r, theta) = if x.abs() > y.abs() {
+        (x, quarter * (y / x))
+    } else {
+        (y, 2.0 * quarter - quarter * (x / y))
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
+/// Direction around +z distributed proportionally to its cosine with the z
+/// axis, from a uniform point of the unit square.
+pub fn sample_cosine_direction((u1, u2): (Float, Float)) -> Vec3 {
+    let phi = 2.0 * consts::PI * u1;
+    let r = Float::sqrt(u2);
+
+    Vec3::new(phi.cos() * r, phi.sin() * r, Float::sqrt(1.0 - u2))
+}
 
 pub fn random_in_unit_sphere() -> Vec3 {
-    let a = random_between(0.0, 2.0 * std::f64::consts::PI);
-    let z = random_between(-1.0, 1.0);
-    let r = f64::sqrt(1.0 - z * z);
+    let u1 = random_01();
+    sample_unit_sphere((u1, random_01()))
+}
 
-    Vec3::new(r * f64::cos(a), r * f64::sin(a), z)
+pub fn random_in_unit_disk() -> Vec3 {
+    let u1 = random_01();
+    sample_unit_disk((u1, random_01()))
 }
 
-pub fn random_in_hemisphere(normal: Vec3) -> Vec3 {
-    let in_unit_sphere = random_in_unit_sphere();
+/// Direction uniformly distributed over the hemisphere around `normal`, from
+/// a uniform point of the unit square.
+pub fn sample_hemisphere(normal: Vec3, u: (Float, Float)) -> Vec3 {
+    let in_unit_sphere = sample_unit_sphere(u);
     if in_unit_sphere.dot(normal) > 0.0 {
         in_unit_sphere
     } else {
@@ -19,21 +191,51 @@ pub fn random_in_hemisphere(normal: Vec3) -> Vec3 {
     }
 }
 
-pub fn random_01() -> f64 {
-    let mut rng = rand::thread_rng();
-    rng.gen()
+pub fn random_in_hemisphere(normal: Vec3) -> Vec3 {
+    let u1 = random_01();
+    sample_hemisphere(normal, (u1, random_01()))
+}
+
+/// Direction around +z distributed proportionally to its cosine with the z axis.
+pub fn random_cosine_direction() -> Vec3 {
+    let u1 = random_01();
+    sample_cosine_direction((u1, random_01()))
+}
+
+/// Uniform number in [0, 1).
+pub fn random_01() -> Float {
+    uniform_from_bits(random_u64())
 }
 
-pub fn random_between(min: f64, max: f64) -> f64 {
-    let mut rng = rand::thread_rng();
-    rng.gen_range(min, max)
+/// Uniform number in [0, 1) made of the top bits of `bits`, for numbers
+/// hashed rather than drawn. As many bits are taken as fit the mantissa
+/// exactly, so the result can't be rounded up to 1.
+pub fn uniform_from_bits(bits: u64) -> Float {
+    let digits = Float::MANTISSA_DIGITS;
+    (bits >> (64 - digits)) as Float / (1u64 << digits) as Float
 }
 
-pub fn deg_to_rad(degrees: f64) -> f64 {
-    degrees * (std::f64::consts::PI / 180.0)
+pub fn random_between(min: Float, max: Float) -> Float {
+    min + (max - min) * random_01()
 }
 
-pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
+/// Power heuristic (beta = 2) weight of a sample drawn from the strategy of
+/// density `pdf` when combined with a strategy of density `other_pdf`.
+pub fn power_heuristic(pdf: Float, other_pdf: Float) -> Float {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+    if a + b == 0.0 {
+        0.0
+    } else {
+        a / (a + b)
+    }
+}
+
+pub fn deg_to_rad(degrees: Float) -> Float {
+    degrees * (consts::PI / 180.0)
+}
+
+pub fn clamp(x: Float, min: Float, max: Float) -> Float {
     if x < min {
         min
     } else if x > max {
@@ -42,3 +244,8 @@ pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
         x
     }
 }
+
+/// Relative luminance of a linear Rec. 709 color.
+pub fn luminance(color: Vec3) -> Float {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
@@ -1,3 +1,5 @@
+use crate::maths::Float;
+
 use std::ops::Add;
 use std::ops::AddAssign;
 use std::ops::Div;
@@ -8,17 +10,23 @@ use std::ops::Sub;
 use std::iter::Sum;
 #[derive(Copy, Clone, Debug)]
 pub struct Vec3 {
-    pub x: f64,
-    pub y: f64,
-    pub z: f64,
+    pub x: Float,
+    pub y: Float,
+    pub z: Float,
 }
 
 impl Vec3 {
-    pub fn new(x: f64, y: f64, z: f64) -> Self {
+    pub fn new(x: Float, y: Float, z: Float) -> Self {
         Vec3 { x, y, z }
     }
 
-    pub fn add(self, vec: Self) -> Self {
+    #[cfg(target_arch = "x86_64")]
+    fn add(self, vec: Self) -> Self {
+        sse2::add(self, vec)
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    fn add(self, vec: Self) -> Self {
         Vec3 {
             x: self.x + vec.x,
             y: self.y + vec.y,
@@ -26,7 +34,13 @@ impl Vec3 {
         }
     }
 
-    pub fn sub(self, vec: Self) -> Self {
+    #[cfg(target_arch = "x86_64")]
+    fn sub(self, vec: Self) -> Self {
+        sse2::sub(self, vec)
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    fn sub(self, vec: Self) -> Self {
         Vec3 {
             x: self.x - vec.x,
             y: self.y - vec.y,
@@ -34,11 +48,17 @@ impl Vec3 {
         }
     }
 
-    pub fn length(self) -> f64 {
-        f64::sqrt(self.length_squared())
+    pub fn length(self) -> Float {
+        Float::sqrt(self.length_squared())
     }
 
-    pub fn neg(self) -> Self {
+    #[cfg(target_arch = "x86_64")]
+    fn neg(self) -> Self {
+        sse2::neg(self)
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    fn neg(self) -> Self {
         Vec3 {
             x: -self.x,
             y: -self.y,
@@ -46,14 +66,20 @@ impl Vec3 {
         }
     }
 
-    pub fn length_squared(self) -> f64 {
+    pub fn length_squared(self) -> Float {
         self.x * self.x + self.y * self.y + self.z * self.z
     }
 
-    pub fn div(self, t: f64) -> Self {
+    fn div(self, t: Float) -> Self {
         self.mult_float(1.0 / t)
     }
 
+    #[cfg(target_arch = "x86_64")]
+    pub fn mult(self, vec: Vec3) -> Self {
+        sse2::mult(self, vec)
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
     pub fn mult(self, vec: Vec3) -> Self {
         Vec3 {
             x: self.x * vec.x,
@@ -62,7 +88,13 @@ impl Vec3 {
         }
     }
 
-    pub fn mult_float(self, t: f64) -> Self {
+    #[cfg(target_arch = "x86_64")]
+    pub fn mult_float(self, t: Float) -> Self {
+        sse2::mult_float(self, t)
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    pub fn mult_float(self, t: Float) -> Self {
         Vec3 {
             x: self.x * t,
             y: self.y * t,
@@ -70,10 +102,22 @@ impl Vec3 {
         }
     }
 
-    pub fn dot(self, vec: Vec3) -> f64 {
+    #[cfg(target_arch = "x86_64")]
+    pub fn dot(self, vec: Vec3) -> Float {
+        sse2::dot(self, vec)
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    pub fn dot(self, vec: Vec3) -> Float {
         self.x * vec.x + self.y * vec.y + self.z * vec.z
     }
 
+    #[cfg(target_arch = "x86_64")]
+    pub fn cross(self, vec: Vec3) -> Self {
+        sse2::cross(self, vec)
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
     pub fn cross(self, vec: Vec3) -> Self {
         Vec3 {
             x: self.y * vec.z - self.z * vec.y,
@@ -109,15 +153,15 @@ impl Mul<Vec3> for Vec3 {
     }
 }
 
-impl Mul<f64> for Vec3 {
+impl Mul<Float> for Vec3 {
     type Output = Vec3;
 
-    fn mul(self, other: f64) -> Vec3 {
+    fn mul(self, other: Float) -> Vec3 {
         self.mult_float(other)
     }
 }
 
-impl Mul<Vec3> for f64 {
+impl Mul<Vec3> for Float {
     type Output = Vec3;
     fn mul(self, rhs: Vec3) -> Vec3 {
         rhs.mult_float(self)
@@ -132,10 +176,10 @@ impl Sub for Vec3 {
     }
 }
 
-impl Div<f64> for Vec3 {
+impl Div<Float> for Vec3 {
     type Output = Vec3;
 
-    fn div(self, other: f64) -> Vec3 {
+    fn div(self, other: Float) -> Vec3 {
         self.div(other)
     }
 }
@@ -144,7 +188,7 @@ impl Neg for Vec3 {
     type Output = Vec3;
 
     fn neg(self) -> Vec3 {
-        Vec3::new(-self.x, -self.y, -self.z)
+        self.neg()
     }
 }
 
@@ -155,7 +199,165 @@ impl Sum for Vec3 {
     }
 }
 
-#[cfg(tests)]
-mod vec3_tests {
-    use super::*;
+/// SSE2 arithmetic of `Vec3`, computing the same roundings in the same
+/// order as the scalar code: x and y share a register, z sits in the low
+/// lane of another. Every x86_64 processor has SSE2, which makes the
+/// intrinsics safe to call.
+#[cfg(all(target_arch = "x86_64", not(feature = "f32")))]
+mod sse2 {
+    use super::Vec3;
+    use core::arch::x86_64::*;
+
+    fn load(vec: Vec3) -> (__m128d, __m128d) {
+        unsafe { (_mm_set_pd(vec.y, vec.x), _mm_set_sd(vec.z)) }
+    }
+
+    fn store(xy: __m128d, z: __m128d) -> Vec3 {
+        unsafe {
+            let y = _mm_unpackhi_pd(xy, xy);
+            Vec3::new(_mm_cvtsd_f64(xy), _mm_cvtsd_f64(y), _mm_cvtsd_f64(z))
+        }
+    }
+
+    pub fn add(a: Vec3, b: Vec3) -> Vec3 {
+        unsafe {
+            let ((a_xy, a_z), (b_xy, b_z)) = (load(a), load(b));
+            store(_mm_add_pd(a_xy, b_xy), _mm_add_sd(a_z, b_z))
+        }
+    }
+
+    pub fn sub(a: Vec3, b: Vec3) -> Vec3 {
+        unsafe {
+            let ((a_xy, a_z), (b_xy, b_z)) = (load(a), load(b));
+            store(_mm_sub_pd(a_xy, b_xy), _mm_sub_sd(a_z, b_z))
+        }
+    }
+
+    pub fn neg(a: Vec3) -> Vec3 {
+        unsafe {
+            let (xy, z) = load(a);
+            let sign = _mm_set1_pd(-0.0);
+            store(_mm_xor_pd(xy, sign), _mm_xor_pd(z, sign))
+        }
+    }
+
+    pub fn mult(a: Vec3, b: Vec3) -> Vec3 {
+        unsafe {
+            let ((a_xy, a_z), (b_xy, b_z)) = (load(a), load(b));
+            store(_mm_mul_pd(a_xy, b_xy), _mm_mul_sd(a_z, b_z))
+        }
+    }
+
+    pub fn mult_float(a: Vec3, t: f64) -> Vec3 {
+        unsafe {
+            let (xy, z) = load(a);
+            let t = _mm_set1_pd(t);
+            store(_mm_mul_pd(xy, t), _mm_mul_sd(z, t))
+        }
+    }
+
+    pub fn dot(a: Vec3, b: Vec3) -> f64 {
+        unsafe {
+            let ((a_xy, a_z), (b_xy, b_z)) = (load(a), load(b));
+            let xy = _mm_mul_pd(a_xy, b_xy);
+            let sum = _mm_add_sd(xy, _mm_unpackhi_pd(xy, xy));
+            _mm_cvtsd_f64(_mm_add_sd(sum, _mm_mul_sd(a_z, b_z)))
+        }
+    }
+
+    pub fn cross(a: Vec3, b: Vec3) -> Vec3 {
+        unsafe {
+            let ((a_xy, a_z), (b_xy, b_z)) = (load(a), load(b));
+            // (y, z) * (z', x') - (z, x) * (y', z') are x and y.
+            let (a_yz, a_zx) = (
+                _mm_shuffle_pd(a_xy, a_z, 0b01),
+                _mm_shuffle_pd(a_z, a_xy, 0b00),
+            );
+            let (b_yz, b_zx) = (
+                _mm_shuffle_pd(b_xy, b_z, 0b01),
+                _mm_shuffle_pd(b_z, b_xy, 0b00),
+            );
+            let xy = _mm_sub_pd(_mm_mul_pd(a_yz, b_zx), _mm_mul_pd(a_zx, b_yz));
+            // x * y' - y * x' is z.
+            let products = _mm_mul_pd(a_xy, _mm_shuffle_pd(b_xy, b_xy, 0b01));
+            store(
+                xy,
+                _mm_sub_sd(products, _mm_unpackhi_pd(products, products)),
+            )
+        }
+    }
+}
+
+/// SSE2 arithmetic of single precision `Vec3`, computing the same roundings
+/// in the same order as the scalar code, with x, y and z in the lower
+/// three lanes of a register.
+#[cfg(all(target_arch = "x86_64", feature = "f32"))]
+mod sse2 {
+    use super::Vec3;
+    use core::arch::x86_64::*;
+
+    /// Mask of `_mm_shuffle_ps` moving lanes `x`, `y` and `z` of a register
+    /// into its lower three lanes.
+    const fn lanes(x: i32, y: i32, z: i32) -> i32 {
+        3 << 6 | z << 4 | y << 2 | x
+    }
+
+    fn load(vec: Vec3) -> __m128 {
+        unsafe { _mm_set_ps(0.0, vec.z, vec.y, vec.x) }
+    }
+
+    fn store(xyz: __m128) -> Vec3 {
+        unsafe {
+            let y = _mm_shuffle_ps(xyz, xyz, lanes(1, 1, 1));
+            let z = _mm_shuffle_ps(xyz, xyz, lanes(2, 2, 2));
+            Vec3::new(_mm_cvtss_f32(xyz), _mm_cvtss_f32(y), _mm_cvtss_f32(z))
+        }
+    }
+
+    pub fn add(a: Vec3, b: Vec3) -> Vec3 {
+        unsafe { store(_mm_add_ps(load(a), load(b))) }
+    }
+
+    pub fn sub(a: Vec3, b: Vec3) -> Vec3 {
+        unsafe { store(_mm_sub_ps(load(a), load(b))) }
+    }
+
+    pub fn neg(a: Vec3) -> Vec3 {
+        unsafe { store(_mm_xor_ps(load(a), _mm_set1_ps(-0.0))) }
+    }
+
+    pub fn mult(a: Vec3, b: Vec3) -> Vec3 {
+        unsafe { store(_mm_mul_ps(load(a), load(b))) }
+    }
+
+    pub fn mult_float(a: Vec3, t: f32) -> Vec3 {
+        unsafe { store(_mm_mul_ps(load(a), _mm_set1_ps(t))) }
+    }
+
+    pub fn dot(a: Vec3, b: Vec3) -> f32 {
+        unsafe {
+            let products = _mm_mul_ps(load(a), load(b));
+            let y = _mm_shuffle_ps(products, products, lanes(1, 1, 1));
+            let z = _mm_shuffle_ps(products, products, lanes(2, 2, 2));
+            _mm_cvtss_f32(_mm_add_ss(_mm_add_ss(products, y), z))
+        }
+    }
+
+    pub fn cross(a: Vec3, b: Vec3) -> Vec3 {
+        unsafe {
+            let (a, b) = (load(a), load(b));
+            // a.yzx * b.zxy - a.zxy * b.yzx
+            let a_yzx = _mm_shuffle_ps(a, a, lanes(1, 2, 0));
+            let a_zxy = _mm_shuffle_ps(a, a, lanes(2, 0, 1));
+            let b_yzx = _mm_shuffle_ps(b, b, lanes(1, 2, 0));
+            let b_zxy = _mm_shuffle_ps(b, b, lanes(2, 0, 1));
+            store(_mm_sub_ps(
+                _mm_mul_ps(a_yzx, b_zxy),
+                _mm_mul_ps(a_zxy, b_yzx),
+            ))
+        }
+    }
 }
+
+#[cfg(test)]
+mod vec3_tests {}
@@ -0,0 +1,281 @@
+use crate::content_hash::{ContentHash, ContentHasher};
+use crate::maths::shading::*;
+use crate::maths::*;
+
+use std::fs::File;
+use std::io::prelude::*;
+
+/// Resolution of the MERL tables over the half vector angle, the difference
+/// angle and the difference azimuth, the last over [0, pi) by reciprocity.
+const THETA_HALF_RES: usize = 90;
+const THETA_DIFF_RES: usize = 90;
+const PHI_DIFF_RES: usize = 180;
+const VALUE_COUNT: usize = THETA_HALF_RES * THETA_DIFF_RES * PHI_DIFF_RES;
+
+/// Factors from the stored values to reflectances, per channel.
+const SCALES: [f64; 3] = [1.0 / 1500.0, 1.15 / 1500.0, 1.66 / 1500.0];
+
+/// Resolution of the sampling tables: outgoing angles, then cells over the
+/// squared sine and the azimuth of incoming directions.
+const SAMPLING_OUTGOING: usize = 16;
+const SAMPLING_SINE: usize = 32;
+const SAMPLING_AZIMUTH: usize = 64;
+
+/// Share of the samples drawn from the cosine instead of the tables, which
+/// covers the directions coarse cells miss.
+const COSINE_SHARE: Float = 0.1;
+
+/// Isotropic BRDF measured from a real material, in the format of the MERL
+/// database (Matusik et al. 2003, "A Data-Driven Reflectance Model"):
+/// reflectances tabulated over the angles between the half vector and the
+/// normal and between the directions and the half vector (Rusinkiewicz
+/// 1998), looked up at the nearest entry.
+///
+/// Directions are sampled with tables of the reflectance times the cosine,
+/// built for a few outgoing angles when the BRDF is loaded, so even the
+/// sharp highlights of metals converge.
+pub struct MeasuredBrdf {
+    /// Reflectances, three per entry, negative for unmeasured ones.
+    values: Vec<f32>,
+    /// Per outgoing angle, the summed weights of the sampling cells, in
+    /// rows of azimuths.
+    cdfs: Vec<Vec<Float>>,
+    /// Share of the light arriving head on that is reflected.
+    albedo: Vec3,
+}
+
+impl std::fmt::Debug for MeasuredBrdf {
+    /// Leaves out the millions of values.
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter
+            .debug_struct("MeasuredBrdf")
+            .field("albedo", &self.albedo)
+            .finish()
+    }
+}
+
+impl ContentHash for MeasuredBrdf {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        for &value in &self.values {
+            hasher.write(&value.to_le_bytes());
+        }
+    }
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Cosine of outgoing directions the sampling table `index` is built for.
+fn sampling_cosine(index: usize) -> Float {
+    1.0 - (index as Float + 0.5) / SAMPLING_OUTGOING as Float
+}
+
+/// Incoming direction at the center of the sampling cell `(row, column)`, for
+/// outgoing directions in the xz plane.
+fn cell_direction(row: Float, column: Float) -> Vec3 {
+    let sin2 = row / SAMPLING_SINE as Float;
+    let phi = column / SAMPLING_AZIMUTH as Float * 2.0 * consts::PI;
+    let sin = sin2.sqrt();
+    Vec3::new(
+        sin * phi.cos(),
+        sin * phi.sin(),
+        (1.0 - sin2).max(0.0).sqrt(),
+    )
+}
+
+impl MeasuredBrdf {
+    /// Loads a `.binary` file of the MERL database.
+    pub fn load(name: &str) -> std::io::Result<Self> {
+        let mut bytes = Vec::new();
+        File::open(name)?.read_to_end(&mut bytes)?;
+        MeasuredBrdf::parse(&bytes)
+    }
+
+    /// Reads a MERL file: three 32-bit sizes, then the red, green and blue
+    /// tables as 64-bit floats, all little endian.
+    pub fn parse(bytes: &[u8]) -> std::io::Result<Self> {
+        if bytes.len() < 12 {
+            return Err(invalid("truncated measured BRDF"));
+        }
+        let size = |index: usize| {
+            let start = index * 4;
+            i32::from_le_bytes([
+                bytes[start],
+                bytes[start + 1],
+                bytes[start + 2],
+                bytes[start + 3],
+            ])
+        };
+        if (size(0), size(1), size(2))
+            != (
+                THETA_HALF_RES as i32,
+                THETA_DIFF_RES as i32,
+                PHI_DIFF_RES as i32,
+            )
+        {
+            return Err(invalid("measured BRDFs must be 90x90x180 tables"));
+        }
+        let data = &bytes[12..];
+        if data.len() != VALUE_COUNT * 3 * 8 {
+            return Err(invalid("measured BRDF of the wrong length"));
+        }
+
+        let mut values = vec![0.0; VALUE_COUNT * 3];
+        for (channel, scale) in SCALES.iter().enumerate() {
+            let table = &data[channel * VALUE_COUNT * 8..(channel + 1) * VALUE_COUNT * 8];
+            for (index, bytes) in table.chunks_exact(8).enumerate() {
+                let mut value = [0; 8];
+                value.copy_from_slice(bytes);
+                values[index * 3 + channel] = (f64::from_le_bytes(value) * scale) as f32;
+            }
+        }
+        Ok(MeasuredBrdf::new(values))
+    }
+
+    /// BRDF of the reflectances `values`, three per entry of the MERL
+    /// tables, in their order.
+    pub fn new(values: Vec<f32>) -> Self {
+        assert_eq!(values.len(), VALUE_COUNT * 3);
+        let mut brdf = MeasuredBrdf {
+            values,
+            cdfs: Vec::new(),
+            albedo: Vec3::new(0.0, 0.0, 0.0),
+        };
+
+        // Cells of even squared sines and azimuths span the same projected
+        // solid angle, so their weight is the reflectance alone.
+        let cell_area = consts::PI / (SAMPLING_SINE * SAMPLING_AZIMUTH) as Float;
+        for index in 0..SAMPLING_OUTGOING {
+            let cos = sampling_cosine(index);
+            let wo = Vec3::new((1.0 - cos * cos).sqrt(), 0.0, cos);
+            let mut total = 0.0;
+            let mut cdf = Vec::with_capacity(SAMPLING_SINE * SAMPLING_AZIMUTH);
+            for row in 0..SAMPLING_SINE {
+                for column in 0..SAMPLING_AZIMUTH {
+                    let wi = cell_direction(row as Float + 0.5, column as Float + 0.5);
+                    let value = brdf.reflectance(wo, wi);
+                    total += (value.x + value.y + value.z) / 3.0;
+                    cdf.push(total);
+                    if index == 0 {
+                        brdf.albedo += value * cell_area;
+                    }
+                }
+            }
+            brdf.cdfs.push(cdf);
+        }
+        brdf
+    }
+
+    /// Share of the light arriving head on that is reflected, about.
+    pub fn albedo(&self) -> Vec3 {
+        self.albedo
+    }
+
+    /// BRDF from `wi` to `wo`, in the shading frame.
+    pub fn reflectance(&self, wo: Vec3, wi: Vec3) -> Vec3 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let half = match half_vector(wo, wi) {
+            Some(half) => half,
+            None => return Vec3::new(0.0, 0.0, 0.0),
+        };
+        let (theta_half, phi_half) = spherical_angles(half);
+
+        // `wi` seen from the half vector, as if it were the normal.
+        let (sin_phi, cos_phi) = (-phi_half).sin_cos();
+        let wi = wi.unit();
+        let rotated = Vec3::new(
+            wi.x * cos_phi - wi.y * sin_phi,
+            wi.x * sin_phi + wi.y * cos_phi,
+            wi.z,
+        );
+        let (sin_theta, cos_theta) = (-theta_half).sin_cos();
+        let diff = Vec3::new(
+            rotated.x * cos_theta + rotated.z * sin_theta,
+            rotated.y,
+            -rotated.x * sin_theta + rotated.z * cos_theta,
+        );
+        let (theta_diff, phi_diff) = spherical_angles(diff);
+
+        // Half angles are stored more densely near the normal.
+        let half_index = (theta_half / consts::FRAC_PI_2).max(0.0).sqrt() * THETA_HALF_RES as Float;
+        let diff_index = theta_diff / consts::FRAC_PI_2 * THETA_DIFF_RES as Float;
+        let phi_diff = phi_diff % consts::PI;
+        let phi_index = phi_diff / consts::PI * PHI_DIFF_RES as Float;
+        let index = |value: Float, resolution: usize| (value as usize).min(resolution - 1);
+        let entry = index(phi_index, PHI_DIFF_RES)
+            + index(diff_index, THETA_DIFF_RES) * PHI_DIFF_RES
+            + index(half_index, THETA_HALF_RES) * PHI_DIFF_RES * THETA_DIFF_RES;
+
+        let value = &self.values[entry * 3..entry * 3 + 3];
+        Vec3::new(
+            value[0].max(0.0) as Float,
+            value[1].max(0.0) as Float,
+            value[2].max(0.0) as Float,
+        )
+    }
+
+    /// Sampling table for `wo`, and the angle turning its directions around
+    /// the normal to face `wo`.
+    fn sampling_table(&self, wo: Vec3) -> (&[Float], Float) {
+        let index = ((1.0 - wo.z) * SAMPLING_OUTGOING as Float) as usize;
+        let cdf = &self.cdfs[index.min(SAMPLING_OUTGOING - 1)];
+        (cdf, wo.y.atan2(wo.x))
+    }
+
+    /// BRDF times the cosine of `wi`, and the density with which `sample`
+    /// draws `wi`, in the shading frame.
+    pub fn eval(&self, wo: Vec3, wi: Vec3) -> (Vec3, Float) {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return (Vec3::new(0.0, 0.0, 0.0), 0.0);
+        }
+        let wi = wi.unit();
+        let value = self.reflectance(wo, wi) * wi.z;
+
+        let (cdf, turn) = self.sampling_table(wo);
+        let total = cdf[cdf.len() - 1];
+        let cosine_pdf = wi.z / consts::PI;
+        if total <= 0.0 {
+            return (value, cosine_pdf);
+        }
+        let phi = (wi.y.atan2(wi.x) - turn).rem_euclid(2.0 * consts::PI);
+        let row = ((1.0 - wi.z * wi.z) * SAMPLING_SINE as Float) as usize;
+        let column = (phi / (2.0 * consts::PI) * SAMPLING_AZIMUTH as Float) as usize;
+        let cell = row.min(SAMPLING_SINE - 1) * SAMPLING_AZIMUTH + column.min(SAMPLING_AZIMUTH - 1);
+        let weight = cdf[cell] - if cell > 0 { cdf[cell - 1] } else { 0.0 };
+        // Cells span a projected solid angle of pi over their count.
+        let table_pdf =
+            weight / total * (SAMPLING_SINE * SAMPLING_AZIMUTH) as Float / consts::PI * wi.z;
+        (
+            value,
+            COSINE_SHARE * cosine_pdf + (1.0 - COSINE_SHARE) * table_pdf,
+        )
+    }
+
+    /// Incoming direction for `wo`, in the shading frame, from a uniform
+    /// number `u` picking the strategy and two more.
+    pub fn sample(&self, wo: Vec3, u: Float, (u1, u2): (Float, Float)) -> Option<Vec3> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let (cdf, turn) = self.sampling_table(wo);
+        let total = cdf[cdf.len() - 1];
+        if u < COSINE_SHARE || total <= 0.0 {
+            return Some(sample_cosine_direction((u1, u2)));
+        }
+
+        // Reuses what `u` has left to pick the cell.
+        let target = (u - COSINE_SHARE) / (1.0 - COSINE_SHARE) * total;
+        let cell = cdf.partition_point(|&sum| sum <= target).min(cdf.len() - 1);
+        let (row, column) = (cell / SAMPLING_AZIMUTH, cell % SAMPLING_AZIMUTH);
+        let wi = cell_direction(row as Float + u1, column as Float + u2);
+        let (sin, cos) = turn.sin_cos();
+        Some(Vec3::new(
+            wi.x * cos - wi.y * sin,
+            wi.x * sin + wi.y * cos,
+            wi.z,
+        ))
+    }
+}
@@ -0,0 +1,1371 @@
+use crate::accelerator::AcceleratorKind;
+use crate::bvh::{Aabb, CompactAabb, NodeBounds, Precision};
+use crate::content_hash::*;
+use crate::grid::Grid;
+use crate::hitable::*;
+use crate::kd_tree::KdTree;
+use crate::material::MaterialId;
+use crate::maths::*;
+use crate::ray::Ray;
+use crate::sphere::{Sphere, UvFrame, UvProjection};
+use crate::texture::BumpMap;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Most triangles in a leaf of the hierarchy of a `Mesh`.
+const LEAF_SIZE: usize = 4;
+
+/// Positions of the corners of a mesh, and the triangles between them as
+/// indices of their corners.
+pub type Triangles = (Vec<Vec3>, Vec<[usize; 3]>);
+
+#[derive(Clone, Copy, Debug)]
+struct MeshNode<B> {
+    bounds: B,
+    /// Leaves: index of their first triangle. Inner nodes: index of their
+    /// second child, the first one following them.
+    index: u32,
+    /// Number of triangles of leaves, 0 for inner nodes.
+    count: u32,
+}
+
+/// Nodes of the hierarchy of a `Mesh`, depth first, every inner node
+/// followed by its first child.
+#[derive(Clone, Debug)]
+enum MeshNodes {
+    Full(Arc<Vec<MeshNode<Aabb>>>),
+    Compact(Arc<Vec<MeshNode<CompactAabb>>>),
+}
+
+/// Corners of the triangles of a `Mesh`.
+#[derive(Clone, Debug)]
+enum Positions {
+    Full(Arc<Vec<Vec3>>),
+    /// Rounded to the closest single precision floats.
+    Compact(Arc<Vec<[f32; 3]>>),
+}
+
+impl Positions {
+    fn len(&self) -> usize {
+        match self {
+            Positions::Full(positions) => positions.len(),
+            Positions::Compact(positions) => positions.len(),
+        }
+    }
+
+    fn get(&self, index: usize) -> Vec3 {
+        match self {
+            Positions::Full(positions) => positions[index],
+            Positions::Compact(positions) => {
+                let [x, y, z] = positions[index];
+                Vec3::new(x as Float, y as Float, z as Float)
+            }
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = Vec3> + '_ {
+        (0..self.len()).map(move |index| self.get(index))
+    }
+}
+
+/// Triangles of one material, like a scanned model or a CAD export, found by
+/// rays through a hierarchy of boxes of their own. Their front faces wind
+/// counterclockwise. They are flat shaded, unless they have normals at their
+/// positions to interpolate, see `with_normals` and `smoothed`. Texture
+/// coordinates at their positions, see `with_uvs`, lay textures and normal
+/// and bump maps over them. Without any, maps are laid flat on every
+/// triangle, one unit of texture per scene unit. Either way `surface_at`
+/// goes over the triangles one after the other along `u`.
+#[derive(Clone, Debug)]
+pub struct Mesh {
+    pub material: MaterialId,
+    positions: Positions,
+    /// In the order of the leaves of the hierarchy.
+    triangles: Arc<Vec<[usize; 3]>>,
+    nodes: MeshNodes,
+    /// Unit normals at the positions, for smooth shading.
+    normals: Option<Arc<Vec<Vec3>>>,
+    /// Texture coordinates at the positions.
+    uvs: Option<Arc<Vec<(Float, Float)>>>,
+    /// Found through instead of the hierarchy, when set.
+    accelerator: Option<TriangleAccelerator>,
+}
+
+/// Detail of a height map built into a mesh, see `Mesh::displaced`.
+#[derive(Clone, Debug)]
+pub struct Displacement {
+    pub bump_map: BumpMap,
+    /// How the height map is laid over the mesh, from its bounding sphere.
+    pub projection: UvProjection,
+    /// Times every triangle is split in four before the corners move.
+    pub levels: usize,
+}
+
+/// Structure a `Mesh` finds its triangles through instead of its hierarchy.
+#[derive(Clone, Debug)]
+enum TriangleAccelerator {
+    KdTree(Arc<KdTree>),
+    Grid(Arc<Grid>),
+}
+
+impl Mesh {
+    /// Mesh of the `triangles` between `positions`, given as indices of
+    /// their corners.
+    pub fn new(positions: Vec<Vec3>, mut triangles: Vec<[usize; 3]>, material: MaterialId) -> Self {
+        assert!(
+            triangles
+                .iter()
+                .flatten()
+                .all(|&index| index < positions.len()),
+            "triangles must index the positions"
+        );
+        let mut nodes = Vec::new();
+        if !triangles.is_empty() {
+            build(&positions, &mut triangles, 0, &mut nodes);
+        }
+        Mesh {
+            material,
+            positions: Positions::Full(Arc::new(positions)),
+            triangles: Arc::new(triangles),
+            nodes: MeshNodes::Full(Arc::new(nodes)),
+            normals: None,
+            uvs: None,
+            accelerator: None,
+        }
+    }
+
+    /// Mesh whose triangles rays find through a structure of `kind`, see
+    /// `Scene::set_accelerator_kind`.
+    pub fn with_accelerator(mut self, kind: AcceleratorKind) -> Self {
+        let boxes = || -> Vec<Aabb> {
+            (0..self.triangles.len())
+                .map(|triangle| {
+                    self.corners(triangle)
+                        .iter()
+                        .copied()
+                        .fold(Aabb::empty(), |bounds, corner| bounds.include(corner))
+                })
+                .collect()
+        };
+        self.accelerator = match kind {
+            AcceleratorKind::Bvh => None,
+            AcceleratorKind::KdTree => {
+                Some(TriangleAccelerator::KdTree(Arc::new(KdTree::new(&boxes()))))
+            }
+            AcceleratorKind::Grid => Some(TriangleAccelerator::Grid(Arc::new(Grid::new(&boxes())))),
+        };
+        self
+    }
+
+    pub fn accelerator_kind(&self) -> AcceleratorKind {
+        match self.accelerator {
+            Some(TriangleAccelerator::KdTree(_)) => AcceleratorKind::KdTree,
+            Some(TriangleAccelerator::Grid(_)) => AcceleratorKind::Grid,
+            None => AcceleratorKind::Bvh,
+        }
+    }
+
+    /// Mesh whose corners and hierarchy are stored in `precision`, see
+    /// `Scene::set_precision`. Corners in single precision are rounded to
+    /// the closest, which moves the triangles by as much: going back to full
+    /// precision keeps them there. Kd-trees and grids are rebuilt around the
+    /// rounded corners, in full precision.
+    pub fn with_precision(mut self, precision: Precision) -> Self {
+        match precision {
+            Precision::Mixed => {
+                if let Positions::Full(positions) = &self.positions {
+                    let rounded = positions
+                        .iter()
+                        .map(|position| {
+                            [to_f32(position.x), to_f32(position.y), to_f32(position.z)]
+                        })
+                        .collect();
+                    self.positions = Positions::Compact(Arc::new(rounded));
+                }
+                if let MeshNodes::Full(nodes) = &self.nodes {
+                    // Boxes rounded outwards hold the rounded corners too.
+                    let compact = nodes
+                        .iter()
+                        .map(|node| MeshNode {
+                            bounds: CompactAabb::new(&node.bounds),
+                            index: node.index,
+                            count: node.count,
+                        })
+                        .collect();
+                    self.nodes = MeshNodes::Compact(Arc::new(compact));
+                }
+            }
+            Precision::Full => {
+                if let Positions::Compact(_) = &self.positions {
+                    self.positions = Positions::Full(Arc::new(self.positions.iter().collect()));
+                }
+                if let MeshNodes::Compact(nodes) = &self.nodes {
+                    let full = nodes
+                        .iter()
+                        .map(|node| MeshNode {
+                            bounds: node.bounds.aabb(),
+                            index: node.index,
+                            count: node.count,
+                        })
+                        .collect();
+                    self.nodes = MeshNodes::Full(Arc::new(full));
+                }
+            }
+        }
+        let kind = self.accelerator_kind();
+        self.with_accelerator(kind)
+    }
+
+    pub fn precision(&self) -> Precision {
+        match self.nodes {
+            MeshNodes::Full(_) => Precision::Full,
+            MeshNodes::Compact(_) => Precision::Mixed,
+        }
+    }
+
+    /// Box, index and count of node `index` of the hierarchy.
+    fn node(&self, index: usize) -> (Aabb, usize, usize) {
+        match &self.nodes {
+            MeshNodes::Full(nodes) => {
+                let node = &nodes[index];
+                (node.bounds, node.index as usize, node.count as usize)
+            }
+            MeshNodes::Compact(nodes) => {
+                let node = &nodes[index];
+                (node.bounds.aabb(), node.index as usize, node.count as usize)
+            }
+        }
+    }
+
+    fn node_count(&self) -> usize {
+        match &self.nodes {
+            MeshNodes::Full(nodes) => nodes.len(),
+            MeshNodes::Compact(nodes) => nodes.len(),
+        }
+    }
+
+    /// Mesh shaded smoothly, the shading normals interpolated over each
+    /// triangle from `normals`, one for each position. Its triangles still
+    /// face the way they wind.
+    pub fn with_normals(mut self, normals: Vec<Vec3>) -> Self {
+        assert_eq!(
+            normals.len(),
+            self.positions.len(),
+            "meshes take a normal for each position"
+        );
+        let normals = normals
+            .into_iter()
+            .map(|normal| {
+                if normal.length_squared() > 0.0 {
+                    normal.unit()
+                } else {
+                    normal
+                }
+            })
+            .collect();
+        self.normals = Some(Arc::new(normals));
+        self
+    }
+
+    /// Mesh with the texture coordinates `uvs`, one pair for each position,
+    /// interpolated over each triangle.
+    pub fn with_uvs(mut self, uvs: Vec<(Float, Float)>) -> Self {
+        assert_eq!(
+            uvs.len(),
+            self.positions.len(),
+            "meshes take texture coordinates for each position"
+        );
+        self.uvs = Some(Arc::new(uvs));
+        self
+    }
+
+    /// Whether the mesh has texture coordinates.
+    pub fn has_uvs(&self) -> bool {
+        self.uvs.is_some()
+    }
+
+    /// Texture coordinates of `triangle` at the point of barycentric
+    /// coordinates (`u`, `v`), if the mesh has any.
+    fn uv(&self, triangle: usize, u: Float, v: Float) -> Option<(Float, Float)> {
+        let uvs = self.uvs.as_ref()?;
+        let [a, b, c] = self.triangles[triangle].map(|corner| uvs[corner]);
+        let w = 1.0 - u - v;
+        Some((a.0 * w + b.0 * u + c.0 * v, a.1 * w + b.1 * u + c.1 * v))
+    }
+
+    /// Mesh shaded smoothly with the normals of the triangles around each
+    /// position, weighted by their areas, for files without normals of their
+    /// own. Positions at the same place are smoothed together, for files
+    /// like STL where triangles don't share their corners.
+    pub fn smoothed(self) -> Self {
+        let positions: Vec<Vec3> = self.positions.iter().collect();
+        let normals = area_weighted_normals(&positions, &self.triangles);
+        self.with_normals(normals)
+    }
+
+    /// Mesh of the triangles of all of `meshes`, so scenes find them through
+    /// one hierarchy rather than one object each. It is smooth, keeping
+    /// their normals, when they all are, and keeps their texture coordinates
+    /// when they all have some.
+    pub fn merged(meshes: &[&Mesh], material: MaterialId) -> Self {
+        let mut positions = Vec::new();
+        let mut triangles = Vec::new();
+        let mut normals = Vec::new();
+        let mut uvs = Vec::new();
+        for mesh in meshes {
+            let offset = positions.len();
+            positions.extend(mesh.positions.iter());
+            let shifted = mesh
+                .triangles
+                .iter()
+                .map(|corners| corners.map(|i| i + offset));
+            triangles.extend(shifted);
+            if let Some(mesh_normals) = &mesh.normals {
+                normals.extend_from_slice(mesh_normals);
+            }
+            if let Some(mesh_uvs) = &mesh.uvs {
+                uvs.extend_from_slice(mesh_uvs);
+            }
+        }
+        let mut mesh = Mesh::new(positions, triangles, material);
+        if !meshes.is_empty() && meshes.iter().all(|mesh| mesh.is_smooth()) {
+            mesh = mesh.with_normals(normals);
+        }
+        if !meshes.is_empty() && meshes.iter().all(|mesh| mesh.has_uvs()) {
+            mesh = mesh.with_uvs(uvs);
+        }
+        mesh
+    }
+
+    /// Mesh with every triangle split in four `displacement.levels` times,
+    /// then every corner pushed out along the normal `smoothed` gives it by
+    /// the height of the bump map there, laid over the mesh from its
+    /// bounding sphere like the normal and bump maps of its material. Unlike
+    /// bump mapping, the detail shows in silhouettes and shadows. Corners at
+    /// the same place move together, so closed meshes stay closed. The mesh
+    /// stays flat shaded unless it was smooth, and keeps its texture
+    /// coordinates.
+    pub fn displaced(&self, displacement: &Displacement) -> Self {
+        let mut positions: Vec<Vec3> = self.positions.iter().collect();
+        let mut uvs = self.uvs.as_ref().map(|uvs| uvs.to_vec());
+        let mut triangles = self.triangles.to_vec();
+        for _ in 0..displacement.levels {
+            let mut midpoints = HashMap::new();
+            let mut split = Vec::with_capacity(triangles.len() * 4);
+            for &[a, b, c] in &triangles {
+                let mut midpoint = |i: usize, j: usize| {
+                    *midpoints.entry((i.min(j), i.max(j))).or_insert_with(|| {
+                        positions.push((positions[i] + positions[j]) * 0.5);
+                        if let Some(uvs) = &mut uvs {
+                            let (a, b) = (uvs[i], uvs[j]);
+                            uvs.push(((a.0 + b.0) * 0.5, (a.1 + b.1) * 0.5));
+                        }
+                        positions.len() - 1
+                    })
+                };
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                split.extend_from_slice(&[[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]]);
+            }
+            triangles = split;
+        }
+
+        let bounds = self.bounding_sphere();
+        let normals = area_weighted_normals(&positions, &triangles);
+        for (position, normal) in positions.iter_mut().zip(normals) {
+            if normal.length_squared() == 0.0 {
+                continue;
+            }
+            let normal = normal.unit();
+            let frame = bounds.projected_uv_frame(*position, normal, displacement.projection);
+            let (u, v) = frame.uv;
+            *position += normal * displacement.bump_map.height_at(u, v);
+        }
+
+        let mut mesh = Mesh::new(positions, triangles, self.material);
+        if self.is_smooth() {
+            mesh = mesh.smoothed();
+        }
+        if let Some(uvs) = uvs {
+            mesh = mesh.with_uvs(uvs);
+        }
+        mesh.with_precision(self.precision())
+            .with_accelerator(self.accelerator_kind())
+    }
+
+    /// Whether the mesh is shaded smoothly.
+    pub fn is_smooth(&self) -> bool {
+        self.normals.is_some()
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    fn corners(&self, triangle: usize) -> [Vec3; 3] {
+        let [a, b, c] = self.triangles[triangle];
+        [
+            self.positions.get(a),
+            self.positions.get(b),
+            self.positions.get(c),
+        ]
+    }
+
+    /// Where `ray` crosses `triangle`, like `hit_triangle`. Triangles in
+    /// single precision get the watertight test, see
+    /// `hit_triangle_watertight`.
+    fn hit_corners(
+        &self,
+        triangle: usize,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+    ) -> Option<(Float, Float, Float)> {
+        let corners = self.corners(triangle);
+        match self.positions {
+            Positions::Full(_) => hit_triangle(corners, ray, t_min, t_max),
+            Positions::Compact(_) => hit_triangle_watertight(corners, ray, t_min, t_max),
+        }
+    }
+
+    /// Triangle `point`, on the surface, lies on.
+    fn triangle_at(&self, point: Vec3) -> Option<usize> {
+        // Points are a little off their triangle, by rounding errors.
+        let count = self.node_count();
+        let tolerance = if count > 0 {
+            let root = self.node(0).0;
+            1e-4 * (root.max - root.min).length()
+        } else {
+            0.0
+        };
+        let mut closest = None;
+        let mut closest_distance = Float::INFINITY;
+        let mut stack = Vec::with_capacity(64);
+        if count > 0 {
+            stack.push(0);
+        }
+        while let Some(index) = stack.pop() {
+            let (bounds, node_index, node_count) = self.node(index);
+            let (min, max) = (bounds.min, bounds.max);
+            let inside = point.x >= min.x - tolerance
+                && point.y >= min.y - tolerance
+                && point.z >= min.z - tolerance
+                && point.x <= max.x + tolerance
+                && point.y <= max.y + tolerance
+                && point.z <= max.z + tolerance;
+            if !inside {
+                continue;
+            }
+            if node_count == 0 {
+                stack.push(node_index);
+                stack.push(index + 1);
+                continue;
+            }
+            for triangle in node_index..node_index + node_count {
+                let distance = (closest_point(self.corners(triangle), point) - point).length();
+                if distance < closest_distance {
+                    closest_distance = distance;
+                    closest = Some(triangle);
+                }
+            }
+        }
+        closest
+    }
+
+    /// Distance from `point` to the closest triangle, infinite for meshes
+    /// without any.
+    pub fn distance_to(&self, point: Vec3) -> Float {
+        match self.triangle_at(point) {
+            Some(triangle) => (closest_point(self.corners(triangle), point) - point).length(),
+            None => Float::INFINITY,
+        }
+    }
+
+    /// Shading normal of `triangle` at the point of barycentric coordinates
+    /// (`u`, `v`), the weights of its second and third corners: its unit
+    /// normal, interpolated on smooth meshes, on the side of its front face.
+    fn shading_normal(&self, triangle: usize, u: Float, v: Float) -> Vec3 {
+        let face = face_normal(self.corners(triangle));
+        let normals = match &self.normals {
+            Some(normals) => normals,
+            None => return face,
+        };
+        let [a, b, c] = self.triangles[triangle];
+        let normal = normals[a] * (1.0 - u - v) + normals[b] * u + normals[c] * v;
+        if normal.length_squared() == 0.0 {
+            return face;
+        }
+        let normal = normal.unit();
+        // Normals disagreeing with the winding are turned around, so the
+        // side the ray hit decides which side is shaded.
+        if normal.dot(face) < 0.0 {
+            -normal
+        } else {
+            normal
+        }
+    }
+
+    /// Unit normal at `point`, on the surface, on the side of the front
+    /// face of its triangle, interpolated on smooth meshes.
+    pub fn normal_at(&self, point: Vec3) -> Vec3 {
+        match self.triangle_at(point) {
+            Some(triangle) => {
+                let (u, v) = barycentric(self.corners(triangle), point);
+                self.shading_normal(triangle, u, v)
+            }
+            None => Vec3::new(0.0, 1.0, 0.0),
+        }
+    }
+
+    /// Texture coordinates of `point`, on the surface, with the frame normal
+    /// and bump maps bend normals in: those of the mesh if it has some, or
+    /// laid along the first edge of its triangle.
+    pub fn uv_frame(&self, point: Vec3) -> UvFrame {
+        let (corners, normal) = match self.triangle_at(point) {
+            Some(triangle) => {
+                let corners = self.corners(triangle);
+                let (u, v) = barycentric(corners, point);
+                let normal = self.shading_normal(triangle, u, v);
+                if let Some(frame) = self.mapped_uv_frame(triangle, (u, v), normal) {
+                    return frame;
+                }
+                (corners, normal)
+            }
+            None => {
+                let corners = [point, point + Vec3::new(1.0, 0.0, 0.0), point];
+                (corners, face_normal(corners))
+            }
+        };
+        // Perpendicular to the shading normal, which smooth meshes bend.
+        let edge = corners[1] - corners[0];
+        let tangent = (edge - normal * edge.dot(normal)).unit();
+        let bitangent = normal.cross(tangent);
+        UvFrame {
+            uv: (point.dot(tangent), point.dot(bitangent)),
+            tangents: (tangent, bitangent),
+            lengths: (1.0, 1.0),
+        }
+    }
+
+    /// Frame of the texture coordinates of the mesh on `triangle`, at the
+    /// point of barycentric coordinates `barycentric`, with the tangents
+    /// perpendicular to `normal`. `None` without texture coordinates, or
+    /// when they don't change over the triangle.
+    fn mapped_uv_frame(
+        &self,
+        triangle: usize,
+        (u, v): (Float, Float),
+        normal: Vec3,
+    ) -> Option<UvFrame> {
+        let uvs = self.uvs.as_ref()?;
+        let [a, b, c] = self.triangles[triangle].map(|corner| uvs[corner]);
+        let [p0, p1, p2] = self.corners(triangle);
+        let (e1, e2) = (p1 - p0, p2 - p0);
+        let (du1, dv1, du2, dv2) = (b.0 - a.0, b.1 - a.1, c.0 - a.0, c.1 - a.1);
+        let determinant = du1 * dv2 - du2 * dv1;
+        if determinant.abs() <= Float::EPSILON {
+            return None;
+        }
+        // Derivatives of the position along `u` and `v`.
+        let dpdu = (e1 * dv2 - e2 * dv1) / determinant;
+        let dpdv = (e2 * du1 - e1 * du2) / determinant;
+        let tangent = dpdu - normal * dpdu.dot(normal);
+        if tangent.length_squared() == 0.0 {
+            return None;
+        }
+        let tangent = tangent.unit();
+        // Mirrored coordinates turn the bitangent around.
+        let bitangent = normal.cross(tangent);
+        let bitangent = if bitangent.dot(dpdv) < 0.0 {
+            -bitangent
+        } else {
+            bitangent
+        };
+        Some(UvFrame {
+            uv: self.uv(triangle, u, v)?,
+            tangents: (tangent, bitangent),
+            lengths: (dpdu.length(), dpdv.length()),
+        })
+    }
+
+    /// Point and normal at texture coordinates (`u`, `v`), the triangles
+    /// following each other along `u`, each covered uniformly.
+    pub fn surface_at(&self, u: Float, v: Float) -> (Vec3, Vec3) {
+        if self.triangles.is_empty() {
+            return (Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        }
+        let count = self.triangles.len();
+        let position = clamp(u, 0.0, 1.0) * count as Float;
+        let triangle = (position as usize).min(count - 1);
+        let [a, b, c] = self.corners(triangle);
+        let root = (position - triangle as Float).sqrt();
+        let v = clamp(v, 0.0, 1.0);
+        let point = a * (1.0 - root) + b * (root * (1.0 - v)) + c * (root * v);
+        (
+            point,
+            self.shading_normal(triangle, root * (1.0 - v), root * v),
+        )
+    }
+
+    /// Closest hit of `ray` through the hierarchy `nodes`, as the ray
+    /// parameter, the triangle and the barycentric coordinates of the hit.
+    fn hit_nodes<B: NodeBounds>(
+        &self,
+        nodes: &[MeshNode<B>],
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+    ) -> Option<(Float, usize, Float, Float)> {
+        let mut closest = None;
+        let mut closest_t = t_max;
+        let node_ray = B::ray(ray);
+        let mut stack = Vec::with_capacity(64);
+        if !nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(index) = stack.pop() {
+            let node = &nodes[index];
+            if !node.bounds.hit(&node_ray, t_min, closest_t) {
+                continue;
+            }
+            let (node_index, node_count) = (node.index as usize, node.count as usize);
+            if node_count == 0 {
+                stack.push(node_index);
+                stack.push(index + 1);
+                continue;
+            }
+            for triangle in node_index..node_index + node_count {
+                if let Some((t, u, v)) = self.hit_corners(triangle, ray, t_min, closest_t) {
+                    closest_t = t;
+                    closest = Some((t, triangle, u, v));
+                }
+            }
+        }
+        closest
+    }
+
+    pub fn bounding_sphere(&self) -> Sphere {
+        match self.node_count() {
+            0 => Sphere::new(Vec3::new(0.0, 0.0, 0.0), 0.0, self.material),
+            _ => {
+                let root = self.node(0).0;
+                let (min, max) = (root.min, root.max);
+                Sphere::new((min + max) / 2.0, (max - min).length() / 2.0, self.material)
+            }
+        }
+    }
+
+    /// Copy of the mesh moved by `transform`.
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        let positions = self
+            .positions
+            .iter()
+            .map(|position| transform.transform_point(position))
+            .collect();
+        let mut mesh = Mesh::new(positions, self.triangles.to_vec(), self.material);
+        if let Some(normals) = &self.normals {
+            let normals = normals
+                .iter()
+                .map(|&normal| transform.transform_normal(normal))
+                .collect();
+            mesh = mesh.with_normals(normals);
+        }
+        mesh.uvs = self.uvs.clone();
+        if transform.determinant3() < 0.0 {
+            // Mirroring turns the winding of the triangles around.
+            let flipped = mesh.triangles.iter().map(|&[a, b, c]| [a, c, b]).collect();
+            mesh.triangles = Arc::new(flipped);
+        }
+        mesh.with_precision(self.precision())
+            .with_accelerator(self.accelerator_kind())
+    }
+}
+
+/// Normals of the triangles around each of `positions`, weighted by their
+/// areas, those at the same place summed together.
+fn area_weighted_normals(positions: &[Vec3], triangles: &[[usize; 3]]) -> Vec<Vec3> {
+    let key = |position: Vec3| {
+        // Adding zero turns -0 into 0.
+        let (x, y, z) = (position.x + 0.0, position.y + 0.0, position.z + 0.0);
+        (x.to_bits(), y.to_bits(), z.to_bits())
+    };
+    let mut sums = HashMap::new();
+    for &[a, b, c] in triangles {
+        let (pa, pb, pc) = (positions[a], positions[b], positions[c]);
+        // Twice the area, along the normal.
+        let normal = (pb - pa).cross(pc - pa);
+        for position in [pa, pb, pc] {
+            *sums
+                .entry(key(position))
+                .or_insert(Vec3::new(0.0, 0.0, 0.0)) += normal;
+        }
+    }
+    positions
+        .iter()
+        .map(|&position| {
+            sums.get(&key(position))
+                .copied()
+                .unwrap_or(Vec3::new(0.0, 0.0, 0.0))
+        })
+        .collect()
+}
+
+/// Unit normal of the front face of a triangle.
+fn face_normal([a, b, c]: [Vec3; 3]) -> Vec3 {
+    (b - a).cross(c - a).unit()
+}
+
+/// Barycentric coordinates of `point`, in the plane of the triangle: the
+/// weights of its second and third corners.
+fn barycentric([a, b, c]: [Vec3; 3], point: Vec3) -> (Float, Float) {
+    let (ab, ac, ap) = (b - a, c - a, point - a);
+    let (d00, d01, d11) = (ab.dot(ab), ab.dot(ac), ac.dot(ac));
+    let (d20, d21) = (ap.dot(ab), ap.dot(ac));
+    let denominator = d00 * d11 - d01 * d01;
+    if denominator == 0.0 {
+        return (0.0, 0.0);
+    }
+    (
+        (d11 * d20 - d01 * d21) / denominator,
+        (d00 * d21 - d01 * d20) / denominator,
+    )
+}
+
+/// Point of the triangle closest to `point`, after Ericson's "Real-Time
+/// Collision Detection".
+fn closest_point([a, b, c]: [Vec3; 3], point: Vec3) -> Vec3 {
+    let (ab, ac, ap) = (b - a, c - a, point - a);
+    let (d1, d2) = (ab.dot(ap), ac.dot(ap));
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+    let bp = point - b;
+    let (d3, d4) = (ab.dot(bp), ac.dot(bp));
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+    let cp = point - c;
+    let (d5, d6) = (ab.dot(cp), ac.dot(cp));
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let denominator = 1.0 / (va + vb + vc);
+    a + ab * (vb * denominator) + ac * (vc * denominator)
+}
+
+/// Ray parameter where `ray` crosses the triangle, and the barycentric
+/// coordinates of the crossing, by Möller and Trumbore.
+fn hit_triangle(
+    [a, b, c]: [Vec3; 3],
+    ray: &Ray,
+    t_min: Float,
+    t_max: Float,
+) -> Option<(Float, Float, Float)> {
+    let (ab, ac) = (b - a, c - a);
+    let p = ray.dir.cross(ac);
+    let determinant = ab.dot(p);
+    if determinant == 0.0 {
+        return None;
+    }
+    let inverse = 1.0 / determinant;
+    let offset = ray.origin - a;
+    let u = offset.dot(p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = offset.cross(ab);
+    let v = ray.dir.dot(q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = ac.dot(q) * inverse;
+    if t > t_min && t < t_max {
+        Some((t, u, v))
+    } else {
+        None
+    }
+}
+
+/// Corners of a triangle relative to the origin of `ray`, their axes
+/// permuted so the ray runs mostly along z and sheared so it runs exactly
+/// along it, after Woop, Benthin and Wald (2013). The ray then crosses the
+/// triangle at the origin of the x and y plane, at the distance along z.
+fn sheared_corners(corners: [Vec3; 3], ray: &Ray) -> [[Float; 3]; 3] {
+    let direction = [ray.dir.x, ray.dir.y, ray.dir.z];
+    let z = (0..3)
+        .max_by(|&i, &j| direction[i].abs().total_cmp(&direction[j].abs()))
+        .unwrap();
+    let (x, y) = ((z + 1) % 3, (z + 2) % 3);
+    let shear = [
+        direction[x] / direction[z],
+        direction[y] / direction[z],
+        1.0 / direction[z],
+    ];
+    corners.map(|corner| {
+        let offset = corner - ray.origin;
+        let offset = [offset.x, offset.y, offset.z];
+        [
+            offset[x] - shear[0] * offset[z],
+            offset[y] - shear[1] * offset[z],
+            shear[2] * offset[z],
+        ]
+    })
+}
+
+/// Twice the signed areas of the triangles the origin makes with the edges
+/// bc, ca and ab of the triangle `a`, `b`, `c` of the plane, the barycentric
+/// weights of the origin.
+fn edge_functions<T>(a: [T; 2], b: [T; 2], c: [T; 2]) -> [T; 3]
+where
+    T: Copy + std::ops::Mul<Output = T> + std::ops::Sub<Output = T>,
+{
+    [
+        c[0] * b[1] - c[1] * b[0],
+        a[0] * c[1] - a[1] * c[0],
+        b[0] * a[1] - b[1] * a[0],
+    ]
+}
+
+/// `hit_triangle` with the watertight test of Woop, Benthin and Wald (2013),
+/// for triangles stored in single precision: which side of each edge the
+/// ray passes is decided in single precision from the sheared corners, the
+/// same for the two triangles sharing the edge, and again in double
+/// precision when the ray seems to go through it, so rays never slip
+/// between neighbouring triangles. The hit is then computed in `Float`.
+fn hit_triangle_watertight(
+    corners: [Vec3; 3],
+    ray: &Ray,
+    t_min: Float,
+    t_max: Float,
+) -> Option<(Float, Float, Float)> {
+    let [a, b, c] = sheared_corners(corners, ray);
+    let single = |corner: [Float; 3]| [to_f32(corner[0]), to_f32(corner[1])];
+    let mut weights = edge_functions(single(a), single(b), single(c)).map(f64::from);
+    if weights.contains(&0.0) {
+        let double = |corner: [Float; 3]| single(corner).map(f64::from);
+        weights = edge_functions(double(a), double(b), double(c));
+    }
+    let inside =
+        weights.iter().all(|&weight| weight >= 0.0) || weights.iter().all(|&weight| weight <= 0.0);
+    if !inside || weights == [0.0; 3] {
+        return None;
+    }
+
+    let [u, v, w] = edge_functions([a[0], a[1]], [b[0], b[1]], [c[0], c[1]]);
+    let determinant = u + v + w;
+    if determinant == 0.0 {
+        return None;
+    }
+    let t = (u * a[2] + v * b[2] + w * c[2]) / determinant;
+    if t > t_min && t < t_max {
+        Some((t, v / determinant, w / determinant))
+    } else {
+        None
+    }
+}
+
+/// Adds the subtree of `triangles`, the first of which is at `offset`,
+/// sorting them in the order of its leaves.
+fn build(
+    positions: &[Vec3],
+    triangles: &mut [[usize; 3]],
+    offset: usize,
+    nodes: &mut Vec<MeshNode<Aabb>>,
+) {
+    let bounds = triangles
+        .iter()
+        .flatten()
+        .fold(Aabb::empty(), |bounds, &index| {
+            let position = positions[index];
+            bounds.merge(Aabb {
+                min: position,
+                max: position,
+            })
+        });
+    let node = nodes.len();
+    nodes.push(MeshNode {
+        bounds,
+        index: offset as u32,
+        count: triangles.len() as u32,
+    });
+    if triangles.len() <= LEAF_SIZE {
+        return;
+    }
+
+    // Halves the triangles at their median along the widest axis.
+    let center = |[a, b, c]: &[usize; 3]| positions[*a] + positions[*b] + positions[*c];
+    let extent = bounds.max - bounds.min;
+    let axis = |point: Vec3| {
+        if extent.x >= extent.y && extent.x >= extent.z {
+            point.x
+        } else if extent.y >= extent.z {
+            point.y
+        } else {
+            point.z
+        }
+    };
+    let middle = triangles.len() / 2;
+    triangles.select_nth_unstable_by(middle, |a, b| axis(center(a)).total_cmp(&axis(center(b))));
+
+    let (first, second) = triangles.split_at_mut(middle);
+    nodes[node].count = 0;
+    build(positions, first, offset, nodes);
+    nodes[node].index = nodes.len() as u32;
+    build(positions, second, offset + middle, nodes);
+}
+
+impl Hitable for Mesh {
+    fn scale(&mut self, factor: Float) {
+        let positions = self
+            .positions
+            .iter()
+            .map(|position| position * factor)
+            .collect();
+        let (normals, uvs, kind) = (
+            self.normals.clone(),
+            self.uvs.clone(),
+            self.accelerator_kind(),
+        );
+        *self = Mesh::new(positions, self.triangles.to_vec(), self.material)
+            .with_precision(self.precision())
+            .with_accelerator(kind);
+        self.normals = normals;
+        self.uvs = uvs;
+    }
+
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let mut closest = None;
+        let mut closest_t = t_max;
+        if let Some(accelerator) = &self.accelerator {
+            let mut uv = (0.0, 0.0);
+            let mut test = |triangle, closest_t| {
+                let (t, u, v) = self.hit_corners(triangle, ray, t_min, closest_t)?;
+                uv = (u, v);
+                Some(t)
+            };
+            let hit = match accelerator {
+                TriangleAccelerator::KdTree(kd_tree) => {
+                    kd_tree.hit_with(ray, t_min, t_max, &mut test)
+                }
+                TriangleAccelerator::Grid(grid) => grid.hit_with(ray, t_min, t_max, &mut test),
+            };
+            if let Some((triangle, t)) = hit {
+                closest_t = t;
+                closest = Some((triangle, uv.0, uv.1));
+            }
+        }
+        if self.accelerator.is_none() {
+            let hit = match &self.nodes {
+                MeshNodes::Full(nodes) => self.hit_nodes(nodes, ray, t_min, closest_t),
+                MeshNodes::Compact(nodes) => self.hit_nodes(nodes, ray, t_min, closest_t),
+            };
+            if let Some((t, triangle, u, v)) = hit {
+                closest_t = t;
+                closest = Some((triangle, u, v));
+            }
+        }
+
+        let (triangle, u, v) = closest?;
+        let normal = face_normal(self.corners(triangle));
+        // The face decides which side was hit, smooth meshes then shading
+        // with the interpolated normal.
+        let mut record = HitRecord::new(ray, ray.at(closest_t), normal, closest_t, self.material);
+        if self.normals.is_some() {
+            let normal = self.shading_normal(triangle, u, v);
+            record.normal = if record.front_face { normal } else { -normal };
+        }
+        record.uv = self.uv(triangle, u, v);
+        Some(record)
+    }
+}
+
+/// Triangles are hashed with their placement, materials being left out
+/// like for shapes.
+impl ContentHash for Mesh {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        hasher.write_u64(self.positions.len() as u64);
+        for position in self.positions.iter() {
+            position.content_hash(hasher);
+        }
+        hasher.write_u64(self.triangles.len() as u64);
+        for &index in self.triangles.iter().flatten() {
+            hasher.write_u64(index as u64);
+        }
+        if let Some(normals) = &self.normals {
+            for normal in normals.iter() {
+                normal.content_hash(hasher);
+            }
+        }
+        if let Some(uvs) = &self.uvs {
+            for &(u, v) in uvs.iter() {
+                hasher.write_float(u);
+                hasher.write_float(v);
+            }
+        }
+    }
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Reads the triangles of a `.ply`, `.stl` or `.obj` file, following its
+/// extension.
+pub fn load_mesh(name: &str) -> std::io::Result<Triangles> {
+    let mut bytes = Vec::new();
+    File::open(name)?.read_to_end(&mut bytes)?;
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("");
+    decode_mesh(&bytes, extension)
+}
+
+/// Decodes the content of a mesh file with the extension `extension`, as
+/// `load_mesh`.
+pub fn decode_mesh(bytes: &[u8], extension: &str) -> std::io::Result<Triangles> {
+    match extension.to_ascii_lowercase().as_str() {
+        "ply" => parse_ply(bytes),
+        "stl" => parse_stl(bytes),
+        "obj" => {
+            let text = std::str::from_utf8(bytes).map_err(|_| invalid("OBJ files are text"))?;
+            parse_obj(text)
+        }
+        _ => Err(invalid("meshes are .ply, .stl or .obj files")),
+    }
+}
+
+/// Triangles of the polygon of `corners`, fanning out of its first corner.
+fn fan(corners: &[usize], triangles: &mut Vec<[usize; 3]>) {
+    for pair in corners.windows(2).skip(1) {
+        triangles.push([corners[0], pair[0], pair[1]]);
+    }
+}
+
+/// Reads the vertices and faces of a Wavefront OBJ file, the polygons
+/// split into triangles. Other statements are left out.
+pub fn parse_obj(text: &str) -> std::io::Result<Triangles> {
+    let mut positions = Vec::new();
+    let mut triangles = Vec::new();
+    for line in text.lines() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("v") => {
+                let mut coordinate = || -> std::io::Result<Float> {
+                    words
+                        .next()
+                        .and_then(|word| word.parse().ok())
+                        .ok_or_else(|| invalid("OBJ vertices take three numbers"))
+                };
+                positions.push(Vec3::new(coordinate()?, coordinate()?, coordinate()?));
+            }
+            Some("f") => {
+                // Indices start at 1, negative ones count back from the last
+                // vertex.
+                let corners = words
+                    .map(|word| {
+                        let index: i64 = word
+                            .split('/')
+                            .next()
+                            .and_then(|index| index.parse().ok())
+                            .ok_or_else(|| invalid("OBJ faces take vertex indices"))?;
+                        let index = if index < 0 {
+                            positions.len() as i64 + index
+                        } else {
+                            index - 1
+                        };
+                        if index < 0 || index as usize >= positions.len() {
+                            return Err(invalid("OBJ face of a missing vertex"));
+                        }
+                        Ok(index as usize)
+                    })
+                    .collect::<std::io::Result<Vec<usize>>>()?;
+                fan(&corners, &mut triangles);
+            }
+            _ => {}
+        }
+    }
+    Ok((positions, triangles))
+}
+
+/// Reads an STL file, binary or text, every triangle having corners of its
+/// own. Binary files have a header of 80 bytes, a 32-bit count of triangles,
+/// then every triangle as 12 32-bit floats, its normal then its corners, and
+/// 2 more bytes, all little endian. Their normals, which follow the winding,
+/// are left out.
+pub fn parse_stl(bytes: &[u8]) -> std::io::Result<Triangles> {
+    let binary_count = bytes
+        .get(80..84)
+        .map(|count| u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize);
+    // Some binary files start with "solid" too, their size tells them apart.
+    let positions: Vec<Vec3> = match binary_count {
+        Some(count) if bytes.len() == 84 + 50 * count => bytes[84..]
+            .chunks_exact(50)
+            .flat_map(|triangle| {
+                let float = move |at: usize| {
+                    let word = [
+                        triangle[at],
+                        triangle[at + 1],
+                        triangle[at + 2],
+                        triangle[at + 3],
+                    ];
+                    f32::from_le_bytes(word) as Float
+                };
+                (0..3).map(move |corner| {
+                    let at = 12 + corner * 12;
+                    Vec3::new(float(at), float(at + 4), float(at + 8))
+                })
+            })
+            .collect(),
+        _ => {
+            let text = std::str::from_utf8(bytes).map_err(|_| invalid("not an STL file"))?;
+            if !text.trim_start().starts_with("solid") {
+                return Err(invalid("not an STL file"));
+            }
+            let mut positions = Vec::new();
+            for line in text.lines() {
+                let mut words = line.split_whitespace();
+                if words.next() != Some("vertex") {
+                    continue;
+                }
+                let coordinates: Vec<Float> = words.filter_map(|word| word.parse().ok()).collect();
+                if coordinates.len() != 3 {
+                    return Err(invalid("STL vertices take three numbers"));
+                }
+                positions.push(Vec3::new(coordinates[0], coordinates[1], coordinates[2]));
+            }
+            if positions.len() % 3 != 0 {
+                return Err(invalid("STL facets have three vertices"));
+            }
+            positions
+        }
+    };
+    let triangles = (0..positions.len() / 3)
+        .map(|triangle| [3 * triangle, 3 * triangle + 1, 3 * triangle + 2])
+        .collect();
+    Ok((positions, triangles))
+}
+
+/// Type of a value of a PLY file.
+#[derive(Clone, Copy)]
+enum PlyType {
+    Int8,
+    Uint8,
+    Int16,
+    Uint16,
+    Int32,
+    Uint32,
+    Float32,
+    Float64,
+}
+
+impl PlyType {
+    fn parse(name: &str) -> std::io::Result<Self> {
+        Ok(match name {
+            "char" | "int8" => PlyType::Int8,
+            "uchar" | "uint8" => PlyType::Uint8,
+            "short" | "int16" => PlyType::Int16,
+            "ushort" | "uint16" => PlyType::Uint16,
+            "int" | "int32" => PlyType::Int32,
+            "uint" | "uint32" => PlyType::Uint32,
+            "float" | "float32" => PlyType::Float32,
+            "double" | "float64" => PlyType::Float64,
+            _ => return Err(invalid("unknown PLY property type")),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PlyType::Int8 | PlyType::Uint8 => 1,
+            PlyType::Int16 | PlyType::Uint16 => 2,
+            PlyType::Int32 | PlyType::Uint32 | PlyType::Float32 => 4,
+            PlyType::Float64 => 8,
+        }
+    }
+}
+
+/// Property of the elements of a PLY file, a list when it has a type for
+/// its length.
+struct PlyProperty {
+    name: String,
+    length: Option<PlyType>,
+    value: PlyType,
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+/// Values of the body of a PLY file, one after the other.
+enum PlyValues<'a> {
+    Text(std::str::SplitAsciiWhitespace<'a>),
+    Binary { bytes: &'a [u8], big_endian: bool },
+}
+
+impl PlyValues<'_> {
+    fn next(&mut self, kind: PlyType) -> std::io::Result<Float> {
+        match self {
+            PlyValues::Text(words) => words
+                .next()
+                .and_then(|word| word.parse().ok())
+                .ok_or_else(|| invalid("truncated PLY file")),
+            PlyValues::Binary { bytes, big_endian } => {
+                let size = kind.size();
+                if bytes.len() < size {
+                    return Err(invalid("truncated PLY file"));
+                }
+                let mut word = [0; 8];
+                word[..size].copy_from_slice(&bytes[..size]);
+                if *big_endian {
+                    word[..size].reverse();
+                }
+                *bytes = &bytes[size..];
+                let [b0, b1, b2, b3, ..] = word;
+                Ok(match kind {
+                    PlyType::Int8 => b0 as i8 as Float,
+                    PlyType::Uint8 => b0 as Float,
+                    PlyType::Int16 => i16::from_le_bytes([b0, b1]) as Float,
+                    PlyType::Uint16 => u16::from_le_bytes([b0, b1]) as Float,
+                    PlyType::Int32 => i32::from_le_bytes([b0, b1, b2, b3]) as Float,
+                    PlyType::Uint32 => u32::from_le_bytes([b0, b1, b2, b3]) as Float,
+                    PlyType::Float32 => f32::from_le_bytes([b0, b1, b2, b3]) as Float,
+                    PlyType::Float64 => f64::from_le_bytes(word) as Float,
+                })
+            }
+        }
+    }
+}
+
+/// Reads the vertices and faces of a PLY file, text or binary of either
+/// endianness, the polygons split into triangles. Vertices take their `x`,
+/// `y` and `z` properties, faces their `vertex_indices` or `vertex_index`
+/// list, and other elements and properties are left out.
+pub fn parse_ply(bytes: &[u8]) -> std::io::Result<Triangles> {
+    const END: &[u8] = b"end_header";
+    let end = bytes
+        .windows(END.len())
+        .position(|window| window == END)
+        .ok_or_else(|| invalid("PLY file without a header"))?;
+    let mut body = end + END.len();
+    while body < bytes.len() && bytes[body] != b'\n' {
+        body += 1;
+    }
+    let header = std::str::from_utf8(&bytes[..end]).map_err(|_| invalid("not a PLY file"))?;
+    let mut lines = header.lines();
+    if lines.next().map(str::trim) != Some("ply") {
+        return Err(invalid("not a PLY file"));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = Vec::new();
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", name, _] => format = Some(name.to_string()),
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid("bad PLY element count"))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", length, value, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| invalid("PLY property outside of elements"))?;
+                element.properties.push(PlyProperty {
+                    name: name.to_string(),
+                    length: Some(PlyType::parse(length)?),
+                    value: PlyType::parse(value)?,
+                });
+            }
+            ["property", value, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| invalid("PLY property outside of elements"))?;
+                element.properties.push(PlyProperty {
+                    name: name.to_string(),
+                    length: None,
+                    value: PlyType::parse(value)?,
+                });
+            }
+            _ => {}
+        }
+    }
+    let body = bytes.get(body + 1..).unwrap_or(&[]);
+    let mut values = match format.as_deref() {
+        Some("ascii") => {
+            let text = std::str::from_utf8(body).map_err(|_| invalid("PLY text isn't text"))?;
+            PlyValues::Text(text.split_ascii_whitespace())
+        }
+        Some("binary_little_endian") => PlyValues::Binary {
+            bytes: body,
+            big_endian: false,
+        },
+        Some("binary_big_endian") => PlyValues::Binary {
+            bytes: body,
+            big_endian: true,
+        },
+        _ => return Err(invalid("unknown PLY format")),
+    };
+
+    let mut positions = Vec::new();
+    let mut triangles = Vec::new();
+    for element in &elements {
+        for _ in 0..element.count {
+            let mut position = Vec3::new(0.0, 0.0, 0.0);
+            for property in &element.properties {
+                let length = match property.length {
+                    Some(kind) => values.next(kind)? as usize,
+                    None => {
+                        let value = values.next(property.value)?;
+                        match property.name.as_str() {
+                            "x" => position.x = value,
+                            "y" => position.y = value,
+                            "z" => position.z = value,
+                            _ => {}
+                        }
+                        continue;
+                    }
+                };
+                let list = (0..length)
+                    .map(|_| values.next(property.value))
+                    .collect::<std::io::Result<Vec<Float>>>()?;
+                let is_face = element.name == "face"
+                    && (property.name == "vertex_indices" || property.name == "vertex_index");
+                if is_face {
+                    let corners: Vec<usize> = list.iter().map(|&index| index as usize).collect();
+                    fan(&corners, &mut triangles);
+                }
+            }
+            if element.name == "vertex" {
+                positions.push(position);
+            }
+        }
+    }
+    if triangles
+        .iter()
+        .flatten()
+        .any(|&index| index >= positions.len())
+    {
+        return Err(invalid("PLY face of a missing vertex"));
+    }
+    Ok((positions, triangles))
+}
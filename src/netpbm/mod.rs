@@ -1,8 +1,20 @@
 use std::fs::File;
 use std::io::prelude::*;
 
-pub fn create_ppm(name: &str, pixels: &[u8], width: u32, height: u32) -> std::io::Result<()> {
-    let header = format!("{}\n{} {}\n{}\n", "P6", width, height, 255);
+/// Writes 8-bit RGB `pixels` as a binary PPM file, with `metadata` stored as
+/// `# key value` comment lines in the header.
+pub fn create_ppm(
+    name: &str,
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    metadata: &[(String, String)],
+) -> std::io::Result<()> {
+    let mut header = String::from("P6\n");
+    for (key, value) in metadata {
+        header.push_str(&format!("# {} {}\n", key, value));
+    }
+    header.push_str(&format!("{} {}\n{}\n", width, height, 255));
 
     let mut file = File::create(name)?;
     file.write_all(header.as_bytes())?;
@@ -10,3 +22,184 @@ pub fn create_ppm(name: &str, pixels: &[u8], width: u32, height: u32) -> std::io
 
     Ok(())
 }
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Reads the next whitespace separated header token of a netpbm file.
+fn read_token(bytes: &[u8], position: &mut usize) -> std::io::Result<String> {
+    while *position < bytes.len() && bytes[*position].is_ascii_whitespace() {
+        *position += 1;
+    }
+    let start = *position;
+    while *position < bytes.len() && !bytes[*position].is_ascii_whitespace() {
+        *position += 1;
+    }
+    if start == *position {
+        return Err(invalid_data("truncated header"));
+    }
+
+    Ok(String::from_utf8_lossy(&bytes[start..*position]).into_owned())
+}
+
+/// Reads a binary PPM (P6) file as RGB bytes, rows from top to bottom, with
+/// values rescaled to the 0-255 range.
+pub fn read_ppm(name: &str) -> std::io::Result<(Vec<u8>, u32, u32)> {
+    let mut bytes = Vec::new();
+    File::open(name)?.read_to_end(&mut bytes)?;
+    decode_ppm(&bytes)
+}
+
+/// Decodes the content of a binary PPM file, as `read_ppm`.
+pub fn decode_ppm(bytes: &[u8]) -> std::io::Result<(Vec<u8>, u32, u32)> {
+    let mut position = 0;
+    if read_token(bytes, &mut position)? != "P6" {
+        return Err(invalid_data("not a binary PPM file"));
+    }
+    let mut header = [0u32; 3];
+    for value in header.iter_mut() {
+        let mut token = read_token(bytes, &mut position)?;
+        // Comments can sit between header fields.
+        while token.starts_with('#') {
+            while position < bytes.len() && bytes[position] != b'\n' {
+                position += 1;
+            }
+            token = read_token(bytes, &mut position)?;
+        }
+        *value = token.parse().map_err(|_| invalid_data("invalid header"))?;
+    }
+    let [width, height, max_value] = header;
+    if max_value == 0 || max_value > 65535 {
+        return Err(invalid_data("invalid maximum value"));
+    }
+    position += 1;
+
+    let bytes_per_value = if max_value < 256 { 1 } else { 2 };
+    let values = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(3))
+        .filter(|values| values.checked_mul(bytes_per_value).is_some())
+        .ok_or_else(|| invalid_data("image too large"))?;
+    let data = bytes
+        .get(position..)
+        .filter(|data| data.len() >= values * bytes_per_value)
+        .ok_or_else(|| invalid_data("truncated pixel data"))?;
+
+    let pixels = data
+        .chunks(bytes_per_value)
+        .take(values)
+        .map(|value| {
+            let value = value
+                .iter()
+                .fold(0u32, |sum, &byte| (sum << 8) | byte as u32);
+            (value.min(max_value) * 255 / max_value) as u8
+        })
+        .collect();
+
+    Ok((pixels, width, height))
+}
+
+/// Reads the `# key value` comment lines of the header of a binary PPM file,
+/// as written by `create_ppm`.
+pub fn read_ppm_metadata(name: &str) -> std::io::Result<Vec<(String, String)>> {
+    let mut bytes = Vec::new();
+    File::open(name)?.read_to_end(&mut bytes)?;
+
+    let mut position = 0;
+    if read_token(&bytes, &mut position)? != "P6" {
+        return Err(invalid_data("not a binary PPM file"));
+    }
+    let mut metadata = Vec::new();
+    let mut fields = 0;
+    while fields < 3 {
+        let start = position;
+        let token = read_token(&bytes, &mut position)?;
+        if !token.starts_with('#') {
+            fields += 1;
+            continue;
+        }
+
+        position = start;
+        while bytes[position] != b'#' {
+            position += 1;
+        }
+        let line_start = position + 1;
+        while position < bytes.len() && bytes[position] != b'\n' {
+            position += 1;
+        }
+        let line = String::from_utf8_lossy(&bytes[line_start..position]);
+        let mut parts = line.trim().splitn(2, ' ');
+        if let (Some(key), Some(value)) = (parts.next(), parts.next()) {
+            metadata.push((key.to_string(), value.trim().to_string()));
+        }
+    }
+
+    Ok(metadata)
+}
+
+/// Reads a PFM (portable float map) file as RGB floats, rows from top to
+/// bottom. Grayscale maps are expanded to RGB.
+pub fn read_pfm(name: &str) -> std::io::Result<(Vec<f32>, u32, u32)> {
+    let mut bytes = Vec::new();
+    File::open(name)?.read_to_end(&mut bytes)?;
+    decode_pfm(&bytes)
+}
+
+/// Decodes the content of a PFM file, as `read_pfm`.
+pub fn decode_pfm(bytes: &[u8]) -> std::io::Result<(Vec<f32>, u32, u32)> {
+    let mut position = 0;
+    let channels = match read_token(bytes, &mut position)?.as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(invalid_data("not a PFM file")),
+    };
+    let width: u32 = read_token(bytes, &mut position)?
+        .parse()
+        .map_err(|_| invalid_data("invalid width"))?;
+    let height: u32 = read_token(bytes, &mut position)?
+        .parse()
+        .map_err(|_| invalid_data("invalid height"))?;
+    let scale: f32 = read_token(bytes, &mut position)?
+        .parse()
+        .map_err(|_| invalid_data("invalid scale"))?;
+    // A single whitespace character separates the header from the data.
+    position += 1;
+
+    let row_length = width as usize * channels;
+    let data_length = row_length
+        .checked_mul(height as usize)
+        .and_then(|floats| floats.checked_mul(4))
+        .ok_or_else(|| invalid_data("image too large"))?;
+    let data = bytes
+        .get(position..)
+        .filter(|data| data.len() >= data_length)
+        .ok_or_else(|| invalid_data("truncated pixel data"))?;
+
+    let floats: Vec<f32> = data[..data_length]
+        .chunks(4)
+        .map(|chunk| {
+            let chunk = [chunk[0], chunk[1], chunk[2], chunk[3]];
+            // The sign of the scale gives the byte order.
+            if scale < 0.0 {
+                f32::from_le_bytes(chunk)
+            } else {
+                f32::from_be_bytes(chunk)
+            }
+        })
+        .collect();
+
+    // Rows are stored from bottom to top.
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 3);
+    for row in floats.chunks(row_length.max(1)).rev() {
+        for pixel in row.chunks(channels) {
+            if channels == 1 {
+                pixels.extend_from_slice(&[pixel[0], pixel[0], pixel[0]]);
+            } else {
+                pixels.extend_from_slice(pixel);
+            }
+        }
+    }
+
+    Ok((pixels, width, height))
+}
@@ -0,0 +1,153 @@
+use crate::maths::*;
+
+/// Gravity at the surface of the Earth, in m/s².
+const GRAVITY: Float = 9.81;
+/// Refractive index of water.
+pub const WATER_REFRACTIVE_INDEX: Float = 1.333;
+/// Wave trains summed by an `Ocean`.
+const WAVE_COUNT: u64 = 32;
+/// How much Gerstner waves sharpen their crests, from 0, sine waves, to 1,
+/// where the sharpest crests would loop.
+const CHOPPINESS: Float = 0.7;
+/// Pierson-Moskowitz constants.
+const PM_ALPHA: Float = 8.1e-3;
+const PM_BETA: Float = 0.74;
+
+/// Absorption coefficients of clear water per meter, for red, green and blue.
+pub fn water_absorption() -> Vec3 {
+    Vec3::new(0.45, 0.06, 0.015)
+}
+
+/// One train of Gerstner waves.
+#[derive(Clone, Copy, Debug)]
+struct Wave {
+    /// Unit direction the crests travel in, over the xz plane.
+    direction: (Float, Float),
+    /// Radians per meter.
+    wavenumber: Float,
+    /// Radians per second, given by the wavenumber in deep water.
+    frequency: Float,
+    /// Meters.
+    amplitude: Float,
+    phase: Float,
+}
+
+/// Waves of the open sea raised by a steady wind: a sum of Gerstner waves
+/// whose heights follow the Pierson-Moskowitz spectrum of a fully developed
+/// sea, travelling at the speed deep water gives them (Tessendorf 2001,
+/// "Simulating Ocean Water"). The sea is level at y = 0 and lengths are in
+/// meters.
+///
+/// Only shading uses the waves, bending the normals of the surfaces given
+/// an ocean (see `Scene::set_ocean`), so the horizon and silhouettes stay
+/// smooth.
+#[derive(Clone, Debug)]
+pub struct Ocean {
+    /// Seconds, moving the waves along.
+    pub time: Float,
+    wind_speed: Float,
+    wind_direction: Float,
+    seed: u64,
+    waves: Vec<Wave>,
+}
+
+/// Uniform number in [0, 1) hashed from `seed` and `index`.
+fn hashed_uniform(seed: u64, index: u64) -> Float {
+    uniform_from_bits(mix_seed(seed, index))
+}
+
+impl Ocean {
+    /// Sea raised by a wind of `wind_speed` m/s blowing towards
+    /// `wind_direction`, in degrees from +x towards +z. Waves go up to 45
+    /// degrees off the wind, the same `seed` giving the same waves. No wind
+    /// leaves the sea flat.
+    pub fn new(wind_speed: Float, wind_direction: Float, seed: u64) -> Self {
+        let mut waves = Vec::new();
+        if wind_speed > 0.0 {
+            // Frequencies around the peak of the spectrum, in bands of even
+            // ratios with one wave each.
+            let peak = 0.877 * GRAVITY / wind_speed;
+            let (lowest, highest) = (0.6 * peak, 20.0 * peak);
+            let ratio = (highest / lowest).powf(1.0 / WAVE_COUNT as Float);
+            for index in 0..WAVE_COUNT {
+                let seed = mix_seed(seed, index);
+                let band_start = lowest * ratio.powi(index as i32);
+                let band_width = band_start * (ratio - 1.0);
+                let frequency = band_start + band_width * hashed_uniform(seed, 0);
+
+                let spectrum = PM_ALPHA * GRAVITY * GRAVITY / frequency.powi(5)
+                    * (-PM_BETA * (GRAVITY / (wind_speed * frequency)).powi(4)).exp();
+                let angle = wind_direction.to_radians()
+                    + (hashed_uniform(seed, 1) - 0.5) * consts::FRAC_PI_2;
+                waves.push(Wave {
+                    direction: (angle.cos(), angle.sin()),
+                    wavenumber: frequency * frequency / GRAVITY,
+                    frequency,
+                    amplitude: (2.0 * spectrum * band_width).sqrt(),
+                    phase: hashed_uniform(seed, 2) * 2.0 * consts::PI,
+                });
+            }
+        }
+
+        Ocean {
+            time: 0.0,
+            wind_speed,
+            wind_direction,
+            seed,
+            waves,
+        }
+    }
+
+    pub fn with_time(self, time: Float) -> Self {
+        Ocean { time, ..self }
+    }
+
+    /// Wind speed, wind direction and seed the ocean was made with.
+    pub fn parameters(&self) -> (Float, Float, u64) {
+        (self.wind_speed, self.wind_direction, self.seed)
+    }
+
+    /// Phase of `wave` at `(x, z)`.
+    fn phase(&self, wave: &Wave, x: Float, z: Float) -> Float {
+        let distance = wave.direction.0 * x + wave.direction.1 * z;
+        wave.wavenumber * distance - wave.frequency * self.time + wave.phase
+    }
+
+    /// Height of the sea above `(x, z)`.
+    pub fn height(&self, x: Float, z: Float) -> Float {
+        self.waves
+            .iter()
+            .map(|wave| wave.amplitude * self.phase(wave, x, z).sin())
+            .sum()
+    }
+
+    /// Unit normal of the sea above `(x, z)`, pointing up.
+    pub fn normal(&self, x: Float, z: Float) -> Vec3 {
+        let sharpness = CHOPPINESS / WAVE_COUNT as Float;
+        let mut normal = Vec3::new(0.0, 1.0, 0.0);
+        for wave in &self.waves {
+            let (sin, cos) = self.phase(wave, x, z).sin_cos();
+            let slope = wave.wavenumber * wave.amplitude;
+            normal.x -= wave.direction.0 * slope * cos;
+            normal.z -= wave.direction.1 * slope * cos;
+            // Crests, bunched up by the orbits of the water, get steeper.
+            normal.y -= sharpness * sin;
+        }
+        normal.unit()
+    }
+
+    /// Bends the unit `normal` of a surface at `point`, in meters, by the
+    /// slopes of the waves, measured along the surface.
+    pub fn shading_normal(&self, point: Vec3, normal: Vec3) -> Vec3 {
+        let wave_normal = self.normal(point.x, point.z);
+        let (slope_x, slope_z) = (
+            -wave_normal.x / wave_normal.y,
+            -wave_normal.z / wave_normal.y,
+        );
+        let x = Vec3::new(1.0, 0.0, 0.0);
+        let z = Vec3::new(0.0, 0.0, 1.0);
+        let tangent_x = x - normal * normal.dot(x);
+        let tangent_z = z - normal * normal.dot(z);
+        (normal - tangent_x * slope_x - tangent_z * slope_z).unit()
+    }
+}
@@ -0,0 +1,111 @@
+use crate::aov::AovImages;
+use crate::maths::*;
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+
+type Device = *mut c_void;
+type Filter = *mut c_void;
+
+/// `OIDN_DEVICE_TYPE_DEFAULT`, the fastest device available.
+const DEVICE_TYPE_DEFAULT: i32 = 0;
+/// `OIDN_FORMAT_FLOAT3`, three packed 32-bit floats per pixel.
+const FORMAT_FLOAT3: i32 = 3;
+/// `OIDN_ERROR_NONE`.
+const ERROR_NONE: i32 = 0;
+
+#[link(name = "OpenImageDenoise")]
+extern "C" {
+    fn oidnNewDevice(device_type: i32) -> Device;
+    fn oidnCommitDevice(device: Device);
+    fn oidnGetDeviceError(device: Device, message: *mut *const c_char) -> i32;
+    fn oidnReleaseDevice(device: Device);
+    fn oidnNewFilter(device: Device, filter_type: *const c_char) -> Filter;
+    fn oidnSetSharedFilterImage(
+        filter: Filter,
+        name: *const c_char,
+        pointer: *mut c_void,
+        format: i32,
+        width: usize,
+        height: usize,
+        byte_offset: usize,
+        pixel_byte_stride: usize,
+        row_byte_stride: usize,
+    );
+    fn oidnSetFilter1b(filter: Filter, name: *const c_char, value: bool);
+    fn oidnCommitFilter(filter: Filter);
+    fn oidnExecuteFilter(filter: Filter);
+    fn oidnReleaseFilter(filter: Filter);
+}
+
+/// The first error `device` ran into since last asked, as an I/O error.
+unsafe fn device_error(device: Device) -> std::io::Result<()> {
+    let mut message = std::ptr::null();
+    if oidnGetDeviceError(device, &mut message) == ERROR_NONE {
+        return Ok(());
+    }
+    let message = if message.is_null() {
+        "OpenImageDenoise failed".to_string()
+    } else {
+        CStr::from_ptr(message).to_string_lossy().into_owned()
+    };
+    Err(std::io::Error::other(message))
+}
+
+/// Runs the `RT` filter of OpenImageDenoise over the colors of row-major,
+/// premultiplied RGBA `pixels`, guided by the albedos and normals of `aovs`.
+/// Alphas are kept as they are.
+pub fn oidn_denoise(
+    pixels: &[Float],
+    aovs: &AovImages,
+    width: usize,
+    height: usize,
+) -> std::io::Result<Vec<Float>> {
+    let mut color: Vec<f32> = pixels
+        .chunks(4)
+        .flat_map(|pixel| pixel[0..3].iter().map(|&x| to_f32(x)))
+        .collect();
+    let mut albedo: Vec<f32> = aovs.albedo.iter().map(|&x| to_f32(x)).collect();
+    let mut normal: Vec<f32> = aovs.normal.iter().map(|&x| to_f32(x)).collect();
+    let mut output = vec![0.0f32; color.len()];
+
+    unsafe {
+        let device = oidnNewDevice(DEVICE_TYPE_DEFAULT);
+        oidnCommitDevice(device);
+        let filter = oidnNewFilter(device, b"RT\0".as_ptr() as *const c_char);
+        let images: [(&[u8], &mut Vec<f32>); 4] = [
+            (b"color\0", &mut color),
+            (b"albedo\0", &mut albedo),
+            (b"normal\0", &mut normal),
+            (b"output\0", &mut output),
+        ];
+        for (name, buffer) in images {
+            oidnSetSharedFilterImage(
+                filter,
+                name.as_ptr() as *const c_char,
+                buffer.as_mut_ptr() as *mut c_void,
+                FORMAT_FLOAT3,
+                width,
+                height,
+                0,
+                0,
+                0,
+            );
+        }
+        oidnSetFilter1b(filter, b"hdr\0".as_ptr() as *const c_char, true);
+        oidnCommitFilter(filter);
+        oidnExecuteFilter(filter);
+        let result = device_error(device);
+        oidnReleaseFilter(filter);
+        oidnReleaseDevice(device);
+        result?;
+    }
+
+    let mut denoised = pixels.to_vec();
+    for (pixel, rgb) in denoised.chunks_mut(4).zip(output.chunks(3)) {
+        for (value, &x) in pixel.iter_mut().zip(rgb) {
+            *value = x as Float;
+        }
+    }
+    Ok(denoised)
+}
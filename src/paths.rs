@@ -0,0 +1,92 @@
+use crate::maths::*;
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufWriter;
+
+/// What happened to a path at one of its vertices.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum VertexKind {
+    Camera,
+    Diffuse,
+    Specular,
+    /// Hit a surface that doesn't scatter light (lights, absorbing metals...).
+    Absorbed,
+    /// Left the scene, the vertex is a point along the escaping direction.
+    Escaped,
+    /// Still bouncing when the maximum depth was reached.
+    Terminated,
+}
+
+impl VertexKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            VertexKind::Camera => "camera",
+            VertexKind::Diffuse => "diffuse",
+            VertexKind::Specular => "specular",
+            VertexKind::Absorbed => "absorbed",
+            VertexKind::Escaped => "escaped",
+            VertexKind::Terminated => "terminated",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct PathVertex {
+    pub position: Vec3,
+    pub kind: VertexKind,
+}
+
+/// Writes every path as a polyline of a Wavefront OBJ file, the kind of each
+/// vertex being kept as a comment.
+pub fn create_paths_obj(name: &str, paths: &[Vec<PathVertex>]) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(name)?);
+
+    let mut first_index = 1;
+    for (index, path) in paths.iter().enumerate() {
+        writeln!(file, "o path_{}", index)?;
+        for vertex in path {
+            let p = vertex.position;
+            writeln!(file, "v {} {} {} # {}", p.x, p.y, p.z, vertex.kind.name())?;
+        }
+
+        if path.len() > 1 {
+            write!(file, "l")?;
+            for i in 0..path.len() {
+                write!(file, " {}", first_index + i)?;
+            }
+            writeln!(file)?;
+        }
+        first_index += path.len();
+    }
+
+    Ok(())
+}
+
+/// Writes the paths as a JSON array of arrays of `{ "position", "kind" }`.
+pub fn create_paths_json(name: &str, paths: &[Vec<PathVertex>]) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(name)?);
+
+    writeln!(file, "[")?;
+    for (index, path) in paths.iter().enumerate() {
+        writeln!(file, "  [")?;
+        for (i, vertex) in path.iter().enumerate() {
+            let p = vertex.position;
+            let separator = if i + 1 < path.len() { "," } else { "" };
+            writeln!(
+                file,
+                "    {{ \"position\": [{}, {}, {}], \"kind\": \"{}\" }}{}",
+                p.x,
+                p.y,
+                p.z,
+                vertex.kind.name(),
+                separator
+            )?;
+        }
+        let separator = if index + 1 < paths.len() { "," } else { "" };
+        writeln!(file, "  ]{}", separator)?;
+    }
+    writeln!(file, "]")?;
+
+    Ok(())
+}
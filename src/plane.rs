@@ -0,0 +1,195 @@
+use crate::hitable::*;
+use crate::material::MaterialId;
+use crate::maths::*;
+use crate::ray::Ray;
+use crate::sphere::UvFrame;
+
+/// Infinite plane through `point`, its front facing along `normal`. Texture
+/// coordinates are distances along the plane in scene units, so textures
+/// repeat every unit.
+#[derive(Clone, Copy, Debug)]
+pub struct Plane {
+    pub point: Vec3,
+    /// Unit normal of the front face.
+    pub normal: Vec3,
+    pub material: MaterialId,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3, material: MaterialId) -> Self {
+        Plane {
+            point,
+            normal: normal.unit(),
+            material,
+        }
+    }
+
+    /// Texture coordinates of `point` on the plane, with the frame normal and
+    /// bump maps bend normals in.
+    pub fn uv_frame(&self, point: Vec3) -> UvFrame {
+        let (tangent, bitangent) = flat_tangents(self.normal);
+        let offset = point - self.point;
+        UvFrame {
+            uv: (offset.dot(tangent), offset.dot(bitangent)),
+            tangents: (tangent, bitangent),
+            lengths: (1.0, 1.0),
+        }
+    }
+
+    /// Point and front normal at texture coordinates (`u`, `v`).
+    pub fn surface_at(&self, u: Float, v: Float) -> (Vec3, Vec3) {
+        let (tangent, bitangent) = flat_tangents(self.normal);
+        (self.point + tangent * u + bitangent * v, self.normal)
+    }
+
+    /// Copy of the plane moved by `transform`, which should keep angles.
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        Plane::new(
+            transform.transform_point(self.point),
+            transform.transform_vector(self.normal),
+            self.material,
+        )
+    }
+}
+
+impl Hitable for Plane {
+    fn scale(&mut self, factor: Float) {
+        self.point = self.point * factor;
+    }
+
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let t = flat_hit(ray, self.point, self.normal)?;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+
+        Some(HitRecord::new(
+            ray,
+            ray.at(t),
+            self.normal,
+            t,
+            self.material,
+        ))
+    }
+}
+
+/// Round flat disk centered on `center`, its front facing along `normal`,
+/// which makes a circular area light with an emissive material. Texture
+/// coordinates are laid over it like a slide projected along the normal,
+/// going from 0 to 1 across its diameter.
+#[derive(Clone, Copy, Debug)]
+pub struct Disk {
+    pub center: Vec3,
+    /// Unit normal of the front face.
+    pub normal: Vec3,
+    pub radius: Float,
+    pub material: MaterialId,
+}
+
+impl Disk {
+    pub fn new(center: Vec3, normal: Vec3, radius: Float, material: MaterialId) -> Self {
+        Disk {
+            center,
+            normal: normal.unit(),
+            radius,
+            material,
+        }
+    }
+
+    /// Texture coordinates of `point` on the disk, with the frame normal and
+    /// bump maps bend normals in.
+    pub fn uv_frame(&self, point: Vec3) -> UvFrame {
+        let (tangent, bitangent) = flat_tangents(self.normal);
+        let diameter = 2.0 * self.radius;
+        let offset = (point - self.center) / diameter;
+        UvFrame {
+            uv: (offset.dot(tangent) + 0.5, offset.dot(bitangent) + 0.5),
+            tangents: (tangent, bitangent),
+            lengths: (diameter, diameter),
+        }
+    }
+
+    /// Point and front normal at texture coordinates (`u`, `v`), which may
+    /// fall outside of the disk in the corners of the unit square.
+    pub fn surface_at(&self, u: Float, v: Float) -> (Vec3, Vec3) {
+        let (tangent, bitangent) = flat_tangents(self.normal);
+        let diameter = 2.0 * self.radius;
+        let point = self.center + (tangent * (u - 0.5) + bitangent * (v - 0.5)) * diameter;
+        (point, self.normal)
+    }
+
+    /// Copy of the disk moved by `transform`, which should keep angles. The
+    /// radius follows the average scale of the transform, like spheres.
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        Disk::new(
+            transform.transform_point(self.center),
+            transform.transform_vector(self.normal),
+            self.radius * transform.determinant3().abs().cbrt(),
+            self.material,
+        )
+    }
+}
+
+impl Hitable for Disk {
+    fn scale(&mut self, factor: Float) {
+        self.center = self.center * factor;
+        self.radius *= factor;
+    }
+
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let t = flat_hit(ray, self.center, self.normal)?;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+        let position = ray.at(t);
+        if (position - self.center).length_squared() > self.radius * self.radius {
+            return None;
+        }
+
+        Some(HitRecord::new(ray, position, self.normal, t, self.material))
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> Float {
+        let hit = match self.hit(&Ray::new(origin, direction), 0.0001, Float::INFINITY) {
+            Some(hit) => hit,
+            None => return 0.0,
+        };
+
+        // Points are picked uniformly over the area, which covers a solid
+        // angle shrinking with the square of the distance and the cosine.
+        let distance_squared = (hit.position - origin).length_squared();
+        let cosine = self.normal.dot(direction).abs() / direction.length();
+        let area = consts::PI * self.radius * self.radius;
+        distance_squared / (cosine * area)
+    }
+
+    fn sample_direction_towards(&self, origin: Vec3, u: (Float, Float)) -> Vec3 {
+        let (tangent, bitangent) = flat_tangents(self.normal);
+        let offset = sample_unit_disk(u) * self.radius;
+        self.center + tangent * offset.x + bitangent * offset.y - origin
+    }
+}
+
+/// Ray parameter where `ray` crosses the plane through `point` facing
+/// `normal`, if it isn't parallel to it.
+fn flat_hit(ray: &Ray, point: Vec3, normal: Vec3) -> Option<Float> {
+    let facing = normal.dot(ray.dir);
+    if facing.abs() < 1e-12 {
+        return None;
+    }
+    Some(normal.dot(point - ray.origin) / facing)
+}
+
+/// Unit tangent and bitangent of a flat surface facing `normal`, making a
+/// right handed frame with it. The tangent follows +x where it can, so `u`
+/// runs to the right and `v` away from the camera on a floor seen from
+/// +z.
+fn flat_tangents(normal: Vec3) -> (Vec3, Vec3) {
+    let along = if normal.x.abs() > 0.9 {
+        Vec3::new(0.0, 0.0, -1.0)
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let tangent = (along - normal * normal.dot(along)).unit();
+    (tangent, normal.cross(tangent))
+}
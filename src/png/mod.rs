@@ -0,0 +1,1001 @@
+use crate::exr::Preview;
+
+use rayon::prelude::*;
+
+use std::fs::File;
+use std::io::prelude::*;
+
+const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// Private ancillary chunk holding a `Preview`: its width and height, big
+/// endian, then its pixels. Readers that don't know it skip it.
+const PREVIEW_CHUNK: &[u8; 4] = b"prVw";
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + u32::from(byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+/// Largest payload a single stored (uncompressed) deflate block can hold.
+const MAX_STORED_BLOCK: usize = 65535;
+
+/// Size of the window deflate matches reach back into.
+const WINDOW: usize = 32768;
+
+/// Longest and shortest match deflate can encode.
+const MAX_MATCH: usize = 258;
+const MIN_MATCH: usize = 3;
+
+/// Number of earlier positions with the same three bytes looked at when
+/// searching for the longest match, trading compression for speed.
+const MAX_CHAIN: usize = 8;
+
+/// Literals and matches per deflate block, each block getting Huffman
+/// codes fitted to its own symbols.
+const BLOCK_SYMBOLS: usize = 1 << 15;
+
+/// A literal byte, or a match of `length` bytes `distance` bytes back.
+#[derive(Clone, Copy)]
+enum Token {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+/// Splits `data` into literals and matches, greedily taking the longest
+/// match found among the last positions starting with the same three bytes.
+fn lz77(data: &[u8]) -> Vec<Token> {
+    const HASH_BITS: u32 = 15;
+    let hash = |position: usize| {
+        let bytes = u32::from(data[position]) << 16
+            | u32::from(data[position + 1]) << 8
+            | u32::from(data[position + 2]);
+        (bytes.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+    };
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut previous = vec![usize::MAX; WINDOW];
+
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    while position < data.len() {
+        let (mut length, mut distance) = (0, 0);
+        if position + MIN_MATCH <= data.len() {
+            let longest = MAX_MATCH.min(data.len() - position);
+            let mut candidate = head[hash(position)];
+            for _ in 0..MAX_CHAIN {
+                if candidate == usize::MAX || position - candidate > WINDOW {
+                    break;
+                }
+                let matched = data[candidate..]
+                    .iter()
+                    .zip(&data[position..position + longest])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if matched > length {
+                    length = matched;
+                    distance = position - candidate;
+                    if matched == longest {
+                        break;
+                    }
+                }
+                candidate = previous[candidate % WINDOW];
+            }
+        }
+
+        if length >= MIN_MATCH {
+            tokens.push(Token::Match {
+                length: length as u16,
+                distance: distance as u16,
+            });
+        } else {
+            tokens.push(Token::Literal(data[position]));
+            length = 1;
+        }
+        for position in
+            position..(position + length).min((data.len() + 1).saturating_sub(MIN_MATCH))
+        {
+            let key = hash(position);
+            previous[position % WINDOW] = head[key];
+            head[key] = position;
+        }
+        position += length;
+    }
+    tokens
+}
+
+/// Lengths of the Huffman codes best fitting `frequencies`, none longer
+/// than `limit`. Frequencies are flattened until the code fits.
+fn code_lengths(frequencies: &[u32], limit: u8) -> Vec<u8> {
+    let mut frequencies = frequencies.to_vec();
+    loop {
+        let mut lengths = vec![0u8; frequencies.len()];
+        let used: Vec<usize> = (0..frequencies.len())
+            .filter(|&symbol| frequencies[symbol] > 0)
+            .collect();
+        if used.len() == 1 {
+            lengths[used[0]] = 1;
+            return lengths;
+        }
+
+        // Parents of the leaves, then of the internal nodes, as the two
+        // least frequent nodes get merged.
+        let leaves = used.len();
+        let mut parents = vec![0; 2 * leaves - 1];
+        let mut heap: std::collections::BinaryHeap<_> = used
+            .iter()
+            .enumerate()
+            .map(|(node, &symbol)| std::cmp::Reverse((frequencies[symbol], node)))
+            .collect();
+        let mut next = leaves;
+        while heap.len() > 1 {
+            let std::cmp::Reverse((a, first)) = heap.pop().unwrap();
+            let std::cmp::Reverse((b, second)) = heap.pop().unwrap();
+            parents[first] = next;
+            parents[second] = next;
+            heap.push(std::cmp::Reverse((a + b, next)));
+            next += 1;
+        }
+
+        let mut depths = vec![0u8; 2 * leaves - 1];
+        for node in (0..2 * leaves - 2).rev() {
+            depths[node] = depths[parents[node]] + 1;
+        }
+        for (node, &symbol) in used.iter().enumerate() {
+            lengths[symbol] = depths[node];
+        }
+        if lengths.iter().all(|&length| length <= limit) {
+            return lengths;
+        }
+        for frequency in frequencies.iter_mut().filter(|frequency| **frequency > 0) {
+            *frequency = *frequency / 2 + 1;
+        }
+    }
+}
+
+/// Canonical codes of the code lengths `lengths`, bit reversed for deflate
+/// writing its Huffman codes most significant bit first.
+fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+    let mut counts = [0u16; 16];
+    for &length in lengths {
+        counts[length as usize] += 1;
+    }
+    counts[0] = 0;
+    let mut next = [0u16; 16];
+    for length in 1..16 {
+        next[length] = (next[length - 1] + counts[length - 1]) << 1;
+    }
+    lengths
+        .iter()
+        .map(|&length| {
+            if length == 0 {
+                return 0;
+            }
+            let code = next[length as usize];
+            next[length as usize] += 1;
+            code.reverse_bits() >> (16 - length)
+        })
+        .collect()
+}
+
+/// Bits of a deflate stream being written, least significant first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn bits(&mut self, value: u32, count: u8) {
+        self.buffer |= u64::from(value) << self.count;
+        self.count += u32::from(count);
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+/// Length and distance symbols of a match, with their extra bits, as
+/// (symbol, extra bits, extra bit count).
+fn match_symbols(length: u16, distance: u16) -> ((usize, u32, u8), (usize, u32, u8)) {
+    let index = LENGTH_BASES.partition_point(|&base| base <= length) - 1;
+    let length = (
+        257 + index,
+        u32::from(length - LENGTH_BASES[index]),
+        LENGTH_EXTRA_BITS[index],
+    );
+    let index = DISTANCE_BASES.partition_point(|&base| base <= distance) - 1;
+    let distance = (
+        index,
+        u32::from(distance - DISTANCE_BASES[index]),
+        DISTANCE_EXTRA_BITS[index],
+    );
+    (length, distance)
+}
+
+/// Writes `tokens` as a deflate block with dynamic Huffman codes.
+fn write_block(out: &mut BitWriter, tokens: &[Token], last: bool) {
+    let mut literal_frequencies = [0u32; 286];
+    let mut distance_frequencies = [0u32; 30];
+    for &token in tokens {
+        match token {
+            Token::Literal(byte) => literal_frequencies[byte as usize] += 1,
+            Token::Match { length, distance } => {
+                let ((length, ..), (distance, ..)) = match_symbols(length, distance);
+                literal_frequencies[length] += 1;
+                distance_frequencies[distance] += 1;
+            }
+        }
+    }
+    literal_frequencies[256] = 1;
+    // Codes of a single symbol leave half of their space unused, which some
+    // decoders refuse.
+    for frequencies in [&mut literal_frequencies[..], &mut distance_frequencies[..]] {
+        for frequency in frequencies.iter_mut().take(2) {
+            *frequency = (*frequency).max(1);
+        }
+    }
+    let literal_lengths = code_lengths(&literal_frequencies, 15);
+    let distance_lengths = code_lengths(&distance_frequencies, 15);
+    let literal_count = 257.max(
+        literal_lengths
+            .iter()
+            .rposition(|&length| length > 0)
+            .unwrap()
+            + 1,
+    );
+    let distance_count = distance_lengths
+        .iter()
+        .rposition(|&length| length > 0)
+        .unwrap()
+        + 1;
+
+    // The code lengths of both alphabets, runs shortened with the repeat
+    // symbols 16 (previous length), 17 and 18 (zeros).
+    let lengths = [
+        &literal_lengths[..literal_count],
+        &distance_lengths[..distance_count],
+    ]
+    .concat();
+    let mut runs: Vec<(u8, u32)> = Vec::new();
+    let mut start = 0;
+    while start < lengths.len() {
+        let length = lengths[start];
+        let run = lengths[start..]
+            .iter()
+            .take_while(|&&x| x == length)
+            .count();
+        let mut left = run;
+        if length == 0 {
+            while left >= 11 {
+                let count = left.min(138);
+                runs.push((18, (count - 11) as u32));
+                left -= count;
+            }
+            if left >= 3 {
+                runs.push((17, (left - 3) as u32));
+                left = 0;
+            }
+        } else {
+            runs.push((length, 0));
+            left -= 1;
+            while left >= 3 {
+                let count = left.min(6);
+                runs.push((16, (count - 3) as u32));
+                left -= count;
+            }
+        }
+        runs.extend(std::iter::repeat_n((length, 0), left));
+        start += run;
+    }
+
+    let mut length_frequencies = [0u32; 19];
+    for &(symbol, _) in &runs {
+        length_frequencies[symbol as usize] += 1;
+    }
+    let length_lengths = code_lengths(&length_frequencies, 7);
+    let length_count = 4.max(
+        CODE_LENGTH_ORDER
+            .iter()
+            .rposition(|&symbol| length_lengths[symbol] > 0)
+            .unwrap()
+            + 1,
+    );
+
+    out.bits(last as u32, 1);
+    out.bits(2, 2);
+    out.bits((literal_count - 257) as u32, 5);
+    out.bits((distance_count - 1) as u32, 5);
+    out.bits((length_count - 4) as u32, 4);
+    for &symbol in &CODE_LENGTH_ORDER[..length_count] {
+        out.bits(u32::from(length_lengths[symbol]), 3);
+    }
+    let length_codes = canonical_codes(&length_lengths);
+    for (symbol, extra) in runs {
+        let symbol = symbol as usize;
+        out.bits(u32::from(length_codes[symbol]), length_lengths[symbol]);
+        match symbol {
+            16 => out.bits(extra, 2),
+            17 => out.bits(extra, 3),
+            18 => out.bits(extra, 7),
+            _ => {}
+        }
+    }
+
+    let literal_codes = canonical_codes(&literal_lengths);
+    let distance_codes = canonical_codes(&distance_lengths);
+    for &token in tokens {
+        match token {
+            Token::Literal(byte) => {
+                let symbol = byte as usize;
+                out.bits(u32::from(literal_codes[symbol]), literal_lengths[symbol]);
+            }
+            Token::Match { length, distance } => {
+                let (
+                    (length, length_extra, length_bits),
+                    (distance, distance_extra, distance_bits),
+                ) = match_symbols(length, distance);
+                out.bits(u32::from(literal_codes[length]), literal_lengths[length]);
+                out.bits(length_extra, length_bits);
+                out.bits(
+                    u32::from(distance_codes[distance]),
+                    distance_lengths[distance],
+                );
+                out.bits(distance_extra, distance_bits);
+            }
+        }
+    }
+    out.bits(u32::from(literal_codes[256]), literal_lengths[256]);
+}
+
+/// Wraps `data` in a zlib stream made of stored deflate blocks, for data
+/// deflate can't shrink, like noise.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+/// Compresses `data` into a zlib stream of deflate blocks with dynamic
+/// Huffman codes, or stores it when that comes out larger.
+fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    let tokens = lz77(data);
+    let mut out = BitWriter::default();
+    let mut blocks = tokens.chunks(BLOCK_SYMBOLS).peekable();
+    if blocks.peek().is_none() {
+        write_block(&mut out, &[], true);
+    }
+    while let Some(block) = blocks.next() {
+        write_block(&mut out, block, blocks.peek().is_none());
+    }
+
+    let mut stream = vec![0x78, 0x9c];
+    stream.extend(out.finish());
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    if stream.len() > data.len() + data.len().div_ceil(MAX_STORED_BLOCK) * 5 + 6 {
+        return zlib_stored(data);
+    }
+    stream
+}
+
+/// `row` filtered with the PNG filter `filter`, given the row `above` it.
+fn filter_row(filter: u8, row: &[u8], above: &[u8], channels: usize, out: &mut [u8]) {
+    for i in 0..row.len() {
+        let (left, upper_left) = if i >= channels {
+            (row[i - channels], above[i - channels])
+        } else {
+            (0, 0)
+        };
+        let predicted = match filter {
+            0 => 0,
+            1 => left,
+            2 => above[i],
+            3 => ((u16::from(left) + u16::from(above[i])) / 2) as u8,
+            _ => paeth(left, above[i], upper_left),
+        };
+        out[i] = row[i].wrapping_sub(predicted);
+    }
+}
+
+/// The rows of `pixels`, `stride` bytes each, every one filtered with the
+/// filter leaving the smallest differences, which compress best, and
+/// prefixed by its type.
+fn filter_scanlines(pixels: &[u8], stride: usize, channels: usize) -> Vec<u8> {
+    let zeros = vec![0; stride];
+    let mut scanlines = vec![0; pixels.len() + pixels.len() / stride];
+    scanlines
+        .par_chunks_mut(stride + 1)
+        .zip(pixels.par_chunks(stride))
+        .enumerate()
+        .for_each(|(j, (scanline, row))| {
+            let above = if j > 0 {
+                &pixels[(j - 1) * stride..j * stride]
+            } else {
+                &zeros
+            };
+            let mut filtered = vec![0; stride];
+            let mut best_score = u64::MAX;
+            for filter in 0..5 {
+                filter_row(filter, row, above, channels, &mut filtered);
+                // Differences read as signed, small either way.
+                let score = filtered
+                    .iter()
+                    .map(|&value| u64::from((value as i8).unsigned_abs()))
+                    .sum();
+                if score < best_score {
+                    best_score = score;
+                    scanline[0] = filter;
+                    scanline[1..].copy_from_slice(&filtered);
+                }
+            }
+        });
+    scanlines
+}
+
+fn push_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    let start = out.len();
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start + 4..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Writes 8-bit RGB (or RGBA when `alpha` is set) `pixels` as a PNG file,
+/// with `metadata` stored in `tEXt` chunks and `preview` in a private chunk.
+pub fn create_png(
+    name: &str,
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    alpha: bool,
+    metadata: &[(String, String)],
+    preview: Option<&Preview>,
+) -> std::io::Result<()> {
+    let bytes = encode_png(pixels, width, height, alpha, metadata, preview);
+    File::create(name)?.write_all(&bytes)
+}
+
+/// The bytes of the PNG file `create_png` writes, for those sending it
+/// elsewhere than to a file.
+pub fn encode_png(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    alpha: bool,
+    metadata: &[(String, String)],
+    preview: Option<&Preview>,
+) -> Vec<u8> {
+    let channels = if alpha { 4 } else { 3 };
+    let color_type = if alpha { 6 } else { 2 };
+    let stride = width as usize * channels;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[8, color_type, 0, 0, 0]);
+
+    let scanlines = filter_scanlines(pixels, stride, channels);
+
+    let mut out = SIGNATURE.to_vec();
+    push_chunk(&mut out, b"IHDR", &header);
+    for (key, value) in metadata {
+        let mut text = key.as_bytes().to_vec();
+        text.push(0);
+        text.extend_from_slice(value.as_bytes());
+        push_chunk(&mut out, b"tEXt", &text);
+    }
+    if let Some(preview) = preview {
+        let mut data = Vec::with_capacity(8 + preview.pixels.len());
+        data.extend_from_slice(&preview.width.to_be_bytes());
+        data.extend_from_slice(&preview.height.to_be_bytes());
+        data.extend_from_slice(&preview.pixels);
+        push_chunk(&mut out, PREVIEW_CHUNK, &data);
+    }
+    push_chunk(&mut out, b"IDAT", &zlib_compress(&scanlines));
+    push_chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+/// Reads the kinds and data of the chunks of a PNG file, up to `IEND`.
+fn read_chunks(name: &str) -> std::io::Result<Vec<([u8; 4], Vec<u8>)>> {
+    let mut bytes = Vec::new();
+    File::open(name)?.read_to_end(&mut bytes)?;
+    split_chunks(&bytes)
+}
+
+/// Kinds and data of the chunks of the PNG image `bytes`, up to `IEND`.
+fn split_chunks(bytes: &[u8]) -> std::io::Result<Vec<([u8; 4], Vec<u8>)>> {
+    let invalid_data = |message| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+
+    if !bytes.starts_with(&SIGNATURE) {
+        return Err(invalid_data("not a PNG file"));
+    }
+
+    let mut chunks = Vec::new();
+    let mut position = SIGNATURE.len();
+    while position + 8 <= bytes.len() {
+        let length = u32::from_be_bytes([
+            bytes[position],
+            bytes[position + 1],
+            bytes[position + 2],
+            bytes[position + 3],
+        ]) as usize;
+        let kind = [
+            bytes[position + 4],
+            bytes[position + 5],
+            bytes[position + 6],
+            bytes[position + 7],
+        ];
+        let data = bytes
+            .get(position + 8..)
+            .and_then(|rest| rest.get(..length))
+            .ok_or_else(|| invalid_data("truncated chunk"))?;
+        if &kind == b"IEND" {
+            break;
+        }
+        chunks.push((kind, data.to_vec()));
+        // Length, kind, data and CRC.
+        position += 12 + length;
+    }
+
+    Ok(chunks)
+}
+
+/// Reads the keyword and text pairs of the `tEXt` chunks of a PNG file.
+pub fn read_png_metadata(name: &str) -> std::io::Result<Vec<(String, String)>> {
+    let mut metadata = Vec::new();
+    for (kind, data) in read_chunks(name)? {
+        if &kind == b"tEXt" {
+            let mut parts = data.splitn(2, |&byte| byte == 0);
+            if let (Some(key), Some(value)) = (parts.next(), parts.next()) {
+                metadata.push((
+                    String::from_utf8_lossy(key).into_owned(),
+                    String::from_utf8_lossy(value).into_owned(),
+                ));
+            }
+        }
+    }
+    Ok(metadata)
+}
+
+/// Reads the preview written by `create_png`, if the file has one.
+pub fn read_png_preview(name: &str) -> std::io::Result<Option<Preview>> {
+    let data = match read_chunks(name)?
+        .into_iter()
+        .find(|(kind, _)| kind == PREVIEW_CHUNK)
+    {
+        Some((_, data)) => data,
+        None => return Ok(None),
+    };
+    let size = |start: usize| {
+        data.get(start..start + 4)
+            .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+    match (size(0), size(4)) {
+        (Some(width), Some(height)) if data.len() == 8 + width as usize * height as usize * 4 => {
+            Ok(Some(Preview {
+                width,
+                height,
+                pixels: data[8..].to_vec(),
+            }))
+        }
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "invalid preview",
+        )),
+    }
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Lengths of the matches of the length symbols of deflate, from 257 on,
+/// before their extra bits, and the number of those.
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// Distances of the matches of the distance symbols of deflate, before
+/// their extra bits, and the number of those.
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Order in which dynamic deflate blocks give the code lengths of the code
+/// length alphabet.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Canonical Huffman code of a deflate alphabet, from the code length of
+/// every symbol: how many codes each length has, and the symbols in the
+/// order of their codes.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0; 16];
+        for length in 1..16 {
+            offsets[length] = offsets[length - 1] + counts[length - 1];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length > 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    /// Codes of the fixed literal and length, and distance, alphabets.
+    fn fixed() -> (Self, Self) {
+        let mut lengths = [8; 288];
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+    }
+}
+
+/// Bits of a deflate stream, least significant first.
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, count: u8) -> std::io::Result<u32> {
+        let mut value = 0;
+        for bit in 0..count {
+            let byte = self
+                .bytes
+                .get(self.position / 8)
+                .ok_or_else(|| invalid("deflate stream ends early"))?;
+            value |= u32::from(byte >> (self.position % 8) & 1) << bit;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+
+    /// Next symbol of `code`, whose bits come most significant first.
+    fn symbol(&mut self, code: &Huffman) -> std::io::Result<u16> {
+        // First code of the current length, and index of its symbol.
+        let (mut value, mut first, mut index) = (0, 0, 0);
+        for &count in &code.counts[1..] {
+            value |= self.bits(1)? as usize;
+            let count = count as usize;
+            if value - first < count {
+                return Ok(code.symbols[index + value - first]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            value <<= 1;
+        }
+        Err(invalid("invalid deflate code"))
+    }
+
+    /// Moves on to the next byte boundary, returning its index.
+    fn align(&mut self) -> usize {
+        self.position = self.position.div_ceil(8) * 8;
+        self.position / 8
+    }
+}
+
+/// Code lengths of the literal and length, and distance, alphabets of a
+/// dynamic deflate block.
+fn dynamic_codes(bits: &mut BitReader) -> std::io::Result<(Huffman, Huffman)> {
+    let literals = bits.bits(5)? as usize + 257;
+    let distances = bits.bits(5)? as usize + 1;
+    let code_lengths = bits.bits(4)? as usize + 4;
+    let mut lengths = [0; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_lengths] {
+        lengths[symbol] = bits.bits(3)? as u8;
+    }
+    let code = Huffman::new(&lengths);
+
+    let mut lengths = Vec::with_capacity(literals + distances);
+    while lengths.len() < literals + distances {
+        let (length, repeat) = match bits.symbol(&code)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or_else(|| invalid("deflate repeat of no length"))?;
+                (previous, 3 + bits.bits(2)?)
+            }
+            17 => (0, 3 + bits.bits(3)?),
+            _ => (0, 11 + bits.bits(7)?),
+        };
+        lengths.extend((0..repeat).map(|_| length));
+    }
+    if lengths.len() > literals + distances {
+        return Err(invalid("deflate code lengths overflow"));
+    }
+    Ok((
+        Huffman::new(&lengths[..literals]),
+        Huffman::new(&lengths[literals..]),
+    ))
+}
+
+/// Data of the zlib stream `stream`, after RFC 1950 and 1951.
+fn zlib_decode(stream: &[u8]) -> std::io::Result<Vec<u8>> {
+    let header = match stream {
+        [method, flags, ..] => u16::from_be_bytes([*method, *flags]),
+        _ => return Err(invalid("zlib stream ends early")),
+    };
+    // Deflate without a preset dictionary.
+    if header >> 8 & 0x0f != 8 || header % 31 != 0 || header & 0x20 != 0 {
+        return Err(invalid("unsupported zlib stream"));
+    }
+    let mut bits = BitReader {
+        bytes: &stream[2..],
+        position: 0,
+    };
+    let mut data = Vec::new();
+    loop {
+        let last = bits.bits(1)? == 1;
+        let (literals, distances) = match bits.bits(2)? {
+            0 => {
+                let start = bits.align();
+                let block = bits
+                    .bytes
+                    .get(start..start + 4)
+                    .and_then(|header| {
+                        let length = u16::from_le_bytes([header[0], header[1]]);
+                        let check = u16::from_le_bytes([header[2], header[3]]);
+                        let end = start + 4 + length as usize;
+                        bits.bytes.get(start + 4..end).filter(|_| length == !check)
+                    })
+                    .ok_or_else(|| invalid("invalid stored deflate block"))?;
+                data.extend_from_slice(block);
+                bits.position = (start + 4 + block.len()) * 8;
+                if last {
+                    break;
+                }
+                continue;
+            }
+            1 => Huffman::fixed(),
+            2 => dynamic_codes(&mut bits)?,
+            _ => return Err(invalid("invalid deflate block")),
+        };
+
+        loop {
+            let symbol = bits.symbol(&literals)? as usize;
+            if symbol < 256 {
+                data.push(symbol as u8);
+                continue;
+            } else if symbol == 256 {
+                break;
+            }
+            let index = symbol - 257;
+            if index >= LENGTH_BASES.len() {
+                return Err(invalid("invalid deflate length"));
+            }
+            let length =
+                LENGTH_BASES[index] as usize + bits.bits(LENGTH_EXTRA_BITS[index])? as usize;
+            let index = bits.symbol(&distances)? as usize;
+            if index >= DISTANCE_BASES.len() {
+                return Err(invalid("invalid deflate distance"));
+            }
+            let distance =
+                DISTANCE_BASES[index] as usize + bits.bits(DISTANCE_EXTRA_BITS[index])? as usize;
+            if distance > data.len() {
+                return Err(invalid("deflate distance before the start"));
+            }
+            // Matches may overlap what they copy.
+            let start = data.len() - distance;
+            for offset in 0..length {
+                data.push(data[start + offset]);
+            }
+        }
+        if last {
+            break;
+        }
+    }
+
+    let end = bits.align();
+    let checksum = bits
+        .bytes
+        .get(end..end + 4)
+        .map(|sum| u32::from_be_bytes([sum[0], sum[1], sum[2], sum[3]]));
+    if checksum != Some(adler32(&data)) {
+        return Err(invalid("zlib checksum mismatch"));
+    }
+    Ok(data)
+}
+
+/// Predictor of the Paeth filter, the neighbor closest to `a + b - c`.
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let estimate = i16::from(a) + i16::from(b) - i16::from(c);
+    let (pa, pb, pc) = (
+        (estimate - i16::from(a)).abs(),
+        (estimate - i16::from(b)).abs(),
+        (estimate - i16::from(c)).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Pixels of the PNG image `bytes`, as red, green, blue and alpha from 0 to
+/// 1, rows from top to bottom, with its width and height. Every bit depth
+/// and color type is read, interlaced images aside.
+pub fn decode_png(bytes: &[u8]) -> std::io::Result<(Vec<f32>, u32, u32)> {
+    let chunks = split_chunks(bytes)?;
+    let header = match chunks.first() {
+        Some((kind, header)) if kind == b"IHDR" && header.len() == 13 => header,
+        _ => return Err(invalid("PNG file without a header")),
+    };
+    let width = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+    let height = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+    let (depth, color_type) = (header[8], header[9]);
+    let channels = match (color_type, depth) {
+        (0, 1 | 2 | 4 | 8 | 16) => 1,
+        (3, 1 | 2 | 4 | 8) => 1,
+        (4, 8 | 16) => 2,
+        (2, 8 | 16) => 3,
+        (6, 8 | 16) => 4,
+        _ => return Err(invalid("invalid PNG color type or bit depth")),
+    };
+    if header[10] != 0 || header[11] != 0 {
+        return Err(invalid("unknown PNG compression or filter method"));
+    }
+    if header[12] != 0 {
+        return Err(invalid("interlaced PNG files aren't supported"));
+    }
+
+    let mut palette: Vec<[f32; 4]> = Vec::new();
+    let mut compressed = Vec::new();
+    for (kind, data) in &chunks {
+        match kind {
+            b"PLTE" => {
+                palette = data
+                    .chunks_exact(3)
+                    .map(|rgb| [rgb[0], rgb[1], rgb[2]].map(|value| value as f32 / 255.0))
+                    .map(|[r, g, b]| [r, g, b, 1.0])
+                    .collect()
+            }
+            b"tRNS" if color_type == 3 => {
+                for (color, &alpha) in palette.iter_mut().zip(data) {
+                    color[3] = alpha as f32 / 255.0;
+                }
+            }
+            b"IDAT" => compressed.extend_from_slice(data),
+            _ => {}
+        }
+    }
+
+    // Bytes of a row, and between a byte and the one filters predict it
+    // from.
+    let pixel_bits = channels * depth as usize;
+    let row_bytes = (width as usize)
+        .checked_mul(pixel_bits)
+        .map(|bits| bits.div_ceil(8))
+        .ok_or_else(|| invalid("PNG image too large"))?;
+    let step = pixel_bits.div_ceil(8);
+    let mut data = zlib_decode(&compressed)?;
+    if Some(data.len()) != (row_bytes + 1).checked_mul(height as usize) {
+        return Err(invalid("PNG image data of the wrong size"));
+    }
+
+    let mut previous = vec![0; row_bytes];
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+    for row in data.chunks_exact_mut(row_bytes + 1) {
+        let (filter, row) = row.split_at_mut(1);
+        for index in 0..row_bytes {
+            let left = if index >= step { row[index - step] } else { 0 };
+            let corner = if index >= step {
+                previous[index - step]
+            } else {
+                0
+            };
+            let above = previous[index];
+            let prediction = match filter[0] {
+                0 => 0,
+                1 => left,
+                2 => above,
+                3 => ((u16::from(left) + u16::from(above)) / 2) as u8,
+                4 => paeth(left, above, corner),
+                _ => return Err(invalid("unknown PNG filter")),
+            };
+            row[index] = row[index].wrapping_add(prediction);
+        }
+
+        // Samples of fewer than 8 bits are packed from the high bit down.
+        let sample = |index: usize| match depth {
+            16 => u16::from_be_bytes([row[2 * index], row[2 * index + 1]]),
+            8 => u16::from(row[index]),
+            _ => {
+                let bit = index * depth as usize;
+                u16::from(row[bit / 8] >> (8 - depth as usize - bit % 8) & ((1 << depth) - 1))
+            }
+        };
+        let max = ((1u32 << depth) - 1) as f32;
+        let value = |index: usize| sample(index) as f32 / max;
+        for first in (0..width as usize).map(|x| x * channels) {
+            match color_type {
+                0 => pixels.extend_from_slice(&[value(first), value(first), value(first), 1.0]),
+                2 => pixels.extend_from_slice(&[
+                    value(first),
+                    value(first + 1),
+                    value(first + 2),
+                    1.0,
+                ]),
+                3 => {
+                    let color = palette
+                        .get(sample(first) as usize)
+                        .ok_or_else(|| invalid("PNG pixel outside of the palette"))?;
+                    pixels.extend_from_slice(color);
+                }
+                4 => {
+                    let gray = value(first);
+                    pixels.extend_from_slice(&[gray, gray, gray, value(first + 1)]);
+                }
+                _ => pixels.extend((0..4).map(|channel| value(first + channel))),
+            }
+        }
+        previous.copy_from_slice(row);
+    }
+    Ok((pixels, width, height))
+}
@@ -0,0 +1,402 @@
+use crate::aov::AovImages;
+use crate::maths::*;
+
+use rayon::prelude::*;
+
+/// B3 spline coefficients of the 5x5 à-trous kernel.
+const KERNEL: [Float; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+#[derive(Clone, Copy, Debug)]
+pub struct DenoiseSettings {
+    /// Number of à-trous passes, the footprint doubling with every pass.
+    pub iterations: usize,
+    /// Luminance difference tolerated between blended pixels, in standard
+    /// deviations of the noise around the filtered pixel.
+    pub sigma_color: Float,
+    /// Normal difference tolerated between blended pixels.
+    pub sigma_normal: Float,
+    /// Depth difference tolerated between blended pixels, relative to the
+    /// depth of the filtered pixel and to the distance between both.
+    pub sigma_depth: Float,
+    /// Albedo difference tolerated between blended pixels, so textures and
+    /// the borders between materials stay sharp.
+    pub sigma_albedo: Float,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        DenoiseSettings {
+            iterations: 5,
+            sigma_color: 4.0,
+            sigma_normal: 0.3,
+            sigma_depth: 0.05,
+            sigma_albedo: 0.1,
+        }
+    }
+}
+
+fn normal_at(aovs: &AovImages, index: usize) -> Vec3 {
+    Vec3::new(
+        aovs.normal[index * 3],
+        aovs.normal[index * 3 + 1],
+        aovs.normal[index * 3 + 2],
+    )
+}
+
+fn albedo_at(aovs: &AovImages, index: usize) -> Vec3 {
+    Vec3::new(
+        aovs.albedo[index * 3],
+        aovs.albedo[index * 3 + 1],
+        aovs.albedo[index * 3 + 2],
+    )
+}
+
+/// What pixels are divided by before filtering, and multiplied by after:
+/// their albedo, or 1 for channels without any, like the background.
+fn modulation(aovs: &AovImages, index: usize) -> [Float; 3] {
+    let albedo = albedo_at(aovs, index);
+    [albedo.x, albedo.y, albedo.z].map(|x| if x > 1e-3 { x } else { 1.0 })
+}
+
+fn pixel_luminance(pixels: &[Float], index: usize) -> Float {
+    luminance(Vec3::new(
+        pixels[index * 4],
+        pixels[index * 4 + 1],
+        pixels[index * 4 + 2],
+    ))
+}
+
+/// Variance of the luminance of the 3x3 pixels around each pixel, standing
+/// for the variance of its noise, which renders don't keep.
+fn local_variance(pixels: &[Float], width: usize, height: usize) -> Vec<Float> {
+    let mut variance = vec![0.0; width * height];
+    variance
+        .par_chunks_mut(width)
+        .enumerate()
+        .for_each(|(j, row)| {
+            for (i, value) in row.iter_mut().enumerate() {
+                let (mut sum, mut sum_squared, mut count) = (0.0, 0.0, 0.0);
+                for y in j.saturating_sub(1)..(j + 2).min(height) {
+                    for x in i.saturating_sub(1)..(i + 2).min(width) {
+                        let l = pixel_luminance(pixels, y * width + x);
+                        sum += l;
+                        sum_squared += l * l;
+                        count += 1.0;
+                    }
+                }
+                let mean = sum / count;
+                *value = (sum_squared / count - mean * mean).max(0.0);
+            }
+        });
+    variance
+}
+
+/// `variance` blurred by a 3x3 Gaussian, steadying the color weights of
+/// `denoise`.
+fn blurred_variance(variance: &[Float], width: usize, height: usize) -> Vec<Float> {
+    const WEIGHTS: [Float; 3] = [0.25, 0.5, 0.25];
+    let mut blurred = vec![0.0; width * height];
+    blurred
+        .par_chunks_mut(width)
+        .enumerate()
+        .for_each(|(j, row)| {
+            for (i, value) in row.iter_mut().enumerate() {
+                let (mut sum, mut total_weight) = (0.0, 0.0);
+                for (ky, &hy) in WEIGHTS.iter().enumerate() {
+                    let y = j as isize + ky as isize - 1;
+                    if y < 0 || y >= height as isize {
+                        continue;
+                    }
+                    for (kx, &hx) in WEIGHTS.iter().enumerate() {
+                        let x = i as isize + kx as isize - 1;
+                        if x < 0 || x >= width as isize {
+                            continue;
+                        }
+                        sum += variance[y as usize * width + x as usize] * hx * hy;
+                        total_weight += hx * hy;
+                    }
+                }
+                *value = sum / total_weight;
+            }
+        });
+    blurred
+}
+
+/// Smooths the noise of row-major, premultiplied RGBA `pixels` with an
+/// edge-avoiding à-trous wavelet filter (Dammertz et al. 2010), guided by
+/// the normals, depths and albedos of `aovs`. Neighbours are blended less
+/// the more their normal, depth or albedo differ, so edges, silhouettes and
+/// textures survive while flat areas get averaged.
+///
+/// Like SVGF (Schied et al. 2017), the filter works on the lighting alone,
+/// the pixels divided by their albedo, and weighs luminance differences by
+/// the noise around each pixel, estimated from its neighbours and filtered
+/// along with the image, so even renders of a few samples per pixel come
+/// out clean while details the noise can't explain stay.
+pub fn denoise(
+    pixels: &[Float],
+    aovs: &AovImages,
+    width: usize,
+    height: usize,
+    settings: &DenoiseSettings,
+) -> Vec<Float> {
+    let mut current = pixels.to_vec();
+    for (index, pixel) in current.chunks_mut(4).enumerate() {
+        for (value, factor) in pixel.iter_mut().zip(modulation(aovs, index)) {
+            *value /= factor;
+        }
+    }
+    let mut variance = local_variance(&current, width, height);
+
+    for iteration in 0..settings.iterations {
+        let step = 1 << iteration;
+        let blurred = blurred_variance(&variance, width, height);
+        let mut next = vec![0.0; current.len()];
+        let mut next_variance = vec![0.0; variance.len()];
+
+        next.par_chunks_mut(width * 4)
+            .zip(next_variance.par_chunks_mut(width))
+            .enumerate()
+            .for_each(|(j, (row, row_variance))| {
+                for i in 0..width {
+                    let center = j * width + i;
+                    let luminance = pixel_luminance(&current, center);
+                    let normal = normal_at(aovs, center);
+                    let albedo = albedo_at(aovs, center);
+                    let depth = aovs.depth[center];
+                    let color_scale = settings.sigma_color * blurred[center].sqrt() + 1e-6;
+
+                    let mut sum = [0.0; 4];
+                    let mut sum_variance = 0.0;
+                    let mut total_weight = 0.0;
+                    for (ky, &hy) in KERNEL.iter().enumerate() {
+                        let y = j as isize + (ky as isize - 2) * step;
+                        if y < 0 || y >= height as isize {
+                            continue;
+                        }
+                        for (kx, &hx) in KERNEL.iter().enumerate() {
+                            let x = i as isize + (kx as isize - 2) * step;
+                            if x < 0 || x >= width as isize {
+                                continue;
+                            }
+
+                            let other = y as usize * width + x as usize;
+                            let other_color = &current[other * 4..other * 4 + 4];
+
+                            let color_distance =
+                                (luminance - pixel_luminance(&current, other)).abs() / color_scale;
+                            let normal_distance =
+                                (normal - normal_at(aovs, other)).length_squared();
+                            let albedo_distance =
+                                (albedo - albedo_at(aovs, other)).length_squared();
+                            let other_depth = aovs.depth[other];
+                            let depth_distance = if depth.is_finite() && other_depth.is_finite() {
+                                (depth - other_depth).abs()
+                                    / (settings.sigma_depth * depth * step as Float + 1e-9)
+                            } else if depth.is_finite() || other_depth.is_finite() {
+                                Float::INFINITY
+                            } else {
+                                0.0
+                            };
+
+                            let weight = hx
+                                * hy
+                                * Float::exp(
+                                    -color_distance
+                                        - normal_distance
+                                            / (settings.sigma_normal * settings.sigma_normal)
+                                        - albedo_distance
+                                            / (settings.sigma_albedo * settings.sigma_albedo)
+                                        - depth_distance,
+                                );
+
+                            for (c, value) in sum.iter_mut().enumerate() {
+                                *value += other_color[c] * weight;
+                            }
+                            sum_variance += variance[other] * weight * weight;
+                            total_weight += weight;
+                        }
+                    }
+
+                    // The center pixel always has a weight, so this never divides by zero.
+                    for c in 0..4 {
+                        row[i * 4 + c] = sum[c] / total_weight;
+                    }
+                    row_variance[i] = sum_variance / (total_weight * total_weight);
+                }
+            });
+
+        current = next;
+        variance = next_variance;
+    }
+
+    for (index, pixel) in current.chunks_mut(4).enumerate() {
+        for (value, factor) in pixel.iter_mut().zip(modulation(aovs, index)) {
+            *value *= factor;
+        }
+    }
+    current
+}
+
+/// Lines of the image the levels of `SensorNoise` are given for.
+const REFERENCE_LINES: Float = 1080.0;
+
+/// Noise of a real camera, added to a render so it matches footage or gets
+/// a filmic texture, see `add_sensor_noise`.
+///
+/// Levels are given for an image of 1080 lines. Other resolutions spread
+/// the same light over more or fewer pixels, so a render looks as noisy as
+/// another of a different size once both are shown at the same size.
+#[derive(Clone, Copy, Debug)]
+pub struct SensorNoise {
+    /// Photons collected by a pixel of linear value 1, the fewer the
+    /// noisier. 0 leaves out the shot noise.
+    pub photons: Float,
+    /// Standard deviation of the noise of the sensor electronics, in photons,
+    /// or in linear values without shot noise.
+    pub read_noise: Float,
+    /// Standard deviation of the film grain, relative to the pixel values.
+    pub grain: Float,
+    /// Width of the grains, in pixels of a 1080 line image.
+    pub grain_size: Float,
+    /// Picks the noise, the same seed giving the same noise.
+    pub seed: u64,
+}
+
+impl Default for SensorNoise {
+    /// A good sensor in daylight, without grain.
+    fn default() -> Self {
+        SensorNoise {
+            photons: 10_000.0,
+            read_noise: 3.0,
+            grain: 0.0,
+            grain_size: 1.5,
+            seed: 0,
+        }
+    }
+}
+
+/// Uniform number in [0, 1) hashed from `seed` and `index`.
+fn hashed_uniform(seed: u64, index: u64) -> Float {
+    uniform_from_bits(mix_seed(seed, index))
+}
+
+/// Standard normal number hashed from `seed` and `index`, by Box-Muller.
+fn hashed_normal(seed: u64, index: u64) -> Float {
+    let u1 = 1.0 - hashed_uniform(seed, index);
+    let u2 = hashed_uniform(!seed, index);
+    (-2.0 * u1.ln()).sqrt() * (2.0 * consts::PI * u2).cos()
+}
+
+/// Number of photons arriving when `mean` are expected, Poisson distributed,
+/// approximated by a normal distribution past a few dozens.
+fn photon_count(mean: Float, seed: u64) -> Float {
+    if mean > 30.0 {
+        return (mean + mean.sqrt() * hashed_normal(seed, 0)).max(0.0);
+    }
+
+    // Counts the arrivals of a Poisson process before time `mean`.
+    let mut time = 0.0;
+    let mut count = 0;
+    loop {
+        time -= (1.0 - hashed_uniform(seed, 1 + count)).ln();
+        if time > mean {
+            return count as Float;
+        }
+        count += 1;
+    }
+}
+
+/// Film grain at `(x, y)`, in grains: smooth noise of standard deviation
+/// about 1 over a lattice of one grain per cell.
+fn grain_at(x: Float, y: Float, seed: u64) -> Float {
+    let (cell_x, cell_y) = (x.floor(), y.floor());
+    let smooth = |t: Float| t * t * (3.0 - 2.0 * t);
+    let (tx, ty) = (smooth(x - cell_x), smooth(y - cell_y));
+    let corner = |dx: Float, dy: Float| {
+        let (i, j) = ((cell_x + dx) as i64 as u64, (cell_y + dy) as i64 as u64);
+        hashed_normal(seed, mix_seed(i, j))
+    };
+    let top = corner(0.0, 0.0) * (1.0 - tx) + corner(1.0, 0.0) * tx;
+    let bottom = corner(0.0, 1.0) * (1.0 - tx) + corner(1.0, 1.0) * tx;
+    // Blending the corners loses some of their spread, which this gives back.
+    (top * (1.0 - ty) + bottom * ty) * 1.35
+}
+
+/// Adds the shot noise of the light, the read noise of the sensor and film
+/// grain to row-major, premultiplied RGBA `pixels`, in place. Colors are
+/// clipped at 0 like on a sensor, and alpha is left alone.
+pub fn add_sensor_noise(pixels: &mut [Float], width: usize, height: usize, noise: &SensorNoise) {
+    let scale = REFERENCE_LINES / height as Float;
+    let photons = noise.photons * scale * scale;
+    // Pixels of lower resolutions stand for several pixels of the reference
+    // one, summing their read noise in photons and averaging it in values.
+    let read_noise = if photons > 0.0 {
+        noise.read_noise * scale
+    } else {
+        noise.read_noise / scale
+    };
+    // Grains keep their size relative to the frame.
+    let grain_size = (noise.grain_size / scale).max(1e-3);
+    let (shot_seed, read_seed, grain_seed) = (
+        mix_seed(noise.seed, 0),
+        mix_seed(noise.seed, 1),
+        mix_seed(noise.seed, 2),
+    );
+
+    pixels
+        .par_chunks_mut(width * 4)
+        .enumerate()
+        .for_each(|(j, row)| {
+            for (i, pixel) in row.chunks_mut(4).enumerate() {
+                let index = ((j * width + i) * 3) as u64;
+                let alpha = pixel[3];
+                let grain = if noise.grain > 0.0 {
+                    let x = (i as Float + 0.5) / grain_size;
+                    let y = (j as Float + 0.5) / grain_size;
+                    1.0 + noise.grain * grain_at(x, y, grain_seed)
+                } else {
+                    1.0
+                };
+
+                for (c, value) in pixel[..3].iter_mut().enumerate() {
+                    let index = index + c as u64;
+                    // Empty pixels of transparent backgrounds stay empty.
+                    let read = read_noise * alpha * hashed_normal(read_seed, index);
+                    let signal = if photons > 0.0 {
+                        let mean = value.max(0.0) * photons;
+                        (photon_count(mean, mix_seed(shot_seed, index)) + read) / photons
+                    } else {
+                        *value + read
+                    };
+                    *value = (signal * grain).max(0.0);
+                }
+            }
+        });
+}
+
+/// How the colors of images with alpha relate to it. Renders are
+/// premultiplied, which compositing over other images needs, but some
+/// formats and packages expect straight colors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// Colors as they would be if the pixel were opaque, alpha only telling
+    /// how much they cover.
+    Straight,
+    /// Colors already scaled by alpha.
+    Premultiplied,
+}
+
+/// Turns row-major, premultiplied RGBA `pixels` into straight ones, in
+/// place. Fully transparent pixels have no color to recover, and keep the
+/// light they add, if any.
+pub fn unpremultiply(pixels: &mut [Float]) {
+    for pixel in pixels.chunks_mut(4) {
+        let alpha = pixel[3];
+        if alpha > 0.0 {
+            for channel in &mut pixel[..3] {
+                *channel /= alpha;
+            }
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use crate::environment::Environment;
+use crate::maths::consts::PI;
+use crate::maths::*;
+
+/// Environment lookups averaged into every cell of a `PreviewEnvironment`,
+/// per side.
+const SAMPLES_PER_CELL_SIDE: usize = 8;
+
+/// Environment boiled down to what previews shade with: the distribution of
+/// its light over the cells of a small latitude/longitude grid, to aim shadow
+/// rays at its bright parts, and the irradiance it sheds on every normal,
+/// projected on the first nine spherical harmonics. Building one reads the
+/// environment a fixed number of times, so previews stay quick however large
+/// its map is.
+///
+/// The cells follow `EnvironmentMap`: the middle of the map faces -z, with
+/// +y at the top.
+#[derive(Clone, Debug)]
+pub struct PreviewEnvironment {
+    columns: usize,
+    rows: usize,
+    /// Cumulative light of the rows, then of the cells of every row, each
+    /// ending with 1.
+    row_cdf: Vec<Float>,
+    cell_cdf: Vec<Float>,
+    /// Spherical harmonics coefficients of the radiance, already convolved
+    /// with the cosine lobe.
+    irradiance: [Vec3; 9],
+}
+
+impl PreviewEnvironment {
+    /// Prefilters `environment` into `columns` by `columns / 2` cells.
+    pub fn build(environment: &Environment, columns: usize) -> Self {
+        let columns = columns.max(2);
+        let rows = columns / 2;
+        let side = SAMPLES_PER_CELL_SIDE;
+        // The sun is far smaller than the cells, so it is left out of the
+        // lookups and added whole to the cell holding it.
+        let radiance = |direction| match environment.sun() {
+            Some(sky) => sky.sky_radiance(direction),
+            None => environment.radiance(direction),
+        };
+
+        let mut irradiance = [Vec3::new(0.0, 0.0, 0.0); 9];
+        let mut weights = Vec::with_capacity(columns * rows);
+        for j in 0..rows {
+            for i in 0..columns {
+                // Cells near the poles cover less of the sphere.
+                let cell_area = cell_solid_angle((j as Float + 0.5) / rows as Float, columns, rows);
+                let mut sum = Vec3::new(0.0, 0.0, 0.0);
+                for y in 0..side {
+                    for x in 0..side {
+                        let s =
+                            (i as Float + (x as Float + 0.5) / side as Float) / columns as Float;
+                        let t = (j as Float + (y as Float + 0.5) / side as Float) / rows as Float;
+                        let direction = map_direction(s, t);
+                        let radiance = radiance(direction);
+                        sum += radiance;
+
+                        let solid_angle =
+                            cell_solid_angle(t, columns, rows) / (side * side) as Float;
+                        add_sh(&mut irradiance, direction, radiance * solid_angle);
+                    }
+                }
+                let radiance = sum / (side * side) as Float;
+                weights.push(luminance(radiance).max(0.0) * cell_area);
+            }
+        }
+        if let Some(sky) = environment.sun() {
+            let direction = sky.sun_direction();
+            let radiance = sky.radiance(direction) - sky.sky_radiance(direction);
+            let solid_angle = 1.0 / sky.sun_pdf(direction);
+            add_sh(&mut irradiance, direction, radiance * solid_angle);
+
+            let (s, t) = map_coordinates(direction);
+            let i = ((s * columns as Float) as usize).min(columns - 1);
+            let j = ((t * rows as Float) as usize).min(rows - 1);
+            weights[j * columns + i] += luminance(radiance) * solid_angle;
+        }
+
+        let mut cell_cdf = Vec::with_capacity(columns * rows);
+        let mut row_cdf = Vec::with_capacity(rows);
+        let mut total = 0.0;
+        for row in weights.chunks(columns) {
+            cell_cdf.extend(normalized(running_sums(row)));
+            total += row.iter().sum::<Float>();
+            row_cdf.push(total);
+        }
+
+        // Irradiance is radiance convolved with the clamped cosine, which
+        // scales every band of the harmonics by its own factor.
+        let bands = [PI, 2.0 * PI / 3.0, PI / 4.0];
+        for (index, coefficient) in irradiance.iter_mut().enumerate() {
+            let band = match index {
+                0 => 0,
+                1..=3 => 1,
+                _ => 2,
+            };
+            *coefficient = *coefficient * bands[band];
+        }
+
+        PreviewEnvironment {
+            columns,
+            rows,
+            row_cdf: normalized(row_cdf),
+            cell_cdf,
+            irradiance,
+        }
+    }
+
+    /// Number of cells across the map.
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// Irradiance reaching a surface facing `normal` from the whole
+    /// environment, ignoring what blocks it.
+    pub fn irradiance(&self, normal: Vec3) -> Vec3 {
+        let basis = sh_basis(normal.unit());
+        let irradiance = self
+            .irradiance
+            .iter()
+            .zip(&basis)
+            .fold(Vec3::new(0.0, 0.0, 0.0), |sum, (&coefficient, &value)| {
+                sum + coefficient * value
+            });
+        // Nine harmonics can ring below zero behind very bright spots.
+        Vec3::new(
+            irradiance.x.max(0.0),
+            irradiance.y.max(0.0),
+            irradiance.z.max(0.0),
+        )
+    }
+
+    /// Direction picked with the probability of the light arriving from it,
+    /// from a uniform point of the unit square. Uniform over the cells of a
+    /// black environment.
+    pub fn sample(&self, (u1, u2): (Float, Float)) -> Vec3 {
+        let j = pick(&self.row_cdf, u1);
+        let row = &self.cell_cdf[j * self.columns..(j + 1) * self.columns];
+        let i = pick(row, u2);
+
+        // Reuse what is left of the numbers inside the picked cell.
+        let within = |cdf: &[Float], index: usize, u: Float| {
+            let start = if index == 0 { 0.0 } else { cdf[index - 1] };
+            let width = cdf[index] - start;
+            if width > 0.0 {
+                clamp((u - start) / width, 0.0, 1.0)
+            } else {
+                0.5
+            }
+        };
+        let s = (i as Float + within(row, i, u2)) / self.columns as Float;
+        let t = (j as Float + within(&self.row_cdf, j, u1)) / self.rows as Float;
+        map_direction(s, t)
+    }
+}
+
+fn running_sums(weights: &[Float]) -> Vec<Float> {
+    let mut total = 0.0;
+    weights
+        .iter()
+        .map(|weight| {
+            total += weight;
+            total
+        })
+        .collect()
+}
+
+/// Running sums `sums` divided by their total, or uniform steps when they
+/// are all zero.
+fn normalized(sums: Vec<Float>) -> Vec<Float> {
+    let count = sums.len();
+    match sums.last() {
+        Some(&total) if total > 0.0 => sums.iter().map(|sum| sum / total).collect(),
+        _ => (1..=count).map(|k| k as Float / count as Float).collect(),
+    }
+}
+
+/// First index whose cumulative value exceeds `u`.
+fn pick(cdf: &[Float], u: Float) -> usize {
+    cdf.iter()
+        .position(|&value| u < value)
+        .unwrap_or(cdf.len() - 1)
+}
+
+/// Direction at (`s`, `t`) of a latitude/longitude map, both in [0, 1).
+fn map_direction(s: Float, t: Float) -> Vec3 {
+    let longitude = (s - 0.5) * 2.0 * PI;
+    let latitude = (0.5 - t) * PI;
+    Vec3::new(
+        latitude.cos() * longitude.sin(),
+        latitude.sin(),
+        -latitude.cos() * longitude.cos(),
+    )
+}
+
+/// Inverse of `map_direction`.
+fn map_coordinates(direction: Vec3) -> (Float, Float) {
+    let direction = direction.unit();
+    let longitude = direction.x.atan2(-direction.z);
+    let latitude = clamp(direction.y, -1.0, 1.0).asin();
+    (longitude / (2.0 * PI) + 0.5, 0.5 - latitude / PI)
+}
+
+/// Solid angle of a cell of a `columns` by `rows` map at height `t`.
+fn cell_solid_angle(t: Float, columns: usize, rows: usize) -> Float {
+    let latitude = (0.5 - t) * PI;
+    latitude.cos() * (2.0 * PI / columns as Float) * (PI / rows as Float)
+}
+
+/// Projects `light` arriving from the unit `direction` onto `coefficients`.
+fn add_sh(coefficients: &mut [Vec3; 9], direction: Vec3, light: Vec3) {
+    for (coefficient, &value) in coefficients.iter_mut().zip(&sh_basis(direction)) {
+        *coefficient += light * value;
+    }
+}
+
+/// Real spherical harmonics of the first three bands at the unit `direction`.
+fn sh_basis(direction: Vec3) -> [Float; 9] {
+    let Vec3 { x, y, z } = direction;
+    [
+        0.282_095,
+        0.488_603 * y,
+        0.488_603 * z,
+        0.488_603 * x,
+        1.092_548 * x * y,
+        1.092_548 * y * z,
+        0.315_392 * (3.0 * z * z - 1.0),
+        1.092_548 * x * z,
+        0.546_274 * (x * x - y * y),
+    ]
+}
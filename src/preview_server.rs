@@ -0,0 +1,98 @@
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Page served at `/`: the preview, fetched again every second.
+const PAGE: &str = "<!DOCTYPE html>
+<html>
+<head><meta charset=\"utf-8\"><title>raytracer</title></head>
+<body style=\"margin: 0; background: #222\">
+<img id=\"preview\" src=\"preview.png\" style=\"display: block; margin: auto; max-width: 100%\">
+<script>
+setInterval(function () {
+  var next = new Image();
+  next.onload = function () { document.getElementById('preview').src = next.src; };
+  next.src = 'preview.png?' + Date.now();
+}, 1000);
+</script>
+</body>
+</html>
+";
+
+/// Latest preview of a render, served over HTTP so renders running on
+/// machines without a screen can be watched from a browser: `/` is a page
+/// showing it and fetching it again every second, `/preview.png` the PNG
+/// file last handed to `publish`, or a 503 until there is one.
+///
+/// Clones share the same preview.
+#[derive(Clone, Debug, Default)]
+pub struct PreviewServer {
+    png: Arc<Mutex<Option<Arc<Vec<u8>>>>>,
+}
+
+impl PreviewServer {
+    /// Server answering the connections to `listener` from a background
+    /// thread, each on its own, for as long as the program runs.
+    pub fn start(listener: TcpListener) -> Self {
+        let server = PreviewServer::default();
+        let shared = server.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let server = shared.clone();
+                // Browsers going away halfway through are no concern of the
+                // render.
+                thread::spawn(move || server.answer(stream));
+            }
+        });
+        server
+    }
+
+    /// Serves the bytes of the PNG file `png` from now on.
+    pub fn publish(&self, png: Vec<u8>) {
+        *self.png.lock().unwrap() = Some(Arc::new(png));
+    }
+
+    /// Answers the request of a connection, then closes it.
+    fn answer(&self, stream: TcpStream) -> std::io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request = String::new();
+        reader.read_line(&mut request)?;
+        // The headers tell nothing the answers depend on.
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+            header.clear();
+        }
+
+        let mut words = request.split_whitespace();
+        let (method, target) = (words.next(), words.next().unwrap_or_default());
+        let path = target.split('?').next().unwrap_or_default();
+        let png = self.png.lock().unwrap().clone();
+        let (status, kind, body): (_, _, &[u8]) = match (method, path, &png) {
+            (Some("GET"), "/", _) => ("200 OK", "text/html; charset=utf-8", PAGE.as_bytes()),
+            (Some("GET"), "/preview.png", Some(png)) => ("200 OK", "image/png", png),
+            (Some("GET"), "/preview.png", None) => {
+                ("503 Service Unavailable", "text/plain", b"no preview yet\n")
+            }
+            (Some("GET"), _, _) => ("404 Not Found", "text/plain", b"not found\n"),
+            _ => (
+                "405 Method Not Allowed",
+                "text/plain",
+                b"only GET is answered\n",
+            ),
+        };
+
+        let mut writer = stream;
+        write!(
+            writer,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+             Cache-Control: no-store\r\nConnection: close\r\n\r\n",
+            status,
+            kind,
+            body.len()
+        )?;
+        writer.write_all(body)?;
+        writer.flush()
+    }
+}
@@ -0,0 +1,246 @@
+use crate::material::BounceKind;
+use crate::maths::shading::*;
+use crate::maths::*;
+use crate::texture::Texture;
+
+use std::sync::Arc;
+
+/// GGX width of the clearcoat, a polished varnish.
+const CLEARCOAT_ALPHA: Float = 0.05;
+
+/// Reflectance of the clearcoat head on, that of a varnish of index 1.5.
+const CLEARCOAT_F0: Float = 0.04;
+
+/// Disney's principled BSDF, after Burley 2012, "Physically-Based Shading at
+/// Disney", and 2015, "Extending the Disney BRDF to a BSDF with Integrated
+/// Subsurface Scattering". Parameters go from 0 to 1 and mean what they do
+/// in Blender's Principled BSDF, so materials made there carry over.
+///
+/// It mixes a diffuse lobe with retro-reflection and sheen, a GGX specular
+/// lobe, a rough glass lobe letting light through and a clearcoat on top.
+/// Metals lose the diffuse and glass lobes and tint their reflections with
+/// the base color.
+#[derive(Clone, Debug)]
+pub struct Principled {
+    pub base_color: Vec3,
+    pub metallic: Float,
+    pub roughness: Float,
+    /// Reflectance of non-metals head on, 0.5 giving the 4% of most
+    /// materials. It also sets the refractive index of transmission.
+    pub specular: Float,
+    /// White reflection at grazing angles, like the fibers of cloth.
+    pub sheen: Float,
+    /// Strength of a polished, colorless reflection on top of the rest.
+    pub clearcoat: Float,
+    /// Share of the light going through the surface, like glass.
+    pub transmission: Float,
+    /// Light given off by the surface, black for most materials.
+    pub emission: Vec3,
+    /// Images changing the parameters over the surface, shared between the
+    /// copies of the material, see `at`.
+    pub textures: Option<Arc<PrincipledTextures>>,
+}
+
+/// Images looked up by the texture coordinates of hits, multiplying the
+/// parameters of a `Principled` material, like the textures of glTF
+/// materials.
+#[derive(Clone, Debug, Default)]
+pub struct PrincipledTextures {
+    pub base_color: Option<Texture>,
+    /// Roughness in green and metallic in blue.
+    pub metallic_roughness: Option<Texture>,
+    pub emission: Option<Texture>,
+}
+
+impl Default for Principled {
+    /// The defaults of Blender, a light gray plastic.
+    fn default() -> Self {
+        Principled {
+            base_color: Vec3::new(0.8, 0.8, 0.8),
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            sheen: 0.0,
+            clearcoat: 0.0,
+            transmission: 0.0,
+            emission: Vec3::new(0.0, 0.0, 0.0),
+            textures: None,
+        }
+    }
+}
+
+impl Principled {
+    /// The material at texture coordinates `uv`, its textures multiplying
+    /// its parameters there. Surfaces without texture coordinates take the
+    /// parameters as they are.
+    pub fn at(&self, uv: Option<(Float, Float)>) -> Principled {
+        let mut principled = Principled {
+            textures: None,
+            ..*self
+        };
+        if let (Some(textures), Some((u, v))) = (&self.textures, uv) {
+            if let Some(texture) = &textures.base_color {
+                principled.base_color = principled.base_color * texture.lookup(u, v);
+            }
+            if let Some(texture) = &textures.metallic_roughness {
+                let value = texture.lookup(u, v);
+                principled.roughness *= value.y;
+                principled.metallic *= value.z;
+            }
+            if let Some(texture) = &textures.emission {
+                principled.emission = principled.emission * texture.lookup(u, v);
+            }
+        }
+        principled
+    }
+
+    /// Refractive index reflecting as much head on as `specular` asks.
+    pub fn refractive_index(&self) -> Float {
+        let r = clamp(0.08 * self.specular, 0.0, 0.9).sqrt();
+        (1.0 + r) / (1.0 - r)
+    }
+
+    /// Reflectance of the specular lobe head on.
+    fn f0(&self) -> Vec3 {
+        let dielectric = Vec3::new(1.0, 1.0, 1.0) * (0.08 * self.specular);
+        dielectric * (1.0 - self.metallic) + self.base_color * self.metallic
+    }
+
+    /// Weights of the diffuse, specular, glass and clearcoat lobes.
+    fn weights(&self) -> [Float; 4] {
+        let (metallic, transmission) = (
+            clamp(self.metallic, 0.0, 1.0),
+            clamp(self.transmission, 0.0, 1.0),
+        );
+        [
+            (1.0 - metallic) * (1.0 - transmission),
+            1.0 - (1.0 - metallic) * transmission,
+            (1.0 - metallic) * transmission,
+            0.25 * self.clearcoat.max(0.0),
+        ]
+    }
+
+    /// Chances of sampling each lobe, following roughly how much light they
+    /// scatter. They only change the noise, not the image.
+    fn probabilities(&self) -> [Float; 4] {
+        let [diffuse, specular, glass, clearcoat] = self.weights();
+        let f0 = luminance(self.f0());
+        let mut probabilities = [
+            diffuse * (1.0 - f0),
+            specular * (f0 + 0.1).min(1.0),
+            glass,
+            clearcoat * 0.5,
+        ];
+        let total: Float = probabilities.iter().sum();
+        for probability in &mut probabilities {
+            *probability /= total;
+        }
+        probabilities
+    }
+
+    /// BSDF times the cosine of `wi`, and the density with which `sample`
+    /// draws `wi`, both directions in the shading frame and `indices` being
+    /// the refractive indices on the side of `wo` and on the other side.
+    pub fn eval(&self, wo: Vec3, wi: Vec3, indices: (Float, Float)) -> (Vec3, Float) {
+        let alpha = ggx_alpha(self.roughness);
+        let [diffuse, specular, glass, clearcoat] = self.weights();
+        let [p_diffuse, p_specular, p_glass, p_clearcoat] = self.probabilities();
+        let mut value = Vec3::new(0.0, 0.0, 0.0);
+        let mut pdf = 0.0;
+        if wo.z <= 0.0 {
+            return (value, pdf);
+        }
+
+        let reflection = if wi.z > 0.0 {
+            half_vector(wo, wi)
+        } else {
+            None
+        };
+        if let Some(h) = reflection {
+            let cos_d = clamp(wi.dot(h), 0.0, 1.0);
+
+            // Diffuse, brighter at grazing angles when rough (retro-reflection),
+            // darker when smooth, and sheen.
+            let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+            let retro = (1.0 + (fd90 - 1.0) * (1.0 - wi.z).powi(5))
+                * (1.0 + (fd90 - 1.0) * (1.0 - wo.z).powi(5));
+            let sheen = self.sheen * (1.0 - cos_d).powi(5);
+            let lobe = self.base_color * (retro / consts::PI) + Vec3::new(sheen, sheen, sheen);
+            // Diffuse lies under the specular lobe, only getting the light it
+            // lets through.
+            let layered = 1.0 - specular * luminance(fresnel_schlick_color(wo.z, self.f0()));
+            value += lobe * (diffuse * layered * wi.z);
+            pdf += p_diffuse * wi.z / consts::PI;
+
+            let cos_o = wo.dot(h);
+            if cos_o > 0.0 {
+                let d = ggx_d(h, alpha) * ggx_g(wo, wi, alpha);
+                let fresnel = fresnel_schlick_color(cos_o, self.f0());
+                value += fresnel * (specular * d / (4.0 * wo.z));
+                pdf += p_specular * ggx_visible_pdf(wo, h, alpha) / (4.0 * cos_o);
+
+                let d = ggx_d(h, CLEARCOAT_ALPHA) * ggx_g(wo, wi, CLEARCOAT_ALPHA);
+                let coat = clearcoat * fresnel_schlick(cos_o, CLEARCOAT_F0) * d / (4.0 * wo.z);
+                value += Vec3::new(coat, coat, coat);
+                pdf += p_clearcoat * ggx_visible_pdf(wo, h, CLEARCOAT_ALPHA) / (4.0 * cos_o);
+            }
+        }
+
+        if p_glass > 0.0 {
+            let (glass_value, glass_pdf) = ggx_scattering(wo, wi, alpha, Some(indices));
+            // Light going through takes the base color.
+            let tint = if wi.z > 0.0 {
+                Vec3::new(1.0, 1.0, 1.0)
+            } else {
+                self.base_color
+            };
+            value += tint * (glass * glass_value);
+            pdf += p_glass * glass_pdf;
+        }
+
+        (value, pdf)
+    }
+
+    /// Direction scattered from `wo`, in the shading frame, and the kind of
+    /// bounce it comes from, from three uniform numbers: `u` picking the lobe
+    /// and the two others the direction. `None` when it would leave through
+    /// the wrong side of the surface.
+    pub fn sample(
+        &self,
+        wo: Vec3,
+        indices: (Float, Float),
+        u: Float,
+        (u1, u2): (Float, Float),
+    ) -> Option<(Vec3, BounceKind)> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let alpha = ggx_alpha(self.roughness);
+        let [p_diffuse, p_specular, p_glass, _] = self.probabilities();
+
+        let (wi, kind) = if u < p_diffuse {
+            (sample_cosine_direction((u1, u2)), BounceKind::Diffuse)
+        } else if u < p_diffuse + p_specular {
+            let m = sample_ggx_visible(wo, alpha, u1, u2);
+            (reflect(-wo, m), BounceKind::Glossy)
+        } else if u < p_diffuse + p_specular + p_glass {
+            // What is left of `u` picks between reflection and refraction.
+            let u = (u - p_diffuse - p_specular) / p_glass;
+            let m = sample_ggx_visible(wo, alpha, u1, u2);
+            let (eta_o, eta_i) = indices;
+            if u < fresnel_dielectric(wo.dot(m), eta_i / eta_o) {
+                (reflect(-wo, m), BounceKind::Glossy)
+            } else {
+                (refract(-wo, m, eta_o / eta_i), BounceKind::Transmission)
+            }
+        } else {
+            let m = sample_ggx_visible(wo, CLEARCOAT_ALPHA, u1, u2);
+            (reflect(-wo, m), BounceKind::Glossy)
+        };
+
+        if (wi.z > 0.0) == (kind == BounceKind::Transmission) || wi.z == 0.0 {
+            return None;
+        }
+        Some((wi, kind))
+    }
+}
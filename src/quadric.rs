@@ -0,0 +1,554 @@
+use crate::hitable::*;
+use crate::material::MaterialId;
+use crate::maths::*;
+use crate::plane::Disk;
+use crate::ray::Ray;
+use crate::sphere::UvFrame;
+
+/// Solid cylinder standing on `base` along `axis`, closed by a disk at both
+/// ends. Around its side `u` goes once around the axis and `v` from the base
+/// to the top, and its caps are laid out like `Disk`s facing out.
+#[derive(Clone, Copy, Debug)]
+pub struct Cylinder {
+    /// Center of the bottom cap.
+    pub base: Vec3,
+    /// Unit vector from the bottom cap to the top one.
+    pub axis: Vec3,
+    pub height: Float,
+    pub radius: Float,
+    pub material: MaterialId,
+}
+
+impl Cylinder {
+    pub fn new(base: Vec3, axis: Vec3, height: Float, radius: Float, material: MaterialId) -> Self {
+        Cylinder {
+            base,
+            axis: axis.unit(),
+            height,
+            radius,
+            material,
+        }
+    }
+
+    /// Whether `point`, on the surface, is on the side rather than a cap.
+    fn on_side(&self, point: Vec3) -> bool {
+        let offset = point - self.base;
+        let h = offset.dot(self.axis);
+        let distance = (offset - self.axis * h).length();
+        (distance - self.radius).abs() <= h.abs().min((h - self.height).abs())
+    }
+
+    /// Outward unit normal at `point`, on the surface.
+    pub fn normal_at(&self, point: Vec3) -> Vec3 {
+        let offset = point - self.base;
+        let h = offset.dot(self.axis);
+        if self.on_side(point) {
+            radial_direction(self.axis, offset)
+        } else if h < self.height / 2.0 {
+            -self.axis
+        } else {
+            self.axis
+        }
+    }
+
+    /// Texture coordinates of `point`, on the surface, with the frame normal
+    /// and bump maps bend normals in.
+    pub fn uv_frame(&self, point: Vec3) -> UvFrame {
+        let offset = point - self.base;
+        let h = offset.dot(self.axis);
+        if !self.on_side(point) {
+            let center = if h < self.height / 2.0 {
+                self.base
+            } else {
+                self.base + self.axis * self.height
+            };
+            let cap = Disk::new(center, self.normal_at(point), self.radius, self.material);
+            return cap.uv_frame(point);
+        }
+
+        let u = turns_around(self.axis, offset);
+        let radial = radial_direction(self.axis, offset);
+        UvFrame {
+            uv: (u, h / self.height),
+            tangents: (self.axis.cross(radial), self.axis),
+            lengths: (2.0 * consts::PI * self.radius, self.height),
+        }
+    }
+
+    /// Point and outward normal of the side at texture coordinates (`u`,
+    /// `v`).
+    pub fn surface_at(&self, u: Float, v: Float) -> (Vec3, Vec3) {
+        let radial = direction_at(self.axis, u);
+        let point = self.base + self.axis * (v * self.height) + radial * self.radius;
+        (point, radial)
+    }
+
+    /// Copy of the cylinder moved by `transform`, which should keep angles.
+    /// Lengths follow the average scale of the transform, like spheres.
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        let scale = transform.determinant3().abs().cbrt();
+        Cylinder::new(
+            transform.transform_point(self.base),
+            transform.transform_vector(self.axis),
+            self.height * scale,
+            self.radius * scale,
+            self.material,
+        )
+    }
+}
+
+impl Hitable for Cylinder {
+    fn scale(&mut self, factor: Float) {
+        self.base = self.base * factor;
+        self.height *= factor;
+        self.radius *= factor;
+    }
+
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let (axis, height) = (self.axis, self.height);
+        let oc = ray.origin - self.base;
+        // The side is where the distance to the axis is the radius.
+        let along = ray.dir - axis * axis.dot(ray.dir);
+        let across = oc - axis * axis.dot(oc);
+        let roots = quadratic_roots(
+            along.dot(along),
+            across.dot(along),
+            across.dot(across) - self.radius * self.radius,
+        );
+        let side = |t: Float| {
+            let offset = oc + ray.dir * t;
+            let h = offset.dot(axis);
+            if (0.0..=height).contains(&h) {
+                Some((t, offset - axis * h))
+            } else {
+                None
+            }
+        };
+
+        let top = self.base + axis * height;
+        let candidates = [
+            roots.and_then(|(t, _)| side(t)),
+            roots.and_then(|(_, t)| side(t)),
+            cap_hit(ray, self.base, -axis, self.radius),
+            cap_hit(ray, top, axis, self.radius),
+        ];
+        closest_hit(ray, &candidates, t_min, t_max, self.material)
+    }
+}
+
+/// Solid cone standing on `base` along `axis`, narrowing from `radius` to
+/// its apex and closed by a disk at the base. Around its side `u` goes once
+/// around the axis and `v` from the base to the apex, and its base is laid
+/// out like a `Disk` facing out.
+#[derive(Clone, Copy, Debug)]
+pub struct Cone {
+    /// Center of the base.
+    pub base: Vec3,
+    /// Unit vector from the base to the apex.
+    pub axis: Vec3,
+    pub height: Float,
+    /// Radius of the base.
+    pub radius: Float,
+    pub material: MaterialId,
+}
+
+impl Cone {
+    pub fn new(base: Vec3, axis: Vec3, height: Float, radius: Float, material: MaterialId) -> Self {
+        Cone {
+            base,
+            axis: axis.unit(),
+            height,
+            radius,
+            material,
+        }
+    }
+
+    fn apex(&self) -> Vec3 {
+        self.base + self.axis * self.height
+    }
+
+    /// Length of the side from the base to the apex.
+    fn slant(&self) -> Float {
+        Float::sqrt(self.height * self.height + self.radius * self.radius)
+    }
+
+    /// Whether `point`, on the surface, is on the side rather than the base.
+    fn on_side(&self, point: Vec3) -> bool {
+        let offset = point - self.base;
+        let h = offset.dot(self.axis);
+        let distance = (offset - self.axis * h).length();
+        let radius = self.radius * (1.0 - h / self.height);
+        (distance - radius).abs() <= h.abs()
+    }
+
+    /// Outward unit normal at `point`, on the surface.
+    pub fn normal_at(&self, point: Vec3) -> Vec3 {
+        if !self.on_side(point) {
+            return -self.axis;
+        }
+        let radial = radial_direction(self.axis, point - self.base);
+        (radial * self.height + self.axis * self.radius) / self.slant()
+    }
+
+    /// Texture coordinates of `point`, on the surface, with the frame normal
+    /// and bump maps bend normals in.
+    pub fn uv_frame(&self, point: Vec3) -> UvFrame {
+        let offset = point - self.base;
+        if !self.on_side(point) {
+            let cap = Disk::new(self.base, -self.axis, self.radius, self.material);
+            return cap.uv_frame(point);
+        }
+
+        let v = offset.dot(self.axis) / self.height;
+        let u = turns_around(self.axis, offset);
+        let radial = radial_direction(self.axis, offset);
+        let slant = self.slant();
+        UvFrame {
+            uv: (u, v),
+            tangents: (
+                self.axis.cross(radial),
+                (self.axis * self.height - radial * self.radius) / slant,
+            ),
+            lengths: (2.0 * consts::PI * self.radius * (1.0 - v), slant),
+        }
+    }
+
+    /// Point and outward normal of the side at texture coordinates (`u`,
+    /// `v`).
+    pub fn surface_at(&self, u: Float, v: Float) -> (Vec3, Vec3) {
+        let radial = direction_at(self.axis, u);
+        let point = self.base + self.axis * (v * self.height) + radial * (self.radius * (1.0 - v));
+        let normal = (radial * self.height + self.axis * self.radius) / self.slant();
+        (point, normal)
+    }
+
+    /// Copy of the cone moved by `transform`, which should keep angles.
+    /// Lengths follow the average scale of the transform, like spheres.
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        let scale = transform.determinant3().abs().cbrt();
+        Cone::new(
+            transform.transform_point(self.base),
+            transform.transform_vector(self.axis),
+            self.height * scale,
+            self.radius * scale,
+            self.material,
+        )
+    }
+}
+
+impl Hitable for Cone {
+    fn scale(&mut self, factor: Float) {
+        self.base = self.base * factor;
+        self.height *= factor;
+        self.radius *= factor;
+    }
+
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        // Seen from the apex, the side is where the distance to the axis
+        // grows with the distance along it by the slope of the cone.
+        let down = -self.axis;
+        let oc = ray.origin - self.apex();
+        let slope = self.radius / self.height;
+        let widening = 1.0 + slope * slope;
+        let (d_down, oc_down) = (ray.dir.dot(down), oc.dot(down));
+        let roots = quadratic_roots(
+            ray.dir.dot(ray.dir) - widening * d_down * d_down,
+            oc.dot(ray.dir) - widening * oc_down * d_down,
+            oc.dot(oc) - widening * oc_down * oc_down,
+        );
+        let height = self.height;
+        let side = |t: Float| {
+            let offset = oc + ray.dir * t;
+            let s = offset.dot(down);
+            // The other nappe of the double cone lies above the apex.
+            if (0.0..=height).contains(&s) {
+                Some((t, offset - down * (widening * s)))
+            } else {
+                None
+            }
+        };
+
+        let candidates = [
+            roots.and_then(|(t, _)| side(t)),
+            roots.and_then(|(_, t)| side(t)),
+            cap_hit(ray, self.base, down, self.radius),
+        ];
+        closest_hit(ray, &candidates, t_min, t_max, self.material)
+    }
+}
+
+/// Cylinder rounded by a hemisphere at both ends, the points within
+/// `radius` of the segment from `start` to `end`. `u` goes once around the
+/// segment, and `v` from the pole beyond `start` to the one beyond `end`,
+/// following the surface.
+#[derive(Clone, Copy, Debug)]
+pub struct Capsule {
+    pub start: Vec3,
+    pub end: Vec3,
+    pub radius: Float,
+    pub material: MaterialId,
+}
+
+impl Capsule {
+    pub fn new(start: Vec3, end: Vec3, radius: Float, material: MaterialId) -> Self {
+        Capsule {
+            start,
+            end,
+            radius,
+            material,
+        }
+    }
+
+    /// Unit vector from `start` to `end`, along y when they are the same.
+    fn axis(&self) -> Vec3 {
+        let axis = self.end - self.start;
+        if axis.length_squared() > 0.0 {
+            axis.unit()
+        } else {
+            Vec3::new(0.0, 1.0, 0.0)
+        }
+    }
+
+    /// Length of the surface from pole to pole.
+    fn meridian(&self) -> Float {
+        (self.end - self.start).length() + consts::PI * self.radius
+    }
+
+    /// Outward unit normal at `point`, on the surface.
+    pub fn normal_at(&self, point: Vec3) -> Vec3 {
+        let axis = self.axis();
+        let h = (point - self.start).dot(axis);
+        if h < 0.0 {
+            (point - self.start).unit()
+        } else if h > (self.end - self.start).length() {
+            (point - self.end).unit()
+        } else {
+            radial_direction(axis, point - self.start)
+        }
+    }
+
+    /// Texture coordinates of `point`, on the surface, with the frame normal
+    /// and bump maps bend normals in.
+    pub fn uv_frame(&self, point: Vec3) -> UvFrame {
+        let axis = self.axis();
+        let length = (self.end - self.start).length();
+        let offset = point - self.start;
+        let h = offset.dot(axis);
+        let radial = radial_direction(axis, offset);
+        let quarter = consts::FRAC_PI_2 * self.radius;
+
+        // Distance from the first pole along the surface, and the direction
+        // it grows in.
+        let (arc, bitangent) = if h < 0.0 {
+            let angle = clamp(-h / self.radius, -1.0, 1.0).acos();
+            (
+                angle * self.radius,
+                axis * angle.sin() + radial * angle.cos(),
+            )
+        } else if h > length {
+            let angle = clamp((h - length) / self.radius, -1.0, 1.0).acos();
+            (
+                quarter + length + quarter - angle * self.radius,
+                axis * angle.sin() - radial * angle.cos(),
+            )
+        } else {
+            (quarter + h, axis)
+        };
+
+        let distance = (offset - axis * h).length();
+        UvFrame {
+            uv: (turns_around(axis, offset), arc / self.meridian()),
+            tangents: (axis.cross(radial), bitangent),
+            lengths: (2.0 * consts::PI * distance, self.meridian()),
+        }
+    }
+
+    /// Point and outward normal at texture coordinates (`u`, `v`).
+    pub fn surface_at(&self, u: Float, v: Float) -> (Vec3, Vec3) {
+        let axis = self.axis();
+        let length = (self.end - self.start).length();
+        let radial = direction_at(axis, u);
+        let quarter = consts::FRAC_PI_2 * self.radius;
+        let arc = v * self.meridian();
+
+        let normal = if arc < quarter {
+            let angle = arc / self.radius;
+            radial * angle.sin() - axis * angle.cos()
+        } else if arc > quarter + length {
+            let angle = (arc - quarter - length) / self.radius;
+            radial * angle.cos() + axis * angle.sin()
+        } else {
+            radial
+        };
+        let center = self.start + axis * clamp(arc - quarter, 0.0, length);
+        (center + normal * self.radius, normal)
+    }
+
+    /// Copy of the capsule moved by `transform`, which should keep angles.
+    /// The radius follows the average scale of the transform, like spheres.
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        Capsule::new(
+            transform.transform_point(self.start),
+            transform.transform_point(self.end),
+            self.radius * transform.determinant3().abs().cbrt(),
+            self.material,
+        )
+    }
+}
+
+impl Hitable for Capsule {
+    fn scale(&mut self, factor: Float) {
+        self.start = self.start * factor;
+        self.end = self.end * factor;
+        self.radius *= factor;
+    }
+
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let axis = self.axis();
+        let length = (self.end - self.start).length();
+        let radius_squared = self.radius * self.radius;
+
+        let oc = ray.origin - self.start;
+        let along = ray.dir - axis * axis.dot(ray.dir);
+        let across = oc - axis * axis.dot(oc);
+        let roots = quadratic_roots(
+            along.dot(along),
+            across.dot(along),
+            across.dot(across) - radius_squared,
+        );
+        let side = |t: Float| {
+            let offset = oc + ray.dir * t;
+            let h = offset.dot(axis);
+            if (0.0..=length).contains(&h) {
+                Some((t, offset - axis * h))
+            } else {
+                None
+            }
+        };
+
+        // Only the half of each end sphere beyond the segment is surface.
+        let end_roots = |center: Vec3| {
+            let oc = ray.origin - center;
+            quadratic_roots(
+                ray.dir.dot(ray.dir),
+                oc.dot(ray.dir),
+                oc.dot(oc) - radius_squared,
+            )
+        };
+        let end = |center: Vec3, outwards: Vec3, t: Float| {
+            let offset = ray.at(t) - center;
+            if offset.dot(outwards) >= 0.0 {
+                Some((t, offset))
+            } else {
+                None
+            }
+        };
+        let start_roots = end_roots(self.start);
+        let end_roots = end_roots(self.end);
+
+        let candidates = [
+            roots.and_then(|(t, _)| side(t)),
+            roots.and_then(|(_, t)| side(t)),
+            start_roots.and_then(|(t, _)| end(self.start, -axis, t)),
+            start_roots.and_then(|(_, t)| end(self.start, -axis, t)),
+            end_roots.and_then(|(t, _)| end(self.end, axis, t)),
+            end_roots.and_then(|(_, t)| end(self.end, axis, t)),
+        ];
+        closest_hit(ray, &candidates, t_min, t_max, self.material)
+    }
+}
+
+/// Roots of `a t^2 + 2 half_b t + c`, the smallest first, or the single
+/// root of the line when `a` vanishes.
+fn quadratic_roots(a: Float, half_b: Float, c: Float) -> Option<(Float, Float)> {
+    if a.abs() < 1e-12 {
+        if half_b == 0.0 {
+            return None;
+        }
+        let t = -c / (2.0 * half_b);
+        return Some((t, t));
+    }
+
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    // Like `Sphere::hit`, avoid subtracting close values.
+    let root = Float::sqrt(discriminant);
+    let q = if half_b > 0.0 {
+        -half_b - root
+    } else {
+        -half_b + root
+    };
+    if q == 0.0 {
+        return None;
+    }
+    let (t1, t2) = (c / q, q / a);
+    Some((t1.min(t2), t1.max(t2)))
+}
+
+/// Ray parameter and outward normal where `ray` crosses the disk of
+/// `radius` around `center`, facing `normal`.
+fn cap_hit(ray: &Ray, center: Vec3, normal: Vec3, radius: Float) -> Option<(Float, Vec3)> {
+    let facing = normal.dot(ray.dir);
+    if facing.abs() < 1e-12 {
+        return None;
+    }
+    let t = normal.dot(center - ray.origin) / facing;
+    if (ray.at(t) - center).length_squared() <= radius * radius {
+        Some((t, normal))
+    } else {
+        None
+    }
+}
+
+/// Hit of `ray` at the closest of `candidates`, ray parameters and outward
+/// normals, in (`t_min`, `t_max`).
+fn closest_hit(
+    ray: &Ray,
+    candidates: &[Option<(Float, Vec3)>],
+    t_min: Float,
+    t_max: Float,
+    material: MaterialId,
+) -> Option<HitRecord> {
+    let (t, normal) = candidates
+        .iter()
+        .flatten()
+        .filter(|(t, _)| *t > t_min && *t < t_max)
+        .min_by(|a, b| a.0.total_cmp(&b.0))?;
+    Some(HitRecord::new(ray, ray.at(*t), *normal, *t, material))
+}
+
+/// Unit vectors perpendicular to `axis` that angles around it start from
+/// and go towards.
+fn radial_axes(axis: Vec3) -> (Vec3, Vec3) {
+    let first = Onb::from_w(axis).u;
+    (first, axis.cross(first))
+}
+
+/// Unit vector from `axis` towards `offset`.
+fn radial_direction(axis: Vec3, offset: Vec3) -> Vec3 {
+    let radial = offset - axis * axis.dot(offset);
+    if radial.length_squared() < 1e-24 {
+        return radial_axes(axis).0;
+    }
+    radial.unit()
+}
+
+/// Angle of `offset` around `axis`, in turns from 0 to 1.
+fn turns_around(axis: Vec3, offset: Vec3) -> Float {
+    let (first, second) = radial_axes(axis);
+    let turns = offset.dot(second).atan2(offset.dot(first)) / (2.0 * consts::PI);
+    if turns < 0.0 {
+        turns + 1.0
+    } else {
+        turns
+    }
+}
+
+/// Unit vector perpendicular to `axis`, `turns` around it.
+fn direction_at(axis: Vec3, turns: Float) -> Vec3 {
+    let (first, second) = radial_axes(axis);
+    let angle = 2.0 * consts::PI * turns;
+    first * angle.cos() + second * angle.sin()
+}
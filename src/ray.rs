@@ -0,0 +1,57 @@
+use crate::maths::*;
+
+/// Half line from `origin` along `dir`. Rays are made with `new`, which also
+/// works out what `RayExt` gives intersection code, so `dir` isn't changed
+/// afterwards.
+#[derive(Copy, Clone, Debug)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub dir: Vec3,
+    inverse_dir: Vec3,
+    /// Bit per axis, x first, set when `dir` goes towards negative values.
+    signs: u8,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, dir: Vec3) -> Self {
+        let inverse_dir = Vec3::new(1.0 / dir.x, 1.0 / dir.y, 1.0 / dir.z);
+        // Going by the inverse counts -0 as negative, like its infinity.
+        let signs = (inverse_dir.x < 0.0) as u8
+            | ((inverse_dir.y < 0.0) as u8) << 1
+            | ((inverse_dir.z < 0.0) as u8) << 2;
+        Ray {
+            origin,
+            dir,
+            inverse_dir,
+            signs,
+        }
+    }
+
+    pub fn at(self, t: Float) -> Vec3 {
+        self.origin + self.dir * t
+    }
+}
+
+/// What slab tests and grid walks need of a ray, worked out once by
+/// `Ray::new` rather than for every box the ray meets.
+pub trait RayExt {
+    /// `1 / dir` along every axis, infinite along those `dir` is flat on.
+    fn inverse_dir(&self) -> Vec3;
+
+    /// Whether `dir` goes towards negative values along x, y and z.
+    fn dir_is_negative(&self) -> [bool; 3];
+}
+
+impl RayExt for Ray {
+    fn inverse_dir(&self) -> Vec3 {
+        self.inverse_dir
+    }
+
+    fn dir_is_negative(&self) -> [bool; 3] {
+        [
+            self.signs & 1 != 0,
+            self.signs & 2 != 0,
+            self.signs & 4 != 0,
+        ]
+    }
+}
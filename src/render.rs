@@ -0,0 +1,1634 @@
+use crate::aov::*;
+use crate::camera::Camera;
+use crate::clouds::{CloudMode, Clouds};
+use crate::film::*;
+use crate::hitable::HitRecord;
+use crate::image::Image;
+use crate::material::{BounceKind, Material, MaterialType, Medium};
+use crate::maths::*;
+use crate::paths::*;
+use crate::preview::PreviewEnvironment;
+use crate::ray::Ray;
+use crate::sampler::*;
+use crate::scene::{ObjectId, Scene};
+
+use rayon::prelude::*;
+
+/// Most particles a random walk through a `Medium` scatters off before it
+/// gives up, as light still inside after that many is mostly absorbed.
+const MAX_WALK_STEPS: usize = 256;
+
+/// Side, in pixels, of the square tiles the image is split into.
+const TILE_SIZE: usize = 16;
+
+/// Sampler dimensions of the camera: the position inside the pixel, then on
+/// the lens.
+const CAMERA_DIMENSIONS: u32 = 4;
+
+/// Sampler dimensions of every bounce: three for scattering, then three for
+/// picking a light and a direction towards it. Materials drawing fewer leave
+/// some unused, so a dimension always serves the same decision.
+const BOUNCE_DIMENSIONS: u32 = 6;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    pub samples_per_pixel: usize,
+    /// Maximum number of bounces, whatever their kind.
+    pub max_depth: i32,
+    pub bounce_limits: BounceLimits,
+    pub transparent_background: bool,
+    /// Distance past which rays stop looking for hits, in scene units.
+    pub max_distance: Float,
+    /// Fraction of `max_distance`, from 0 to 1, over which surfaces fade
+    /// into what lies behind them as they get further, 0 for them to vanish
+    /// all at once past it. Surfaces are skipped at random, more often the
+    /// further they are, so the fade holds for every bounce. Shadow rays
+    /// still stop at `max_distance`.
+    pub distance_fade: Float,
+    /// Luminance above which light reaching the camera after a single
+    /// bounce, straight from the lights, is scaled down. Lights seen by the
+    /// camera are left alone.
+    pub clamp_direct: Float,
+    /// Luminance above which light reaching the camera after more than one
+    /// bounce is scaled down, trading a little energy for fewer fireflies.
+    /// Indirect light is blurry enough to lose far less to it than direct
+    /// light does, so this can be much lower than `clamp_direct`.
+    pub clamp_indirect: Float,
+    /// Luminance above which whole samples are scaled down.
+    pub clamp_sample: Float,
+    /// Samples whose luminance exceeds the mean of the other samples of their
+    /// pixel by more than this many standard deviations are scaled down to
+    /// that bound, infinite to keep every sample as it is.
+    pub outlier_sigma: Float,
+    /// Number of groups the samples of a pixel are split into, the pixel
+    /// taking the median of their means instead of the plain mean. Rare
+    /// fireflies only reach a minority of the groups, so they are dropped at
+    /// the cost of a slight darkening bias. 0 or 1 for the plain mean.
+    pub median_of_means: usize,
+    pub filter: Filter,
+    /// Placement of the samples inside their pixel.
+    pub sampler: SamplerType,
+    /// Whether all pixels follow the same sample sequences, shifted by a blue
+    /// noise texture, so the noise left at low sample counts is spread evenly
+    /// instead of clumping.
+    pub blue_noise: bool,
+    /// Voxels per side of the `LightCache` given to the scene before
+    /// rendering, 0 for none. Renderers don't build it themselves.
+    pub light_cache: usize,
+    /// Columns of the `PreviewEnvironment` given to the scene before
+    /// rendering, 0 for none. Renderers don't build it themselves.
+    pub preview: usize,
+    /// Whether the camera rays of every pixel are traced in packets, which
+    /// is faster when pixels have many samples. Same image either way.
+    pub ray_packets: bool,
+    /// Base seed of the per-pixel random sequences.
+    pub seed: u64,
+    /// Number of worker threads, 0 letting rayon decide.
+    pub threads: usize,
+    /// Part of the image rendered, the rest left black, or transparent with
+    /// a transparent background. Its pixels are the same as in the whole
+    /// image, but for those the filter reaches past its border. `None`
+    /// renders the whole image.
+    pub crop: Option<Crop>,
+}
+
+/// Rectangle of pixels of an image, from its top left corner.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Crop {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Crop {
+    pub fn contains(&self, i: usize, j: usize) -> bool {
+        (self.x..self.x + self.width).contains(&i) && (self.y..self.y + self.height).contains(&j)
+    }
+
+    /// Whether the crop has pixels and they are all in a `width` x `height`
+    /// image, without overflowing however far off it is.
+    pub fn fits(&self, width: usize, height: usize) -> bool {
+        let inside = |start: usize, size: usize, bound: usize| {
+            size > 0 && start.checked_add(size).is_some_and(|end| end <= bound)
+        };
+        inside(self.x, self.width, width) && inside(self.y, self.height, height)
+    }
+}
+
+/// Maximum number of bounces of each kind a path can take, on top of
+/// `RenderSettings::max_depth`. Lights are still sampled directly from the
+/// last surface, so a diffuse limit of 0 keeps their direct lighting.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BounceLimits {
+    pub diffuse: i32,
+    pub glossy: i32,
+    pub transmission: i32,
+    /// Scattering events inside media, subsurface random walks and clouds
+    /// alike.
+    pub volume: i32,
+}
+
+impl Default for BounceLimits {
+    fn default() -> Self {
+        BounceLimits {
+            diffuse: i32::MAX,
+            glossy: i32::MAX,
+            transmission: i32::MAX,
+            volume: i32::MAX,
+        }
+    }
+}
+
+impl BounceLimits {
+    pub fn limit(&self, kind: BounceKind) -> i32 {
+        match kind {
+            BounceKind::Diffuse => self.diffuse,
+            BounceKind::Glossy => self.glossy,
+            BounceKind::Transmission => self.transmission,
+            BounceKind::Volume => self.volume,
+        }
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            width: 1920,
+            height: 1080,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            bounce_limits: BounceLimits::default(),
+            transparent_background: false,
+            max_distance: Float::INFINITY,
+            distance_fade: 0.1,
+            clamp_direct: Float::INFINITY,
+            clamp_indirect: Float::INFINITY,
+            clamp_sample: Float::INFINITY,
+            outlier_sigma: Float::INFINITY,
+            median_of_means: 0,
+            filter: Filter::default(),
+            sampler: SamplerType::default(),
+            blue_noise: false,
+            light_cache: 0,
+            preview: 0,
+            ray_packets: false,
+            seed: 0,
+            threads: 0,
+            crop: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Tile {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+fn tiles(area: Crop) -> Vec<Tile> {
+    let (right, bottom) = (area.x + area.width, area.y + area.height);
+    let mut tiles = Vec::new();
+    for y in (area.y..bottom).step_by(TILE_SIZE) {
+        for x in (area.x..right).step_by(TILE_SIZE) {
+            tiles.push(Tile {
+                x,
+                y,
+                width: TILE_SIZE.min(right - x),
+                height: TILE_SIZE.min(bottom - y),
+            });
+        }
+    }
+    tiles
+}
+
+/// Renders scenes on a dedicated pool of worker threads.
+///
+/// Every sample reseeds the random generator of the thread rendering it from
+/// `settings.seed`, its pixel and its own index, so a given seed produces the
+/// exact same image whatever the number of threads, the order tiles finish
+/// in or the samples computed before.
+pub struct Renderer {
+    pool: rayon::ThreadPool,
+    pub settings: RenderSettings,
+    /// Samples of every pixel, row-major, when they aren't all given
+    /// `settings.samples_per_pixel`.
+    sample_counts: Option<Vec<usize>>,
+    /// Camera of the previous frame, for motion vectors.
+    previous_camera: Option<Camera>,
+}
+
+impl Renderer {
+    pub fn new(settings: RenderSettings) -> Result<Self, rayon::ThreadPoolBuildError> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(settings.threads)
+            .build()?;
+
+        Ok(Renderer {
+            pool,
+            settings,
+            sample_counts: None,
+            previous_camera: None,
+        })
+    }
+
+    pub fn threads(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+
+    /// Spreads the sample budget of the image, `samples_per_pixel` times the
+    /// number of pixels, proportionally to the row-major `importance` of each
+    /// pixel. Every pixel keeps an eighth of its uniform share, so areas the
+    /// map deems unimportant still converge.
+    pub fn set_importance(&mut self, importance: &[Float]) {
+        let settings = self.settings;
+        let pixels = settings.width * settings.height;
+        assert_eq!(importance.len(), pixels, "one importance value per pixel");
+
+        let minimum = (settings.samples_per_pixel / 8).max(1);
+        let budget = (settings.samples_per_pixel * pixels).saturating_sub(minimum * pixels);
+        let total: Float = importance
+            .iter()
+            .map(|&value| {
+                if value.is_finite() {
+                    value.max(0.0)
+                } else {
+                    0.0
+                }
+            })
+            .sum();
+
+        // Round the running sum rather than every share, so the counts add up
+        // to the budget exactly.
+        let mut counts = Vec::with_capacity(pixels);
+        let mut sum = 0.0;
+        let mut given = 0;
+        for &value in importance {
+            let value = if value.is_finite() {
+                value.max(0.0)
+            } else {
+                0.0
+            };
+            sum += if total > 0.0 {
+                value / total
+            } else {
+                1.0 / pixels as Float
+            };
+            let target = (sum * budget as Float).round() as usize;
+            counts.push(minimum + target - given);
+            given = target;
+        }
+
+        self.sample_counts = Some(counts);
+    }
+
+    /// Gives every pixel `settings.samples_per_pixel` samples again.
+    pub fn clear_importance(&mut self) {
+        self.sample_counts = None;
+    }
+
+    /// Makes motion vectors follow the camera from `camera`, where it was in
+    /// the previous frame, to the camera rendering the current one. Without
+    /// one, only objects given a `Scene::set_previous_transform` move.
+    pub fn set_previous_camera(&mut self, camera: Option<Camera>) {
+        self.previous_camera = camera;
+    }
+
+    /// Renders `samples` per pixel and measures how noisy every pixel is, as
+    /// the standard error of its gamma encoded and clamped luminance, so the
+    /// map follows the noise visible in the output. The map is blurred over
+    /// 3x3 pixels, the estimates of a handful of samples being noisy
+    /// themselves. Meant as a cheap prior pass for `set_importance`.
+    pub fn noise_map(&self, scene: &Scene, camera: &Camera, samples: usize) -> Vec<Float> {
+        let settings = RenderSettings {
+            samples_per_pixel: samples.max(2),
+            // Keep the prior independent from the final render.
+            seed: !self.settings.seed,
+            ..self.settings
+        };
+
+        let rows: Vec<Vec<Float>> = self.pool.install(|| {
+            (0..settings.height)
+                .into_par_iter()
+                .map(|j| {
+                    (0..settings.width)
+                        .map(|i| {
+                            let mut sum = 0.0;
+                            let mut sum_squared = 0.0;
+                            for sample in 0..settings.samples_per_pixel {
+                                let samples = settings.samples_per_pixel;
+                                let mut sampler = start_sample(i, j, sample, samples, &settings);
+                                let (_, _, ray) =
+                                    pixel_sample(i, j, &mut sampler, camera, &settings);
+                                let color = trace_ray(
+                                    &ray,
+                                    None,
+                                    scene,
+                                    &settings,
+                                    &mut sampler,
+                                    None,
+                                    None,
+                                )
+                                .0;
+                                let value = clamp(luminance(color), 0.0, 1.0).sqrt();
+                                sum += value;
+                                sum_squared += value * value;
+                            }
+
+                            let n = settings.samples_per_pixel as Float;
+                            let mean = sum / n;
+                            let variance = ((sum_squared - mean * mean * n) / (n - 1.0)).max(0.0);
+                            (variance / n).sqrt()
+                        })
+                        .collect()
+                })
+                .collect()
+        });
+
+        let (width, height) = (settings.width, settings.height);
+        let noise = rows.concat();
+        let mut blurred = vec![0.0; noise.len()];
+        for j in 0..height {
+            for i in 0..width {
+                let mut sum = 0.0;
+                let mut count = 0.0;
+                for y in j.saturating_sub(1)..(j + 2).min(height) {
+                    for x in i.saturating_sub(1)..(i + 2).min(width) {
+                        sum += noise[y * width + x];
+                        count += 1.0;
+                    }
+                }
+                blurred[j * width + i] = sum / count;
+            }
+        }
+
+        blurred
+    }
+
+    /// Renders the scene into an image of linear, premultiplied RGBA pixels,
+    /// scaled by the exposure of the camera if it has one.
+    pub fn render(&self, scene: &Scene, camera: &Camera) -> Image {
+        self.render_tiles(scene, camera, false).0
+    }
+
+    /// Like `render`, also returning the albedo, normal, depth and motion
+    /// seen by the camera rays.
+    pub fn render_with_aovs(&self, scene: &Scene, camera: &Camera) -> (Image, AovImages) {
+        let (pixels, aovs) = self.render_tiles(scene, camera, true);
+        (pixels, aovs.expect("auxiliary outputs were requested"))
+    }
+
+    /// Renders the pixels of `region` alone, the same as those of the whole
+    /// image: the samples of the pixels around it the filter reaches are
+    /// rendered too, then left out. Fails for regions with no pixels or
+    /// going past the image.
+    pub fn render_region(
+        &mut self,
+        scene: &Scene,
+        camera: &Camera,
+        region: Crop,
+    ) -> std::io::Result<Image> {
+        let settings = self.settings;
+        if !region.fits(settings.width, settings.height) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "bad region",
+            ));
+        }
+        let margin = Float::max(settings.filter.radius() - 0.5, 0.0).ceil() as usize;
+        let (x, y) = (
+            region.x.saturating_sub(margin),
+            region.y.saturating_sub(margin),
+        );
+        let grown = Crop {
+            x,
+            y,
+            width: (region.x + region.width)
+                .saturating_add(margin)
+                .min(settings.width)
+                - x,
+            height: (region.y + region.height)
+                .saturating_add(margin)
+                .min(settings.height)
+                - y,
+        };
+        self.settings.crop = Some(grown);
+        let image = self.render(scene, camera);
+        self.settings = settings;
+        Ok(image.cropped(region))
+    }
+
+    fn render_tiles(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        aovs: bool,
+    ) -> (Image, Option<AovImages>) {
+        let settings = self.settings;
+        let (width, height) = (settings.width, settings.height);
+
+        let sample_counts = self.sample_counts.as_deref();
+        let cameras = (camera, self.previous_camera.as_ref().unwrap_or(camera));
+        let area = settings.crop.unwrap_or(Crop {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        });
+        let tile_outputs: Vec<(Film, Option<AovBuffer>)> = self.pool.install(|| {
+            tiles(area)
+                .into_par_iter()
+                .map(|tile| render_tile(tile, scene, cameras, &settings, aovs, sample_counts))
+                .collect()
+        });
+
+        // Tile films overlap where the filter spreads samples across tile
+        // borders, merging them in tile order keeps the sums deterministic.
+        let mut film = Film::new(0, 0, width, height);
+        let mut aov_buffer = if aovs {
+            Some(AovBuffer::new(0, 0, width, height))
+        } else {
+            None
+        };
+        for (tile_film, tile_aovs) in &tile_outputs {
+            film.merge(tile_film);
+            if let (Some(buffer), Some(tile_aovs)) = (&mut aov_buffer, tile_aovs) {
+                buffer.merge(tile_aovs);
+            }
+        }
+
+        let mut pixels = film.resolve();
+        // The filter spreads the samples of the crop a little past it.
+        if let Some(crop) = settings.crop {
+            let alpha = if settings.transparent_background {
+                0.0
+            } else {
+                1.0
+            };
+            for (index, pixel) in pixels.chunks_mut(4).enumerate() {
+                if !crop.contains(index % width, index / width) {
+                    pixel.copy_from_slice(&[0.0, 0.0, 0.0, alpha]);
+                }
+            }
+        }
+        if let Some(exposure) = camera.exposure() {
+            let scale = exposure.scale();
+            for pixel in pixels.chunks_mut(4) {
+                for channel in &mut pixel[..3] {
+                    *channel *= scale;
+                }
+            }
+        }
+
+        (
+            Image::new(width, height, pixels),
+            aov_buffer.map(|buffer| buffer.resolve()),
+        )
+    }
+
+    /// Renders the material of `sphere` into its texture space, lit by the
+    /// scene environment alone, as row-major, linear RGBA pixels with `v`
+    /// growing upwards.
+    pub fn bake_uv(&self, scene: &Scene, sphere: ObjectId) -> Vec<Float> {
+        let settings = self.settings;
+        let id = sphere;
+        let sphere = scene.sphere(id);
+
+        let rows: Vec<Vec<Float>> = self.pool.install(|| {
+            (0..settings.height)
+                .into_par_iter()
+                .map(|j| {
+                    let mut row = Vec::with_capacity(settings.width * 4);
+                    for i in 0..settings.width {
+                        let mut color = Vec3::new(0.0, 0.0, 0.0);
+                        for sample in 0..settings.samples_per_pixel {
+                            let samples = settings.samples_per_pixel;
+                            let mut sampler = start_sample(i, j, sample, samples, &settings);
+                            let (dx, dy) = sampler.get_2d();
+                            let u = (i as Float + dx) / settings.width as Float;
+                            let v = 1.0 - (j as Float + dy) / settings.height as Float;
+                            let (position, normal) = match scene.shape(id) {
+                                Some(shape) => shape.surface_at(u, v),
+                                None => sphere.surface_at(u, v),
+                            };
+
+                            // Look at the surface head on.
+                            let ray = Ray::new(position + normal, -normal);
+                            let mut record =
+                                HitRecord::new(&ray, position, normal, 1.0, sphere.material);
+                            scene.apply_shading_normal(id, &mut record);
+                            sampler.set_dimension(CAMERA_DIMENSIONS);
+                            color += probe_color(&ray, &record, scene, &mut sampler);
+                        }
+
+                        let color = color / settings.samples_per_pixel as Float;
+                        row.extend_from_slice(&[color.x, color.y, color.z, 1.0]);
+                    }
+                    row
+                })
+                .collect()
+        });
+
+        rows.concat()
+    }
+}
+
+/// Single bounce shading of `record` with the environment as the only light.
+fn probe_color(ray: &Ray, record: &HitRecord, scene: &Scene, sampler: &mut dyn Sampler) -> Vec3 {
+    let material = scene.material(record.material);
+    let mut color = material.emitted(record);
+
+    if let Some(scatter) = material.scatter(ray, record, sampler) {
+        let scattered = &scatter.scattered;
+        let light = scene
+            .environment
+            .radiance_from(scattered.origin, scattered.dir);
+        if scatter.is_specular {
+            color += scatter.attenuation * light;
+        } else if scatter.pdf > 0.0 {
+            let scattering_pdf = material.scattering_pdf(ray, record, &scatter.scattered);
+            color += scatter.attenuation * light * (scattering_pdf / scatter.pdf);
+        }
+    }
+
+    color
+}
+
+/// Starts sample `sample` out of `samples` of pixel (`i`, `j`), returning its
+/// sampler. Every sample has a random sequence of its own, so any sample of
+/// any pixel can be computed without drawing the ones before it.
+fn start_sample(
+    i: usize,
+    j: usize,
+    sample: usize,
+    samples: usize,
+    settings: &RenderSettings,
+) -> PixelSampler {
+    if settings.blue_noise {
+        seed_random(mix_seed(settings.seed, sample as u64));
+        return settings
+            .sampler
+            .start_sample(settings.seed, sample, samples)
+            .dithered(i, j);
+    }
+
+    let pixel_seed = mix_seed(settings.seed, (j * settings.width + i) as u64);
+    seed_random(mix_seed(pixel_seed, sample as u64));
+    settings.sampler.start_sample(pixel_seed, sample, samples)
+}
+
+/// Raster position of a sample inside pixel (`i`, `j`), (0, 0) being the top
+/// left corner of the image, and the camera ray going through it.
+fn pixel_sample(
+    i: usize,
+    j: usize,
+    sampler: &mut dyn Sampler,
+    camera: &Camera,
+    settings: &RenderSettings,
+) -> (Float, Float, Ray) {
+    let (dx, dy) = sampler.get_2d();
+    let sx = i as Float + dx;
+    let sy = j as Float + dy;
+    let u = sx / settings.width as Float;
+    let v = 1.0 - sy / settings.height as Float;
+
+    (sx, sy, camera.get_ray(u, v, sampler.get_2d()))
+}
+
+/// Renders `tile` through the first of `cameras`, the second being the camera
+/// of the previous frame.
+fn render_tile(
+    tile: Tile,
+    scene: &Scene,
+    (camera, previous_camera): (&Camera, &Camera),
+    settings: &RenderSettings,
+    aovs: bool,
+    sample_counts: Option<&[usize]>,
+) -> (Film, Option<AovBuffer>) {
+    // Grow the tile film by the pixels the filter can reach past the tile.
+    let margin = Float::max(settings.filter.radius() - 0.5, 0.0).ceil() as usize;
+    let x = tile.x.saturating_sub(margin);
+    let y = tile.y.saturating_sub(margin);
+    let mut film = Film::new(
+        x,
+        y,
+        (tile.x + tile.width + margin).min(settings.width) - x,
+        (tile.y + tile.height + margin).min(settings.height) - y,
+    );
+
+    let mut aov_buffer = if aovs {
+        Some(AovBuffer::new(tile.x, tile.y, tile.width, tile.height))
+    } else {
+        None
+    };
+
+    let mut pixel_samples = Vec::new();
+    for j in tile.y..tile.y + tile.height {
+        for i in tile.x..tile.x + tile.width {
+            let samples = sample_counts.map_or(settings.samples_per_pixel, |counts| {
+                counts[j * settings.width + i]
+            });
+            let packets = if settings.ray_packets {
+                camera_samples(i, j, samples, scene, camera, settings)
+            } else {
+                Vec::new()
+            };
+            for sample in 0..samples {
+                let (mut sampler, sx, sy, ray, first_hit) = match packets.get(sample) {
+                    Some(start) => {
+                        restore_random(start.random);
+                        (
+                            start.sampler,
+                            start.sx,
+                            start.sy,
+                            start.ray,
+                            Some(start.hit),
+                        )
+                    }
+                    None => {
+                        let mut sampler = start_sample(i, j, sample, samples, settings);
+                        let (sx, sy, ray) = pixel_sample(i, j, &mut sampler, camera, settings);
+                        (sampler, sx, sy, ray, None)
+                    }
+                };
+
+                // Misses contribute black, so filtering yields premultiplied alpha.
+                let mut aov = Aov::default();
+                let (color, alpha) = trace_ray(
+                    &ray,
+                    first_hit,
+                    scene,
+                    settings,
+                    &mut sampler,
+                    None,
+                    aov_buffer.as_ref().map(|_| &mut aov),
+                );
+                pixel_samples.push((sx, sy, color, alpha));
+                if let Some(buffer) = &mut aov_buffer {
+                    aov.motion = motion_vector(&ray, &aov, camera, previous_camera, settings);
+                    buffer.add_sample(i, j, &aov);
+                }
+            }
+
+            if settings.outlier_sigma.is_finite() {
+                reject_outliers(&mut pixel_samples, settings.outlier_sigma);
+            }
+            if settings.median_of_means > 1 {
+                median_of_means(&mut pixel_samples, settings.median_of_means);
+            }
+            for (sx, sy, color, alpha) in pixel_samples.drain(..) {
+                film.add_sample(sx, sy, color, alpha, &settings.filter);
+            }
+        }
+    }
+
+    (film, aov_buffer)
+}
+
+/// `Aov::motion` of the camera ray `ray` of `camera`, whose first hit is in
+/// `aov`, from where `previous_camera` saw it in the previous frame.
+fn motion_vector(
+    ray: &Ray,
+    aov: &Aov,
+    camera: &Camera,
+    previous_camera: &Camera,
+    settings: &RenderSettings,
+) -> (Float, Float) {
+    let (now, before) = if aov.depth.is_finite() {
+        let position = ray.origin + ray.dir.unit() * aov.depth;
+        (
+            camera.project(position),
+            previous_camera.project(aov.previous_position),
+        )
+    } else {
+        (
+            camera.project_direction(ray.dir),
+            previous_camera.project_direction(ray.dir),
+        )
+    };
+    match (now, before) {
+        (Some(now), Some(before)) => (
+            (now.0 - before.0) * settings.width as Float,
+            (before.1 - now.1) * settings.height as Float,
+        ),
+        _ => (0.0, 0.0),
+    }
+}
+
+/// Camera sample whose first hit was already found.
+struct CameraSample {
+    sx: Float,
+    sy: Float,
+    ray: Ray,
+    sampler: PixelSampler,
+    /// Random sequence of the sample, right after its camera ray was drawn.
+    random: RandomState,
+    hit: Option<(ObjectId, HitRecord)>,
+}
+
+/// Draws the camera rays of the `samples` samples of pixel (`i`, `j`) and
+/// finds their first hits in packets, so coherent rays share the traversal.
+fn camera_samples(
+    i: usize,
+    j: usize,
+    samples: usize,
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+) -> Vec<CameraSample> {
+    let mut camera_samples = Vec::with_capacity(samples);
+    let mut rays = Vec::with_capacity(samples);
+    let mut t_max = Vec::with_capacity(samples);
+    for sample in 0..samples {
+        let mut sampler = start_sample(i, j, sample, samples, settings);
+        let (sx, sy, ray) = pixel_sample(i, j, &mut sampler, camera, settings);
+        camera_samples.push(CameraSample {
+            sx,
+            sy,
+            ray,
+            sampler,
+            random: save_random(),
+            hit: None,
+        });
+        rays.push(ray);
+        t_max.push(settings.max_distance / ray.dir.length());
+    }
+
+    let hits = scene.hit_objects(&rays, scene.epsilon(), &t_max);
+    for (camera_sample, hit) in camera_samples.iter_mut().zip(hits) {
+        camera_sample.hit = hit;
+    }
+    camera_samples
+}
+
+/// Scales down the samples of a pixel whose luminance exceeds the mean of the
+/// other samples by more than `sigma` of their standard deviations. The other
+/// samples being the reference keeps a single firefly from raising its own
+/// bound. Values up to 1, the brightest an 8-bit image shows, are always kept,
+/// so sparse but legitimate highlights of low sample counts survive.
+fn reject_outliers(samples: &mut [(Float, Float, Vec3, Float)], sigma: Float) {
+    // With too few samples, the other ones say nothing about the distribution.
+    if samples.len() < 4 {
+        return;
+    }
+
+    let others = (samples.len() - 1) as Float;
+    let sum: Float = samples.iter().map(|sample| luminance(sample.2)).sum();
+    let sum_squared: Float = samples
+        .iter()
+        .map(|sample| luminance(sample.2).powi(2))
+        .sum();
+
+    for sample in samples.iter_mut() {
+        let value = luminance(sample.2);
+        let mean = (sum - value) / others;
+        let variance = Float::max((sum_squared - value * value) / others - mean * mean, 0.0);
+        let bound = Float::max(mean + sigma * variance.sqrt(), 1.0);
+        sample.2 = clamp_luminance(sample.2, bound);
+    }
+}
+
+/// Rescales the samples of a pixel, per channel, so that their mean becomes
+/// the median of the means of `groups` groups of consecutive samples. Samples
+/// keep their positions and relative weights, so the pixel filter still
+/// applies, and a box filter gives exactly the median of means.
+fn median_of_means(samples: &mut [(Float, Float, Vec3, Float)], groups: usize) {
+    // Groups need a few samples each for their means to mean anything.
+    let groups = groups.min(samples.len() / 2);
+    if groups < 2 {
+        return;
+    }
+
+    let channel = |sample: &(Float, Float, Vec3, Float), index: usize| match index {
+        0 => sample.2.x,
+        1 => sample.2.y,
+        2 => sample.2.z,
+        _ => sample.3,
+    };
+    let mut scale = [1.0; 4];
+    for (index, scale) in scale.iter_mut().enumerate() {
+        let mut means: Vec<Float> = (0..groups)
+            .map(|group| {
+                let start = group * samples.len() / groups;
+                let end = (group + 1) * samples.len() / groups;
+                let group = &samples[start..end];
+                group
+                    .iter()
+                    .map(|sample| channel(sample, index))
+                    .sum::<Float>()
+                    / group.len() as Float
+            })
+            .collect();
+        means.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let middle = means.len() / 2;
+        let median = if means.len().is_multiple_of(2) {
+            0.5 * (means[middle - 1] + means[middle])
+        } else {
+            means[middle]
+        };
+
+        let mean = samples
+            .iter()
+            .map(|sample| channel(sample, index))
+            .sum::<Float>()
+            / samples.len() as Float;
+        if mean > 0.0 {
+            *scale = median / mean;
+        }
+    }
+
+    for sample in samples.iter_mut() {
+        sample.2 = Vec3::new(
+            sample.2.x * scale[0],
+            sample.2.y * scale[1],
+            sample.2.z * scale[2],
+        );
+        sample.3 *= scale[3];
+    }
+}
+
+/// `color` scaled down to a luminance of at most `max`, keeping its hue.
+fn clamp_luminance(color: Vec3, max: Float) -> Vec3 {
+    let value = luminance(color);
+    if value > max {
+        color * (max / value)
+    } else {
+        color
+    }
+}
+
+/// Replays the samples of pixel (`x`, `y`) exactly as `Renderer::render`
+/// would, recording the vertices of every path.
+pub fn trace_pixel(
+    x: usize,
+    y: usize,
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+) -> Vec<Vec<PathVertex>> {
+    (0..settings.samples_per_pixel)
+        .map(|sample| {
+            let samples = settings.samples_per_pixel;
+            let mut sampler = start_sample(x, y, sample, samples, settings);
+            let (_, _, ray) = pixel_sample(x, y, &mut sampler, camera, settings);
+            let mut path = Vec::new();
+            trace_ray(
+                &ray,
+                None,
+                scene,
+                settings,
+                &mut sampler,
+                Some(&mut path),
+                None,
+            );
+            path
+        })
+        .collect()
+}
+
+/// First surface seen through a pixel.
+#[derive(Clone, Debug)]
+pub struct FirstHit {
+    pub object: ObjectId,
+    pub material: MaterialType,
+    pub position: Vec3,
+    /// Distance from the camera, in scene units.
+    pub depth: Float,
+    pub normal: Vec3,
+}
+
+/// Statistics of the samples of one pixel.
+#[derive(Clone, Debug)]
+pub struct PixelInfo {
+    pub samples: usize,
+    pub mean: Vec3,
+    /// Per channel sample variance of the radiance.
+    pub variance: Vec3,
+    pub alpha: Float,
+    pub first_hit: Option<FirstHit>,
+}
+
+/// Replays the samples of pixel (`x`, `y`) like `trace_pixel` and gathers
+/// their statistics, along with the surface seen through the pixel center.
+pub fn inspect_pixel(
+    x: usize,
+    y: usize,
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+) -> PixelInfo {
+    let mut sum = Vec3::new(0.0, 0.0, 0.0);
+    let mut sum_squared = Vec3::new(0.0, 0.0, 0.0);
+    let mut alpha = 0.0;
+    for sample in 0..settings.samples_per_pixel {
+        let mut sampler = start_sample(x, y, sample, settings.samples_per_pixel, settings);
+        let (_, _, ray) = pixel_sample(x, y, &mut sampler, camera, settings);
+        let (color, sample_alpha) =
+            trace_ray(&ray, None, scene, settings, &mut sampler, None, None);
+        sum += color;
+        sum_squared += color * color;
+        alpha += sample_alpha;
+    }
+
+    let samples = settings.samples_per_pixel as Float;
+    let mean = sum / samples;
+    let variance = if settings.samples_per_pixel > 1 {
+        (sum_squared - mean * mean * samples) / (samples - 1.0)
+    } else {
+        Vec3::new(0.0, 0.0, 0.0)
+    };
+
+    let u = (x as Float + 0.5) / settings.width as Float;
+    let v = ((settings.height - 1 - y) as Float + 0.5) / settings.height as Float;
+    let ray = camera.get_ray(u, v, (0.5, 0.5));
+    let first_hit = scene
+        .hit_object(
+            &ray,
+            scene.epsilon(),
+            settings.max_distance / ray.dir.length(),
+        )
+        .map(|(object, record)| FirstHit {
+            object,
+            material: scene.material(record.material).clone(),
+            position: record.position,
+            depth: record.t * ray.dir.length(),
+            normal: record.normal,
+        });
+
+    PixelInfo {
+        samples: settings.samples_per_pixel,
+        mean,
+        variance,
+        alpha: alpha / samples,
+        first_hit,
+    }
+}
+
+/// Radiance carried back along `ray`, along with its alpha coverage.
+///
+/// When `settings.transparent_background` is set, camera rays escaping
+/// straight to the environment are fully transparent instead of picking up its
+/// color. Secondary rays still see the environment, so objects keep their
+/// lighting. Surfaces further than `settings.max_distance` along a ray are
+/// ignored, whatever the bounce, and those closer to it fade out following
+/// `settings.distance_fade`. Light arriving through a single bounce is
+/// clamped to `settings.clamp_direct`, through more to
+/// `settings.clamp_indirect`, and the total to `settings.clamp_sample`, all
+/// in luminance.
+pub fn ray_color(ray: &Ray, scene: &Scene, settings: &RenderSettings) -> (Vec3, Float) {
+    trace_ray(
+        ray,
+        None,
+        scene,
+        settings,
+        &mut IndependentSampler,
+        None,
+        None,
+    )
+}
+
+fn record(path: &mut Option<&mut Vec<PathVertex>>, position: Vec3, kind: VertexKind) {
+    if let Some(path) = path {
+        path.push(PathVertex { position, kind });
+    }
+}
+
+/// Follows `ray` through `medium` from `t_start`, scattering off particles
+/// until it reaches a surface, and returns that hit, `ray` and `throughput`
+/// being updated to the last step of the walk. `None` when it gave up,
+/// or when its scattering took `volume_bounces` past the volume limit.
+///
+/// Distances are sampled following the extinction of one channel, picked at
+/// random for the whole walk, which is then weighted against the chances of
+/// the other channels making it (the "spectral MIS" of Wilkie et al. 2014),
+/// so media of any color converge.
+#[allow(clippy::too_many_arguments)]
+fn random_walk(
+    ray: &mut Ray,
+    throughput: &mut Vec3,
+    medium: &Medium,
+    t_start: Float,
+    scene: &Scene,
+    settings: &RenderSettings,
+    path: &mut Option<&mut Vec<PathVertex>>,
+    volume_bounces: &mut i32,
+) -> Option<(ObjectId, HitRecord)> {
+    let extinction = medium.extinction;
+    let channel = ((random_01() * 3.0) as usize).min(2);
+    let channel_extinction = [extinction.x, extinction.y, extinction.z][channel];
+    let transmittance = |distance: Float| {
+        Vec3::new(
+            (-extinction.x * distance).exp(),
+            (-extinction.y * distance).exp(),
+            (-extinction.z * distance).exp(),
+        )
+    };
+
+    // Light carried by the walk and chances of each channel sampling it,
+    // both rescaled as they go so they don't underflow.
+    let mut carried = Vec3::new(1.0, 1.0, 1.0);
+    let mut pdfs = Vec3::new(1.0, 1.0, 1.0);
+    let mut step = |value: Vec3, pdf: Vec3| {
+        let scale = 1.0 / pdf.x.max(pdf.y).max(pdf.z).max(1e-30);
+        carried = carried * value * scale;
+        pdfs = pdfs * pdf * scale;
+    };
+
+    // Unit directions make ray parameters distances.
+    *ray = Ray::new(ray.origin, ray.dir.unit());
+    let mut t_start = t_start;
+    for _ in 0..MAX_WALK_STEPS {
+        let hit = scene.hit_object(ray, t_start, settings.max_distance);
+        let distance = -(1.0 - random_01()).ln() / channel_extinction;
+
+        match hit {
+            Some((_, record)) if record.t <= distance => {
+                let transmittance = transmittance(record.t);
+                step(transmittance, transmittance);
+                let pdf = (pdfs.x + pdfs.y + pdfs.z) / 3.0;
+                *throughput = *throughput * carried / pdf;
+                return hit;
+            }
+            _ => {
+                *volume_bounces += 1;
+                if *volume_bounces > settings.bounce_limits.volume {
+                    return None;
+                }
+                // Particles scatter the same in every direction, so the
+                // phase function and its density cancel out.
+                let pdf = extinction * transmittance(distance);
+                step(medium.scattering_albedo * pdf, pdf);
+                let position = ray.at(distance);
+                record(path, position, VertexKind::Diffuse);
+                *ray = Ray::new(position, sample_unit_sphere((random_01(), random_01())));
+                t_start = 0.0;
+            }
+        }
+    }
+    None
+}
+
+/// Radiance and alpha of `ray`, like `ray_color`. `first_hit` is its closest
+/// hit, when already found.
+fn trace_ray(
+    ray: &Ray,
+    mut first_hit: Option<Option<(ObjectId, HitRecord)>>,
+    scene: &Scene,
+    settings: &RenderSettings,
+    sampler: &mut dyn Sampler,
+    mut path: Option<&mut Vec<PathVertex>>,
+    mut aov: Option<&mut Aov>,
+) -> (Vec3, Float) {
+    let max_depth = settings.max_depth;
+    let t_min = scene.epsilon();
+
+    let mut ray = *ray;
+    let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+    let mut radiance = Vec3::new(0.0, 0.0, 0.0);
+
+    // Density with which the material sampled the current ray, zero when the
+    // bounce was specular (or for camera rays) and light sampling couldn't
+    // have found the same path.
+    let mut material_pdf = 0.0;
+
+    // Bounces taken so far, per kind.
+    let mut bounces = [0; 4];
+
+    // Light reaching a shadow catcher hit by the camera ray, with and
+    // without the objects casting shadows. `catching` is set while the ray
+    // scattered off the catcher hasn't hit anything yet.
+    let mut caught = false;
+    let mut catching = false;
+    let mut shadowed = Vec3::new(0.0, 0.0, 0.0);
+    let mut unshadowed = Vec3::new(0.0, 0.0, 0.0);
+
+    // Dielectric media the ray is inside of, in the order it entered them,
+    // starting with those holding the camera, and where along the ray to
+    // look for the next hit, past the surfaces of media crossed without a
+    // bounce, and where it entered the current medium.
+    let mut media = scene.media_at(ray.origin);
+    let mut t_start = t_min;
+    let mut t_medium = 0.0;
+
+    // Escaping rays are drawn one meter long in path dumps.
+    let escape_length = scene.units.from_meters(1.0);
+    record(&mut path, ray.origin, VertexKind::Camera);
+
+    for depth in 0..max_depth {
+        // Directions aren't normalized, so convert the distance to a ray parameter.
+        let t_max = settings.max_distance / ray.dir.length();
+        let medium = scene
+            .current_medium(&media)
+            .and_then(|medium| scene.material(medium).medium());
+        let hit = match (first_hit.take(), medium) {
+            (Some(hit), _) => fade_hit(hit, &ray, scene, settings),
+            (None, Some(medium)) => {
+                let walk = random_walk(
+                    &mut ray,
+                    &mut throughput,
+                    &medium,
+                    t_start,
+                    scene,
+                    settings,
+                    &mut path,
+                    &mut bounces[BounceKind::Volume as usize],
+                );
+                if walk.is_none() {
+                    record(&mut path, ray.origin, VertexKind::Absorbed);
+                    break;
+                }
+                walk
+            }
+            (None, None) => fade_hit(
+                scene.hit_object(&ray, t_start, t_max),
+                &ray,
+                scene,
+                settings,
+            ),
+        };
+        // Rays outside dielectric media may scatter off clouds before
+        // reaching what they hit.
+        let clouds = scene
+            .clouds
+            .as_ref()
+            .filter(|clouds| clouds.mode == CloudMode::Medium);
+        if let (None, Some(clouds)) = (medium, clouds) {
+            let t_end = hit.as_ref().map_or(t_max, |(_, hit_info)| hit_info.t);
+            if let Some(t) = clouds.sample_collision(&ray, t_start, t_end) {
+                let position = ray.at(t);
+                if let Some(current) = scene.current_medium(&media) {
+                    let distance = (t - t_medium) * ray.dir.length();
+                    throughput = throughput * scene.transmittance(current, distance);
+                }
+                throughput = throughput * clouds.albedo;
+                catching = false;
+
+                let bounce_dimension = CAMERA_DIMENSIONS + depth as u32 * BOUNCE_DIMENSIONS;
+                sampler.set_dimension(bounce_dimension + 3);
+                let light = cloud_light(clouds, &ray, position, scene, settings, sampler);
+                radiance += clamp_bounce(throughput * light, depth + 1, settings);
+                let volume_bounces = &mut bounces[BounceKind::Volume as usize];
+                *volume_bounces += 1;
+                if depth + 1 == max_depth || *volume_bounces > settings.bounce_limits.volume {
+                    record(&mut path, position, VertexKind::Terminated);
+                    break;
+                }
+                record(&mut path, position, VertexKind::Diffuse);
+
+                // Sampling the phase function exactly leaves the throughput
+                // as it is.
+                sampler.set_dimension(bounce_dimension);
+                let direction = clouds.sample_phase(ray.dir, sampler.get_2d());
+                material_pdf = clouds.phase(ray.dir.unit().dot(direction));
+                ray = Ray::new(position, direction);
+                t_start = 0.0;
+                t_medium = 0.0;
+                continue;
+            }
+        }
+        let (object, mut hit_info) = match hit {
+            Some(hit) => hit,
+            None if depth == 0 && settings.transparent_background => {
+                let end = ray.origin + ray.dir.unit() * escape_length;
+                record(&mut path, end, VertexKind::Escaped);
+                return (Vec3::new(0.0, 0.0, 0.0), 0.0);
+            }
+            None => {
+                let end = ray.origin + ray.dir.unit() * escape_length;
+                record(&mut path, end, VertexKind::Escaped);
+
+                let mut background = scene.environment.radiance_from(ray.origin, ray.dir);
+                if let Some(clouds) = &scene.clouds {
+                    if clouds.mode == CloudMode::Background {
+                        background = clouds.over_background(&ray, background, &scene.environment);
+                    }
+                }
+                if depth == 0 {
+                    if let Some(aov) = aov.take() {
+                        aov.albedo = Vec3::new(
+                            clamp(background.x, 0.0, 1.0),
+                            clamp(background.y, 0.0, 1.0),
+                            clamp(background.z, 0.0, 1.0),
+                        );
+                    }
+                }
+                let background = background * sun_weight(&ray, scene, material_pdf);
+                if catching {
+                    shadowed += throughput * background;
+                } else {
+                    radiance += clamp_bounce(throughput * background, depth, settings);
+                }
+                break;
+            }
+        };
+        if let (None, Some(current)) = (medium, scene.current_medium(&media)) {
+            let distance = (hit_info.t - t_medium) * ray.dir.length();
+            throughput = throughput * scene.transmittance(current, distance);
+        }
+        scene.apply_shading_normal(object, &mut hit_info);
+
+        let material = scene.material(hit_info.material);
+        if depth == 0 {
+            if let Some(aov) = aov.take() {
+                aov.albedo = material.albedo(&hit_info);
+                aov.normal = hit_info.normal;
+                aov.depth = hit_info.t * ray.dir.length();
+                aov.previous_position = scene.previous_position(object, hit_info.position);
+            }
+        }
+        if !scene.shades(&hit_info) {
+            record(&mut path, hit_info.position, VertexKind::Absorbed);
+            break;
+        }
+        if material.refractive_index().is_some() {
+            // Surfaces of media losing to the one the ray is in don't bend
+            // it, see `Scene::set_priority`.
+            let current = scene.current_medium(&media);
+            let inside = media
+                .iter()
+                .rposition(|&medium| medium == hit_info.material);
+            let crossed = if hit_info.front_face {
+                let priority = scene.priority(hit_info.material);
+                if current.is_some_and(|current| priority < scene.priority(current)) {
+                    media.push(hit_info.material);
+                    true
+                } else {
+                    hit_info.outside_index = scene.refractive_index(current);
+                    false
+                }
+            } else {
+                match inside {
+                    Some(index) if current != Some(hit_info.material) => {
+                        media.remove(index);
+                        true
+                    }
+                    _ => {
+                        let mut outside = media.clone();
+                        if let Some(index) = inside {
+                            outside.remove(index);
+                        }
+                        let outside = scene.current_medium(&outside);
+                        hit_info.outside_index = scene.refractive_index(outside);
+                        false
+                    }
+                }
+            };
+            if crossed {
+                t_start = hit_info.t + t_min;
+                t_medium = hit_info.t;
+                continue;
+            }
+        }
+        let emitted = material.emitted(&hit_info);
+        let weight = if material_pdf > 0.0 {
+            let light_pdf = scene.light_pdf(ray.origin, ray.dir);
+            Some(power_heuristic(material_pdf, light_pdf))
+        } else {
+            None
+        };
+        if catching && !scene.casts_shadows(object) {
+            // The light seen from the catcher wasn't blocked.
+            shadowed += throughput * emitted * weight.unwrap_or(1.0);
+            record(&mut path, hit_info.position, VertexKind::Absorbed);
+            break;
+        }
+        catching = false;
+        let is_catcher = depth == 0 && scene.is_shadow_catcher(object);
+        caught |= is_catcher;
+        if is_catcher {
+            // The catcher itself is only seen through its shadows.
+        } else if let Some(weight) = weight {
+            radiance += clamp_bounce(throughput * emitted * weight, depth, settings);
+        } else {
+            radiance += clamp_bounce(throughput * emitted, depth, settings);
+        }
+
+        let bounce_dimension = CAMERA_DIMENSIONS + depth as u32 * BOUNCE_DIMENSIONS;
+        sampler.set_dimension(bounce_dimension);
+        let scatter = match material.scatter(&ray, &hit_info, sampler) {
+            Some(scatter) => scatter,
+            None => {
+                record(&mut path, hit_info.position, VertexKind::Absorbed);
+                break;
+            }
+        };
+        if let (Some(preview), false) = (scene.preview_environment(), scatter.is_specular) {
+            // Previews follow mirrors and glass but stop at the first other
+            // surface, lit by the prefiltered environment alone.
+            if !is_catcher {
+                sampler.set_dimension(bounce_dimension + 3);
+                let light = preview_light(&hit_info, scene, preview, settings, sampler);
+                radiance += clamp_bounce(throughput * light, depth + 1, settings);
+            }
+            record(&mut path, hit_info.position, VertexKind::Terminated);
+            break;
+        }
+
+        // Thin glass lets light through without holding a medium.
+        let is_medium = material.refractive_index().is_some() || material.medium().is_some();
+        if scatter.kind == BounceKind::Transmission && is_medium {
+            if hit_info.front_face {
+                media.push(hit_info.material);
+            } else if let Some(index) = media.iter().rposition(|&m| m == hit_info.material) {
+                media.remove(index);
+            }
+        }
+        t_start = t_min;
+        t_medium = 0.0;
+
+        let kind_bounces = &mut bounces[scatter.kind as usize];
+        *kind_bounces += 1;
+        let last_bounce =
+            depth + 1 == max_depth || *kind_bounces > settings.bounce_limits.limit(scatter.kind);
+
+        if last_bounce {
+            record(&mut path, hit_info.position, VertexKind::Terminated);
+        } else if scatter.is_specular {
+            record(&mut path, hit_info.position, VertexKind::Specular);
+        } else {
+            record(&mut path, hit_info.position, VertexKind::Diffuse);
+        }
+
+        if scatter.is_specular {
+            if last_bounce {
+                break;
+            }
+            material_pdf = 0.0;
+            throughput = throughput * scatter.attenuation;
+            ray = scatter.scattered;
+            if is_catcher {
+                unshadowed += throughput * unshadowed_light(&ray, scene, settings, 0.0);
+                catching = true;
+            }
+            continue;
+        }
+
+        // Next-event estimation: connect non-specular hits to a light directly,
+        // weighted against the chance of hitting it by following the material.
+        sampler.set_dimension(bounce_dimension + 3);
+        if let Some(direction) = scene.sample_light_direction(hit_info.position, sampler) {
+            let light_ray = Ray::new(hit_info.position, direction);
+            let light_pdf = scene.light_pdf(hit_info.position, direction);
+            let light_scattering_pdf = material.scattering_pdf(&ray, &hit_info, &light_ray);
+            let attenuation = material
+                .directional_attenuation(&ray, &hit_info, &light_ray)
+                .unwrap_or(scatter.attenuation);
+
+            if light_pdf > 0.0 && light_scattering_pdf > 0.0 {
+                let t_max = settings.max_distance / light_ray.dir.length();
+                // Directions towards the sun reach it by escaping.
+                let emitted = light_emitted(&light_ray, scene, t_min, t_max);
+                let sampling_pdf = material.sampling_pdf(&ray, &hit_info, &light_ray);
+                let weight = power_heuristic(light_pdf, sampling_pdf);
+                if is_catcher {
+                    let scale =
+                        throughput * attenuation * (light_scattering_pdf * weight / light_pdf);
+                    if let Some(emitted) = emitted {
+                        shadowed += scale * emitted;
+                    }
+                    unshadowed += scale * unshadowed_light(&light_ray, scene, settings, 0.0);
+                } else if let Some(emitted) = emitted {
+                    let light = throughput
+                        * attenuation
+                        * emitted
+                        * (light_scattering_pdf * weight / light_pdf);
+                    radiance += clamp_bounce(light, depth + 1, settings);
+                }
+            }
+        }
+
+        // Delta lights can't be hit, so they are all connected to directly.
+        for light in &scene.delta_lights {
+            let sample = match light.illuminate(hit_info.position) {
+                Some(sample) => sample,
+                None => continue,
+            };
+            let light_ray = Ray::new(hit_info.position, sample.direction);
+            let light_scattering_pdf = material.scattering_pdf(&ray, &hit_info, &light_ray);
+            if light_scattering_pdf <= 0.0 {
+                continue;
+            }
+
+            let attenuation = material
+                .directional_attenuation(&ray, &hit_info, &light_ray)
+                .unwrap_or(scatter.attenuation);
+
+            let t_max = Float::min(sample.distance - t_min, settings.max_distance);
+            let light = throughput * attenuation * sample.irradiance * light_scattering_pdf;
+            let visible = scene.hit(&light_ray, t_min, t_max).is_none();
+            let light = light * cloud_transmittance(&light_ray, scene, t_min, t_max);
+            if is_catcher {
+                if visible {
+                    shadowed += light;
+                }
+                let blocked = scene.hit_filtered(&light_ray, t_min, t_max, |sphere| {
+                    !scene.casts_shadows(sphere)
+                });
+                if blocked.is_none() {
+                    unshadowed += light;
+                }
+            } else if visible {
+                radiance += clamp_bounce(light, depth + 1, settings);
+            }
+        }
+
+        let scattering_pdf = material.scattering_pdf(&ray, &hit_info, &scatter.scattered);
+        if last_bounce || scatter.pdf <= 0.0 || scattering_pdf <= 0.0 {
+            break;
+        }
+
+        material_pdf = scatter.pdf;
+        throughput = throughput * scatter.attenuation * (scattering_pdf / scatter.pdf);
+        ray = scatter.scattered;
+        if is_catcher {
+            unshadowed += throughput * unshadowed_light(&ray, scene, settings, material_pdf);
+            catching = true;
+        }
+    }
+
+    // Shadow catchers are as opaque as the share of their light blocked by
+    // other objects.
+    let alpha = if caught {
+        let unshadowed = luminance(unshadowed);
+        if unshadowed > 0.0 {
+            clamp(1.0 - luminance(shadowed) / unshadowed, 0.0, 1.0)
+        } else {
+            0.0
+        }
+    } else {
+        1.0
+    };
+
+    (clamp_luminance(radiance, settings.clamp_sample), alpha)
+}
+
+/// Light leaving the non-specular surface of `record` in a preview: the
+/// irradiance of the environment on its albedo, unless a shadow ray aimed at
+/// the bright parts of the environment is blocked.
+fn preview_light(
+    record: &HitRecord,
+    scene: &Scene,
+    preview: &PreviewEnvironment,
+    settings: &RenderSettings,
+    sampler: &mut dyn Sampler,
+) -> Vec3 {
+    // Light from behind the surface stands for light from its mirror image.
+    let normal = record.normal;
+    let mut direction = preview.sample(sampler.get_2d());
+    if direction.dot(normal) < 0.0 {
+        direction = direction - normal * (2.0 * direction.dot(normal));
+    }
+    let shadow_ray = Ray::new(record.position, direction);
+    let t_max = settings.max_distance / direction.length();
+    if scene.hit(&shadow_ray, scene.epsilon(), t_max).is_some() {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+    let material = scene.material(record.material);
+    material.albedo(record) * preview.irradiance(normal) / consts::PI
+}
+
+/// Light arriving along `ray` when the objects casting shadows are ignored,
+/// weighted against light sampling like a ray sampled by a material with
+/// density `material_pdf`, zero for specular bounces.
+fn unshadowed_light(
+    ray: &Ray,
+    scene: &Scene,
+    settings: &RenderSettings,
+    material_pdf: Float,
+) -> Vec3 {
+    let t_max = settings.max_distance / ray.dir.length();
+    let hit = scene.hit_filtered(ray, scene.epsilon(), t_max, |sphere| {
+        !scene.casts_shadows(sphere)
+    });
+    match hit {
+        None => {
+            let radiance = scene.environment.radiance_from(ray.origin, ray.dir);
+            radiance * sun_weight(ray, scene, material_pdf)
+        }
+        Some((_, record)) if scene.shades(&record) => {
+            let emitted = scene.material(record.material).emitted(&record);
+            if material_pdf > 0.0 {
+                let light_pdf = scene.light_pdf(ray.origin, ray.dir);
+                emitted * power_heuristic(material_pdf, light_pdf)
+            } else {
+                emitted
+            }
+        }
+        Some(_) => Vec3::new(0.0, 0.0, 0.0),
+    }
+}
+
+/// Light emitted towards the origin of `light_ray` by what it hits between
+/// `t_min` and `t_max`, or by the sun when it escapes, dimmed by the clouds
+/// on the way. `None` when something else blocks it.
+fn light_emitted(light_ray: &Ray, scene: &Scene, t_min: Float, t_max: Float) -> Option<Vec3> {
+    // Directions towards the sun reach it by escaping.
+    let (emitted, t_end) = match scene.hit(light_ray, t_min, t_max) {
+        Some(light_hit) if scene.shades(&light_hit) => (
+            scene.material(light_hit.material).emitted(&light_hit),
+            light_hit.t,
+        ),
+        Some(_) => return None,
+        None => (scene.environment.sun()?.radiance(light_ray.dir), t_max),
+    };
+    Some(emitted * cloud_transmittance(light_ray, scene, t_min, t_end))
+}
+
+/// Share of the light going through the clouds along `ray` between `t_min`
+/// and `t_max`, 1 unless they are a medium.
+fn cloud_transmittance(ray: &Ray, scene: &Scene, t_min: Float, t_max: Float) -> Float {
+    match &scene.clouds {
+        Some(clouds) if clouds.mode == CloudMode::Medium => clouds.transmittance(ray, t_min, t_max),
+        _ => 1.0,
+    }
+}
+
+/// Light scattered by the droplets of `clouds` at `position` back along
+/// `ray`, from a light sampled like from surfaces and from every delta
+/// light.
+fn cloud_light(
+    clouds: &Clouds,
+    ray: &Ray,
+    position: Vec3,
+    scene: &Scene,
+    settings: &RenderSettings,
+    sampler: &mut dyn Sampler,
+) -> Vec3 {
+    let t_min = scene.epsilon();
+    let incoming = ray.dir.unit();
+    let mut light = Vec3::new(0.0, 0.0, 0.0);
+    if let Some(direction) = scene.sample_light_direction(position, sampler) {
+        let light_ray = Ray::new(position, direction);
+        let light_pdf = scene.light_pdf(position, direction);
+        let phase = clouds.phase(incoming.dot(direction.unit()));
+        if light_pdf > 0.0 {
+            let t_max = settings.max_distance / light_ray.dir.length();
+            if let Some(emitted) = light_emitted(&light_ray, scene, t_min, t_max) {
+                let weight = power_heuristic(light_pdf, phase);
+                light += emitted * (phase * weight / light_pdf);
+            }
+        }
+    }
+    for delta in &scene.delta_lights {
+        let sample = match delta.illuminate(position) {
+            Some(sample) => sample,
+            None => continue,
+        };
+        let light_ray = Ray::new(position, sample.direction);
+        let t_max = Float::min(sample.distance - t_min, settings.max_distance);
+        if scene.hit(&light_ray, t_min, t_max).is_none() {
+            let phase = clouds.phase(incoming.dot(sample.direction.unit()));
+            let transmittance = clouds.transmittance(&light_ray, t_min, t_max);
+            light += sample.irradiance * (phase * transmittance);
+        }
+    }
+    light
+}
+
+/// MIS weight of the environment seen along `ray`, which escaped the scene
+/// after a bounce sampled with density `material_pdf`, against sampling the
+/// sun. 1 without a sun or after specular bounces.
+fn sun_weight(ray: &Ray, scene: &Scene, material_pdf: Float) -> Float {
+    if material_pdf > 0.0 && scene.environment.sun().is_some() {
+        power_heuristic(material_pdf, scene.light_pdf(ray.origin, ray.dir))
+    } else {
+        1.0
+    }
+}
+
+/// `hit`, the closest hit of `ray`, or the next one behind it when it fades
+/// out, see `RenderSettings::distance_fade`.
+fn fade_hit(
+    mut hit: Option<(ObjectId, HitRecord)>,
+    ray: &Ray,
+    scene: &Scene,
+    settings: &RenderSettings,
+) -> Option<(ObjectId, HitRecord)> {
+    let length = ray.dir.length();
+    let band = settings.max_distance * settings.distance_fade;
+    let start = settings.max_distance - band;
+    while let Some((_, record)) = &hit {
+        let distance = record.t * length;
+        // Also false for infinite distances, which never fade.
+        if !(distance > start && random_01() < (distance - start) / band) {
+            break;
+        }
+        let t_start = record.t + scene.epsilon();
+        hit = scene.hit_object(ray, t_start, settings.max_distance / length);
+    }
+    hit
+}
+
+/// Clamps light that bounced `bounces` times before reaching the camera,
+/// following whether it is direct or indirect.
+fn clamp_bounce(light: Vec3, bounces: i32, settings: &RenderSettings) -> Vec3 {
+    match bounces {
+        0 => light,
+        1 => clamp_luminance(light, settings.clamp_direct),
+        _ => clamp_luminance(light, settings.clamp_indirect),
+    }
+}
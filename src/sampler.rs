@@ -0,0 +1,272 @@
+use crate::blue_noise::BlueNoise;
+use crate::maths::*;
+
+/// Source of the uniform numbers of one sample, dimension after dimension.
+///
+/// Every random decision of a sample (position in the pixel, on the lens,
+/// scattering direction, light picked...) takes its own dimension. Samplers
+/// spreading the values of each dimension evenly over the samples of a pixel
+/// converge faster than independent numbers, as long as a given dimension is
+/// used for the same decision by every sample, which `set_dimension` allows.
+pub trait Sampler {
+    /// Uniform number in [0, 1) for the next dimension.
+    fn get_1d(&mut self) -> Float;
+
+    /// Uniform point in [0, 1) x [0, 1) for the next two dimensions.
+    fn get_2d(&mut self) -> (Float, Float) {
+        let x = self.get_1d();
+        (x, self.get_1d())
+    }
+
+    /// Makes `dimension` the next dimension to be drawn.
+    fn set_dimension(&mut self, dimension: u32);
+}
+
+/// Independent numbers from the random sequence of the current thread, for
+/// when no pixel sample is being computed.
+pub struct IndependentSampler;
+
+impl Sampler for IndependentSampler {
+    fn get_1d(&mut self) -> Float {
+        random_01()
+    }
+
+    fn set_dimension(&mut self, _dimension: u32) {}
+}
+
+/// How the samples of a pixel are spread.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SamplerType {
+    /// Independent random numbers.
+    #[default]
+    Independent,
+    /// The pixel is split into a grid of as many cells as the sample count
+    /// allows, each sample being jittered inside its own cell. Samples past
+    /// the largest square count, and the other dimensions, are independent.
+    Stratified,
+    /// Halton sequence, with random digit permutations per pixel. Dimensions
+    /// past the first primes are independent.
+    Halton,
+    /// Owen scrambled Sobol (0, 2) sequence, with an independent shuffle of
+    /// the samples for every pair of dimensions (Burley 2020).
+    Sobol,
+}
+
+impl SamplerType {
+    /// Sampler for sample `index` out of `count` of the pixel whose sequences
+    /// are scrambled by `seed`. Values only depend on those and the random
+    /// sequence of the current thread, so samples can still be computed in
+    /// any order.
+    pub fn start_sample(self, seed: u64, index: usize, count: usize) -> PixelSampler {
+        PixelSampler {
+            sampler_type: self,
+            seed,
+            index: index as u64,
+            count: count as u64,
+            dimension: 0,
+            dither: None,
+        }
+    }
+}
+
+/// Sampler of a single pixel sample, created by `SamplerType::start_sample`.
+#[derive(Clone, Copy, Debug)]
+pub struct PixelSampler {
+    sampler_type: SamplerType,
+    seed: u64,
+    index: u64,
+    count: u64,
+    dimension: u32,
+    /// Pixel whose blue noise value shifts every dimension.
+    dither: Option<(usize, usize)>,
+}
+
+impl PixelSampler {
+    /// Shifts the values of every dimension, wrapping around 1, by the value
+    /// of the shared blue noise texture at pixel (`x`, `y`), the texture being
+    /// offset differently for each dimension. When neighbouring pixels use the
+    /// same sequences, their errors then differ the way the texture values
+    /// do, and what is left of the noise has no low frequencies (Georgiev and
+    /// Fajardo 2016).
+    pub fn dithered(self, x: usize, y: usize) -> Self {
+        PixelSampler {
+            dither: Some((x, y)),
+            ..self
+        }
+    }
+
+    fn value(&self, dimension: u32) -> Float {
+        match self.sampler_type {
+            SamplerType::Independent => random_01(),
+            SamplerType::Stratified => {
+                let cells = (self.count as Float).sqrt() as u64;
+                if dimension >= 2 || self.index >= cells * cells {
+                    return random_01();
+                }
+                let cell = if dimension == 0 {
+                    self.index % cells
+                } else {
+                    self.index / cells
+                };
+                (cell as Float + random_01()) / cells as Float
+            }
+            SamplerType::Halton => match PRIMES.get(dimension as usize) {
+                Some(&base) => scrambled_radical_inverse(
+                    self.index,
+                    self.count,
+                    base,
+                    mix_seed(self.seed, dimension as u64),
+                ),
+                None => random_01(),
+            },
+            SamplerType::Sobol => {
+                let pair_seed = mix_seed(self.seed, (dimension / 2) as u64);
+                let index = nested_uniform_scramble(self.index as u32, pair_seed as u32);
+                let value = if dimension & 1 == 0 {
+                    index.reverse_bits()
+                } else {
+                    sobol_second_dimension(index)
+                };
+                let value = nested_uniform_scramble(value, (pair_seed >> 32) as u32 ^ dimension);
+                let bits = Float::MANTISSA_DIGITS.min(32);
+                (value >> (32 - bits)) as Float / (1u64 << bits) as Float
+            }
+        }
+    }
+}
+
+impl Sampler for PixelSampler {
+    fn get_1d(&mut self) -> Float {
+        let dimension = self.dimension;
+        self.dimension += 1;
+
+        let value = self.value(dimension);
+        let (x, y) = match self.dither {
+            Some(pixel) => pixel,
+            None => return value,
+        };
+
+        let texture = BlueNoise::shared();
+        let offset = mix_seed(!self.seed, dimension as u64);
+        let x = x + (offset as u32) as usize % texture.size();
+        let y = y + (offset >> 32) as usize % texture.size();
+        let value = value + texture.value(x, y);
+        if value >= 1.0 {
+            value - 1.0
+        } else {
+            value
+        }
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+}
+
+/// Bases of the Halton dimensions.
+const PRIMES: [u64; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+/// Digits of `index` in `base` mirrored around the decimal point, each digit
+/// position having its own random permutation of the digit values. `count`
+/// is the number of indices in use, whose digit positions are all permuted.
+fn scrambled_radical_inverse(mut index: u64, count: u64, base: u64, seed: u64) -> Float {
+    let inverse_base = 1.0 / base as Float;
+    let mut scale = 1.0;
+    let mut value = 0.0;
+    let mut position = 0;
+
+    let mut last = count.saturating_sub(1).max(index);
+    while last != 0 {
+        scale *= inverse_base;
+        let digit = permute(
+            (index % base) as u32,
+            base as u32,
+            mix_seed(seed, position) as u32,
+        );
+        value += digit as Float * scale;
+
+        index /= base;
+        last /= base;
+        position += 1;
+    }
+
+    // The leading zeros past the last digit are permuted too, or values
+    // wouldn't be uniform. Independent random digits add up to a uniform
+    // number, so draw that instead of going through them one by one.
+    let tail = uniform_from_bits(mix_seed(seed, position));
+    value += tail * scale;
+
+    // Rounding can land on 1 when every digit is the largest one.
+    value.min(1.0 - Float::EPSILON / 2.0)
+}
+
+/// Element `i` of a random permutation of [0, `length`) picked by `seed`,
+/// without building the permutation (Kensler 2013). A bijective hash of the
+/// smallest power of two range holding `length` is repeated until it lands
+/// back in [0, `length`).
+fn permute(mut i: u32, length: u32, seed: u32) -> u32 {
+    let mut mask = length - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= seed >> 16;
+        i ^= (i & mask) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= seed >> 23;
+        i ^= (i & mask) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & mask) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= mask;
+        i ^= i >> 5;
+        if i < length {
+            break;
+        }
+    }
+
+    (i.wrapping_add(seed)) % length
+}
+
+/// Second dimension of the Sobol sequence, whose generator matrix is the
+/// binary Pascal matrix.
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let mut direction = 1u32 << 31;
+    let mut value = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            value ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    value
+}
+
+/// Hash based Owen scrambling of the bits of `x`: every bit is flipped
+/// depending on the bits above it (Laine and Karras 2011, Burley 2020).
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x.reverse_bits()
+}
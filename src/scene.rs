@@ -0,0 +1,1348 @@
+use crate::accelerator::{Accelerator, AcceleratorKind};
+use crate::animation::Animation;
+use crate::bvh::{BvhQuality, Precision, RAY_PACKET_WIDTH};
+use crate::camera::CameraView;
+use crate::clouds::Clouds;
+use crate::content_hash::ContentHash;
+use crate::environment::Environment;
+use crate::hitable::*;
+use crate::light::DeltaLight;
+use crate::light_cache::LightCache;
+use crate::material::{MaterialId, MaterialType, ThinFilm};
+use crate::maths::*;
+use crate::mesh::Mesh;
+use crate::ocean::*;
+use crate::plane::{Disk, Plane};
+use crate::preview::PreviewEnvironment;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::shape::Shape;
+use crate::sky::PhysicalSky;
+use crate::sphere::{Sphere, UvProjection};
+use crate::stats::*;
+use crate::texture::{BumpMap, NormalMap};
+
+use std::collections::HashSet;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+/// Distance, in meters, a scattered ray has to travel before it is allowed to hit anything.
+#[cfg(not(feature = "f32"))]
+const EPSILON_METERS: Float = 0.0001;
+/// Single precision needs a wider margin.
+#[cfg(feature = "f32")]
+const EPSILON_METERS: Float = 0.001;
+
+/// Length unit a scene was authored in.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Units {
+    Meters,
+    Centimeters,
+    Millimeters,
+}
+
+impl Units {
+    pub fn meters_per_unit(self) -> Float {
+        match self {
+            Units::Meters => 1.0,
+            Units::Centimeters => 0.01,
+            Units::Millimeters => 0.001,
+        }
+    }
+
+    /// Converts a length given in meters into this unit.
+    pub fn from_meters(self, meters: Float) -> Float {
+        meters / self.meters_per_unit()
+    }
+}
+
+/// Handle to an object of a `Scene`: a sphere, or a shape, mesh or instance
+/// with its bounding sphere in `Scene::spheres`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ObjectId(pub usize);
+
+/// Objects and materials are stored in flat arrays and refer to each other
+/// through handles, so spheres can share or swap materials cheaply.
+pub struct Scene {
+    pub materials: Vec<MaterialType>,
+    /// Spheres of the scene, and the bounding spheres of its other objects,
+    /// indexed by `ObjectId`. Once it has been rendered, move them through
+    /// the scene methods, or call `invalidate_bvh`, so rays see them where
+    /// they are.
+    pub spheres: Vec<Sphere>,
+    /// Spheres as they were added, before `update_transform`.
+    rest_spheres: Vec<Sphere>,
+    /// Shape of every object that isn't a sphere, taking the place of its
+    /// bounding sphere, which rays don't hit, and the same before
+    /// `update_transform`.
+    shapes: Vec<Option<Shape>>,
+    rest_shapes: Vec<Option<Shape>>,
+    /// Transform of each sphere given to `update_transform`, and the one it
+    /// had in the previous frame of an animation, for motion vectors.
+    transforms: Vec<Mat4>,
+    previous_transforms: Vec<Option<Mat4>>,
+    /// Emitters sampled for direct lighting.
+    pub lights: Vec<ObjectId>,
+    /// Point, directional and spot lights, all of them sending a shadow ray
+    /// from every non-specular hit.
+    pub delta_lights: Vec<DeltaLight>,
+    pub environment: Environment,
+    /// Layer of clouds over the scene, if it has one.
+    pub clouds: Option<Clouds>,
+    pub units: Units,
+    /// Camera the scene was set up with, if it came with one.
+    pub camera: Option<CameraView>,
+    /// Keyframes of the camera and objects, see `Animation::apply`.
+    pub animation: Animation,
+    /// Optional names of the spheres and materials, for reports.
+    names: Vec<Option<String>>,
+    material_names: Vec<Option<String>>,
+    /// Whether each material shades both sides of surfaces.
+    two_sided: Vec<bool>,
+    /// Priority of each material among overlapping media.
+    priorities: Vec<i32>,
+    /// Absorption coefficients of the medium inside each material, per
+    /// scene unit.
+    absorptions: Vec<Vec3>,
+    normal_maps: Vec<Option<NormalMap>>,
+    bump_maps: Vec<Option<BumpMap>>,
+    oceans: Vec<Option<Ocean>>,
+    thin_films: Vec<Option<ThinFilm>>,
+    /// Whether each sphere only shows the shadows and reflections it
+    /// receives to the camera.
+    shadow_catchers: Vec<bool>,
+    /// How texture coordinates are laid over each sphere, when given.
+    uv_projections: Vec<Option<UvProjection>>,
+    stats: Option<StatsCounters>,
+    accelerator_kind: AcceleratorKind,
+    bvh_quality: BvhQuality,
+    precision: Precision,
+    /// Built on the first intersection test.
+    accelerator: OnceLock<Box<dyn Accelerator>>,
+    /// Shapes left out of the accelerator, built along with it.
+    unbounded: OnceLock<Vec<usize>>,
+    light_cache: Option<LightCache>,
+    preview_environment: Option<PreviewEnvironment>,
+    /// Replaces the fixed self-intersection offset, in scene units.
+    epsilon: Option<Float>,
+}
+
+impl Scene {
+    pub fn new(units: Units) -> Self {
+        Scene {
+            materials: Vec::new(),
+            spheres: Vec::new(),
+            rest_spheres: Vec::new(),
+            shapes: Vec::new(),
+            rest_shapes: Vec::new(),
+            transforms: Vec::new(),
+            previous_transforms: Vec::new(),
+            lights: Vec::new(),
+            delta_lights: Vec::new(),
+            environment: Environment::default(),
+            clouds: None,
+            units,
+            camera: None,
+            animation: Animation::default(),
+            names: Vec::new(),
+            material_names: Vec::new(),
+            two_sided: Vec::new(),
+            priorities: Vec::new(),
+            absorptions: Vec::new(),
+            normal_maps: Vec::new(),
+            bump_maps: Vec::new(),
+            oceans: Vec::new(),
+            thin_films: Vec::new(),
+            shadow_catchers: Vec::new(),
+            uv_projections: Vec::new(),
+            stats: None,
+            accelerator_kind: AcceleratorKind::default(),
+            bvh_quality: BvhQuality::default(),
+            precision: Precision::default(),
+            accelerator: OnceLock::new(),
+            unbounded: OnceLock::new(),
+            light_cache: None,
+            preview_environment: None,
+            epsilon: None,
+        }
+    }
+
+    /// Adds a material. Lights only emit from the outside of surfaces by
+    /// default, other materials shade both sides.
+    pub fn add_material(&mut self, material: MaterialType) -> MaterialId {
+        let is_light = matches!(material, MaterialType::DiffuseLight { .. });
+        self.materials.push(material);
+        self.material_names.push(None);
+        self.two_sided.push(!is_light);
+        self.priorities.push(0);
+        self.absorptions.push(Vec3::new(0.0, 0.0, 0.0));
+        self.normal_maps.push(None);
+        self.bump_maps.push(None);
+        self.oceans.push(None);
+        self.thin_films.push(None);
+        MaterialId(self.materials.len() - 1)
+    }
+
+    pub fn material(&self, id: MaterialId) -> &MaterialType {
+        &self.materials[id.0]
+    }
+
+    pub fn add(&mut self, sphere: Sphere) -> ObjectId {
+        self.rest_spheres.push(sphere);
+        self.spheres.push(sphere);
+        self.shapes.push(None);
+        self.rest_shapes.push(None);
+        self.transforms.push(Mat4::identity());
+        self.previous_transforms.push(None);
+        self.names.push(None);
+        self.shadow_catchers.push(false);
+        self.uv_projections.push(None);
+        self.invalidate_bvh();
+        if let Some(stats) = &mut self.stats {
+            stats.push();
+        }
+        ObjectId(self.spheres.len() - 1)
+    }
+
+    /// Adds an emissive sphere and registers it for direct light sampling.
+    pub fn add_light(&mut self, sphere: Sphere) -> ObjectId {
+        let id = self.add(sphere);
+        self.lights.push(id);
+        id
+    }
+
+    /// Adds an object other than a sphere. It gets a sphere id like others, so
+    /// names, shadow catchers and lights work the same, but the sphere
+    /// stored under it only bounds the shape: rays hit the shape itself.
+    pub fn add_shape(&mut self, shape: Shape) -> ObjectId {
+        let shape = shape
+            .with_precision(self.precision)
+            .with_accelerator(self.accelerator_kind);
+        let id = self.add(shape.bounding_sphere());
+        self.shapes[id.0] = Some(shape.clone());
+        self.rest_shapes[id.0] = Some(shape);
+        id
+    }
+
+    /// Puts `shape` in place of the object `id`, in the space it was added
+    /// in, keeping its material, name and transform, so objects can stand
+    /// in for others until they are loaded (see `MeshStream`).
+    pub fn replace_shape(&mut self, id: ObjectId, mut shape: Shape) {
+        shape.set_material(self.rest_spheres[id.0].material);
+        let shape = shape
+            .with_precision(self.precision)
+            .with_accelerator(self.accelerator_kind);
+        let transform = self.transforms[id.0];
+        let cull_backfaces = self.rest_spheres[id.0].cull_backfaces;
+        self.rest_spheres[id.0] = shape.bounding_sphere();
+        self.rest_spheres[id.0].cull_backfaces = cull_backfaces;
+        self.spheres[id.0] = self.rest_spheres[id.0].transformed(&transform);
+        self.shapes[id.0] = Some(shape.transformed(&transform));
+        self.rest_shapes[id.0] = Some(shape);
+        self.invalidate_bvh();
+    }
+
+    pub fn add_plane(&mut self, plane: Plane) -> ObjectId {
+        self.add_shape(Shape::Plane(plane))
+    }
+
+    pub fn add_disk(&mut self, disk: Disk) -> ObjectId {
+        self.add_shape(Shape::Disk(disk))
+    }
+
+    /// Adds an emissive disk and registers it for direct light sampling.
+    /// It only emits from its front unless its material is two sided.
+    pub fn add_disk_light(&mut self, disk: Disk) -> ObjectId {
+        let id = self.add_disk(disk);
+        self.lights.push(id);
+        id
+    }
+
+    pub fn sphere(&self, id: ObjectId) -> &Sphere {
+        &self.spheres[id.0]
+    }
+
+    /// Shape added under `id`, if it isn't a sphere.
+    pub fn shape(&self, id: ObjectId) -> Option<&Shape> {
+        self.shapes[id.0].as_ref()
+    }
+
+    /// Surface rays hit for the object `id`: its sphere or its shape.
+    pub fn surface(&self, id: ObjectId) -> &dyn Hitable {
+        match self.shape(id) {
+            Some(shape) => shape,
+            None => self.sphere(id),
+        }
+    }
+
+    pub fn set_name(&mut self, sphere: ObjectId, name: &str) {
+        self.names[sphere.0] = Some(name.to_string());
+    }
+
+    pub fn name(&self, sphere: ObjectId) -> Option<&str> {
+        self.names[sphere.0].as_deref()
+    }
+
+    /// First sphere called `name`.
+    pub fn sphere_named(&self, name: &str) -> Option<ObjectId> {
+        self.names
+            .iter()
+            .position(|other| other.as_deref() == Some(name))
+            .map(ObjectId)
+    }
+
+    pub fn set_material_name(&mut self, material: MaterialId, name: &str) {
+        self.material_names[material.0] = Some(name.to_string());
+    }
+
+    pub fn material_name(&self, material: MaterialId) -> Option<&str> {
+        self.material_names[material.0].as_deref()
+    }
+
+    /// First material called `name`.
+    pub fn material_named(&self, name: &str) -> Option<MaterialId> {
+        self.material_names
+            .iter()
+            .position(|other| other.as_deref() == Some(name))
+            .map(MaterialId)
+    }
+
+    /// Sets whether `material` shades the inside of surfaces too. One sided
+    /// materials are black when seen from inside, and dielectrics need both
+    /// sides to let light out.
+    pub fn set_two_sided(&mut self, material: MaterialId, two_sided: bool) {
+        self.two_sided[material.0] = two_sided;
+    }
+
+    pub fn two_sided(&self, material: MaterialId) -> bool {
+        self.two_sided[material.0]
+    }
+
+    /// Sets the priority of the medium inside `material` where it overlaps
+    /// others, like ice in a drink in a glass. Where media overlap, rays are
+    /// inside the one with the highest priority, the one entered last among
+    /// equal priorities, and the surfaces of the others are ignored there.
+    /// Only dielectrics are media. Priorities default to 0.
+    pub fn set_priority(&mut self, material: MaterialId, priority: i32) {
+        self.priorities[material.0] = priority;
+    }
+
+    pub fn priority(&self, material: MaterialId) -> i32 {
+        self.priorities[material.0]
+    }
+
+    /// Makes the medium inside `material` absorb light like colored glass:
+    /// light going a distance `d` through it keeps `exp(-d * absorption)`
+    /// of each channel, following the Beer-Lambert law. Coefficients are per
+    /// scene unit, and only dielectrics are media.
+    pub fn set_absorption(&mut self, material: MaterialId, absorption: Vec3) {
+        self.absorptions[material.0] = absorption;
+    }
+
+    pub fn absorption(&self, material: MaterialId) -> Vec3 {
+        self.absorptions[material.0]
+    }
+
+    /// Share of each channel of the light going `distance` through `medium`.
+    pub fn transmittance(&self, medium: MaterialId, distance: Float) -> Vec3 {
+        let absorption = self.absorption(medium);
+        Vec3::new(
+            (-absorption.x * distance).exp(),
+            (-absorption.y * distance).exp(),
+            (-absorption.z * distance).exp(),
+        )
+    }
+
+    /// Medium a ray is in, out of the `media` it entered, in order.
+    pub fn current_medium(&self, media: &[MaterialId]) -> Option<MaterialId> {
+        let mut current: Option<MaterialId> = None;
+        for &medium in media {
+            if current.is_none_or(|current| self.priority(medium) >= self.priority(current)) {
+                current = Some(medium);
+            }
+        }
+        current
+    }
+
+    /// Media holding `point`, in the order a ray coming from outside of them
+    /// all would enter them, the largest spheres first. Rays starting inside
+    /// media, like those of a camera under water, start with them.
+    pub fn media_at(&self, point: Vec3) -> Vec<MaterialId> {
+        let mut spheres: Vec<&Sphere> = self
+            .accelerator()
+            .candidates(point)
+            .into_iter()
+            .filter(|&index| self.shapes[index].is_none())
+            .map(|index| &self.spheres[index])
+            .filter(|sphere| {
+                let material = self.material(sphere.material);
+                let is_medium =
+                    material.refractive_index().is_some() || material.medium().is_some();
+                // Spheres of negative radius are hollow, their inside is outside.
+                is_medium
+                    && sphere.radius > 0.0
+                    && (point - sphere.position).length_squared() < sphere.radius * sphere.radius
+            })
+            .collect();
+        spheres.sort_by(|a, b| b.radius.total_cmp(&a.radius));
+        spheres.into_iter().map(|sphere| sphere.material).collect()
+    }
+
+    /// Refractive index inside `medium`, air's when there is none.
+    pub fn refractive_index(&self, medium: Option<MaterialId>) -> Float {
+        medium
+            .and_then(|medium| self.material(medium).refractive_index())
+            .unwrap_or(1.0)
+    }
+
+    /// Makes `material` shade with the normals of `normal_map`.
+    pub fn set_normal_map(&mut self, material: MaterialId, normal_map: Option<NormalMap>) {
+        self.normal_maps[material.0] = normal_map;
+    }
+
+    pub fn normal_map(&self, material: MaterialId) -> Option<&NormalMap> {
+        self.normal_maps[material.0].as_ref()
+    }
+
+    /// Makes `material` shade with the bumps of `bump_map`, on top of its
+    /// normal map if it has both. Heights are in scene units.
+    pub fn set_bump_map(&mut self, material: MaterialId, bump_map: Option<BumpMap>) {
+        self.bump_maps[material.0] = bump_map;
+    }
+
+    pub fn bump_map(&self, material: MaterialId) -> Option<&BumpMap> {
+        self.bump_maps[material.0].as_ref()
+    }
+
+    /// Makes `material` shade with the waves of `ocean`, on top of its
+    /// normal and bump maps. Surfaces keep their shape, only their normals
+    /// follow the waves.
+    pub fn set_ocean(&mut self, material: MaterialId, ocean: Option<Ocean>) {
+        self.oceans[material.0] = ocean;
+    }
+
+    pub fn ocean(&self, material: MaterialId) -> Option<&Ocean> {
+        self.oceans[material.0].as_ref()
+    }
+
+    /// Coats `material`, a metal or a dielectric, with `film`. Other
+    /// materials ignore it.
+    pub fn set_thin_film(&mut self, material: MaterialId, film: Option<ThinFilm>) {
+        self.thin_films[material.0] = film;
+    }
+
+    pub fn thin_film(&self, material: MaterialId) -> Option<ThinFilm> {
+        self.thin_films[material.0]
+    }
+
+    /// Adds a water material: a dielectric of the refractive index of water
+    /// absorbing light like clear water.
+    pub fn add_water(&mut self) -> MaterialId {
+        let water = self.add_material(MaterialType::Dialectric {
+            refractive_index: WATER_REFRACTIVE_INDEX,
+        });
+        let absorption = water_absorption() * self.units.meters_per_unit();
+        self.set_absorption(water, absorption);
+        water
+    }
+
+    /// Replaces the normal of `record`, a hit on `sphere`, by the one the
+    /// normal and bump maps and the ocean of its material give, if any, and
+    /// gives it the thin film of the material, and the tangent of the
+    /// surface if the material is anisotropic.
+    pub fn apply_shading_normal(&self, sphere: ObjectId, record: &mut HitRecord) {
+        record.film = self.thin_film(record.material);
+        let normal_map = self.normal_map(record.material);
+        let bump_map = self.bump_map(record.material);
+        let ocean = self.ocean(record.material);
+        let anisotropic = matches!(
+            self.material(record.material),
+            MaterialType::AnisotropicMetal { .. }
+        );
+        if normal_map.is_none() && bump_map.is_none() && ocean.is_none() && !anisotropic {
+            return;
+        }
+
+        let projection = self.uv_projection(sphere);
+        let shape = self.shape(sphere);
+        let sphere = self.sphere(sphere);
+        let mut normal = match shape {
+            Some(shape) => shape.normal_at(record.position),
+            None => (record.position - sphere.position) / sphere.radius,
+        };
+        if normal_map.is_some() || bump_map.is_some() || anisotropic {
+            // Meshes are projected from their bounding sphere when given a
+            // projection, other shapes have their own texture coordinates.
+            let frame = match (shape, projection) {
+                (Some(Shape::Mesh(mesh)), Some(projection)) => mesh
+                    .bounding_sphere()
+                    .projected_uv_frame(record.position, normal, projection),
+                (Some(shape), _) => shape.uv_frame(record.position),
+                (None, projection) => {
+                    sphere.uv_frame(record.position, projection.unwrap_or_default())
+                }
+            };
+            let ((u, v), (tangent, bitangent)) = (frame.uv, frame.tangents);
+            record.tangent = Some(tangent);
+            if let Some(normal_map) = normal_map {
+                normal = normal_map.normal(u, v, tangent, bitangent, normal);
+            }
+            if let Some(bump_map) = bump_map {
+                let (u_length, v_length) = frame.lengths;
+                normal = bump_map.normal((u, v), (tangent, bitangent, normal), u_length, v_length);
+            }
+        }
+        if let Some(ocean) = ocean {
+            let point = record.position * self.units.meters_per_unit();
+            normal = ocean.shading_normal(point, normal);
+        }
+        record.normal = if record.front_face { normal } else { -normal };
+    }
+
+    /// Whether the side of the surface hit by `record` scatters and emits light.
+    pub fn shades(&self, record: &HitRecord) -> bool {
+        record.front_face || self.two_sided(record.material)
+    }
+
+    /// Makes `sphere` a shadow catcher: camera rays hitting it see through
+    /// it, only keeping the shadows other objects cast on it, as alpha, and
+    /// the light they reflect off it. Other rays shade it with its material.
+    /// Meant for floors under objects rendered over a transparent background.
+    pub fn set_shadow_catcher(&mut self, sphere: ObjectId, shadow_catcher: bool) {
+        self.shadow_catchers[sphere.0] = shadow_catcher;
+    }
+
+    pub fn is_shadow_catcher(&self, sphere: ObjectId) -> bool {
+        self.shadow_catchers[sphere.0]
+    }
+
+    /// Lays the texture coordinates of the normal and bump maps over
+    /// `sphere` following `projection`, spherical by default. Meshes are
+    /// projected from their bounding sphere, and follow the first edge of
+    /// each triangle by default. UV bakes keep the spherical layout, and
+    /// other shapes their own.
+    pub fn set_uv_projection(&mut self, sphere: ObjectId, projection: UvProjection) {
+        self.uv_projections[sphere.0] = Some(projection);
+    }
+
+    /// Projection given to `sphere`, if any.
+    pub fn uv_projection(&self, sphere: ObjectId) -> Option<UvProjection> {
+        self.uv_projections[sphere.0]
+    }
+
+    /// Sets `Sphere::cull_backfaces` of `sphere`, wherever it was moved.
+    pub fn set_cull_backfaces(&mut self, sphere: ObjectId, cull_backfaces: bool) {
+        self.spheres[sphere.0].cull_backfaces = cull_backfaces;
+        self.rest_spheres[sphere.0].cull_backfaces = cull_backfaces;
+        self.invalidate_bvh();
+    }
+
+    /// Whether `sphere` casts shadows on shadow catchers, which lights and
+    /// catchers don't.
+    pub fn casts_shadows(&self, sphere: ObjectId) -> bool {
+        !self.shadow_catchers[sphere.0] && !self.lights.contains(&sphere)
+    }
+
+    /// Starts counting intersection tests, hits and time per sphere. Timing
+    /// every test slows rendering down, so this is off by default.
+    pub fn enable_stats(&mut self) {
+        self.stats = Some(StatsCounters::new(self.spheres.len()));
+        self.invalidate_bvh();
+    }
+
+    /// Counters of every sphere, when enabled.
+    pub fn stats(&self) -> Option<Vec<ObjectStats>> {
+        self.stats.as_ref().map(StatsCounters::snapshot)
+    }
+
+    /// Overrides the material of a single sphere.
+    pub fn set_material(&mut self, sphere: ObjectId, material: MaterialId) {
+        self.spheres[sphere.0].material = material;
+        self.rest_spheres[sphere.0].material = material;
+        let shapes = self.shapes[sphere.0].iter_mut();
+        for shape in shapes.chain(self.rest_shapes[sphere.0].iter_mut()) {
+            shape.set_material(material);
+        }
+    }
+
+    /// Places a sphere with `transform`, relative to where it was added, so
+    /// animations can update objects between frames without rebuilding the
+    /// scene.
+    pub fn update_transform(&mut self, sphere: ObjectId, transform: &Mat4) {
+        self.transforms[sphere.0] = *transform;
+        self.spheres[sphere.0] = self.rest_spheres[sphere.0].transformed(transform);
+        if let Some(rest) = &self.rest_shapes[sphere.0] {
+            self.shapes[sphere.0] = Some(rest.transformed(transform));
+        }
+        self.invalidate_bvh();
+    }
+
+    /// Places the objects of the animation where they are at `time`, in
+    /// seconds, and where they were at `previous`, if given, for motion
+    /// vectors.
+    pub fn animate(&mut self, time: Float, previous: Option<Float>) {
+        let current = self.animation.transforms_at(time);
+        let previous = previous.map(|previous| self.animation.transforms_at(previous));
+        for (index, (sphere, transform)) in current.iter().enumerate() {
+            self.update_transform(*sphere, transform);
+            let before = previous.as_ref().map(|previous| &previous[index].1);
+            self.set_previous_transform(*sphere, before);
+        }
+    }
+
+    /// Tells where `sphere` was in the previous frame, with `transform`
+    /// relative to where it was added like `update_transform`, so motion
+    /// vectors follow it. `None` leaves it still.
+    pub fn set_previous_transform(&mut self, sphere: ObjectId, transform: Option<&Mat4>) {
+        self.previous_transforms[sphere.0] = transform.copied();
+    }
+
+    /// Where `point`, on `sphere`, was in the previous frame.
+    pub fn previous_position(&self, sphere: ObjectId, point: Vec3) -> Vec3 {
+        let current = &self.transforms[sphere.0];
+        match &self.previous_transforms[sphere.0] {
+            Some(previous) if current.determinant3() != 0.0 => {
+                previous.transform_point(current.inverse().transform_point(point))
+            }
+            _ => point,
+        }
+    }
+
+    /// Replaces a material, for every sphere using it.
+    pub fn update_material(&mut self, id: MaterialId, material: MaterialType) {
+        self.materials[id.0] = material;
+    }
+
+    /// Picks one light uniformly, the sun of the environment counting as one,
+    /// and samples a direction towards it.
+    pub fn sample_light_direction(&self, origin: Vec3, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        let lights = self.lights_from(origin);
+        let sun = self.environment.sun();
+        let count = lights.len() + sun.is_some() as usize;
+        if count == 0 {
+            return None;
+        }
+
+        let index = ((sampler.get_1d() * count as Float) as usize).min(count - 1);
+        match (lights.get(index), sun) {
+            (Some(&light), _) => Some(
+                self.surface(light)
+                    .sample_direction_towards(origin, sampler.get_2d()),
+            ),
+            (None, Some(sun)) => Some(sun.sample_sun(sampler.get_2d())),
+            (None, None) => None,
+        }
+    }
+
+    /// Density of `sample_light_direction(origin, sampler)` returning `direction`.
+    pub fn light_pdf(&self, origin: Vec3, direction: Vec3) -> Float {
+        let lights = self.lights_from(origin);
+        let sun = self.environment.sun();
+        let count = lights.len() + sun.is_some() as usize;
+        if count == 0 {
+            return 0.0;
+        }
+
+        let mut sum: Float = lights
+            .iter()
+            .map(|&light| self.surface(light).pdf_value(origin, direction))
+            .sum();
+        if let Some(sun) = sun {
+            sum += sun.sun_pdf(direction);
+        }
+
+        sum / count as Float
+    }
+
+    /// Sets how the BVH the spheres are found through is built.
+    pub fn set_bvh_quality(&mut self, quality: BvhQuality) {
+        self.bvh_quality = quality;
+        self.invalidate_bvh();
+    }
+
+    pub fn bvh_quality(&self) -> BvhQuality {
+        self.bvh_quality
+    }
+
+    /// Sets the structure the spheres and the triangles of meshes are found
+    /// through, rebuilding those of the meshes already added.
+    pub fn set_accelerator_kind(&mut self, kind: AcceleratorKind) {
+        self.accelerator_kind = kind;
+        let shapes = self.shapes.iter_mut().flatten();
+        for (shape, rest) in shapes.zip(self.rest_shapes.iter_mut().flatten()) {
+            // Shapes still where they were added share the structures of
+            // their rest pose.
+            let moved = shape.content_digest() != rest.content_digest();
+            *rest = rest.clone().with_accelerator(kind);
+            *shape = if moved {
+                shape.clone().with_accelerator(kind)
+            } else {
+                rest.clone()
+            };
+        }
+        self.invalidate_bvh();
+    }
+
+    pub fn accelerator_kind(&self) -> AcceleratorKind {
+        self.accelerator_kind
+    }
+
+    /// Sets the precision the hierarchies of the spheres and of meshes are
+    /// stored and traversed in, converting the meshes already added. Mixed
+    /// precision rounds the corners of meshes to single precision for good,
+    /// so set it before adding them.
+    pub fn set_precision(&mut self, precision: Precision) {
+        self.precision = precision;
+        let shapes = self.shapes.iter_mut().flatten();
+        for (shape, rest) in shapes.zip(self.rest_shapes.iter_mut().flatten()) {
+            let moved = shape.content_digest() != rest.content_digest();
+            *rest = rest.clone().with_precision(precision);
+            *shape = if moved {
+                shape.clone().with_precision(precision)
+            } else {
+                rest.clone()
+            };
+        }
+        self.invalidate_bvh();
+    }
+
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    /// Rebuilds the BVH, or the structure replacing it, on the next
+    /// intersection test and drops the light cache, after `spheres` changed.
+    pub fn invalidate_bvh(&mut self) {
+        self.accelerator = OnceLock::new();
+        self.unbounded = OnceLock::new();
+        self.light_cache = None;
+    }
+
+    /// Only samples the lights `cache` deems visible from each point, see
+    /// `LightCache`. Dropped when the geometry changes.
+    pub fn set_light_cache(&mut self, cache: Option<LightCache>) {
+        self.light_cache = cache;
+    }
+
+    /// Shades camera hits with `preview` alone instead of tracing paths, see
+    /// `PreviewEnvironment`. Rebuild it after changing the environment.
+    pub fn set_preview_environment(&mut self, preview: Option<PreviewEnvironment>) {
+        self.preview_environment = preview;
+    }
+
+    pub fn preview_environment(&self) -> Option<&PreviewEnvironment> {
+        self.preview_environment.as_ref()
+    }
+
+    /// Lights sampled from `origin`.
+    fn lights_from(&self, origin: Vec3) -> &[ObjectId] {
+        self.light_cache
+            .as_ref()
+            .and_then(|cache| cache.lights(origin))
+            .unwrap_or(&self.lights)
+    }
+
+    /// Structure the objects are found through, built on first use. Other
+    /// shapes than spheres are in it by their bounding spheres, apart from
+    /// the unbounded ones, which are tested on their own. With stats on,
+    /// spheres are tested like shapes, so that each test is timed.
+    pub fn accelerator(&self) -> &dyn Accelerator {
+        self.accelerator
+            .get_or_init(|| {
+                let (mut indices, mut shapes) = (Vec::new(), Vec::new());
+                for (index, shape) in self.shapes.iter().enumerate() {
+                    match shape {
+                        None if self.stats.is_none() => indices.push(index),
+                        Some(_) if !self.spheres[index].radius.is_finite() => {}
+                        _ => shapes.push(index),
+                    }
+                }
+                self.accelerator_kind.build(
+                    &self.spheres,
+                    indices,
+                    shapes,
+                    self.bvh_quality,
+                    self.precision,
+                )
+            })
+            .as_ref()
+    }
+
+    /// Hit of `ray` on the object `index` alone, counted in the stats.
+    fn hit_surface(
+        &self,
+        index: usize,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+    ) -> Option<HitRecord> {
+        let surface = self.surface(ObjectId(index));
+        match &self.stats {
+            Some(stats) => {
+                let start = Instant::now();
+                let hit = surface.hit(ray, t_min, t_max);
+                stats.record_test(index, start.elapsed().as_nanos() as u64);
+                hit
+            }
+            None => surface.hit(ray, t_min, t_max),
+        }
+    }
+
+    /// Closest hit of `ray` on the unbounded shapes, which the accelerator
+    /// leaves out.
+    fn hit_unbounded(
+        &self,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+    ) -> Option<(ObjectId, HitRecord)> {
+        let mut closest: Option<(ObjectId, HitRecord)> = None;
+        let mut closest_t = t_max;
+        let unbounded = self.unbounded.get_or_init(|| {
+            (0..self.shapes.len())
+                .filter(|&index| {
+                    self.shapes[index].is_some() && !self.spheres[index].radius.is_finite()
+                })
+                .collect()
+        });
+        for &index in unbounded {
+            if let Some(record) = self.hit_surface(index, ray, t_min, closest_t) {
+                closest_t = record.t;
+                closest = Some((ObjectId(index), record));
+            }
+        }
+        closest
+    }
+
+    /// Closest hit of `ray` through the accelerator, the objects it doesn't
+    /// test itself being hit through `object_hit`, which keeps the last of
+    /// their hits.
+    fn hit_accelerated(
+        &self,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+    ) -> Option<(ObjectId, HitRecord)> {
+        let mut object_hit = None;
+        let mut test = |index: usize, closest_t| {
+            let record = self.hit_surface(index, ray, t_min, closest_t)?;
+            object_hit = Some((index, record));
+            Some(record.t)
+        };
+        let (index, _) = self.accelerator().hit(ray, t_min, t_max, &mut test)?;
+        self.closest_record(index, object_hit, ray, t_min, t_max)
+    }
+
+    /// Hit record of the closest hit found by the accelerator, on the object
+    /// `index`: the last one the callback kept when it was on that object,
+    /// the sphere's own otherwise. The distances matching `Sphere::hit`, the
+    /// sphere finds the same hit.
+    fn closest_record(
+        &self,
+        index: usize,
+        object_hit: Option<(usize, HitRecord)>,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+    ) -> Option<(ObjectId, HitRecord)> {
+        let record = match object_hit {
+            Some((object, record)) if object == index => record,
+            _ => self.spheres[index].hit(ray, t_min, t_max)?,
+        };
+        Some((ObjectId(index), record))
+    }
+
+    /// Self-intersection offset for rays leaving a surface, in scene units.
+    pub fn epsilon(&self) -> Float {
+        self.epsilon.unwrap_or_else(|| self.default_epsilon())
+    }
+
+    /// Offset used unless `set_epsilon` replaced it, the same number of
+    /// meters whatever the scene.
+    pub fn default_epsilon(&self) -> Float {
+        self.units.from_meters(EPSILON_METERS)
+    }
+
+    /// Replaces the self-intersection offset, in scene units, `None` going
+    /// back to the default one. See `SceneDefaults` for one following the
+    /// size of the scene.
+    pub fn set_epsilon(&mut self, epsilon: Option<Float>) {
+        self.epsilon = epsilon;
+    }
+
+    /// Closest intersection of `ray` with any object of the scene.
+    pub fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        self.hit_object(ray, t_min, t_max).map(|(_, record)| record)
+    }
+
+    /// Like `hit`, also returning the sphere that was hit.
+    pub fn hit_object(
+        &self,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+    ) -> Option<(ObjectId, HitRecord)> {
+        let hit = self.hit_accelerated(ray, t_min, t_max);
+        let t_max = hit.map_or(t_max, |(_, record)| record.t);
+        let hit = self.hit_unbounded(ray, t_min, t_max).or(hit);
+        self.record_hit(hit);
+        hit
+    }
+
+    /// Counts the hit of the closest object in the stats, when on.
+    fn record_hit(&self, hit: Option<(ObjectId, HitRecord)>) {
+        if let (Some(stats), Some((sphere, _))) = (&self.stats, hit) {
+            stats.record_hit(sphere.0);
+        }
+    }
+
+    /// Like `hit_object` for every ray of `rays`, each with its own
+    /// `t_max`. The rays are traced in packets, so this is faster when they
+    /// go the same way.
+    pub fn hit_objects(
+        &self,
+        rays: &[Ray],
+        t_min: Float,
+        t_max: &[Float],
+    ) -> Vec<Option<(ObjectId, HitRecord)>> {
+        let mut hits = Vec::with_capacity(rays.len());
+        for (rays, t_max) in rays
+            .chunks(RAY_PACKET_WIDTH)
+            .zip(t_max.chunks(RAY_PACKET_WIDTH))
+        {
+            let mut object_hits = [None; RAY_PACKET_WIDTH];
+            let mut test = |lane: usize, index: usize, closest_t| {
+                let record = self.hit_surface(index, &rays[lane], t_min, closest_t)?;
+                object_hits[lane] = Some((index, record));
+                Some(record.t)
+            };
+            let closest = self.accelerator().hit_packet(rays, t_min, t_max, &mut test);
+            for (lane, ray) in rays.iter().enumerate() {
+                let hit = closest[lane].and_then(|(index, _)| {
+                    self.closest_record(index, object_hits[lane], ray, t_min, t_max[lane])
+                });
+                let t_max = hit.map_or(t_max[lane], |(_, record)| record.t);
+                let hit = self.hit_unbounded(ray, t_min, t_max).or(hit);
+                self.record_hit(hit);
+                hits.push(hit);
+            }
+        }
+        hits
+    }
+
+    /// Closest intersection of `ray` with the spheres for which `keep` is
+    /// true. Not counted in the stats.
+    pub fn hit_filtered(
+        &self,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+        keep: impl Fn(ObjectId) -> bool,
+    ) -> Option<(ObjectId, HitRecord)> {
+        let mut closest: Option<(ObjectId, HitRecord)> = None;
+        let mut closest_t = t_max;
+
+        for index in 0..self.spheres.len() {
+            if !keep(ObjectId(index)) {
+                continue;
+            }
+            if let Some(record) = self.surface(ObjectId(index)).hit(ray, t_min, closest_t) {
+                closest_t = record.t;
+                closest = Some((ObjectId(index), record));
+            }
+        }
+
+        closest
+    }
+
+    /// Removes `spheres`, renumbering those left in order. Lights, names and
+    /// the other tables of the spheres follow them.
+    pub fn remove_spheres(&mut self, spheres: &[ObjectId]) {
+        let removed: HashSet<usize> = spheres.iter().map(|sphere| sphere.0).collect();
+        let mut new_ids = Vec::with_capacity(self.spheres.len());
+        let mut next = 0;
+        for index in 0..self.spheres.len() {
+            if removed.contains(&index) {
+                new_ids.push(None);
+            } else {
+                new_ids.push(Some(ObjectId(next)));
+                next += 1;
+            }
+        }
+
+        retain_unremoved(&mut self.spheres, &removed);
+        retain_unremoved(&mut self.rest_spheres, &removed);
+        retain_unremoved(&mut self.transforms, &removed);
+        retain_unremoved(&mut self.previous_transforms, &removed);
+        retain_unremoved(&mut self.names, &removed);
+        retain_unremoved(&mut self.shadow_catchers, &removed);
+        retain_unremoved(&mut self.uv_projections, &removed);
+        retain_unremoved(&mut self.shapes, &removed);
+        retain_unremoved(&mut self.rest_shapes, &removed);
+        self.lights = self
+            .lights
+            .iter()
+            .filter_map(|light| new_ids[light.0])
+            .collect();
+        self.animation.remap(&new_ids);
+        if self.stats.is_some() {
+            self.enable_stats();
+        }
+        self.invalidate_bvh();
+    }
+
+    /// Cuts the cost of building and traversing the BVH. Instances of a
+    /// single level are flattened into meshes placed once and for all, rays
+    /// no longer being moved into their space. Static meshes sharing a
+    /// material and options are merged into the first of them, the BVH then
+    /// holding one object for them all, which the hierarchy of the merged
+    /// mesh splits up again. Last, the spheres that can't change the image
+    /// are removed: spheres of zero radius, clear spheres of the refractive
+    /// index of air touching no other medium, and copies of earlier spheres.
+    ///
+    /// Animated objects are left alone. Named objects are kept, for sweeps
+    /// and reports to find, as are meshes laid out with a uv projection,
+    /// which follows their own bounds. Materials should be final, as a
+    /// sweep of the refractive index could make a removed sphere visible
+    /// again.
+    pub fn optimize(&mut self) -> OptimizationReport {
+        let mut report = OptimizationReport::default();
+        let animated: HashSet<ObjectId> =
+            self.animation.objects.iter().map(|(id, ..)| *id).collect();
+
+        let flattened: Vec<(ObjectId, Mesh)> = self
+            .rest_shapes
+            .iter()
+            .enumerate()
+            .filter_map(|(index, shape)| match shape {
+                Some(Shape::Instance(instance))
+                    if !animated.contains(&ObjectId(index))
+                        && instance.levels().level_count() == 1 =>
+                {
+                    let mesh = instance.levels().level(0);
+                    Some((ObjectId(index), mesh.transformed(instance.transform())))
+                }
+                _ => None,
+            })
+            .collect();
+        report.flattened = flattened.len();
+        for (id, mesh) in flattened {
+            self.replace_shape(id, Shape::Mesh(mesh));
+        }
+
+        // Groups of meshes that can be merged, in the order they were added.
+        let mut groups: Vec<(_, Vec<(ObjectId, &Mesh)>)> = Vec::new();
+        for (index, shape) in self.rest_shapes.iter().enumerate() {
+            let mesh = match shape {
+                Some(Shape::Mesh(mesh)) => mesh,
+                _ => continue,
+            };
+            let id = ObjectId(index);
+            if animated.contains(&id)
+                || self.names[index].is_some()
+                || self.uv_projections[index].is_some()
+                || self.transforms[index] != Mat4::identity()
+            {
+                continue;
+            }
+            let sphere = &self.rest_spheres[index];
+            let key = (
+                sphere.material,
+                sphere.cull_backfaces,
+                self.shadow_catchers[index],
+                mesh.is_smooth(),
+            );
+            match groups.iter_mut().find(|(other, _)| *other == key) {
+                Some((_, meshes)) => meshes.push((id, mesh)),
+                None => groups.push((key, vec![(id, mesh)])),
+            }
+        }
+        let merged: Vec<(ObjectId, Vec<ObjectId>, Mesh)> = groups
+            .into_iter()
+            .filter(|(_, meshes)| meshes.len() > 1)
+            .map(|((material, ..), meshes)| {
+                let ids: Vec<ObjectId> = meshes.iter().map(|(id, _)| *id).collect();
+                let meshes: Vec<&Mesh> = meshes.iter().map(|(_, mesh)| *mesh).collect();
+                (ids[0], ids, Mesh::merged(&meshes, material))
+            })
+            .collect();
+        let mut removed = Vec::new();
+        for (id, ids, mesh) in merged {
+            self.replace_shape(id, Shape::Mesh(mesh));
+            report.merged += ids.len() - 1;
+            removed.extend_from_slice(&ids[1..]);
+        }
+
+        let is_medium = |sphere: &Sphere| {
+            let material = self.material(sphere.material);
+            material.refractive_index().is_some() || material.medium().is_some()
+        };
+        let media: Vec<(usize, &Sphere)> = self
+            .spheres
+            .iter()
+            .enumerate()
+            .filter(|(_, sphere)| is_medium(sphere))
+            .collect();
+
+        let mut seen = HashSet::new();
+        for (index, sphere) in self.spheres.iter().enumerate() {
+            let id = ObjectId(index);
+            if self.names[index].is_some() || self.shape(id).is_some() {
+                continue;
+            }
+
+            if sphere.radius == 0.0 {
+                report.degenerate += 1;
+                removed.push(id);
+                continue;
+            }
+
+            let material = sphere.material;
+            let clear = self.material(material).refractive_index() == Some(1.0)
+                && !matches!(self.material(material), MaterialType::Principled(_))
+                && self.absorption(material).length_squared() == 0.0
+                && self.thin_film(material).is_none();
+            let alone = media.iter().all(|&(other_index, other)| {
+                let reach = sphere.radius.abs() + other.radius.abs();
+                other_index == index || (sphere.position - other.position).length() >= reach
+            });
+            if clear && alone {
+                report.invisible += 1;
+                removed.push(id);
+                continue;
+            }
+
+            let key = (
+                (sphere.position.x.to_bits(), sphere.position.y.to_bits()),
+                (sphere.position.z.to_bits(), sphere.radius.to_bits()),
+                (material.0, sphere.cull_backfaces),
+                (
+                    self.shadow_catchers[index],
+                    self.uv_projections[index].map(|projection| projection as u8),
+                ),
+                self.lights.contains(&id),
+            );
+            if !seen.insert(key) {
+                report.duplicates += 1;
+                removed.push(id);
+            }
+        }
+
+        self.remove_spheres(&removed);
+        report
+    }
+
+    /// Rescales every object so the scene is expressed in `units`.
+    ///
+    /// Returns the scale factor that was applied, so lengths living outside of
+    /// the scene (camera position, focus distance...) can be converted as well.
+    pub fn convert_to(&mut self, units: Units) -> Float {
+        let factor = self.units.meters_per_unit() / units.meters_per_unit();
+
+        for sphere in self.spheres.iter_mut().chain(self.rest_spheres.iter_mut()) {
+            sphere.scale(factor);
+        }
+        let shapes = self.shapes.iter_mut().chain(self.rest_shapes.iter_mut());
+        for shape in shapes.flatten() {
+            shape.scale(factor);
+        }
+        // Transforms move converted points by converted distances.
+        let scaling = Mat4::scaling(Vec3::new(factor, factor, factor));
+        let unscaling = Mat4::scaling(Vec3::new(1.0, 1.0, 1.0) / factor);
+        let previous = self.previous_transforms.iter_mut().flatten();
+        for transform in self.transforms.iter_mut().chain(previous) {
+            *transform = scaling * *transform * unscaling;
+        }
+        for light in &mut self.delta_lights {
+            light.scale(factor);
+        }
+        for bump_map in self.bump_maps.iter_mut().flatten() {
+            bump_map.height *= factor;
+        }
+        for absorption in &mut self.absorptions {
+            *absorption = *absorption / factor;
+        }
+        for material in &mut self.materials {
+            if let MaterialType::Subsurface { radius, .. } = material {
+                *radius = *radius * factor;
+            }
+        }
+        if let Some(camera) = &mut self.camera {
+            camera.look_from = camera.look_from * factor;
+            camera.look_at = camera.look_at * factor;
+        }
+        self.animation.scale(factor);
+        if let Some(clouds) = &mut self.clouds {
+            clouds.scale(factor);
+        }
+        if let Environment::Map(map) = &mut self.environment {
+            if let Some(ground) = map.ground_projection_mut() {
+                ground.scale(factor);
+            }
+        }
+        if let Some(epsilon) = &mut self.epsilon {
+            *epsilon *= factor;
+        }
+        self.units = units;
+        self.invalidate_bvh();
+
+        factor
+    }
+}
+
+/// Drops the items of `items` whose index is in `removed`.
+fn retain_unremoved<T>(items: &mut Vec<T>, removed: &HashSet<usize>) {
+    let mut index = 0;
+    items.retain(|_| {
+        index += 1;
+        !removed.contains(&(index - 1))
+    });
+}
+
+/// What `Scene::optimize` flattened and merged, and the spheres it
+/// removed, by reason.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OptimizationReport {
+    /// Instances turned into meshes.
+    pub flattened: usize,
+    /// Meshes merged into another, and removed.
+    pub merged: usize,
+    /// Spheres of zero radius, which rays can't hit.
+    pub degenerate: usize,
+    /// Clear spheres of the refractive index of air, which neither bend
+    /// nor absorb light.
+    pub invisible: usize,
+    /// Spheres identical to an earlier one.
+    pub duplicates: usize,
+}
+
+impl OptimizationReport {
+    /// Spheres removed, leaving out merged meshes.
+    pub fn removed(&self) -> usize {
+        self.degenerate + self.invisible + self.duplicates
+    }
+}
+
+impl std::fmt::Display for OptimizationReport {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            formatter,
+            "flattened {} instances, merged {} meshes into others, removed {} spheres: \
+             {} of zero radius, {} invisible, {} duplicates",
+            self.flattened,
+            self.merged,
+            self.removed(),
+            self.degenerate,
+            self.invisible,
+            self.duplicates
+        )
+    }
+}
+
+/// Small balls scattered around three big ones, laid out and colored by
+/// `seed`, which the path sampling random sequence doesn't touch.
+pub fn make_random_scene(seed: u64) -> Scene {
+    // Separate streams, so a change to the materials doesn't move the balls.
+    let mut layout = RandomStream::new(seed, "layout");
+    let mut colors = RandomStream::new(seed, "colors");
+    let mut scene = Scene::new(Units::Meters);
+
+    let ground = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    scene.set_material_name(ground, "ground");
+    let glass = scene.add_material(MaterialType::Dialectric {
+        refractive_index: 1.5,
+    });
+    scene.set_material_name(glass, "glass");
+
+    let id = scene.add(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground));
+    scene.set_name(id, "ground");
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = colors.next_01();
+
+            let center = Vec3::new(
+                a as Float + 0.9 * layout.next_01(),
+                0.2,
+                b as Float + 0.9 * layout.next_01(),
+            );
+
+            if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                if choose_mat < 0.8 {
+                    // diffuse
+                    let albedo = Vec3::new(colors.next_01(), colors.next_01(), colors.next_01());
+                    let material = scene.add_material(MaterialType::Lambertian { albedo });
+                    scene.add(Sphere::new(center, 0.2, material));
+                } else if choose_mat < 0.95 {
+                    let albedo = Vec3::new(colors.between(0.5, 1.0), colors.between(0.5, 1.0), 1.0);
+                    let fuzziness = colors.between(0.0, 0.5);
+                    let material = scene.add_material(MaterialType::Metal { albedo, fuzziness });
+                    scene.add(Sphere::new(center, 0.2, material));
+                } else {
+                    scene.add(Sphere::new(center, 0.2, glass));
+                }
+            }
+        }
+    }
+
+    let id = scene.add(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, glass));
+    scene.set_name(id, "glass ball");
+
+    let brown = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.4, 0.2, 0.1),
+    });
+    scene.set_material_name(brown, "brown");
+    let id = scene.add(Sphere::new(Vec3::new(-4.0, 1.0, 0.0), 1.0, brown));
+    scene.set_name(id, "diffuse ball");
+
+    let mirror = scene.add_material(MaterialType::Metal {
+        albedo: Vec3::new(0.7, 0.6, 0.5),
+        fuzziness: 0.0,
+    });
+    scene.set_material_name(mirror, "mirror");
+    let id = scene.add(Sphere::new(Vec3::new(4.0, 1.0, 0.0), 1.0, mirror));
+    scene.set_name(id, "metal ball");
+
+    scene
+}
+
+/// Balls floating on a sea raised by a fresh breeze, under a low sun, with
+/// waves picked by `seed`.
+pub fn make_ocean_scene(seed: u64) -> Scene {
+    let mut scene = Scene::new(Units::Meters);
+    scene.environment = Environment::Sky(PhysicalSky::new(20.0, 200.0, 3.0));
+
+    let water = scene.add_water();
+    scene.set_material_name(water, "water");
+    let waves = RandomStream::new(seed, "waves").next_u64();
+    scene.set_ocean(water, Some(Ocean::new(8.0, 190.0, waves)));
+    // A sphere large enough to look flat up to the horizon.
+    let id = scene.add(Sphere::new(Vec3::new(0.0, -10_000.0, 0.0), 10_000.0, water));
+    scene.set_name(id, "sea");
+
+    let glass = scene.add_material(MaterialType::Dialectric {
+        refractive_index: 1.5,
+    });
+    scene.set_material_name(glass, "glass");
+    let id = scene.add(Sphere::new(Vec3::new(0.0, 0.6, 0.0), 1.0, glass));
+    scene.set_name(id, "glass ball");
+
+    let buoy = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.8, 0.15, 0.05),
+    });
+    scene.set_material_name(buoy, "buoy");
+    let id = scene.add(Sphere::new(Vec3::new(-4.0, 0.5, 0.5), 1.0, buoy));
+    scene.set_name(id, "buoy");
+
+    let mirror = scene.add_material(MaterialType::Metal {
+        albedo: Vec3::new(0.7, 0.6, 0.5),
+        fuzziness: 0.0,
+    });
+    scene.set_material_name(mirror, "mirror");
+    let id = scene.add(Sphere::new(Vec3::new(4.0, 0.7, -0.5), 1.0, mirror));
+    scene.set_name(id, "metal ball");
+
+    scene
+}
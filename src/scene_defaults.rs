@@ -0,0 +1,207 @@
+use crate::bvh::Aabb;
+use crate::camera::Camera;
+use crate::environment::Environment;
+use crate::material::Material;
+use crate::maths::consts::PI;
+use crate::maths::*;
+use crate::ray::Ray;
+use crate::scene::Scene;
+
+/// Extent, in meters, of the scenes the default self-intersection offset
+/// was picked for.
+const REFERENCE_EXTENT_METERS: Float = 20.0;
+
+/// Exposure changes smaller than this many stops are left out, so scenes
+/// that already look right keep their brightness.
+const EXPOSURE_TOLERANCE_STOPS: Float = 1.0;
+
+/// Columns and rows of the grid of camera rays finding the surfaces the
+/// exposure is measured on.
+const EXPOSURE_RAYS: (usize, usize) = (16, 9);
+
+/// Cosine weighted directions per side of the grid averaging the light of
+/// the environment.
+const ENVIRONMENT_SAMPLES_PER_SIDE: usize = 16;
+
+/// Settings derived from a scene, for scene files that don't tune them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SceneDefaults {
+    /// Diagonal of the bounds of the finite objects, but the largest one,
+    /// usually a ground or a backdrop, in scene units.
+    pub extent: Float,
+    /// Self-intersection offset following the extent, in scene units, see
+    /// `Scene::set_epsilon`.
+    pub epsilon: Float,
+    /// Distance from the camera to the surface in the middle of the view,
+    /// `None` when it sees the sky there.
+    pub focus_distance: Option<Float>,
+    /// Stops of exposure bringing white surfaces seen by the camera to
+    /// about 1, going by the median of the light the emitters, delta lights
+    /// and environment shed on them. `None` when they are already within a
+    /// stop of it, or the camera sees no lit surface.
+    pub exposure_stops: Option<Float>,
+}
+
+impl SceneDefaults {
+    /// Defaults for `scene` seen by `camera`.
+    pub fn derive(scene: &Scene, camera: &Camera) -> Self {
+        let extent = extent(scene);
+        let epsilon = if extent > 0.0 {
+            scene.default_epsilon() * extent / scene.units.from_meters(REFERENCE_EXTENT_METERS)
+        } else {
+            scene.default_epsilon()
+        };
+
+        let ray = camera.get_ray(0.5, 0.5, (0.5, 0.5));
+        let focus_distance = scene
+            .hit(&ray, epsilon, Float::INFINITY)
+            .map(|hit| hit.t * ray.dir.length());
+
+        let (columns, rows) = EXPOSURE_RAYS;
+        let mut radiances = Vec::with_capacity(columns * rows);
+        for j in 0..rows {
+            for i in 0..columns {
+                let u = (i as Float + 0.5) / columns as Float;
+                let v = (j as Float + 0.5) / rows as Float;
+                let ray = camera.get_ray(u, v, (0.5, 0.5));
+                if let Some(hit) = scene.hit(&ray, epsilon, Float::INFINITY) {
+                    let point = hit.position + hit.normal * epsilon;
+                    let irradiance = irradiance(scene, point, hit.normal, epsilon);
+                    // White lambertian surfaces reflect a π-th of it.
+                    radiances.push(luminance(irradiance) / PI);
+                }
+            }
+        }
+        radiances.sort_by(|a, b| a.total_cmp(b));
+        let exposure_stops = radiances
+            .get(radiances.len() / 2)
+            .filter(|&&median| median > 0.0)
+            .map(|median| -median.log2())
+            .filter(|stops| stops.abs() >= EXPOSURE_TOLERANCE_STOPS);
+
+        SceneDefaults {
+            extent,
+            epsilon,
+            focus_distance,
+            exposure_stops,
+        }
+    }
+}
+
+impl std::fmt::Display for SceneDefaults {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            formatter,
+            "extent {:.3}, epsilon {:.3e}",
+            self.extent, self.epsilon
+        )?;
+        match self.focus_distance {
+            Some(distance) => write!(formatter, ", focus distance {:.3}", distance)?,
+            None => write!(formatter, ", nothing to focus on")?,
+        }
+        match self.exposure_stops {
+            Some(stops) => write!(formatter, ", exposure {:+.1} stops", stops),
+            None => write!(formatter, ", exposure unchanged"),
+        }
+    }
+}
+
+/// Diagonal of the bounds of the finite objects of `scene` but the largest.
+fn extent(scene: &Scene) -> Float {
+    let finite: Vec<_> = scene
+        .spheres
+        .iter()
+        .filter(|sphere| sphere.radius.is_finite())
+        .collect();
+    let largest = finite
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.radius.abs().total_cmp(&b.radius.abs()))
+        .map(|(index, _)| index);
+
+    let bounds = finite
+        .iter()
+        .enumerate()
+        .filter(|&(index, _)| finite.len() < 2 || Some(index) != largest)
+        .fold(Aabb::empty(), |bounds, (_, sphere)| {
+            bounds.merge(Aabb::of_sphere(sphere))
+        });
+    if finite.is_empty() {
+        0.0
+    } else {
+        (bounds.max - bounds.min).length()
+    }
+}
+
+/// Irradiance reaching `point` on a surface facing `normal`, only checking
+/// shadows for the emitters and delta lights.
+fn irradiance(scene: &Scene, point: Vec3, normal: Vec3, epsilon: Float) -> Vec3 {
+    let mut irradiance = environment_irradiance(&scene.environment, normal);
+
+    // Emitters are taken as spheres seen head on, which their bounding
+    // spheres are for disks.
+    for &light in &scene.lights {
+        let bounds = scene.sphere(light);
+        let ray = Ray::new(point, bounds.position - point);
+        let cosine = ray.dir.unit().dot(normal);
+        if cosine <= 0.0 {
+            continue;
+        }
+        let hit = match scene.hit_object(&ray, epsilon, Float::INFINITY) {
+            Some((id, hit)) if id == light => hit,
+            _ => continue,
+        };
+        let emitted = scene.material(hit.material).emitted(&hit);
+        let distance_squared = (bounds.position - point).length_squared();
+        let sin_squared = (bounds.radius * bounds.radius / distance_squared).min(1.0);
+        let solid_angle = 2.0 * PI * (1.0 - (1.0 - sin_squared).sqrt());
+        irradiance += emitted * (solid_angle * cosine);
+    }
+
+    for light in &scene.delta_lights {
+        if let Some(sample) = light.illuminate(point) {
+            let cosine = sample.direction.dot(normal);
+            let ray = Ray::new(point, sample.direction);
+            let t_max = sample.distance - epsilon;
+            if cosine > 0.0 && scene.hit(&ray, epsilon, t_max).is_none() {
+                irradiance += sample.irradiance * cosine;
+            }
+        }
+    }
+
+    irradiance
+}
+
+/// Irradiance the environment sheds on a surface facing `normal`, ignoring
+/// what blocks it.
+fn environment_irradiance(environment: &Environment, normal: Vec3) -> Vec3 {
+    let uvw = Onb::from_w(normal);
+    let side = ENVIRONMENT_SAMPLES_PER_SIDE;
+    let mut sum = Vec3::new(0.0, 0.0, 0.0);
+    for i in 0..side {
+        for j in 0..side {
+            let u = (
+                (i as Float + 0.5) / side as Float,
+                (j as Float + 0.5) / side as Float,
+            );
+            let direction = uvw.local(sample_cosine_direction(u));
+            // The sun is too small for the grid, it is added on its own.
+            sum += match environment.sun() {
+                Some(sky) => sky.sky_radiance(direction),
+                None => environment.radiance(direction),
+            };
+        }
+    }
+    let mut irradiance = sum * (PI / (side * side) as Float);
+
+    if let Some(sky) = environment.sun() {
+        let sun = sky.sun_direction();
+        let cosine = sun.dot(normal);
+        if cosine > 0.0 {
+            let radiance = sky.radiance(sun) - sky.sky_radiance(sun);
+            irradiance += radiance * (cosine / sky.sun_pdf(sun));
+        }
+    }
+
+    irradiance
+}
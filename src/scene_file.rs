@@ -0,0 +1,1208 @@
+//! Line based scene description.
+//!
+//! ```text
+//! # Comments start with a hash.
+//! units centimeters
+//! bvh sah
+//! environment gradient 1 1 1 0.5 0.7 1
+//! plane 0 0 0 0 1 0 lambertian 0.5 0.5 0.5 name ground shadow-catcher
+//! sphere 0 1 0 1 dielectric 1.5
+//! sphere 4 1 0 1 metal 0.7 0.6 0.5 0
+//! sphere 0 5 0 0.5 light 4 4 4
+//! disk 3 4 -2 0 -1 0 0.5 light 8 8 8
+//! cylinder 2 0 3 0 1 0 1.5 0.4 lambertian 0.2 0.3 0.8
+//! capsule -2 0.3 3 -1 0.3 4 0.3 metal 0.8 0.8 0.8 0.1
+//! menger 6 1 -3 1 3 lambertian 0.8 0.8 0.8
+//! blend -6 1 -3 0.8 -5 1.5 -3 0.5 0.6 lambertian 0.9 0.4 0.1
+//! heightfield -20 0 -40 40 20 3 noise 7 lambertian 0.4 0.5 0.3
+//! curve -1 0 -6 -0.5 1 -6 0.5 1 -6 1 2 -6 0.05 0.01 hair 0.3 0.2 0.1 0.3 0.3
+//! curves 0 1.5 -8 0.01 straight.hair hair 0.9 0.7 0.4 0.25 0.3
+//! mesh 8 0 -8 0.02 bunny.ply principled 0.7 0.7 0.7 roughness 0.4
+//! sphere -4 1 0 1 rough-metal 0.95 0.64 0.54 0.3
+//! sphere -4 1 4 1 anisotropic-metal 0.91 0.92 0.92 0.1 0.5 tangent-rotation 90
+//! sphere 0 1 3 1 rough-dielectric 1.5 0.2
+//! sphere 3 1 -3 1 thin-glass bottle
+//! sphere 0 1 -3 1 principled 0.8 0.1 0.1 roughness 0.3 clearcoat 1
+//! sphere 3 0.5 3 0.5 subsurface 0.9 0.8 0.6 0.3 0.1 0.05
+//! sphere -3 0.5 -3 0.5 measured gold-metallic-paint.binary
+//! sphere 0 -10000 0 10000 water ocean 8 190
+//! ```
+//!
+//! `plane X Y Z NX NY NZ` and `disk X Y Z NX NY NZ RADIUS` take a point of
+//! the plane or the center of the disk, then the normal of their front face.
+//! `cylinder X Y Z AX AY AZ HEIGHT RADIUS` and `cone X Y Z AX AY AZ HEIGHT
+//! RADIUS` take the center of their base then their axis, and
+//! `capsule X1 Y1 Z1 X2 Y2 Z2 RADIUS` the ends of its segment (see
+//! `Cylinder`, `Cone` and `Capsule`). All are followed by a material and
+//! options like spheres. `mandelbulb X Y Z SIZE POWER` and
+//! `menger X Y Z SIZE ITERATIONS` center a fractal on the point, scaled by
+//! `SIZE`, the sponge filling a cube twice that across, and
+//! `blend X1 Y1 Z1 R1 X2 Y2 Z2 R2 K` merges two spheres like drops of
+//! liquid over a width of `K` (see `DistanceFunction`).
+//! `heightfield X Y Z WIDTH DEPTH HEIGHT FILE` lays terrain from the corner
+//! with the lowest x and z over `WIDTH` along x and `DEPTH` along z, white in
+//! the grayscale `.ppm`, `.pfm` or `.hdr` image rising `HEIGHT` above the
+//! corner, the top of the image towards -z, and `noise SEED` in place of the
+//! file raises hills of fractal noise instead (see `Heightfield`).
+//! `curve X1 Y1 Z1 X2 Y2 Z2 X3 Y3 Z3 X4 Y4 Z4 R1 R2` is a Bézier curve
+//! from its first control point to its last, its radius going from `R1` to
+//! `R2`, and `curves X Y Z SCALE FILE` loads the strands of a `.hair` file
+//! relative to the scene file, scaled by `SCALE` then moved by the point
+//! (see `Curve` and `parse_hair`). `mesh X Y Z SCALE FILE` loads the
+//! triangles of a `.ply`, `.stl` or `.obj` file the same way, flat shaded or,
+//! followed by `smooth`, with normals averaged around their corners (see
+//! `Mesh`, `Mesh::smoothed` and `load_mesh`), or in the background when the
+//! scene is loaded by `load_scene_streamed`. Then `displace LEVELS HEIGHT
+//! TEXTURE` splits its triangles in four `LEVELS` times, up to 8, and pushes
+//! their corners out by `HEIGHT` times the luminance of the texture, an
+//! image relative to the scene file or one named by `texture`, laid over the
+//! mesh following its `uv-projection` (see `Mesh::displaced`). Disks with a
+//! `light` material are sampled for direct lighting, but the other shapes
+//! aren't.
+//!
+//! `rough-metal` takes the color reflected head on then the roughness, and
+//! `rough-dielectric` the refractive index then the roughness, both from 0,
+//! polished, to 1 (see `MaterialType::Microfacet`). `anisotropic-metal` takes
+//! the color reflected head on then the roughnesses along and across the
+//! tangent of the sphere, along increasing u of its `uv-projection`, and
+//! `tangent-rotation DEGREES` turns the tangent about the normal (see
+//! `MaterialType::AnisotropicMetal`). `principled` takes the base color, its
+//! other parameters following the sphere as `metallic`, `roughness`,
+//! `specular`, `sheen`, `clearcoat` and `transmission`, each with a value
+//! from 0 to 1 and Blender's default when left out (see `Principled`).
+//! `subsurface` takes the color of a translucent material then how far light
+//! goes inside it, in scene units, for red, green and blue (see
+//! `MaterialType::Subsurface`, whose refractive index is 1.4). `measured`
+//! takes a BRDF of the MERL database, a `.binary` file relative to the scene
+//! file (see `MeasuredBrdf`). `hair` takes the color of a lock of hair then
+//! its roughnesses along and around the fibers, from 0 to 1, and is meant
+//! for curves (see `Hair`). `thin-glass IOR R G B` is glass as thin as
+//! window panes, bubbles or the walls of bottles, letting light through
+//! unbent and tinted by the color it takes crossing the glass head on, or
+//! `thin-glass` followed by `window`, `clear`, `bottle`, `amber` or
+//! `bubble` picks common glass (see `MaterialType::ThinGlass`).
+//! `water` is a dielectric absorbing light like
+//! clear water (see `Scene::add_water`), and `ocean WIND DIRECTION` bends the
+//! normals of the material of a sphere by the waves a wind of `WIND` m/s
+//! blowing towards `DIRECTION`, in degrees from +x towards +z, raises on a
+//! sea level at y = 0 (see `Ocean`). `thin-film THICKNESS IOR` coats a metal
+//! or a dielectric with a film `THICKNESS` nanometers thick of index `IOR`,
+//! tinting its reflections like oil on water (see `ThinFilm`). Spheres can be
+//! given a name for reports, and those with a `light` material are sampled
+//! for direct lighting. Lights only emit from their outside unless followed
+//! by `two-sided`, and other materials shade both sides of their surface
+//! unless followed by `one-sided`. Spheres followed by `shadow-catcher` only
+//! show the shadows and reflections of the other objects, see
+//! `Scene::set_shadow_catcher`, and those followed by `cull-backfaces` can
+//! only be hit from the outside. `normal-map FILE` bends the shading normals
+//! of the material of a sphere following a tangent space normal map, a
+//! `.ppm`, `.pfm` or `.hdr` image relative to the scene file, and
+//! `normal-strength S` then scales its bumps (see `NormalMap`). Height maps
+//! are given with `bump-map FILE`, optionally followed by `bump-height H`,
+//! the height of white in scene units, one centimeter by default (see
+//! `BumpMap`). Both maps follow `uv-projection spherical`, the default,
+//! `cylindrical`, `planar` or `box` (see `UvProjection`). Meshes given one
+//! are projected from their bounding sphere, and otherwise follow the first
+//! edge of each triangle, while other shapes have their own. Dielectric
+//! spheres overlapping others, like ice in a drink, are given a
+//! `priority N`, the medium with the highest priority filling the overlap
+//! (see `Scene::set_priority`). Followed by `absorption R G B`, they tint
+//! the light going through them like colored glass, keeping
+//! `exp(-d * absorption)` of each channel over a distance `d` in scene units
+//! (see `Scene::set_absorption`). The environment is either `constant R G B`,
+//! `gradient BOTTOM TOP`, `sky ELEVATION AZIMUTH TURBIDITY`, a physical sky
+//! lit by a sun whose angles are in degrees (see `PhysicalSky`), or
+//! `map FILE`, an equirectangular `.hdr` or `.pfm` image whose path is
+//! relative to the scene file. Maps can be followed by `yaw DEGREES`,
+//! `pitch DEGREES`, `exposure STOPS` and `saturation S` adjustments, see
+//! `MapAdjustments`, and by `ground HEIGHT RADIUS`, projecting their lower
+//! half on the ground at y = 0 as photographed `HEIGHT` above it, under a
+//! dome of `RADIUS` around the origin (see `GroundProjection`):
+//!
+//! ```text
+//! environment map field.hdr yaw 90 exposure -1.5 saturation 0.8 ground 1.7 50
+//! ```
+//!
+//! `clouds BOTTOM TOP COVERAGE DENSITY` lays a layer of clouds between two
+//! heights, covering about `COVERAGE` of the sky, from 0 to 1, with a chance
+//! of `DENSITY` per scene unit of meeting a droplet in their thickest parts.
+//! They are drawn over the environment by default, or followed by `medium`
+//! they scatter the light going through them and shadow the scene. Options
+//! `size`, the width of the largest clouds, `seed`, `albedo`, `anisotropy`
+//! and `range`, how far rays are followed through the layer, follow (see
+//! `Clouds`):
+//!
+//! ```text
+//! environment sky 30 120 3
+//! clouds 800 1400 0.4 0.05 medium size 3000 seed 7
+//! ```
+//!
+//! Point, directional and spot lights, which have no surface, are given by
+//! their position, their direction or both, then their intensity, spot lights
+//! ending with the half angles of their full and fading cones, in degrees
+//! (see `DeltaLight`):
+//!
+//! ```text
+//! point-light 0 4 0 20 20 20
+//! directional-light -1 -2 -1 2 2 1.8
+//! spot-light 0 4 2 0 -1 -0.5 40 40 40 20 30
+//! ```
+//!
+//! `bvh` picks how the hierarchy rays find spheres through is built: `sah`,
+//! the default, makes faster trees, `median` builds them faster.
+//! `accelerator kd-tree` finds spheres and the triangles of meshes through
+//! kd-trees instead, `accelerator grid` through uniform grids, quick to
+//! build for scenes changing every frame, and `accelerator bvh` goes back to
+//! hierarchies (see `AcceleratorKind`). `precision mixed` stores the boxes
+//! of hierarchies and the corners of meshes in single precision, halving
+//! their memory for large scenes, and `precision full`, the default, keeps
+//! them in the precision of the build (see `Precision`).
+//!
+//! `material NAME` followed by a material and its options defines a material
+//! objects share by following their shape with `use NAME` instead, so that
+//! editing it changes all of them. Options of materials, like `priority` or
+//! `normal-map`, go on the line defining a shared material rather than on
+//! those of its objects. `texture NAME FILE` loads an image once, and its
+//! name can then be given in place of a file to `normal-map`, `bump-map` and
+//! `heightfield`:
+//!
+//! ```text
+//! texture bricks bricks-normal.ppm
+//! material walls principled 0.6 0.3 0.2 roughness 0.8 normal-map bricks
+//! plane 0 0 -5 0 0 1 use walls
+//! plane -5 0 0 1 0 0 use walls name side
+//! ```
+//!
+//! `lod NAME FILE` loads a mesh once, its finest level, followed by
+//! `DISTANCE FILE` pairs giving coarser levels and the distances from which
+//! they take over, in the units of the files. `instance X Y Z SCALE DEGREES
+//! NAME` places a copy of them, scaled, then turned around y and moved to
+//! the point, so many copies take the memory of one (see `Instance`):
+//!
+//! ```text
+//! lod tree tree-high.ply 20 tree-medium.ply 60 tree-low.ply
+//! instance 4 0 -2 1.2 30 tree lambertian 0.3 0.5 0.2
+//! instance -3 0 -6 0.9 145 tree lambertian 0.3 0.5 0.2
+//! ```
+//!
+//! `gltf FILE` adds the meshes, materials, lights and camera of a glTF 2.0
+//! file, a `.gltf` or a `.glb` relative to the scene file (see
+//! `parse_gltf`), and glTF files can be loaded as scenes on their own.
+//!
+//! `keyframe camera TIME FROM_X FROM_Y FROM_Z AT_X AT_Y AT_Z` places the
+//! camera at `TIME` seconds, optionally followed by `fov DEGREES` and
+//! `up X Y Z`, which otherwise keep the values of the last camera keyframe.
+//! `keyframe NAME TIME X Y Z` moves the object called `NAME` by the offset
+//! from where it was added, optionally turned by `rotate X Y Z`, in degrees
+//! around x, then y, then z, and scaled by `scale S`, both about its center.
+//! Values go on to the next keyframe along a `smooth` curve, the default,
+//! `linear`ly, or in a `step` at the next one (see `Animation`):
+//!
+//! ```text
+//! sphere 0 1 0 1 metal 0.7 0.6 0.5 0 name ball
+//! keyframe ball 0 0 0 0
+//! keyframe ball 2 0 3 0 rotate 0 180 0 linear
+//! keyframe camera 0 13 2 3 0 0 0 fov 20
+//! keyframe camera 4 3 2 13 0 1 0
+//! ```
+//!
+//! `include FILE` reads another scene file in its place, like objects or
+//! settings shared between scenes. Paths in the included file are relative
+//! to it, and files can't include themselves, even through others:
+//!
+//! ```text
+//! include ../shared/studio.txt
+//! ```
+
+use crate::accelerator::AcceleratorKind;
+use crate::animation::{Interpolation, Keyframe, Pose};
+use crate::bvh::{BvhQuality, Precision};
+use crate::clouds::{CloudMode, Clouds};
+use crate::curve::{load_hair, Curve, Curves};
+use crate::environment::*;
+use crate::gltf::load_gltf;
+use crate::hair::Hair;
+use crate::heightfield::{fractal_noise, Heightfield};
+use crate::instance::{Instance, LevelsOfDetail};
+use crate::light::DeltaLight;
+use crate::material::{Fresnel, MaterialId, MaterialType, ThinFilm};
+use crate::maths::*;
+use crate::measured::MeasuredBrdf;
+use crate::mesh::{load_mesh, Displacement, Mesh};
+use crate::ocean::Ocean;
+use crate::plane::{Disk, Plane};
+use crate::principled::Principled;
+use crate::quadric::{Capsule, Cone, Cylinder};
+use crate::scene::*;
+use crate::sdf::{DistanceFunction, Sdf};
+use crate::shape::Shape;
+use crate::sky::PhysicalSky;
+use crate::sphere::{Sphere, UvProjection};
+use crate::streaming::{MeshStream, StreamedMesh};
+use crate::texture::{BumpMap, NormalMap, Texture};
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Iterations of the mandelbulbs of scene files, enough for the details to
+/// be finer than the steps of sphere tracing.
+const MANDELBULB_ITERATIONS: u32 = 12;
+
+/// Samples along both sides of noise heightfields.
+const TERRAIN_SAMPLES: usize = 257;
+
+/// Hills across noise heightfields, at the coarsest level of the noise.
+const TERRAIN_FEATURES: Float = 4.0;
+
+/// Most times the triangles of displaced meshes are split in four, each
+/// level quadrupling their count.
+const MAX_DISPLACEMENT_LEVELS: usize = 8;
+
+/// Words of the line being parsed, reporting errors with its location.
+struct Line<'a> {
+    name: &'a str,
+    number: usize,
+    words: std::str::SplitWhitespace<'a>,
+}
+
+impl<'a> Line<'a> {
+    fn error(&self, message: &str) -> std::io::Error {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{}:{}: {}", self.name, self.number, message),
+        )
+    }
+
+    fn word(&mut self) -> std::io::Result<&'a str> {
+        match self.words.next() {
+            Some(word) => Ok(word),
+            None => Err(self.error("missing value")),
+        }
+    }
+
+    fn number(&mut self) -> std::io::Result<Float> {
+        let word = self.word()?;
+        match word.parse::<Float>() {
+            Ok(number) if number.is_finite() => Ok(number),
+            _ => Err(self.error(&format!("invalid number '{}'", word))),
+        }
+    }
+
+    fn vec3(&mut self) -> std::io::Result<Vec3> {
+        Ok(Vec3::new(self.number()?, self.number()?, self.number()?))
+    }
+
+    /// Material of a sphere, files being relative to `directory`.
+    fn material(&mut self, directory: &Path) -> std::io::Result<MaterialType> {
+        match self.word()? {
+            "lambertian" => Ok(MaterialType::Lambertian {
+                albedo: self.vec3()?,
+            }),
+            "metal" => Ok(MaterialType::Metal {
+                albedo: self.vec3()?,
+                fuzziness: self.number()?,
+            }),
+            "dielectric" => Ok(MaterialType::Dialectric {
+                refractive_index: self.number()?,
+            }),
+            "thin-glass" => {
+                let word = self.word()?;
+                if let Some(preset) = MaterialType::thin_glass_preset(word) {
+                    return Ok(preset);
+                }
+                match word.parse::<Float>() {
+                    Ok(refractive_index) if refractive_index >= 1.0 => {
+                        Ok(MaterialType::ThinGlass {
+                            refractive_index,
+                            tint: self.vec3()?,
+                        })
+                    }
+                    _ => Err(self.error(&format!("unknown thin glass '{}'", word))),
+                }
+            }
+            "light" => Ok(MaterialType::DiffuseLight { emit: self.vec3()? }),
+            "rough-metal" => {
+                let f0 = self.vec3()?;
+                Ok(MaterialType::Microfacet {
+                    roughness: self.number()?,
+                    fresnel: Fresnel::Conductor { f0 },
+                })
+            }
+            "anisotropic-metal" => Ok(MaterialType::AnisotropicMetal {
+                f0: self.vec3()?,
+                roughness_u: self.number()?,
+                roughness_v: self.number()?,
+                rotation: 0.0,
+            }),
+            "rough-dielectric" => {
+                let refractive_index = self.number()?;
+                Ok(MaterialType::Microfacet {
+                    roughness: self.number()?,
+                    fresnel: Fresnel::Dielectric { refractive_index },
+                })
+            }
+            "subsurface" => Ok(MaterialType::Subsurface {
+                albedo: self.vec3()?,
+                radius: self.vec3()?,
+                refractive_index: 1.4,
+            }),
+            "principled" => Ok(MaterialType::Principled(Principled {
+                base_color: self.vec3()?,
+                ..Principled::default()
+            })),
+            "measured" => {
+                let file = directory.join(self.word()?);
+                let file = file.to_string_lossy();
+                let brdf = MeasuredBrdf::load(&file)
+                    .map_err(|error| self.error(&format!("{}: {}", file, error)))?;
+                Ok(MaterialType::Measured(Arc::new(brdf)))
+            }
+            "hair" => Ok(MaterialType::Hair(Hair::new(
+                self.vec3()?,
+                self.number()?,
+                self.number()?,
+            ))),
+            other => Err(self.error(&format!("unknown material '{}'", other))),
+        }
+    }
+
+    /// Texture called by the next word, or else loaded from the file it
+    /// names relative to `directory`.
+    fn texture(
+        &mut self,
+        directory: &Path,
+        textures: &HashMap<String, Texture>,
+    ) -> std::io::Result<Texture> {
+        let word = self.word()?;
+        if let Some(texture) = textures.get(word) {
+            return Ok(texture.clone());
+        }
+        let file = directory.join(word);
+        let file = file.to_string_lossy();
+        Texture::load(&file).map_err(|error| self.error(&format!("{}: {}", file, error)))
+    }
+
+    /// Optional adjustments and ground projection ending an
+    /// `environment map` line.
+    fn map_options(&mut self) -> std::io::Result<(MapAdjustments, Option<GroundProjection>)> {
+        let mut adjustments = MapAdjustments::default();
+        let mut ground = None;
+        while let Some(word) = self.words.next() {
+            match word {
+                "ground" => {
+                    let (height, radius) = (self.number()?, self.number()?);
+                    if height <= 0.0 || radius <= height {
+                        let message = "the ground needs a height above 0 and a radius above it";
+                        return Err(self.error(message));
+                    }
+                    ground = Some(GroundProjection {
+                        center: Vec3::new(0.0, 0.0, 0.0),
+                        height,
+                        radius,
+                    });
+                }
+                "yaw" => adjustments.yaw_degrees = self.number()?,
+                "pitch" => adjustments.pitch_degrees = self.number()?,
+                "exposure" => adjustments.exposure = self.number()?,
+                "saturation" => {
+                    adjustments.saturation = self.number()?;
+                    if adjustments.saturation < 0.0 {
+                        return Err(self.error("saturation can't be negative"));
+                    }
+                }
+                other => return Err(self.error(&format!("unexpected '{}'", other))),
+            }
+        }
+        Ok((adjustments, ground))
+    }
+
+    /// Layer of clouds following `clouds`, with its options.
+    fn clouds(&mut self) -> std::io::Result<Clouds> {
+        let (bottom, top) = (self.number()?, self.number()?);
+        if top <= bottom {
+            return Err(self.error("clouds must have a top above their bottom"));
+        }
+        let coverage = self.number()?;
+        if !(0.0..=1.0).contains(&coverage) {
+            return Err(self.error("cloud coverage must be between 0 and 1"));
+        }
+        let density = self.number()?;
+        if density < 0.0 {
+            return Err(self.error("cloud density can't be negative"));
+        }
+        let mode = match self.words.clone().next() {
+            Some("medium") => CloudMode::Medium,
+            _ => CloudMode::Background,
+        };
+        if let Some("background" | "medium") = self.words.clone().next() {
+            self.words.next();
+        }
+        let mut clouds = Clouds::new(bottom, top, coverage, density, mode);
+        while let Some(word) = self.words.next() {
+            match word {
+                "size" => {
+                    clouds.feature_size = self.number()?;
+                    if clouds.feature_size <= 0.0 {
+                        return Err(self.error("cloud size must be positive"));
+                    }
+                }
+                "seed" => {
+                    let seed = self.number()?;
+                    if seed < 0.0 || seed.fract() != 0.0 {
+                        return Err(self.error("seeds must be natural numbers"));
+                    }
+                    clouds.seed = seed as u64;
+                }
+                "albedo" => {
+                    clouds.albedo = self.number()?;
+                    if !(0.0..=1.0).contains(&clouds.albedo) {
+                        return Err(self.error("cloud albedo must be between 0 and 1"));
+                    }
+                }
+                "anisotropy" => {
+                    clouds.anisotropy = self.number()?;
+                    if clouds.anisotropy.abs() >= 1.0 {
+                        return Err(self.error("cloud anisotropy must be between -1 and 1"));
+                    }
+                }
+                "range" => {
+                    clouds.range = self.number()?;
+                    if clouds.range <= 0.0 {
+                        return Err(self.error("cloud range must be positive"));
+                    }
+                }
+                other => return Err(self.error(&format!("unexpected '{}'", other))),
+            }
+        }
+        Ok(clouds)
+    }
+
+    fn end(&mut self) -> std::io::Result<()> {
+        match self.words.next() {
+            Some(word) => Err(self.error(&format!("unexpected '{}'", word))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// What parsing a scene keeps across the files it includes.
+#[derive(Default)]
+struct Context {
+    /// Files whose includes are being parsed, to catch files including
+    /// themselves.
+    including: Vec<PathBuf>,
+    /// Textures defined with `texture`, by name.
+    textures: HashMap<String, Texture>,
+    /// Meshes defined with `lod`, by name.
+    lods: HashMap<String, Arc<LevelsOfDetail>>,
+    /// Meshes left for `MeshStream` to load, when they are streamed in.
+    streamed: Option<Vec<StreamedMesh>>,
+}
+
+/// Adds the material starting at the next word of `line` to `scene`.
+fn add_material(
+    scene: &mut Scene,
+    line: &mut Line,
+    directory: &Path,
+) -> std::io::Result<MaterialId> {
+    // Water is a dielectric with an absorption, set on the scene.
+    if line.words.clone().next() == Some("water") {
+        line.words.next();
+        return Ok(scene.add_water());
+    }
+    let material = line.material(directory)?;
+    Ok(scene.add_material(material))
+}
+
+/// Adds the keyframe following `keyframe` on `line` to the animation of
+/// `scene`.
+fn add_keyframe(scene: &mut Scene, line: &mut Line) -> std::io::Result<()> {
+    let target = line.word()?;
+    let time = line.number()?;
+    let mut interpolation = Interpolation::Smooth;
+    if target == "camera" {
+        // Keyframes leaving out the field of view or the up direction keep
+        // those of the last one.
+        let last = scene
+            .animation
+            .camera
+            .keyframes()
+            .last()
+            .map(|key| key.value);
+        let mut view = last.or(scene.camera).unwrap_or_default();
+        view.look_from = line.vec3()?;
+        view.look_at = line.vec3()?;
+        while let Some(word) = line.words.next() {
+            match word {
+                "fov" => view.vertical_fov = line.number()?,
+                "up" => view.up = line.vec3()?,
+                word => match Interpolation::from_name(word) {
+                    Some(name) => interpolation = name,
+                    None => return Err(line.error(&format!("unexpected '{}'", word))),
+                },
+            }
+        }
+        scene.animation.camera.insert(Keyframe {
+            time,
+            value: view,
+            interpolation,
+        });
+        return Ok(());
+    }
+
+    let sphere = match scene.sphere_named(target) {
+        Some(sphere) => sphere,
+        None => return Err(line.error(&format!("no object named '{}'", target))),
+    };
+    let mut pose = Pose {
+        offset: line.vec3()?,
+        ..Pose::default()
+    };
+    while let Some(word) = line.words.next() {
+        match word {
+            "rotate" => pose.rotation = line.vec3()?,
+            "scale" => {
+                pose.scale = line.number()?;
+                if pose.scale <= 0.0 {
+                    return Err(line.error("keyframes must have a positive scale"));
+                }
+            }
+            word => match Interpolation::from_name(word) {
+                Some(name) => interpolation = name,
+                None => return Err(line.error(&format!("unexpected '{}'", word))),
+            },
+        }
+    }
+    let pivot = scene.sphere(sphere).position;
+    scene
+        .animation
+        .object_track(sphere, pivot)
+        .insert(Keyframe {
+            time,
+            value: pose,
+            interpolation,
+        });
+    Ok(())
+}
+
+/// Applies the option `word` of a line to `material`, returning whether it
+/// is an option of materials.
+fn material_option(
+    scene: &mut Scene,
+    line: &mut Line,
+    word: &str,
+    material: MaterialId,
+    directory: &Path,
+    textures: &HashMap<String, Texture>,
+) -> std::io::Result<bool> {
+    match word {
+        "one-sided" => scene.set_two_sided(material, false),
+        "two-sided" => scene.set_two_sided(material, true),
+        "priority" => {
+            let priority = line.number()?;
+            if priority.fract() != 0.0 {
+                return Err(line.error("priorities are whole numbers"));
+            }
+            scene.set_priority(material, priority as i32);
+        }
+        "absorption" => scene.set_absorption(material, line.vec3()?),
+        "ocean" => {
+            let ocean = Ocean::new(line.number()?, line.number()?, 0);
+            scene.set_ocean(material, Some(ocean));
+        }
+        "thin-film" => {
+            let film = ThinFilm {
+                thickness: line.number()?,
+                refractive_index: line.number()?,
+            };
+            scene.set_thin_film(material, Some(film));
+        }
+        "normal-map" => {
+            let normal_map = NormalMap {
+                texture: line.texture(directory, textures)?,
+                strength: 1.0,
+            };
+            scene.set_normal_map(material, Some(normal_map));
+        }
+        "bump-map" => {
+            let bump_map = BumpMap {
+                texture: line.texture(directory, textures)?,
+                height: scene.units.from_meters(0.01),
+            };
+            scene.set_bump_map(material, Some(bump_map));
+        }
+        "bump-height" => {
+            let height = line.number()?;
+            let mut bump_map = match scene.bump_map(material) {
+                Some(bump_map) => bump_map.clone(),
+                None => return Err(line.error("bump-height needs a bump-map")),
+            };
+            bump_map.height = height;
+            scene.set_bump_map(material, Some(bump_map));
+        }
+        "normal-strength" => {
+            let strength = line.number()?;
+            let mut normal_map = match scene.normal_map(material) {
+                Some(normal_map) => normal_map.clone(),
+                None => return Err(line.error("normal-strength needs a normal-map")),
+            };
+            normal_map.strength = strength;
+            scene.set_normal_map(material, Some(normal_map));
+        }
+        "tangent-rotation" => {
+            let degrees = line.number()?;
+            let material_type = match scene.material(material) {
+                MaterialType::AnisotropicMetal {
+                    f0,
+                    roughness_u,
+                    roughness_v,
+                    ..
+                } => MaterialType::AnisotropicMetal {
+                    f0: *f0,
+                    roughness_u: *roughness_u,
+                    roughness_v: *roughness_v,
+                    rotation: degrees,
+                },
+                _ => {
+                    let message = "tangent-rotation needs an anisotropic metal";
+                    return Err(line.error(message));
+                }
+            };
+            scene.update_material(material, material_type);
+        }
+        "metallic" | "roughness" | "specular" | "sheen" | "clearcoat" | "transmission" => {
+            let value = line.number()?;
+            let mut principled = match scene.material(material) {
+                MaterialType::Principled(principled) => principled.clone(),
+                _ => {
+                    let message = format!("{} needs a principled material", word);
+                    return Err(line.error(&message));
+                }
+            };
+            match word {
+                "metallic" => principled.metallic = value,
+                "roughness" => principled.roughness = value,
+                "specular" => principled.specular = value,
+                "sheen" => principled.sheen = value,
+                "clearcoat" => principled.clearcoat = value,
+                _ => principled.transmission = value,
+            }
+            scene.update_material(material, MaterialType::Principled(principled));
+        }
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Parses the scene description `source`. `name` is used in error messages
+/// and relative paths are resolved against `directory`.
+pub fn parse_scene(source: &str, name: &str, directory: &Path) -> std::io::Result<Scene> {
+    let mut scene = Scene::new(Units::Meters);
+    parse_into(&mut scene, source, name, directory, &mut Context::default())?;
+    Ok(scene)
+}
+
+/// Adds what `source` describes to `scene`, like `parse_scene`, in the
+/// `context` of the files including it.
+fn parse_into(
+    scene: &mut Scene,
+    source: &str,
+    name: &str,
+    directory: &Path,
+    context: &mut Context,
+) -> std::io::Result<()> {
+    for (index, text) in source.lines().enumerate() {
+        let text = text.split('#').next().unwrap_or("");
+        let mut line = Line {
+            name,
+            number: index + 1,
+            words: text.split_whitespace(),
+        };
+
+        let keyword = match line.words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        match keyword {
+            "units" => {
+                scene.units = match line.word()? {
+                    "meters" => Units::Meters,
+                    "centimeters" => Units::Centimeters,
+                    "millimeters" => Units::Millimeters,
+                    other => return Err(line.error(&format!("unknown units '{}'", other))),
+                }
+            }
+            "bvh" => scene.set_bvh_quality(match line.word()? {
+                "median" => BvhQuality::Median,
+                "sah" => BvhQuality::Sah,
+                other => return Err(line.error(&format!("unknown bvh quality '{}'", other))),
+            }),
+            "accelerator" => {
+                let name = line.word()?;
+                match AcceleratorKind::from_name(name) {
+                    Some(kind) => scene.set_accelerator_kind(kind),
+                    None => return Err(line.error(&format!("unknown accelerator '{}'", name))),
+                }
+            }
+            "precision" => {
+                let name = line.word()?;
+                match Precision::from_name(name) {
+                    Some(precision) => scene.set_precision(precision),
+                    None => return Err(line.error(&format!("unknown precision '{}'", name))),
+                }
+            }
+            "environment" => {
+                scene.environment = match line.word()? {
+                    "constant" => Environment::Constant(line.vec3()?),
+                    "gradient" => Environment::Gradient {
+                        bottom: line.vec3()?,
+                        top: line.vec3()?,
+                    },
+                    "sky" => Environment::Sky(PhysicalSky::new(
+                        line.number()?,
+                        line.number()?,
+                        line.number()?,
+                    )),
+                    "map" => {
+                        let file = directory.join(line.word()?);
+                        let file = file.to_string_lossy();
+                        let map = EnvironmentMap::load(&file)
+                            .map_err(|error| line.error(&format!("{}: {}", file, error)))?;
+                        let (adjustments, ground) = line.map_options()?;
+                        Environment::Map(map.adjust(&adjustments).with_ground_projection(ground))
+                    }
+                    other => return Err(line.error(&format!("unknown environment '{}'", other))),
+                }
+            }
+            "clouds" => scene.clouds = Some(line.clouds()?),
+            "point-light" => scene.delta_lights.push(DeltaLight::Point {
+                position: line.vec3()?,
+                intensity: line.vec3()?,
+            }),
+            "directional-light" => scene.delta_lights.push(DeltaLight::Directional {
+                direction: line.vec3()?,
+                irradiance: line.vec3()?,
+            }),
+            "spot-light" => scene.delta_lights.push(DeltaLight::Spot {
+                position: line.vec3()?,
+                direction: line.vec3()?,
+                intensity: line.vec3()?,
+                inner_angle: line.number()?,
+                outer_angle: line.number()?,
+            }),
+            "sphere" | "plane" | "disk" | "cylinder" | "cone" | "capsule" | "mandelbulb"
+            | "menger" | "blend" | "heightfield" | "curve" | "curves" | "mesh" | "instance" => {
+                let position = line.vec3()?;
+                // Shapes get their material once it is parsed.
+                let unset = MaterialId(0);
+                let mut streamed = None;
+                let mut displacement = None;
+                let (radius, mut shape) = match keyword {
+                    "sphere" => (line.number()?, None),
+                    "mandelbulb" => {
+                        let scale = line.number()?;
+                        let function = DistanceFunction::Mandelbulb {
+                            power: line.number()?,
+                            iterations: MANDELBULB_ITERATIONS,
+                        };
+                        let sdf = Sdf::new(function, position, scale, unset);
+                        (0.0, Some(Shape::Sdf(sdf)))
+                    }
+                    "menger" => {
+                        let scale = line.number()?;
+                        let iterations = line.number()?;
+                        if iterations < 0.0 || iterations.fract() != 0.0 {
+                            return Err(line.error("iterations must be a natural number"));
+                        }
+                        let function = DistanceFunction::Menger {
+                            iterations: iterations as u32,
+                        };
+                        let sdf = Sdf::new(function, position, scale, unset);
+                        (0.0, Some(Shape::Sdf(sdf)))
+                    }
+                    "blend" => {
+                        let first = DistanceFunction::Sphere {
+                            center: position,
+                            radius: line.number()?,
+                        };
+                        let second = DistanceFunction::Sphere {
+                            center: line.vec3()?,
+                            radius: line.number()?,
+                        };
+                        let function = first.blend(second, line.number()?);
+                        let origin = Vec3::new(0.0, 0.0, 0.0);
+                        (
+                            0.0,
+                            Some(Shape::Sdf(Sdf::new(function, origin, 1.0, unset))),
+                        )
+                    }
+                    "heightfield" => {
+                        let size = (line.number()?, line.number()?);
+                        let height = line.number()?;
+                        let terrain = match line.words.clone().next() {
+                            Some("noise") => {
+                                line.words.next();
+                                let seed = line.number()?;
+                                if seed < 0.0 || seed.fract() != 0.0 {
+                                    return Err(line.error("seeds must be natural numbers"));
+                                }
+                                let seed = seed as u64;
+                                let noise = |u, v| {
+                                    let features = TERRAIN_FEATURES;
+                                    fractal_noise(seed, u * features, v * features, 6)
+                                };
+                                let resolution = (TERRAIN_SAMPLES, TERRAIN_SAMPLES);
+                                Heightfield::from_function(
+                                    noise, resolution, position, size, height, unset,
+                                )
+                            }
+                            _ => {
+                                let texture = line.texture(directory, &context.textures)?;
+                                Heightfield::from_texture(&texture, position, size, height, unset)
+                            }
+                        };
+                        (0.0, Some(Shape::Heightfield(terrain)))
+                    }
+                    "curve" => {
+                        let points = [position, line.vec3()?, line.vec3()?, line.vec3()?];
+                        let curve = Curve::new(points, (line.number()?, line.number()?));
+                        (0.0, Some(Shape::Curves(Curves::new(vec![curve], unset))))
+                    }
+                    "curves" => {
+                        let scale = line.number()?;
+                        let file = directory.join(line.word()?);
+                        let file = file.to_string_lossy();
+                        let strands = load_hair(&file)
+                            .map_err(|error| line.error(&format!("{}: {}", file, error)))?;
+                        let placement = Mat4::translation(position)
+                            * Mat4::scaling(Vec3::new(scale, scale, scale));
+                        let curves = Curves::new(strands, unset).transformed(&placement);
+                        (0.0, Some(Shape::Curves(curves)))
+                    }
+                    "mesh" => {
+                        let scale = line.number()?;
+                        let file = directory.join(line.word()?);
+                        let file = file.to_string_lossy();
+                        let placement = Mat4::translation(position)
+                            * Mat4::scaling(Vec3::new(scale, scale, scale));
+                        let smooth = line.words.clone().next() == Some("smooth");
+                        if smooth {
+                            line.words.next();
+                        }
+                        if line.words.clone().next() == Some("displace") {
+                            line.words.next();
+                            let levels = line.number()?;
+                            if !(0.0..=MAX_DISPLACEMENT_LEVELS as Float).contains(&levels)
+                                || levels.fract() != 0.0
+                            {
+                                let message = format!(
+                                    "displacement levels go from 0 to {}",
+                                    MAX_DISPLACEMENT_LEVELS
+                                );
+                                return Err(line.error(&message));
+                            }
+                            let height = line.number()?;
+                            let texture = line.texture(directory, &context.textures)?;
+                            // The projection follows the options of the
+                            // object, read below.
+                            displacement = Some(Displacement {
+                                bump_map: BumpMap { texture, height },
+                                projection: UvProjection::default(),
+                                levels: levels as usize,
+                            });
+                        }
+                        if context.streamed.is_some() {
+                            // Stands in as a sphere rays can't hit until the
+                            // mesh streams in.
+                            streamed = Some(StreamedMesh {
+                                id: ObjectId(0),
+                                location: format!("{}:{}", line.name, line.number),
+                                file: file.into_owned(),
+                                placement,
+                                smooth,
+                                displacement: None,
+                            });
+                            (0.0, None)
+                        } else {
+                            let (positions, triangles) = load_mesh(&file)
+                                .map_err(|error| line.error(&format!("{}: {}", file, error)))?;
+                            let mut mesh = Mesh::new(positions, triangles, unset);
+                            if smooth {
+                                mesh = mesh.smoothed();
+                            }
+                            (0.0, Some(Shape::Mesh(mesh.transformed(&placement))))
+                        }
+                    }
+                    "instance" => {
+                        let scale = line.number()?;
+                        let angle = line.number()?;
+                        let name = line.word()?;
+                        let levels = match context.lods.get(name) {
+                            Some(levels) => Arc::clone(levels),
+                            None => return Err(line.error(&format!("no lod named '{}'", name))),
+                        };
+                        if scale <= 0.0 {
+                            return Err(line.error("instances must have a positive scale"));
+                        }
+                        let placement = Mat4::translation(position)
+                            * Mat4::rotation_y(angle.to_radians())
+                            * Mat4::scaling(Vec3::new(scale, scale, scale));
+                        (
+                            0.0,
+                            Some(Shape::Instance(Instance::new(levels, &placement, unset))),
+                        )
+                    }
+                    "capsule" => {
+                        let end = line.vec3()?;
+                        let radius = line.number()?;
+                        let capsule = Capsule::new(position, end, radius, unset);
+                        (radius, Some(Shape::Capsule(capsule)))
+                    }
+                    _ => {
+                        let direction = line.vec3()?;
+                        if direction.length_squared() == 0.0 {
+                            return Err(line.error("normals and axes can't be zero"));
+                        }
+                        let shape = match keyword {
+                            "plane" => Shape::Plane(Plane::new(position, direction, unset)),
+                            "disk" => {
+                                let radius = line.number()?;
+                                Shape::Disk(Disk::new(position, direction, radius, unset))
+                            }
+                            "cylinder" => {
+                                let (height, radius) = (line.number()?, line.number()?);
+                                let cylinder =
+                                    Cylinder::new(position, direction, height, radius, unset);
+                                Shape::Cylinder(cylinder)
+                            }
+                            _ => {
+                                let (height, radius) = (line.number()?, line.number()?);
+                                Shape::Cone(Cone::new(position, direction, height, radius, unset))
+                            }
+                        };
+                        (0.0, Some(shape))
+                    }
+                };
+                let shared = line.words.clone().next() == Some("use");
+                let material = if shared {
+                    line.words.next();
+                    let name = line.word()?;
+                    match scene.material_named(name) {
+                        Some(material) => material,
+                        None => return Err(line.error(&format!("no material named '{}'", name))),
+                    }
+                } else {
+                    add_material(scene, &mut line, directory)?
+                };
+                let is_light =
+                    matches!(scene.material(material), MaterialType::DiffuseLight { .. });
+                if let Some(shape) = &mut shape {
+                    shape.set_material(material);
+                }
+                let id = match shape {
+                    None if streamed.is_some() => scene.add(Sphere::new(position, 0.0, material)),
+                    None if is_light => scene.add_light(Sphere::new(position, radius, material)),
+                    None => scene.add(Sphere::new(position, radius, material)),
+                    Some(Shape::Disk(disk)) if is_light => scene.add_disk_light(disk),
+                    // Other shapes aren't sampled, so they only light what
+                    // bounces towards them.
+                    Some(shape) => scene.add_shape(shape),
+                };
+
+                while let Some(word) = line.words.next() {
+                    match word {
+                        "name" => scene.set_name(id, line.word()?),
+                        "shadow-catcher" => scene.set_shadow_catcher(id, true),
+                        "cull-backfaces" => scene.set_cull_backfaces(id, true),
+                        "uv-projection" => {
+                            if !matches!(scene.shape(id), None | Some(Shape::Mesh(_))) {
+                                let message = "only spheres and meshes take a uv projection";
+                                return Err(line.error(message));
+                            }
+                            let projection = match line.word()? {
+                                "spherical" => UvProjection::Spherical,
+                                "cylindrical" => UvProjection::Cylindrical,
+                                "planar" => UvProjection::Planar,
+                                "box" => UvProjection::Box,
+                                other => {
+                                    let message = format!("unknown uv projection '{}'", other);
+                                    return Err(line.error(&message));
+                                }
+                            };
+                            scene.set_uv_projection(id, projection);
+                        }
+                        // Shared materials are changed where they are
+                        // defined, not by one of their objects.
+                        word if !shared => {
+                            let textures = &context.textures;
+                            if !material_option(
+                                scene, &mut line, word, material, directory, textures,
+                            )? {
+                                return Err(line.error(&format!("unexpected '{}'", word)));
+                            }
+                        }
+                        word => {
+                            let message = format!("unexpected '{}' after a shared material", word);
+                            return Err(line.error(&message));
+                        }
+                    }
+                }
+                if let Some(displacement) = &mut displacement {
+                    displacement.projection = scene.uv_projection(id).unwrap_or_default();
+                }
+                if let (Some(mut mesh), Some(streamed)) = (streamed, &mut context.streamed) {
+                    mesh.id = id;
+                    mesh.displacement = displacement;
+                    streamed.push(mesh);
+                } else if let (Some(displacement), Some(Shape::Mesh(mesh))) =
+                    (displacement, scene.shape(id))
+                {
+                    let displaced = mesh.displaced(&displacement);
+                    scene.replace_shape(id, Shape::Mesh(displaced));
+                }
+            }
+            "keyframe" => add_keyframe(scene, &mut line)?,
+            "material" => {
+                let name = line.word()?;
+                if scene.material_named(name).is_some() {
+                    let message = format!("material '{}' is already defined", name);
+                    return Err(line.error(&message));
+                }
+                let material = add_material(scene, &mut line, directory)?;
+                scene.set_material_name(material, name);
+                while let Some(word) = line.words.next() {
+                    let textures = &context.textures;
+                    if !material_option(scene, &mut line, word, material, directory, textures)? {
+                        return Err(line.error(&format!("unexpected '{}'", word)));
+                    }
+                }
+            }
+            "texture" => {
+                let name = line.word()?;
+                if context.textures.contains_key(name) {
+                    let message = format!("texture '{}' is already defined", name);
+                    return Err(line.error(&message));
+                }
+                let texture = line.texture(directory, &context.textures)?;
+                context.textures.insert(name.to_string(), texture);
+            }
+            "lod" => {
+                let name = line.word()?;
+                if context.lods.contains_key(name) {
+                    let message = format!("lod '{}' is already defined", name);
+                    return Err(line.error(&message));
+                }
+                let load = |line: &mut Line| -> std::io::Result<Mesh> {
+                    let file = directory.join(line.word()?);
+                    let file = file.to_string_lossy();
+                    let (positions, triangles) = load_mesh(&file)
+                        .map_err(|error| line.error(&format!("{}: {}", file, error)))?;
+                    Ok(Mesh::new(positions, triangles, MaterialId(0)))
+                };
+                let mut levels = LevelsOfDetail::new(load(&mut line)?);
+                let mut last = 0.0;
+                while line.words.clone().next().is_some() {
+                    let distance = line.number()?;
+                    if distance <= last {
+                        return Err(line.error("lod distances must go up"));
+                    }
+                    last = distance;
+                    levels = levels.with_level(distance, load(&mut line)?);
+                }
+                context.lods.insert(name.to_string(), Arc::new(levels));
+            }
+            "gltf" => {
+                let file = directory.join(line.word()?);
+                let file = file.to_string_lossy();
+                load_gltf(&file, scene)
+                    .map_err(|error| line.error(&format!("{}: {}", file, error)))?;
+            }
+            "include" => {
+                let file = directory.join(line.word()?);
+                let error = |error| line.error(&format!("{}: {}", file.display(), error));
+                let path = file.canonicalize().map_err(error)?;
+                if context.including.contains(&path) {
+                    let message = format!("{} includes itself", file.display());
+                    return Err(line.error(&message));
+                }
+                let mut source = String::new();
+                File::open(&path)
+                    .and_then(|mut included| included.read_to_string(&mut source))
+                    .map_err(error)?;
+
+                // Paths are relative to the file they are written in.
+                context.including.push(path);
+                let directory = file.parent().unwrap_or_else(|| Path::new(""));
+                let name = file.to_string_lossy();
+                parse_into(scene, &source, &name, directory, context)?;
+                context.including.pop();
+            }
+            other => return Err(line.error(&format!("unknown keyword '{}'", other))),
+        }
+
+        line.end()?;
+    }
+
+    Ok(())
+}
+
+/// Loads the scene file `name`, or the glTF file if it ends with `.gltf`
+/// or `.glb` (see `parse_gltf`).
+pub fn load_scene(name: &str) -> std::io::Result<Scene> {
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    if let Some("gltf" | "glb") = extension.as_deref() {
+        let mut scene = Scene::new(Units::Meters);
+        load_gltf(name, &mut scene)?;
+        return Ok(scene);
+    }
+
+    let mut source = String::new();
+    File::open(name)?.read_to_string(&mut source)?;
+
+    let directory = Path::new(name).parent().unwrap_or_else(|| Path::new(""));
+    let mut scene = Scene::new(Units::Meters);
+    let mut context = Context {
+        including: vec![Path::new(name).canonicalize()?],
+        ..Context::default()
+    };
+    parse_into(&mut scene, &source, name, directory, &mut context)?;
+    Ok(scene)
+}
+
+/// Loads the scene file `name` like `load_scene`, but leaves its meshes to
+/// load on background threads, so rendering can start before they are all
+/// in. They come in through the returned stream, see `MeshStream`. glTF
+/// files are loaded whole, with nothing left to stream.
+pub fn load_scene_streamed(name: &str) -> std::io::Result<(Scene, MeshStream)> {
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    if let Some("gltf" | "glb") = extension.as_deref() {
+        let scene = load_scene(name)?;
+        let stream = MeshStream::start(Vec::new(), scene.units);
+        return Ok((scene, stream));
+    }
+
+    let mut source = String::new();
+    File::open(name)?.read_to_string(&mut source)?;
+
+    let directory = Path::new(name).parent().unwrap_or_else(|| Path::new(""));
+    let mut scene = Scene::new(Units::Meters);
+    let mut context = Context {
+        including: vec![Path::new(name).canonicalize()?],
+        streamed: Some(Vec::new()),
+        ..Context::default()
+    };
+    parse_into(&mut scene, &source, name, directory, &mut context)?;
+    let meshes = context.streamed.unwrap_or_default();
+    let stream = MeshStream::start(meshes, scene.units);
+    Ok((scene, stream))
+}
@@ -0,0 +1,317 @@
+use crate::hitable::*;
+use crate::material::MaterialId;
+use crate::maths::*;
+use crate::ray::Ray;
+use crate::sphere::{Sphere, UvFrame, UvProjection};
+
+use std::sync::Arc;
+
+/// Distance, in the units of a distance function, under which sphere
+/// tracing takes a point to be on the surface.
+#[cfg(not(feature = "f32"))]
+const SURFACE_DISTANCE: Float = 1e-5;
+/// Single precision can't get as close.
+#[cfg(feature = "f32")]
+const SURFACE_DISTANCE: Float = 1e-3;
+
+/// Most steps sphere tracing takes along a ray before giving up.
+const MAX_STEPS: usize = 512;
+
+/// Fraction of the estimated distance sphere tracing steps by, as the
+/// estimates of fractals can overshoot a little.
+const STEP_FACTOR: Float = 0.9;
+
+/// Signed distance from points to a surface, negative inside, or an estimate
+/// never above it.
+#[derive(Clone)]
+pub enum DistanceFunction {
+    Sphere {
+        center: Vec3,
+        radius: Float,
+    },
+    /// The power 8 bulb for `power` 8, inside the sphere of radius 1.2
+    /// around the origin, its poles along y.
+    Mandelbulb {
+        power: Float,
+        iterations: u32,
+    },
+    /// Menger sponge filling the cube from -1 to 1 on every axis.
+    Menger {
+        iterations: u32,
+    },
+    /// Union of both surfaces, filled in where they come within about `k`
+    /// of each other, like blobs merging.
+    SmoothUnion(Box<DistanceFunction>, Box<DistanceFunction>, Float),
+    /// Any function, with the radius of a sphere around the origin holding
+    /// its surface.
+    Custom {
+        function: Arc<dyn Fn(Vec3) -> Float + Send + Sync>,
+        radius: Float,
+    },
+}
+
+impl std::fmt::Debug for DistanceFunction {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DistanceFunction::Sphere { center, radius } => formatter
+                .debug_struct("Sphere")
+                .field("center", center)
+                .field("radius", radius)
+                .finish(),
+            DistanceFunction::Mandelbulb { power, iterations } => formatter
+                .debug_struct("Mandelbulb")
+                .field("power", power)
+                .field("iterations", iterations)
+                .finish(),
+            DistanceFunction::Menger { iterations } => formatter
+                .debug_struct("Menger")
+                .field("iterations", iterations)
+                .finish(),
+            DistanceFunction::SmoothUnion(a, b, k) => formatter
+                .debug_tuple("SmoothUnion")
+                .field(a)
+                .field(b)
+                .field(k)
+                .finish(),
+            DistanceFunction::Custom { radius, .. } => formatter
+                .debug_struct("Custom")
+                .field("radius", radius)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+impl DistanceFunction {
+    /// Smooth union of `self` and `other`, see `SmoothUnion`.
+    pub fn blend(self, other: DistanceFunction, k: Float) -> Self {
+        DistanceFunction::SmoothUnion(Box::new(self), Box::new(other), k)
+    }
+
+    pub fn distance(&self, point: Vec3) -> Float {
+        match self {
+            DistanceFunction::Sphere { center, radius } => (point - *center).length() - radius,
+            DistanceFunction::Mandelbulb { power, iterations } => {
+                mandelbulb(point, *power, *iterations)
+            }
+            DistanceFunction::Menger { iterations } => menger(point, *iterations),
+            DistanceFunction::SmoothUnion(a, b, k) => {
+                smooth_min(a.distance(point), b.distance(point), *k)
+            }
+            DistanceFunction::Custom { function, .. } => function(point),
+        }
+    }
+
+    /// Center and radius of a sphere holding the surface.
+    pub fn bounds(&self) -> (Vec3, Float) {
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+        match self {
+            DistanceFunction::Sphere { center, radius } => (*center, radius.abs()),
+            DistanceFunction::Mandelbulb { .. } => (origin, 1.2),
+            DistanceFunction::Menger { .. } => (origin, Float::sqrt(3.0)),
+            DistanceFunction::SmoothUnion(a, b, k) => {
+                let ((a_center, a_radius), (b_center, b_radius)) = (a.bounds(), b.bounds());
+                let gap = (b_center - a_center).length();
+                if gap + b_radius <= a_radius {
+                    (a_center, a_radius + k)
+                } else if gap + a_radius <= b_radius {
+                    (b_center, b_radius + k)
+                } else {
+                    let radius = (gap + a_radius + b_radius) / 2.0;
+                    let center = a_center + (b_center - a_center) * ((radius - a_radius) / gap);
+                    (center, radius + k)
+                }
+            }
+            DistanceFunction::Custom { radius, .. } => (origin, *radius),
+        }
+    }
+}
+
+/// Polynomial smooth minimum of `a` and `b`, joining them over a width of
+/// `k`.
+pub fn smooth_min(a: Float, b: Float, k: Float) -> Float {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (k - (a - b).abs()).max(0.0) / k;
+    a.min(b) - h * h * k / 4.0
+}
+
+/// Distance estimate of the mandelbulb of `power`, from the derivative of
+/// its iteration.
+fn mandelbulb(point: Vec3, power: Float, iterations: u32) -> Float {
+    // The formula has its poles along z.
+    let c = Vec3::new(point.x, point.z, point.y);
+    let mut z = c;
+    let mut derivative = 1.0;
+    let mut r = z.length();
+    for _ in 0..iterations {
+        if r > 2.0 || r == 0.0 {
+            break;
+        }
+        let theta = (z.z / r).acos() * power;
+        let phi = z.y.atan2(z.x) * power;
+        derivative = r.powf(power - 1.0) * power * derivative + 1.0;
+        z = Vec3::new(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            theta.cos(),
+        ) * r.powf(power)
+            + c;
+        r = z.length();
+    }
+    if r == 0.0 {
+        return 0.0;
+    }
+    0.5 * r.ln() * r / derivative
+}
+
+/// Distance to the Menger sponge of `iterations` holes deep, carving the
+/// crosses of every level out of the cube.
+fn menger(point: Vec3, iterations: u32) -> Float {
+    let outside = Vec3::new(point.x.abs(), point.y.abs(), point.z.abs()) - Vec3::new(1.0, 1.0, 1.0);
+    let clamped = Vec3::new(outside.x.max(0.0), outside.y.max(0.0), outside.z.max(0.0));
+    let mut distance = clamped.length() + outside.x.max(outside.y).max(outside.z).min(0.0);
+
+    let mut scale = 1.0;
+    for _ in 0..iterations {
+        // Distances to the middle of the cross of the cell holding the point.
+        let cell = |x: Float| (1.0 - 3.0 * ((x * scale).rem_euclid(2.0) - 1.0).abs()).abs();
+        let (x, y, z) = (cell(point.x), cell(point.y), cell(point.z));
+        scale *= 3.0;
+        let cross = (x.max(y)).min(y.max(z)).min(z.max(x));
+        distance = distance.max((cross - 1.0) / scale);
+    }
+    distance
+}
+
+/// Surface of a `DistanceFunction`, moved to `center` and scaled by
+/// `scale`, found by sphere tracing. Texture coordinates are those of its
+/// bounding sphere right above or below, see `Sphere::uv_at`.
+#[derive(Clone, Debug)]
+pub struct Sdf {
+    pub function: DistanceFunction,
+    pub center: Vec3,
+    pub scale: Float,
+    pub material: MaterialId,
+}
+
+impl Sdf {
+    pub fn new(
+        function: DistanceFunction,
+        center: Vec3,
+        scale: Float,
+        material: MaterialId,
+    ) -> Self {
+        Sdf {
+            function,
+            center,
+            scale,
+            material,
+        }
+    }
+
+    /// Distance from `point` to the surface, in scene units.
+    pub fn distance(&self, point: Vec3) -> Float {
+        self.function.distance((point - self.center) / self.scale) * self.scale
+    }
+
+    /// Sphere holding the surface.
+    pub fn bounding_sphere(&self) -> Sphere {
+        let (center, radius) = self.function.bounds();
+        Sphere::new(
+            self.center + center * self.scale,
+            radius * self.scale,
+            self.material,
+        )
+    }
+
+    /// Outward unit normal at `point`, by central differences.
+    pub fn normal_at(&self, point: Vec3) -> Vec3 {
+        let h = SURFACE_DISTANCE * self.scale;
+        let axis = |offset: Vec3| self.distance(point + offset) - self.distance(point - offset);
+        let gradient = Vec3::new(
+            axis(Vec3::new(h, 0.0, 0.0)),
+            axis(Vec3::new(0.0, h, 0.0)),
+            axis(Vec3::new(0.0, 0.0, h)),
+        );
+        if gradient.length_squared() == 0.0 {
+            return (point - self.bounding_sphere().position).unit();
+        }
+        gradient.unit()
+    }
+
+    pub fn uv_frame(&self, point: Vec3) -> UvFrame {
+        self.bounding_sphere()
+            .uv_frame(point, UvProjection::Spherical)
+    }
+
+    /// Point and outward normal at texture coordinates (`u`, `v`), where the
+    /// surface is first met coming from the bounding sphere towards its
+    /// center, or on the bounding sphere when it's missed.
+    pub fn surface_at(&self, u: Float, v: Float) -> (Vec3, Vec3) {
+        let bounds = self.bounding_sphere();
+        let (point, normal) = bounds.surface_at(u, v);
+        let ray = Ray::new(point, -normal);
+        match self.hit(&ray, 0.0, bounds.radius) {
+            Some(hit) => (hit.position, self.normal_at(hit.position)),
+            None => (point, normal),
+        }
+    }
+
+    /// Copy of the surface moved by `transform`, following its translation
+    /// and average scale. Distance functions keep their orientation.
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        Sdf {
+            center: transform.transform_point(self.center),
+            scale: self.scale * transform.determinant3().abs().cbrt(),
+            ..self.clone()
+        }
+    }
+}
+
+impl Hitable for Sdf {
+    fn scale(&mut self, factor: Float) {
+        self.center = self.center * factor;
+        self.scale *= factor;
+    }
+
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        // Only march where the ray is inside the bounding sphere.
+        let bounds = self.bounding_sphere();
+        let oc = ray.origin - bounds.position;
+        let a = ray.dir.length_squared();
+        let half_b = oc.dot(ray.dir);
+        let c = oc.length_squared() - bounds.radius * bounds.radius;
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        let end = ((-half_b + root) / a).min(t_max);
+        let mut t = ((-half_b - root) / a).max(t_min);
+
+        // Rays leaving the surface start on it, so hits only count once they
+        // got clear of it.
+        let speed = a.sqrt();
+        let threshold = SURFACE_DISTANCE * self.scale;
+        let mut clear = self.distance(ray.origin).abs() >= threshold;
+        for _ in 0..MAX_STEPS {
+            if t >= end {
+                return None;
+            }
+            let position = ray.at(t);
+            let distance = self.distance(position).abs();
+            if distance < threshold {
+                if clear {
+                    let normal = self.normal_at(position);
+                    return Some(HitRecord::new(ray, position, normal, t, self.material));
+                }
+                t += threshold / speed;
+            } else {
+                clear = true;
+                t += (distance * STEP_FACTOR).max(threshold) / speed;
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,215 @@
+use crate::accelerator::AcceleratorKind;
+use crate::bvh::Precision;
+use crate::curve::Curves;
+use crate::heightfield::Heightfield;
+use crate::hitable::*;
+use crate::instance::Instance;
+use crate::material::MaterialId;
+use crate::maths::*;
+use crate::mesh::Mesh;
+use crate::plane::{Disk, Plane};
+use crate::quadric::{Capsule, Cone, Cylinder};
+use crate::ray::Ray;
+use crate::sdf::Sdf;
+use crate::sphere::{Sphere, UvFrame};
+
+/// Object other than a sphere, standing in for one in a scene, see
+/// `Scene::add_shape`.
+#[derive(Clone, Debug)]
+pub enum Shape {
+    Plane(Plane),
+    Disk(Disk),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Capsule(Capsule),
+    Sdf(Sdf),
+    Heightfield(Heightfield),
+    Curves(Curves),
+    Mesh(Mesh),
+    Instance(Instance),
+}
+
+impl Shape {
+    fn hitable(&self) -> &dyn Hitable {
+        match self {
+            Shape::Plane(plane) => plane,
+            Shape::Disk(disk) => disk,
+            Shape::Cylinder(cylinder) => cylinder,
+            Shape::Cone(cone) => cone,
+            Shape::Capsule(capsule) => capsule,
+            Shape::Sdf(sdf) => sdf,
+            Shape::Heightfield(terrain) => terrain,
+            Shape::Curves(curves) => curves,
+            Shape::Mesh(mesh) => mesh,
+            Shape::Instance(instance) => instance,
+        }
+    }
+
+    /// Outward unit normal at `point`, on the surface, the front of planes,
+    /// disks and the triangles of meshes, the top of heightfields and away
+    /// from the axis of curves.
+    pub fn normal_at(&self, point: Vec3) -> Vec3 {
+        match self {
+            Shape::Plane(plane) => plane.normal,
+            Shape::Disk(disk) => disk.normal,
+            Shape::Cylinder(cylinder) => cylinder.normal_at(point),
+            Shape::Cone(cone) => cone.normal_at(point),
+            Shape::Capsule(capsule) => capsule.normal_at(point),
+            Shape::Sdf(sdf) => sdf.normal_at(point),
+            Shape::Heightfield(terrain) => terrain.normal_at(point),
+            Shape::Curves(curves) => curves.normal_at(point),
+            Shape::Mesh(mesh) => mesh.normal_at(point),
+            Shape::Instance(instance) => instance.normal_at(point),
+        }
+    }
+
+    pub fn uv_frame(&self, point: Vec3) -> UvFrame {
+        match self {
+            Shape::Plane(plane) => plane.uv_frame(point),
+            Shape::Disk(disk) => disk.uv_frame(point),
+            Shape::Cylinder(cylinder) => cylinder.uv_frame(point),
+            Shape::Cone(cone) => cone.uv_frame(point),
+            Shape::Capsule(capsule) => capsule.uv_frame(point),
+            Shape::Sdf(sdf) => sdf.uv_frame(point),
+            Shape::Heightfield(terrain) => terrain.uv_frame(point),
+            Shape::Curves(curves) => curves.uv_frame(point),
+            Shape::Mesh(mesh) => mesh.uv_frame(point),
+            Shape::Instance(instance) => instance.uv_frame(point),
+        }
+    }
+
+    /// Point and outward normal at texture coordinates (`u`, `v`), on the
+    /// side of cylinders and cones, under the bounding sphere of distance
+    /// fields.
+    pub fn surface_at(&self, u: Float, v: Float) -> (Vec3, Vec3) {
+        match self {
+            Shape::Plane(plane) => plane.surface_at(u, v),
+            Shape::Disk(disk) => disk.surface_at(u, v),
+            Shape::Cylinder(cylinder) => cylinder.surface_at(u, v),
+            Shape::Cone(cone) => cone.surface_at(u, v),
+            Shape::Capsule(capsule) => capsule.surface_at(u, v),
+            Shape::Sdf(sdf) => sdf.surface_at(u, v),
+            Shape::Heightfield(terrain) => terrain.surface_at(u, v),
+            Shape::Curves(curves) => curves.surface_at(u, v),
+            Shape::Mesh(mesh) => mesh.surface_at(u, v),
+            Shape::Instance(instance) => instance.surface_at(u, v),
+        }
+    }
+
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        match self {
+            Shape::Plane(plane) => Shape::Plane(plane.transformed(transform)),
+            Shape::Disk(disk) => Shape::Disk(disk.transformed(transform)),
+            Shape::Cylinder(cylinder) => Shape::Cylinder(cylinder.transformed(transform)),
+            Shape::Cone(cone) => Shape::Cone(cone.transformed(transform)),
+            Shape::Capsule(capsule) => Shape::Capsule(capsule.transformed(transform)),
+            Shape::Sdf(sdf) => Shape::Sdf(sdf.transformed(transform)),
+            Shape::Heightfield(terrain) => Shape::Heightfield(terrain.transformed(transform)),
+            Shape::Curves(curves) => Shape::Curves(curves.transformed(transform)),
+            Shape::Mesh(mesh) => Shape::Mesh(mesh.transformed(transform)),
+            Shape::Instance(instance) => Shape::Instance(instance.transformed(transform)),
+        }
+    }
+
+    /// Shape finding its parts through structures of `kind`, meshes being
+    /// the only shapes with their own.
+    pub fn with_accelerator(self, kind: AcceleratorKind) -> Self {
+        match self {
+            Shape::Mesh(mesh) => Shape::Mesh(mesh.with_accelerator(kind)),
+            shape => shape,
+        }
+    }
+
+    /// Shape storing its parts in `precision`, meshes again being the only
+    /// shapes concerned.
+    pub fn with_precision(self, precision: Precision) -> Self {
+        match self {
+            Shape::Mesh(mesh) => Shape::Mesh(mesh.with_precision(precision)),
+            shape => shape,
+        }
+    }
+
+    pub fn set_material(&mut self, material: MaterialId) {
+        match self {
+            Shape::Plane(plane) => plane.material = material,
+            Shape::Disk(disk) => disk.material = material,
+            Shape::Cylinder(cylinder) => cylinder.material = material,
+            Shape::Cone(cone) => cone.material = material,
+            Shape::Capsule(capsule) => capsule.material = material,
+            Shape::Sdf(sdf) => sdf.material = material,
+            Shape::Heightfield(terrain) => terrain.material = material,
+            Shape::Curves(curves) => curves.material = material,
+            Shape::Mesh(mesh) => mesh.material = material,
+            Shape::Instance(instance) => instance.material = material,
+        }
+    }
+
+    /// Sphere bounding the shape, infinite for planes, which keeps its place
+    /// among the spheres of a scene.
+    pub fn bounding_sphere(&self) -> Sphere {
+        // Cylinders and cones fit in the sphere through the rims of their
+        // ends.
+        let around_axis = |base: Vec3, axis: Vec3, height: Float, radius: Float, material| {
+            let half = height / 2.0;
+            let radius = Float::sqrt(half * half + radius * radius);
+            Sphere::new(base + axis * half, radius, material)
+        };
+        match self {
+            Shape::Plane(plane) => Sphere::new(plane.point, Float::INFINITY, plane.material),
+            Shape::Disk(disk) => Sphere::new(disk.center, disk.radius, disk.material),
+            Shape::Cylinder(cylinder) => around_axis(
+                cylinder.base,
+                cylinder.axis,
+                cylinder.height,
+                cylinder.radius,
+                cylinder.material,
+            ),
+            Shape::Cone(cone) => around_axis(
+                cone.base,
+                cone.axis,
+                cone.height,
+                cone.radius,
+                cone.material,
+            ),
+            Shape::Capsule(capsule) => {
+                let half = (capsule.end - capsule.start).length() / 2.0;
+                let center = (capsule.start + capsule.end) / 2.0;
+                Sphere::new(center, half + capsule.radius, capsule.material)
+            }
+            Shape::Sdf(sdf) => sdf.bounding_sphere(),
+            Shape::Heightfield(terrain) => terrain.bounding_sphere(),
+            Shape::Curves(curves) => curves.bounding_sphere(),
+            Shape::Mesh(mesh) => mesh.bounding_sphere(),
+            Shape::Instance(instance) => instance.bounding_sphere(),
+        }
+    }
+}
+
+impl Hitable for Shape {
+    fn scale(&mut self, factor: Float) {
+        match self {
+            Shape::Plane(plane) => plane.scale(factor),
+            Shape::Disk(disk) => disk.scale(factor),
+            Shape::Cylinder(cylinder) => cylinder.scale(factor),
+            Shape::Cone(cone) => cone.scale(factor),
+            Shape::Capsule(capsule) => capsule.scale(factor),
+            Shape::Sdf(sdf) => sdf.scale(factor),
+            Shape::Heightfield(terrain) => terrain.scale(factor),
+            Shape::Curves(curves) => curves.scale(factor),
+            Shape::Mesh(mesh) => mesh.scale(factor),
+            Shape::Instance(instance) => instance.scale(factor),
+        }
+    }
+
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        self.hitable().hit(ray, t_min, t_max)
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> Float {
+        self.hitable().pdf_value(origin, direction)
+    }
+
+    fn sample_direction_towards(&self, origin: Vec3, u: (Float, Float)) -> Vec3 {
+        self.hitable().sample_direction_towards(origin, u)
+    }
+}
@@ -0,0 +1,201 @@
+use crate::maths::consts::PI;
+use crate::maths::*;
+
+/// Scale from the luminance of the model, in kcd/m², to scene radiance,
+/// putting a clear midday zenith around 0.5.
+const LUMINANCE_SCALE: Float = 0.06;
+
+/// Irradiance the sun gives above the atmosphere, in scene units, about 16
+/// times the radiance of a clear zenith like on a real summer day.
+const SUN_IRRADIANCE: Float = 8.0;
+
+/// Angular radius of the sun disk, in radians.
+const SUN_RADIUS: Float = 0.004_65;
+
+/// Wavelengths, in micrometers, standing for the red, green and blue
+/// channels when the atmosphere dims the sun.
+const WAVELENGTHS: [Float; 3] = [0.65, 0.57, 0.475];
+
+/// Clear sky of Preetham et al. 1999, "A Practical Analytic Model for
+/// Daylight", with the sun disk on top.
+///
+/// The sky brightens towards the sun and the horizon, turning hazier and
+/// yellower as turbidity grows from 2, a very clear sky, to 10, a hazy one.
+/// Directions below the horizon see the sky at the horizon. The sun is also
+/// sampled for direct lighting, see `Scene::sample_light_direction`.
+#[derive(Clone, Debug)]
+pub struct PhysicalSky {
+    sun_elevation: Float,
+    sun_azimuth: Float,
+    turbidity: Float,
+    /// Unit direction towards the sun.
+    sun_direction: Vec3,
+    /// Perez coefficients A to E of the luminance and x, y chromaticities.
+    perez: [[Float; 5]; 3],
+    /// Luminance and chromaticities at the zenith, divided by the Perez
+    /// function there so it scales the function directly.
+    zenith: [Float; 3],
+    sun_radiance: Vec3,
+    /// Cosine of the angular radius of the sun.
+    sun_cos_max: Float,
+}
+
+impl PhysicalSky {
+    /// Sky with the sun `elevation` degrees above the horizon and `azimuth`
+    /// degrees from -z towards +x.
+    pub fn new(elevation: Float, azimuth: Float, turbidity: Float) -> Self {
+        let turbidity = clamp(turbidity, 1.7, 10.0);
+        let elevation = clamp(elevation, 0.0, 90.0);
+        let (el, az) = (deg_to_rad(elevation), deg_to_rad(azimuth));
+        let sun_direction = Vec3::new(az.sin() * el.cos(), el.sin(), -az.cos() * el.cos());
+
+        let t = turbidity;
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        // Zenith values, from the angle between the zenith and the sun.
+        let theta = PI / 2.0 - el;
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta);
+        let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let polynomial = |coefficients: [[Float; 4]; 3]| {
+            let powers = [theta.powi(3), theta.powi(2), theta, 1.0];
+            let row =
+                |row: [Float; 4]| -> Float { row.iter().zip(&powers).map(|(c, p)| c * p).sum() };
+            t * t * row(coefficients[0]) + t * row(coefficients[1]) + row(coefficients[2])
+        };
+        let x = polynomial([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let y = polynomial([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+        let mut zenith = [luminance.max(0.0), x, y];
+        for (zenith, coefficients) in zenith.iter_mut().zip(&perez) {
+            *zenith /= perez_function(coefficients, 1.0, theta.cos());
+        }
+
+        // Light of the sun thinned by Rayleigh scattering and aerosols along
+        // the relative air mass of Kasten and Young.
+        let sun_degrees = 90.0 - elevation;
+        let air_mass = 1.0 / (el.sin() + 0.15 * (93.885 - sun_degrees).powf(-1.253));
+        let beta = 0.04608 * t - 0.04586;
+        let transmittance = |wavelength: Float| {
+            let rayleigh = 0.008735 * wavelength.powf(-4.08);
+            let aerosols = beta * wavelength.powf(-1.3);
+            (-(rayleigh + aerosols) * air_mass).exp()
+        };
+        let sun_cos_max = SUN_RADIUS.cos();
+        let solid_angle = 2.0 * PI * (1.0 - sun_cos_max);
+        let sun_radiance = Vec3::new(
+            transmittance(WAVELENGTHS[0]),
+            transmittance(WAVELENGTHS[1]),
+            transmittance(WAVELENGTHS[2]),
+        ) * (SUN_IRRADIANCE / solid_angle);
+
+        PhysicalSky {
+            sun_elevation: elevation,
+            sun_azimuth: azimuth,
+            turbidity,
+            sun_direction,
+            perez,
+            zenith,
+            sun_radiance,
+            sun_cos_max,
+        }
+    }
+
+    /// Sun elevation and azimuth, in degrees, and turbidity.
+    pub fn parameters(&self) -> (Float, Float, Float) {
+        (self.sun_elevation, self.sun_azimuth, self.turbidity)
+    }
+
+    pub fn sun_direction(&self) -> Vec3 {
+        self.sun_direction
+    }
+
+    /// Radiance of the sky arriving from `direction`, without the sun.
+    pub fn sky_radiance(&self, direction: Vec3) -> Vec3 {
+        let direction = direction.unit();
+        // Below the horizon, the sky keeps its color at the horizon.
+        let cos_theta = direction.y.max(0.01);
+        let cos_gamma = clamp(direction.dot(self.sun_direction), -1.0, 1.0);
+
+        let mut values = [0.0; 3];
+        for (index, value) in values.iter_mut().enumerate() {
+            *value = self.zenith[index] * perez_function(&self.perez[index], cos_theta, cos_gamma);
+        }
+        let [luminance, x, y] = values;
+
+        // xyY to linear sRGB.
+        let luminance = luminance * LUMINANCE_SCALE;
+        let big_x = x / y * luminance;
+        let big_z = (1.0 - x - y) / y * luminance;
+        Vec3::new(
+            (3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z).max(0.0),
+            (-0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z).max(0.0),
+            (0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z).max(0.0),
+        )
+    }
+
+    /// Radiance arriving from `direction`, the sun included.
+    pub fn radiance(&self, direction: Vec3) -> Vec3 {
+        let sky = self.sky_radiance(direction);
+        if direction.unit().dot(self.sun_direction) >= self.sun_cos_max {
+            sky + self.sun_radiance
+        } else {
+            sky
+        }
+    }
+
+    /// Direction uniformly sampled inside the sun disk.
+    pub fn sample_sun(&self, (r1, r2): (Float, Float)) -> Vec3 {
+        let z = 1.0 + r2 * (self.sun_cos_max - 1.0);
+        let phi = 2.0 * PI * r1;
+        let sin_theta = Float::sqrt(1.0 - z * z);
+
+        let uvw = Onb::from_w(self.sun_direction);
+        uvw.local(Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z))
+    }
+
+    /// Density of `sample_sun` returning `direction`.
+    pub fn sun_pdf(&self, direction: Vec3) -> Float {
+        if direction.unit().dot(self.sun_direction) >= self.sun_cos_max {
+            1.0 / (2.0 * PI * (1.0 - self.sun_cos_max))
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Perez et al. sky distribution at `cos_theta` from the zenith and
+/// `cos_gamma` from the sun.
+fn perez_function(coefficients: &[Float; 5], cos_theta: Float, cos_gamma: Float) -> Float {
+    let [a, b, c, d, e] = *coefficients;
+    let gamma = cos_gamma.acos();
+    (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+}
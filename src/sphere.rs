@@ -0,0 +1,392 @@
+use crate::hitable::*;
+use crate::material::MaterialId;
+use crate::maths::*;
+use crate::ray::Ray;
+
+/// How texture coordinates are laid over a sphere, see
+/// `Scene::set_uv_projection`. Projections other than `Spherical` are
+/// centered on the sphere and span its diameter, so textures keep their
+/// place as it moves.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UvProjection {
+    /// Longitude and latitude, see `Sphere::uv_at`.
+    #[default]
+    Spherical,
+    /// Around the y axis like the label of a can, `v` following the height.
+    Cylindrical,
+    /// Straight along -z like a slide projector, the back getting the mirror
+    /// image of the front.
+    Planar,
+    /// Along the x, y or z axis, whichever the surface faces most, each of
+    /// the six sides showing the whole texture.
+    Box,
+}
+
+/// Texture coordinates at a point of a surface and how they run along it.
+#[derive(Clone, Copy, Debug)]
+pub struct UvFrame {
+    pub uv: (Float, Float),
+    /// Unit tangent and bitangent, along increasing `u` and `v` in the plane
+    /// of the surface.
+    pub tangents: (Vec3, Vec3),
+    /// Distances along the surface covered over a unit change of `u` and `v`.
+    pub lengths: (Float, Float),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Sphere {
+    pub position: Vec3,
+    pub radius: Float,
+
+    pub material: MaterialId,
+    /// Whether rays only hit the outside of the sphere and go through it from
+    /// the inside, like a room whose walls a camera outside sees through.
+    /// Only the near root of the intersection test is then checked.
+    pub cull_backfaces: bool,
+}
+
+impl Sphere {
+    pub fn new(position: Vec3, radius: Float, material: MaterialId) -> Self {
+        Sphere {
+            position,
+            radius,
+            material,
+            cull_backfaces: false,
+        }
+    }
+
+    /// Point and outward normal at texture coordinates (`u`, `v`). `u` goes
+    /// around the y axis starting from -x, `v` from the bottom to the top.
+    pub fn surface_at(&self, u: Float, v: Float) -> (Vec3, Vec3) {
+        let theta = v * consts::PI;
+        let phi = (u - 0.5) * 2.0 * consts::PI;
+        let normal = Vec3::new(
+            theta.sin() * phi.cos(),
+            -theta.cos(),
+            -theta.sin() * phi.sin(),
+        );
+
+        (self.position + normal * self.radius, normal)
+    }
+
+    /// Texture coordinates of `point` on the surface, as `surface_at` takes.
+    pub fn uv_at(&self, point: Vec3) -> (Float, Float) {
+        let normal = (point - self.position) / self.radius;
+        let theta = clamp(-normal.y, -1.0, 1.0).acos();
+        let phi = (-normal.z).atan2(normal.x);
+        (phi / (2.0 * consts::PI) + 0.5, theta / consts::PI)
+    }
+
+    /// Unit tangent and bitangent at texture coordinates (`u`, `v`), along
+    /// increasing `u` and `v`. With the outward normal, they make a right
+    /// handed frame.
+    pub fn tangents_at(&self, u: Float, v: Float) -> (Vec3, Vec3) {
+        let theta = v * consts::PI;
+        let phi = (u - 0.5) * 2.0 * consts::PI;
+        let tangent = Vec3::new(-phi.sin(), 0.0, -phi.cos());
+        let bitangent = Vec3::new(
+            theta.cos() * phi.cos(),
+            theta.sin(),
+            -theta.cos() * phi.sin(),
+        );
+        (tangent, bitangent)
+    }
+
+    /// Texture coordinates of `point` on the surface following `projection`,
+    /// with the frame normal and bump maps bend normals in.
+    pub fn uv_frame(&self, point: Vec3, projection: UvProjection) -> UvFrame {
+        let normal = ((point - self.position) / self.radius).unit();
+        self.projected_uv_frame(point, normal, projection)
+    }
+
+    /// Texture coordinates of `point`, inside the sphere on a surface facing
+    /// `normal`, projected from the sphere following `projection`, like
+    /// those of the meshes it bounds. The frame lies in that surface.
+    pub fn projected_uv_frame(
+        &self,
+        point: Vec3,
+        normal: Vec3,
+        projection: UvProjection,
+    ) -> UvFrame {
+        let radius = self.radius.abs();
+        let offset = (point - self.position) / self.radius;
+        let direction = if offset.length_squared() > 0.0 {
+            offset.unit()
+        } else {
+            normal
+        };
+        let (u, v) = self.uv_at(self.position + direction * self.radius);
+        // The tangents of the sphere, brought into the surface.
+        let onto_surface = |vector: Vec3| {
+            let along = vector - normal * normal.dot(vector);
+            if along.length_squared() < 1e-12 {
+                Onb::from_w(normal).u
+            } else {
+                along.unit()
+            }
+        };
+        let (tangent, bitangent) = self.tangents_at(u, v);
+        let (tangent, bitangent) = (onto_surface(tangent), onto_surface(bitangent));
+
+        // Coordinate going from 0 to 1 along `axis` over the diameter, its
+        // direction on the surface and the distance covered along it.
+        let linear = |axis: Vec3| {
+            let coordinate = (axis.dot(offset) + 1.0) / 2.0;
+            let along = axis - normal * normal.dot(axis);
+            if along.length_squared() < 1e-12 {
+                // The surface faces the axis, which doesn't change along it.
+                return (coordinate, Onb::from_w(normal).u, Float::INFINITY);
+            }
+            let along = along.unit();
+            (coordinate, along, 2.0 * radius / axis.dot(along))
+        };
+        let project = |u_axis: Vec3, v_axis: Vec3| {
+            let (u, tangent, u_length) = linear(u_axis);
+            let (v, bitangent, v_length) = linear(v_axis);
+            UvFrame {
+                uv: (u, v),
+                tangents: (tangent, bitangent),
+                lengths: (u_length, v_length),
+            }
+        };
+        let (x, y, z) = (
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+
+        // Around the sphere, `u` covers circles narrowing towards the poles.
+        let around = 2.0 * consts::PI * radius * (v * consts::PI).sin();
+        match projection {
+            UvProjection::Spherical => UvFrame {
+                uv: (u, v),
+                tangents: (tangent, bitangent),
+                lengths: (around, consts::PI * radius),
+            },
+            UvProjection::Cylindrical => {
+                let (v, bitangent, v_length) = linear(y);
+                UvFrame {
+                    uv: (u, v),
+                    tangents: (tangent, bitangent),
+                    lengths: (around, v_length),
+                }
+            }
+            UvProjection::Planar => project(x, y),
+            UvProjection::Box => {
+                // Faces are seen from outside, their u, v and normal making a
+                // right handed frame like the other projections.
+                let (ax, ay, az) = (normal.x.abs(), normal.y.abs(), normal.z.abs());
+                if ax >= ay && ax >= az {
+                    project(if normal.x > 0.0 { -z } else { z }, y)
+                } else if ay >= az {
+                    project(x, if normal.y > 0.0 { -z } else { z })
+                } else {
+                    project(if normal.z > 0.0 { x } else { -x }, y)
+                }
+            }
+        }
+    }
+
+    /// Copy of the sphere moved by `transform`. Spheres stay round, so the
+    /// radius follows the average scale of the transform.
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        Sphere {
+            position: transform.transform_point(self.position),
+            radius: self.radius * transform.determinant3().abs().cbrt(),
+            material: self.material,
+            cull_backfaces: self.cull_backfaces,
+        }
+    }
+}
+
+impl Hitable for Sphere {
+    fn scale(&mut self, factor: Float) {
+        self.position = self.position * factor;
+        self.radius *= factor;
+    }
+
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        let oc = ray.origin - self.position;
+        let a = ray.dir.dot(ray.dir);
+        let half_b = oc.dot(ray.dir);
+        let c = oc.dot(oc) - self.radius * self.radius;
+        // Rays starting inside only hit the back of the surface.
+        if self.cull_backfaces && c <= 0.0 {
+            return None;
+        }
+
+        // The discriminant comes from the distance between the center and the
+        // line of the ray, rather than as a difference of large squares, and
+        // the roots avoid subtracting close values, so hits stay precise far
+        // from the sphere and on large spheres (Haines et al. 2019).
+        let to_line = oc - ray.dir * (half_b / a);
+        let discriminant = a * (self.radius * self.radius - to_line.length_squared());
+
+        if discriminant < 0.0 {
+            None
+        } else {
+            let root = Float::sqrt(discriminant);
+            let q = if half_b > 0.0 {
+                -half_b - root
+            } else {
+                -half_b + root
+            };
+            if q == 0.0 {
+                return None;
+            }
+            let (t1, t2) = (c / q, q / a);
+            let (t1, t2) = (t1.min(t2), t1.max(t2));
+
+            let t = if t1 < t_max && t1 > t_min {
+                t1
+            } else if self.cull_backfaces {
+                return None;
+            } else if t2 < t_max && t2 > t_min {
+                t2
+            } else {
+                return None;
+            };
+
+            let position = ray.at(t);
+            let outward_normal = position - self.position;
+
+            Some(HitRecord::new(
+                ray,
+                position,
+                outward_normal,
+                t,
+                self.material,
+            ))
+        }
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> Float {
+        let distance_squared = (self.position - origin).length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return 1.0 / (4.0 * consts::PI);
+        }
+
+        if self
+            .hit(&Ray::new(origin, direction), 0.0001, Float::INFINITY)
+            .is_none()
+        {
+            return 0.0;
+        }
+
+        let cos_theta_max = Float::sqrt(1.0 - self.radius * self.radius / distance_squared);
+        let solid_angle = 2.0 * consts::PI * (1.0 - cos_theta_max);
+
+        1.0 / solid_angle
+    }
+
+    fn sample_direction_towards(&self, origin: Vec3, (r1, r2): (Float, Float)) -> Vec3 {
+        let direction = self.position - origin;
+        let distance_squared = direction.length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return sample_unit_sphere((r1, r2));
+        }
+
+        // Uniformly sample the cone of directions subtended by the sphere.
+        let cos_theta_max = Float::sqrt(1.0 - self.radius * self.radius / distance_squared);
+        let z = 1.0 + r2 * (cos_theta_max - 1.0);
+        let phi = 2.0 * consts::PI * r1;
+        let sin_theta = Float::sqrt(1.0 - z * z);
+
+        let uvw = Onb::from_w(direction);
+        uvw.local(Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z))
+    }
+}
+
+/// Number of spheres in a `SpherePacket`.
+pub const PACKET_WIDTH: usize = 4;
+
+/// Up to `PACKET_WIDTH` spheres laid out field by field, so one ray is tested
+/// against all of them with the same instructions applied to every lane, which
+/// the compiler turns into SIMD arithmetic.
+#[derive(Clone, Copy, Debug)]
+pub struct SpherePacket {
+    x: [Float; PACKET_WIDTH],
+    y: [Float; PACKET_WIDTH],
+    z: [Float; PACKET_WIDTH],
+    radius: [Float; PACKET_WIDTH],
+    cull_backfaces: [bool; PACKET_WIDTH],
+    len: usize,
+}
+
+impl SpherePacket {
+    /// Packet of the first `PACKET_WIDTH` spheres of `spheres`.
+    pub fn new(spheres: &[Sphere]) -> Self {
+        let mut packet = SpherePacket {
+            x: [0.0; PACKET_WIDTH],
+            y: [0.0; PACKET_WIDTH],
+            z: [0.0; PACKET_WIDTH],
+            radius: [0.0; PACKET_WIDTH],
+            cull_backfaces: [false; PACKET_WIDTH],
+            len: spheres.len().min(PACKET_WIDTH),
+        };
+        for (lane, sphere) in spheres.iter().take(PACKET_WIDTH).enumerate() {
+            packet.x[lane] = sphere.position.x;
+            packet.y[lane] = sphere.position.y;
+            packet.z[lane] = sphere.position.z;
+            packet.radius[lane] = sphere.radius;
+            packet.cull_backfaces[lane] = sphere.cull_backfaces;
+        }
+        packet
+    }
+
+    /// Ray parameter of the closest hit of every sphere in (`t_min`,
+    /// `t_max`), infinite for the spheres missed and the unused lanes. The
+    /// arithmetic is that of `Sphere::hit`, so both find the same hits.
+    pub fn hit_distances(&self, ray: &Ray, t_min: Float, t_max: Float) -> [Float; PACKET_WIDTH] {
+        let (origin, dir) = (ray.origin, ray.dir);
+        let a = dir.dot(dir);
+        let mut distances = [Float::INFINITY; PACKET_WIDTH];
+
+        // Most spheres are missed, so first find which lines cross them, for
+        // every lane at once.
+        let mut half_b = [0.0; PACKET_WIDTH];
+        let mut c = [0.0; PACKET_WIDTH];
+        let mut discriminant = [0.0; PACKET_WIDTH];
+        for lane in 0..PACKET_WIDTH {
+            let ocx = origin.x - self.x[lane];
+            let ocy = origin.y - self.y[lane];
+            let ocz = origin.z - self.z[lane];
+            let radius_squared = self.radius[lane] * self.radius[lane];
+            half_b[lane] = ocx * dir.x + ocy * dir.y + ocz * dir.z;
+            c[lane] = ocx * ocx + ocy * ocy + ocz * ocz - radius_squared;
+
+            let scale = half_b[lane] / a;
+            let to_line_x = ocx - dir.x * scale;
+            let to_line_y = ocy - dir.y * scale;
+            let to_line_z = ocz - dir.z * scale;
+            let to_line_squared =
+                to_line_x * to_line_x + to_line_y * to_line_y + to_line_z * to_line_z;
+            discriminant[lane] = a * (radius_squared - to_line_squared);
+        }
+
+        for lane in 0..self.len {
+            if discriminant[lane] < 0.0 || (self.cull_backfaces[lane] && c[lane] <= 0.0) {
+                continue;
+            }
+            let root = Float::sqrt(discriminant[lane]);
+            let q = if half_b[lane] > 0.0 {
+                -half_b[lane] - root
+            } else {
+                -half_b[lane] + root
+            };
+            if q == 0.0 {
+                continue;
+            }
+            let (t1, t2) = (c[lane] / q, q / a);
+            let (t1, t2) = (t1.min(t2), t1.max(t2));
+
+            if t1 < t_max && t1 > t_min {
+                distances[lane] = t1;
+            } else if !self.cull_backfaces[lane] && t2 < t_max && t2 > t_min {
+                distances[lane] = t2;
+            }
+        }
+
+        distances
+    }
+}
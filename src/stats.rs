@@ -0,0 +1,64 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters of one object, gathered while rendering.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ObjectStats {
+    /// Intersection tests against the object.
+    pub tests: u64,
+    /// Times the object was the closest hit of a ray.
+    pub hits: u64,
+    /// Time spent intersecting the object.
+    pub nanoseconds: u64,
+}
+
+impl ObjectStats {
+    pub fn add(&mut self, other: &ObjectStats) {
+        self.tests += other.tests;
+        self.hits += other.hits;
+        self.nanoseconds += other.nanoseconds;
+    }
+}
+
+/// Per object counters shared by the render threads.
+#[derive(Default)]
+pub struct StatsCounters {
+    tests: Vec<AtomicU64>,
+    hits: Vec<AtomicU64>,
+    nanoseconds: Vec<AtomicU64>,
+}
+
+impl StatsCounters {
+    pub fn new(objects: usize) -> Self {
+        let mut counters = StatsCounters::default();
+        for _ in 0..objects {
+            counters.push();
+        }
+        counters
+    }
+
+    /// Adds counters for one more object.
+    pub fn push(&mut self) {
+        self.tests.push(AtomicU64::new(0));
+        self.hits.push(AtomicU64::new(0));
+        self.nanoseconds.push(AtomicU64::new(0));
+    }
+
+    pub fn record_test(&self, object: usize, nanoseconds: u64) {
+        self.tests[object].fetch_add(1, Ordering::Relaxed);
+        self.nanoseconds[object].fetch_add(nanoseconds, Ordering::Relaxed);
+    }
+
+    pub fn record_hit(&self, object: usize) {
+        self.hits[object].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> Vec<ObjectStats> {
+        (0..self.tests.len())
+            .map(|object| ObjectStats {
+                tests: self.tests[object].load(Ordering::Relaxed),
+                hits: self.hits[object].load(Ordering::Relaxed),
+                nanoseconds: self.nanoseconds[object].load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
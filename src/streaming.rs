@@ -0,0 +1,214 @@
+use crate::bvh::Aabb;
+use crate::hitable::Hitable;
+use crate::material::MaterialId;
+use crate::maths::*;
+use crate::mesh::{load_mesh, Displacement, Mesh};
+use crate::scene::{ObjectId, Scene, Units};
+use crate::shape::Shape;
+
+use rayon::prelude::*;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread;
+
+/// Mesh of a scene file left to load in the background, see
+/// `load_scene_streamed`.
+#[derive(Clone, Debug)]
+pub struct StreamedMesh {
+    /// Object standing in for the mesh until it is loaded.
+    pub id: ObjectId,
+    /// File and line the mesh was given on, for errors.
+    pub location: String,
+    pub file: String,
+    /// Where the mesh goes, in the units of the scene file.
+    pub placement: Mat4,
+    pub smooth: bool,
+    pub displacement: Option<Displacement>,
+}
+
+/// What the loading threads send about a mesh: the box around it as soon as
+/// its file is read, then the mesh once its hierarchy is built.
+enum Arrival {
+    Proxy(ObjectId, Mesh),
+    Mesh(ObjectId, Mesh),
+    Failed(std::io::Error),
+}
+
+/// Meshes loading on background threads, each standing in the scene as an
+/// object rays can't hit, then as its bounding box, and then as itself, so
+/// renders can start before every mesh is in and get closer to the scene
+/// as they come. Meshes are put in their place by `poll`, `wait` or
+/// `finish`, between renders.
+pub struct MeshStream {
+    arrivals: Receiver<Arrival>,
+    /// Units of the scene file, which the meshes are placed in.
+    units: Units,
+    total: usize,
+    pending: usize,
+}
+
+impl MeshStream {
+    /// Starts loading `meshes`, placed in `units`, on rayon's global pool,
+    /// apart from the threads of `Renderer`s.
+    pub fn start(meshes: Vec<StreamedMesh>, units: Units) -> Self {
+        let (sender, arrivals) = channel();
+        let total = meshes.len();
+        if total > 0 {
+            thread::spawn(move || {
+                meshes
+                    .into_par_iter()
+                    .for_each_with(sender, |sender, mesh| load(mesh, sender));
+            });
+        }
+        MeshStream {
+            arrivals,
+            units,
+            total,
+            pending: total,
+        }
+    }
+
+    /// Number of meshes streamed in all.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Number of meshes not in their place yet, standing as nothing or as
+    /// their box.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// Puts the meshes and boxes loaded so far in their place in `scene`,
+    /// without waiting for the others, and returns how many were.
+    ///
+    /// Meshes that couldn't be loaded are reported one per call, and are no
+    /// longer pending: they stay out of the scene.
+    pub fn poll(&mut self, scene: &mut Scene) -> std::io::Result<usize> {
+        let mut placed = 0;
+        while self.pending > 0 {
+            match self.arrivals.try_recv() {
+                Ok(arrival) => {
+                    self.place(arrival, scene)?;
+                    placed += 1;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Err(self.stopped()),
+            }
+        }
+        Ok(placed)
+    }
+
+    /// Waits for a mesh or box to load, unless none is pending, then puts
+    /// it and the others loaded since in their place like `poll`.
+    pub fn wait(&mut self, scene: &mut Scene) -> std::io::Result<usize> {
+        if self.pending == 0 {
+            return Ok(0);
+        }
+        match self.arrivals.recv() {
+            Ok(arrival) => self.place(arrival, scene)?,
+            Err(_) => return Err(self.stopped()),
+        }
+        Ok(1 + self.poll(scene)?)
+    }
+
+    /// Waits for all the meshes and puts them in their place, stopping at
+    /// the first that couldn't be loaded.
+    pub fn finish(mut self, scene: &mut Scene) -> std::io::Result<()> {
+        while self.pending > 0 {
+            self.wait(scene)?;
+        }
+        Ok(())
+    }
+
+    fn place(&mut self, arrival: Arrival, scene: &mut Scene) -> std::io::Result<()> {
+        let (id, mesh) = match arrival {
+            Arrival::Proxy(id, mesh) => (id, mesh),
+            Arrival::Mesh(id, mesh) => {
+                self.pending -= 1;
+                (id, mesh)
+            }
+            Arrival::Failed(error) => {
+                self.pending -= 1;
+                return Err(error);
+            }
+        };
+        let mut shape = Shape::Mesh(mesh);
+        // The scene may have been converted to other units since it was
+        // loaded.
+        let factor = self.units.meters_per_unit() / scene.units.meters_per_unit();
+        if factor != 1.0 {
+            shape.scale(factor);
+        }
+        scene.replace_shape(id, shape);
+        Ok(())
+    }
+
+    /// Error for loading threads that stopped before sending every mesh.
+    fn stopped(&mut self) -> std::io::Error {
+        self.pending = 0;
+        std::io::Error::other("the meshes stopped loading")
+    }
+}
+
+/// Loads `mesh`, sending its box then itself, or why it couldn't be loaded.
+fn load(mesh: StreamedMesh, sender: &mut Sender<Arrival>) {
+    let (positions, triangles) = match load_mesh(&mesh.file) {
+        Ok(triangles) => triangles,
+        Err(error) => {
+            let message = format!("{}: {}: {}", mesh.location, mesh.file, error);
+            let error = std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+            let _ = sender.send(Arrival::Failed(error));
+            return;
+        }
+    };
+    if let Some(proxy) = bounding_box(&positions) {
+        let proxy = proxy.transformed(&mesh.placement);
+        // The render went on without the stream when nobody listens.
+        if sender.send(Arrival::Proxy(mesh.id, proxy)).is_err() {
+            return;
+        }
+    }
+    let mut loaded = Mesh::new(positions, triangles, MaterialId(0));
+    if mesh.smooth {
+        loaded = loaded.smoothed();
+    }
+    let mut loaded = loaded.transformed(&mesh.placement);
+    if let Some(displacement) = &mesh.displacement {
+        loaded = loaded.displaced(displacement);
+    }
+    let _ = sender.send(Arrival::Mesh(mesh.id, loaded));
+}
+
+/// Box of 12 triangles around `positions`, if there are any.
+fn bounding_box(positions: &[Vec3]) -> Option<Mesh> {
+    if positions.is_empty() {
+        return None;
+    }
+    let Aabb { min, max } = positions
+        .iter()
+        .fold(Aabb::empty(), |bounds, &point| bounds.include(point));
+    let corners = (0..8)
+        .map(|corner| {
+            Vec3::new(
+                if corner & 1 == 0 { min.x } else { max.x },
+                if corner & 2 == 0 { min.y } else { max.y },
+                if corner & 4 == 0 { min.z } else { max.z },
+            )
+        })
+        .collect();
+    let faces = vec![
+        [0, 2, 1],
+        [1, 2, 3],
+        [4, 5, 6],
+        [5, 7, 6],
+        [0, 1, 4],
+        [1, 5, 4],
+        [2, 6, 3],
+        [3, 6, 7],
+        [0, 4, 2],
+        [2, 4, 6],
+        [1, 3, 5],
+        [3, 7, 5],
+    ];
+    Some(Mesh::new(corners, faces, MaterialId(0)))
+}
@@ -0,0 +1,306 @@
+use crate::environment::Environment;
+use crate::light::DeltaLight;
+use crate::material::{Fresnel, MaterialId, MaterialType};
+use crate::maths::*;
+use crate::ocean::Ocean;
+use crate::preview::PreviewEnvironment;
+use crate::principled::Principled;
+use crate::scene::Scene;
+use crate::sky::PhysicalSky;
+
+/// What a `Sweep` varies.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SweepParameter {
+    /// Fuzziness of metals and roughness of microfacet materials.
+    Roughness,
+    /// Refractive index of dielectrics, smooth or rough.
+    Ior,
+    /// Factor on the emission of lights, over the values of the scene.
+    Intensity,
+    SunElevation,
+    SunAzimuth,
+    Turbidity,
+    /// Time of the oceans of the scene, in seconds.
+    OceanTime,
+}
+
+/// Renders of one scene with a single parameter taking evenly spaced values,
+/// like the roughness of a material, for comparison strips.
+///
+/// Written `TARGET.PARAMETER=START:END:STEP`, where the target is either
+/// `material`, for every material the parameter applies to, the name of a
+/// sphere, for its material alone, `lights`, `sun` or `ocean`:
+///
+/// ```text
+/// material.roughness=0:1:0.1
+/// ball.ior=1:2.4:0.2
+/// lights.intensity=0.5:2:0.5
+/// sun.elevation=5:85:10
+/// ocean.time=0:2:0.04
+/// ```
+///
+/// Only materials and the environment change between values, so the scene
+/// and its BVH are built once.
+#[derive(Clone, Debug)]
+pub struct Sweep {
+    /// `None` for every material, or the name of a sphere.
+    target: Option<String>,
+    parameter: SweepParameter,
+    values: Vec<Float>,
+    /// Materials changed by the sweep, with their values in the scene.
+    materials: Vec<(MaterialId, MaterialType)>,
+    /// Materials with an ocean, for time sweeps.
+    oceans: Vec<(MaterialId, Ocean)>,
+    delta_lights: Vec<DeltaLight>,
+    /// Sun elevation, azimuth and turbidity of the scene.
+    sky: (Float, Float, Float),
+}
+
+fn invalid(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
+}
+
+impl Sweep {
+    pub fn parse(text: &str) -> std::io::Result<Self> {
+        let (name, range) = text
+            .split_once('=')
+            .ok_or_else(|| invalid(format!("'{}' has no range", text)))?;
+        let (target, parameter) = name
+            .split_once('.')
+            .ok_or_else(|| invalid(format!("'{}' is not TARGET.PARAMETER", name)))?;
+
+        let (target, parameter) = match (target, parameter) {
+            ("sun", "elevation") => (None, SweepParameter::SunElevation),
+            ("sun", "azimuth") => (None, SweepParameter::SunAzimuth),
+            ("sun", "turbidity") => (None, SweepParameter::Turbidity),
+            ("lights", "intensity") => (None, SweepParameter::Intensity),
+            ("ocean", "time") => (None, SweepParameter::OceanTime),
+            ("sun", _) | ("lights", _) | ("ocean", _) => {
+                return Err(invalid(format!("unknown parameter '{}'", name)))
+            }
+            (target, parameter) => {
+                let parameter = match parameter {
+                    "roughness" => SweepParameter::Roughness,
+                    "ior" => SweepParameter::Ior,
+                    _ => return Err(invalid(format!("unknown parameter '{}'", parameter))),
+                };
+                let target = match target {
+                    "material" => None,
+                    name => Some(name.to_string()),
+                };
+                (target, parameter)
+            }
+        };
+
+        let bounds: Vec<Float> = range
+            .split(':')
+            .map(|value| {
+                value
+                    .parse::<Float>()
+                    .ok()
+                    .filter(|value| value.is_finite())
+            })
+            .collect::<Option<_>>()
+            .ok_or_else(|| invalid(format!("invalid range '{}'", range)))?;
+        let (start, end, step) = match bounds[..] {
+            [start, end, step] => (start, end, step),
+            _ => return Err(invalid(format!("'{}' is not START:END:STEP", range))),
+        };
+        if step == 0.0 || (end - start) * step < 0.0 {
+            return Err(invalid(format!("'{}' never reaches its end", range)));
+        }
+
+        // The end is included even when rounding makes the last step fall
+        // slightly short.
+        let steps = ((end - start) / step + 1e-6).floor() as usize;
+        let values = (0..=steps)
+            .map(|index| start + step * index as Float)
+            .collect();
+
+        Ok(Sweep {
+            target,
+            parameter,
+            values,
+            materials: Vec::new(),
+            oceans: Vec::new(),
+            delta_lights: Vec::new(),
+            sky: (0.0, 0.0, 0.0),
+        })
+    }
+
+    /// Finds what the sweep changes in `scene`, keeping the values it starts
+    /// from, and fails when there is nothing to change.
+    pub fn bind(&mut self, scene: &Scene) -> std::io::Result<()> {
+        let candidates: Vec<MaterialId> = match &self.target {
+            None => (0..scene.materials.len()).map(MaterialId).collect(),
+            Some(name) => match scene.sphere_named(name) {
+                Some(sphere) => vec![scene.sphere(sphere).material],
+                None => return Err(invalid(format!("no sphere is named {}", name))),
+            },
+        };
+        self.materials = candidates
+            .into_iter()
+            .map(|material| (material, scene.material(material).clone()))
+            .filter(|(_, material)| {
+                matches!(
+                    (material, &self.parameter),
+                    (MaterialType::Metal { .. }, SweepParameter::Roughness)
+                        | (MaterialType::Microfacet { .. }, SweepParameter::Roughness)
+                        | (MaterialType::Principled(_), SweepParameter::Roughness)
+                        | (MaterialType::Dialectric { .. }, SweepParameter::Ior)
+                        | (MaterialType::Subsurface { .. }, SweepParameter::Ior)
+                        | (MaterialType::ThinGlass { .. }, SweepParameter::Ior)
+                        | (
+                            MaterialType::Microfacet {
+                                fresnel: Fresnel::Dielectric { .. },
+                                ..
+                            },
+                            SweepParameter::Ior
+                        )
+                        | (MaterialType::DiffuseLight { .. }, SweepParameter::Intensity)
+                )
+            })
+            .collect();
+        self.delta_lights = scene.delta_lights.clone();
+        if self.parameter == SweepParameter::OceanTime {
+            self.oceans = (0..scene.materials.len())
+                .map(MaterialId)
+                .filter_map(|material| Some((material, scene.ocean(material)?.clone())))
+                .collect();
+        }
+
+        match self.parameter {
+            SweepParameter::Roughness | SweepParameter::Ior if self.materials.is_empty() => {
+                Err(invalid(String::from("no material has the swept parameter")))
+            }
+            SweepParameter::Intensity
+                if self.materials.is_empty() && self.delta_lights.is_empty() =>
+            {
+                Err(invalid(String::from("the scene has no lights")))
+            }
+            SweepParameter::SunElevation
+            | SweepParameter::SunAzimuth
+            | SweepParameter::Turbidity => match scene.environment.sun() {
+                Some(sky) => {
+                    self.sky = sky.parameters();
+                    Ok(())
+                }
+                None => Err(invalid(String::from("the environment has no sun"))),
+            },
+            SweepParameter::OceanTime if self.oceans.is_empty() => {
+                Err(invalid(String::from("the scene has no ocean")))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn values(&self) -> &[Float] {
+        &self.values
+    }
+
+    /// Label of the parameter taking `value`, like `roughness=0.3`.
+    pub fn label(&self, value: Float) -> String {
+        let parameter = match self.parameter {
+            SweepParameter::Roughness => "roughness",
+            SweepParameter::Ior => "ior",
+            SweepParameter::Intensity => "intensity",
+            SweepParameter::SunElevation => "elevation",
+            SweepParameter::SunAzimuth => "azimuth",
+            SweepParameter::Turbidity => "turbidity",
+            SweepParameter::OceanTime => "time",
+        };
+        // Steps like 0.1 add up to values like 0.30000000000000004.
+        format!("{}={}", parameter, (value * 1e6).round() / 1e6)
+    }
+
+    /// Sets the parameter of `scene`, which the sweep was bound to, to `value`.
+    pub fn apply(&self, scene: &mut Scene, value: Float) {
+        for (id, material) in &self.materials {
+            let material = match material.clone() {
+                MaterialType::Metal { albedo, .. } => MaterialType::Metal {
+                    albedo,
+                    fuzziness: value,
+                },
+                MaterialType::Dialectric { .. } => MaterialType::Dialectric {
+                    refractive_index: value,
+                },
+                MaterialType::Subsurface { albedo, radius, .. } => MaterialType::Subsurface {
+                    albedo,
+                    radius,
+                    refractive_index: value,
+                },
+                MaterialType::ThinGlass { tint, .. } => MaterialType::ThinGlass {
+                    refractive_index: value,
+                    tint,
+                },
+                MaterialType::DiffuseLight { emit } => {
+                    MaterialType::DiffuseLight { emit: emit * value }
+                }
+                MaterialType::Microfacet { fresnel, .. }
+                    if self.parameter == SweepParameter::Roughness =>
+                {
+                    MaterialType::Microfacet {
+                        roughness: value,
+                        fresnel,
+                    }
+                }
+                MaterialType::Principled(principled) => MaterialType::Principled(Principled {
+                    roughness: value,
+                    ..principled
+                }),
+                MaterialType::Microfacet { roughness, .. } => MaterialType::Microfacet {
+                    roughness,
+                    fresnel: Fresnel::Dielectric {
+                        refractive_index: value,
+                    },
+                },
+                material => material,
+            };
+            scene.update_material(*id, material);
+        }
+
+        match self.parameter {
+            SweepParameter::Intensity => {
+                scene.delta_lights = self
+                    .delta_lights
+                    .iter()
+                    .map(|light| scaled_delta_light(light, value))
+                    .collect();
+            }
+            SweepParameter::SunElevation
+            | SweepParameter::SunAzimuth
+            | SweepParameter::Turbidity => {
+                let (mut elevation, mut azimuth, mut turbidity) = self.sky;
+                match self.parameter {
+                    SweepParameter::SunElevation => elevation = value,
+                    SweepParameter::SunAzimuth => azimuth = value,
+                    _ => turbidity = value,
+                }
+                scene.environment =
+                    Environment::Sky(PhysicalSky::new(elevation, azimuth, turbidity));
+                if let Some(columns) = scene.preview_environment().map(|preview| preview.columns())
+                {
+                    let preview = PreviewEnvironment::build(&scene.environment, columns);
+                    scene.set_preview_environment(Some(preview));
+                }
+            }
+            SweepParameter::OceanTime => {
+                for (material, ocean) in &self.oceans {
+                    scene.set_ocean(*material, Some(ocean.clone().with_time(value)));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn scaled_delta_light(light: &DeltaLight, factor: Float) -> DeltaLight {
+    let mut light = *light;
+    match &mut light {
+        DeltaLight::Point { intensity, .. } | DeltaLight::Spot { intensity, .. } => {
+            *intensity = *intensity * factor
+        }
+        DeltaLight::Directional { irradiance, .. } => *irradiance = *irradiance * factor,
+    }
+    light
+}
@@ -0,0 +1,210 @@
+use crate::content_hash::*;
+use crate::hdr::read_hdr;
+use crate::maths::*;
+use crate::netpbm::{read_pfm, read_ppm};
+use crate::png::decode_png;
+
+use std::path::Path;
+
+/// RGB image looked up by texture coordinates, `u` going right and `v` up,
+/// both from 0 to 1. It repeats along `u`, which goes around spheres.
+#[derive(Clone, Debug)]
+pub struct Texture {
+    width: usize,
+    height: usize,
+    /// Rows from top to bottom.
+    pixels: Vec<Vec3>,
+}
+
+impl Texture {
+    /// Texture of `width` x `height` `pixels`, rows from top to bottom.
+    pub fn new(pixels: Vec<Vec3>, width: usize, height: usize) -> Self {
+        assert_eq!(pixels.len(), width * height);
+        Texture {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Loads a `.ppm`, `.pfm` or `.hdr` image. 8-bit values are mapped to
+    /// [0, 1] as they are, without decoding gamma, as data textures like
+    /// normal maps are stored.
+    pub fn load(name: &str) -> std::io::Result<Self> {
+        let extension = Path::new(name)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("");
+        let (pixels, width, height) = match extension {
+            "ppm" => {
+                let (bytes, width, height) = read_ppm(name)?;
+                let pixels = bytes.iter().map(|&x| x as f32 / 255.0).collect();
+                (pixels, width, height)
+            }
+            "pfm" => read_pfm(name)?,
+            "hdr" => read_hdr(name)?,
+            _ => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "textures must be .ppm, .pfm or .hdr files",
+                ))
+            }
+        };
+        if width == 0 || height == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "empty texture",
+            ));
+        }
+
+        let pixels = pixels
+            .chunks(3)
+            .map(|pixel| Vec3::new(pixel[0] as Float, pixel[1] as Float, pixel[2] as Float))
+            .collect();
+        Ok(Texture::new(pixels, width as usize, height as usize))
+    }
+
+    /// Decodes a PNG image held in memory, like those of glTF files, leaving
+    /// out its alpha. `srgb` turns the sRGB values of color images into
+    /// linear ones, which data like normal maps are stored as already.
+    pub fn decode_png(bytes: &[u8], srgb: bool) -> std::io::Result<Self> {
+        let (pixels, width, height) = decode_png(bytes)?;
+        if width == 0 || height == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "empty texture",
+            ));
+        }
+        let decode = |value: f32| {
+            let value = value as Float;
+            if !srgb {
+                value
+            } else if value <= 0.04045 {
+                value / 12.92
+            } else {
+                ((value + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        let pixels = pixels
+            .chunks(4)
+            .map(|pixel| Vec3::new(decode(pixel[0]), decode(pixel[1]), decode(pixel[2])))
+            .collect();
+        Ok(Texture::new(pixels, width as usize, height as usize))
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Texel `x` from the left of row `y` from the top.
+    pub fn pixel(&self, x: usize, y: usize) -> Vec3 {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Bilinearly filtered value at (`u`, `v`).
+    pub fn lookup(&self, u: Float, v: Float) -> Vec3 {
+        let x = (u - u.floor()) * self.width as Float - 0.5;
+        let y = (1.0 - clamp(v, 0.0, 1.0)) * self.height as Float - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+
+        let texel = |x: Float, y: Float| {
+            let x = (x as isize).rem_euclid(self.width as isize) as usize;
+            let y = (y.max(0.0) as usize).min(self.height - 1);
+            self.pixels[y * self.width + x]
+        };
+        let top = texel(x0, y0) * (1.0 - tx) + texel(x0 + 1.0, y0) * tx;
+        let bottom = texel(x0, y0 + 1.0) * (1.0 - tx) + texel(x0 + 1.0, y0 + 1.0) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+}
+
+impl ContentHash for Texture {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        hasher.write_u64(self.width as u64);
+        hasher.write_u64(self.height as u64);
+        self.pixels[..].content_hash(hasher);
+    }
+}
+
+/// Tangent space normal map, bending shading normals to fake small bumps
+/// without adding geometry.
+///
+/// Colors in [0, 1] map to normal components in [-1, 1], red along the
+/// direction of increasing `u`, green along increasing `v` and blue away from
+/// the surface, so the flat color is (0.5, 0.5, 1).
+#[derive(Clone, Debug)]
+pub struct NormalMap {
+    pub texture: Texture,
+    /// Factor on the tilt of the normals, 1 keeping them as stored.
+    pub strength: Float,
+}
+
+impl NormalMap {
+    /// Shading normal at (`u`, `v`) of a surface with unit `normal`, `tangent`
+    /// and `bitangent` along increasing `u` and `v`.
+    pub fn normal(&self, u: Float, v: Float, tangent: Vec3, bitangent: Vec3, normal: Vec3) -> Vec3 {
+        let color = self.texture.lookup(u, v);
+        let x = (2.0 * color.x - 1.0) * self.strength;
+        let y = (2.0 * color.y - 1.0) * self.strength;
+        let z = (2.0 * color.z - 1.0).max(0.0);
+        let bent = tangent * x + bitangent * y + normal * z;
+        if bent.length_squared() > 0.0 {
+            bent.unit()
+        } else {
+            normal
+        }
+    }
+}
+
+impl ContentHash for NormalMap {
+    fn content_hash(&self, hasher: &mut ContentHasher) {
+        self.texture.content_hash(hasher);
+        hasher.write_float(self.strength);
+    }
+}
+
+/// Height map bending shading normals as if the surface were pushed out by
+/// `height` times the luminance of `texture`, with the slopes found by finite
+/// differences over one texel. The surface itself doesn't move, so
+/// silhouettes and shadows stay smooth.
+#[derive(Clone, Debug)]
+pub struct BumpMap {
+    pub texture: Texture,
+    /// Displacement of white texels, in scene units.
+    pub height: Float,
+}
+
+impl BumpMap {
+    /// Height of the surface at (`u`, `v`), in scene units.
+    pub fn height_at(&self, u: Float, v: Float) -> Float {
+        luminance(self.texture.lookup(u, v)) * self.height
+    }
+
+    /// Shading normal at (`u`, `v`) of a surface with unit `normal`, `tangent`
+    /// and `bitangent` along increasing `u` and `v`, the surface moving by
+    /// `u_length` and `v_length` over a unit change of `u` and `v`.
+    pub fn normal(
+        &self,
+        (u, v): (Float, Float),
+        (tangent, bitangent, normal): (Vec3, Vec3, Vec3),
+        u_length: Float,
+        v_length: Float,
+    ) -> Vec3 {
+        let du = 1.0 / self.texture.width() as Float;
+        let dv = 1.0 / self.texture.height() as Float;
+        let slope_u = (self.height_at(u + du, v) - self.height_at(u - du, v)) / (2.0 * du);
+        let slope_v = (self.height_at(u, v + dv) - self.height_at(u, v - dv)) / (2.0 * dv);
+
+        // Cross product of the displaced derivatives, divided by both lengths.
+        // Lengths vanish at poles, where the slope along them means nothing.
+        let bent = normal
+            - tangent * (slope_u / u_length.max(Float::EPSILON))
+            - bitangent * (slope_v / v_length.max(Float::EPSILON));
+        bent.unit()
+    }
+}
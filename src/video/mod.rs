@@ -0,0 +1,140 @@
+use crate::maths::Float;
+
+use std::io::prelude::*;
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+/// Extensions of the video files frames can be encoded into.
+pub const VIDEO_EXTENSIONS: [&str; 4] = ["mp4", "mkv", "mov", "webm"];
+
+/// Whether `name` is a video file, going by its extension.
+pub fn is_video(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| VIDEO_EXTENSIONS.contains(&extension))
+}
+
+/// Arguments of ffmpeg reading frames of `width` x `height` 8-bit RGB
+/// pixels from its standard input, `fps` frames per second, and encoding
+/// them into `name`: VP9 for .webm files, H.264 for the others. Both are
+/// stored in 4:2:0, which needs an even width and height, so odd ones get a
+/// black column or row.
+pub fn ffmpeg_arguments(name: &str, width: u32, height: u32, fps: Float) -> Vec<String> {
+    let mut arguments: Vec<String> = [
+        "-hide_banner",
+        "-loglevel",
+        "error",
+        "-y",
+        "-f",
+        "rawvideo",
+        "-pix_fmt",
+        "rgb24",
+        "-s",
+    ]
+    .iter()
+    .map(|&argument| String::from(argument))
+    .collect();
+    arguments.push(format!("{}x{}", width, height));
+    arguments.push(String::from("-framerate"));
+    arguments.push(fps.to_string());
+    arguments.extend(
+        ["-i", "-", "-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2"]
+            .iter()
+            .map(|&argument| String::from(argument)),
+    );
+    let codec: &[&str] = if name.ends_with(".webm") {
+        &["-c:v", "libvpx-vp9", "-crf", "30", "-b:v", "0"]
+    } else {
+        &["-c:v", "libx264", "-crf", "18", "-preset", "slow"]
+    };
+    arguments.extend(codec.iter().map(|&argument| String::from(argument)));
+    arguments.extend(
+        ["-pix_fmt", "yuv420p", name]
+            .iter()
+            .map(|&argument| String::from(argument)),
+    );
+    arguments
+}
+
+/// Video written frame by frame, piped as raw pixels into an encoder
+/// running alongside, so animations don't leave an image per frame behind.
+pub struct VideoWriter {
+    encoder: Child,
+    input: ChildStdin,
+    frame_size: usize,
+    frames: usize,
+}
+
+impl VideoWriter {
+    /// Starts `program`, ffmpeg or a program taking the same arguments, to
+    /// encode frames of `width` x `height` pixels into `name` at `fps`
+    /// frames per second, see `ffmpeg_arguments`.
+    pub fn new(
+        program: &str,
+        name: &str,
+        width: u32,
+        height: u32,
+        fps: Float,
+    ) -> std::io::Result<Self> {
+        let mut encoder = Command::new(program)
+            .args(ffmpeg_arguments(name, width, height, fps))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .map_err(|error| {
+                std::io::Error::new(
+                    error.kind(),
+                    format!("could not start {}: {}", program, error),
+                )
+            })?;
+        let input = encoder
+            .stdin
+            .take()
+            .expect("the input of the encoder is piped");
+        Ok(VideoWriter {
+            encoder,
+            input,
+            frame_size: width as usize * height as usize * 3,
+            frames: 0,
+        })
+    }
+
+    /// Adds a frame of 8-bit RGB `pixels`, row-major.
+    pub fn write_frame(&mut self, pixels: &[u8]) -> std::io::Result<()> {
+        if pixels.len() != self.frame_size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "frames take {} bytes, not {}",
+                    self.frame_size,
+                    pixels.len()
+                ),
+            ));
+        }
+        self.input.write_all(pixels)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Number of frames written so far.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Ends the video, waiting for the encoder to finish the file.
+    pub fn finish(self) -> std::io::Result<()> {
+        let VideoWriter {
+            mut encoder, input, ..
+        } = self;
+        drop(input);
+        let status = encoder.wait()?;
+        if !status.success() {
+            return Err(std::io::Error::other(format!(
+                "the encoder failed ({})",
+                status
+            )));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,21 @@
+//! Conversions between alpha conventions.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+#[test]
+fn unpremultiplying_recovers_opaque_colors() {
+    let mut pixels: Vec<Float> = vec![
+        0.2, 0.1, 0.05, 0.5, // half covered
+        0.4, 0.3, 0.2, 1.0, // opaque
+        0.0, 0.0, 0.0, 0.0, // empty
+        0.1, 0.1, 0.1, 0.0, // light without coverage
+    ];
+    unpremultiply(&mut pixels);
+    let expected = [
+        0.4, 0.2, 0.1, 0.5, 0.4, 0.3, 0.2, 1.0, 0.0, 0.0, 0.0, 0.0, 0.1, 0.1, 0.1, 0.0,
+    ];
+    for (value, expected) in pixels.iter().zip(&expected) {
+        assert!((value - expected).abs() < 1e-6, "{:?}", pixels);
+    }
+}
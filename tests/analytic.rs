@@ -0,0 +1,167 @@
+//! Renders of scenes whose light has a closed form, within the noise of
+//! their samples, so changes to how paths are traced are checked against
+//! the right answer rather than against themselves.
+
+use raytracer::maths::consts::PI;
+use raytracer::maths::*;
+use raytracer::*;
+
+const SAMPLES: usize = 4000;
+
+/// Mean of the red channel of `SAMPLES` paths of `ray`, and its standard
+/// error.
+fn estimate(scene: &Scene, ray: &Ray) -> (Float, Float) {
+    let settings = RenderSettings::default();
+    let (mut sum, mut sum_squared) = (0.0, 0.0);
+    for _ in 0..SAMPLES {
+        let value = ray_color(ray, scene, &settings).0.x;
+        sum += value;
+        sum_squared += value * value;
+    }
+    let n = SAMPLES as Float;
+    let mean = sum / n;
+    let variance = ((sum_squared - mean * mean * n) / (n - 1.0)).max(0.0);
+    (mean, (variance / n).sqrt())
+}
+
+/// Checks that `ray` sees `expected`, within 4 standard errors of the
+/// estimate, plus a little for rounding.
+fn assert_estimates(scene: &Scene, ray: &Ray, expected: Float) {
+    let (mean, error) = estimate(scene, ray);
+    assert!(
+        (mean - expected).abs() <= 4.0 * error + 1e-3 * expected,
+        "{} != {} (standard error {})",
+        mean,
+        expected,
+        error
+    );
+}
+
+fn lambertian(scene: &mut Scene, albedo: Float) -> MaterialId {
+    scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(albedo, albedo, albedo),
+    })
+}
+
+fn dark_scene() -> Scene {
+    let mut scene = Scene::new(Units::Meters);
+    scene.environment = Environment::Constant(Vec3::new(0.0, 0.0, 0.0));
+    scene
+}
+
+#[test]
+fn furnace_spheres_reflect_their_albedo() {
+    // Light leaving a convex sphere never comes back to it, so under a
+    // uniform sky it reflects the sky times its albedo, once.
+    seed_random(61);
+    let mut scene = Scene::new(Units::Meters);
+    scene.environment = Environment::Constant(Vec3::new(2.0, 2.0, 2.0));
+    let material = lambertian(&mut scene, 0.3);
+    scene.add(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, material));
+    for &target in &[Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.6, 0.5, 0.0)] {
+        let origin = Vec3::new(0.0, 0.0, 5.0);
+        assert_estimates(&scene, &Ray::new(origin, target - origin), 0.6);
+    }
+}
+
+#[test]
+fn parallel_plates_reflect_the_emitting_one() {
+    // An emitting plane filling the sky of a facing one gives it an
+    // irradiance of pi times its radiance, which a Lambertian plane
+    // reflects times albedo / pi.
+    seed_random(62);
+    let mut scene = dark_scene();
+    let emit = scene.add_material(MaterialType::DiffuseLight {
+        emit: Vec3::new(3.0, 3.0, 3.0),
+    });
+    let floor = lambertian(&mut scene, 0.4);
+    scene.add_plane(Plane::new(
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, -1.0, 0.0),
+        emit,
+    ));
+    scene.add_plane(Plane::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        floor,
+    ));
+    let origin = Vec3::new(0.0, 0.5, 0.0);
+    assert_estimates(&scene, &Ray::new(origin, Vec3::new(0.3, -1.0, 0.2)), 1.2);
+    assert_estimates(&scene, &Ray::new(origin, Vec3::new(0.0, 1.0, 0.0)), 3.0);
+}
+
+#[test]
+fn sphere_lights_follow_their_solid_angle() {
+    // A sphere of radius r at distance d subtends sin^2 = (r / d)^2 of the
+    // projected hemisphere, whole above the horizon, so a plane below gets
+    // pi L (r / d)^2 cos(theta).
+    seed_random(63);
+    let mut scene = dark_scene();
+    let emit = scene.add_material(MaterialType::DiffuseLight {
+        emit: Vec3::new(5.0, 5.0, 5.0),
+    });
+    let floor = lambertian(&mut scene, 0.5);
+    let (center, radius) = (Vec3::new(0.0, 2.0, 0.0), 0.5);
+    scene.add_light(Sphere::new(center, radius, emit));
+    scene.add_plane(Plane::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        floor,
+    ));
+    for &x in &[0.0, 1.0, 2.5] {
+        let point = Vec3::new(x, 0.0, 0.0);
+        let to_light = center - point;
+        let distance = to_light.length();
+        let cos_theta = to_light.y / distance;
+        let expected = 0.5 * 5.0 * (radius / distance).powi(2) * cos_theta;
+        let origin = point + Vec3::new(0.0, 0.5, 0.5);
+        assert_estimates(&scene, &Ray::new(origin, point - origin), expected);
+    }
+}
+
+#[test]
+fn point_lights_light_planes_by_inverse_squares() {
+    // Irradiance I cos(theta) / d^2, reflected times albedo / pi.
+    let mut scene = dark_scene();
+    let floor = lambertian(&mut scene, 0.7);
+    scene.add_plane(Plane::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        floor,
+    ));
+    let light = Vec3::new(0.0, 1.5, 0.0);
+    scene.delta_lights.push(DeltaLight::Point {
+        position: light,
+        intensity: Vec3::new(10.0, 10.0, 10.0),
+    });
+    for &x in &[0.0, 0.7, 3.0] {
+        let point = Vec3::new(x, 0.0, 0.0);
+        let distance = (light - point).length();
+        let expected = 0.7 / PI * 10.0 * (1.5 / distance) / (distance * distance);
+        let ray = Ray::new(point + Vec3::new(0.2, 1.0, 0.0), Vec3::new(-0.2, -1.0, 0.0));
+        let value = ray_color(&ray, &scene, &RenderSettings::default()).0.x;
+        assert!((value - expected).abs() < 1e-4, "{} != {}", value, expected);
+    }
+}
+
+#[test]
+fn integrating_spheres_add_up_every_bounce() {
+    // Inside a Lambertian sphere of radius r around a point light, every
+    // point of the wall gets I / r^2 straight from the light and pi L from
+    // the rest of the wall, so L = albedo / pi (I / r^2 + pi L), which sums
+    // the bounces to L = albedo I / (pi r^2 (1 - albedo)).
+    seed_random(64);
+    let mut scene = dark_scene();
+    let albedo = 0.5;
+    let wall = lambertian(&mut scene, albedo);
+    scene.add(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 2.0, wall));
+    scene.delta_lights.push(DeltaLight::Point {
+        position: Vec3::new(0.0, 0.0, 0.0),
+        intensity: Vec3::new(4.0, 4.0, 4.0),
+    });
+    let expected = albedo * 4.0 / (PI * 4.0 * (1.0 - albedo));
+    let origin = Vec3::new(0.3, -0.2, 0.5);
+    for &direction in &[Vec3::new(1.0, 0.0, 0.0), Vec3::new(-0.3, 0.8, -0.5)] {
+        assert_estimates(&scene, &Ray::new(origin, direction), expected);
+    }
+}
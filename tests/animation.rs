@@ -0,0 +1,107 @@
+//! Keyframes of cameras and objects interpolated over time.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+use std::path::Path;
+
+fn pose(x: Float) -> Pose {
+    Pose {
+        offset: Vec3::new(x, 0.0, 0.0),
+        ..Pose::default()
+    }
+}
+
+fn track(keys: &[(Float, Float)], interpolation: Interpolation) -> Track<Pose> {
+    let mut track = Track::default();
+    // Out of order, as tracks sort their keyframes.
+    for &(time, x) in keys.iter().rev() {
+        track.insert(Keyframe {
+            time,
+            value: pose(x),
+            interpolation,
+        });
+    }
+    track
+}
+
+fn x_at(track: &Track<Pose>, time: Float) -> Float {
+    track.at(time).unwrap().offset.x
+}
+
+#[test]
+fn tracks_interpolate_between_keyframes() {
+    let keys = [(0.0, 0.0), (1.0, 2.0), (3.0, 3.0)];
+    let linear = track(&keys, Interpolation::Linear);
+    assert!((x_at(&linear, 0.5) - 1.0).abs() < 1e-4);
+    assert!((x_at(&linear, 2.0) - 2.5).abs() < 1e-4);
+    // Values hold outside the keyframes.
+    assert_eq!(x_at(&linear, -1.0), 0.0);
+    assert_eq!(x_at(&linear, 5.0), 3.0);
+
+    let step = track(&keys, Interpolation::Step);
+    assert_eq!(x_at(&step, 0.99), 0.0);
+    assert_eq!(x_at(&step, 1.0), 2.0);
+
+    // Smooth curves go through the keyframes without jumps or kinks.
+    let smooth = track(&keys, Interpolation::Smooth);
+    for &(time, x) in &keys {
+        assert!((x_at(&smooth, time) - x).abs() < 1e-4);
+    }
+    let slope = |time: Float| (x_at(&smooth, time + 1e-5) - x_at(&smooth, time - 1e-5)) / 2e-5;
+    assert!((x_at(&smooth, 1.0 - 1e-7) - 2.0).abs() < 1e-5);
+    assert!((slope(1.0 - 1e-3) - slope(1.0 + 1e-3)).abs() < 1e-2);
+    // Two keyframes make a straight line.
+    let two = track(&[(0.0, 0.0), (2.0, 4.0)], Interpolation::Smooth);
+    assert!((x_at(&two, 0.5) - 1.0).abs() < 1e-4);
+
+    assert!(Track::<Pose>::default().at(0.0).is_none());
+    assert_eq!(Interpolation::from_name("step"), Some(Interpolation::Step));
+}
+
+#[test]
+fn scene_files_animate_objects_and_cameras() {
+    let source = "sphere 0 -1000 0 1000 lambertian 0.5 0.5 0.5\n\
+                  sphere 0 1 0 1 metal 0.7 0.6 0.5 0 name ball\n\
+                  keyframe ball 0 0 0 0 linear\n\
+                  keyframe ball 2 4 0 0 scale 2\n\
+                  keyframe camera 0 10 2 0 0 0 0 fov 30\n\
+                  keyframe camera 1 0 2 10 0 0 0 linear\n";
+    let mut scene = parse_scene(source, "animation", Path::new("")).unwrap();
+    assert!((scene.animation.duration() - 2.0).abs() < 1e-4);
+
+    let ball = scene.sphere_named("ball").unwrap();
+    scene.animate(1.0, Some(0.5));
+    let sphere = *scene.sphere(ball);
+    assert!((sphere.position - Vec3::new(2.0, 1.0, 0.0)).length() < 1e-4);
+    assert!((sphere.radius - 1.5).abs() < 1e-4);
+    // The top of the ball was a unit to the left and a bit lower.
+    let top = scene.previous_position(ball, Vec3::new(2.0, 2.5, 0.0));
+    assert!((top - Vec3::new(1.0, 2.25, 0.0)).length() < 1e-4);
+
+    // Camera keyframes keep the field of view of the last one.
+    let view = scene.animation.camera.at(0.5).unwrap();
+    assert!((view.look_from - Vec3::new(5.0, 2.0, 5.0)).length() < 1e-4);
+    assert_eq!(view.vertical_fov, 30.0);
+
+    // Converting units moves the keyframes along.
+    let source = format!("units centimeters\n{}", source);
+    let mut scene = parse_scene(&source, "animation", Path::new("")).unwrap();
+    scene.convert_to(Units::Meters);
+    scene.animate(2.0, None);
+    let sphere = scene.sphere(ball);
+    assert!((sphere.position - Vec3::new(0.04, 0.01, 0.0)).length() < 1e-4);
+    let view = scene.animation.camera.at(1.0).unwrap();
+    assert!((view.look_from - Vec3::new(0.0, 0.02, 0.1)).length() < 1e-4);
+
+    for (line, message) in &[
+        ("keyframe nobody 0 0 0 0", "no object named 'nobody'"),
+        (
+            "keyframe camera 0 1 2 3 0 0 0 bouncy",
+            "unexpected 'bouncy'",
+        ),
+    ] {
+        let error = parse_scene(line, "animation", Path::new("")).err().unwrap();
+        assert!(error.to_string().contains(message), "{}", error);
+    }
+}
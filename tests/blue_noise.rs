@@ -0,0 +1,57 @@
+//! Distribution of the blue noise texture and of the dithered samplers.
+
+use raytracer::maths::Float;
+use raytracer::*;
+
+#[test]
+fn blue_noise_holds_every_rank_once() {
+    let texture = BlueNoise::generate(16, 3);
+    let pixels = texture.size() * texture.size();
+    let mut ranks: Vec<usize> = (0..pixels)
+        .map(|pixel| (texture.value(pixel % 16, pixel / 16) * pixels as Float) as usize)
+        .collect();
+    ranks.sort_unstable();
+    assert!(ranks.iter().enumerate().all(|(index, &rank)| index == rank));
+    assert_eq!(texture.value(3, 5), texture.value(3 + 16, 5 + 32));
+}
+
+#[test]
+fn blue_noise_has_no_low_frequencies() {
+    // Means over 4x4 blocks of uniform white noise have a variance of
+    // 1 / (12 * 16). Blue noise mostly cancels out over such small areas.
+    let texture = BlueNoise::shared();
+    let size = texture.size();
+    let mut variance = 0.0;
+    for y in 0..size {
+        for x in 0..size {
+            let mut sum = 0.0;
+            for dy in 0..4 {
+                for dx in 0..4 {
+                    sum += texture.value(x + dx, y + dy);
+                }
+            }
+            let mean = sum / 16.0 - 0.5;
+            variance += mean * mean / (size * size) as Float;
+        }
+    }
+    assert!(variance < 0.25 / (12.0 * 16.0), "{}", variance);
+}
+
+#[test]
+fn dithered_samples_cover_every_interval_over_a_tile() {
+    // The pixels of a tile shift the same value by every rank of the texture.
+    let size = BlueNoise::shared().size();
+    for &dimension in &[0, 5] {
+        let mut counts = [0; 16];
+        for y in 0..size {
+            for x in 0..size {
+                let mut sampler = SamplerType::Sobol.start_sample(7, 2, 4).dithered(x, y);
+                sampler.set_dimension(dimension);
+                let value = sampler.get_1d();
+                assert!((0.0..1.0).contains(&value));
+                counts[(value * 16.0) as usize] += 1;
+            }
+        }
+        assert!(counts.iter().all(|&count| count == size * size / 16));
+    }
+}
@@ -0,0 +1,96 @@
+//! Closest hits found through the BVH against testing every sphere.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+/// Closest hit of `ray` testing the spheres one by one.
+fn closest_hit(spheres: &[Sphere], ray: &Ray) -> Option<(usize, Float)> {
+    let mut closest = None;
+    let mut closest_t = Float::INFINITY;
+    for (index, sphere) in spheres.iter().enumerate() {
+        if let Some(hit) = sphere.hit(ray, 1e-4, closest_t) {
+            closest_t = hit.t;
+            closest = Some((index, hit.t));
+        }
+    }
+    closest
+}
+
+#[test]
+fn bvh_finds_the_closest_hit() {
+    seed_random(11);
+    let spheres: Vec<Sphere> = (0..200)
+        .map(|_| {
+            let center = Vec3::new(
+                random_between(-10.0, 10.0),
+                random_between(-10.0, 10.0),
+                random_between(-10.0, 10.0),
+            );
+            Sphere::new(center, random_between(0.05, 1.0), MaterialId(0))
+        })
+        .collect();
+
+    for &quality in &[BvhQuality::Median, BvhQuality::Sah] {
+        let bvh = Bvh::new(&spheres, quality);
+        for ray in 0..2000 {
+            let origin = Vec3::new(
+                random_between(-12.0, 12.0),
+                random_between(-12.0, 12.0),
+                random_between(-12.0, 12.0),
+            );
+            let mut direction = sample_unit_sphere((random_01(), random_01()));
+            // Axis aligned rays have infinite inverse directions.
+            if ray % 10 == 0 {
+                direction = Vec3::new(0.0, 0.0, if ray % 20 == 0 { 1.0 } else { -1.0 });
+            }
+            let ray = Ray::new(origin, direction);
+            assert_eq!(
+                bvh.hit(&ray, 1e-4, Float::INFINITY),
+                closest_hit(&spheres, &ray)
+            );
+        }
+    }
+}
+
+#[test]
+fn empty_bvh_is_never_hit() {
+    let bvh = Bvh::new(&[], BvhQuality::Sah);
+    let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+    assert_eq!(bvh.hit(&ray, 1e-4, Float::INFINITY), None);
+}
+
+#[test]
+fn ray_packets_match_single_rays() {
+    seed_random(12);
+    let spheres: Vec<Sphere> = (0..100)
+        .map(|_| {
+            let center = Vec3::new(
+                random_between(-5.0, 5.0),
+                random_between(-5.0, 5.0),
+                random_between(-20.0, -5.0),
+            );
+            Sphere::new(center, random_between(0.1, 1.0), MaterialId(0))
+        })
+        .collect();
+    let bvh = Bvh::new(&spheres, BvhQuality::Sah);
+
+    // Coherent rays from a common origin, some packets left partial.
+    for &len in &[RAY_PACKET_WIDTH, 5, 1] {
+        for _ in 0..200 {
+            let target = Vec3::new(random_between(-5.0, 5.0), random_between(-5.0, 5.0), -10.0);
+            let rays: Vec<Ray> = (0..len)
+                .map(|_| {
+                    let jitter =
+                        Vec3::new(random_between(-0.5, 0.5), random_between(-0.5, 0.5), 0.0);
+                    Ray::new(Vec3::new(0.0, 0.0, 0.0), target + jitter)
+                })
+                .collect();
+            let t_max: Vec<Float> = (0..len).map(|lane| 8.0 + 4.0 * lane as Float).collect();
+            let hits = bvh.hit_packet(&rays, 1e-4, &t_max);
+            for (lane, ray) in rays.iter().enumerate() {
+                assert_eq!(hits[lane], bvh.hit(ray, 1e-4, t_max[lane]));
+            }
+            assert!(hits[len..].iter().all(Option::is_none));
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Photographic exposure, turntables and navigation of cameras.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+fn assert_close(a: Float, b: Float) {
+    assert!((a - b).abs() <= 1e-4 * b.abs(), "{} != {}", a, b);
+}
+
+#[test]
+fn exposure_follows_photographic_stops() {
+    let base = Exposure {
+        iso: 100.0,
+        shutter: 1.0,
+        f_number: 1.0,
+    };
+    assert_close(base.ev100(), 0.0);
+    assert_close(base.scale(), 1.0 / 1.2);
+
+    // One stop more light from each of the three settings.
+    for exposure in &[
+        Exposure { iso: 200.0, ..base },
+        Exposure {
+            shutter: 2.0,
+            ..base
+        },
+        Exposure {
+            f_number: (0.5 as Float).sqrt(),
+            ..base
+        },
+    ] {
+        assert_close(exposure.ev100(), -1.0);
+        assert_close(exposure.scale(), 2.0 * base.scale());
+    }
+
+    // Sunny 16 is about EV 15.
+    assert!((Exposure::default().ev100() - 14.64).abs() < 0.01);
+}
+
+#[test]
+fn exposure_scales_rendered_images() {
+    let mut scene = Scene::new(Units::Meters);
+    let white = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.8, 0.8, 0.8),
+    });
+    scene.add(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, white));
+    scene.environment = Environment::Constant(Vec3::new(1000.0, 1000.0, 1000.0));
+
+    let settings = RenderSettings {
+        width: 8,
+        height: 6,
+        samples_per_pixel: 2,
+        threads: 1,
+        ..RenderSettings::default()
+    };
+    let camera = Camera::new(
+        Vec3::new(0.0, 0.0, 5.0),
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        40.0,
+        8.0 / 6.0,
+        0.0,
+        5.0,
+    );
+    let exposure = Exposure {
+        iso: 400.0,
+        shutter: 1.0 / 60.0,
+        f_number: 4.0,
+    };
+
+    let renderer = Renderer::new(settings).unwrap();
+    let raw = renderer.render(&scene, &camera);
+    let exposed = renderer.render(&scene, &camera.with_exposure(exposure));
+    for (raw, exposed) in raw.pixels().chunks(4).zip(exposed.pixels().chunks(4)) {
+        for channel in 0..3 {
+            assert_close(exposed[channel], raw[channel] * exposure.scale());
+        }
+        assert_eq!(exposed[3], raw[3]);
+    }
+}
+
+#[test]
+fn turntables_keep_the_distance_and_elevation() {
+    let view = CameraView {
+        look_from: Vec3::new(4.0, 3.0, 1.0),
+        look_at: Vec3::new(1.0, 1.0, 1.0),
+        up: Vec3::new(0.0, 2.0, 0.0),
+        vertical_fov: 30.0,
+    };
+    // A quarter turn counterclockwise seen from above takes +x to -z.
+    let quarter = view.orbited(90.0);
+    assert!((quarter.look_from - Vec3::new(1.0, 3.0, -2.0)).length() < 1e-4);
+    assert_eq!(quarter.vertical_fov, 30.0);
+    for &degrees in &[17.0, 180.0, 300.0] {
+        let turned = view.orbited(degrees);
+        let offset = turned.look_from - turned.look_at;
+        assert_close(offset.length(), (13.0 as Float).sqrt());
+        assert_close(offset.y, 2.0);
+    }
+    let around = view.orbited(360.0);
+    assert!((around.look_from - view.look_from).length() < 1e-4);
+}
+
+#[test]
+fn navigation_walks_and_orbits_the_view() {
+    let view = CameraView {
+        look_from: Vec3::new(0.0, 2.0, 10.0),
+        look_at: Vec3::new(0.0, 2.0, 0.0),
+        up: Vec3::new(0.0, 1.0, 0.0),
+        vertical_fov: 40.0,
+    };
+    // W steps a tenth of the way forward, D to the right, both points
+    // together.
+    let walked = Navigation::from_key('w').unwrap().apply(&view);
+    assert!((walked.look_from - Vec3::new(0.0, 2.0, 9.0)).length() < 1e-4);
+    assert!((walked.look_at - Vec3::new(0.0, 2.0, -1.0)).length() < 1e-4);
+    let walked = Navigation::from_key('D').unwrap().apply(&view);
+    assert!((walked.look_from - Vec3::new(1.0, 2.0, 10.0)).length() < 1e-4);
+    assert_eq!(Navigation::from_key('x'), None);
+
+    // I looks down from higher, at the same distance.
+    let tilted = Navigation::from_key('i').unwrap().apply(&view);
+    assert_close(
+        tilted.look_from.y - 2.0,
+        10.0 * (5.0 as Float).to_radians().sin(),
+    );
+    assert_close((tilted.look_from - tilted.look_at).length(), 10.0);
+    // Tilts stop short of looking along up.
+    let top = view.tilted(200.0);
+    let offset = top.look_from - top.look_at;
+    assert_close(
+        offset.y / offset.length(),
+        (89.0 as Float).to_radians().sin(),
+    );
+
+    // Dragging the mouse across the height of the image turns the camera
+    // by the field of view, the scene following the mouse.
+    let drag = Navigation::from_drag(300.0, 0.0, 300, &view);
+    match drag {
+        Navigation::Orbit { yaw, pitch } => {
+            assert_close(yaw, -40.0);
+            assert_eq!(pitch, 0.0);
+        }
+        _ => panic!("{:?}", drag),
+    }
+    let dragged = drag.apply(&view);
+    assert!(dragged.look_from.x < 0.0);
+}
@@ -0,0 +1,182 @@
+//! Procedural cloud layers.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+use std::path::Path;
+
+/// Layer from 10 to 20 fully covering the sky.
+fn overcast(mode: CloudMode) -> Clouds {
+    Clouds::new(10.0, 20.0, 1.0, 0.2, mode)
+}
+
+/// Density integrated along `ray` from 0 to 1, in many small steps.
+fn optical_depth(clouds: &Clouds, ray: &Ray) -> Float {
+    let steps = 20_000;
+    let step = 1.0 / steps as Float;
+    let length = ray.dir.length() * step;
+    (0..steps)
+        .map(|i| clouds.density_at(ray.at((i as Float + 0.5) * step)) * length)
+        .sum()
+}
+
+#[test]
+fn clouds_stay_inside_their_layer() {
+    let clouds = overcast(CloudMode::Medium);
+    for i in 0..200 {
+        let x = i as Float * 3.7;
+        let below = Vec3::new(x, 9.9, -x);
+        let above = Vec3::new(x, 20.1, x);
+        assert_eq!(clouds.density_at(below), 0.0);
+        assert_eq!(clouds.density_at(above), 0.0);
+        let inside = clouds.density_at(Vec3::new(x, 15.0, x * 0.5));
+        assert!((0.0..=clouds.density).contains(&inside), "{}", inside);
+    }
+
+    let clear = Clouds::new(10.0, 20.0, 0.0, 0.2, CloudMode::Medium);
+    assert_eq!(clear.density_at(Vec3::new(1.0, 15.0, 2.0)), 0.0);
+    let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+    assert_eq!(clear.transmittance(&ray, 0.0, 100.0), 1.0);
+}
+
+#[test]
+fn tracking_matches_the_optical_depth() {
+    let clouds = overcast(CloudMode::Medium);
+    // Slanted through the whole layer from below it.
+    let ray = Ray::new(Vec3::new(3.0, 0.0, -2.0), Vec3::new(7.0, 30.0, 4.0));
+    let expected = (-optical_depth(&clouds, &ray)).exp();
+    assert!(expected > 0.05 && expected < 0.95, "{}", expected);
+
+    seed_random(11);
+    let count = 40_000;
+    let ratio: Float = (0..count)
+        .map(|_| clouds.transmittance(&ray, 0.0, 1.0))
+        .sum::<Float>()
+        / count as Float;
+    let escaped = (0..count)
+        .filter(|_| clouds.sample_collision(&ray, 0.0, 1.0).is_none())
+        .count() as Float
+        / count as Float;
+    assert!((ratio - expected).abs() < 0.01, "{} != {}", ratio, expected);
+    assert!(
+        (escaped - expected).abs() < 0.01,
+        "{} != {}",
+        escaped,
+        expected
+    );
+
+    // Collisions only happen inside the layer.
+    for _ in 0..1000 {
+        if let Some(t) = clouds.sample_collision(&ray, 0.0, 1.0) {
+            let y = ray.at(t).y;
+            assert!((10.0..=20.0).contains(&y), "{}", y);
+        }
+    }
+}
+
+#[test]
+fn the_phase_function_is_normalized_and_sampled_exactly() {
+    let clouds = overcast(CloudMode::Medium);
+    let steps = 100_000;
+    // Integrated over the sphere by the cosine of the angle.
+    let integral: Float = (0..steps)
+        .map(|i| {
+            let cos = -1.0 + 2.0 * (i as Float + 0.5) / steps as Float;
+            clouds.phase(cos) * 2.0 * consts::PI * 2.0 / steps as Float
+        })
+        .sum();
+    assert!((integral - 1.0).abs() < 1e-3, "{}", integral);
+
+    seed_random(3);
+    let direction = Vec3::new(0.3, -0.2, 0.9).unit();
+    let count = 50_000;
+    let mean: Float = (0..count)
+        .map(|_| {
+            let sample = clouds.sample_phase(direction, (random_01(), random_01()));
+            assert!((sample.length() - 1.0).abs() < 1e-6);
+            sample.dot(direction)
+        })
+        .sum::<Float>()
+        / count as Float;
+    assert!((mean - clouds.anisotropy).abs() < 0.01, "{}", mean);
+}
+
+#[test]
+fn background_clouds_veil_the_sky_above() {
+    let clouds = overcast(CloudMode::Background);
+    let environment = Environment::Constant(Vec3::new(0.0, 0.0, 1.0));
+    let background = environment.radiance(Vec3::new(0.0, 1.0, 0.0));
+
+    // Looking down from under the layer misses it.
+    let down = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.2, -1.0, 0.0));
+    let seen = clouds.over_background(&down, background, &environment);
+    assert_eq!((seen.x, seen.y, seen.z), (0.0, 0.0, 1.0));
+
+    // Looking up through it, the blue of the sky is partly hidden by clouds
+    // lit by it alone, which scatter back less than they block.
+    let up = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.1, 1.0, 0.2));
+    let seen = clouds.over_background(&up, background, &environment);
+    assert!(seen.z > 0.0 && seen.z < 1.0, "{}", seen.z);
+    assert_eq!(seen.x, 0.0);
+}
+
+#[test]
+fn scene_files_add_clouds() {
+    let source = "units centimeters\n\
+                  environment sky 30 120 3\n\
+                  clouds 800 1400 0.4 0.05 medium size 3000 seed 7 anisotropy 0.8\n";
+    let mut scene = parse_scene(source, "clouds", Path::new("")).unwrap();
+    let clouds = scene.clouds.clone().unwrap();
+    assert_eq!(clouds.mode, CloudMode::Medium);
+    assert_eq!((clouds.bottom, clouds.top), (800.0, 1400.0));
+    assert_eq!(clouds.feature_size, 3000.0);
+    assert_eq!(clouds.seed, 7);
+    assert_eq!(clouds.anisotropy, 0.8);
+
+    scene.convert_to(Units::Meters);
+    let clouds = scene.clouds.unwrap();
+    assert!((clouds.top - 14.0).abs() < 1e-4);
+    assert!((clouds.density - 5.0).abs() < 1e-4);
+
+    let scene = parse_scene("clouds 10 20 0.5 0.1", "clouds", Path::new("")).unwrap();
+    assert_eq!(scene.clouds.unwrap().mode, CloudMode::Background);
+
+    for line in [
+        "clouds 20 10 0.5 0.1",
+        "clouds 10 20 1.5 0.1",
+        "clouds 10 20 0.5 -1",
+        "clouds 10 20 0.5 0.1 anisotropy 1",
+        "clouds 10 20 0.5 0.1 medium size 0",
+        "clouds 10 20 0.5 0.1 thick",
+    ] {
+        assert!(
+            parse_scene(line, "clouds", Path::new("")).is_err(),
+            "{}",
+            line
+        );
+    }
+}
+
+#[test]
+fn volume_limits_stop_paths_in_clouds() {
+    let mut scene = Scene::new(Units::Meters);
+    scene.environment = Environment::Constant(Vec3::new(1.0, 1.0, 1.0));
+    let mut clouds = Clouds::new(10.0, 20.0, 1.0, 2.0, CloudMode::Medium);
+    clouds.albedo = 1.0;
+    scene.clouds = Some(clouds);
+    let brightness = |volume| {
+        let mut settings = RenderSettings::default();
+        settings.bounce_limits.volume = volume;
+        let samples = 2000;
+        let mut sum = 0.0;
+        for sample in 0..samples {
+            seed_random(sample);
+            let ray = Ray::new(Vec3::new(0.0, 15.0, 0.0), Vec3::new(0.3, -1.0, 0.1));
+            sum += ray_color(&ray, &scene, &settings).0.x;
+        }
+        sum / samples as Float
+    };
+    // Light scattered many times through the layer is lost past the limit.
+    let (once, unlimited) = (brightness(1), brightness(i32::MAX));
+    assert!(once < unlimited - 0.2, "{} {}", once, unlimited);
+}
@@ -0,0 +1,51 @@
+//! Layout of contact sheets.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+/// Opaque `width` x `height` image of a single gray `value`.
+fn image(value: Float, width: usize, height: usize) -> Vec<Float> {
+    [value, value, value, 1.0].repeat(width * height)
+}
+
+#[test]
+fn contact_sheets_lay_out_labeled_cells() {
+    let (width, height) = (20, 10);
+    let mut sheet = ContactSheet::new(width, height);
+    for index in 0..5 {
+        sheet.add(&image(0.1 * (index + 1) as Float, width, height), "A=1");
+    }
+    assert_eq!(sheet.grid(), (3, 2));
+    assert_eq!(
+        ContactSheet::new(width, height).with_columns(5).grid(),
+        (1, 1)
+    );
+
+    let (sheet_width, sheet_height) = sheet.size();
+    let pixels = sheet.compose();
+    assert_eq!(pixels.len(), sheet_width * sheet_height * 4);
+    let pixel = |x: usize, y: usize| &pixels[(y * sheet_width + x) * 4..][..4];
+
+    // Every cell is copied whole, row after row.
+    for index in 0..5 {
+        let (x, y) = sheet.cell_position(index);
+        assert!(x + width <= sheet_width && y + height <= sheet_height);
+        let value = 0.1 * (index + 1) as Float;
+        assert_eq!(pixel(x, y), &[value, value, value, 1.0][..]);
+        assert_eq!(
+            pixel(x + width - 1, y + height - 1),
+            &[value, value, value, 1.0][..]
+        );
+    }
+    assert_ne!(sheet.cell_position(1).0, sheet.cell_position(0).0);
+    assert_eq!(sheet.cell_position(3).0, sheet.cell_position(0).0);
+    assert!(sheet.cell_position(3).1 > sheet.cell_position(0).1 + height);
+
+    // Labels are drawn in white just under their cell.
+    let (x, y) = sheet.cell_position(4);
+    let label_pixels = (y + height..y + height + 8)
+        .flat_map(|y| (x..x + width).map(move |x| (x, y)))
+        .filter(|&(x, y)| y < sheet_height && pixel(x, y) == [1.0, 1.0, 1.0, 1.0])
+        .count();
+    assert!(label_pixels > 10);
+}
@@ -0,0 +1,143 @@
+//! Render hashes and previews, and their round trip through image files.
+
+use raytracer::exr::*;
+use raytracer::maths::*;
+use raytracer::netpbm::*;
+use raytracer::png::*;
+use raytracer::*;
+
+fn scene() -> Scene {
+    let mut scene = Scene::new(Units::Meters);
+    let material = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    scene.add(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, material));
+    scene
+}
+
+fn camera() -> Camera {
+    Camera::new(
+        Vec3::new(13.0, 2.0, 3.0),
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        20.0,
+        1.5,
+        0.1,
+        10.0,
+    )
+}
+
+#[test]
+fn hashes_track_each_component() {
+    let settings = RenderSettings::default();
+    let hashes = RenderHashes::new(&scene(), &camera(), &settings);
+    assert_eq!(hashes, RenderHashes::new(&scene(), &camera(), &settings));
+
+    let threads = RenderSettings {
+        threads: 3,
+        ..settings
+    };
+    assert!(hashes
+        .changed(&RenderHashes::new(&scene(), &camera(), &threads))
+        .is_empty());
+
+    let seed = RenderSettings {
+        seed: 1,
+        ..settings
+    };
+    let changed = hashes.changed(&RenderHashes::new(&scene(), &camera(), &seed));
+    assert_eq!(changed, vec!["settings"]);
+
+    let mut moved = scene();
+    moved.spheres[0].radius = 2.0;
+    let changed = hashes.changed(&RenderHashes::new(&moved, &camera(), &settings));
+    assert_eq!(changed, vec!["geometry"]);
+
+    let mut recolored = scene();
+    recolored.materials[0] = MaterialType::Metal {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+        fuzziness: 0.0,
+    };
+    let changed = hashes.changed(&RenderHashes::new(&recolored, &camera(), &settings));
+    assert_eq!(changed, vec!["materials"]);
+
+    let fisheye = camera().with_projection(Projection::Fisheye { fov_degrees: 180.0 });
+    let changed = hashes.changed(&RenderHashes::new(&scene(), &fisheye, &settings));
+    assert_eq!(changed, vec!["camera"]);
+}
+
+#[test]
+fn hashes_round_trip_through_images() {
+    let hashes = RenderHashes::new(&scene(), &camera(), &RenderSettings::default());
+    let metadata = hashes.to_metadata();
+    let directory = std::env::temp_dir();
+    let name = |extension: &str| {
+        let file = format!("raytracer-hashes-{}.{}", std::process::id(), extension);
+        directory.join(file).to_string_lossy().into_owned()
+    };
+
+    let ppm = name("ppm");
+    create_ppm(&ppm, &[10, 20, 30, 40, 50, 60], 2, 1, &metadata).unwrap();
+    let stored = read_ppm_metadata(&ppm).unwrap();
+    assert_eq!(
+        read_ppm(&ppm).unwrap(),
+        (vec![10, 20, 30, 40, 50, 60], 2, 1)
+    );
+
+    let png = name("png");
+    create_png(
+        &png,
+        &[10, 20, 30, 40, 50, 60],
+        2,
+        1,
+        false,
+        &metadata,
+        None,
+    )
+    .unwrap();
+    assert_eq!(read_png_metadata(&png).unwrap(), stored);
+
+    let exr = name("exr");
+    create_exr(&exr, &[0.5; 6], 2, 1, false, &metadata, None).unwrap();
+    assert_eq!(read_exr_metadata(&exr).unwrap(), stored);
+
+    for file in &[ppm, png, exr] {
+        std::fs::remove_file(file).unwrap();
+    }
+    assert_eq!(stored, metadata);
+    assert_eq!(RenderHashes::from_metadata(&stored), Some(hashes));
+}
+
+#[test]
+fn previews_round_trip_through_images() {
+    let preview = Preview {
+        width: 2,
+        height: 1,
+        pixels: vec![255, 0, 0, 255, 0, 128, 255, 64],
+    };
+    let metadata = vec![(String::from("raytracer.seed"), String::from("7"))];
+    let directory = std::env::temp_dir();
+    let name = |extension: &str| {
+        let file = format!("raytracer-preview-{}.{}", std::process::id(), extension);
+        directory.join(file).to_string_lossy().into_owned()
+    };
+
+    let png = name("png");
+    let pixels = [10, 20, 30, 40, 50, 60];
+    create_png(&png, &pixels, 2, 1, false, &metadata, Some(&preview)).unwrap();
+    assert_eq!(read_png_preview(&png).unwrap(), Some(preview.clone()));
+    assert_eq!(read_png_metadata(&png).unwrap(), metadata);
+
+    let exr = name("exr");
+    create_exr(&exr, &[0.5; 6], 2, 1, false, &metadata, Some(&preview)).unwrap();
+    assert_eq!(read_exr_preview(&exr).unwrap(), Some(preview));
+    assert_eq!(read_exr_metadata(&exr).unwrap(), metadata);
+
+    // Files without one have none.
+    create_png(&png, &pixels, 2, 1, false, &metadata, None).unwrap();
+    assert_eq!(read_png_preview(&png).unwrap(), None);
+
+    for file in &[png, exr] {
+        std::fs::remove_file(file).unwrap();
+    }
+}
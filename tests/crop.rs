@@ -0,0 +1,90 @@
+//! Renders of a rectangle of the image, matching the whole render there.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+const WIDTH: usize = 40;
+const HEIGHT: usize = 24;
+
+fn scene() -> Scene {
+    let mut scene = Scene::new(Units::Meters);
+    let ground = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    let red = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.8, 0.2, 0.1),
+    });
+    scene.add(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground));
+    scene.add(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, red));
+    scene
+}
+
+fn render(filter: Filter, crop: Option<Crop>, transparent_background: bool) -> Vec<Float> {
+    let settings = RenderSettings {
+        width: WIDTH,
+        height: HEIGHT,
+        samples_per_pixel: 3,
+        max_depth: 4,
+        filter,
+        transparent_background,
+        crop,
+        seed: 5,
+        threads: 2,
+        ..RenderSettings::default()
+    };
+    let camera = Camera::new(
+        Vec3::new(0.0, 2.0, 6.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        40.0,
+        WIDTH as Float / HEIGHT as Float,
+        0.0,
+        6.0,
+    );
+    Renderer::new(settings)
+        .unwrap()
+        .render(&scene(), &camera)
+        .into_pixels()
+}
+
+#[test]
+fn crops_render_the_same_pixels_as_whole_images() {
+    let crop = Crop {
+        x: 13,
+        y: 5,
+        width: 19,
+        height: 11,
+    };
+    for &transparent in &[false, true] {
+        let filter = Filter::default();
+        let whole = render(filter, None, transparent);
+        let cropped = render(filter, Some(crop), transparent);
+        for j in 0..HEIGHT {
+            for i in 0..WIDTH {
+                let index = (j * WIDTH + i) * 4;
+                let pixel = &cropped[index..index + 4];
+                if crop.contains(i, j) {
+                    assert_eq!(pixel, &whole[index..index + 4], "({}, {})", i, j);
+                } else {
+                    let alpha = if transparent { 0.0 } else { 1.0 };
+                    assert_eq!(pixel, &[0.0, 0.0, 0.0, alpha][..], "({}, {})", i, j);
+                }
+            }
+        }
+    }
+
+    // Wider filters only change the pixels they reach past the border from,
+    // and the order tile films add up in.
+    let filter = Filter::Tent { radius: 1.0 };
+    let whole = render(filter, None, false);
+    let cropped = render(filter, Some(crop), false);
+    for j in crop.y + 1..crop.y + crop.height - 1 {
+        for i in crop.x + 1..crop.x + crop.width - 1 {
+            let index = (j * WIDTH + i) * 4;
+            for channel in 0..4 {
+                let (a, b) = (cropped[index + channel], whole[index + channel]);
+                assert!((a - b).abs() <= 1e-5 * b.abs().max(1.0), "{} != {}", a, b);
+            }
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! Curves for hair and wires, and the hair BSDF shading them.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+const EPSILON: Float = 1e-9;
+
+fn assert_close(a: Float, b: Float) {
+    assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+}
+
+/// Straight curve along x from -`half` to `half`, of `radius` all along.
+fn straight(half: Float, radius: Float) -> Curve {
+    let point = |x: Float| Vec3::new(x, 0.0, 0.0);
+    Curve::new(
+        [
+            point(-half),
+            point(-half / 3.0),
+            point(half / 3.0),
+            point(half),
+        ],
+        (radius, radius),
+    )
+}
+
+#[test]
+fn straight_curves_are_hit_like_capsules() {
+    let wire = Curves::new(vec![straight(2.0, 0.1)], MaterialId(0));
+    let down = |x: Float, z: Float| Ray::new(Vec3::new(x, 5.0, z), Vec3::new(0.0, -2.0, 0.0));
+
+    let hit = wire.hit(&down(0.5, 0.0), EPSILON, Float::INFINITY).unwrap();
+    assert_close(hit.t, 4.9 / 2.0);
+    assert_close(hit.normal.y, 1.0);
+    assert!(hit.front_face);
+    let tangent = hit.tangent.unwrap();
+    assert_close(tangent.x.abs(), 1.0);
+
+    // Off the axis, the surface is lower.
+    let side = wire
+        .hit(&down(-1.0, 0.06), EPSILON, Float::INFINITY)
+        .unwrap();
+    assert_close(side.position.y, Float::sqrt(0.01 - 0.0036));
+    assert_close(side.normal.z, 0.6);
+
+    assert!(wire
+        .hit(&down(0.0, 0.11), EPSILON, Float::INFINITY)
+        .is_none());
+    assert!(wire
+        .hit(&down(2.5, 0.0), EPSILON, Float::INFINITY)
+        .is_none());
+}
+
+#[test]
+fn rays_leaving_fibers_do_not_hit_them_again() {
+    let wire = Curves::new(vec![straight(2.0, 0.1)], MaterialId(0));
+    let ray = Ray::new(Vec3::new(0.3, 5.0, 0.02), Vec3::new(0.0, -1.0, 0.0));
+    let hit = wire.hit(&ray, EPSILON, Float::INFINITY).unwrap();
+
+    // Through the fiber, as light going through hair does, and back out.
+    let through = Ray::new(hit.position, Vec3::new(0.1, -1.0, 0.0));
+    assert!(wire.hit(&through, EPSILON, Float::INFINITY).is_none());
+    let back = Ray::new(hit.position, wire.normal_at(hit.position));
+    assert!(wire.hit(&back, EPSILON, Float::INFINITY).is_none());
+}
+
+#[test]
+fn catmull_rom_splines_go_through_their_points() {
+    let points = [
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(1.0, 1.0, 0.0),
+        Vec3::new(2.0, 0.0, 1.0),
+        Vec3::new(3.0, 2.0, 1.0),
+    ];
+    let curves = Curve::catmull_rom(&points, &[0.4, 0.3, 0.2, 0.1]);
+    assert_eq!(curves.len(), 3);
+    for (i, curve) in curves.iter().enumerate() {
+        assert!((curve.point(0.0) - points[i]).length() < 1e-6);
+        assert!((curve.point(1.0) - points[i + 1]).length() < 1e-6);
+        assert_close(curve.radius(0.5), 0.35 - 0.1 * i as Float);
+    }
+    // Smooth where curves meet.
+    for pair in curves.windows(2) {
+        let (before, after) = (pair[0].derivative(1.0), pair[1].derivative(0.0));
+        assert!((before - after).length() < 1e-6);
+    }
+}
+
+/// `.hair` file of two strands of three points, along x at y = 0 then 1.
+fn hair_file() -> Vec<u8> {
+    let mut bytes = b"HAIR".to_vec();
+    for value in &[2u32, 6, 1 | 2 | 4, 0] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes.resize(128, 0);
+    for &segments in &[2u16, 2] {
+        bytes.extend_from_slice(&segments.to_le_bytes());
+    }
+    for strand in 0..2 {
+        for x in 0..3 {
+            for &coordinate in &[x as f32, strand as f32, 0.0] {
+                bytes.extend_from_slice(&coordinate.to_le_bytes());
+            }
+        }
+    }
+    for _ in 0..6 {
+        bytes.extend_from_slice(&0.2f32.to_le_bytes());
+    }
+    bytes
+}
+
+#[test]
+fn hair_files_load_strands_as_splines() {
+    let curves = parse_hair(&hair_file()).unwrap();
+    assert_eq!(curves.len(), 4);
+    assert!((curves[3].point(1.0) - Vec3::new(2.0, 1.0, 0.0)).length() < 1e-6);
+    assert_close(curves[0].radii.0, 0.1);
+
+    let mut truncated = hair_file();
+    truncated.truncate(200);
+    assert!(parse_hair(&truncated).is_err());
+    assert!(parse_hair(b"HAIR").is_err());
+
+    // The hierarchy finds every strand.
+    let strands = Curves::new(curves, MaterialId(0));
+    for &y in &[0.0, 1.0] {
+        let ray = Ray::new(Vec3::new(1.5, y, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = strands.hit(&ray, EPSILON, Float::INFINITY).unwrap();
+        assert_close(hit.t, 4.9);
+    }
+}
+
+/// Estimates of the light `hair` scatters towards `wo` from a white sky,
+/// with its own sampling and with uniform directions, and of the integral
+/// of its sampling density.
+fn estimates(hair: &Hair, wo: Vec3, h: Float) -> (Vec3, Vec3, Float) {
+    let samples = 200_000;
+    let mut stream = RandomStream::new(2, "hair");
+    let mut sampled = Vec3::new(0.0, 0.0, 0.0);
+    let mut uniform = Vec3::new(0.0, 0.0, 0.0);
+    let mut density = 0.0;
+    for _ in 0..samples {
+        let angles = (stream.next_01(), stream.next_01());
+        let (wi, _) = hair.sample(wo, h, stream.next_01(), angles, stream.next_01());
+        let (value, pdf) = hair.eval(wo, wi, h);
+        if pdf > 0.0 {
+            sampled += value / pdf;
+        }
+
+        let wi = sample_unit_sphere((stream.next_01(), stream.next_01()));
+        let (value, pdf) = hair.eval(wo, wi, h);
+        uniform += value * 4.0 * consts::PI;
+        density += pdf * 4.0 * consts::PI;
+    }
+    let samples = samples as Float;
+    (sampled / samples, uniform / samples, density / samples)
+}
+
+#[test]
+fn hair_conserves_energy_and_samples_its_density() {
+    let wo = Vec3::new(0.3, 0.1, 0.9).unit();
+    let white = Hair::new(Vec3::new(1.0, 1.0, 1.0), 0.3, 0.3);
+    let (sampled, uniform, density) = estimates(&white, wo, 0.2);
+    assert!((density - 1.0).abs() < 0.05, "{}", density);
+    for &(a, b) in &[(sampled.x, uniform.x), (sampled.y, uniform.y)] {
+        assert!((a - b).abs() < 0.05, "{} != {}", a, b);
+    }
+    // White hair loses no light.
+    assert!(sampled.x > 0.95 && sampled.x < 1.02, "{:?}", sampled);
+
+    let brown = Hair::new(Vec3::new(0.4, 0.25, 0.1), 0.3, 0.3);
+    let (sampled, _, _) = estimates(&brown, wo, -0.5);
+    assert!(sampled.x < 1.0 && sampled.x > sampled.y && sampled.y > sampled.z);
+}
+
+#[test]
+fn scenes_hit_curves_through_their_ids() {
+    let mut scene = Scene::new(Units::Meters);
+    let hair = scene.add_material(MaterialType::Hair(Hair::new(
+        Vec3::new(0.5, 0.3, 0.2),
+        0.3,
+        0.3,
+    )));
+    let mut curves = Curves::new(vec![straight(1.0, 0.05)], MaterialId(0));
+    curves.material = hair;
+    let id = scene.add_shape(Shape::Curves(curves));
+    assert_close(
+        scene.sphere(id).radius,
+        Float::sqrt(1.05 * 1.05 + 2.0 * 0.05 * 0.05),
+    );
+
+    let down = Ray::new(Vec3::new(0.2, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+    let (hit_id, mut hit) = scene.hit_object(&down, EPSILON, Float::INFINITY).unwrap();
+    assert_eq!(hit_id, id);
+    assert_close(hit.t, 4.95);
+    scene.apply_shading_normal(hit_id, &mut hit);
+    assert!(hit.tangent.is_some());
+}
@@ -0,0 +1,82 @@
+//! Point, directional and spot lights.
+
+use raytracer::maths::consts::PI;
+use raytracer::maths::*;
+use raytracer::*;
+
+/// Gray ground under a black sky, lit by `light` alone.
+fn ground(light: DeltaLight) -> Scene {
+    let mut scene = Scene::new(Units::Meters);
+    scene.environment = Environment::Constant(Vec3::new(0.0, 0.0, 0.0));
+    let gray = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    scene.add(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, gray));
+    scene.delta_lights.push(light);
+    scene
+}
+
+/// Red channel of the ground at (`x`, 0, 0), seen from above.
+fn ground_radiance(scene: &Scene, x: Float) -> Float {
+    let ray = Ray::new(Vec3::new(x, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+    ray_color(&ray, scene, &RenderSettings::default()).0.x
+}
+
+#[test]
+fn lights_follow_inverse_squares_and_cones() {
+    let point = DeltaLight::Point {
+        position: Vec3::new(0.0, 2.0, 0.0),
+        intensity: Vec3::new(8.0, 8.0, 8.0),
+    };
+    let sample = point.illuminate(Vec3::new(0.0, 0.0, 0.0)).unwrap();
+    assert_eq!(sample.distance, 2.0);
+    assert_eq!(sample.irradiance.x, 2.0);
+    assert_eq!(sample.direction.y, 1.0);
+
+    let spot = DeltaLight::Spot {
+        position: Vec3::new(0.0, 2.0, 0.0),
+        direction: Vec3::new(0.0, -1.0, 0.0),
+        intensity: Vec3::new(8.0, 8.0, 8.0),
+        inner_angle: 20.0,
+        outer_angle: 40.0,
+    };
+    let at = |x: Float| {
+        spot.illuminate(Vec3::new(x, 0.0, 0.0))
+            .map_or(0.0, |sample| sample.irradiance.x * sample.distance.powi(2))
+    };
+    assert_eq!(at(0.0), 8.0);
+    let fading = at(2.0 * (30.0 as Float).to_radians().tan());
+    assert!(fading > 0.0 && fading < 8.0, "{}", fading);
+    assert_eq!(at(2.0), 0.0);
+}
+
+#[test]
+fn delta_lights_light_the_ground() {
+    // Lambertian surfaces reflect albedo / pi of the irradiance.
+    let point = ground(DeltaLight::Point {
+        position: Vec3::new(0.0, 2.0, 0.0),
+        intensity: Vec3::new(8.0, 8.0, 8.0),
+    });
+    assert_close(ground_radiance(&point, 0.0), 0.5 / PI * 2.0);
+
+    let sun = ground(DeltaLight::Directional {
+        direction: Vec3::new(1.0, -1.0, 0.0),
+        irradiance: Vec3::new(2.0, 2.0, 2.0),
+    });
+    let cos = (0.5 as Float).sqrt();
+    assert_close(ground_radiance(&sun, 0.0), 0.5 / PI * 2.0 * cos);
+
+    // Blocked by a ball.
+    let mut shadowed = ground(DeltaLight::Directional {
+        direction: Vec3::new(1.0, -1.0, 0.0),
+        irradiance: Vec3::new(2.0, 2.0, 2.0),
+    });
+    let gray = MaterialId(0);
+    shadowed.add(Sphere::new(Vec3::new(-2.0, 2.0, 0.0), 0.5, gray));
+    assert_eq!(ground_radiance(&shadowed, 0.0), 0.0);
+    assert_eq!(ground_radiance(&shadowed, 1.5), ground_radiance(&sun, 1.5));
+}
+
+fn assert_close(value: Float, expected: Float) {
+    assert!((value - expected).abs() < 1e-4, "{} != {}", value, expected);
+}
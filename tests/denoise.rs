@@ -0,0 +1,69 @@
+//! The à-trous denoiser cleaning up noise without blurring edges.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+const SIZE: usize = 32;
+
+/// Image of a light grey right half next to a dark left half, lit evenly,
+/// with its auxiliary images.
+fn two_albedos(noise: Float) -> (Vec<Float>, AovImages) {
+    seed_random(51);
+    let albedo = |i: usize| if i < SIZE / 2 { 0.2 } else { 0.8 };
+    let mut pixels = Vec::new();
+    let mut aovs = AovImages {
+        albedo: Vec::new(),
+        normal: Vec::new(),
+        depth: Vec::new(),
+        motion: Vec::new(),
+    };
+    for _ in 0..SIZE {
+        for i in 0..SIZE {
+            let value = albedo(i) * (1.0 + noise * random_between(-1.0, 1.0));
+            pixels.extend_from_slice(&[value, value, value, 1.0]);
+            aovs.albedo.extend_from_slice(&[albedo(i); 3]);
+            aovs.normal.extend_from_slice(&[0.0, 0.0, 1.0]);
+            aovs.depth.push(2.0);
+            aovs.motion.extend_from_slice(&[0.0, 0.0]);
+        }
+    }
+    (pixels, aovs)
+}
+
+/// Root mean square error of the red channel of `pixels` against the clean
+/// image.
+fn error(pixels: &[Float]) -> Float {
+    let (clean, _) = two_albedos(0.0);
+    let sum: Float = pixels
+        .chunks(4)
+        .zip(clean.chunks(4))
+        .map(|(pixel, clean)| (pixel[0] - clean[0]).powi(2))
+        .sum();
+    (sum / (SIZE * SIZE) as Float).sqrt()
+}
+
+#[test]
+fn denoising_removes_noise_and_keeps_edges() {
+    let settings = DenoiseSettings::default();
+    let (clean, aovs) = two_albedos(0.0);
+    let denoised = denoise(&clean, &aovs, SIZE, SIZE, &settings);
+    assert!(error(&denoised) < 1e-4);
+
+    let (noisy, aovs) = two_albedos(0.5);
+    let denoised = denoise(&noisy, &aovs, SIZE, SIZE, &settings);
+    assert!(
+        error(&denoised) < error(&noisy) / 4.0,
+        "{} {}",
+        error(&denoised),
+        error(&noisy)
+    );
+    // The pixels on either side of the border between albedos stay apart.
+    let row = SIZE / 2 * SIZE;
+    let (left, right) = (
+        denoised[(row + SIZE / 2 - 1) * 4],
+        denoised[(row + SIZE / 2) * 4],
+    );
+    assert!((left - 0.2).abs() < 0.05, "{}", left);
+    assert!((right - 0.8).abs() < 0.1, "{}", right);
+    assert!(denoised.chunks(4).all(|pixel| (pixel[3] - 1.0).abs() < 1e-4));
+}
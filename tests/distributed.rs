@@ -0,0 +1,164 @@
+//! Images farmed out to workers over TCP, region by region.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+const WIDTH: usize = 300;
+const HEIGHT: usize = 140;
+
+fn scene() -> Scene {
+    let mut scene = Scene::new(Units::Meters);
+    let ground = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    let metal = scene.add_material(MaterialType::Metal {
+        albedo: Vec3::new(0.8, 0.7, 0.6),
+        fuzziness: 0.2,
+    });
+    scene.add(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground));
+    scene.add(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, metal));
+    scene
+}
+
+fn camera() -> Camera {
+    Camera::new(
+        Vec3::new(0.0, 2.0, 6.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        40.0,
+        WIDTH as Float / HEIGHT as Float,
+        0.0,
+        6.0,
+    )
+}
+
+fn settings(seed: u64) -> RenderSettings {
+    RenderSettings {
+        width: WIDTH,
+        height: HEIGHT,
+        samples_per_pixel: 2,
+        max_depth: 4,
+        filter: Filter::Tent { radius: 1.0 },
+        seed,
+        threads: 1,
+        ..RenderSettings::default()
+    }
+}
+
+/// Starts a worker rendering with `settings` on its own thread, and returns
+/// its address.
+fn start_worker(settings: RenderSettings) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        let (scene, camera) = (scene(), camera());
+        let hashes = RenderHashes::new(&scene, &camera, &settings);
+        let mut renderer = Renderer::new(settings).unwrap();
+        serve(listener, &hashes, |region| {
+            renderer.render_region(&scene, &camera, region)
+        })
+        .unwrap();
+    });
+    address
+}
+
+/// Address nothing listens on anymore.
+fn dead_address() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+#[test]
+fn regions_cover_images_once() {
+    let regions = split_image(300, 140, 128);
+    assert_eq!(regions.len(), 6);
+    let covered: usize = regions
+        .iter()
+        .map(|region| region.width * region.height)
+        .sum();
+    assert_eq!(covered, 300 * 140);
+    assert_eq!(
+        regions[5],
+        Crop {
+            x: 256,
+            y: 128,
+            width: 44,
+            height: 12
+        }
+    );
+}
+
+#[test]
+fn workers_render_the_same_image() {
+    let settings = settings(7);
+    let (scene, camera) = (scene(), camera());
+    let local = Renderer::new(settings).unwrap().render(&scene, &camera);
+
+    // Dead workers leave their regions to the others.
+    let workers = vec![
+        start_worker(settings),
+        dead_address(),
+        start_worker(settings),
+    ];
+    let hashes = RenderHashes::new(&scene, &camera, &settings);
+    let farmed = render_distributed(&workers, &hashes, WIDTH, HEIGHT).unwrap();
+    assert_eq!(
+        (farmed.width(), farmed.height()),
+        (local.width(), local.height())
+    );
+    for (a, b) in farmed.pixels().iter().zip(local.pixels()) {
+        assert!((a - b).abs() <= 1e-5 * b.abs().max(1.0), "{} != {}", a, b);
+    }
+
+    // Workers refuse regions of other renders.
+    let other = start_worker(RenderSettings {
+        seed: 8,
+        ..settings
+    });
+    let error = render_distributed(&[other, dead_address()], &hashes, WIDTH, HEIGHT)
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(error.contains("every worker failed"), "{}", error);
+    assert!(error.contains("different settings"), "{}", error);
+}
+
+#[test]
+fn workers_refuse_regions_off_the_image() {
+    let settings = settings(7);
+    let hashes = RenderHashes::new(&scene(), &camera(), &settings);
+    let stream = TcpStream::connect(start_worker(settings)).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut writer = stream;
+    let mut ask = |region: &str| {
+        let mut line = String::from("render");
+        for (_, hash) in hashes.components().iter() {
+            line.push_str(&format!(" {:016x}", hash));
+        }
+        writeln!(writer, "{} {}", line, region).unwrap();
+        let mut answer = String::new();
+        reader.read_line(&mut answer).unwrap();
+        answer
+    };
+
+    let huge = usize::MAX;
+    for region in &[
+        "300 0 1 1".to_string(),
+        "0 139 1 2".to_string(),
+        "0 0 0 5".to_string(),
+        format!("{} 0 2 1", huge),
+        format!("0 0 1 {}", huge),
+    ] {
+        assert_eq!(ask(region), "error bad region\n", "{}", region);
+    }
+    // The worker is still up for the regions of the image.
+    assert_eq!(
+        ask("299 139 1 1"),
+        format!("pixels 4 {}\n", std::mem::size_of::<Float>())
+    );
+}
@@ -0,0 +1,137 @@
+//! Lookups into adjusted and ground projected environment maps.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+fn assert_vec_close(a: Vec3, b: Vec3) {
+    assert!((a - b).length() < 1e-6, "{:?} != {:?}", a, b);
+}
+
+/// 8x4 map whose texels all have different colors.
+fn map() -> EnvironmentMap {
+    let pixels: Vec<f32> = (0..32)
+        .flat_map(|texel| vec![texel as f32, 1.0, 0.5 * (texel % 3) as f32])
+        .collect();
+    EnvironmentMap::new(&pixels, 8, 4)
+}
+
+#[test]
+fn rotations_move_the_map() {
+    let front = Vec3::new(0.1, 0.2, -1.0);
+    let turned = map().adjust(&MapAdjustments {
+        yaw_degrees: 90.0,
+        ..MapAdjustments::default()
+    });
+    // Seen from above, the front of the map turned from -z to -x.
+    assert_vec_close(
+        turned.lookup(Vec3::new(-1.0, 0.2, -0.1)),
+        map().lookup(front),
+    );
+
+    let lifted = map().adjust(&MapAdjustments {
+        pitch_degrees: 90.0,
+        ..MapAdjustments::default()
+    });
+    assert_vec_close(lifted.lookup(Vec3::new(0.1, 1.0, 0.2)), map().lookup(front));
+
+    let both = map().adjust(&MapAdjustments {
+        yaw_degrees: 90.0,
+        pitch_degrees: 90.0,
+        ..MapAdjustments::default()
+    });
+    let direction = Vec3::new(0.3, -0.4, 0.8);
+    let twice = map()
+        .adjust(&MapAdjustments {
+            pitch_degrees: 90.0,
+            ..MapAdjustments::default()
+        })
+        .adjust(&MapAdjustments {
+            yaw_degrees: 90.0,
+            ..MapAdjustments::default()
+        });
+    assert_vec_close(both.lookup(direction), twice.lookup(direction));
+}
+
+#[test]
+fn exposure_and_saturation_scale_colors() {
+    let direction = Vec3::new(0.3, 0.4, -0.8);
+    let color = map().lookup(direction);
+
+    let brighter = map().adjust(&MapAdjustments {
+        exposure: 1.0,
+        ..MapAdjustments::default()
+    });
+    assert_vec_close(brighter.lookup(direction), color * 2.0);
+
+    let gray = map().adjust(&MapAdjustments {
+        saturation: 0.0,
+        ..MapAdjustments::default()
+    });
+    let y = luminance(color);
+    assert_vec_close(gray.lookup(direction), Vec3::new(y, y, y));
+}
+
+#[test]
+fn projected_ground_is_seen_from_where_it_was_photographed() {
+    let projected = map().with_ground_projection(Some(GroundProjection {
+        center: Vec3::new(0.0, 0.0, 0.0),
+        height: 2.0,
+        radius: 20.0,
+    }));
+    // From where the photograph was taken, nothing changes.
+    let eye = Vec3::new(0.0, 2.0, 0.0);
+    for direction in &[Vec3::new(0.3, -0.4, 0.8), Vec3::new(-0.2, 0.5, -0.1)] {
+        assert_vec_close(
+            projected.lookup_from(eye, *direction),
+            map().lookup(*direction),
+        );
+    }
+
+    // Elsewhere, rays look at the ground under them...
+    let down = Vec3::new(0.0, -1.0, 0.0);
+    let ground = projected.lookup_from(Vec3::new(3.0, 0.5, 0.0), down);
+    assert_vec_close(ground, map().lookup(Vec3::new(3.0, -2.0, 0.0)));
+    assert!((ground - map().lookup(down)).length() > 0.1);
+    // ...or at the dome.
+    let up = Vec3::new(0.0, 1.0, 0.0);
+    let dome = projected.lookup_from(Vec3::new(5.0, 1.0, 0.0), up);
+    let top = Vec3::new(5.0, (375.0 as Float).sqrt(), 0.0);
+    assert_vec_close(dome, map().lookup(top - eye));
+
+    // Maps without ground only depend on directions.
+    assert_vec_close(
+        map().lookup_from(Vec3::new(3.0, 0.5, 0.0), down),
+        map().lookup(down),
+    );
+}
+
+#[test]
+fn physical_sky_follows_the_sun() {
+    let sky = PhysicalSky::new(30.0, 90.0, 3.0);
+    assert_vec_close(
+        sky.sun_direction(),
+        Vec3::new((0.75 as Float).sqrt(), 0.5, 0.0),
+    );
+
+    // Clear skies are blue overhead, brighter towards the sun and redder at
+    // sunset.
+    let zenith = sky.radiance(Vec3::new(0.0, 1.0, 0.0));
+    assert!(zenith.z > zenith.x, "{:?}", zenith);
+    let near_sun = sky.radiance(Vec3::new(0.8, 0.5, 0.1));
+    let away = sky.radiance(Vec3::new(-0.8, 0.5, -0.1));
+    assert!(luminance(near_sun) > luminance(away));
+    let sun = sky.radiance(sky.sun_direction());
+    assert!(luminance(sun) > 1000.0 * luminance(zenith));
+    let sunset = PhysicalSky::new(2.0, 90.0, 3.0);
+    let setting_sun = sunset.radiance(sunset.sun_direction());
+    assert!(setting_sun.x / setting_sun.z > sun.x / sun.z);
+
+    // Sun samples land on the disk, where the density is uniform.
+    seed_random(4);
+    let pdf = sky.sun_pdf(sky.sun_direction());
+    for _ in 0..100 {
+        let direction = sky.sample_sun((random_01(), random_01()));
+        assert_eq!(sky.sun_pdf(direction), pdf);
+    }
+    assert_eq!(sky.sun_pdf(Vec3::new(0.0, 1.0, 0.0)), 0.0);
+}
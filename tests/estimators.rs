@@ -0,0 +1,202 @@
+//! Pixel estimators robust to fireflies.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+/// Ground lit only by a tiny, very bright sphere that isn't sampled as a
+/// light, so the few paths finding it are fireflies.
+fn render(median_of_means: usize) -> Vec<Float> {
+    let mut scene = Scene::new(Units::Meters);
+    scene.environment = Environment::Constant(Vec3::new(0.1, 0.1, 0.1));
+    let gray = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    let light = scene.add_material(MaterialType::DiffuseLight {
+        emit: Vec3::new(1e4, 1e4, 1e4),
+    });
+    scene.add(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, gray));
+    scene.add(Sphere::new(Vec3::new(0.0, 3.0, 0.0), 0.1, light));
+    let camera = Camera::new(
+        Vec3::new(0.0, 2.0, 4.0),
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        60.0,
+        1.0,
+        0.0,
+        4.0,
+    );
+
+    let settings = RenderSettings {
+        width: 16,
+        height: 16,
+        samples_per_pixel: 32,
+        max_depth: 4,
+        median_of_means,
+        seed: 3,
+        threads: 1,
+        ..RenderSettings::default()
+    };
+    Renderer::new(settings)
+        .unwrap()
+        .render(&scene, &camera)
+        .into_pixels()
+}
+
+fn brightest(pixels: &[Float]) -> Float {
+    pixels.chunks(4).map(|pixel| pixel[0]).fold(0.0, Float::max)
+}
+
+#[test]
+fn median_of_means_drops_fireflies() {
+    let mean = render(0);
+    let robust = render(8);
+    assert!(brightest(&mean) > 10.0, "{}", brightest(&mean));
+    assert!(brightest(&robust) < 1.0, "{}", brightest(&robust));
+
+    // Alpha, the same in every sample, is left alone.
+    for (mean, robust) in mean.chunks(4).zip(robust.chunks(4)) {
+        assert_eq!(mean[3], robust[3]);
+    }
+}
+
+#[test]
+fn direct_clamping_spares_visible_lights() {
+    let mut scene = Scene::new(Units::Meters);
+    scene.environment = Environment::Constant(Vec3::new(0.0, 0.0, 0.0));
+    let gray = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    let light = scene.add_material(MaterialType::DiffuseLight {
+        emit: Vec3::new(1e4, 1e4, 1e4),
+    });
+    scene.add(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, gray));
+    scene.add_light(Sphere::new(Vec3::new(0.0, 3.0, 0.0), 0.1, light));
+
+    // A single bounce, so the ground only gets direct light.
+    let settings = |clamp_direct| RenderSettings {
+        max_depth: 1,
+        clamp_direct,
+        ..RenderSettings::default()
+    };
+    let ground = Ray::new(Vec3::new(0.0, 1.0, 1.0), Vec3::new(0.0, -1.0, -0.5));
+    let (unclamped, _) = ray_color(&ground, &scene, &settings(Float::INFINITY));
+    let (clamped, _) = ray_color(&ground, &scene, &settings(1.0));
+    assert!(luminance(unclamped) > 2.0, "{:?}", unclamped);
+    assert!((luminance(clamped) - 1.0).abs() < 1e-3, "{:?}", clamped);
+
+    let up = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+    let (seen, _) = ray_color(&up, &scene, &settings(1.0));
+    assert_eq!(seen.x, 1e4);
+}
+
+#[test]
+fn indirect_clamping_spares_direct_light() {
+    let mut scene = Scene::new(Units::Meters);
+    scene.environment = Environment::Constant(Vec3::new(10.0, 10.0, 10.0));
+    let mirror = scene.add_material(MaterialType::Metal {
+        albedo: Vec3::new(1.0, 1.0, 1.0),
+        fuzziness: 0.0,
+    });
+    let light = scene.add_material(MaterialType::DiffuseLight {
+        emit: Vec3::new(1e4, 1e4, 1e4),
+    });
+    // Rays grazing the first mirror go up to the second, which sends them
+    // off to the sky: it is only reached through two bounces.
+    let half = Float::sqrt(0.5);
+    scene.add(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, mirror));
+    scene.add(Sphere::new(
+        Vec3::new(-2.0 * half, 5.0 + half, 0.0),
+        1.0,
+        mirror,
+    ));
+    scene.add(Sphere::new(Vec3::new(0.0, 0.0, 10.0), 1.0, light));
+
+    let settings = |clamp_indirect| RenderSettings {
+        clamp_indirect,
+        ..RenderSettings::default()
+    };
+    let indirect = Ray::new(Vec3::new(-5.0, half, 0.0), Vec3::new(1.0, 0.0, 0.0));
+    let (unclamped, _) = ray_color(&indirect, &scene, &settings(Float::INFINITY));
+    let (clamped, _) = ray_color(&indirect, &scene, &settings(1.0));
+    assert!(
+        (luminance(unclamped) - 10.0).abs() < 1e-3,
+        "{:?}",
+        unclamped
+    );
+    assert!((luminance(clamped) - 1.0).abs() < 1e-3, "{:?}", clamped);
+
+    // Head on, the first mirror sends rays straight back to the sky.
+    let direct = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+    let (reflected, _) = ray_color(&direct, &scene, &settings(1.0));
+    assert!(
+        (luminance(reflected) - 10.0).abs() < 1e-3,
+        "{:?}",
+        reflected
+    );
+
+    let seen = RenderSettings {
+        clamp_direct: 1.0,
+        ..settings(1.0)
+    };
+    let up = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, 1.0));
+    assert_eq!(ray_color(&up, &scene, &seen).0.x, 1e4);
+}
+
+#[test]
+fn outlier_rejection_brings_fireflies_down_to_the_other_samples() {
+    let mut scene = Scene::new(Units::Meters);
+    scene.environment = Environment::Constant(Vec3::new(2.0, 2.0, 2.0));
+    let light = scene.add_material(MaterialType::DiffuseLight {
+        emit: Vec3::new(100.0, 100.0, 100.0),
+    });
+    // The light covers the top left cell of the 4x4 strata of the single
+    // pixel, so one sample sees it and the others the sky.
+    let positions = vec![
+        Vec3::new(-3.0, 0.5, -1.0),
+        Vec3::new(-0.5, 0.5, -1.0),
+        Vec3::new(-0.5, 3.0, -1.0),
+        Vec3::new(-3.0, 3.0, -1.0),
+    ];
+    scene.add_shape(Shape::Mesh(Mesh::new(
+        positions,
+        vec![[0, 1, 2], [0, 2, 3]],
+        light,
+    )));
+    let camera = Camera::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        90.0,
+        1.0,
+        0.0,
+        1.0,
+    );
+
+    let render = |outlier_sigma| {
+        let settings = RenderSettings {
+            width: 1,
+            height: 1,
+            samples_per_pixel: 16,
+            sampler: SamplerType::Stratified,
+            outlier_sigma,
+            seed: 3,
+            threads: 1,
+            ..RenderSettings::default()
+        };
+        Renderer::new(settings)
+            .unwrap()
+            .render(&scene, &camera)
+            .pixel(0, 0)
+    };
+    let mean = render(Float::INFINITY);
+    assert!(
+        (mean[0] - (15.0 * 2.0 + 100.0) / 16.0).abs() < 1e-3,
+        "{:?}",
+        mean
+    );
+    // The other samples all being the same, the firefly is scaled down to
+    // them exactly.
+    let robust = render(3.0);
+    assert!((robust[0] - 2.0).abs() < 1e-3, "{:?}", robust);
+    assert_eq!(robust[3], 1.0);
+}
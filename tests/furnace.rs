@@ -0,0 +1,160 @@
+//! White furnace tests: spheres under a uniform white sky, which materials
+//! reflecting or letting through all the light they get should disappear
+//! into, and which no material may outshine.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+use std::sync::Arc;
+
+/// Average color of a sphere of `material` filling the view, lit by a white
+/// environment.
+fn furnace(material: MaterialType) -> Vec3 {
+    let mut scene = Scene::new(Units::Meters);
+    scene.environment = Environment::Constant(Vec3::new(1.0, 1.0, 1.0));
+    let material = scene.add_material(material);
+    scene.add(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, material));
+    // Narrow enough for the sphere to cover every pixel.
+    let camera = Camera::new(
+        Vec3::new(0.0, 0.0, 5.0),
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        10.0,
+        1.0,
+        0.0,
+        5.0,
+    );
+    let settings = RenderSettings {
+        width: 16,
+        height: 16,
+        samples_per_pixel: 64,
+        max_depth: 64,
+        seed: 1,
+        threads: 1,
+        ..RenderSettings::default()
+    };
+    let image = Renderer::new(settings).unwrap().render(&scene, &camera);
+    let mut sum = Vec3::new(0.0, 0.0, 0.0);
+    for pixel in image.pixels().chunks(4) {
+        assert_eq!(pixel[3], 1.0);
+        sum += Vec3::new(pixel[0], pixel[1], pixel[2]);
+    }
+    sum / (image.width() * image.height()) as Float
+}
+
+fn assert_between(color: Vec3, lowest: Float, highest: Float, material: &str) {
+    for &channel in &[color.x, color.y, color.z] {
+        assert!(
+            channel >= lowest && channel <= highest,
+            "{}: {:?} not in [{}, {}]",
+            material,
+            color,
+            lowest,
+            highest
+        );
+    }
+}
+
+#[test]
+fn lossless_materials_vanish_in_a_white_furnace() {
+    let white = Vec3::new(1.0, 1.0, 1.0);
+    let materials = vec![
+        ("lambertian", MaterialType::Lambertian { albedo: white }),
+        (
+            "metal",
+            MaterialType::Metal {
+                albedo: white,
+                fuzziness: 0.0,
+            },
+        ),
+        (
+            "dielectric",
+            MaterialType::Dialectric {
+                refractive_index: 1.5,
+            },
+        ),
+        (
+            "thin glass",
+            MaterialType::ThinGlass {
+                refractive_index: 1.5,
+                tint: white,
+            },
+        ),
+    ];
+    for (name, material) in materials {
+        assert_between(furnace(material), 0.98, 1.02, name);
+    }
+}
+
+#[test]
+fn no_material_outshines_a_white_furnace() {
+    let white = Vec3::new(1.0, 1.0, 1.0);
+    let principled = |metallic, roughness, transmission| {
+        MaterialType::Principled(Principled {
+            base_color: white,
+            metallic,
+            roughness,
+            transmission,
+            ..Principled::default()
+        })
+    };
+    let materials = vec![
+        (
+            "fuzzy metal",
+            MaterialType::Metal {
+                albedo: white,
+                fuzziness: 0.8,
+            },
+        ),
+        (
+            "rough metal",
+            MaterialType::Microfacet {
+                roughness: 0.6,
+                fresnel: Fresnel::Conductor { f0: white },
+            },
+        ),
+        (
+            "brushed metal",
+            MaterialType::AnisotropicMetal {
+                f0: white,
+                roughness_u: 0.2,
+                roughness_v: 0.6,
+                rotation: 0.0,
+            },
+        ),
+        (
+            "rough dielectric",
+            MaterialType::Microfacet {
+                roughness: 0.6,
+                fresnel: Fresnel::Dielectric {
+                    refractive_index: 1.5,
+                },
+            },
+        ),
+        ("principled plastic", principled(0.0, 0.5, 0.0)),
+        ("rough principled plastic", principled(0.0, 1.0, 0.0)),
+        ("principled metal", principled(1.0, 0.5, 0.0)),
+        ("principled glass", principled(0.0, 0.3, 1.0)),
+        (
+            "subsurface",
+            MaterialType::Subsurface {
+                albedo: white,
+                radius: Vec3::new(0.1, 0.1, 0.1),
+                refractive_index: 1.4,
+            },
+        ),
+        (
+            "measured",
+            MaterialType::Measured(Arc::new(MeasuredBrdf::new(vec![
+                0.9 / std::f32::consts::PI;
+                90 * 90 * 180 * 3
+            ]))),
+        ),
+        ("hair", MaterialType::Hair(Hair::new(white, 0.3, 0.3))),
+    ];
+    // Rough microfacets lose the light bouncing between facets, so they only
+    // come close.
+    for (name, material) in materials {
+        assert_between(furnace(material), 0.75, 1.02, name);
+    }
+}
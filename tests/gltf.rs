@@ -0,0 +1,308 @@
+//! Scenes imported from glTF files.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+use std::fs;
+use std::path::Path;
+
+const EPSILON: Float = 1e-9;
+
+fn assert_close(a: Float, b: Float) {
+    assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+}
+
+/// Positions of a unit square of the xy plane facing +z, then the indices
+/// of its two triangles.
+fn square_buffer() -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for corner in &[
+        [0.0f32, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0],
+    ] {
+        for coordinate in corner {
+            bytes.extend_from_slice(&coordinate.to_le_bytes());
+        }
+    }
+    for index in &[0u16, 1, 2, 0, 2, 3] {
+        bytes.extend_from_slice(&index.to_le_bytes());
+    }
+    bytes
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+            bits | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+/// glTF document of the square, twice as large and 5 m away along -z,
+/// seen by a camera turned towards -x and lit by a point light, `buffer`
+/// being the members of its buffer other than its length.
+fn document(buffer: &str) -> String {
+    format!(
+        r#"{{
+  "asset": {{"version": "2.0", "generator": "hand \"written\""}},
+  "extensionsUsed": ["KHR_lights_punctual"],
+  "scene": 0,
+  "scenes": [{{"nodes": [0, 2, 3]}}],
+  "nodes": [
+    {{"name": "parent", "translation": [0, 0, -5], "children": [1]}},
+    {{"name": "square", "mesh": 0, "scale": [2, 2, 2]}},
+    {{"camera": 0, "translation": [0, 1, 3], "rotation": [0, 0.70710678, 0, 0.70710678]}},
+    {{"matrix": [1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 4, 0, 1],
+      "extensions": {{"KHR_lights_punctual": {{"light": 0}}}}}}
+  ],
+  "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}, "indices": 1, "material": 0}}]}}],
+  "materials": [{{
+    "name": "red",
+    "pbrMetallicRoughness": {{"baseColorFactor": [0.8, 0.1, 0.1, 1], "metallicFactor": 0, "roughnessFactor": 0.4}}
+  }}],
+  "accessors": [
+    {{"bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3"}},
+    {{"bufferView": 1, "componentType": 5123, "count": 6, "type": "SCALAR"}}
+  ],
+  "bufferViews": [
+    {{"buffer": 0, "byteOffset": 0, "byteLength": 48}},
+    {{"buffer": 0, "byteOffset": 48, "byteLength": 12}}
+  ],
+  "buffers": [{{"byteLength": 60{}}}],
+  "cameras": [{{"type": "perspective", "perspective": {{"yfov": 0.5, "znear": 0.1}}}}],
+  "extensions": {{"KHR_lights_punctual": {{"lights": [
+    {{"type": "point", "color": [1, 0.5, 0.5], "intensity": 10}}
+  ]}}}}
+}}"#,
+        buffer
+    )
+}
+
+/// The square, hit from the origin, in a scene of `units`.
+fn assert_square(scene: &Scene, units: Units) {
+    let meter = units.from_meters(1.0);
+    let ray = Ray::new(Vec3::new(0.5, 0.5, 0.0) * meter, Vec3::new(0.0, 0.0, -1.0));
+    let (id, hit) = scene.hit_object(&ray, EPSILON, Float::INFINITY).unwrap();
+    assert_close(hit.t, 5.0 * meter);
+    assert!(hit.front_face);
+    assert_eq!(scene.name(id), Some("square"));
+    let beside = Ray::new(Vec3::new(2.5, 0.5, 0.0) * meter, Vec3::new(0.0, 0.0, -1.0));
+    assert!(scene
+        .hit_object(&beside, EPSILON, Float::INFINITY)
+        .is_none());
+
+    let red = scene.material_named("red").unwrap();
+    assert_eq!(hit.material, red);
+    match scene.material(red) {
+        MaterialType::Principled(principled) => {
+            assert_close(principled.base_color.y, 0.1);
+            assert_close(principled.metallic, 0.0);
+            assert_close(principled.roughness, 0.4);
+        }
+        other => panic!("{:?}", other),
+    }
+
+    let camera = scene.camera.unwrap();
+    assert!((camera.look_from - Vec3::new(0.0, 1.0, 3.0) * meter).length() < 1e-4);
+    let forward = (camera.look_at - camera.look_from).unit();
+    assert_close(forward.x, -1.0);
+    assert_close(camera.vertical_fov, Float::to_degrees(0.5));
+
+    match scene.delta_lights[..] {
+        [DeltaLight::Point {
+            position,
+            intensity,
+        }] => {
+            assert_close(position.y, 4.0 * meter);
+            assert_close(intensity.z, 5.0 * meter * meter);
+        }
+        ref other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn gltf_files_bring_meshes_materials_cameras_and_lights() {
+    let uri = format!(
+        r#", "uri": "data:application/octet-stream;base64,{}""#,
+        base64(&square_buffer())
+    );
+    let mut scene = Scene::new(Units::Meters);
+    parse_gltf(document(&uri).as_bytes(), Path::new(""), &mut scene).unwrap();
+    assert_square(&scene, Units::Meters);
+}
+
+#[test]
+fn glb_files_are_converted_into_scene_units() {
+    let mut json = document("").into_bytes();
+    json.resize(json.len().div_ceil(4) * 4, b' ');
+    let buffer = square_buffer();
+    let mut glb = b"glTF".to_vec();
+    glb.extend_from_slice(&2u32.to_le_bytes());
+    glb.extend_from_slice(&((12 + 8 + json.len() + 8 + buffer.len()) as u32).to_le_bytes());
+    glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    glb.extend_from_slice(b"JSON");
+    glb.extend_from_slice(&json);
+    glb.extend_from_slice(&(buffer.len() as u32).to_le_bytes());
+    glb.extend_from_slice(b"BIN\0");
+    glb.extend_from_slice(&buffer);
+
+    let mut scene = Scene::new(Units::Centimeters);
+    parse_gltf(&glb, Path::new(""), &mut scene).unwrap();
+    assert_square(&scene, Units::Centimeters);
+
+    glb.truncate(glb.len() - 10);
+    let mut scene = Scene::new(Units::Meters);
+    assert!(parse_gltf(&glb, Path::new(""), &mut scene).is_err());
+}
+
+#[test]
+fn gltf_scenes_load_their_buffers_from_files() {
+    let directory = std::env::temp_dir().join(format!("raytracer-gltf-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("square data.bin"), square_buffer()).unwrap();
+    let file = directory.join("square.gltf");
+    fs::write(&file, document(r#", "uri": "square%20data.bin""#)).unwrap();
+
+    let scene = load_scene(&file.to_string_lossy()).unwrap();
+    assert_square(&scene, Units::Meters);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn invalid_gltf_files_are_rejected() {
+    let uri = format!(
+        r#", "uri": "data:application/octet-stream;base64,{}""#,
+        base64(&square_buffer())
+    );
+    let valid = document(&uri);
+    let broken = [
+        valid.replace(r#""version": "2.0""#, r#""version": "1.0""#),
+        valid.replace(r#""count": 6"#, r#""count": 7"#),
+        valid.replace(r#""children": [1]"#, r#""children": [0]"#),
+        valid.replace(r#""indices": 1"#, r#""indices": 2"#),
+        valid.replace(
+            r#""extensionsUsed""#,
+            r#""extensionsRequired": ["KHR_draco_mesh_compression"], "extensionsUsed""#,
+        ),
+        valid.replace("\"scenes\": [{", "\"scenes\": [[{"),
+        valid.replace(
+            r#""baseColorFactor""#,
+            r#""baseColorTexture": {"index": 0}, "baseColorFactor""#,
+        ),
+        valid.replace(
+            r#""baseColorFactor""#,
+            r#""metallicRoughnessTexture": {"index": 0}, "baseColorFactor""#,
+        ),
+        "[".repeat(10_000),
+    ];
+    for document in &broken {
+        let mut scene = Scene::new(Units::Meters);
+        assert!(parse_gltf(document.as_bytes(), Path::new(""), &mut scene).is_err());
+    }
+}
+
+/// The square with texture coordinates, the whole image across it, and
+/// the material textured by a PNG of an orange and a blue texel.
+fn textured_document() -> String {
+    let mut buffer = square_buffer();
+    for uv in &[[0.0f32, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]] {
+        for coordinate in uv {
+            buffer.extend_from_slice(&coordinate.to_le_bytes());
+        }
+    }
+    let png = raytracer::png::encode_png(&[255, 128, 0, 0, 0, 255], 2, 1, false, &[], None);
+    let uri = format!(
+        r#", "uri": "data:application/octet-stream;base64,{}""#,
+        base64(&buffer)
+    );
+    document(&uri)
+        .replace(r#""byteLength": 60"#, r#""byteLength": 92"#)
+        .replace(
+            r#""attributes": {"POSITION": 0}"#,
+            r#""attributes": {"POSITION": 0, "TEXCOORD_0": 2}"#,
+        )
+        .replace(
+            r#"{"bufferView": 1, "componentType": 5123, "count": 6, "type": "SCALAR"}"#,
+            r#"{"bufferView": 1, "componentType": 5123, "count": 6, "type": "SCALAR"},
+    {"bufferView": 2, "componentType": 5126, "count": 4, "type": "VEC2"}"#,
+        )
+        .replace(
+            r#"{"buffer": 0, "byteOffset": 48, "byteLength": 12}"#,
+            r#"{"buffer": 0, "byteOffset": 48, "byteLength": 12},
+    {"buffer": 0, "byteOffset": 60, "byteLength": 32}"#,
+        )
+        .replace(
+            r#""baseColorFactor""#,
+            r#""baseColorTexture": {"index": 0}, "metallicRoughnessTexture": {"index": 0}, "baseColorFactor""#,
+        )
+        .replace(
+            r#""name": "red","#,
+            r#""name": "red",
+    "normalTexture": {"index": 0, "scale": 0.5},
+    "emissiveTexture": {"index": 0},
+    "emissiveFactor": [1, 1, 1],"#,
+        )
+        .replace(
+            r#""cameras""#,
+            &format!(
+                r#""textures": [{{"source": 0}}],
+  "images": [{{"uri": "data:image/png;base64,{}"}}],
+  "cameras""#,
+                base64(&png)
+            ),
+        )
+}
+
+#[test]
+fn gltf_textures_follow_texture_coordinates() {
+    let mut scene = Scene::new(Units::Meters);
+    parse_gltf(textured_document().as_bytes(), Path::new(""), &mut scene).unwrap();
+    let red = scene.material_named("red").unwrap();
+    let principled = match scene.material(red) {
+        MaterialType::Principled(principled) => principled,
+        other => panic!("{:?}", other),
+    };
+    assert_eq!(scene.normal_map(red).unwrap().strength, 0.5);
+
+    // The square goes from 0 to 2 along x, over both texels.
+    let down = |x: Float| Ray::new(Vec3::new(x, 0.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+    let hit = scene.hit(&down(0.5), EPSILON, Float::INFINITY).unwrap();
+    let (u, v) = hit.uv.unwrap();
+    assert_close(u, 0.25);
+    assert_close(v, 0.25);
+    let orange = principled.at(hit.uv);
+    // Base colors and emission are in sRGB, roughness isn't.
+    assert_close(orange.base_color.x, 0.8);
+    assert_close(orange.base_color.y, 0.1 * 0.21586);
+    assert_close(orange.emission.y, 0.21586);
+    assert_close(orange.roughness, 0.4 * 128.0 / 255.0);
+    assert_close(orange.metallic, 0.0);
+
+    let hit = scene.hit(&down(1.5), EPSILON, Float::INFINITY).unwrap();
+    let blue = principled.at(hit.uv);
+    assert_close(blue.base_color.x, 0.0);
+    assert_close(blue.base_color.z, 0.1);
+    assert_close(blue.emission.z, 1.0);
+    // Surfaces without texture coordinates keep the factors.
+    assert_close(principled.at(None).base_color.x, 0.8);
+
+    let jpeg = textured_document().replace("data:image/png;base64,", "data:image/jpeg;base64,/9j/");
+    let mut scene = Scene::new(Units::Meters);
+    let error = parse_gltf(jpeg.as_bytes(), Path::new(""), &mut scene)
+        .err()
+        .unwrap();
+    assert!(error.to_string().contains("only PNG textures"), "{}", error);
+}
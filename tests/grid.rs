@@ -0,0 +1,134 @@
+//! Closest hits found through uniform grids against testing every object.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+use std::path::Path;
+
+/// Closest hit of `ray` testing the spheres one by one.
+fn closest_hit(spheres: &[Sphere], ray: &Ray) -> Option<(usize, Float)> {
+    let mut closest = None;
+    let mut closest_t = Float::INFINITY;
+    for (index, sphere) in spheres.iter().enumerate() {
+        if let Some(hit) = sphere.hit(ray, 1e-4, closest_t) {
+            closest_t = hit.t;
+            closest = Some((index, hit.t));
+        }
+    }
+    closest
+}
+
+fn random_point(extent: Float) -> Vec3 {
+    Vec3::new(
+        random_between(-extent, extent),
+        random_between(-extent, extent),
+        random_between(-extent, extent),
+    )
+}
+
+#[test]
+fn grids_find_the_closest_hit() {
+    seed_random(31);
+    let mut spheres: Vec<Sphere> = (0..300)
+        .map(|_| Sphere::new(random_point(10.0), random_between(0.05, 1.0), MaterialId(0)))
+        .collect();
+    // A large sphere spanning many cells.
+    spheres.push(Sphere::new(Vec3::new(2.0, -3.0, 1.0), 4.0, MaterialId(0)));
+    // And a ground much larger than the rest, left out of the cells.
+    spheres.push(Sphere::new(
+        Vec3::new(0.0, -108.0, 0.0),
+        100.0,
+        MaterialId(0),
+    ));
+    let grid = SphereGrid::new(&spheres);
+    for index in 0..3000 {
+        let mut direction = sample_unit_sphere((random_01(), random_01()));
+        if index % 10 == 0 {
+            direction = Vec3::new(0.0, 0.0, if index % 20 == 0 { 1.0 } else { -1.0 });
+        }
+        let ray = Ray::new(random_point(12.0), direction);
+        assert_eq!(
+            grid.hit(&ray, 1e-4, Float::INFINITY),
+            closest_hit(&spheres, &ray)
+        );
+    }
+
+    let empty = SphereGrid::new(&[]);
+    let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+    assert_eq!(empty.hit(&ray, 1e-4, Float::INFINITY), None);
+}
+
+#[test]
+fn grids_find_the_boxes_holding_a_point() {
+    seed_random(32);
+    let spheres: Vec<Sphere> = (0..200)
+        .map(|_| Sphere::new(random_point(10.0), random_between(0.05, 1.0), MaterialId(0)))
+        .collect();
+    let grid = SphereGrid::new(&spheres);
+    for _ in 0..500 {
+        let point = random_point(11.0);
+        let found = grid.candidates(point);
+        let expected: Vec<usize> = (0..spheres.len())
+            .filter(|&index| Aabb::of_sphere(&spheres[index]).contains(point))
+            .collect();
+        assert!(expected.iter().all(|index| found.contains(index)));
+    }
+}
+
+#[test]
+fn grids_lay_flat_scenes_in_one_layer() {
+    let boxes: Vec<Aabb> = (0..100)
+        .map(|index| {
+            let corner = Vec3::new((index % 10) as Float, 0.0, (index / 10) as Float);
+            Aabb::empty()
+                .include(corner)
+                .include(corner + Vec3::new(0.5, 0.0, 0.5))
+        })
+        .collect();
+    let grid = Grid::new(&boxes);
+    let [x, y, z] = grid.resolution();
+    assert_eq!(y, 1);
+    assert!(x > 5 && x == z, "{:?}", grid.resolution());
+
+    // Rays lying in the plane of the boxes still find them.
+    let ray = Ray::new(Vec3::new(-1.0, 0.0, 0.25), Vec3::new(1.0, 0.0, 0.0));
+    let hit = grid.hit_with(&ray, 0.0, Float::INFINITY, |item, _| {
+        boxes[item]
+            .clip(&ray, 0.0, Float::INFINITY)
+            .map(|(near, _)| near)
+    });
+    assert_eq!(hit, Some((0, 1.0)));
+}
+
+#[test]
+fn meshes_and_scenes_find_objects_through_grids() {
+    seed_random(33);
+    let positions: Vec<Vec3> = (0..600).map(|_| random_point(2.0)).collect();
+    let triangles: Vec<[usize; 3]> = (0..200).map(|i| [3 * i, 3 * i + 1, 3 * i + 2]).collect();
+    let bvh = Mesh::new(positions.clone(), triangles.clone(), MaterialId(0));
+    let grid =
+        Mesh::new(positions, triangles, MaterialId(0)).with_accelerator(AcceleratorKind::Grid);
+    assert_eq!(grid.accelerator_kind(), AcceleratorKind::Grid);
+    for _ in 0..2000 {
+        let origin = sample_unit_sphere((random_01(), random_01())) * 4.0;
+        let ray = Ray::new(origin, random_point(1.5) - origin);
+        let expected = bvh.hit(&ray, 1e-6, Float::INFINITY).map(|hit| hit.t);
+        assert_eq!(
+            grid.hit(&ray, 1e-6, Float::INFINITY).map(|hit| hit.t),
+            expected
+        );
+    }
+
+    let source = "sphere 0 -1000 0 1000 lambertian 0.5 0.5 0.5\n\
+                  sphere 0 1 0 1 metal 0.7 0.6 0.5 0\n\
+                  accelerator grid\n";
+    let scene = parse_scene(source, "grid", Path::new("")).unwrap();
+    assert_eq!(scene.accelerator_kind(), AcceleratorKind::Grid);
+    assert_eq!(
+        AcceleratorKind::from_name("grid"),
+        Some(AcceleratorKind::Grid)
+    );
+    let ray = Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+    let hit = scene.hit(&ray, 1e-4, Float::INFINITY).unwrap();
+    assert!((hit.t - 3.0).abs() < 1e-4);
+}
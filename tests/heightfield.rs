@@ -0,0 +1,146 @@
+//! Rays walking the grid of heightfields.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+const EPSILON: Float = 1e-9;
+
+fn assert_close(a: Float, b: Float) {
+    assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+}
+
+fn terrain(function: impl Fn(Float, Float) -> Float, resolution: usize) -> Heightfield {
+    Heightfield::from_function(
+        function,
+        (resolution, resolution),
+        Vec3::new(-2.0, 1.0, -2.0),
+        (4.0, 4.0),
+        2.0,
+        MaterialId(0),
+    )
+}
+
+/// Height of the terrain under `x`, `z`, in the scene.
+fn height_at(terrain: &Heightfield, x: Float, z: Float) -> Float {
+    let u = (x - terrain.origin.x) / terrain.size.0;
+    let v = 1.0 - (z - terrain.origin.z) / terrain.size.1;
+    terrain.surface_at(u, v).0.y
+}
+
+#[test]
+fn ramps_are_hit_where_they_rise_to_the_ray() {
+    // Rises from 1 at x = -2 to 3 at x = 2.
+    let ramp = terrain(|u, _| u, 9);
+    let down = |x: Float, z: Float| Ray::new(Vec3::new(x, 10.0, z), Vec3::new(0.0, -1.0, 0.0));
+
+    for &(x, z) in &[(-1.5, 0.3), (0.0, 0.0), (1.9, -1.9), (0.25, 0.75)] {
+        let hit = ramp.hit(&down(x, z), EPSILON, Float::INFINITY).unwrap();
+        assert_close(hit.position.y, 1.0 + (x + 2.0) / 2.0);
+        assert_close(hit.normal.dot(Vec3::new(-0.5, 1.0, 0.0).unit()), 1.0);
+        assert!(hit.front_face);
+    }
+    assert!(ramp
+        .hit(&down(2.5, 0.0), EPSILON, Float::INFINITY)
+        .is_none());
+
+    // Along the slope, from below the high end.
+    let across = Ray::new(Vec3::new(3.0, 2.0, 0.5), Vec3::new(-1.0, 0.0, 0.0));
+    let hit = ramp.hit(&across, EPSILON, Float::INFINITY).unwrap();
+    assert_close(hit.position.x, 0.0);
+    assert!(!hit.front_face);
+}
+
+#[test]
+fn hits_are_the_first_crossing_of_the_terrain() {
+    let hills = terrain(|u, v| fractal_noise(3, u * 4.0, v * 4.0, 5), 65);
+    let mut stream = RandomStream::new(1, "rays");
+    for _ in 0..200 {
+        let origin = Vec3::new(stream.between(-2.0, 2.0), 4.0, stream.between(-2.0, 2.0));
+        let target = Vec3::new(stream.between(-2.0, 2.0), 1.5, stream.between(-2.0, 2.0));
+        let ray = Ray::new(origin, target - origin);
+        let hit = match hills.hit(&ray, EPSILON, Float::INFINITY) {
+            Some(hit) => hit,
+            None => {
+                assert!(height_at(&hills, target.x, target.z) < target.y);
+                continue;
+            }
+        };
+        assert_close(
+            hit.position.y,
+            height_at(&hills, hit.position.x, hit.position.z),
+        );
+
+        // Nothing is crossed on the way.
+        for step in 1..100 {
+            let point = ray.at(hit.t * step as Float / 100.0);
+            let inside = point.x.abs() <= 2.0 && point.z.abs() <= 2.0;
+            assert!(!inside || point.y > height_at(&hills, point.x, point.z) - 1e-4);
+        }
+    }
+}
+
+#[test]
+fn rays_leaving_the_terrain_do_not_hit_it_again() {
+    let hills = terrain(|u, v| fractal_noise(5, u * 2.0, v * 2.0, 3), 33);
+    let ray = Ray::new(Vec3::new(0.3, 10.0, -0.2), Vec3::new(0.0, -1.0, 0.0));
+    let hit = hills.hit(&ray, EPSILON, Float::INFINITY).unwrap();
+
+    let up = Ray::new(hit.position, hills.normal_at(hit.position));
+    assert!(hills.hit(&up, 1e-6, Float::INFINITY).is_none());
+    let on = Ray::new(hit.position, Vec3::new(0.0, -1.0, 0.0));
+    assert!(hills.hit(&on, 1e-6, Float::INFINITY).is_none());
+}
+
+#[test]
+fn images_are_laid_with_their_top_towards_minus_z() {
+    // White top row, black bottom row.
+    let pixels = vec![
+        Vec3::new(1.0, 1.0, 1.0),
+        Vec3::new(1.0, 1.0, 1.0),
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 0.0),
+    ];
+    let texture = Texture::new(pixels, 2, 2);
+    let slope = Heightfield::from_texture(
+        &texture,
+        Vec3::new(0.0, 0.0, 0.0),
+        (1.0, 1.0),
+        1.0,
+        MaterialId(0),
+    );
+    assert_eq!(slope.resolution(), (2, 2));
+    assert_close(height_at(&slope, 0.5, 0.0), 1.0);
+    assert_close(height_at(&slope, 0.5, 1.0), 0.0);
+    let bounds = slope.bounding_sphere();
+    assert_close(bounds.radius, Float::sqrt(3.0) / 2.0);
+}
+
+#[test]
+fn fractal_noise_is_seeded() {
+    let mut same = true;
+    for i in 0..50 {
+        let (x, y) = (i as Float * 0.37, i as Float * 0.11);
+        let value = fractal_noise(1, x, y, 4);
+        assert!((0.0..=1.0).contains(&value));
+        assert_eq!(value, fractal_noise(1, x, y, 4));
+        same &= value == fractal_noise(2, x, y, 4);
+    }
+    assert!(!same);
+}
+
+#[test]
+fn scenes_hit_heightfields_through_their_ids() {
+    let mut scene = Scene::new(Units::Meters);
+    let gray = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    let mut flat = terrain(|_, _| 0.25, 5);
+    flat.material = gray;
+    let id = scene.add_shape(Shape::Heightfield(flat));
+
+    let down = Ray::new(Vec3::new(1.0, 5.0, 1.0), Vec3::new(0.0, -1.0, 0.0));
+    let (hit_id, hit) = scene.hit_object(&down, EPSILON, Float::INFINITY).unwrap();
+    assert_eq!(hit_id, id);
+    assert_close(hit.t, 3.5);
+    assert_eq!(hit.material, gray);
+}
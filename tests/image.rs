@@ -0,0 +1,93 @@
+//! Rendered images kept in memory, read and composited without files.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+const WIDTH: usize = 16;
+const HEIGHT: usize = 10;
+
+/// Red sphere over a transparent background.
+fn render() -> Image {
+    let mut scene = Scene::new(Units::Meters);
+    let red = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.8, 0.2, 0.1),
+    });
+    scene.add(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, red));
+    let settings = RenderSettings {
+        width: WIDTH,
+        height: HEIGHT,
+        samples_per_pixel: 4,
+        max_depth: 4,
+        transparent_background: true,
+        seed: 3,
+        threads: 1,
+        ..RenderSettings::default()
+    };
+    let camera = Camera::new(
+        Vec3::new(0.0, 0.0, 5.0),
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        WIDTH as Float / HEIGHT as Float,
+        0.0,
+        5.0,
+    );
+    Renderer::new(settings).unwrap().render(&scene, &camera)
+}
+
+#[test]
+fn pixels_are_read_row_by_row() {
+    let image = render();
+    assert_eq!((image.width(), image.height()), (WIDTH, HEIGHT));
+    assert_eq!(image.pixels().len(), WIDTH * HEIGHT * 4);
+    let (x, y) = (5, 7);
+    let index = (y * WIDTH + x) * 4;
+    assert_eq!(&image.pixel(x, y)[..], &image.pixels()[index..index + 4]);
+    // The sphere covers the middle, the corners are empty.
+    assert_eq!(image.pixel(WIDTH / 2, HEIGHT / 2)[3], 1.0);
+    assert_eq!(image.pixel(0, 0), [0.0; 4]);
+
+    let single = image.to_f32();
+    assert_eq!(single.pixel(x, y)[1], to_f32(image.pixel(x, y)[1]));
+    let double = image.to_f64();
+    assert_eq!(double.pixels().len(), image.pixels().len());
+
+    let crop = Crop {
+        x: 3,
+        y: 2,
+        width: 6,
+        height: 4,
+    };
+    let cropped = image.cropped(crop);
+    assert_eq!((cropped.width(), cropped.height()), (6, 4));
+    assert_eq!(cropped.pixel(2, 3), image.pixel(5, 5));
+
+    let mut edited = image.clone();
+    edited.set_pixel(x, y, [0.5, 0.25, 0.0, 1.0]);
+    assert_eq!(edited.pixel(x, y), [0.5, 0.25, 0.0, 1.0]);
+    assert_eq!(edited.into_pixels()[index + 1], 0.25);
+}
+
+#[test]
+fn images_composite_over_backgrounds() {
+    let image = render();
+    let blue = [0.0, 0.0, 1.0, 1.0];
+    let background = Image::new(WIDTH, HEIGHT, blue.repeat(WIDTH * HEIGHT));
+    let composite = image.over(&background);
+    // The background shows where the sphere doesn't cover it.
+    assert_eq!(composite.pixel(0, 0), blue);
+    assert_eq!(
+        composite.pixel(WIDTH / 2, HEIGHT / 2),
+        image.pixel(WIDTH / 2, HEIGHT / 2)
+    );
+    for (front, over) in image.pixels().chunks(4).zip(composite.pixels().chunks(4)) {
+        assert!((over[3] - 1.0).abs() < 1e-6);
+        assert!((over[2] - (front[2] + 1.0 - front[3])).abs() < 1e-6);
+    }
+}
+
+#[test]
+#[should_panic(expected = "outside the 16x10 image")]
+fn pixels_outside_images_panic() {
+    render().pixel(WIDTH, 0);
+}
@@ -0,0 +1,134 @@
+//! Instances of meshes with levels of detail.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+use std::fs;
+use std::sync::Arc;
+
+const EPSILON: Float = 1e-9;
+
+fn assert_close(a: Float, b: Float) {
+    assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+}
+
+/// Square from -1 to 1 in x and z at height `y`, facing up.
+fn square(y: Float) -> Mesh {
+    let positions = vec![
+        Vec3::new(-1.0, y, -1.0),
+        Vec3::new(-1.0, y, 1.0),
+        Vec3::new(1.0, y, 1.0),
+        Vec3::new(1.0, y, -1.0),
+    ];
+    Mesh::new(positions, vec![[0, 1, 2], [0, 2, 3]], MaterialId(0))
+}
+
+/// Levels told apart by their height: the finest at 0, used up to 10 units
+/// away, then one at -0.5.
+fn levels() -> Arc<LevelsOfDetail> {
+    Arc::new(LevelsOfDetail::new(square(0.0)).with_level(10.0, square(-0.5)))
+}
+
+#[test]
+fn instances_are_placed_by_their_transform() {
+    let placement = Mat4::translation(Vec3::new(5.0, 1.0, 0.0))
+        * Mat4::rotation_y(consts::FRAC_PI_4)
+        * Mat4::scaling(Vec3::new(2.0, 2.0, 2.0));
+    let instance = Instance::new(levels(), &placement, MaterialId(3));
+
+    let down = Ray::new(Vec3::new(6.0, 4.0, 0.5), Vec3::new(0.0, -1.0, 0.0));
+    let hit = instance.hit(&down, EPSILON, Float::INFINITY).unwrap();
+    assert_close(hit.t, 3.0);
+    assert!(hit.front_face);
+    assert_close(hit.normal.y, 1.0);
+    assert_eq!(hit.material, MaterialId(3));
+    assert_close(instance.normal_at(hit.position).y, 1.0);
+    // The corners of the square are 2 * sqrt(2) from its center once scaled,
+    // its diagonals turned onto the x and z axes.
+    let corner = Ray::new(Vec3::new(7.7, 4.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+    assert!(instance.hit(&corner, EPSILON, Float::INFINITY).is_some());
+    let past = Ray::new(Vec3::new(5.0, 4.0, 2.9), Vec3::new(0.0, -1.0, 0.0));
+    assert!(instance.hit(&past, EPSILON, Float::INFINITY).is_none());
+
+    // Around both levels, the coarse one half a unit below the fine one.
+    let bounds = instance.bounding_sphere();
+    assert!((bounds.position - Vec3::new(5.0, 1.0, 0.0)).length() < 1e-4);
+    assert_close(bounds.radius, 2.0 * (0.5 + Float::sqrt(2.0)));
+
+    let mut scaled = Shape::Instance(instance);
+    scaled.scale(0.5);
+    let down = Ray::new(Vec3::new(3.0, 4.0, 0.25), Vec3::new(0.0, -1.0, 0.0));
+    assert_close(scaled.hit(&down, EPSILON, Float::INFINITY).unwrap().t, 3.5);
+}
+
+#[test]
+fn distant_rays_see_coarser_levels() {
+    let scale = 3.0;
+    let placement = Mat4::scaling(Vec3::new(scale, scale, scale));
+    let instance = Instance::new(levels(), &placement, MaterialId(0));
+    let ray_from = |height: Float| Ray::new(Vec3::new(0.0, height, 0.0), Vec3::new(0.0, -1.0, 0.0));
+
+    // Distances count in the units of the meshes, three times smaller.
+    let near = ray_from(29.0);
+    assert_eq!(instance.level_for(&near), 0);
+    assert_close(
+        instance.hit(&near, EPSILON, Float::INFINITY).unwrap().t,
+        29.0,
+    );
+    let far = ray_from(40.0);
+    assert_eq!(instance.level_for(&far), 1);
+    assert_close(
+        instance.hit(&far, EPSILON, Float::INFINITY).unwrap().t,
+        41.5,
+    );
+
+    // Halfway through the blend, rays pick either level about as often.
+    let mut stream = RandomStream::new(2, "origins");
+    let count = 2000;
+    let coarse = (0..count)
+        .filter(|_| {
+            // Around the center, 11.25 units away in the space of the mesh.
+            let offset = Vec3::new(stream.between(-0.1, 0.1), 0.0, stream.between(-0.1, 0.1));
+            let origin = Vec3::new(0.0, 11.25 * scale, 0.0) + offset;
+            let origin = origin.unit() * (11.25 * scale);
+            instance.level_for(&Ray::new(origin, -origin)) == 1
+        })
+        .count();
+    let share = coarse as Float / count as Float;
+    assert!((share - 0.5).abs() < 0.05, "{}", share);
+}
+
+#[test]
+fn scene_files_place_instances_of_named_lods() {
+    let directory = std::env::temp_dir().join(format!("raytracer-lod-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let obj = |y: Float| {
+        format!(
+            "v -1 {y} -1\nv -1 {y} 1\nv 1 {y} 1\nv 1 {y} -1\nf 1 2 3 4\n",
+            y = y
+        )
+    };
+    fs::write(directory.join("fine.obj"), obj(0.0)).unwrap();
+    fs::write(directory.join("coarse.obj"), obj(-0.5)).unwrap();
+    let source = "lod square fine.obj 10 coarse.obj
+instance 0 0 0 1 0 square lambertian 0.5 0.5 0.5 name first
+instance 5 0 0 2 90 square lambertian 0.5 0.5 0.5";
+    let scene = parse_scene(source, "lod", &directory).unwrap();
+    let first = scene.sphere_named("first").unwrap();
+    match scene.shape(first) {
+        Some(Shape::Instance(instance)) => assert_eq!(instance.levels().level_count(), 2),
+        other => panic!("{:?}", other),
+    }
+    let down = Ray::new(Vec3::new(5.0, 3.0, 1.5), Vec3::new(0.0, -1.0, 0.0));
+    let (_, hit) = scene.hit_object(&down, EPSILON, Float::INFINITY).unwrap();
+    assert_close(hit.t, 3.0);
+
+    for broken in &[
+        "instance 0 0 0 1 0 nothing lambertian 0.5 0.5 0.5",
+        "lod square fine.obj 10 coarse.obj 5 fine.obj",
+        "lod square fine.obj\nlod square coarse.obj",
+    ] {
+        assert!(parse_scene(broken, "lod", &directory).is_err());
+    }
+    fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,507 @@
+//! Rays with known analytic answers fired at every primitive.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+const EPSILON: Float = 1e-9;
+
+fn material() -> MaterialId {
+    MaterialId(0)
+}
+
+fn assert_close(a: Float, b: Float) {
+    assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+}
+
+fn assert_vec_close(a: Vec3, b: Vec3) {
+    assert!((a - b).length() < 1e-6, "{:?} != {:?}", a, b);
+}
+
+fn unit_sphere() -> Sphere {
+    Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, material())
+}
+
+#[test]
+fn sphere_head_on_hit() {
+    let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+    let hit = unit_sphere().hit(&ray, EPSILON, Float::INFINITY).unwrap();
+
+    assert_close(hit.t, 4.0);
+    assert_vec_close(hit.position, Vec3::new(0.0, 0.0, -1.0));
+    assert_vec_close(hit.normal, Vec3::new(0.0, 0.0, -1.0));
+    assert!(hit.front_face);
+}
+
+#[test]
+fn sphere_t_scales_with_direction_length() {
+    let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 2.0));
+    let hit = unit_sphere().hit(&ray, EPSILON, Float::INFINITY).unwrap();
+
+    assert_close(hit.t, 2.0);
+    assert_vec_close(hit.position, Vec3::new(0.0, 0.0, -1.0));
+}
+
+#[test]
+fn sphere_off_center_hit() {
+    // Offset by half the radius, the entry point is at z = -sqrt(3) / 2.
+    let ray = Ray::new(Vec3::new(0.5, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+    let hit = unit_sphere().hit(&ray, EPSILON, Float::INFINITY).unwrap();
+
+    let z = -Float::sqrt(3.0) / 2.0;
+    assert_close(hit.t, 5.0 + z);
+    assert_vec_close(hit.normal, Vec3::new(0.5, 0.0, z));
+}
+
+#[test]
+fn sphere_tangent_ray_touches_once() {
+    let ray = Ray::new(Vec3::new(-5.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+    let hit = unit_sphere().hit(&ray, EPSILON, Float::INFINITY).unwrap();
+
+    assert_close(hit.t, 5.0);
+    assert_vec_close(hit.position, Vec3::new(0.0, 1.0, 0.0));
+}
+
+#[test]
+fn sphere_grazing_ray_just_outside_misses() {
+    let ray = Ray::new(Vec3::new(-5.0, 1.0 + 1e-7, 0.0), Vec3::new(1.0, 0.0, 0.0));
+
+    assert!(unit_sphere().hit(&ray, EPSILON, Float::INFINITY).is_none());
+}
+
+#[test]
+fn sphere_grazing_ray_just_inside_hits_twice() {
+    let ray = Ray::new(Vec3::new(-5.0, 1.0 - 1e-7, 0.0), Vec3::new(1.0, 0.0, 0.0));
+    let sphere = unit_sphere();
+    let first = sphere.hit(&ray, EPSILON, Float::INFINITY).unwrap();
+    let second = sphere
+        .hit(&ray, first.t + EPSILON, Float::INFINITY)
+        .unwrap();
+
+    assert!(first.t < 5.0 && second.t > 5.0);
+    assert!(second.t - first.t < 1e-2);
+}
+
+#[test]
+fn sphere_ray_from_inside_hits_back_face() {
+    let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+    let hit = unit_sphere().hit(&ray, EPSILON, Float::INFINITY).unwrap();
+
+    assert_close(hit.t, 1.0);
+    assert!(!hit.front_face);
+    // Shading normals always face the incoming ray.
+    assert_vec_close(hit.normal, Vec3::new(0.0, -1.0, 0.0));
+}
+
+#[test]
+fn sphere_behind_origin_is_missed() {
+    let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, 1.0));
+
+    assert!(unit_sphere().hit(&ray, EPSILON, Float::INFINITY).is_none());
+}
+
+#[test]
+fn sphere_respects_t_range() {
+    let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+    let sphere = unit_sphere();
+
+    assert!(sphere.hit(&ray, EPSILON, 3.9).is_none());
+
+    // Skipping the entry point returns the exit point.
+    let exit = sphere.hit(&ray, 4.5, Float::INFINITY).unwrap();
+    assert_close(exit.t, 6.0);
+    assert!(!exit.front_face);
+}
+
+#[test]
+fn sphere_light_samples_point_at_the_sphere() {
+    let sphere = Sphere::new(Vec3::new(0.0, 3.0, 0.0), 0.5, material());
+    let origin = Vec3::new(0.0, 0.0, 0.0);
+
+    for i in 0..100 {
+        let u = ((i % 10) as Float / 10.0, (i / 10) as Float / 10.0);
+        let direction = sphere.sample_direction_towards(origin, u);
+        assert!(sphere
+            .hit(&Ray::new(origin, direction), EPSILON, Float::INFINITY)
+            .is_some());
+        assert!(sphere.pdf_value(origin, direction) > 0.0);
+    }
+}
+
+#[test]
+fn hit_record_side_follows_the_ray() {
+    let position = Vec3::new(0.0, 1.0, 0.0);
+    let outward = Vec3::new(0.0, 2.0, 0.0);
+
+    let down = Ray::new(Vec3::new(0.0, 3.0, 0.0), Vec3::new(0.3, -1.0, 0.0));
+    let hit = HitRecord::new(&down, position, outward, 2.0, material());
+    assert!(hit.front_face);
+    assert_vec_close(hit.normal, Vec3::new(0.0, 1.0, 0.0));
+
+    let up = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.3, 1.0, 0.0));
+    let hit = HitRecord::new(&up, position, outward, 1.0, material());
+    assert!(!hit.front_face);
+    assert_vec_close(hit.normal, Vec3::new(0.0, -1.0, 0.0));
+}
+
+#[test]
+fn one_sided_materials_only_shade_their_outside() {
+    let mut scene = Scene::new(Units::Meters);
+    let diffuse = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    let light = scene.add_material(MaterialType::DiffuseLight {
+        emit: Vec3::new(1.0, 1.0, 1.0),
+    });
+    assert!(scene.two_sided(diffuse));
+    assert!(!scene.two_sided(light));
+
+    let inside = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+    let outside = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+    let sphere = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, diffuse);
+    let back = sphere.hit(&inside, EPSILON, Float::INFINITY).unwrap();
+    let front = sphere.hit(&outside, EPSILON, Float::INFINITY).unwrap();
+    assert!(scene.shades(&back) && scene.shades(&front));
+
+    scene.set_two_sided(diffuse, false);
+    assert!(!scene.shades(&back) && scene.shades(&front));
+}
+
+#[test]
+fn sphere_packets_match_single_spheres() {
+    seed_random(3);
+    let spheres: Vec<Sphere> = (0..3)
+        .map(|_| {
+            let center = Vec3::new(random_between(-2.0, 2.0), random_between(-2.0, 2.0), 0.0);
+            Sphere::new(center, random_between(0.1, 1.5), material())
+        })
+        .collect();
+    let packet = SpherePacket::new(&spheres);
+
+    for _ in 0..1000 {
+        let origin = Vec3::new(random_between(-4.0, 4.0), random_between(-4.0, 4.0), -5.0);
+        let ray = Ray::new(origin, sample_unit_sphere((random_01(), random_01())));
+        let distances = packet.hit_distances(&ray, EPSILON, 8.0);
+        for (sphere, &distance) in spheres.iter().zip(&distances) {
+            let t = sphere.hit(&ray, EPSILON, 8.0).map(|hit| hit.t);
+            assert_eq!(t.unwrap_or(Float::INFINITY), distance);
+        }
+        // The fourth lane is unused.
+        assert_eq!(distances[3], Float::INFINITY);
+    }
+}
+
+#[test]
+fn culled_spheres_are_only_hit_from_outside() {
+    let mut sphere = unit_sphere();
+    sphere.cull_backfaces = true;
+    let packet = SpherePacket::new(&[sphere, unit_sphere()]);
+
+    let outside = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+    let hit = sphere.hit(&outside, EPSILON, Float::INFINITY).unwrap();
+    assert_close(hit.t, 4.0);
+    assert!(hit.front_face);
+    assert_eq!(
+        packet.hit_distances(&outside, EPSILON, Float::INFINITY)[0],
+        hit.t
+    );
+
+    // Past the front of the sphere only the back is left.
+    assert!(sphere.hit(&outside, 4.5, Float::INFINITY).is_none());
+    assert_eq!(
+        packet.hit_distances(&outside, 4.5, Float::INFINITY)[0],
+        Float::INFINITY
+    );
+
+    let inside = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+    assert!(sphere.hit(&inside, EPSILON, Float::INFINITY).is_none());
+    let distances = packet.hit_distances(&inside, EPSILON, Float::INFINITY);
+    assert_eq!(distances[0], Float::INFINITY);
+    assert_close(distances[1], 1.0);
+}
+
+#[test]
+fn planes_are_hit_from_both_sides() {
+    let plane = Plane::new(
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, 2.0, 0.0),
+        material(),
+    );
+
+    let down = Ray::new(Vec3::new(1.0, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+    let hit = plane.hit(&down, EPSILON, Float::INFINITY).unwrap();
+    assert_close(hit.t, 2.0);
+    assert_vec_close(hit.position, Vec3::new(1.0, 1.0, 0.0));
+    assert!(hit.front_face);
+
+    let up = Ray::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.5, 1.0, 0.0));
+    let hit = plane.hit(&up, EPSILON, Float::INFINITY).unwrap();
+    assert_close(hit.t, 2.0);
+    assert!(!hit.front_face);
+    assert_vec_close(hit.normal, Vec3::new(0.0, -1.0, 0.0));
+
+    let parallel = Ray::new(Vec3::new(0.0, 3.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+    assert!(plane.hit(&parallel, EPSILON, Float::INFINITY).is_none());
+    assert!(plane.hit(&down, EPSILON, 1.5).is_none());
+
+    // `u` follows +x and `v` goes away from a camera looking down -z.
+    let frame = plane.uv_frame(Vec3::new(2.0, 1.0, -3.0));
+    assert_close(frame.uv.0, 2.0);
+    assert_close(frame.uv.1, 3.0);
+    assert_vec_close(frame.tangents.0, Vec3::new(1.0, 0.0, 0.0));
+    assert_vec_close(frame.tangents.1, Vec3::new(0.0, 0.0, -1.0));
+    let (position, normal) = plane.surface_at(2.0, 3.0);
+    assert_vec_close(position, Vec3::new(2.0, 1.0, -3.0));
+    assert_vec_close(normal, plane.normal);
+}
+
+#[test]
+fn disk_light_samples_cover_the_disk() {
+    let disk = Disk::new(
+        Vec3::new(0.0, 3.0, 0.0),
+        Vec3::new(0.0, -1.0, 0.0),
+        0.5,
+        material(),
+    );
+    let origin = Vec3::new(0.0, 0.0, 0.0);
+    let rim = Ray::new(origin, Vec3::new(0.6, 3.0, 0.0));
+    assert!(disk.hit(&rim, EPSILON, Float::INFINITY).is_none());
+    let frame = disk.uv_frame(Vec3::new(0.5, 3.0, 0.0));
+    assert_close(frame.uv.0, 1.0);
+    assert_close(frame.uv.1, 0.5);
+
+    // Away from the corners of the square, which land on the rim.
+    for i in 0..100 {
+        let u = (
+            ((i % 10) as Float + 0.5) / 10.0,
+            ((i / 10) as Float + 0.5) / 10.0,
+        );
+        let direction = disk.sample_direction_towards(origin, u);
+        assert!(disk
+            .hit(&Ray::new(origin, direction), EPSILON, Float::INFINITY)
+            .is_some());
+        assert!(disk.pdf_value(origin, direction) > 0.0);
+    }
+
+    // The density integrates to one over the sphere of directions.
+    seed_random(5);
+    let samples = 200_000;
+    let mut sum = 0.0;
+    for _ in 0..samples {
+        let direction = sample_unit_sphere((random_01(), random_01()));
+        sum += disk.pdf_value(origin, direction) * 4.0 * consts::PI;
+    }
+    let integral = sum / samples as Float;
+    assert!((integral - 1.0).abs() < 0.05, "{}", integral);
+}
+
+#[test]
+fn scenes_hit_planes_and_disks_beside_spheres() {
+    let mut scene = Scene::new(Units::Meters);
+    let gray = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    let light = scene.add_material(MaterialType::DiffuseLight {
+        emit: Vec3::new(4.0, 4.0, 4.0),
+    });
+    let ball = scene.add(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, gray));
+    let ground = scene.add_plane(Plane::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        gray,
+    ));
+    let lamp = scene.add_disk_light(Disk::new(
+        Vec3::new(0.0, 4.0, 0.0),
+        Vec3::new(0.0, -1.0, 0.0),
+        1.0,
+        light,
+    ));
+    assert_eq!(scene.lights, vec![lamp]);
+
+    let down = |x: Float| Ray::new(Vec3::new(x, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+    let (id, hit) = scene
+        .hit_object(&down(0.0), EPSILON, Float::INFINITY)
+        .unwrap();
+    assert_eq!(id, ball);
+    assert_close(hit.t, 1.0);
+    let (id, hit) = scene
+        .hit_object(&down(3.0), EPSILON, Float::INFINITY)
+        .unwrap();
+    assert_eq!(id, ground);
+    assert_close(hit.t, 3.0);
+    let hits = scene.hit_objects(&[down(0.0), down(3.0)], EPSILON, &[10.0, 10.0]);
+    assert_eq!(hits[0].map(|(id, _)| id), Some(ball));
+    assert_eq!(hits[1].map(|(id, _)| id), Some(ground));
+
+    let up = Ray::new(Vec3::new(0.5, 2.5, 0.0), Vec3::new(0.0, 1.0, 0.0));
+    let (id, _) = scene.hit_object(&up, EPSILON, Float::INFINITY).unwrap();
+    assert_eq!(id, lamp);
+
+    // Planes and disks follow their ids when spheres before them go.
+    scene.remove_spheres(&[ball]);
+    let (id, _) = scene
+        .hit_object(&down(0.0), EPSILON, Float::INFINITY)
+        .unwrap();
+    assert_eq!(id, ObjectId(0));
+    assert_eq!(scene.lights, vec![ObjectId(1)]);
+    assert!(scene.shape(ObjectId(1)).is_some());
+}
+
+#[test]
+fn quadrics_are_hit_on_their_sides_and_ends() {
+    let material = MaterialId(0);
+    let up = Vec3::new(0.0, 1.0, 0.0);
+    let down = |x: Float| Ray::new(Vec3::new(x, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+    let across = |y: Float| Ray::new(Vec3::new(-5.0, y, 0.0), Vec3::new(1.0, 0.0, 0.0));
+
+    let cylinder = Cylinder::new(Vec3::new(0.0, 0.0, 0.0), up, 2.0, 1.0, material);
+    let hit = cylinder
+        .hit(&across(1.0), EPSILON, Float::INFINITY)
+        .unwrap();
+    assert_close(hit.t, 4.0);
+    assert_close(hit.normal.x, -1.0);
+    assert_close(cylinder.uv_frame(hit.position).uv.1, 0.5);
+    let hit = cylinder.hit(&down(0.5), EPSILON, Float::INFINITY).unwrap();
+    assert_close(hit.t, 3.0);
+    assert_close(hit.normal.y, 1.0);
+    assert!(cylinder
+        .hit(&across(2.5), EPSILON, Float::INFINITY)
+        .is_none());
+    // From the inside, rays leave through the far wall.
+    let inside = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+    let hit = cylinder.hit(&inside, EPSILON, Float::INFINITY).unwrap();
+    assert_close(hit.t, 1.0);
+    assert!(!hit.front_face);
+
+    // The apex is at the top, so the side narrows on the way up.
+    let cone = Cone::new(Vec3::new(0.0, 0.0, 0.0), up, 2.0, 1.0, material);
+    let hit = cone.hit(&across(1.0), EPSILON, Float::INFINITY).unwrap();
+    assert_close(hit.t, 4.5);
+    let slope = Vec3::new(-2.0, 1.0, 0.0).unit();
+    assert_close(hit.normal.dot(slope), 1.0);
+    let hit = cone.hit(&down(0.5), EPSILON, Float::INFINITY).unwrap();
+    assert_close(hit.t, 4.0);
+    let below = Ray::new(Vec3::new(0.5, -1.0, 0.0), up);
+    let hit = cone.hit(&below, EPSILON, Float::INFINITY).unwrap();
+    assert_close(hit.t, 1.0);
+    assert_close(hit.normal.y, -1.0);
+
+    let capsule = Capsule::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 2.0, 0.0),
+        1.0,
+        material,
+    );
+    let hit = capsule.hit(&down(0.0), EPSILON, Float::INFINITY).unwrap();
+    assert_close(hit.t, 2.0);
+    assert_close(hit.normal.y, 1.0);
+    let hit = capsule.hit(&across(1.0), EPSILON, Float::INFINITY).unwrap();
+    assert_close(hit.t, 4.0);
+    assert_close(hit.normal.x, -1.0);
+    let hit = capsule
+        .hit(&across(-0.5), EPSILON, Float::INFINITY)
+        .unwrap();
+    assert_close(hit.normal.length(), 1.0);
+    assert!(hit.normal.y < 0.0);
+    assert!(capsule
+        .hit(&across(-1.5), EPSILON, Float::INFINITY)
+        .is_none());
+}
+
+#[test]
+fn scenes_hit_quadrics_through_their_ids() {
+    let mut scene = Scene::new(Units::Meters);
+    let gray = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    let ball = scene.add(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, gray));
+    let post = scene.add_shape(Shape::Cylinder(Cylinder::new(
+        Vec3::new(4.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        3.0,
+        0.5,
+        gray,
+    )));
+    // The bounding sphere reaches the rims of both caps.
+    let bounds = scene.sphere(post);
+    assert_close((bounds.position - Vec3::new(4.0, 1.5, 0.0)).length(), 0.0);
+    assert_close(bounds.radius, (1.5 as Float).hypot(0.5));
+
+    let down = |x: Float| Ray::new(Vec3::new(x, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+    let (id, hit) = scene
+        .hit_object(&down(4.0), EPSILON, Float::INFINITY)
+        .unwrap();
+    assert_eq!(id, post);
+    assert_close(hit.t, 2.0);
+    let (id, _) = scene
+        .hit_object(&down(0.0), EPSILON, Float::INFINITY)
+        .unwrap();
+    assert_eq!(id, ball);
+    assert!(scene
+        .hit_object(&down(2.0), EPSILON, Float::INFINITY)
+        .is_none());
+}
+
+#[test]
+fn scenes_find_quadrics_through_every_accelerator() {
+    seed_random(41);
+    let mut scene = Scene::new(Units::Meters);
+    let gray = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    let random_point = || {
+        Vec3::new(
+            random_between(-10.0, 10.0),
+            random_between(0.0, 10.0),
+            random_between(-10.0, 10.0),
+        )
+    };
+    for _ in 0..200 {
+        let start = random_point();
+        let end = start + sample_unit_sphere((random_01(), random_01()));
+        scene.add_shape(Shape::Capsule(Capsule::new(start, end, 0.3, gray)));
+        scene.add(Sphere::new(random_point(), 0.5, gray));
+    }
+    scene.add_plane(Plane::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        gray,
+    ));
+
+    let rays: Vec<Ray> = (0..400)
+        .map(|_| {
+            let origin = random_point() * 1.5;
+            Ray::new(origin, random_point() - origin)
+        })
+        .collect();
+    let closest_hit = |scene: &Scene, ray: &Ray| {
+        let mut closest = None;
+        let mut closest_t = Float::INFINITY;
+        for index in 0..scene.spheres.len() {
+            if let Some(hit) = scene.surface(ObjectId(index)).hit(ray, EPSILON, closest_t) {
+                closest_t = hit.t;
+                closest = Some((ObjectId(index), hit.t));
+            }
+        }
+        closest
+    };
+    let expected: Vec<_> = rays.iter().map(|ray| closest_hit(&scene, ray)).collect();
+    assert!(expected
+        .iter()
+        .any(|hit| hit.is_some_and(|(id, _)| scene.shape(id).is_some())));
+
+    let t_max = vec![Float::INFINITY; rays.len()];
+    for &kind in &AcceleratorKind::ALL {
+        scene.set_accelerator_kind(kind);
+        let hits = scene.hit_objects(&rays, EPSILON, &t_max);
+        for ((ray, expected), packet_hit) in rays.iter().zip(&expected).zip(hits) {
+            let hit = scene.hit_object(ray, EPSILON, Float::INFINITY);
+            assert_eq!(hit.map(|(id, hit)| (id, hit.t)), *expected, "{:?}", kind);
+            assert_eq!(
+                packet_hit.map(|(id, hit)| (id, hit.t)),
+                *expected,
+                "{:?}",
+                kind
+            );
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! Closest hits found through kd-trees against testing every object.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+use std::path::Path;
+
+/// Closest hit of `ray` testing the spheres one by one.
+fn closest_hit(spheres: &[Sphere], ray: &Ray) -> Option<(usize, Float)> {
+    let mut closest = None;
+    let mut closest_t = Float::INFINITY;
+    for (index, sphere) in spheres.iter().enumerate() {
+        if let Some(hit) = sphere.hit(ray, 1e-4, closest_t) {
+            closest_t = hit.t;
+            closest = Some((index, hit.t));
+        }
+    }
+    closest
+}
+
+fn random_spheres(count: usize) -> Vec<Sphere> {
+    (0..count)
+        .map(|_| {
+            let center = Vec3::new(
+                random_between(-10.0, 10.0),
+                random_between(-10.0, 10.0),
+                random_between(-10.0, 10.0),
+            );
+            Sphere::new(center, random_between(0.05, 1.0), MaterialId(0))
+        })
+        .collect()
+}
+
+/// Ray from anywhere around the spheres, every tenth along an axis.
+fn random_ray(index: i32) -> Ray {
+    let origin = Vec3::new(
+        random_between(-12.0, 12.0),
+        random_between(-12.0, 12.0),
+        random_between(-12.0, 12.0),
+    );
+    let mut direction = sample_unit_sphere((random_01(), random_01()));
+    if index % 10 == 0 {
+        direction = Vec3::new(0.0, if index % 20 == 0 { 1.0 } else { -1.0 }, 0.0);
+    }
+    Ray::new(origin, direction)
+}
+
+#[test]
+fn kd_trees_find_the_closest_hit() {
+    seed_random(21);
+    let spheres = random_spheres(300);
+    let tree = SphereKdTree::new(&spheres);
+    for index in 0..3000 {
+        let ray = random_ray(index);
+        assert_eq!(
+            tree.hit(&ray, 1e-4, Float::INFINITY),
+            closest_hit(&spheres, &ray)
+        );
+    }
+
+    let empty = SphereKdTree::new(&[]);
+    let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+    assert_eq!(empty.hit(&ray, 1e-4, Float::INFINITY), None);
+}
+
+#[test]
+fn kd_trees_find_the_boxes_holding_a_point() {
+    seed_random(22);
+    let spheres = random_spheres(200);
+    let tree = SphereKdTree::new(&spheres);
+    for _ in 0..500 {
+        let point = Vec3::new(
+            random_between(-11.0, 11.0),
+            random_between(-11.0, 11.0),
+            random_between(-11.0, 11.0),
+        );
+        let mut found = tree.candidates(point);
+        found.sort_unstable();
+        let expected: Vec<usize> = (0..spheres.len())
+            .filter(|&index| Aabb::of_sphere(&spheres[index]).contains(point))
+            .collect();
+        assert!(expected.iter().all(|index| found.contains(index)));
+        let mut unique = found.clone();
+        unique.dedup();
+        assert_eq!(unique, found);
+    }
+}
+
+/// Bumpy ball of many triangles.
+fn ball() -> Mesh {
+    let (rows, columns) = (30, 40);
+    let mut positions = Vec::new();
+    for row in 0..=rows {
+        let theta = consts::PI * row as Float / rows as Float;
+        for column in 0..columns {
+            let phi = 2.0 * consts::PI * column as Float / columns as Float;
+            let radius = 1.0 + 0.1 * (5.0 * phi).sin();
+            positions.push(Vec3::new(
+                radius * theta.sin() * phi.cos(),
+                theta.cos(),
+                radius * theta.sin() * phi.sin(),
+            ));
+        }
+    }
+    let mut triangles = Vec::new();
+    for row in 0..rows {
+        for column in 0..columns {
+            let next = (column + 1) % columns;
+            let (a, b) = (row * columns + column, row * columns + next);
+            let (c, d) = (a + columns, b + columns);
+            triangles.push([a, c, b]);
+            triangles.push([b, c, d]);
+        }
+    }
+    Mesh::new(positions, triangles, MaterialId(0))
+}
+
+#[test]
+fn meshes_hit_the_same_through_kd_trees() {
+    seed_random(23);
+    let bvh = ball();
+    let kd_tree = ball().with_accelerator(AcceleratorKind::KdTree);
+    assert_eq!(kd_tree.accelerator_kind(), AcceleratorKind::KdTree);
+    let moved = kd_tree.transformed(&Mat4::translation(Vec3::new(1.0, 0.0, 0.0)));
+    assert_eq!(moved.accelerator_kind(), AcceleratorKind::KdTree);
+
+    for _ in 0..2000 {
+        let origin = sample_unit_sphere((random_01(), random_01())) * 3.0;
+        let target = sample_unit_sphere((random_01(), random_01())) * 0.8;
+        let ray = Ray::new(origin, target - origin);
+        let expected = bvh.hit(&ray, 1e-6, Float::INFINITY);
+        let found = kd_tree.hit(&ray, 1e-6, Float::INFINITY);
+        match (expected, found) {
+            (Some(expected), Some(found)) => {
+                assert_eq!(expected.t, found.t);
+                assert_eq!(expected.normal.x, found.normal.x);
+            }
+            (expected, found) => assert_eq!(expected.is_some(), found.is_some()),
+        }
+    }
+}
+
+#[test]
+fn scenes_pick_their_accelerator() {
+    let source = "sphere 0 -1000 0 1000 lambertian 0.5 0.5 0.5\n\
+                  sphere 0 1 0 1 metal 0.7 0.6 0.5 0\n\
+                  sphere 3 1 0 1 lambertian 0.2 0.3 0.8\n\
+                  accelerator kd-tree\n";
+    let mut scene = parse_scene(source, "kd", Path::new("")).unwrap();
+    assert_eq!(scene.accelerator_kind(), AcceleratorKind::KdTree);
+
+    let camera = Camera::new(
+        Vec3::new(13.0, 2.0, 3.0),
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        20.0,
+        1.5,
+        0.0,
+        10.0,
+    );
+    let timings = benchmark_accelerators(&mut scene, &camera, 30, 20);
+    assert_eq!(timings.len(), AcceleratorKind::ALL.len());
+    assert!(timings.iter().all(|timing| timing.rays == 600));
+    assert!(timings.iter().all(|timing| timing.hits == timings[0].hits));
+    assert!(timings[0].hits > 0 && timings[0].hits < 600);
+    assert_eq!(scene.accelerator_kind(), AcceleratorKind::KdTree);
+
+    let error = parse_scene("accelerator octree", "kd", Path::new(""))
+        .err()
+        .unwrap();
+    assert!(
+        error.to_string().contains("unknown accelerator"),
+        "{}",
+        error
+    );
+}
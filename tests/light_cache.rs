@@ -0,0 +1,41 @@
+//! Lights culled by the light cache.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+#[test]
+fn lights_behind_walls_are_culled() {
+    // A closed room with a lamp inside and a sun outside.
+    let mut scene = Scene::new(Units::Meters);
+    let wall = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.7, 0.7, 0.7),
+    });
+    let light = scene.add_material(MaterialType::DiffuseLight {
+        emit: Vec3::new(4.0, 4.0, 4.0),
+    });
+    scene.add(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 10.0, wall));
+    let lamp = scene.add_light(Sphere::new(Vec3::new(0.0, 5.0, 0.0), 1.0, light));
+    scene.add_light(Sphere::new(Vec3::new(0.0, 30.0, 0.0), 5.0, light));
+    let camera = Camera::new(
+        Vec3::new(0.0, 0.0, 8.0),
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        90.0,
+        1.5,
+        0.0,
+        8.0,
+    );
+
+    let cache = LightCache::build(&scene, &camera, 4);
+    let wall = Vec3::new(0.0, 0.0, -9.99);
+    assert_eq!(cache.lights(wall), Some(&[lamp][..]));
+    // Away from the surfaces the camera sees, nothing is known.
+    assert_eq!(cache.lights(Vec3::new(0.0, 30.0, 0.0)), None);
+
+    let towards_lamp = Vec3::new(0.0, 5.0, 0.0) - wall;
+    let towards_sun = Vec3::new(0.0, 30.0, 0.0) - wall;
+    assert!(scene.light_pdf(wall, towards_sun) > 0.0);
+    scene.set_light_cache(Some(cache));
+    assert!(scene.light_pdf(wall, towards_lamp) > 0.0);
+    assert_eq!(scene.light_pdf(wall, towards_sun), 0.0);
+}
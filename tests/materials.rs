@@ -0,0 +1,340 @@
+//! Sampling of microfacet and principled materials checked against their
+//! densities.
+
+use raytracer::maths::shading::*;
+use raytracer::maths::*;
+use raytracer::*;
+
+use raytracer::maths::consts::PI;
+
+use std::path::Path;
+
+/// Hit of a ray coming down at 30 degrees from the normal on a surface
+/// facing +z.
+fn hit(outside: bool) -> (Ray, HitRecord) {
+    let direction = Vec3::new((PI / 6.0).sin(), 0.0, -(PI / 6.0).cos());
+    let (direction, normal) = if outside {
+        (direction, Vec3::new(0.0, 0.0, 1.0))
+    } else {
+        (direction, Vec3::new(0.0, 0.0, -1.0))
+    };
+    let ray = Ray::new(Vec3::new(0.0, 0.0, 1.0), direction);
+    let record = HitRecord::new(&ray, Vec3::new(0.0, 0.0, 0.0), normal, 1.0, MaterialId(0));
+    (ray, record)
+}
+
+/// Midpoint rule integral of `f` over the sphere of directions.
+fn integrate_sphere(f: impl Fn(Vec3) -> Float) -> Float {
+    let (thetas, phis) = (1000, 400);
+    let d_theta = PI / thetas as Float;
+    let d_phi = 2.0 * PI / phis as Float;
+
+    let mut sum = 0.0;
+    for i in 0..thetas {
+        let theta = (i as Float + 0.5) * d_theta;
+        for j in 0..phis {
+            let phi = (j as Float + 0.5) * d_phi;
+            sum += f(spherical_direction(theta, phi)) * theta.sin() * d_theta * d_phi;
+        }
+    }
+    sum
+}
+
+#[test]
+fn microfacet_sampling_follows_its_density() {
+    seed_random(3);
+    let materials = [
+        MaterialType::Microfacet {
+            roughness: 0.7,
+            fresnel: Fresnel::Conductor {
+                f0: Vec3::new(0.9, 0.6, 0.3),
+            },
+        },
+        MaterialType::Microfacet {
+            roughness: 0.7,
+            fresnel: Fresnel::Dielectric {
+                refractive_index: 1.5,
+            },
+        },
+        MaterialType::AnisotropicMetal {
+            f0: Vec3::new(0.9, 0.9, 0.9),
+            roughness_u: 0.2,
+            roughness_v: 0.7,
+            rotation: 30.0,
+        },
+        MaterialType::Principled(Principled {
+            base_color: Vec3::new(0.8, 0.3, 0.1),
+            sheen: 0.5,
+            clearcoat: 1.0,
+            ..Principled::default()
+        }),
+        MaterialType::Principled(Principled {
+            metallic: 0.3,
+            roughness: 0.4,
+            transmission: 0.8,
+            ..Principled::default()
+        }),
+    ];
+    for material in &materials {
+        // Principled weights divide sums of several lobes, whose rounding
+        // goes past 1e-4 in single precision.
+        let slack = match material {
+            MaterialType::Principled(_) => 1e-3,
+            _ => 1e-4,
+        };
+        for &outside in &[true, false] {
+            let (ray, record) = hit(outside);
+            let samples = 100_000;
+            let mut sampled = 0;
+            for _ in 0..samples {
+                let scatter = match material.scatter(&ray, &record, &mut IndependentSampler) {
+                    Some(scatter) => scatter,
+                    None => continue,
+                };
+                sampled += 1;
+                assert!(!scatter.is_specular);
+                let scattered = &scatter.scattered;
+                let pdf = material.sampling_pdf(&ray, &record, scattered);
+                assert!(
+                    (pdf - scatter.pdf).abs() <= 1e-3 * pdf,
+                    "{} != {}",
+                    pdf,
+                    scatter.pdf
+                );
+                // Sampled rays are weighted by the masking of their direction,
+                // or not at all when the attenuation holds the whole BSDF.
+                let weight = material.scattering_pdf(&ray, &record, scattered) / scatter.pdf;
+                assert!(weight > 0.0 && weight <= 1.0 + slack, "{}", weight);
+            }
+
+            // Samples lost below the surface are missing from the density too.
+            let total = integrate_sphere(|direction| {
+                material.sampling_pdf(&ray, &record, &Ray::new(record.position, direction))
+            });
+            let share = sampled as Float / samples as Float;
+            assert!((total - share).abs() < 0.01, "{} != {}", total, share);
+        }
+    }
+}
+
+#[test]
+fn rough_metals_tint_reflections_towards_white() {
+    let material = MaterialType::Microfacet {
+        roughness: 0.5,
+        fresnel: Fresnel::Conductor {
+            f0: Vec3::new(0.9, 0.6, 0.3),
+        },
+    };
+    let (ray, record) = hit(true);
+    let attenuation = |direction: Vec3| {
+        material
+            .directional_attenuation(&ray, &record, &Ray::new(record.position, direction))
+            .unwrap()
+    };
+
+    // Mirror direction, seen at 30 degrees, against a grazing one.
+    let mirror = Vec3::new(ray.dir.x, ray.dir.y, -ray.dir.z);
+    let grazing = Vec3::new(1.0, 0.0, 0.01);
+    let (mirror, grazing) = (attenuation(mirror), attenuation(grazing));
+    assert!(mirror.z < mirror.x && mirror.z > 0.3);
+    assert!(grazing.z > mirror.z && grazing.x > mirror.x);
+    assert_eq!(material.albedo(&record).x, 0.9);
+}
+
+#[test]
+fn anisotropic_metals_stretch_highlights_across_the_tangent() {
+    let (ray, mut record) = hit(true);
+    record.tangent = Some(Vec3::new(1.0, 0.0, 0.0));
+    let mirror = Vec3::new(ray.dir.x, ray.dir.y, -ray.dir.z);
+    let brdf = |rotation: Float, offset: Vec3| {
+        let material = MaterialType::AnisotropicMetal {
+            f0: Vec3::new(0.9, 0.9, 0.9),
+            roughness_u: 0.2,
+            roughness_v: 0.6,
+            rotation,
+        };
+        let scattered = Ray::new(record.position, (mirror + offset).unit());
+        material.scattering_pdf(&ray, &record, &scattered)
+    };
+
+    // Rough across the tangent, the highlight spreads along y.
+    let (along_x, along_y) = (Vec3::new(0.0, 0.0, 0.15), Vec3::new(0.0, 0.15, 0.0));
+    assert!(brdf(0.0, along_y) > 2.0 * brdf(0.0, along_x));
+    // Turning the tangent a quarter turn swaps them.
+    assert!(brdf(90.0, along_x) > 2.0 * brdf(90.0, along_y));
+    // Highlights peak in the mirror direction either way.
+    let zero = Vec3::new(0.0, 0.0, 0.0);
+    assert!(brdf(0.0, zero) > brdf(0.0, along_y));
+    assert!(brdf(90.0, zero) > brdf(90.0, along_x));
+}
+
+#[test]
+fn thin_films_interfere_with_their_reflections() {
+    // Without a film the surface reflects like bare glass.
+    for &cos_theta in &[1.0, 0.7, 0.2] {
+        let bare = fresnel_dielectric(cos_theta, 1.5);
+        let film = thin_film_reflectance(cos_theta, (1.0, 1.33, 1.5), 0.0, 550.0);
+        assert!((film - bare).abs() < 1e-4, "{} != {}", film, bare);
+    }
+
+    // A soap bubble reflects some wavelengths far more than others, which
+    // change with its thickness and the angle.
+    let film = ThinFilm {
+        thickness: 300.0,
+        refractive_index: 1.33,
+    };
+    let head_on = film.reflectance(1.0, 1.0, Vec3::new(1.0, 1.0, 1.0));
+    let thicker = ThinFilm {
+        thickness: 400.0,
+        ..film
+    }
+    .reflectance(1.0, 1.0, Vec3::new(1.0, 1.0, 1.0));
+    let tilted = film.reflectance(0.5, 1.0, Vec3::new(1.0, 1.0, 1.0));
+    for color in &[head_on, thicker, tilted] {
+        let (lowest, highest) = (
+            color.x.min(color.y).min(color.z),
+            color.x.max(color.y).max(color.z),
+        );
+        assert!(lowest >= 0.0 && highest <= 1.0);
+        assert!(highest > 1.5 * lowest, "{:?}", color);
+    }
+    assert!((head_on - thicker).length() > 0.01);
+    assert!((head_on - tilted).length() > 0.01);
+    for wavelength in 380..780 {
+        let reflectance = thin_film_reflectance(0.8, (1.0, 1.33, 1.0), 300.0, wavelength as Float);
+        assert!((0.0..=1.0).contains(&reflectance));
+    }
+
+    // Coated metals tint their reflections.
+    let material = MaterialType::Metal {
+        albedo: Vec3::new(0.9, 0.9, 0.9),
+        fuzziness: 0.0,
+    };
+    let (ray, mut record) = hit(true);
+    let bare = material
+        .scatter(&ray, &record, &mut IndependentSampler)
+        .unwrap();
+    record.film = Some(film);
+    let coated = material
+        .scatter(&ray, &record, &mut IndependentSampler)
+        .unwrap();
+    assert_eq!(bare.attenuation.y, 0.9);
+    assert!((coated.attenuation - bare.attenuation).length() > 0.01);
+}
+
+#[test]
+fn principled_materials_mix_their_lobes() {
+    let (ray, record) = hit(true);
+    let mirror = Ray::new(record.position, Vec3::new(ray.dir.x, ray.dir.y, -ray.dir.z));
+    let reflectance = |principled: Principled| {
+        let material = MaterialType::Principled(principled);
+        let pdf = material.scattering_pdf(&ray, &record, &mirror);
+        material
+            .directional_attenuation(&ray, &record, &mirror)
+            .unwrap()
+            * pdf
+    };
+
+    // Metals reflect their color, plastics a little white on top of it.
+    let base_color = Vec3::new(0.9, 0.2, 0.1);
+    let plastic = reflectance(Principled {
+        base_color,
+        ..Principled::default()
+    });
+    let metal = reflectance(Principled {
+        base_color,
+        metallic: 1.0,
+        ..Principled::default()
+    });
+    let coated = reflectance(Principled {
+        base_color,
+        clearcoat: 1.0,
+        ..Principled::default()
+    });
+    assert!(metal.x > plastic.x && metal.z < metal.x / 5.0);
+    assert!(coated.z > plastic.z);
+    assert!(MaterialType::Principled(Principled::default())
+        .refractive_index()
+        .is_none());
+
+    let glass = Principled {
+        transmission: 1.0,
+        ..Principled::default()
+    };
+    assert!((glass.refractive_index() - 1.5).abs() < 0.01);
+    let through = Ray::new(record.position, ray.dir);
+    let material = MaterialType::Principled(glass);
+    assert!(material.scattering_pdf(&ray, &record, &through) > 0.0);
+}
+
+#[test]
+fn thin_glass_sums_the_light_between_its_faces() {
+    // Head on, each face reflects 4% of the light, and the light bouncing
+    // between them adds up to 2R / (1 + R) of it.
+    let white = Vec3::new(1.0, 1.0, 1.0);
+    let (reflected, _) = thin_glass_split(1.0, 1.5, white);
+    assert!((reflected.x - 0.08 / 1.04).abs() < 1e-4, "{:?}", reflected);
+    for &cos_theta in &[1.0, 0.6, 0.2, 0.01] {
+        let (reflected, through) = thin_glass_split(cos_theta, 1.5, white);
+        assert!((reflected.y + through.y - 1.0).abs() < 1e-4);
+        assert!(reflected.y >= fresnel_dielectric(cos_theta, 1.5));
+    }
+    // Tinted glass absorbs more at grazing angles, where light crosses
+    // more of it.
+    let green = Vec3::new(0.5, 0.8, 0.4);
+    let (_, head_on) = thin_glass_split(1.0, 1.5, green);
+    let (_, tilted) = thin_glass_split(0.3, 1.5, green);
+    assert!(head_on.y < 0.8 && head_on.y > 0.7, "{:?}", head_on);
+    assert!(tilted.x < head_on.x * 0.95);
+
+    // What gets through goes on unbent, on the other side of the surface.
+    seed_random(5);
+    let material = MaterialType::thin_glass_preset("bottle").unwrap();
+    let (ray, record) = hit(true);
+    let (mut reflections, count) = (0, 20_000);
+    let mut light = Vec3::new(0.0, 0.0, 0.0);
+    for _ in 0..count {
+        let scatter = material
+            .scatter(&ray, &record, &mut IndependentSampler)
+            .unwrap();
+        assert!(scatter.is_specular);
+        light += scatter.attenuation;
+        if scatter.kind == BounceKind::Transmission {
+            assert!((scatter.scattered.dir - ray.dir).length() < 1e-6);
+        } else {
+            assert!(scatter.scattered.dir.z > 0.0);
+            reflections += 1;
+        }
+    }
+    assert!(reflections > 0 && reflections < count / 5);
+    let refractive_index = material.refractive_index();
+    assert!(refractive_index.is_none() && material.medium().is_none());
+    let (reflected, through) = match material {
+        MaterialType::ThinGlass {
+            refractive_index,
+            tint,
+        } => thin_glass_split((PI / 6.0).cos(), refractive_index, tint),
+        _ => unreachable!(),
+    };
+    let expected = reflected + through;
+    let light = light / count as Float;
+    assert!((light - expected).length() < 0.02, "{:?}", light);
+    assert!(MaterialType::thin_glass_preset("stained").is_none());
+
+    let source = "sphere 0 1 0 1 thin-glass window\nsphere 3 1 0 1 thin-glass 1.45 0.9 0.9 1\n";
+    let scene = parse_scene(source, "glass", Path::new("")).unwrap();
+    assert!(matches!(
+        scene.material(MaterialId(1)),
+        MaterialType::ThinGlass { tint, .. } if tint.z == 1.0
+    ));
+    for line in [
+        "sphere 0 1 0 1 thin-glass stained",
+        "sphere 0 1 0 1 thin-glass 0.5 1 1 1",
+    ] {
+        assert!(
+            parse_scene(line, "glass", Path::new("")).is_err(),
+            "{}",
+            line
+        );
+    }
+}
@@ -0,0 +1,58 @@
+//! Surfaces past the maximum ray distance, and fading out before it.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+/// Coverage of the single pixel looking straight at a wall `distance` away,
+/// with rays stopping at 10.
+fn coverage(distance: Float, distance_fade: Float) -> Float {
+    let mut scene = Scene::new(Units::Meters);
+    let light = scene.add_material(MaterialType::DiffuseLight {
+        emit: Vec3::new(1.0, 1.0, 1.0),
+    });
+    let wall = Plane::new(
+        Vec3::new(0.0, 0.0, -distance),
+        Vec3::new(0.0, 0.0, 1.0),
+        light,
+    );
+    scene.add_shape(Shape::Plane(wall));
+    let camera = Camera::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        1.0,
+        1.0,
+        0.0,
+        1.0,
+    );
+    let settings = RenderSettings {
+        width: 1,
+        height: 1,
+        samples_per_pixel: 400,
+        transparent_background: true,
+        max_distance: 10.0,
+        distance_fade,
+        seed: 3,
+        threads: 1,
+        ..RenderSettings::default()
+    };
+    Renderer::new(settings)
+        .unwrap()
+        .render(&scene, &camera)
+        .pixel(0, 0)[3]
+}
+
+#[test]
+fn surfaces_fade_out_before_the_maximum_distance() {
+    assert_eq!(coverage(4.0, 0.5), 1.0);
+    // 80% of the way through the fade.
+    let fading = coverage(9.0, 0.5);
+    assert!((fading - 0.2).abs() < 0.05, "{}", fading);
+    assert_eq!(coverage(11.0, 0.5), 0.0);
+}
+
+#[test]
+fn surfaces_are_cut_off_without_a_fade() {
+    assert_eq!(coverage(9.99, 0.0), 1.0);
+    assert_eq!(coverage(10.01, 0.0), 0.0);
+}
@@ -0,0 +1,94 @@
+//! Measured BRDFs of the MERL database.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+const ENTRIES: usize = 90 * 90 * 180;
+
+/// MERL file of a BRDF reflecting `value` of every channel in all directions.
+fn constant_file(value: f64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for size in &[90i32, 90, 180] {
+        bytes.extend_from_slice(&size.to_le_bytes());
+    }
+    for scale in &[1.0 / 1500.0, 1.15 / 1500.0, 1.66 / 1500.0] {
+        for _ in 0..ENTRIES {
+            bytes.extend_from_slice(&(value / scale).to_le_bytes());
+        }
+    }
+    bytes
+}
+
+/// Estimates of the light `brdf` reflects towards `wo` from a white sky,
+/// with its own sampling and with uniform directions, and of the integral
+/// of its sampling density.
+fn estimates(brdf: &MeasuredBrdf, wo: Vec3) -> (Vec3, Vec3, Float) {
+    let samples = 200_000;
+    let mut sampled = Vec3::new(0.0, 0.0, 0.0);
+    let mut uniform = Vec3::new(0.0, 0.0, 0.0);
+    let mut density = 0.0;
+    for sample in 0..samples {
+        seed_random(sample);
+        if let Some(wi) = brdf.sample(wo, random_01(), (random_01(), random_01())) {
+            let (value, pdf) = brdf.eval(wo, wi);
+            if pdf > 0.0 {
+                sampled += value / pdf;
+            }
+        }
+
+        let wi = sample_hemisphere(Vec3::new(0.0, 0.0, 1.0), (random_01(), random_01()));
+        let (value, pdf) = brdf.eval(wo, wi);
+        uniform += value * 2.0 * consts::PI;
+        density += pdf * 2.0 * consts::PI;
+    }
+    let samples = samples as Float;
+    (sampled / samples, uniform / samples, density / samples)
+}
+
+#[test]
+fn measured_brdfs_load_merl_files() {
+    let brdf = MeasuredBrdf::parse(&constant_file(0.25)).unwrap();
+    let wo = Vec3::new(0.3, 0.2, 0.9).unit();
+    let wi = Vec3::new(-0.5, 0.1, 0.6).unit();
+    let reflectance = brdf.reflectance(wo, wi);
+    for channel in &[reflectance.x, reflectance.y, reflectance.z] {
+        assert!((channel - 0.25).abs() < 1e-6);
+    }
+    assert_eq!(brdf.reflectance(wo, -wi).x, 0.0);
+    assert!((brdf.albedo().y - 0.25 * consts::PI).abs() < 1e-3);
+
+    let (sampled, _, density) = estimates(&brdf, wo);
+    assert!(
+        (sampled.x - 0.25 * consts::PI).abs() < 0.01,
+        "{:?}",
+        sampled
+    );
+    assert!((density - 1.0).abs() < 0.01, "{}", density);
+
+    assert!(MeasuredBrdf::parse(&[0; 12]).is_err());
+    assert!(MeasuredBrdf::parse(&constant_file(0.25)[..1000]).is_err());
+}
+
+#[test]
+fn measured_brdfs_sample_their_highlights() {
+    // Glossy: only half vectors within 18 degrees of the normal reflect.
+    let mut values = vec![0.0; ENTRIES * 3];
+    for (entry, value) in values.chunks_mut(3).enumerate() {
+        if entry / (90 * 180) < 40 {
+            value.copy_from_slice(&[2.0, 1.0, 0.5]);
+        }
+    }
+    let brdf = MeasuredBrdf::new(values);
+
+    for &cos in &[0.95, 0.6, 0.2] {
+        let wo = Vec3::new((1.0 - cos * cos as Float).sqrt(), 0.0, cos);
+        let (sampled, uniform, density) = estimates(&brdf, wo);
+        assert!(
+            (sampled - uniform).length() < 0.05 * uniform.length(),
+            "{:?} {:?}",
+            sampled,
+            uniform
+        );
+        assert!((density - 1.0).abs() < 0.02, "{}", density);
+    }
+}
@@ -0,0 +1,155 @@
+//! Priorities of overlapping dielectric media and their absorption.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+/// Glass ball holding a diamond, the diamond given `diamond_priority`.
+fn scene(diamond: bool, diamond_priority: i32) -> Scene {
+    let mut scene = Scene::new(Units::Meters);
+    scene.environment = Environment::Gradient {
+        bottom: Vec3::new(1.0, 1.0, 1.0),
+        top: Vec3::new(0.2, 0.4, 1.0),
+    };
+    let glass = scene.add_material(MaterialType::Dialectric {
+        refractive_index: 1.5,
+    });
+    scene.set_priority(glass, 1);
+    scene.add(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 2.0, glass));
+    if diamond {
+        let diamond = scene.add_material(MaterialType::Dialectric {
+            refractive_index: 2.4,
+        });
+        scene.set_priority(diamond, diamond_priority);
+        scene.add(Sphere::new(Vec3::new(0.3, 0.2, 0.0), 1.0, diamond));
+    }
+    scene
+}
+
+fn colors(scene: &Scene) -> Vec<Vec3> {
+    let settings = RenderSettings::default();
+    (0..32)
+        .map(|sample| {
+            seed_random(sample);
+            let ray = Ray::new(Vec3::new(0.1, 0.3, 5.0), Vec3::new(0.0, 0.0, -1.0));
+            ray_color(&ray, scene, &settings).0
+        })
+        .collect()
+}
+
+#[test]
+fn current_medium_follows_priorities() {
+    let (glass, diamond) = (MaterialId(0), MaterialId(1));
+    let lower = scene(true, 0);
+    assert_eq!(lower.current_medium(&[]), None);
+    assert_eq!(lower.current_medium(&[glass, diamond]), Some(glass));
+    assert_eq!(lower.refractive_index(None), 1.0);
+    assert_eq!(lower.refractive_index(Some(diamond)), 2.4);
+
+    // Equal priorities go to the medium entered last.
+    let equal = scene(true, 1);
+    assert_eq!(equal.current_medium(&[glass, diamond]), Some(diamond));
+    assert_eq!(equal.current_medium(&[diamond, glass]), Some(glass));
+}
+
+#[test]
+fn lower_priority_media_are_hidden() {
+    let empty = colors(&scene(false, 0));
+    let hidden = colors(&scene(true, 0));
+    let shown = colors(&scene(true, 2));
+    for (empty, hidden) in empty.iter().zip(&hidden) {
+        assert_eq!((*empty - *hidden).length(), 0.0);
+    }
+    assert!(empty
+        .iter()
+        .zip(&shown)
+        .any(|(empty, shown)| (*empty - *shown).length() > 1e-3));
+}
+
+#[test]
+fn absorbing_media_tint_light_going_through() {
+    let mut scene = Scene::new(Units::Meters);
+    scene.environment = Environment::Constant(Vec3::new(1.0, 1.0, 1.0));
+    let glass = scene.add_material(MaterialType::Dialectric {
+        refractive_index: 1.5,
+    });
+    scene.set_absorption(glass, Vec3::new(0.0, 0.5, 1.0));
+    scene.add(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, glass));
+    assert_eq!(scene.transmittance(glass, 2.0).y, (-1.0 as Float).exp());
+
+    // Rays through the center go 2 meters through the glass, the few
+    // reflected off it keep their color.
+    let settings = RenderSettings::default();
+    let samples = 2000;
+    let mut sum = Vec3::new(0.0, 0.0, 0.0);
+    for sample in 0..samples {
+        seed_random(sample);
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        sum += ray_color(&ray, &scene, &settings).0;
+    }
+    let color = sum / samples as Float;
+    assert!((color.x - 1.0).abs() < 1e-6, "{:?}", color);
+    assert!(
+        (color.y - (-1.0 as Float).exp()).abs() < 0.05,
+        "{:?}",
+        color
+    );
+    assert!(
+        (color.z - (-2.0 as Float).exp()).abs() < 0.06,
+        "{:?}",
+        color
+    );
+
+    // Converting units keeps the color.
+    scene.convert_to(Units::Centimeters);
+    assert!((scene.transmittance(glass, 200.0).y - (-1.0 as Float).exp()).abs() < 1e-6);
+}
+
+#[test]
+fn rays_start_inside_the_media_holding_them() {
+    let mut scene = Scene::new(Units::Meters);
+    scene.environment = Environment::Constant(Vec3::new(1.0, 1.0, 1.0));
+    let water = scene.add_water();
+    scene.set_absorption(water, Vec3::new(0.0, 0.5, 0.0));
+    let glass = scene.add_material(MaterialType::Dialectric {
+        refractive_index: 1.5,
+    });
+    let wall = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    scene.add(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 0.5, glass));
+    scene.add(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, water));
+    scene.add(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 3.0, wall));
+
+    let origin = Vec3::new(0.0, 0.0, 0.0);
+    assert_eq!(scene.media_at(origin), vec![water, glass]);
+    assert_eq!(scene.media_at(Vec3::new(0.0, 0.7, 0.0)), vec![water]);
+    assert_eq!(scene.media_at(Vec3::new(0.0, 2.0, 0.0)), vec![]);
+
+    // Leaving the glass into the water, then the water into the air, the
+    // light keeps what half a meter of water leaves, and some reflected
+    // back inside.
+    let mut scene = Scene::new(Units::Meters);
+    scene.environment = Environment::Constant(Vec3::new(1.0, 1.0, 1.0));
+    let water = scene.add_water();
+    scene.set_absorption(water, Vec3::new(0.0, 0.5, 0.0));
+    let glass = scene.add_material(MaterialType::Dialectric {
+        refractive_index: 1.5,
+    });
+    scene.add(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 0.5, glass));
+    scene.add(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, water));
+    let settings = RenderSettings::default();
+    let samples = 2000;
+    let mut sum = Vec3::new(0.0, 0.0, 0.0);
+    for sample in 0..samples {
+        seed_random(sample);
+        let ray = Ray::new(origin, Vec3::new(0.0, 0.0, 1.0));
+        sum += ray_color(&ray, &scene, &settings).0;
+    }
+    let color = sum / samples as Float;
+    assert!((color.x - 1.0).abs() < 1e-3, "{:?}", color);
+    assert!(
+        (color.y - (-0.25 as Float).exp()).abs() < 0.05,
+        "{:?}",
+        color
+    );
+}
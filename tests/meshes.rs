@@ -0,0 +1,373 @@
+//! Triangle meshes and the PLY, STL and OBJ files they are read from.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+const EPSILON: Float = 1e-9;
+
+fn assert_close(a: Float, b: Float) {
+    assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+}
+
+/// Unit square of the xz plane facing up, as a PLY header and its
+/// vertices, each with a property meshes leave out.
+const SQUARE_HEADER: &str = "ply
+format FORMAT 1.0
+comment a square
+element vertex 4
+property float x
+property float y
+property float z
+property uchar flags
+element face 1
+property list uchar int vertex_indices
+end_header
+";
+
+const SQUARE: [[f32; 3]; 4] = [
+    [0.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0],
+    [1.0, 0.0, 1.0],
+    [1.0, 0.0, 0.0],
+];
+
+fn binary_square(big_endian: bool) -> Vec<u8> {
+    let format = if big_endian {
+        "binary_big_endian"
+    } else {
+        "binary_little_endian"
+    };
+    let mut bytes = SQUARE_HEADER.replace("FORMAT", format).into_bytes();
+    let order = |bytes: [u8; 4]| {
+        let mut bytes = bytes;
+        if big_endian {
+            bytes.reverse();
+        }
+        bytes
+    };
+    for vertex in &SQUARE {
+        for coordinate in vertex {
+            bytes.extend_from_slice(&order(coordinate.to_le_bytes()));
+        }
+        bytes.push(7);
+    }
+    bytes.push(4);
+    for index in 0..4i32 {
+        bytes.extend_from_slice(&order(index.to_le_bytes()));
+    }
+    bytes
+}
+
+fn assert_square((positions, triangles): &Triangles) {
+    assert_eq!(positions.len(), 4);
+    assert_eq!(triangles, &vec![[0, 1, 2], [0, 2, 3]]);
+    assert!((positions[2] - Vec3::new(1.0, 0.0, 1.0)).length() < 1e-6);
+
+    let mesh = Mesh::new(positions.clone(), triangles.clone(), MaterialId(0));
+    let down = Ray::new(Vec3::new(0.3, 2.0, 0.6), Vec3::new(0.0, -1.0, 0.0));
+    let hit = mesh.hit(&down, EPSILON, Float::INFINITY).unwrap();
+    assert_close(hit.t, 2.0);
+    assert!(hit.front_face);
+}
+
+#[test]
+fn ply_files_are_read_as_text_and_binary() {
+    let mut text = SQUARE_HEADER.replace("FORMAT", "ascii");
+    text.push_str("0 0 0 7\n0 0 1 7\n1 0 1 7\n1 0 0 7\n4 0 1 2 3\n");
+    assert_square(&parse_ply(text.as_bytes()).unwrap());
+    assert_square(&parse_ply(&binary_square(false)).unwrap());
+    assert_square(&parse_ply(&binary_square(true)).unwrap());
+
+    let mut truncated = binary_square(false);
+    truncated.truncate(truncated.len() - 2);
+    assert!(parse_ply(&truncated).is_err());
+    assert!(parse_ply(b"solid nothing").is_err());
+}
+
+#[test]
+fn stl_files_are_read_as_text_and_binary() {
+    let text = "solid square
+facet normal 0 1 0
+outer loop
+vertex 0 0 0
+vertex 0 0 1
+vertex 1 0 1
+endloop
+endfacet
+facet normal 0 1 0
+outer loop
+vertex 0 0 0
+vertex 1 0 1
+vertex 1 0 0
+endloop
+endfacet
+endsolid square
+";
+    let (positions, triangles) = parse_stl(text.as_bytes()).unwrap();
+    assert_eq!((positions.len(), triangles.len()), (6, 2));
+    assert_eq!(triangles[1], [3, 4, 5]);
+
+    // Binary, with a header starting like a text file.
+    let mut bytes = b"solid binary".to_vec();
+    bytes.resize(80, 0);
+    bytes.extend_from_slice(&2u32.to_le_bytes());
+    for corners in &[[0, 1, 2], [0, 2, 3]] {
+        for value in &[0.0f32, 1.0, 0.0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for &corner in corners {
+            for coordinate in &SQUARE[corner] {
+                bytes.extend_from_slice(&coordinate.to_le_bytes());
+            }
+        }
+        bytes.extend_from_slice(&[0, 0]);
+    }
+    let (binary, triangles) = parse_stl(&bytes).unwrap();
+    assert_eq!(triangles.len(), 2);
+    for (a, b) in binary.iter().zip(&positions) {
+        assert!((*a - *b).length() < 1e-6);
+    }
+}
+
+#[test]
+fn obj_files_split_polygons_into_triangles() {
+    let text = "# a square and a triangle
+v 0 0 0
+v 0 0 1
+v 1 0 1
+v 1 0 0
+vn 0 1 0
+f 1//1 2//1 3//1 4//1
+v 0 1 0
+f -1 1/1 4
+";
+    let (positions, triangles) = parse_obj(text).unwrap();
+    assert_eq!(positions.len(), 5);
+    assert_eq!(triangles, vec![[0, 1, 2], [0, 2, 3], [4, 0, 3]]);
+    assert!(parse_obj("f 1 2 3").is_err());
+
+    // Mesh files are decoded following their extension, whatever its case.
+    assert_eq!(decode_mesh(text.as_bytes(), "OBJ").unwrap().1, triangles);
+    assert!(decode_mesh(&[0xff, 0xfe], "obj").is_err());
+    assert!(decode_mesh(text.as_bytes(), "dae").is_err());
+}
+
+/// Cube from -1 to 1, its faces wound counterclockwise seen from outside.
+fn cube() -> Mesh {
+    let positions = (0..8)
+        .map(|i| {
+            let coordinate = |bit: usize| if i & bit != 0 { 1.0 } else { -1.0 };
+            Vec3::new(coordinate(1), coordinate(2), coordinate(4))
+        })
+        .collect();
+    let quads = [
+        [0, 4, 6, 2],
+        [1, 3, 7, 5],
+        [0, 1, 5, 4],
+        [2, 6, 7, 3],
+        [0, 2, 3, 1],
+        [4, 5, 7, 6],
+    ];
+    let triangles = quads
+        .iter()
+        .flat_map(|&[a, b, c, d]| vec![[a, b, c], [a, c, d]])
+        .collect();
+    Mesh::new(positions, triangles, MaterialId(0))
+}
+
+#[test]
+fn closed_meshes_are_entered_and_left() {
+    let cube = cube();
+    assert_eq!(cube.triangle_count(), 12);
+    let mut stream = RandomStream::new(4, "rays");
+    for _ in 0..100 {
+        let target = Vec3::new(
+            stream.between(-0.9, 0.9),
+            stream.between(-0.9, 0.9),
+            stream.between(-0.9, 0.9),
+        );
+        let origin = sample_unit_sphere((stream.next_01(), stream.next_01())) * 5.0;
+        let ray = Ray::new(origin, target - origin);
+        let entry = cube.hit(&ray, EPSILON, Float::INFINITY).unwrap();
+        assert!(entry.front_face);
+        let largest = entry.position.x.abs().max(entry.position.y.abs());
+        assert_close(largest.max(entry.position.z.abs()), 1.0);
+        assert_close(entry.normal.dot(cube.normal_at(entry.position)), 1.0);
+
+        let inside = Ray::new(entry.position, ray.dir);
+        let exit = cube.hit(&inside, 1e-6, Float::INFINITY).unwrap();
+        assert!(!exit.front_face);
+    }
+}
+
+#[test]
+fn mirrored_meshes_keep_their_faces_outward() {
+    let mirrored = cube().transformed(&Mat4::scaling(Vec3::new(-2.0, 2.0, 2.0)));
+    let ray = Ray::new(Vec3::new(0.5, 0.5, 10.0), Vec3::new(0.0, 0.0, -1.0));
+    let hit = mirrored.hit(&ray, EPSILON, Float::INFINITY).unwrap();
+    assert_close(hit.t, 8.0);
+    assert!(hit.front_face);
+    assert_close(mirrored.bounding_sphere().radius, Float::sqrt(12.0));
+}
+
+#[test]
+fn scenes_hit_meshes_through_their_ids() {
+    let mut scene = Scene::new(Units::Meters);
+    let gray = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    let mut mesh = cube();
+    mesh.material = gray;
+    let id = scene.add_shape(Shape::Mesh(mesh));
+
+    let down = Ray::new(Vec3::new(0.2, 5.0, -0.3), Vec3::new(0.0, -1.0, 0.0));
+    let (hit_id, hit) = scene.hit_object(&down, EPSILON, Float::INFINITY).unwrap();
+    assert_eq!(hit_id, id);
+    assert_close(hit.t, 4.0);
+    assert_eq!(hit.material, gray);
+}
+
+/// Octahedron of radius 1, a very coarse sphere.
+fn octahedron() -> Mesh {
+    let positions = vec![
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec3::new(-1.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, -1.0, 0.0),
+        Vec3::new(0.0, 0.0, 1.0),
+        Vec3::new(0.0, 0.0, -1.0),
+    ];
+    let mut triangles = Vec::new();
+    // Around the y axis, above then below.
+    for &(a, b) in &[(0, 4), (4, 1), (1, 5), (5, 0)] {
+        triangles.push([a, 2, b]);
+        triangles.push([a, b, 3]);
+    }
+    Mesh::new(positions, triangles, MaterialId(0))
+}
+
+#[test]
+fn smooth_meshes_interpolate_their_normals() {
+    let flat = octahedron();
+    assert!(!flat.is_smooth());
+    let smooth = flat.clone().smoothed();
+    assert!(smooth.is_smooth());
+
+    // Towards a point of the face between +x, +y and +z, near +x.
+    let target = Vec3::new(0.8, 0.1, 0.1);
+    let ray = Ray::new(target * 3.0, -target);
+    let flat_hit = flat.hit(&ray, EPSILON, Float::INFINITY).unwrap();
+    let smooth_hit = smooth.hit(&ray, EPSILON, Float::INFINITY).unwrap();
+    assert_close(flat_hit.t, smooth_hit.t);
+    assert!(smooth_hit.front_face);
+    let face = Vec3::new(1.0, 1.0, 1.0).unit();
+    assert_close(flat_hit.normal.dot(face), 1.0);
+    // Corners get the average of the faces around them, which is the axis.
+    let expected = (Vec3::new(1.0, 0.0, 0.0) * 0.8
+        + Vec3::new(0.0, 1.0, 0.0) * 0.1
+        + Vec3::new(0.0, 0.0, 1.0) * 0.1)
+        .unit();
+    assert_close(smooth_hit.normal.dot(expected), 1.0);
+    assert_close(smooth.normal_at(smooth_hit.position).dot(expected), 1.0);
+
+    // Inside, normals face the ray too.
+    let inside = Ray::new(Vec3::new(0.0, 0.0, 0.0), target);
+    let exit = smooth.hit(&inside, EPSILON, Float::INFINITY).unwrap();
+    assert!(!exit.front_face);
+    assert_close(exit.normal.dot(-expected), 1.0);
+
+    // Mirrored and stretched, normals still face out of the surface.
+    let mirrored = smooth.transformed(&Mat4::scaling(Vec3::new(-1.0, 2.0, 1.0)));
+    let ray = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+    let hit = mirrored.hit(&ray, EPSILON, Float::INFINITY).unwrap();
+    assert!(hit.front_face);
+    assert_close(hit.normal.x, -1.0);
+}
+
+#[test]
+fn smoothing_joins_corners_at_the_same_place() {
+    // Triangles with corners of their own, facing +z and +x.
+    let text = "solid corner
+facet normal 0 0 1
+outer loop
+vertex 0 0 0
+vertex 1 0 0
+vertex 0 1 0
+endloop
+endfacet
+facet normal 1 0 0
+outer loop
+vertex 0 0 0
+vertex 0 0 1
+vertex 0 -1 0
+endloop
+endfacet
+endsolid corner
+";
+    let (positions, triangles) = parse_stl(text.as_bytes()).unwrap();
+    let mesh = Mesh::new(positions, triangles, MaterialId(0)).smoothed();
+    let ray = Ray::new(Vec3::new(0.01, 0.01, 5.0), Vec3::new(0.0, 0.0, -1.0));
+    let hit = mesh.hit(&ray, EPSILON, Float::INFINITY).unwrap();
+    // Near the shared corner, halfway between the two faces.
+    assert!(hit.normal.x > 0.6 && hit.normal.z > 0.6);
+}
+
+/// Height map of a single texel of `gray`.
+fn flat_height(gray: Float, height: Float) -> BumpMap {
+    BumpMap {
+        texture: Texture::new(vec![Vec3::new(gray, gray, gray)], 1, 1),
+        height,
+    }
+}
+
+#[test]
+fn displaced_meshes_are_split_and_pushed_out() {
+    // Square of the xy plane from -1 to 1, facing +z.
+    let positions = vec![
+        Vec3::new(-1.0, -1.0, 0.0),
+        Vec3::new(1.0, -1.0, 0.0),
+        Vec3::new(1.0, 1.0, 0.0),
+        Vec3::new(-1.0, 1.0, 0.0),
+    ];
+    let square = Mesh::new(positions, vec![[0, 1, 2], [0, 2, 3]], MaterialId(0));
+    let displacement = Displacement {
+        bump_map: flat_height(0.5, 2.0),
+        projection: UvProjection::Planar,
+        levels: 3,
+    };
+    let displaced = square.displaced(&displacement);
+    assert_eq!(displaced.triangle_count(), 2 * 4 * 4 * 4);
+    assert!(!displaced.is_smooth());
+    let down = Ray::new(Vec3::new(0.3, -0.2, 5.0), Vec3::new(0.0, 0.0, -1.0));
+    let hit = displaced.hit(&down, EPSILON, Float::INFINITY).unwrap();
+    assert_close(hit.t, 4.0);
+    assert_close(hit.normal.z, 1.0);
+
+    // Corners at the same place move together, so the cube stays closed.
+    let displacement = Displacement {
+        bump_map: flat_height(1.0, 0.5),
+        projection: UvProjection::Box,
+        levels: 2,
+    };
+    let cube = cube().smoothed().displaced(&displacement);
+    assert!(cube.is_smooth());
+    let mut stream = RandomStream::new(9, "rays");
+    for _ in 0..100 {
+        let target = Vec3::new(
+            stream.between(-0.9, 0.9),
+            stream.between(-0.9, 0.9),
+            stream.between(-0.9, 0.9),
+        );
+        let origin = sample_unit_sphere((stream.next_01(), stream.next_01())) * 5.0;
+        let ray = Ray::new(origin, target - origin);
+        let entry = cube.hit(&ray, EPSILON, Float::INFINITY).unwrap();
+        assert!(entry.front_face);
+        // Corners were pushed out by half a unit, the middle of faces most.
+        assert!(entry.position.length() > 1.0 - 1e-6);
+        let inside = Ray::new(entry.position, ray.dir);
+        let exit = cube.hit(&inside, 1e-6, Float::INFINITY).unwrap();
+        assert!(!exit.front_face);
+    }
+    let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+    assert_close(cube.hit(&ray, EPSILON, Float::INFINITY).unwrap().t, 3.5);
+}
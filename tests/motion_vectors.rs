@@ -0,0 +1,168 @@
+//! Motion vectors of objects and cameras moving between frames, and the
+//! passes of progressive renders they carry over.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+fn camera(look_from: Vec3, projection: Projection) -> Camera {
+    Camera::new(
+        look_from,
+        look_from + Vec3::new(0.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        40.0,
+        1.5,
+        0.0,
+        4.0,
+    )
+    .with_projection(projection)
+}
+
+fn settings() -> RenderSettings {
+    RenderSettings {
+        width: 24,
+        height: 16,
+        samples_per_pixel: 4,
+        max_depth: 4,
+        seed: 2,
+        threads: 1,
+        ..RenderSettings::default()
+    }
+}
+
+/// Motion vector of pixel (`i`, `j`) of `aovs`.
+fn motion(aovs: &AovImages, i: usize, j: usize) -> (Float, Float) {
+    let index = (j * settings().width + i) * 2;
+    (aovs.motion[index], aovs.motion[index + 1])
+}
+
+#[test]
+fn cameras_project_points_back_where_their_rays_go() {
+    let origin = Vec3::new(1.0, 2.0, 3.0);
+    for projection in [
+        Projection::Perspective,
+        Projection::Orthographic { height: 3.0 },
+        Projection::Fisheye { fov_degrees: 180.0 },
+        Projection::Equirectangular,
+    ] {
+        let camera = camera(origin, projection);
+        for &(s, t) in &[(0.5, 0.5), (0.1, 0.8), (0.9, 0.3), (0.3, 0.05)] {
+            let ray = camera.get_ray(s, t, (0.5, 0.5));
+            let (ps, pt) = camera.project(ray.at(2.5)).unwrap();
+            assert!(
+                (ps - s).abs() < 1e-4 && (pt - t).abs() < 1e-4,
+                "{:?}: ({}, {}) != ({}, {})",
+                projection,
+                ps,
+                pt,
+                s,
+                t
+            );
+        }
+    }
+    let behind = origin + Vec3::new(0.0, 0.0, 1.0);
+    let perspective = camera(origin, Projection::Perspective);
+    assert!(perspective.project(behind).is_none());
+}
+
+#[test]
+fn moving_objects_and_cameras_get_motion_vectors() {
+    let mut scene = Scene::new(Units::Meters);
+    scene.environment = Environment::Constant(Vec3::new(0.5, 0.5, 0.5));
+    let material = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    let ball = scene.add(Sphere::new(Vec3::new(0.0, 0.0, -4.0), 1.5, material));
+    // Four units across the image, six pixels per unit.
+    let orthographic = camera(
+        Vec3::new(0.0, 0.0, 0.0),
+        Projection::Orthographic { height: 16.0 / 6.0 },
+    );
+    let mut renderer = Renderer::new(settings()).unwrap();
+
+    // Nothing moves in still scenes.
+    let (_, aovs) = renderer.render_with_aovs(&scene, &orthographic);
+    assert!(aovs.motion.iter().all(|&x| x.abs() < 1e-3));
+
+    // The ball went half a unit right and a quarter up since the last frame.
+    scene.update_transform(ball, &Mat4::translation(Vec3::new(0.5, 0.25, 0.0)));
+    scene.set_previous_transform(ball, Some(&Mat4::identity()));
+    let (_, aovs) = renderer.render_with_aovs(&scene, &orthographic);
+    let (x, y) = motion(&aovs, 13, 7);
+    assert!(
+        (x - 3.0).abs() < 1e-3 && (y + 1.5).abs() < 1e-3,
+        "{} {}",
+        x,
+        y
+    );
+    assert_eq!(motion(&aovs, 0, 0), (0.0, 0.0));
+
+    // Panning the camera sweeps the still background the other way.
+    scene.set_previous_transform(ball, None);
+    let perspective = camera(Vec3::new(0.0, 0.0, 0.0), Projection::Perspective);
+    let mut previous = perspective;
+    previous.update_view(
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(-0.1, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 0.0),
+    );
+    renderer.set_previous_camera(Some(previous));
+    let (_, aovs) = renderer.render_with_aovs(&scene, &perspective);
+    for &(i, j) in &[(0, 0), (12, 8), (23, 15)] {
+        let (x, y) = motion(&aovs, i, j);
+        assert!(x < -1.0 && y.abs() < 0.5, "{} {}", x, y);
+    }
+}
+
+#[test]
+fn passes_are_carried_over_along_motion_vectors() {
+    let mut scene = Scene::new(Units::Meters);
+    scene.environment = Environment::Constant(Vec3::new(0.5, 0.5, 0.5));
+    let red = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.8, 0.2, 0.1),
+    });
+    scene.add(Sphere::new(Vec3::new(0.0, 0.0, -4.0), 1.5, red));
+    let projection = Projection::Orthographic { height: 16.0 / 6.0 };
+    let before = camera(Vec3::new(0.0, 0.0, 0.0), projection);
+    let mut renderer = Renderer::new(settings()).unwrap();
+    let (image, aovs) = renderer.render_with_aovs(&scene, &before);
+    let mut accumulation = Accumulation::default();
+    accumulation.add(image.pixels());
+    accumulation.add(renderer.render(&scene, &before).pixels());
+    let (old, depth) = (accumulation.mean(), aovs.depth);
+
+    // Half a unit to the right, the ball goes three pixels left.
+    let after = camera(Vec3::new(0.5, 0.0, 0.0), projection);
+    renderer.set_previous_camera(Some(before));
+    let (_, aovs) = renderer.render_with_aovs(&scene, &after);
+    let width = settings().width;
+    let pixel = |pixels: &[Float], i: usize, j: usize| {
+        let index = (j * width + i) * 4;
+        pixels[index..index + 4].to_vec()
+    };
+    let mut carried = accumulation.clone();
+    carried.reproject(&aovs, &depth, 0.5, width, 8.0);
+    assert_eq!(carried.passes(), 0);
+    let mean = carried.mean();
+    assert_eq!(pixel(&mean, 9, 8), pixel(&old, 12, 8));
+    // The background stays put, but where the ball was, it starts over.
+    assert_eq!(pixel(&mean, 23, 8), pixel(&old, 23, 8));
+    assert_eq!(pixel(&mean, 19, 8), vec![0.0; 4]);
+
+    // So do those that showed something else before.
+    let mut occluded = depth.clone();
+    occluded[8 * width + 12] = 1.0;
+    let mut carried = accumulation.clone();
+    carried.reproject(&aovs, &occluded, 0.5, width, 8.0);
+    assert_eq!(pixel(&carried.mean(), 9, 8), vec![0.0; 4]);
+
+    // Passes carried over count for at most the weight given.
+    let mut carried = accumulation;
+    carried.reproject(&aovs, &depth, 0.5, width, 1.0);
+    let (image, _) = renderer.render_with_aovs(&scene, &after);
+    carried.add(image.pixels());
+    let (mean, new) = (carried.mean(), pixel(image.pixels(), 9, 8));
+    for (channel, value) in pixel(&mean, 9, 8).iter().enumerate() {
+        let expected = (pixel(&old, 12, 8)[channel] + new[channel]) / 2.0;
+        assert!((value - expected).abs() < 1e-6, "{} != {}", value, expected);
+    }
+}
@@ -0,0 +1,203 @@
+//! Tangent frames of spheres, their UV projections and the normals normal
+//! and bump maps give.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+fn assert_vec_close(a: Vec3, b: Vec3) {
+    assert!((a - b).length() < 1e-6, "{:?} != {:?}", a, b);
+}
+
+/// Texture of a single color.
+fn uniform(color: Vec3) -> Texture {
+    Texture::new(vec![color; 4], 2, 2)
+}
+
+#[test]
+fn sphere_tangent_frames_follow_uvs() {
+    let sphere = Sphere::new(Vec3::new(1.0, 2.0, 3.0), 2.0, MaterialId(0));
+    for &(u, v) in &[(0.1, 0.2), (0.5, 0.5), (0.8, 0.9), (0.3, 0.7)] {
+        let (position, normal) = sphere.surface_at(u, v);
+        let (found_u, found_v) = sphere.uv_at(position);
+        assert!((found_u - u).abs() < 1e-5 && (found_v - v).abs() < 1e-5);
+
+        let (tangent, bitangent) = sphere.tangents_at(u, v);
+        assert_vec_close(tangent.cross(bitangent), normal);
+        // Tangents point where the surface goes as u and v grow.
+        let step = 1e-4;
+        let along_u = sphere.surface_at(u + step, v).0 - position;
+        let along_v = sphere.surface_at(u, v + step).0 - position;
+        assert!(along_u.unit().dot(tangent) > 0.999);
+        assert!(along_v.unit().dot(bitangent) > 0.999);
+    }
+}
+
+#[test]
+fn normal_maps_bend_shading_normals() {
+    let mut scene = Scene::new(Units::Meters);
+    let gray = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    let ball = scene.add(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, gray));
+    let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+    let (_, hit) = scene.hit_object(&ray, 1e-4, Float::INFINITY).unwrap();
+
+    // The flat color keeps the normal.
+    scene.set_normal_map(
+        gray,
+        Some(NormalMap {
+            texture: uniform(Vec3::new(0.5, 0.5, 1.0)),
+            strength: 1.0,
+        }),
+    );
+    let mut flat = hit;
+    scene.apply_shading_normal(ball, &mut flat);
+    assert_vec_close(flat.normal, hit.normal);
+
+    // Red tilts it along u, which goes towards +x at the front of spheres.
+    scene.set_normal_map(
+        gray,
+        Some(NormalMap {
+            texture: uniform(Vec3::new(1.0, 0.5, 1.0)),
+            strength: 1.0,
+        }),
+    );
+    let mut tilted = hit;
+    scene.apply_shading_normal(ball, &mut tilted);
+    let half = (0.5 as Float).sqrt();
+    assert_vec_close(tilted.normal, Vec3::new(half, 0.0, half));
+}
+
+#[test]
+fn meshes_follow_their_uv_projection() {
+    let mut scene = Scene::new(Units::Meters);
+    let gray = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    // A square of the xy plane facing +z, its first edge along the diagonal.
+    let positions = vec![
+        Vec3::new(-1.0, -1.0, 0.0),
+        Vec3::new(1.0, 1.0, 0.0),
+        Vec3::new(-1.0, 1.0, 0.0),
+        Vec3::new(1.0, -1.0, 0.0),
+    ];
+    let square = Mesh::new(positions, vec![[0, 1, 2], [0, 3, 1]], gray);
+    let id = scene.add_shape(Shape::Mesh(square));
+    scene.set_normal_map(
+        gray,
+        Some(NormalMap {
+            texture: uniform(Vec3::new(1.0, 0.5, 1.0)),
+            strength: 1.0,
+        }),
+    );
+    let ray = Ray::new(Vec3::new(-0.2, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+    let (_, hit) = scene.hit_object(&ray, 1e-4, Float::INFINITY).unwrap();
+
+    // Without a projection, u runs along the first edge of the triangle.
+    let mut along_edge = hit;
+    scene.apply_shading_normal(id, &mut along_edge);
+    assert_vec_close(
+        along_edge.normal,
+        Vec3::new(0.5, 0.5, (0.5 as Float).sqrt()),
+    );
+
+    // Projected from the bounding sphere, u runs along +x like on spheres.
+    let half = (0.5 as Float).sqrt();
+    for &projection in &[UvProjection::Planar, UvProjection::Box] {
+        scene.set_uv_projection(id, projection);
+        let mut projected = hit;
+        scene.apply_shading_normal(id, &mut projected);
+        assert_vec_close(projected.normal, Vec3::new(half, 0.0, half));
+    }
+}
+
+#[test]
+fn bump_maps_tilt_normals_down_slopes() {
+    let mut scene = Scene::new(Units::Meters);
+    let gray = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    let ball = scene.add(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, gray));
+    let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+    let (_, hit) = scene.hit_object(&ray, 1e-4, Float::INFINITY).unwrap();
+
+    // A constant height keeps the normal.
+    scene.set_bump_map(
+        gray,
+        Some(BumpMap {
+            texture: uniform(Vec3::new(0.7, 0.7, 0.7)),
+            height: 1.0,
+        }),
+    );
+    let mut flat = hit;
+    scene.apply_shading_normal(ball, &mut flat);
+    assert_vec_close(flat.normal, hit.normal);
+
+    // Height growing with u, by 2π over the whole texture, rises as fast as
+    // the equator goes around, tilting the normal away from +x by 45°.
+    let ramp = (0..8)
+        .map(|x| Vec3::new(1.0, 1.0, 1.0) * (x as Float / 8.0))
+        .collect();
+    scene.set_bump_map(
+        gray,
+        Some(BumpMap {
+            texture: Texture::new(ramp, 8, 1),
+            height: 2.0 * consts::PI,
+        }),
+    );
+    let mut tilted = hit;
+    scene.apply_shading_normal(ball, &mut tilted);
+    let half = (0.5 as Float).sqrt();
+    assert_vec_close(tilted.normal, Vec3::new(-half, 0.0, half));
+}
+
+#[test]
+fn uv_projections_frame_their_axes() {
+    let center = Vec3::new(1.0, 2.0, 3.0);
+    let sphere = Sphere::new(center, 2.0, MaterialId(0));
+    let frame = |offset: Vec3, projection| sphere.uv_frame(center + offset * 2.0, projection);
+
+    let front = frame(Vec3::new(0.0, 0.0, 1.0), UvProjection::Planar);
+    assert_eq!(front.uv, (0.5, 0.5));
+    assert_vec_close(front.tangents.0, Vec3::new(1.0, 0.0, 0.0));
+    assert_vec_close(front.tangents.1, Vec3::new(0.0, 1.0, 0.0));
+    assert_eq!(front.lengths, (4.0, 4.0));
+
+    // Box faces are seen from outside, the top one with -z up.
+    let side = frame(Vec3::new(1.0, 0.0, 0.0), UvProjection::Box);
+    assert_vec_close(side.tangents.0, Vec3::new(0.0, 0.0, -1.0));
+    let normal = Vec3::new(0.3, 0.9, -0.1).unit();
+    let top = frame(normal, UvProjection::Box);
+    assert_vec_close(top.tangents.0.cross(top.tangents.1).unit(), normal);
+    assert!(top.uv.0 > 0.5 && top.uv.1 > 0.5);
+
+    let label = frame(Vec3::new(0.0, 0.6, 0.8), UvProjection::Cylindrical);
+    assert!((label.uv.1 - 0.8).abs() < 1e-9);
+    assert_eq!(
+        label.uv.0,
+        frame(Vec3::new(0.0, 0.6, 0.8), UvProjection::Spherical)
+            .uv
+            .0
+    );
+
+    // Whatever the projection, frames lie in the surface.
+    for &projection in &[
+        UvProjection::Spherical,
+        UvProjection::Cylindrical,
+        UvProjection::Planar,
+        UvProjection::Box,
+    ] {
+        for index in 0..200 {
+            let normal =
+                sample_unit_sphere((index as Float / 200.0, (index * 7 % 200) as Float / 200.0));
+            let frame = frame(normal, projection);
+            let (u, v) = frame.uv;
+            assert!((0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v));
+            for tangent in &[frame.tangents.0, frame.tangents.1] {
+                assert!((tangent.length() - 1.0).abs() < 1e-4);
+                assert!(tangent.dot(normal).abs() < 1e-4, "{:?}", projection);
+            }
+            assert!(frame.lengths.0 > 0.0 && frame.lengths.1 > 0.0);
+        }
+    }
+}
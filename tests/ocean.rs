@@ -0,0 +1,80 @@
+//! Procedural ocean waves and the water material.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+#[test]
+fn ocean_waves_tilt_normals_and_move() {
+    let ocean = Ocean::new(8.0, 30.0, 1);
+    let points = (0..64).map(|index| (index as Float * 0.37, index as Float * -0.61));
+
+    let mut tilt: Float = 0.0;
+    for (x, z) in points.clone() {
+        let normal = ocean.normal(x, z);
+        assert!((normal.length() - 1.0).abs() < 1e-4);
+        assert!(normal.y > 0.0);
+        tilt = tilt.max(1.0 - normal.y);
+        // Waves of an 8 m/s wind stay within a few meters.
+        assert!(ocean.height(x, z).abs() < 5.0);
+    }
+    assert!(tilt > 0.01);
+
+    // Time moves the waves, the same seed and time giving the same waves.
+    let later = ocean.clone().with_time(0.5);
+    assert!(points
+        .clone()
+        .any(|(x, z)| (later.normal(x, z) - ocean.normal(x, z)).length() > 1e-3));
+    let again = Ocean::new(8.0, 30.0, 1).with_time(0.5);
+    for (x, z) in points.clone() {
+        assert_eq!(later.height(x, z), again.height(x, z));
+    }
+
+    let calm = Ocean::new(0.0, 30.0, 1);
+    for (x, z) in points {
+        assert_eq!(calm.height(x, z), 0.0);
+        assert_eq!(calm.normal(x, z).y, 1.0);
+    }
+}
+
+#[test]
+fn water_spheres_take_oceans() {
+    let source = "units centimeters\n\
+                  sphere 0 -100000 0 100000 water ocean 8 30 name sea";
+    let mut scene = parse_scene(source, "ocean", std::path::Path::new("")).unwrap();
+    let water = MaterialId(0);
+    assert!(matches!(
+        scene.material(water),
+        MaterialType::Dialectric { refractive_index } if *refractive_index == WATER_REFRACTIVE_INDEX
+    ));
+    // Absorption is per scene unit.
+    assert!((scene.absorption(water).x - water_absorption().x / 100.0).abs() < 1e-6);
+    assert!(scene.ocean(water).is_some());
+
+    // Sweeping the time moves every ocean.
+    let hash = materials_hash(&scene);
+    let mut sweep = Sweep::parse("ocean.time=0:1:0.25").unwrap();
+    sweep.bind(&scene).unwrap();
+    sweep.apply(&mut scene, 0.75);
+    assert_eq!(scene.ocean(water).unwrap().time, 0.75);
+    assert_ne!(materials_hash(&scene), hash);
+
+    scene.set_ocean(water, None);
+    assert!(Sweep::parse("ocean.time=0:1:0.25")
+        .unwrap()
+        .bind(&scene)
+        .is_err());
+    assert!(Sweep::parse("ocean.speed=0:1:0.25").is_err());
+}
+
+fn materials_hash(scene: &Scene) -> u64 {
+    let camera = Camera::new(
+        Vec3::new(0.0, 100.0, 0.0),
+        Vec3::new(100.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        40.0,
+        1.0,
+        0.0,
+        1.0,
+    );
+    RenderHashes::new(scene, &camera, &RenderSettings::default()).materials
+}
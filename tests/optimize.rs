@@ -0,0 +1,133 @@
+//! Flattening of instances, merging of meshes and removal of the spheres
+//! that can't change the image.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+use std::sync::Arc;
+
+#[test]
+fn optimizing_removes_spheres_that_cannot_show() {
+    let mut scene = Scene::new(Units::Meters);
+    let gray = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    let air = scene.add_material(MaterialType::Dialectric {
+        refractive_index: 1.0,
+    });
+    let glass = scene.add_material(MaterialType::Dialectric {
+        refractive_index: 1.5,
+    });
+    let light = scene.add_material(MaterialType::DiffuseLight {
+        emit: Vec3::new(4.0, 4.0, 4.0),
+    });
+
+    let ground = Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, gray);
+    scene.add(ground);
+    scene.add(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 0.0, gray));
+    scene.add(ground);
+    // Air in the open is invisible, but a bubble in glass isn't.
+    scene.add(Sphere::new(Vec3::new(5.0, 1.0, 0.0), 1.0, air));
+    scene.add(Sphere::new(Vec3::new(-5.0, 1.0, 0.0), 1.0, glass));
+    scene.add(Sphere::new(Vec3::new(-5.0, 1.0, 0.0), 0.5, air));
+    scene.add_light(Sphere::new(Vec3::new(0.0, 5.0, 0.0), 0.5, light));
+    let named = scene.add(Sphere::new(Vec3::new(3.0, 1.0, 3.0), 0.0, gray));
+    scene.set_name(named, "marker");
+
+    let report = scene.optimize();
+    assert_eq!(
+        report,
+        OptimizationReport {
+            flattened: 0,
+            merged: 0,
+            degenerate: 1,
+            invisible: 1,
+            duplicates: 1,
+        }
+    );
+    assert_eq!(report.removed(), 3);
+    assert_eq!(scene.spheres.len(), 5);
+
+    // Lights and names follow their spheres, which keep their order.
+    assert_eq!(scene.sphere(ObjectId(2)).radius, 0.5);
+    assert_eq!(scene.lights, vec![ObjectId(3)]);
+    assert_eq!(scene.sphere(scene.lights[0]).material, light);
+    assert_eq!(scene.sphere_named("marker"), Some(ObjectId(4)));
+
+    let ray = Ray::new(Vec3::new(0.0, 5.0, 10.0), Vec3::new(0.0, 0.0, -1.0));
+    let (hit, _) = scene.hit_object(&ray, 1e-3, Float::INFINITY).unwrap();
+    assert_eq!(hit, ObjectId(3));
+
+    assert_eq!(scene.optimize().removed(), 0);
+}
+
+/// Triangle of the xz plane facing up, from `corner` to one unit along x
+/// and along -z.
+fn triangle(corner: Vec3, material: MaterialId) -> Mesh {
+    let positions = vec![
+        corner,
+        corner + Vec3::new(1.0, 0.0, 0.0),
+        corner + Vec3::new(0.0, 0.0, -1.0),
+    ];
+    Mesh::new(positions, vec![[0, 1, 2]], material)
+}
+
+#[test]
+fn optimizing_flattens_instances_and_merges_static_meshes() {
+    let mut scene = Scene::new(Units::Meters);
+    let gray = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    let red = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.8, 0.1, 0.1),
+    });
+    let origin = Vec3::new(0.0, 0.0, 0.0);
+    let first = scene.add_shape(Shape::Mesh(triangle(origin, gray)));
+    let moving = scene.add_shape(Shape::Mesh(triangle(Vec3::new(5.0, 0.0, 0.0), gray)));
+    scene
+        .animation
+        .object_track(moving, Vec3::new(5.0, 0.0, 0.0))
+        .insert(Keyframe {
+            time: 0.0,
+            value: Pose::default(),
+            interpolation: Interpolation::Linear,
+        });
+    scene.add_shape(Shape::Mesh(triangle(Vec3::new(2.0, 0.0, 0.0), red)));
+    let named = scene.add_shape(Shape::Mesh(triangle(Vec3::new(8.0, 0.0, 0.0), gray)));
+    scene.set_name(named, "kept");
+    // Placed 3 units along +x and raised by one.
+    let levels = Arc::new(LevelsOfDetail::new(triangle(origin, MaterialId(0))));
+    let placement = Mat4::translation(Vec3::new(3.0, 1.0, 0.0));
+    scene.add_shape(Shape::Instance(Instance::new(levels, &placement, gray)));
+
+    let report = scene.optimize();
+    assert_eq!((report.flattened, report.merged), (1, 1));
+    assert_eq!(report.removed(), 0);
+    assert_eq!(scene.spheres.len(), 4);
+    assert_eq!(scene.sphere_named("kept"), Some(ObjectId(3)));
+    assert!(report
+        .to_string()
+        .starts_with("flattened 1 instances, merged 1 meshes"));
+
+    // The instance is now part of the first mesh, where it was placed.
+    let down = Ray::new(Vec3::new(3.2, 5.0, -0.2), Vec3::new(0.0, -1.0, 0.0));
+    let (id, hit) = scene.hit_object(&down, 1e-9, Float::INFINITY).unwrap();
+    assert_eq!(id, first);
+    assert!((hit.t - 4.0).abs() < 1e-9);
+    assert_eq!(hit.material, gray);
+    match scene.shape(first) {
+        Some(Shape::Mesh(mesh)) => assert_eq!(mesh.triangle_count(), 2),
+        other => panic!("{:?}", other),
+    }
+    // Animated meshes and other materials stay apart.
+    let down = Ray::new(Vec3::new(5.2, 5.0, -0.2), Vec3::new(0.0, -1.0, 0.0));
+    assert_eq!(
+        scene.hit_object(&down, 1e-9, Float::INFINITY).unwrap().0,
+        moving
+    );
+    let down = Ray::new(Vec3::new(2.2, 5.0, -0.2), Vec3::new(0.0, -1.0, 0.0));
+    let (_, hit) = scene.hit_object(&down, 1e-9, Float::INFINITY).unwrap();
+    assert_eq!(hit.material, red);
+
+    assert_eq!(scene.optimize(), OptimizationReport::default());
+}
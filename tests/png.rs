@@ -0,0 +1,94 @@
+//! PNG images decoded, like the textures of glTF files.
+
+use raytracer::png::*;
+
+/// 16 x 10 RGB image compressed with dynamic Huffman codes, its rows
+/// filtered in turn with every filter.
+const FILTERED: &str = "iVBORw0KGgoAAAANSUhEUgAAABAAAAAKCAIAAAAy3EnLAAAA9klEQVR42p2PsUrDABRFT1XIW14oQsAHGQIZhAzSCEJAIQUhQ6HQoVtBSrdu9QMe1N2hk3MHd/sBgt1cq1/Q2amLu1FxkkIpHC53uVwOQBMSaEEbejCECUxhBnNYwBJWsIYNNLB6EOzOQT3AAizEIizGUizDcqzASqzCulgfG2AjbHzIOaKh6LFoJGqisWgimoqeimaiZ6K56IVoIfos+nL0/UAAIUQQb2fwV5ymp4lXLR+3/b7nT0N/n/jn1E9mfjn3m4XfLf1x5a9r/9i4NniopbPd2UP6lu2WV6Kl6LVoJdoR7Yq+/ZdOIYMcCiih+slfRnV+ARVNPF+Pdb94AAAAAElFTkSuQmCC";
+
+/// 4 x 2 image of 2-bit palette indices, red, green, blue and white, the
+/// green half transparent.
+const PALETTE: &str = "iVBORw0KGgoAAAANSUhEUgAAAAQAAAACAgMAAAACxpXwAAAADFBMVEX/AAAA/wAAAP/////7AGD2AAAAAnRSTlP/gAgPs2oAAAAMSURBVHjaY5BmeAIAATkBAHuZQjcAAAAASUVORK5CYII=";
+
+fn decode_base64(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let (mut bits, mut count) = (0u32, 0);
+    for byte in text.bytes().filter(|&byte| byte != b'=') {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            _ => 63,
+        };
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    bytes
+}
+
+#[test]
+fn pngs_decode_through_every_filter_and_code() {
+    let (pixels, width, height) = decode_png(&decode_base64(FILTERED)).unwrap();
+    assert_eq!((width, height), (16, 10));
+    for y in 0..10 {
+        for x in 0..16 {
+            let expected = [(x * 16) % 256, (y * 25) % 256, (x * y * 7) % 256];
+            let pixel = &pixels[(y * 16 + x) * 4..][..4];
+            for (value, expected) in pixel.iter().zip(&expected) {
+                assert_eq!((value * 255.0).round() as usize, *expected, "{} {}", x, y);
+            }
+            assert_eq!(pixel[3], 1.0);
+        }
+    }
+
+    let (pixels, width, height) = decode_png(&decode_base64(PALETTE)).unwrap();
+    assert_eq!((width, height), (4, 2));
+    let red = [1.0, 0.0, 0.0, 1.0];
+    let green = [0.0, 1.0, 0.0, 128.0 / 255.0];
+    let (blue, white) = ([0.0, 0.0, 1.0, 1.0], [1.0; 4]);
+    let expected = [red, green, blue, white, white, blue, green, red].concat();
+    assert_eq!(pixels, expected);
+}
+
+#[test]
+fn encoded_pngs_decode_to_their_pixels() {
+    let pixels: Vec<u8> = (0..5 * 3 * 4)
+        .map(|value| (value * 37 % 256) as u8)
+        .collect();
+    let png = encode_png(&pixels, 5, 3, true, &[], None);
+    let (decoded, width, height) = decode_png(&png).unwrap();
+    assert_eq!((width, height), (5, 3));
+    let decoded: Vec<u8> = decoded
+        .iter()
+        .map(|value| (value * 255.0).round() as u8)
+        .collect();
+    assert_eq!(decoded, pixels);
+
+    let mut broken = png.clone();
+    broken.truncate(png.len() - 20);
+    assert!(decode_png(&broken).is_err());
+    assert!(decode_png(b"not a png").is_err());
+}
+
+#[test]
+fn smooth_images_compress() {
+    let pixels: Vec<u8> = (0..64 * 48)
+        .flat_map(|index| {
+            let (x, y) = (index % 64, index / 64);
+            [(x * 4) as u8, (y * 5) as u8, ((x + y) * 2) as u8]
+        })
+        .collect();
+    let png = encode_png(&pixels, 64, 48, false, &[], None);
+    assert!(png.len() < pixels.len() / 10, "{} bytes", png.len());
+    let (decoded, ..) = decode_png(&png).unwrap();
+    let decoded: Vec<u8> = decoded
+        .chunks(4)
+        .flat_map(|pixel| pixel[..3].iter().map(|value| (value * 255.0).round() as u8))
+        .collect();
+    assert_eq!(decoded, pixels);
+}
@@ -0,0 +1,150 @@
+//! Hierarchies traversed in single precision finding the hits of full
+//! precision ones.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+use std::path::Path;
+
+fn random_point(center: Vec3, extent: Float) -> Vec3 {
+    center
+        + Vec3::new(
+            random_between(-extent, extent),
+            random_between(-extent, extent),
+            random_between(-extent, extent),
+        )
+}
+
+#[test]
+fn mixed_precision_bvhs_find_the_same_hits() {
+    seed_random(41);
+    // Far from the origin, where single precision is coarse.
+    for &center in &[Vec3::new(0.0, 0.0, 0.0), Vec3::new(3e4, -2e4, 5e4)] {
+        let spheres: Vec<Sphere> = (0..300)
+            .map(|_| {
+                Sphere::new(
+                    random_point(center, 10.0),
+                    random_between(0.05, 1.0),
+                    MaterialId(0),
+                )
+            })
+            .collect();
+        let full = Bvh::new(&spheres, BvhQuality::Sah);
+        let mixed = Bvh::new(&spheres, BvhQuality::Sah).with_precision(Precision::Mixed);
+        assert_eq!(mixed.precision(), Precision::Mixed);
+
+        for index in 0..3000 {
+            let origin = random_point(center, 12.0);
+            let direction = match index % 3 {
+                // Grazing the extreme points of spheres, where their boxes
+                // touch them.
+                0 => {
+                    let sphere = &spheres[index % spheres.len()];
+                    sphere.position + Vec3::new(sphere.radius, 0.0, 0.0) - origin
+                }
+                1 => Vec3::new(0.0, if index % 2 == 0 { 1.0 } else { -1.0 }, 0.0),
+                _ => sample_unit_sphere((random_01(), random_01())),
+            };
+            let ray = Ray::new(origin, direction);
+            assert_eq!(
+                mixed.hit(&ray, 1e-4, Float::INFINITY),
+                full.hit(&ray, 1e-4, Float::INFINITY)
+            );
+        }
+
+        let rays: Vec<Ray> = (0..RAY_PACKET_WIDTH)
+            .map(|_| {
+                Ray::new(
+                    center + Vec3::new(0.0, 0.0, 20.0),
+                    random_point(center, 8.0) - center,
+                )
+            })
+            .collect();
+        let t_max = vec![Float::INFINITY; rays.len()];
+        assert_eq!(
+            mixed.hit_packet(&rays, 1e-4, &t_max),
+            full.hit_packet(&rays, 1e-4, &t_max)
+        );
+    }
+}
+
+#[test]
+fn mixed_precision_meshes_and_scenes_find_the_same_hits() {
+    seed_random(42);
+    // Corners already in single precision, which rounding leaves alone.
+    let single = |point: Vec3| {
+        Vec3::new(
+            to_f32(point.x) as Float,
+            to_f32(point.y) as Float,
+            to_f32(point.z) as Float,
+        )
+    };
+    let positions: Vec<Vec3> = (0..600)
+        .map(|_| single(random_point(Vec3::new(0.0, 0.0, 0.0), 2.0)))
+        .collect();
+    let triangles: Vec<[usize; 3]> = (0..200).map(|i| [3 * i, 3 * i + 1, 3 * i + 2]).collect();
+    let full = Mesh::new(positions.clone(), triangles.clone(), MaterialId(0));
+    let mixed = Mesh::new(positions, triangles, MaterialId(0)).with_precision(Precision::Mixed);
+    assert_eq!(mixed.precision(), Precision::Mixed);
+    for _ in 0..2000 {
+        let origin = sample_unit_sphere((random_01(), random_01())) * 4.0;
+        let ray = Ray::new(origin, random_point(Vec3::new(0.0, 0.0, 0.0), 1.5) - origin);
+        // The watertight test of mixed precision rounds differently.
+        let (mixed, full) = (
+            mixed.hit(&ray, 1e-6, Float::INFINITY).map(|hit| hit.t),
+            full.hit(&ray, 1e-6, Float::INFINITY).map(|hit| hit.t),
+        );
+        match (mixed, full) {
+            (Some(mixed), Some(full)) => assert!((mixed - full).abs() < 1e-4 * full),
+            _ => assert_eq!(mixed, full),
+        }
+    }
+    // Moving meshes keeps their precision.
+    let moved = mixed.transformed(&Mat4::translation(Vec3::new(1.0, 0.0, 0.0)));
+    assert_eq!(moved.precision(), Precision::Mixed);
+    assert_eq!(
+        mixed.with_precision(Precision::Full).precision(),
+        Precision::Full
+    );
+
+    let source = "sphere 0 -1000 0 1000 lambertian 0.5 0.5 0.5\n\
+                  sphere 0 1 0 1 metal 0.7 0.6 0.5 0\n\
+                  precision mixed\n";
+    let scene = parse_scene(source, "precision", Path::new("")).unwrap();
+    assert_eq!(scene.precision(), Precision::Mixed);
+    let ray = Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+    let hit = scene.hit(&ray, 1e-4, Float::INFINITY).unwrap();
+    assert!((hit.t - 3.0).abs() < 1e-4);
+
+    let error = parse_scene("precision half", "precision", Path::new(""))
+        .err()
+        .unwrap();
+    assert!(error.to_string().contains("unknown precision 'half'"));
+}
+
+#[test]
+fn mixed_precision_meshes_are_watertight() {
+    // A fan of thin triangles around the origin, which rays through their
+    // shared corner and edges must not slip between.
+    let count = 64;
+    let mut positions = vec![Vec3::new(0.0, 0.0, 0.0)];
+    for index in 0..count {
+        let angle = index as Float / count as Float * 2.0 * consts::PI;
+        positions.push(Vec3::new(angle.cos(), angle.sin(), 0.0));
+    }
+    let triangles: Vec<[usize; 3]> = (0..count)
+        .map(|index| [0, index + 1, (index + 1) % count + 1])
+        .collect();
+    let mesh =
+        Mesh::new(positions.clone(), triangles, MaterialId(0)).with_precision(Precision::Mixed);
+
+    seed_random(43);
+    for index in 0..4000 {
+        let corner = positions[index % count + 1];
+        let target = corner * random_01();
+        let origin = random_point(Vec3::new(0.0, 0.0, 3.0), 2.0);
+        let ray = Ray::new(origin, target - origin);
+        let hit = mesh.hit(&ray, 1e-6, Float::INFINITY);
+        assert!(hit.is_some(), "{:?} slipped through", ray);
+    }
+}
@@ -0,0 +1,77 @@
+//! Environments prefiltered for previews.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+#[test]
+fn uniform_light_gives_uniform_irradiance() {
+    let white = Environment::Constant(Vec3::new(1.0, 1.0, 1.0));
+    let preview = PreviewEnvironment::build(&white, 32);
+    for &normal in &[
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, -1.0, 0.0),
+        Vec3::new(1.0, 2.0, -3.0),
+    ] {
+        let irradiance = preview.irradiance(normal);
+        assert!((irradiance.y - consts::PI).abs() < 0.01, "{:?}", irradiance);
+    }
+
+    // Lambertian surfaces under it reflect their albedo, in a single step.
+    let mut scene = Scene::new(Units::Meters);
+    scene.environment = white;
+    let gray = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    scene.add(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, gray));
+    scene.set_preview_environment(Some(preview));
+    let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+    let (color, alpha) = ray_color(&ray, &scene, &RenderSettings::default());
+    assert!((color.y - 0.5).abs() < 0.01, "{:?}", color);
+    assert_eq!(alpha, 1.0);
+}
+
+#[test]
+fn samples_aim_at_the_bright_parts() {
+    // A black 8x4 map with a single bright texel, above the horizon.
+    let mut pixels = vec![0.0; 8 * 4 * 3];
+    let texel = 8 + 5;
+    pixels[texel * 3..texel * 3 + 3].copy_from_slice(&[10.0, 10.0, 10.0]);
+    let environment = Environment::Map(EnvironmentMap::new(&pixels, 8, 4));
+    let preview = PreviewEnvironment::build(&environment, 16);
+
+    for i in 0..8 {
+        for j in 0..8 {
+            let u = ((i as Float + 0.5) / 8.0, (j as Float + 0.5) / 8.0);
+            let direction = preview.sample(u);
+            assert!((direction.length() - 1.0).abs() < 1e-6);
+            assert_eq!(environment.radiance(direction).x, 10.0);
+        }
+    }
+
+    // The bright texel lights what faces it more than what faces away.
+    let up = preview.irradiance(Vec3::new(0.0, 1.0, 0.0));
+    let down = preview.irradiance(Vec3::new(0.0, -1.0, 0.0));
+    assert!(up.x > 4.0 * down.x, "{:?} {:?}", up, down);
+}
+
+#[test]
+fn the_sun_is_kept_however_small() {
+    let sky = PhysicalSky::new(40.0, 30.0, 3.0);
+    let sun = sky.sun_direction();
+    let preview = PreviewEnvironment::build(&Environment::Sky(sky), 16);
+
+    let mut towards_sun = 0;
+    for i in 0..16 {
+        for j in 0..16 {
+            let u = ((i as Float + 0.5) / 16.0, (j as Float + 0.5) / 16.0);
+            if preview.sample(u).dot(sun) > 0.9 {
+                towards_sun += 1;
+            }
+        }
+    }
+    assert!(towards_sun > 128, "{}", towards_sun);
+
+    let facing = preview.irradiance(sun);
+    let away = preview.irradiance(-sun);
+    assert!(facing.y > 2.0 * away.y, "{:?} {:?}", facing, away);
+}
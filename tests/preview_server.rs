@@ -0,0 +1,65 @@
+//! Previews served over HTTP, for browsers to watch renders from afar.
+
+use raytracer::png::*;
+use raytracer::*;
+
+use std::io::prelude::*;
+use std::net::{TcpListener, TcpStream};
+
+/// Status line and body of the answer to a GET of `path`.
+fn get(address: &str, path: &str) -> (String, Vec<u8>) {
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, address).unwrap();
+    let mut answer = Vec::new();
+    stream.read_to_end(&mut answer).unwrap();
+    let end = answer
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .expect("the answer has headers");
+    let headers = String::from_utf8(answer[..end].to_vec()).unwrap();
+    let status = headers.lines().next().unwrap().to_string();
+    let length = headers
+        .lines()
+        .find_map(|line| line.strip_prefix("Content-Length: "))
+        .map(|length| length.parse::<usize>().unwrap());
+    let body = answer[end + 4..].to_vec();
+    assert_eq!(length, Some(body.len()));
+    (status, body)
+}
+
+#[test]
+fn the_latest_preview_is_served() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let server = PreviewServer::start(listener);
+
+    let (status, _) = get(&address, "/preview.png");
+    assert_eq!(status, "HTTP/1.1 503 Service Unavailable");
+    let (status, page) = get(&address, "/");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert!(String::from_utf8(page).unwrap().contains("preview.png"));
+
+    let first = encode_png(&[255, 0, 0, 0, 255, 0], 2, 1, false, &[], None);
+    server.publish(first.clone());
+    let (status, png) = get(&address, "/preview.png");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert_eq!(png, first);
+    // Browsers add a query to get past their caches.
+    let second = encode_png(&[0, 0, 255, 0, 0, 0], 2, 1, false, &[], None);
+    server.clone().publish(second.clone());
+    assert_eq!(get(&address, "/preview.png?1234").1, second);
+
+    assert_eq!(get(&address, "/other").0, "HTTP/1.1 404 Not Found");
+}
+
+#[test]
+fn encoded_pngs_match_written_ones() {
+    let name = std::env::temp_dir().join("raytracer_encoded.png");
+    let name = name.to_str().unwrap();
+    let pixels = [10, 20, 30, 40, 50, 60, 70, 80];
+    let metadata = [("Software".to_string(), "raytracer".to_string())];
+    create_png(name, &pixels, 2, 1, true, &metadata, None).unwrap();
+    let written = std::fs::read(name).unwrap();
+    std::fs::remove_file(name).unwrap();
+    assert_eq!(encode_png(&pixels, 2, 1, true, &metadata, None), written);
+}
@@ -0,0 +1,53 @@
+//! Random streams of scene generators, which path sampling mustn't disturb.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+fn layout(scene: &Scene) -> Vec<[Float; 4]> {
+    scene
+        .spheres
+        .iter()
+        .map(|sphere| {
+            let p = sphere.position;
+            [p.x, p.y, p.z, sphere.radius]
+        })
+        .collect()
+}
+
+#[test]
+fn generated_scenes_ignore_the_sampling_sequence() {
+    seed_random(1);
+    let first = make_random_scene(7);
+    seed_random(2);
+    random_u64();
+    let second = make_random_scene(7);
+    assert_eq!(layout(&first), layout(&second));
+    assert_eq!(
+        format!("{:?}", first.materials),
+        format!("{:?}", second.materials)
+    );
+
+    assert_ne!(layout(&first), layout(&make_random_scene(8)));
+}
+
+#[test]
+fn named_streams_are_unrelated() {
+    let mut a = RandomStream::new(3, "layout");
+    let mut b = RandomStream::new(3, "colors");
+    let mut again = RandomStream::new(3, "layout");
+    for _ in 0..16 {
+        let x = a.next_u64();
+        assert_eq!(x, again.next_u64());
+        assert_ne!(x, b.next_u64());
+    }
+    let u = a.next_01();
+    assert!((0.0..1.0).contains(&u));
+}
+
+#[test]
+fn hashed_bits_stay_below_one() {
+    assert_eq!(uniform_from_bits(0), 0.0);
+    assert_eq!(uniform_from_bits(1 << 63), 0.5);
+    let largest = uniform_from_bits(u64::MAX);
+    assert!(largest < 1.0 && largest > 1.0 - 2.0 * Float::EPSILON);
+}
@@ -0,0 +1,71 @@
+//! Stratification guarantees of the pixel samplers.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+/// Points of dimensions `dimension` and `dimension + 1` of `count` samples.
+fn points(
+    sampler_type: SamplerType,
+    seed: u64,
+    count: usize,
+    dimension: u32,
+) -> Vec<(Float, Float)> {
+    (0..count)
+        .map(|index| {
+            seed_random(index as u64);
+            let mut sampler = sampler_type.start_sample(seed, index, count);
+            sampler.set_dimension(dimension);
+            sampler.get_2d()
+        })
+        .collect()
+}
+
+/// Number of points in every cell of a `columns` x `rows` grid.
+fn cell_counts(points: &[(Float, Float)], columns: usize, rows: usize) -> Vec<usize> {
+    let mut counts = vec![0; columns * rows];
+    for &(x, y) in points {
+        assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+        counts[(y * rows as Float) as usize * columns + (x * columns as Float) as usize] += 1;
+    }
+    counts
+}
+
+#[test]
+fn stratified_samples_fill_every_cell() {
+    let points = points(SamplerType::Stratified, 7, 16, 0);
+    assert!(cell_counts(&points, 4, 4).iter().all(|&count| count == 1));
+}
+
+#[test]
+fn sobol_samples_are_elementary_intervals() {
+    // Every pair of dimensions is a (0, 2) sequence: 16 samples put one point
+    // in every 16x1, 8x2, 4x4, 2x8 and 1x16 cell, whatever the scrambling.
+    for seed in 0..4 {
+        for &dimension in &[0, 2, 10, 30] {
+            let points = points(SamplerType::Sobol, seed, 16, dimension);
+            for &(columns, rows) in &[(16, 1), (8, 2), (4, 4), (2, 8), (1, 16)] {
+                let counts = cell_counts(&points, columns, rows);
+                assert!(counts.iter().all(|&count| count == 1), "{:?}", counts);
+            }
+        }
+    }
+}
+
+#[test]
+fn halton_samples_are_stratified_per_dimension() {
+    // The first 2^k samples of the base 2 dimension and the first 3^k
+    // samples of the base 3 one fall in distinct intervals.
+    let points = points(SamplerType::Halton, 3, 27, 0);
+    assert!(cell_counts(&points[..16], 16, 1)
+        .iter()
+        .all(|&count| count == 1));
+    assert!(cell_counts(&points, 1, 27).iter().all(|&count| count == 1));
+}
+
+#[test]
+fn scrambling_follows_the_seed() {
+    for &sampler_type in &[SamplerType::Halton, SamplerType::Sobol] {
+        assert_eq!(points(sampler_type, 5, 8, 4), points(sampler_type, 5, 8, 4));
+        assert_ne!(points(sampler_type, 5, 8, 4), points(sampler_type, 6, 8, 4));
+    }
+}
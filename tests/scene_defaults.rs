@@ -0,0 +1,68 @@
+//! Settings derived from scenes that don't tune them.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+fn camera() -> Camera {
+    Camera::new(
+        Vec3::new(0.0, 1.0, 10.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0,
+        16.0 / 9.0,
+        0.0,
+        1.0,
+    )
+}
+
+fn scene(scale: Float, environment: Float) -> Scene {
+    let mut scene = Scene::new(Units::Meters);
+    scene.environment = Environment::Constant(Vec3::new(environment, environment, environment));
+    let white = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(1.0, 1.0, 1.0),
+    });
+    // The ground is left out of the extent.
+    scene.add(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, white));
+    scene.add(Sphere::new(Vec3::new(0.0, 1.0, 0.0), scale, white));
+    scene.add(Sphere::new(Vec3::new(3.0 * scale, 1.0, 0.0), scale, white));
+    scene
+}
+
+#[test]
+fn the_offset_follows_the_extent() {
+    let small = SceneDefaults::derive(&scene(0.01, 1.0), &camera());
+    let large = SceneDefaults::derive(&scene(1.0, 1.0), &camera());
+    assert!((large.extent - (5.0 * 5.0 + 2.0 * 2.0 + 2.0 * 2.0 as Float).sqrt()).abs() < 1e-6);
+    assert!((large.epsilon / small.epsilon - 100.0).abs() < 1e-3);
+
+    let mut scene = scene(1.0, 1.0);
+    scene.set_epsilon(Some(large.epsilon));
+    scene.convert_to(Units::Centimeters);
+    assert!((scene.epsilon() / (large.epsilon * 100.0) - 1.0).abs() < 1e-6);
+    scene.set_epsilon(None);
+    assert_eq!(scene.epsilon(), scene.default_epsilon());
+}
+
+#[test]
+fn the_focus_falls_on_the_middle_of_the_view() {
+    let defaults = SceneDefaults::derive(&scene(1.0, 1.0), &camera());
+    assert!((defaults.focus_distance.unwrap() - 9.0).abs() < 1e-6);
+
+    let empty = Scene::new(Units::Meters);
+    assert_eq!(
+        SceneDefaults::derive(&empty, &camera()).focus_distance,
+        None
+    );
+}
+
+#[test]
+fn dim_scenes_are_brightened() {
+    // White surfaces under a uniform sky reflect its radiance.
+    let defaults = SceneDefaults::derive(&scene(1.0, 0.25), &camera());
+    assert!((defaults.exposure_stops.unwrap() - 2.0).abs() < 0.01);
+    let defaults = SceneDefaults::derive(&scene(1.0, 1.5), &camera());
+    assert_eq!(defaults.exposure_stops, None);
+
+    let exposure = Exposure::from_stops(2.0);
+    assert!((exposure.scale() - 4.0).abs() < 1e-6);
+}
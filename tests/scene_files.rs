@@ -0,0 +1,166 @@
+//! Scene files spread over several files.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Empty directory of its own for `test`, with a `shared` subdirectory.
+fn directory(test: &str) -> PathBuf {
+    let name = format!("raytracer-{}-{}", test, std::process::id());
+    let directory = std::env::temp_dir().join(name);
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(directory.join("shared")).unwrap();
+    directory
+}
+
+#[test]
+fn included_files_add_to_the_scene_from_where_they_are() {
+    let directory = directory("include");
+    fs::write(
+        directory.join("shared").join("triangle.obj"),
+        "v 0 0 0\nv 1 0 0\nv 0 0 -1\nf 1 2 3\n",
+    )
+    .unwrap();
+    // The mesh is found next to the included file.
+    fs::write(
+        directory.join("shared").join("props.txt"),
+        "sphere 0 1 0 1 lambertian 0.5 0.5 0.5 name prop\n\
+         mesh 5 0 0 1 triangle.obj smooth lambertian 0.5 0.5 0.5\n",
+    )
+    .unwrap();
+    let main = directory.join("main.txt");
+    fs::write(
+        &main,
+        "units centimeters\ninclude shared/props.txt\nsphere 3 1 0 1 metal 0.8 0.8 0.8 0",
+    )
+    .unwrap();
+
+    let scene = load_scene(&main.to_string_lossy()).unwrap();
+    assert_eq!(scene.units, Units::Centimeters);
+    assert_eq!(scene.spheres.len(), 3);
+    assert!(scene.sphere_named("prop").is_some());
+    let down = Ray::new(Vec3::new(5.2, 1.0, -0.2), Vec3::new(0.0, -1.0, 0.0));
+    let (id, hit) = scene.hit_object(&down, 1e-9, Float::INFINITY).unwrap();
+    match scene.shape(id) {
+        Some(Shape::Mesh(mesh)) => assert!(mesh.is_smooth()),
+        other => panic!("{:?}", other),
+    }
+    assert!((hit.t - 1.0).abs() < 1e-6);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn files_including_themselves_are_rejected() {
+    let directory = directory("cycle");
+    let first = directory.join("first.txt");
+    fs::write(
+        &first,
+        "sphere 0 1 0 1 lambertian 0.5 0.5 0.5\ninclude shared/second.txt",
+    )
+    .unwrap();
+    fs::write(
+        directory.join("shared").join("second.txt"),
+        "include ../first.txt",
+    )
+    .unwrap();
+
+    let error = load_scene(&first.to_string_lossy()).err().unwrap();
+    assert!(error.to_string().contains("includes itself"), "{}", error);
+    let missing = parse_scene("include nowhere.txt", "missing", &directory)
+        .err()
+        .unwrap();
+    assert!(missing.to_string().starts_with("missing:1:"), "{}", missing);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn objects_share_named_materials_and_textures() {
+    let directory = directory("library");
+    let mut flat = b"P6\n1 1\n255\n".to_vec();
+    flat.extend_from_slice(&[128, 128, 255]);
+    fs::write(directory.join("shared").join("flat.ppm"), flat).unwrap();
+    let source = "texture flat shared/flat.ppm
+material glass dielectric 1.5 priority 2 normal-map flat
+material lamp light 4 4 4 two-sided
+sphere 0 1 0 1 use glass name first
+sphere 3 1 0 1 use glass
+disk 0 5 0 0 -1 0 1 use lamp
+sphere 6 1 0 1 lambertian 0.5 0.5 0.5 bump-map flat
+";
+    let scene = parse_scene(source, "library", &directory).unwrap();
+    let glass = scene.material_named("glass").unwrap();
+    let first = scene.sphere_named("first").unwrap();
+    assert_eq!(scene.sphere(first).material, glass);
+    assert_eq!(scene.priority(glass), 2);
+    assert!(scene.normal_map(glass).is_some());
+    let lamp = scene.material_named("lamp").unwrap();
+    assert!(scene.two_sided(lamp));
+    assert_eq!(scene.lights.len(), 1);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn meshes_are_displaced_by_their_textures() {
+    let directory = directory("displace");
+    fs::write(
+        directory.join("square.obj"),
+        "v -1 0 1\nv 1 0 1\nv 1 0 -1\nv -1 0 -1\nf 1 2 3 4\n",
+    )
+    .unwrap();
+    let mut white = b"P6\n1 1\n255\n".to_vec();
+    white.extend_from_slice(&[255, 255, 255]);
+    fs::write(directory.join("white.ppm"), white).unwrap();
+    let source = "texture white white.ppm
+mesh 0 0 0 1 square.obj displace 2 0.25 white lambertian 0.5 0.5 0.5 uv-projection box
+";
+    let scene = parse_scene(source, "displace", &directory).unwrap();
+    let down = Ray::new(Vec3::new(0.2, 5.0, -0.3), Vec3::new(0.0, -1.0, 0.0));
+    let (id, hit) = scene.hit_object(&down, 1e-9, Float::INFINITY).unwrap();
+    match scene.shape(id) {
+        Some(Shape::Mesh(mesh)) => assert_eq!(mesh.triangle_count(), 2 * 4 * 4),
+        other => panic!("{:?}", other),
+    }
+    assert!((hit.t - 4.75).abs() < 1e-6);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn shared_materials_are_checked() {
+    let errors = [
+        ("sphere 0 1 0 1 use glass", "no material named 'glass'"),
+        (
+            "material glass dielectric 1.5\nmaterial glass dielectric 1.3",
+            "material 'glass' is already defined",
+        ),
+        (
+            "material glass dielectric 1.5\nsphere 0 1 0 1 use glass priority 2",
+            "unexpected 'priority' after a shared material",
+        ),
+        (
+            "material glass dielectric 1.5 name ball",
+            "unexpected 'name'",
+        ),
+        (
+            "sphere 0 1 0 1 lambertian 1 1 1 normal-map missing",
+            "missing",
+        ),
+        (
+            "plane 0 0 0 0 1 0 lambertian 1 1 1 uv-projection box",
+            "only spheres and meshes take a uv projection",
+        ),
+        (
+            "mesh 0 0 0 1 rock.obj displace 9 0.1 rock.ppm lambertian 1 1 1",
+            "displacement levels go from 0 to 8",
+        ),
+    ];
+    for (source, message) in &errors {
+        let error = parse_scene(source, "checks", Path::new("")).err().unwrap();
+        assert!(error.to_string().contains(message), "{}", error);
+    }
+}
@@ -0,0 +1,162 @@
+//! Sphere tracing of distance fields against surfaces with known answers.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+use std::sync::Arc;
+
+const EPSILON: Float = 1e-9;
+
+/// Sphere tracing stops within a small distance of the surface.
+fn assert_near(a: Float, b: Float) {
+    assert!((a - b).abs() < 5e-3, "{} != {}", a, b);
+}
+
+fn sdf(function: DistanceFunction, center: Vec3, scale: Float) -> Sdf {
+    Sdf::new(function, center, scale, MaterialId(0))
+}
+
+fn origin() -> Vec3 {
+    Vec3::new(0.0, 0.0, 0.0)
+}
+
+fn ball(center: Vec3, radius: Float) -> DistanceFunction {
+    DistanceFunction::Sphere { center, radius }
+}
+
+#[test]
+fn distance_spheres_match_spheres() {
+    let field = sdf(ball(origin(), 1.0), Vec3::new(1.0, 0.0, 0.0), 2.0);
+    let sphere = Sphere::new(Vec3::new(1.0, 0.0, 0.0), 2.0, MaterialId(0));
+
+    for &(y, z) in &[(0.0, 0.0), (1.0, 0.5), (-1.5, 0.8)] {
+        // Directions aren't normalized, to check steps are taken in t.
+        let ray = Ray::new(Vec3::new(-6.0, y, z), Vec3::new(2.0, 0.0, 0.0));
+        let expected = sphere.hit(&ray, EPSILON, Float::INFINITY).unwrap();
+        let hit = field.hit(&ray, EPSILON, Float::INFINITY).unwrap();
+        assert_near(hit.t, expected.t);
+        assert!((hit.normal - expected.normal).length() < 1e-2);
+        assert!(hit.front_face);
+    }
+    let bounds = field.bounding_sphere();
+    assert_near(bounds.radius, 2.0);
+    assert!(field
+        .hit(
+            &Ray::new(Vec3::new(-6.0, 2.5, 0.0), Vec3::new(1.0, 0.0, 0.0)),
+            EPSILON,
+            Float::INFINITY
+        )
+        .is_none());
+}
+
+#[test]
+fn rays_leaving_the_surface_find_the_far_side() {
+    let field = sdf(ball(origin(), 1.0), origin(), 1.0);
+    let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+    let entry = field.hit(&ray, EPSILON, Float::INFINITY).unwrap();
+
+    // Refracted straight through, from right on the surface.
+    let through = Ray::new(entry.position, Vec3::new(0.0, 0.0, 1.0));
+    let exit = field.hit(&through, EPSILON, Float::INFINITY).unwrap();
+    assert_near(exit.t, 2.0);
+    assert!(!exit.front_face);
+
+    // Reflected back out, nothing else is there.
+    let back = Ray::new(entry.position, Vec3::new(0.3, 0.0, -1.0));
+    assert!(field.hit(&back, EPSILON, Float::INFINITY).is_none());
+}
+
+#[test]
+fn menger_sponges_have_holes_through_them() {
+    let sponge = sdf(
+        DistanceFunction::Menger { iterations: 3 },
+        Vec3::new(0.0, 1.0, 0.0),
+        1.0,
+    );
+    let along_z = |x: Float, y: Float| Ray::new(Vec3::new(x, y, -5.0), Vec3::new(0.0, 0.0, 1.0));
+
+    // A corner of the front face, then the middle hole and a hole of the
+    // second level, centered a third of the way from the edge.
+    let corner = sponge.hit(&along_z(0.95, 1.95), EPSILON, Float::INFINITY);
+    assert_near(corner.unwrap().t, 4.0);
+    assert!(sponge
+        .hit(&along_z(0.0, 1.0), EPSILON, Float::INFINITY)
+        .is_none());
+    assert!(sponge
+        .hit(
+            &along_z(2.0 / 3.0, 1.0 + 2.0 / 3.0),
+            EPSILON,
+            Float::INFINITY
+        )
+        .is_none());
+    assert!(sponge
+        .hit(&along_z(1.2, 1.0), EPSILON, Float::INFINITY)
+        .is_none());
+}
+
+#[test]
+fn mandelbulbs_fill_their_bounds() {
+    let bulb = sdf(
+        DistanceFunction::Mandelbulb {
+            power: 8.0,
+            iterations: 12,
+        },
+        origin(),
+        2.0,
+    );
+    let ray = Ray::new(Vec3::new(-10.0, 0.1, 0.0), Vec3::new(1.0, 0.0, 0.0));
+    let hit = bulb.hit(&ray, EPSILON, Float::INFINITY).unwrap();
+    assert!(hit.t > 10.0 - 2.4 && hit.t < 10.0, "{}", hit.t);
+    assert!(bulb.distance(hit.position).abs() < 5e-3);
+
+    let above = Ray::new(Vec3::new(-10.0, 3.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+    assert!(bulb.hit(&above, EPSILON, Float::INFINITY).is_none());
+}
+
+#[test]
+fn smooth_unions_bridge_the_gap_between_surfaces() {
+    let drops = |k: Float| {
+        let left = ball(Vec3::new(-1.5, 0.0, 0.0), 1.0);
+        sdf(
+            left.blend(ball(Vec3::new(1.5, 0.0, 0.0), 1.0), k),
+            origin(),
+            1.0,
+        )
+    };
+    let down = Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+
+    assert!(drops(0.0).hit(&down, EPSILON, Float::INFINITY).is_none());
+    let hit = drops(3.0).hit(&down, EPSILON, Float::INFINITY).unwrap();
+    assert!(hit.t < 5.0 && hit.t > 4.0, "{}", hit.t);
+    assert_near(hit.normal.y, 1.0);
+
+    // Far from the gap the spheres keep their shape.
+    assert_near(smooth_min(0.0, 5.0, 1.0), 0.0);
+    assert_near(smooth_min(2.0, 2.0, 1.0), 1.75);
+}
+
+#[test]
+fn scenes_hit_custom_distance_fields() {
+    let mut scene = Scene::new(Units::Meters);
+    let gray = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    // A cube, half a unit across, around the origin of the function.
+    let function = DistanceFunction::Custom {
+        function: Arc::new(|p: Vec3| p.x.abs().max(p.y.abs()).max(p.z.abs()) - 0.5),
+        radius: 1.0,
+    };
+    let cube = scene.add_shape(Shape::Sdf(Sdf::new(
+        function,
+        Vec3::new(3.0, 1.0, 0.0),
+        2.0,
+        gray,
+    )));
+    assert_near(scene.sphere(cube).radius, 2.0);
+
+    let down = Ray::new(Vec3::new(3.5, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+    let (id, hit) = scene.hit_object(&down, EPSILON, Float::INFINITY).unwrap();
+    assert_eq!(id, cube);
+    assert_near(hit.t, 3.0);
+    assert_near(hit.normal.y, 1.0);
+}
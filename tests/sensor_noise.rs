@@ -0,0 +1,95 @@
+//! Statistics of the simulated camera noise.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+/// Mean and variance of the red channel of `pixels`.
+fn red_statistics(pixels: &[Float]) -> (Float, Float) {
+    let reds: Vec<Float> = pixels.chunks(4).map(|pixel| pixel[0]).collect();
+    let mean = reds.iter().sum::<Float>() / reds.len() as Float;
+    let variance = reds.iter().map(|red| (red - mean).powi(2)).sum::<Float>() / reds.len() as Float;
+    (mean, variance)
+}
+
+/// Opaque `width` x `height` gray image of `value`, with `noise` added.
+fn noisy_gray(value: Float, width: usize, height: usize, noise: &SensorNoise) -> Vec<Float> {
+    let mut pixels = [value, value, value, 1.0].repeat(width * height);
+    add_sensor_noise(&mut pixels, width, height, noise);
+    pixels
+}
+
+#[test]
+fn shot_noise_follows_the_photons_and_resolution() {
+    let noise = SensorNoise {
+        photons: 1000.0,
+        read_noise: 0.0,
+        ..SensorNoise::default()
+    };
+
+    // At 1080 lines, a pixel of 0.5 counts 500 photons give or take √500.
+    let pixels = noisy_gray(0.5, 100, 1080, &noise);
+    let (mean, variance) = red_statistics(&pixels);
+    assert!((mean - 0.5).abs() < 1e-3, "{}", mean);
+    assert!(
+        (variance / (0.5 / 1000.0) - 1.0).abs() < 0.05,
+        "{}",
+        variance
+    );
+    assert!(pixels.chunks(4).all(|pixel| pixel[3] == 1.0));
+
+    // Twice the lines give pixels a quarter as big, so four times as noisy.
+    let (_, fine_variance) = red_statistics(&noisy_gray(0.5, 100, 2160, &noise));
+    assert!(
+        (fine_variance / variance - 4.0).abs() < 0.3,
+        "{}",
+        fine_variance
+    );
+
+    // Dim pixels count few photons, never fewer than none.
+    let pixels = noisy_gray(0.002, 100, 1080, &noise);
+    let (mean, _) = red_statistics(&pixels);
+    assert!((mean - 0.002).abs() < 2e-4, "{}", mean);
+    assert!(pixels.iter().all(|&value| value >= 0.0));
+}
+
+#[test]
+fn noise_depends_on_the_seed_only() {
+    let noise = SensorNoise {
+        grain: 0.2,
+        ..SensorNoise::default()
+    };
+    let reseeded = SensorNoise { seed: 1, ..noise };
+    let first = noisy_gray(0.3, 40, 30, &noise);
+    assert_eq!(first, noisy_gray(0.3, 40, 30, &noise));
+    assert_ne!(first, noisy_gray(0.3, 40, 30, &reseeded));
+}
+
+#[test]
+fn grain_keeps_black_and_transparent_pixels() {
+    let noise = SensorNoise {
+        photons: 0.0,
+        read_noise: 0.05,
+        grain: 0.3,
+        grain_size: 4.0,
+        seed: 7,
+    };
+    let mut pixels = [0.0, 0.0, 0.0, 0.0].repeat(64 * 64);
+    add_sensor_noise(&mut pixels, 64, 64, &noise);
+    assert!(pixels.iter().all(|&value| value == 0.0));
+
+    // Grains spread over several pixels, so neighbours look alike.
+    let noise = SensorNoise {
+        read_noise: 0.0,
+        ..noise
+    };
+    let pixels = noisy_gray(0.5, 256, 1080, &noise);
+    let (mean, variance) = red_statistics(&pixels);
+    assert!((mean - 0.5).abs() < 0.02, "{}", mean);
+    let neighbours = pixels
+        .chunks(4)
+        .zip(pixels.chunks(4).skip(1))
+        .map(|(a, b)| (a[0] - b[0]).powi(2))
+        .sum::<Float>()
+        / (pixels.len() / 4) as Float;
+    assert!(neighbours < 0.5 * variance, "{} {}", neighbours, variance);
+}
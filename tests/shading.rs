@@ -0,0 +1,194 @@
+//! Shading helpers checked against identities and numerical integration.
+
+use raytracer::maths::shading::*;
+use raytracer::maths::*;
+
+use raytracer::maths::consts::PI;
+
+/// Tolerances are widened to a few ulps of 1 in single precision builds.
+fn assert_close(a: Float, b: Float, tolerance: Float) {
+    let tolerance = tolerance.max(64.0 * Float::EPSILON);
+    assert!((a - b).abs() < tolerance, "{} != {}", a, b);
+}
+
+fn assert_vec_close(a: Vec3, b: Vec3) {
+    let tolerance = (1e-9 as Float).max(64.0 * Float::EPSILON);
+    assert!((a - b).length() < tolerance, "{:?} != {:?}", a, b);
+}
+
+/// Midpoint rule integral of `f` over the hemisphere above +z.
+fn integrate_hemisphere(f: impl Fn(Vec3) -> Float) -> Float {
+    let (thetas, phis) = (1000, 200);
+    let d_theta = PI / 2.0 / thetas as Float;
+    let d_phi = 2.0 * PI / phis as Float;
+
+    let mut sum = 0.0;
+    for i in 0..thetas {
+        let theta = (i as Float + 0.5) * d_theta;
+        for j in 0..phis {
+            let phi = (j as Float + 0.5) * d_phi;
+            sum += f(spherical_direction(theta, phi)) * theta.sin() * d_theta * d_phi;
+        }
+    }
+    sum
+}
+
+#[test]
+fn spherical_round_trip() {
+    for &(theta, phi) in &[(0.3, 0.1), (1.2, 2.5), (2.8, 4.0), (PI / 2.0, 6.0)] {
+        let direction = spherical_direction(theta, phi);
+        assert_close(direction.length(), 1.0, 1e-12);
+
+        let (theta2, phi2) = spherical_angles(direction * 3.0);
+        assert_close(theta2, theta, 1e-9);
+        assert_close(phi2, phi, 1e-9);
+    }
+    assert_vec_close(spherical_direction(0.0, 1.0), Vec3::new(0.0, 0.0, 1.0));
+}
+
+#[test]
+fn hemisphere_sides() {
+    let normal = Vec3::new(0.0, 1.0, 0.0);
+    assert!(same_hemisphere(
+        Vec3::new(1.0, 0.5, 0.0),
+        Vec3::new(0.0, 2.0, 1.0),
+        normal
+    ));
+    assert!(!same_hemisphere(
+        Vec3::new(1.0, 0.5, 0.0),
+        Vec3::new(0.0, -2.0, 1.0),
+        normal
+    ));
+    assert!(!same_hemisphere(
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        normal
+    ));
+}
+
+#[test]
+fn reflection_half_vector_is_the_normal() {
+    let normal = Vec3::new(0.0, 0.0, 1.0);
+    let wo = spherical_direction(0.7, 1.3);
+    let wi = reflect(-wo, normal);
+
+    assert_vec_close(half_vector(wo, wi).unwrap(), normal);
+    assert!(half_vector(wo, -wo).is_none());
+}
+
+#[test]
+fn refraction_half_vector_is_the_normal() {
+    let normal = Vec3::new(0.0, 0.0, 1.0);
+    let wo = spherical_direction(0.7, 1.3);
+    let wi = refract(-wo, normal, 1.0 / 1.5);
+
+    // Snell's law holds for the refracted direction.
+    assert_close(wo.z.acos().sin(), 1.5 * (-wi.z).acos().sin(), 1e-9);
+    assert_vec_close(refraction_half_vector(wo, wi, 1.0, 1.5).unwrap(), normal);
+    assert_vec_close(refraction_half_vector(wi, wo, 1.5, 1.0).unwrap(), -normal);
+}
+
+#[test]
+fn fresnel_limits() {
+    let f0 = schlick_f0(1.5);
+    assert_close(f0, 0.04, 1e-12);
+    assert_close(fresnel_dielectric(1.0, 1.5), f0, 1e-12);
+    assert_close(fresnel_schlick(1.0, f0), f0, 1e-12);
+    assert_close(fresnel_schlick(0.0, f0), 1.0, 1e-12);
+    assert_close(fresnel_dielectric(1e-9, 1.5), 1.0, 1e-6);
+
+    // Leaving glass past the critical angle reflects everything.
+    let critical = (1.0 as Float / 1.5).asin();
+    assert_close(
+        fresnel_dielectric(-(critical + 0.01).cos(), 1.5),
+        1.0,
+        1e-12,
+    );
+    assert!(fresnel_dielectric(-(critical - 0.01).cos(), 1.5) < 1.0);
+
+    let color = fresnel_schlick_color(0.5, Vec3::new(0.9, 0.6, 0.3));
+    assert_close(color.y, fresnel_schlick(0.5, 0.6), 1e-12);
+}
+
+#[test]
+fn fresnel_dielectric_matches_across_the_interface() {
+    // Reflectance is the same from both sides along a refracted pair.
+    let cos_outside: Float = 0.6;
+    let sin_inside = (1.0 - cos_outside * cos_outside).sqrt() / 1.5;
+    let cos_inside = (1.0 - sin_inside * sin_inside).sqrt();
+    assert_close(
+        fresnel_dielectric(cos_outside, 1.5),
+        fresnel_dielectric(-cos_inside, 1.5),
+        1e-12,
+    );
+}
+
+#[test]
+fn ggx_projected_normals_integrate_to_one() {
+    for &alpha in &[0.2, 0.5, 1.0] {
+        assert_close(integrate_hemisphere(|m| ggx_pdf(m, alpha)), 1.0, 1e-3);
+    }
+}
+
+#[test]
+fn ggx_visible_normals_integrate_to_one() {
+    for &alpha in &[0.3, 0.8] {
+        for &theta in &[0.0, 0.6, 1.3] {
+            let wo = spherical_direction(theta, 0.4);
+            let total = integrate_hemisphere(|m| ggx_visible_pdf(wo, m, alpha));
+            assert_close(total, 1.0, 1e-3);
+        }
+    }
+    assert_close(ggx_g1(Vec3::new(0.0, 0.0, 1.0), 0.5), 1.0, 1e-12);
+}
+
+#[test]
+fn ggx_sampling_follows_its_pdf() {
+    // The fraction of stratified samples within a cone must match the
+    // integral of the density over that cone.
+    let alpha = 0.4;
+    let cone: Float = 0.5;
+    let wo = spherical_direction(1.0, 0.0);
+    let (thetas, phis) = (4000, 16);
+    let d_theta = cone / thetas as Float;
+    let d_phi = 2.0 * PI / phis as Float;
+    let mut expected = 0.0;
+    let mut expected_visible = 0.0;
+    for i in 0..thetas {
+        let theta = (i as Float + 0.5) * d_theta;
+        for j in 0..phis {
+            let m = spherical_direction(theta, (j as Float + 0.5) * d_phi);
+            expected += ggx_pdf(m, alpha) * theta.sin() * d_theta * d_phi;
+            expected_visible += ggx_visible_pdf(wo, m, alpha) * theta.sin() * d_theta * d_phi;
+        }
+    }
+
+    let n = 200;
+    let mut inside = 0;
+    let mut inside_visible = 0;
+    for a in 0..n {
+        for b in 0..n {
+            let (u1, u2) = (
+                (a as Float + 0.5) / n as Float,
+                (b as Float + 0.5) / n as Float,
+            );
+            let m = sample_ggx(alpha, u1, u2);
+            assert_close(m.length(), 1.0, 1e-9);
+            if m.z > cone.cos() {
+                inside += 1;
+            }
+
+            let visible = sample_ggx_visible(wo, alpha, u1, u2);
+            assert!(visible.dot(wo) >= -1e-9 && visible.z >= 0.0);
+            if visible.z > cone.cos() {
+                inside_visible += 1;
+            }
+        }
+    }
+    assert_close(inside as Float / (n * n) as Float, expected, 1e-3);
+    assert_close(
+        inside_visible as Float / (n * n) as Float,
+        expected_visible,
+        1e-3,
+    );
+}
@@ -0,0 +1,53 @@
+//! Shadows and reflections kept by shadow catchers.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+/// Shadow catching ground under a ball, lit by a uniform sky.
+fn scene() -> Scene {
+    let mut scene = Scene::new(Units::Meters);
+    scene.environment = Environment::Constant(Vec3::new(1.0, 1.0, 1.0));
+    let gray = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    let ground = scene.add(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, gray));
+    scene.set_shadow_catcher(ground, true);
+    scene.add(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, gray));
+    scene
+}
+
+/// Mean color and alpha of `samples` paths looking down at (`x`, 0, `z`).
+fn look_down(scene: &Scene, x: Float, z: Float, samples: u64) -> (Vec3, Float) {
+    let settings = RenderSettings::default();
+    let ray = Ray::new(Vec3::new(x, 5.0, z + 5.0), Vec3::new(0.0, -1.0, -1.0));
+    let mut color = Vec3::new(0.0, 0.0, 0.0);
+    let mut alpha = 0.0;
+    for sample in 0..samples {
+        seed_random(sample);
+        let (sample_color, sample_alpha) = ray_color(&ray, scene, &settings);
+        color += sample_color;
+        alpha += sample_alpha;
+    }
+    (color / samples as Float, alpha / samples as Float)
+}
+
+#[test]
+fn unshadowed_ground_is_transparent() {
+    let (color, alpha) = look_down(&scene(), 30.0, 0.0, 64);
+    assert_eq!(alpha, 0.0);
+    assert_eq!(color.length(), 0.0);
+}
+
+#[test]
+fn ground_keeps_contact_shadows() {
+    // Next to the ball, which hides a good part of the sky and reflects
+    // some of its light onto the ground.
+    let (color, alpha) = look_down(&scene(), 1.1, 0.0, 256);
+    assert!(alpha > 0.2 && alpha < 1.0, "{}", alpha);
+    assert!(color.x > 0.0 && color.x < alpha, "{:?}", color);
+
+    // An ordinary ground is opaque.
+    let mut opaque = scene();
+    opaque.set_shadow_catcher(ObjectId(0), false);
+    assert_eq!(look_down(&opaque, 1.1, 0.0, 16).1, 1.0);
+}
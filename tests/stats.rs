@@ -0,0 +1,52 @@
+//! Intersection stats gathered along the path of normal renders.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+#[test]
+fn stats_count_the_tests_the_accelerator_makes() {
+    let mut scene = Scene::new(Units::Meters);
+    let gray = scene.add_material(MaterialType::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    });
+    for x in 0..20 {
+        for z in 0..20 {
+            let center = Vec3::new(x as Float * 3.0, 0.0, z as Float * 3.0);
+            scene.add(Sphere::new(center, 1.0, gray));
+        }
+    }
+    let post = scene.add_shape(Shape::Capsule(Capsule::new(
+        Vec3::new(0.0, 2.0, 0.0),
+        Vec3::new(0.0, 4.0, 0.0),
+        0.5,
+        gray,
+    )));
+    let ground = scene.add_plane(Plane::new(
+        Vec3::new(0.0, -1.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        gray,
+    ));
+    scene.enable_stats();
+
+    for &kind in &AcceleratorKind::ALL {
+        scene.set_accelerator_kind(kind);
+        let down = |x: Float| Ray::new(Vec3::new(x, 10.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let rays = [down(0.0), down(1.5), down(3.0)];
+        let hits = scene.hit_objects(&rays, 1e-4, &[Float::INFINITY; 3]);
+        let ids: Vec<_> = hits.iter().map(|hit| hit.unwrap().0).collect();
+        assert_eq!(ids, vec![post, ground, ObjectId(20)], "{:?}", kind);
+        let (id, _) = scene.hit_object(&down(0.0), 1e-4, Float::INFINITY).unwrap();
+        assert_eq!(id, post);
+    }
+
+    let stats = scene.stats().unwrap();
+    assert_eq!(stats.len(), scene.spheres.len());
+    let runs = AcceleratorKind::ALL.len() as u64;
+    assert_eq!(stats[post.0].hits, 2 * runs);
+    assert_eq!(stats[ground.0].hits, runs);
+    assert_eq!(stats[20].hits, runs);
+    // Far spheres are left to the accelerator, not tested one by one.
+    let tests: u64 = stats.iter().map(|object| object.tests).sum();
+    assert!(tests < 20 * runs, "{}", tests);
+    assert_eq!(stats[399].tests, 0);
+}
@@ -0,0 +1,82 @@
+//! Scenes rendered while their meshes load in the background.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+use std::fs;
+
+/// Scene file of a square mesh lying at y = 1, 2 m across, in centimeters,
+/// over a floor, and of a mesh whose file is missing when `missing`.
+fn write_scene(name: &str, missing: bool) -> String {
+    let directory = std::env::temp_dir().join(name);
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(
+        directory.join("square.obj"),
+        "v -1 0 -1\nv 1 0 -1\nv 1 0 1\nv -1 0 1\nf 1 2 3\nf 1 3 4\n",
+    )
+    .unwrap();
+    let mut source = String::from(
+        "units centimeters\n\
+         plane 0 0 0 0 1 0 lambertian 0.5 0.5 0.5\n\
+         mesh 0 100 0 100 square.obj metal 0.9 0.9 0.9 0 name square\n",
+    );
+    if missing {
+        source.push_str("mesh 0 50 0 1 missing.obj lambertian 0.5 0.5 0.5\n");
+    }
+    let file = directory.join("scene.txt");
+    fs::write(&file, source).unwrap();
+    file.to_string_lossy().into_owned()
+}
+
+#[test]
+fn streamed_meshes_come_in_after_the_scene() {
+    let name = write_scene("raytracer_streaming", false);
+    let (mut scene, stream) = load_scene_streamed(&name).unwrap();
+    assert_eq!((stream.total(), stream.pending()), (1, 1));
+    // Rays go through meshes that haven't come in yet.
+    scene.convert_to(Units::Meters);
+    let down = Ray::new(Vec3::new(0.5, 3.0, 0.5), Vec3::new(0.0, -1.0, 0.0));
+    let hit = scene.hit(&down, 1e-4, Float::INFINITY).unwrap();
+    assert!((hit.t - 3.0).abs() < 1e-6);
+
+    let square = scene.sphere_named("square").unwrap();
+    stream.finish(&mut scene).unwrap();
+    let hit = scene.hit(&down, 1e-4, Float::INFINITY).unwrap();
+    // Converted to meters, though it came in after the scene was.
+    assert!((hit.t - 2.0).abs() < 1e-6, "{}", hit.t);
+    assert!(matches!(
+        scene.material(hit.material),
+        MaterialType::Metal { .. }
+    ));
+    assert!(matches!(scene.shape(square), Some(Shape::Mesh(_))));
+    // Past the square, rays find the floor again, the box gone.
+    let past = Ray::new(Vec3::new(1.5, 3.0, 0.5), Vec3::new(0.0, -1.0, 0.0));
+    let hit = scene.hit(&past, 1e-4, Float::INFINITY).unwrap();
+    assert!((hit.t - 3.0).abs() < 1e-6);
+
+    // Loading the whole scene first gives the same.
+    let mut whole = load_scene(&name).unwrap();
+    whole.convert_to(Units::Meters);
+    let hit = whole.hit(&down, 1e-4, Float::INFINITY).unwrap();
+    assert!((hit.t - 2.0).abs() < 1e-6);
+}
+
+#[test]
+fn missing_streamed_meshes_are_reported() {
+    let name = write_scene("raytracer_streaming_missing", true);
+    let (mut scene, mut stream) = load_scene_streamed(&name).unwrap();
+    assert_eq!(stream.pending(), 2);
+    let mut errors = Vec::new();
+    while stream.pending() > 0 {
+        if let Err(error) = stream.wait(&mut scene) {
+            errors.push(error.to_string());
+        }
+    }
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].contains("scene.txt:4: "), "{}", errors[0]);
+    assert!(errors[0].contains("missing.obj"), "{}", errors[0]);
+    // The other mesh still came in.
+    let square = scene.sphere_named("square").unwrap();
+    assert!(matches!(scene.shape(square), Some(Shape::Mesh(_))));
+}
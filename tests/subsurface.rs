@@ -0,0 +1,71 @@
+//! Random walks through subsurface materials.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+/// Mean color of rays hitting a subsurface ball of `albedo` and `radius`
+/// under a white sky, straight on and near the rim, with `settings`.
+fn furnace(albedo: Vec3, radius: Vec3, settings: &RenderSettings) -> Vec3 {
+    let mut scene = Scene::new(Units::Meters);
+    scene.environment = Environment::Constant(Vec3::new(1.0, 1.0, 1.0));
+    let material = scene.add_material(MaterialType::Subsurface {
+        albedo,
+        radius,
+        refractive_index: 1.4,
+    });
+    scene.add(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, material));
+
+    let samples = 4000;
+    let mut sum = Vec3::new(0.0, 0.0, 0.0);
+    for sample in 0..samples {
+        seed_random(sample);
+        let x = if sample % 2 == 0 { 0.0 } else { 0.8 };
+        let ray = Ray::new(Vec3::new(x, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        sum += ray_color(&ray, &scene, settings).0;
+    }
+    sum / samples as Float
+}
+
+#[test]
+fn random_walks_keep_the_light_of_white_media() {
+    let settings = RenderSettings::default();
+    let color = furnace(
+        Vec3::new(1.0, 1.0, 1.0),
+        Vec3::new(0.1, 0.2, 0.4),
+        &settings,
+    );
+    for &channel in &[color.x, color.y, color.z] {
+        assert!((channel - 1.0).abs() < 0.05, "{:?}", color);
+    }
+}
+
+#[test]
+fn subsurface_materials_take_their_albedo() {
+    // Light the surface reflects adds a little white.
+    let albedo = Vec3::new(0.8, 0.5, 0.2);
+    let settings = RenderSettings::default();
+    let color = furnace(albedo, Vec3::new(0.05, 0.05, 0.05), &settings);
+    for &(channel, albedo) in &[
+        (color.x, albedo.x),
+        (color.y, albedo.y),
+        (color.z, albedo.z),
+    ] {
+        assert!(channel > albedo && channel < albedo + 0.12, "{:?}", color);
+    }
+}
+
+#[test]
+fn volume_limits_cut_random_walks_short() {
+    let white = Vec3::new(1.0, 1.0, 1.0);
+    let radius = Vec3::new(0.1, 0.1, 0.1);
+    let limited = |volume| {
+        let mut settings = RenderSettings::default();
+        settings.bounce_limits.volume = volume;
+        furnace(white, radius, &settings).x
+    };
+    // Without scattering only the light reflected off the surface is left,
+    // and each step allowed lets more out.
+    let (none, few, many) = (limited(0), limited(8), limited(i32::MAX));
+    assert!(none < 0.15, "{}", none);
+    assert!(few > none && few < many - 0.1, "{} {} {}", none, few, many);
+}
@@ -0,0 +1,68 @@
+//! Parameter sweeps over scenes.
+
+use raytracer::maths::*;
+use raytracer::*;
+
+fn scene() -> Scene {
+    let source = "sphere 0 1 0 1 metal 0.8 0.8 0.8 0.1 name ball\n\
+                  sphere 3 1 0 1 metal 0.5 0.5 0.5 0.2\n\
+                  sphere 0 5 0 1 light 2 2 2\n\
+                  point-light 0 4 0 4 4 4";
+    parse_scene(source, "sweep", std::path::Path::new("")).unwrap()
+}
+
+#[test]
+fn sweeps_include_their_end() {
+    let sweep = Sweep::parse("material.roughness=0:1:0.1").unwrap();
+    assert_eq!(sweep.values().len(), 11);
+    assert_eq!(sweep.label(sweep.values()[3]), "roughness=0.3");
+
+    assert!(Sweep::parse("material.roughness=0:1").is_err());
+    assert!(Sweep::parse("material.roughness=1:0:0.1").is_err());
+    assert!(Sweep::parse("sun.roughness=0:1:0.1").is_err());
+    assert!(Sweep::parse("roughness=0:1:0.1").is_err());
+}
+
+#[test]
+fn sweeps_change_their_target_only() {
+    let mut scene = scene();
+    let mut sweep = Sweep::parse("ball.roughness=0:1:0.5").unwrap();
+    sweep.bind(&scene).unwrap();
+    sweep.apply(&mut scene, 0.5);
+    assert!(matches!(
+        scene.material(MaterialId(0)),
+        MaterialType::Metal { fuzziness, .. } if *fuzziness == 0.5
+    ));
+    assert!(matches!(
+        scene.material(MaterialId(1)),
+        MaterialType::Metal { fuzziness, .. } if *fuzziness == 0.2
+    ));
+
+    // Intensities scale the lights of the scene, not those of the last value.
+    let mut sweep = Sweep::parse("lights.intensity=1:3:1").unwrap();
+    sweep.bind(&scene).unwrap();
+    sweep.apply(&mut scene, 2.0);
+    sweep.apply(&mut scene, 3.0);
+    assert!(matches!(
+        scene.material(MaterialId(2)),
+        MaterialType::DiffuseLight { emit } if emit.x == 6.0
+    ));
+    let irradiance = scene.delta_lights[0]
+        .illuminate(Vec3::new(0.0, 0.0, 0.0))
+        .unwrap()
+        .irradiance;
+    assert_eq!(irradiance.x, 0.75);
+
+    assert!(Sweep::parse("glass.ior=1:2:0.5")
+        .unwrap()
+        .bind(&scene)
+        .is_err());
+    assert!(Sweep::parse("material.ior=1:2:0.5")
+        .unwrap()
+        .bind(&scene)
+        .is_err());
+    assert!(Sweep::parse("sun.elevation=0:90:10")
+        .unwrap()
+        .bind(&scene)
+        .is_err());
+}
@@ -0,0 +1,62 @@
+//! Frames piped into a video encoder.
+
+use raytracer::video::*;
+
+use std::fs;
+
+#[test]
+fn ffmpeg_gets_the_frame_size_rate_and_codec() {
+    let arguments = ffmpeg_arguments("out.webm", 41, 30, 12.5);
+    let after = |flag: &str| {
+        let index = arguments.iter().position(|argument| argument == flag);
+        arguments[index.unwrap() + 1].clone()
+    };
+    assert_eq!(after("-s"), "41x30");
+    assert_eq!(after("-framerate"), "12.5");
+    assert_eq!(after("-i"), "-");
+    assert_eq!(after("-c:v"), "libvpx-vp9");
+    assert_eq!(arguments.last().unwrap(), "out.webm");
+    let arguments = ffmpeg_arguments("out.mp4", 40, 30, 24.0);
+    assert!(arguments.iter().any(|argument| argument == "libx264"));
+
+    assert!(is_video("frames/out.mp4") && is_video("out.webm"));
+    assert!(!is_video("out.png") && !is_video("mp4"));
+}
+
+#[cfg(unix)]
+#[test]
+fn video_writers_pipe_raw_frames_into_the_encoder() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let directory = std::env::temp_dir().join("raytracer_video");
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    // Stands for ffmpeg, copying its input to the output file, its last
+    // argument.
+    let encoder = directory.join("encoder.sh");
+    fs::write(
+        &encoder,
+        "#!/bin/sh\nfor last; do :; done\ncat > \"$last\"\n",
+    )
+    .unwrap();
+    fs::set_permissions(&encoder, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let output = directory.join("out.mp4");
+    let (encoder, output) = (encoder.to_str().unwrap(), output.to_str().unwrap());
+    let mut video = VideoWriter::new(encoder, output, 3, 2, 24.0).unwrap();
+    video.write_frame(&[10; 18]).unwrap();
+    video.write_frame(&[20; 18]).unwrap();
+    // Frames of the wrong size are turned down.
+    assert!(video.write_frame(&[0; 12]).is_err());
+    assert_eq!(video.frames(), 2);
+    video.finish().unwrap();
+    let written = fs::read(output).unwrap();
+    assert_eq!(written, [[10; 18], [20; 18]].concat());
+
+    let error = VideoWriter::new("no-such-encoder", output, 3, 2, 24.0)
+        .err()
+        .unwrap();
+    assert!(error
+        .to_string()
+        .contains("could not start no-such-encoder"));
+}